@@ -14,18 +14,30 @@ function build_rust_targets() {
   cargo build --release --manifest-path dpe/Cargo.toml --features=$profile --no-default-features
   cargo build --release --manifest-path simulator/Cargo.toml --features=$profile,openssl --no-default-features
   cargo build --release --manifest-path tools/Cargo.toml --features=$profile --no-default-features
+  cargo build --release --manifest-path client/Cargo.toml --features=$profile --no-default-features
+  cargo build --release --manifest-path conformance/Cargo.toml --features=$profile --no-default-features
+  cargo build --release --manifest-path ffi/Cargo.toml --features=$profile,std --no-default-features
+  cargo build --release --manifest-path examples/embedded/Cargo.toml --target thumbv7em-none-eabihf --features=$profile --no-default-features
 
   cargo build --manifest-path crypto/Cargo.toml --no-default-features
   cargo build --manifest-path platform/Cargo.toml --features=$profile --no-default-features
   cargo build --manifest-path dpe/Cargo.toml --features=$profile --no-default-features
   cargo build --manifest-path simulator/Cargo.toml --features=$profile,openssl --no-default-features
   cargo build --manifest-path tools/Cargo.toml --features=$profile --no-default-features
+  cargo build --manifest-path client/Cargo.toml --features=$profile --no-default-features
+  cargo build --manifest-path conformance/Cargo.toml --features=$profile --no-default-features
+  cargo build --manifest-path ffi/Cargo.toml --features=$profile,std --no-default-features
 
   cargo clippy --manifest-path crypto/Cargo.toml --no-default-features -- --deny=warnings
   cargo clippy --manifest-path platform/Cargo.toml --features=$profile --no-default-features -- --deny=warnings
   cargo clippy --manifest-path dpe/Cargo.toml --features=$profile --no-default-features -- --deny=warnings
   cargo clippy --manifest-path simulator/Cargo.toml --features=$profile,openssl --no-default-features -- --deny=warnings
   cargo clippy --manifest-path tools/Cargo.toml --features=$profile --no-default-features -- --deny=warnings
+  cargo clippy --manifest-path client/Cargo.toml --features=$profile --no-default-features -- --deny=warnings
+  cargo clippy --manifest-path conformance/Cargo.toml --features=$profile --no-default-features -- --deny=warnings
+  cargo clippy --manifest-path ffi/Cargo.toml --features=$profile,std --no-default-features -- --deny=warnings
+  cargo clippy --manifest-path python/Cargo.toml --features=$profile --no-default-features -- --deny=warnings
+  cargo clippy --manifest-path examples/embedded/Cargo.toml --target thumbv7em-none-eabihf --features=$profile --no-default-features -- --deny=warnings
 }
 
 function format_rust_targets() {
@@ -34,6 +46,11 @@ function format_rust_targets() {
   cargo fmt --manifest-path dpe/Cargo.toml --check
   cargo fmt --manifest-path simulator/Cargo.toml --check
   cargo fmt --manifest-path tools/Cargo.toml --check
+  cargo fmt --manifest-path client/Cargo.toml --check
+  cargo fmt --manifest-path conformance/Cargo.toml --check
+  cargo fmt --manifest-path ffi/Cargo.toml --check
+  cargo fmt --manifest-path python/Cargo.toml --check
+  cargo fmt --manifest-path examples/embedded/Cargo.toml --check
 }
 
 function format_go_targets() {
@@ -49,8 +66,45 @@ function test_rust_targets() {
 
   cargo test --manifest-path platform/Cargo.toml --features=$profile --no-default-features
   cargo test --manifest-path crypto/Cargo.toml --no-default-features
+  cargo test --manifest-path crypto/Cargo.toml --no-default-features --features=rustcrypto
   cargo test --manifest-path dpe/Cargo.toml --features=$profile --no-default-features -- --test-threads=1
+  cargo test --manifest-path dpe/Cargo.toml --features=$profile,serde --no-default-features serde_hex
+  cargo test --manifest-path dpe/Cargo.toml --features=$profile,chain_digest_measurement --no-default-features -- --test-threads=1
+  cargo test --manifest-path dpe/Cargo.toml --features=$profile,no-large-stack --no-default-features arena::
+  cargo test --release --manifest-path dpe/Cargo.toml --features=$profile,no-large-stack --no-default-features stack_usage
+  ARBITRARY_MAX_HANDLES=8 cargo test --manifest-path dpe/Cargo.toml --features=$profile,arbitrary_max_handles --no-default-features -- --test-threads=1
+  ARBITRARY_MAX_HANDLES=64 cargo test --manifest-path dpe/Cargo.toml --features=$profile,arbitrary_max_handles --no-default-features -- --test-threads=1
   cargo test --manifest-path simulator/Cargo.toml --features=$profile,openssl --no-default-features
+  cargo test --manifest-path client/Cargo.toml --features=$profile --no-default-features
+  cargo test --manifest-path conformance/Cargo.toml --features=$profile --no-default-features
+  cargo test --manifest-path ffi/Cargo.toml --features=$profile,std --no-default-features
+
+  ( cd python
+    python3 -m venv .venv
+    . .venv/bin/activate
+    pip install maturin
+    maturin develop --extras test --features=$profile --no-default-features
+    pytest tests
+    deactivate
+  )
+}
+
+# Bounds the stack one command takes on the embedded target, where the
+# instance and the arena live in static memory. cargo-call-stack reports
+# "max >= N" when it can't bound a call, which fails the check too.
+function check_stack_usage() {
+  profile=$1
+  budget=16384
+
+  ( cd examples/embedded
+    cargo +nightly-2023-11-16 call-stack --bin dpe-embedded-example --target thumbv7em-none-eabihf \
+      --features=$profile,no-large-stack --no-default-features dpe_handle_command > /tmp/dpe-call-stack.dot
+  )
+  usage=$(sed -n 's/.*label="dpe_handle_command\\nmax = \([0-9]*\).*/\1/p' /tmp/dpe-call-stack.dot)
+  if [ -z "$usage" ] || [ "$usage" -gt "$budget" ]; then
+    echo "dpe_handle_command stack usage ${usage:-unbounded} exceeds $budget bytes"
+    exit 1
+  fi
 }
 
 # TODO: Support building the simulator for different profiles
@@ -68,18 +122,35 @@ function run_verification_tests() {
 format_rust_targets
 format_go_targets
 
+rustup toolchain install nightly-2023-11-16 --component rust-src --target thumbv7em-none-eabihf
+# cargo-call-stack parses the LLVM IR of one nightly, so pin the release that
+# matches the toolchain above and its dependencies.
+cargo +nightly-2023-11-16 install cargo-call-stack --version 0.1.15 --locked
+
 # Run tests for P256 profile
 build_rust_targets dpe_profile_p256_sha256
 test_rust_targets dpe_profile_p256_sha256
+check_stack_usage dpe_profile_p256_sha256
 run_verification_tests dpe_profile_p256_sha256 openssl
 run_verification_tests dpe_profile_p256_sha256 rustcrypto
 
 # Run tests for P384 profile
 build_rust_targets dpe_profile_p384_sha384
 test_rust_targets dpe_profile_p384_sha384
+check_stack_usage dpe_profile_p384_sha384
 run_verification_tests dpe_profile_p384_sha384 openssl
 run_verification_tests dpe_profile_p384_sha384 rustcrypto
 
+# Run tests for P521 profile. The Go client does not support it yet.
+build_rust_targets dpe_profile_p521_sha512
+test_rust_targets dpe_profile_p521_sha512
+check_stack_usage dpe_profile_p521_sha512
+
+# Run tests for the ML-DSA-87 profile. Only the dpe crate supports it so far.
+cargo build --release --manifest-path dpe/Cargo.toml --features=dpe_profile_mldsa87_sha384 --no-default-features
+cargo clippy --manifest-path dpe/Cargo.toml --features=dpe_profile_mldsa87_sha384 --no-default-features -- --deny=warnings
+cargo test --manifest-path dpe/Cargo.toml --features=dpe_profile_mldsa87_sha384 --no-default-features -- --test-threads=1
+
 # Build fuzz target
 ( cd dpe/fuzz
   rustup toolchain install nightly-2023-11-16
@@ -0,0 +1,48 @@
+/*++
+Licensed under the Apache-2.0 license.
+
+Abstract:
+    Conversion of derived public keys to COSE_Key.
+--*/
+use crate::ClientError;
+use crypto::{CryptoBuf, EcdsaPub};
+use dpe::{cwt::CwtWriter, response::CertifyKeyResp};
+
+/// Encodes the raw public key returned by CertifyKey as an EC2 COSE_Key
+/// (RFC 9053), the same encoding DPE puts in CWTs and attestation tokens.
+///
+/// This is what CertifyKey returns in place of a certificate with
+/// `CertifyKeyCmd::FORMAT_COSE_KEY`, but works whatever format was requested.
+pub fn cose_key(resp: &CertifyKeyResp) -> Result<Vec<u8>, ClientError> {
+    let pub_key = EcdsaPub {
+        x: CryptoBuf::new(&resp.derived_pubkey_x).map_err(|_| ClientError::MalformedResponse)?,
+        y: CryptoBuf::new(&resp.derived_pubkey_y).map_err(|_| ClientError::MalformedResponse)?,
+    };
+    let mut cose_key = vec![0u8; CwtWriter::COSE_KEY_SIZE];
+    CwtWriter::new(&mut cose_key)
+        .encode_ec2_cose_key(&pub_key)
+        .map_err(|_| ClientError::MalformedResponse)?;
+    Ok(cose_key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dpe::DPE_PROFILE;
+    use zerocopy::FromBytes;
+
+    #[test]
+    fn test_cose_key() {
+        let mut resp = CertifyKeyResp::new_zeroed();
+        resp.derived_pubkey_x = [0xAA; DPE_PROFILE.get_ecc_int_size()];
+        resp.derived_pubkey_y = [0xBB; DPE_PROFILE.get_ecc_int_size()];
+        let cose_key = cose_key(&resp).unwrap();
+        assert_eq!(cose_key.len(), CwtWriter::COSE_KEY_SIZE);
+
+        // A map of five entries, starting with kty: EC2.
+        assert_eq!(cose_key[..3], [0xA5, 0x01, 0x02]);
+        // y is the last entry, a byte string of the coordinate.
+        let y_start = cose_key.len() - DPE_PROFILE.get_ecc_int_size();
+        assert!(cose_key[y_start..].iter().all(|&b| b == 0xBB));
+    }
+}
@@ -0,0 +1,720 @@
+/*++
+Licensed under the Apache-2.0 license.
+
+Abstract:
+    Client library for sending commands to a DPE over a transport.
+--*/
+pub use transport::{
+    InProcessTransport, MctpTransport, TcpTransport, Transport, UnixTransport,
+    SIMULATOR_SOCKET_PATH,
+};
+
+use crypto::{CryptoBuf, EcdsaSig, EcdsaSigFormat};
+use dpe::{
+    commands::{
+        AttestCmd, CertifyKeyCmd, CloseSessionCmd, Command, CommandHdr, DeriveContextCmd,
+        DestroyCtxCmd, ExportCdiCmd, ExtendTciCmd, GetCertificateChainCmd, GetCertificateChunkCmd,
+        GetCommandCounterCmd, GetContextInfoCmd, GetTaggedTciCmd, HashFinalCmd, HashUpdateCmd,
+        InitCtxCmd, OpenSessionCmd, RotateCtxCmd, RotateCtxFlags, SealCmd, SealFlags, SignCmd,
+        TagTciCmd, UnsealCmd,
+    },
+    context::ContextHandle,
+    response::{
+        AttestResp, CertifyKeyResp, DeriveContextResp, DpeErrorCode, ExportCdiResp,
+        GetCertificateChainResp, GetCommandCounterResp, GetContextInfoResp, GetProfileResp,
+        GetTaggedTciResp, HashFinalResp, HashInitializeResp, NewHandleResp, OpenSessionResp,
+        ResponseHdr, SealResp, SignResp, UnsealResp,
+    },
+    wire::WireEndian,
+    DPE_PROFILE,
+};
+use platform::{mctp::MctpError, PlatformError, MAX_CHUNK_SIZE};
+use zerocopy::{AsBytes, FromBytes};
+
+pub mod cose;
+pub mod pem;
+pub mod transport;
+pub mod verify;
+
+#[derive(Debug)]
+pub enum ClientError {
+    /// The transport failed to deliver the command or read the response.
+    Io(std::io::Error),
+    /// The DPE completed the command with a non-zero status.
+    Dpe(u32),
+    /// The response was truncated or did not carry the DPE response magic.
+    MalformedResponse,
+    /// The MCTP binding could not build or reassemble a message.
+    Mctp(MctpError),
+}
+
+impl From<std::io::Error> for ClientError {
+    fn from(err: std::io::Error) -> Self {
+        ClientError::Io(err)
+    }
+}
+
+impl From<MctpError> for ClientError {
+    fn from(err: MctpError) -> Self {
+        ClientError::Mctp(err)
+    }
+}
+
+impl ClientError {
+    /// Returns whether this is the DPE status for `code`.
+    pub fn is_dpe_error(&self, code: DpeErrorCode) -> bool {
+        matches!(self, ClientError::Dpe(status) if *status == code.get_error_code())
+    }
+}
+
+/// Sends typed DPE commands from a single locality and parses the responses.
+pub struct DpeClient<T: Transport> {
+    transport: T,
+    locality: u32,
+}
+
+impl<T: Transport> DpeClient<T> {
+    pub fn new(transport: T, locality: u32) -> DpeClient<T> {
+        DpeClient {
+            transport,
+            locality,
+        }
+    }
+
+    pub fn locality(&self) -> u32 {
+        self.locality
+    }
+
+    pub fn set_locality(&mut self, locality: u32) {
+        self.locality = locality;
+    }
+
+    pub fn transport(&mut self) -> &mut T {
+        &mut self.transport
+    }
+
+    pub fn get_profile(&mut self) -> Result<GetProfileResp, ClientError> {
+        self.execute_raw(Command::GET_PROFILE, &[])
+    }
+
+    pub fn initialize_context(&mut self, cmd: &InitCtxCmd) -> Result<NewHandleResp, ClientError> {
+        self.execute(Command::INITIALIZE_CONTEXT, cmd)
+    }
+
+    /// Initializes a simulation context whose keys are derived from `seed`.
+    /// `cmd` must set `InitCtxCmd::SEED_FLAG_MASK`.
+    pub fn initialize_context_with_seed(
+        &mut self,
+        cmd: &InitCtxCmd,
+        seed: &[u8; DPE_PROFILE.get_hash_size()],
+    ) -> Result<NewHandleResp, ClientError> {
+        let mut body = wire_bytes(cmd);
+        body.extend_from_slice(seed);
+        self.execute_raw(Command::INITIALIZE_CONTEXT, &body)
+    }
+
+    pub fn derive_context(
+        &mut self,
+        cmd: &DeriveContextCmd,
+    ) -> Result<DeriveContextResp, ClientError> {
+        self.execute(Command::DERIVE_CONTEXT, cmd)
+    }
+
+    /// Derives a context that records `svn`. `cmd` must set
+    /// `DeriveContextFlags::INPUT_SVN`.
+    pub fn derive_context_with_svn(
+        &mut self,
+        cmd: &DeriveContextCmd,
+        svn: u32,
+    ) -> Result<DeriveContextResp, ClientError> {
+        let mut body = wire_bytes(cmd);
+        body.extend_from_slice(&svn.to_le_bytes());
+        self.execute_raw(Command::DERIVE_CONTEXT, &body)
+    }
+
+    pub fn certify_key(&mut self, cmd: &CertifyKeyCmd) -> Result<CertifyKeyResp, ClientError> {
+        self.execute(Command::CERTIFY_KEY, cmd)
+    }
+
+    pub fn get_certificate_chunk(
+        &mut self,
+        cmd: &GetCertificateChunkCmd,
+    ) -> Result<GetCertificateChainResp, ClientError> {
+        self.execute(Command::GET_CERTIFICATE_CHUNK, cmd)
+    }
+
+    /// Reads the `total_size` byte certificate produced by the last
+    /// CertifyKey.
+    pub fn read_certificate(&mut self, total_size: u32) -> Result<Vec<u8>, ClientError> {
+        let mut cert = Vec::new();
+        while cert.len() < total_size as usize {
+            let resp = self.get_certificate_chunk(&GetCertificateChunkCmd {
+                offset: cert.len() as u32,
+                size: MAX_CHUNK_SIZE as u32,
+            })?;
+            let chunk = resp
+                .certificate_chain
+                .get(..resp.certificate_size as usize)
+                .filter(|chunk| !chunk.is_empty())
+                .ok_or(ClientError::MalformedResponse)?;
+            cert.extend_from_slice(chunk);
+        }
+        Ok(cert)
+    }
+
+    pub fn sign(&mut self, cmd: &SignCmd) -> Result<SignResp, ClientError> {
+        self.execute(Command::SIGN, cmd)
+    }
+
+    /// Signs `message`, which the DPE hashes itself. `cmd` must set
+    /// `SignFlags::IS_MESSAGE`; its `digest` is ignored.
+    pub fn sign_message(&mut self, cmd: &SignCmd, message: &[u8]) -> Result<SignResp, ClientError> {
+        let mut body = wire_bytes(cmd);
+        body.extend_from_slice(&(message.len() as u32).to_le_bytes());
+        body.extend_from_slice(message);
+        self.execute_raw(Command::SIGN, &body)
+    }
+
+    pub fn rotate_context_handle(
+        &mut self,
+        cmd: &RotateCtxCmd,
+    ) -> Result<NewHandleResp, ClientError> {
+        self.execute(Command::ROTATE_CONTEXT_HANDLE, cmd)
+    }
+
+    /// Moves the default context to a random handle, so that other callers in
+    /// the locality can't use it, and returns that handle.
+    pub fn protect_default_context(&mut self) -> Result<ContextHandle, ClientError> {
+        let resp = self.rotate_context_handle(&RotateCtxCmd {
+            handle: ContextHandle::default(),
+            flags: RotateCtxFlags::empty(),
+        })?;
+        Ok(resp.handle)
+    }
+
+    /// Makes the context at `handle` the default context again. Fails with
+    /// `InvalidArgument` if the locality has gained a default context or
+    /// another context on a random handle since it was protected.
+    pub fn restore_default_context(&mut self, handle: ContextHandle) -> Result<(), ClientError> {
+        self.rotate_context_handle(&RotateCtxCmd {
+            handle,
+            flags: RotateCtxFlags::TARGET_IS_DEFAULT,
+        })?;
+        Ok(())
+    }
+
+    pub fn destroy_context(&mut self, cmd: &DestroyCtxCmd) -> Result<ResponseHdr, ClientError> {
+        self.execute(Command::DESTROY_CONTEXT, cmd)
+    }
+
+    pub fn get_certificate_chain(
+        &mut self,
+        cmd: &GetCertificateChainCmd,
+    ) -> Result<GetCertificateChainResp, ClientError> {
+        self.execute(Command::GET_CERTIFICATE_CHAIN, cmd)
+    }
+
+    /// Reads the whole certificate chain by requesting chunks until a short one is returned.
+    ///
+    /// When the length of the chain is a multiple of the chunk size, the read
+    /// after the last full chunk returns nothing or fails because the offset
+    /// is past the end. Either one ends the chain.
+    pub fn read_certificate_chain(&mut self) -> Result<Vec<u8>, ClientError> {
+        let past_end = [
+            DpeErrorCode::InvalidArgument,
+            DpeErrorCode::Platform(PlatformError::CertificateChainError),
+        ];
+        let mut chain = Vec::new();
+        loop {
+            let resp = match self.get_certificate_chain(&GetCertificateChainCmd {
+                offset: chain.len() as u32,
+                size: MAX_CHUNK_SIZE as u32,
+            }) {
+                Ok(resp) => resp,
+                Err(ClientError::Dpe(status))
+                    if !chain.is_empty()
+                        && past_end.iter().any(|e| e.get_error_code() == status) =>
+                {
+                    return Ok(chain);
+                }
+                Err(e) => return Err(e),
+            };
+            let chunk = resp
+                .certificate_chain
+                .get(..resp.certificate_size as usize)
+                .ok_or(ClientError::MalformedResponse)?;
+            chain.extend_from_slice(chunk);
+            if chunk.len() < MAX_CHUNK_SIZE {
+                return Ok(chain);
+            }
+        }
+    }
+
+    /// Reads the whole certificate chain and converts it to concatenated PEM
+    /// certificates.
+    pub fn read_certificate_chain_pem(&mut self) -> Result<String, ClientError> {
+        pem::der_chain_to_pem(&self.read_certificate_chain()?)
+    }
+
+    pub fn extend_tci(&mut self, cmd: &ExtendTciCmd) -> Result<NewHandleResp, ClientError> {
+        self.execute(Command::EXTEND_TCI, cmd)
+    }
+
+    pub fn tag_tci(&mut self, cmd: &TagTciCmd) -> Result<NewHandleResp, ClientError> {
+        self.execute(Command::TAG_TCI, cmd)
+    }
+
+    pub fn get_tagged_tci(
+        &mut self,
+        cmd: &GetTaggedTciCmd,
+    ) -> Result<GetTaggedTciResp, ClientError> {
+        self.execute(Command::GET_TAGGED_TCI, cmd)
+    }
+
+    pub fn open_session(&mut self, cmd: &OpenSessionCmd) -> Result<OpenSessionResp, ClientError> {
+        self.execute(Command::OPEN_SESSION, cmd)
+    }
+
+    pub fn close_session(&mut self, cmd: &CloseSessionCmd) -> Result<ResponseHdr, ClientError> {
+        self.execute(Command::CLOSE_SESSION, cmd)
+    }
+
+    pub fn export_cdi(&mut self, cmd: &ExportCdiCmd) -> Result<ExportCdiResp, ClientError> {
+        self.execute(Command::EXPORT_CDI, cmd)
+    }
+
+    pub fn get_command_counter(
+        &mut self,
+        cmd: &GetCommandCounterCmd,
+    ) -> Result<GetCommandCounterResp, ClientError> {
+        self.execute(Command::GET_COMMAND_COUNTER, cmd)
+    }
+
+    pub fn hash_initialize(&mut self) -> Result<HashInitializeResp, ClientError> {
+        self.execute_raw(Command::HASH_INITIALIZE, &[])
+    }
+
+    /// Adds `data` to the hash `stream_id` started with `hash_initialize`.
+    pub fn hash_update(&mut self, stream_id: u32, data: &[u8]) -> Result<ResponseHdr, ClientError> {
+        let mut body = wire_bytes(&HashUpdateCmd {
+            stream_id,
+            size: data.len() as u32,
+        });
+        body.extend_from_slice(data);
+        self.execute_raw(Command::HASH_UPDATE, &body)
+    }
+
+    pub fn hash_final(&mut self, cmd: &HashFinalCmd) -> Result<HashFinalResp, ClientError> {
+        self.execute(Command::HASH_FINAL, cmd)
+    }
+
+    pub fn get_context_info(
+        &mut self,
+        cmd: &GetContextInfoCmd,
+    ) -> Result<GetContextInfoResp, ClientError> {
+        self.execute(Command::GET_CONTEXT_INFO, cmd)
+    }
+
+    /// Requests an attestation token. The full token can be read with
+    /// `read_certificate(resp.token_size)`.
+    pub fn attest(&mut self, cmd: &AttestCmd) -> Result<AttestResp, ClientError> {
+        self.execute(Command::ATTEST, cmd)
+    }
+
+    /// Seals `data` to the context `handle` names, under the key derived
+    /// with `label`.
+    pub fn seal(
+        &mut self,
+        handle: ContextHandle,
+        label: &[u8; DPE_PROFILE.get_hash_size()],
+        data: &[u8],
+    ) -> Result<SealResp, ClientError> {
+        self.send_seal(
+            SealCmd {
+                handle,
+                label: *label,
+                flags: SealFlags::empty(),
+                tci: [0; DPE_PROFILE.get_tci_size()],
+                size: data.len() as u32,
+            },
+            data,
+        )
+    }
+
+    /// Seals `data` so that only a child of the context `handle` names can
+    /// unseal it, once its cumulative TCI is `tci`.
+    pub fn seal_to_tci(
+        &mut self,
+        handle: ContextHandle,
+        label: &[u8; DPE_PROFILE.get_hash_size()],
+        tci: &[u8; DPE_PROFILE.get_tci_size()],
+        data: &[u8],
+    ) -> Result<SealResp, ClientError> {
+        self.send_seal(
+            SealCmd {
+                handle,
+                label: *label,
+                flags: SealFlags::EXPECTED_TCI,
+                tci: *tci,
+                size: data.len() as u32,
+            },
+            data,
+        )
+    }
+
+    fn send_seal(&mut self, cmd: SealCmd, data: &[u8]) -> Result<SealResp, ClientError> {
+        let mut body = wire_bytes(&cmd);
+        body.extend_from_slice(data);
+        self.execute_raw(Command::SEAL, &body)
+    }
+
+    /// Unseals data returned by `seal`. `sealed` is the first `sealed_size`
+    /// bytes of `SealResp::sealed`.
+    pub fn unseal(
+        &mut self,
+        handle: ContextHandle,
+        label: &[u8; DPE_PROFILE.get_hash_size()],
+        sealed: &[u8],
+    ) -> Result<UnsealResp, ClientError> {
+        let mut body = wire_bytes(&UnsealCmd {
+            handle,
+            label: *label,
+            size: sealed.len() as u32,
+        });
+        body.extend_from_slice(sealed);
+        self.execute_raw(Command::UNSEAL, &body)
+    }
+
+    /// Sends `cmd` and parses the response as `R`.
+    fn execute<C, R>(&mut self, cmd_id: u32, cmd: &C) -> Result<R, ClientError>
+    where
+        C: AsBytes + FromBytes + WireEndian,
+        R: FromBytes + WireEndian,
+    {
+        self.execute_raw(cmd_id, &wire_bytes(cmd))
+    }
+
+    /// Sends a command whose body is already serialized and parses the
+    /// response as `R`.
+    ///
+    /// Responses with a non-zero status are returned as `ClientError::Dpe`
+    /// since they only carry a response header.
+    fn execute_raw<R: FromBytes + WireEndian>(
+        &mut self,
+        cmd_id: u32,
+        body: &[u8],
+    ) -> Result<R, ClientError> {
+        let mut cmd = wire_bytes(&CommandHdr::new(cmd_id));
+        cmd.extend_from_slice(body);
+
+        let resp = self.transport.send(self.locality, &cmd)?;
+        let hdr = ResponseHdr::read_from_prefix(resp.as_slice())
+            .map(ResponseHdr::from_le)
+            .ok_or(ClientError::MalformedResponse)?;
+        if hdr.magic != ResponseHdr::DPE_RESPONSE_MAGIC {
+            return Err(ClientError::MalformedResponse);
+        }
+        if hdr.status != DpeErrorCode::NoError.get_error_code() {
+            return Err(ClientError::Dpe(hdr.status));
+        }
+        R::read_from_prefix(resp.as_slice())
+            .map(R::from_le)
+            .ok_or(ClientError::MalformedResponse)
+    }
+}
+
+/// Encodes the ECDSA signature in a Sign response as a DER ECDSA-Sig-Value,
+/// the form most verifiers expect.
+///
+/// The response carries `r` and `s` as fixed-width integers. Symmetric
+/// signatures have no DER form.
+pub fn signature_der(resp: &SignResp) -> Result<Vec<u8>, ClientError> {
+    let sig = EcdsaSig {
+        r: CryptoBuf::new(&resp.sig_r_or_hmac).map_err(|_| ClientError::MalformedResponse)?,
+        s: CryptoBuf::new(&resp.sig_s).map_err(|_| ClientError::MalformedResponse)?,
+    };
+    let mut der = vec![0; EcdsaSig::MAX_SERIALIZED_SIZE];
+    let len = sig
+        .serialize(EcdsaSigFormat::Der, &mut der)
+        .map_err(|_| ClientError::MalformedResponse)?;
+    der.truncate(len);
+    Ok(der)
+}
+
+/// Returns `value` serialized in wire byte order.
+fn wire_bytes<T: AsBytes + FromBytes + WireEndian>(value: &T) -> Vec<u8> {
+    // Copy the value since converting it consumes it.
+    T::read_from(value.as_bytes())
+        .map(T::to_le)
+        .map_or_else(Vec::new, |value| value.as_bytes().to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crypto::{Crypto, Digest, EcdsaPub, OpensslCrypto};
+    use dpe::{
+        commands::{CertifyKeyFlags, DeriveContextFlags, SignFlags},
+        dpe_instance::{DpeEnv, DpeTypes},
+        support::Support,
+        DpeInstance, DPE_PROFILE,
+    };
+    use platform::default::{DefaultPlatform, AUTO_INIT_LOCALITY, TEST_CERT_CHAIN, TEST_CERT_PEM};
+
+    struct TestTypes;
+
+    impl DpeTypes for TestTypes {
+        type Crypto<'a> = OpensslCrypto;
+        type Platform<'a> = DefaultPlatform;
+    }
+
+    fn new_client(support: Support) -> DpeClient<InProcessTransport<'static, TestTypes>> {
+        let mut env = DpeEnv::<TestTypes> {
+            crypto: OpensslCrypto::new(),
+            platform: DefaultPlatform,
+            arena: &mut [],
+        };
+        let dpe = DpeInstance::new(&mut env, support).unwrap();
+        DpeClient::new(InProcessTransport::new(dpe, env), AUTO_INIT_LOCALITY)
+    }
+
+    #[test]
+    fn test_get_profile() {
+        let mut client = new_client(Support::AUTO_INIT | Support::X509);
+        let profile = client.get_profile().unwrap();
+        assert_eq!(profile.resp_hdr.profile, DPE_PROFILE as u32);
+        assert_eq!(profile.flags, (Support::AUTO_INIT | Support::X509).bits());
+
+        // The flags decode back to the configuration the instance was built with.
+        let support = Support::AUTO_INIT | Support::SESSIONS | Support::EXTEND_TCI;
+        let profile = new_client(support).get_profile().unwrap();
+        assert_eq!(profile.support(), support);
+    }
+
+    #[test]
+    fn test_context_lifecycle() {
+        let mut client = new_client(Support::X509 | Support::ROTATE_CONTEXT);
+        client
+            .initialize_context(&InitCtxCmd::new_use_default())
+            .unwrap();
+
+        let derived = client
+            .derive_context(&DeriveContextCmd {
+                handle: ContextHandle::default(),
+                data: [1; DPE_PROFILE.get_tci_size()],
+                flags: DeriveContextFlags::MAKE_DEFAULT | DeriveContextFlags::INPUT_ALLOW_X509,
+                tci_type: 0,
+                target_locality: AUTO_INIT_LOCALITY,
+            })
+            .unwrap();
+        assert_eq!(derived.handle, ContextHandle::default());
+
+        let certified = client
+            .certify_key(&CertifyKeyCmd {
+                handle: ContextHandle::default(),
+                flags: CertifyKeyFlags::empty(),
+                label: [0; DPE_PROFILE.get_hash_size()],
+                format: CertifyKeyCmd::FORMAT_X509,
+            })
+            .unwrap();
+        assert_ne!(certified.cert_size, 0);
+        assert_eq!(certified.cert_size, certified.total_size);
+        assert_eq!(
+            client.read_certificate(certified.total_size).unwrap(),
+            &certified.cert[..certified.cert_size as usize]
+        );
+
+        client
+            .sign(&SignCmd {
+                handle: ContextHandle::default(),
+                label: [0; DPE_PROFILE.get_hash_size()],
+                flags: SignFlags::empty(),
+                digest: [0; DPE_PROFILE.get_hash_size()],
+            })
+            .unwrap();
+
+        let rotated = client
+            .rotate_context_handle(&RotateCtxCmd {
+                handle: ContextHandle::default(),
+                flags: RotateCtxFlags::empty(),
+            })
+            .unwrap();
+        assert_ne!(rotated.handle, ContextHandle::default());
+
+        client
+            .destroy_context(&DestroyCtxCmd {
+                handle: rotated.handle,
+            })
+            .unwrap();
+    }
+
+    #[test]
+    fn test_protect_default_context() {
+        let mut client = new_client(Support::AUTO_INIT | Support::ROTATE_CONTEXT);
+        let handle = client.protect_default_context().unwrap();
+        assert_ne!(handle, ContextHandle::default());
+
+        // There is no default context left to protect.
+        let err = client.protect_default_context().unwrap_err();
+        assert!(err.is_dpe_error(DpeErrorCode::InvalidHandle));
+
+        client.restore_default_context(handle).unwrap();
+        let err = client.restore_default_context(handle).unwrap_err();
+        assert!(err.is_dpe_error(DpeErrorCode::InvalidHandle));
+        client.protect_default_context().unwrap();
+    }
+
+    #[test]
+    fn test_error_status() {
+        let mut client = new_client(Support::AUTO_INIT);
+
+        // A second default context cannot be initialized.
+        let err = client
+            .initialize_context(&InitCtxCmd::new_use_default())
+            .unwrap_err();
+        assert!(err.is_dpe_error(DpeErrorCode::ArgumentNotSupported));
+
+        // Unsupported commands are reported with their status.
+        let err = client.tag_tci(&TagTciCmd {
+            handle: ContextHandle::default(),
+            tag: 0,
+        });
+        assert!(err.unwrap_err().is_dpe_error(DpeErrorCode::InvalidCommand));
+    }
+
+    #[test]
+    fn test_signature_der() {
+        let mut client = new_client(Support::AUTO_INIT | Support::X509);
+        let certified = client
+            .certify_key(&CertifyKeyCmd {
+                handle: ContextHandle::default(),
+                flags: CertifyKeyFlags::empty(),
+                label: [0; DPE_PROFILE.get_hash_size()],
+                format: CertifyKeyCmd::FORMAT_X509,
+            })
+            .unwrap();
+        let digest = [0x5a; DPE_PROFILE.get_hash_size()];
+        let resp = client
+            .sign(&SignCmd {
+                handle: ContextHandle::default(),
+                label: [0; DPE_PROFILE.get_hash_size()],
+                flags: SignFlags::empty(),
+                digest,
+            })
+            .unwrap();
+
+        let der = signature_der(&resp).unwrap();
+        let algs = DPE_PROFILE.alg_len();
+        let sig = EcdsaSig::deserialize(algs, EcdsaSigFormat::Der, &der).unwrap();
+        assert_eq!(sig.r.bytes(), resp.sig_r_or_hmac);
+        assert_eq!(sig.s.bytes(), resp.sig_s);
+
+        let pub_key = EcdsaPub {
+            x: CryptoBuf::new(&certified.derived_pubkey_x).unwrap(),
+            y: CryptoBuf::new(&certified.derived_pubkey_y).unwrap(),
+        };
+        let digest = Digest::new(&digest).unwrap();
+        assert_eq!(
+            OpensslCrypto::new().ecdsa_verify(algs, &digest, &pub_key, &sig),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn test_read_certificate_chain() {
+        let mut client = new_client(Support::AUTO_INIT);
+        assert_eq!(client.read_certificate_chain().unwrap(), TEST_CERT_CHAIN);
+        assert_eq!(
+            client.read_certificate_chain_pem().unwrap().as_bytes(),
+            TEST_CERT_PEM
+        );
+    }
+
+    /// Serves a certificate chain of `len` bytes, the way a DPE does.
+    struct ChainTransport {
+        len: usize,
+        /// Whether reading at the end returns an empty chunk rather than
+        /// `InvalidArgument`.
+        empty_at_end: bool,
+    }
+
+    impl Transport for ChainTransport {
+        fn send(&mut self, _locality: u32, cmd: &[u8]) -> Result<Vec<u8>, ClientError> {
+            let cmd = GetCertificateChainCmd::read_from_prefix(
+                &cmd[core::mem::size_of::<CommandHdr>()..],
+            )
+            .unwrap()
+            .from_le();
+            let offset = cmd.offset as usize;
+            if offset > self.len || (offset == self.len && !self.empty_at_end) {
+                return Ok(wire_bytes(&ResponseHdr::new(DpeErrorCode::InvalidArgument)));
+            }
+            let size = (self.len - offset).min(cmd.size as usize);
+            let mut resp = GetCertificateChainResp {
+                resp_hdr: ResponseHdr::new(DpeErrorCode::NoError),
+                certificate_size: size as u32,
+                certificate_chain: [0; MAX_CHUNK_SIZE],
+            };
+            resp.certificate_chain[..size].fill(0xAA);
+            Ok(wire_bytes(&resp))
+        }
+    }
+
+    #[test]
+    fn test_read_certificate_chain_chunk_multiple() {
+        for empty_at_end in [false, true] {
+            for len in [1, MAX_CHUNK_SIZE - 1, MAX_CHUNK_SIZE, 2 * MAX_CHUNK_SIZE] {
+                let mut client =
+                    DpeClient::new(ChainTransport { len, empty_at_end }, AUTO_INIT_LOCALITY);
+                assert_eq!(client.read_certificate_chain().unwrap(), vec![0xAA; len]);
+            }
+        }
+
+        // An empty chain is an error rather than the end of the chain.
+        let mut client = DpeClient::new(
+            ChainTransport {
+                len: 0,
+                empty_at_end: false,
+            },
+            AUTO_INIT_LOCALITY,
+        );
+        assert!(matches!(
+            client.read_certificate_chain(),
+            Err(ClientError::Dpe(_))
+        ));
+    }
+
+    #[test]
+    fn test_verify_chain() {
+        let mut client = new_client(Support::AUTO_INIT | Support::X509);
+        let chain = client.read_certificate_chain().unwrap();
+        let resp = client
+            .certify_key(&CertifyKeyCmd {
+                handle: ContextHandle::default(),
+                flags: CertifyKeyFlags::empty(),
+                label: [0; DPE_PROFILE.get_hash_size()],
+                format: CertifyKeyCmd::FORMAT_X509,
+            })
+            .unwrap();
+        let mut leaf = client.read_certificate(resp.total_size).unwrap();
+
+        let report = verify::verify_chain(&chain, &leaf).unwrap();
+        assert!(report.is_valid(), "{report:?}");
+        assert_eq!(report.certs.len(), 2);
+        assert!(report.certs[0].signature_checked);
+        assert!(report.certs[0].is_ca);
+        assert!(report.leaf().has_tcb_info);
+        assert!(report.leaf().has_ueid);
+
+        // A leaf with a corrupted signature is reported, not rejected.
+        *leaf.last_mut().unwrap() ^= 1;
+        let report = verify::verify_chain(&chain, &leaf).unwrap();
+        assert!(!report.is_valid());
+        assert_eq!(report.leaf().problems, [verify::ChainProblem::BadSignature]);
+
+        assert!(matches!(
+            verify::verify_chain(&chain[1..], &leaf),
+            Err(ClientError::MalformedResponse)
+        ));
+    }
+}
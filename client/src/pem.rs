@@ -0,0 +1,134 @@
+/*++
+Licensed under the Apache-2.0 license.
+
+Abstract:
+    Conversion of DER certificate chains to PEM.
+--*/
+use crate::ClientError;
+use core::cmp::min;
+
+const BASE64_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Number of base64 characters per line, as written by OpenSSL.
+const LINE_LEN: usize = 64;
+
+/// Converts a chain of concatenated DER certificates, as returned by
+/// GetCertificateChain, to concatenated PEM certificates in the same order.
+///
+/// The result can be handed to `openssl verify` or a TLS stack as is.
+/// Returns `ClientError::MalformedResponse` if the chain does not split into
+/// whole DER SEQUENCEs.
+pub fn der_chain_to_pem(mut chain: &[u8]) -> Result<String, ClientError> {
+    let mut pem = String::new();
+    while !chain.is_empty() {
+        let len = der_sequence_len(chain).ok_or(ClientError::MalformedResponse)?;
+        let (cert, rest) = chain.split_at(len);
+        pem.push_str("-----BEGIN CERTIFICATE-----\n");
+        let encoded = base64_encode(cert);
+        let mut line_start = 0;
+        while line_start < encoded.len() {
+            let line_end = min(line_start + LINE_LEN, encoded.len());
+            pem.push_str(&encoded[line_start..line_end]);
+            pem.push('\n');
+            line_start = line_end;
+        }
+        pem.push_str("-----END CERTIFICATE-----\n");
+        chain = rest;
+    }
+    Ok(pem)
+}
+
+/// Returns the encoded length of the DER SEQUENCE at the start of `der`, or
+/// `None` if `der` does not start with a complete one.
+fn der_sequence_len(der: &[u8]) -> Option<usize> {
+    const SEQUENCE: u8 = 0x30;
+
+    let (&tag, rest) = der.split_first()?;
+    let (&first, rest) = rest.split_first()?;
+    if tag != SEQUENCE {
+        return None;
+    }
+    let (header_len, content_len) = if first < 0x80 {
+        (2, usize::from(first))
+    } else {
+        let num_bytes = usize::from(first & 0x7f);
+        if num_bytes == 0 || num_bytes > 4 {
+            return None;
+        }
+        let len = rest
+            .get(..num_bytes)?
+            .iter()
+            .fold(0usize, |len, byte| (len << 8) | usize::from(*byte));
+        (2 + num_bytes, len)
+    };
+    let len = header_len.checked_add(content_len)?;
+    (len <= der.len()).then_some(len)
+}
+
+/// Encodes `bytes` as padded base64 with the standard alphabet.
+fn base64_encode(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity((bytes.len() + 2) / 3 * 4);
+    for chunk in bytes.chunks(3) {
+        let b = [
+            chunk[0],
+            chunk.get(1).copied().unwrap_or(0),
+            chunk.get(2).copied().unwrap_or(0),
+        ];
+        let n = (u32::from(b[0]) << 16) | (u32::from(b[1]) << 8) | u32::from(b[2]);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                let idx = (n >> (18 - 6 * i)) & 0x3f;
+                out.push(char::from(BASE64_ALPHABET[idx as usize]));
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use platform::default::{TEST_CERT_CHAIN, TEST_CERT_PEM};
+
+    #[test]
+    fn test_base64_encode() {
+        assert_eq!(base64_encode(b""), "");
+        assert_eq!(base64_encode(b"f"), "Zg==");
+        assert_eq!(base64_encode(b"fo"), "Zm8=");
+        assert_eq!(base64_encode(b"foo"), "Zm9v");
+        assert_eq!(base64_encode(b"foobar"), "Zm9vYmFy");
+        assert_eq!(base64_encode(&[0xfb, 0xff]), "+/8=");
+    }
+
+    #[test]
+    fn test_der_chain_to_pem() {
+        // Matches what OpenSSL wrote for the same certificate.
+        let pem = der_chain_to_pem(TEST_CERT_CHAIN).unwrap();
+        assert_eq!(pem.as_bytes(), TEST_CERT_PEM);
+
+        let chain = [TEST_CERT_CHAIN, TEST_CERT_CHAIN].concat();
+        assert_eq!(der_chain_to_pem(&chain).unwrap(), pem.repeat(2));
+        assert_eq!(der_chain_to_pem(&[]).unwrap(), "");
+    }
+
+    #[test]
+    fn test_malformed_chain() {
+        let truncated = &TEST_CERT_CHAIN[..TEST_CERT_CHAIN.len() - 1];
+        assert!(matches!(
+            der_chain_to_pem(truncated),
+            Err(ClientError::MalformedResponse)
+        ));
+        let trailing = [TEST_CERT_CHAIN, &[0x30]].concat();
+        assert!(matches!(
+            der_chain_to_pem(&trailing),
+            Err(ClientError::MalformedResponse)
+        ));
+        assert!(matches!(
+            der_chain_to_pem(&[0x04, 0x00]),
+            Err(ClientError::MalformedResponse)
+        ));
+    }
+}
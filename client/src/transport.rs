@@ -0,0 +1,354 @@
+// Licensed under the Apache-2.0 license
+
+use crate::ClientError;
+use dpe::{
+    dpe_instance::{DpeEnv, DpeTypes},
+    response::Response,
+    DpeInstance,
+};
+use platform::mctp::{
+    Fragmenter, MsgInfo, Reassembler, BASELINE_MTU, MAX_PACKET_SIZE, MSG_TYPE_DPE,
+};
+use std::io::{Error, ErrorKind, Read, Write};
+use std::mem::size_of;
+use std::net::{SocketAddr, TcpStream};
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+
+/// Default path of the Unix socket served by the DPE simulator.
+pub const SIMULATOR_SOCKET_PATH: &str = "/tmp/dpe-sim.socket";
+
+/// Carries serialized commands to a DPE and returns its serialized responses.
+pub trait Transport {
+    /// Sends `cmd` on behalf of `locality` and returns the raw response.
+    fn send(&mut self, locality: u32, cmd: &[u8]) -> Result<Vec<u8>, ClientError>;
+}
+
+/// Largest request the simulator accepts: a locality followed by a command.
+pub const MAX_REQUEST_SIZE: usize = 4096;
+
+/// Writes `parts` as one frame, preceded by their total length as a
+/// little-endian u32. This is the framing used by the simulator.
+pub fn write_frame(stream: &mut impl Write, parts: &[&[u8]]) -> std::io::Result<()> {
+    let len = u32::try_from(parts.iter().map(|part| part.len()).sum::<usize>())
+        .map_err(|_| Error::new(ErrorKind::InvalidInput, "frame too large"))?;
+    stream.write_all(&len.to_le_bytes())?;
+    for part in parts {
+        stream.write_all(part)?;
+    }
+    stream.flush()
+}
+
+/// Reads one frame written by `write_frame`, rejecting frames longer than
+/// `max_len`. Returns None if the peer closed the connection between frames.
+pub fn read_frame(stream: &mut impl Read, max_len: usize) -> std::io::Result<Option<Vec<u8>>> {
+    let mut len = [0u8; 4];
+    match stream.read_exact(&mut len) {
+        Err(err) if err.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+        result => result?,
+    }
+    let len = u32::from_le_bytes(len) as usize;
+    if len > max_len {
+        return Err(Error::new(ErrorKind::InvalidData, "frame too large"));
+    }
+    let mut frame = vec![0u8; len];
+    stream.read_exact(&mut frame)?;
+    Ok(Some(frame))
+}
+
+/// Sends a single locality-prefixed request and reads its response, each in
+/// a frame written by `write_frame`.
+fn exchange(
+    stream: &mut (impl Read + Write),
+    locality: u32,
+    cmd: &[u8],
+) -> std::io::Result<Vec<u8>> {
+    write_frame(stream, &[&locality.to_le_bytes(), cmd])?;
+    read_frame(stream, size_of::<Response>())?.ok_or_else(|| {
+        Error::new(
+            ErrorKind::UnexpectedEof,
+            "connection closed before the response",
+        )
+    })
+}
+
+/// Talks to a DPE over a Unix domain socket, one connection per command.
+pub struct UnixTransport {
+    path: PathBuf,
+}
+
+impl UnixTransport {
+    pub fn new(path: impl Into<PathBuf>) -> UnixTransport {
+        UnixTransport { path: path.into() }
+    }
+}
+
+impl Default for UnixTransport {
+    fn default() -> Self {
+        Self::new(SIMULATOR_SOCKET_PATH)
+    }
+}
+
+impl Transport for UnixTransport {
+    fn send(&mut self, locality: u32, cmd: &[u8]) -> Result<Vec<u8>, ClientError> {
+        let mut stream = UnixStream::connect(&self.path)?;
+        Ok(exchange(&mut stream, locality, cmd)?)
+    }
+}
+
+/// Talks to a DPE over TCP, one connection per command.
+pub struct TcpTransport {
+    addr: SocketAddr,
+}
+
+impl TcpTransport {
+    pub fn new(addr: SocketAddr) -> TcpTransport {
+        TcpTransport { addr }
+    }
+}
+
+impl Transport for TcpTransport {
+    fn send(&mut self, locality: u32, cmd: &[u8]) -> Result<Vec<u8>, ClientError> {
+        let mut stream = TcpStream::connect(self.addr)?;
+        stream.set_nodelay(true)?;
+        Ok(exchange(&mut stream, locality, cmd)?)
+    }
+}
+
+/// Largest MCTP message a response can arrive in, counting the message type.
+const MAX_MCTP_RESPONSE_SIZE: usize = 1 + size_of::<Response>();
+
+/// Writes one MCTP packet, preceded by its length as a little-endian u16.
+/// This is the framing the simulator uses in `--mctp` mode.
+pub fn write_mctp_packet(stream: &mut impl Write, packet: &[u8]) -> std::io::Result<()> {
+    let len = u16::try_from(packet.len())
+        .map_err(|_| Error::new(ErrorKind::InvalidInput, "MCTP packet too large"))?;
+    stream.write_all(&len.to_le_bytes())?;
+    stream.write_all(packet)
+}
+
+/// Reads one packet written by `write_mctp_packet` into `buf`. Returns None if
+/// the peer closed the connection between packets.
+pub fn read_mctp_packet<'a>(
+    stream: &mut impl Read,
+    buf: &'a mut [u8; MAX_PACKET_SIZE],
+) -> std::io::Result<Option<&'a [u8]>> {
+    let mut len = [0u8; 2];
+    match stream.read_exact(&mut len) {
+        Err(err) if err.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+        result => result?,
+    }
+    let packet = buf
+        .get_mut(..u16::from_le_bytes(len) as usize)
+        .ok_or_else(|| Error::new(ErrorKind::InvalidData, "MCTP packet too large"))?;
+    stream.read_exact(packet)?;
+    Ok(Some(packet))
+}
+
+/// Talks to a DPE over MCTP carried on a byte stream, such as the simulator
+/// started with `--mctp`.
+///
+/// The locality is sent as the source EID, so it must fit in a byte.
+pub struct MctpTransport<S: Read + Write> {
+    stream: S,
+    dest_eid: u8,
+    mtu: usize,
+    next_tag: u8,
+    reassembler: Box<Reassembler<MAX_MCTP_RESPONSE_SIZE>>,
+}
+
+impl<S: Read + Write> MctpTransport<S> {
+    /// Sends commands to the endpoint `dest_eid` over `stream` using the
+    /// baseline MTU.
+    pub fn new(stream: S, dest_eid: u8) -> MctpTransport<S> {
+        MctpTransport {
+            stream,
+            dest_eid,
+            mtu: BASELINE_MTU,
+            next_tag: 0,
+            reassembler: Box::default(),
+        }
+    }
+
+    /// Sets the largest packet payload sent to the DPE. Must be between
+    /// `BASELINE_MTU` and `MAX_MTU`.
+    pub fn set_mtu(&mut self, mtu: usize) {
+        self.mtu = mtu;
+    }
+}
+
+impl<S: Read + Write> Transport for MctpTransport<S> {
+    fn send(&mut self, locality: u32, cmd: &[u8]) -> Result<Vec<u8>, ClientError> {
+        let src_eid = u8::try_from(locality).map_err(|_| {
+            Error::new(
+                ErrorKind::InvalidInput,
+                "locality does not fit in an MCTP EID",
+            )
+        })?;
+        let request = MsgInfo {
+            dest_eid: self.dest_eid,
+            src_eid,
+            tag_owner: true,
+            msg_tag: self.next_tag,
+        };
+        self.next_tag = (self.next_tag + 1) % 8;
+
+        let mut buf = [0u8; MAX_PACKET_SIZE];
+        let mut fragmenter = Fragmenter::new(request, self.mtu, MSG_TYPE_DPE, cmd)?;
+        while let Some(len) = fragmenter.next_packet(&mut buf) {
+            write_mctp_packet(&mut self.stream, &buf[..len])?;
+        }
+
+        loop {
+            let packet = read_mctp_packet(&mut self.stream, &mut buf)?.ok_or_else(|| {
+                Error::new(ErrorKind::UnexpectedEof, "connection closed mid-response")
+            })?;
+            if let Some(msg) = self.reassembler.push(packet)? {
+                if msg.info != request.response() || msg.msg_type != MSG_TYPE_DPE {
+                    return Err(ClientError::MalformedResponse);
+                }
+                return Ok(msg.body.to_vec());
+            }
+        }
+    }
+}
+
+/// Executes commands directly against a `DpeInstance` owned by the transport.
+pub struct InProcessTransport<'a, T: DpeTypes + 'a> {
+    pub dpe: DpeInstance,
+    pub env: DpeEnv<'a, T>,
+}
+
+impl<'a, T: DpeTypes + 'a> InProcessTransport<'a, T> {
+    pub fn new(dpe: DpeInstance, env: DpeEnv<'a, T>) -> InProcessTransport<'a, T> {
+        InProcessTransport { dpe, env }
+    }
+}
+
+impl<'a, T: DpeTypes + 'a> Transport for InProcessTransport<'a, T> {
+    fn send(&mut self, locality: u32, cmd: &[u8]) -> Result<Vec<u8>, ClientError> {
+        let response = self
+            .dpe
+            .execute_serialized_command(&mut self.env, locality, cmd)
+            .map_err(|err| ClientError::Dpe(err.get_error_code()))?;
+        Ok(response.as_bytes().to_vec())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::DpeClient;
+    use crypto::OpensslCrypto;
+    use dpe::{
+        commands::{CertifyKeyCmd, CertifyKeyFlags},
+        context::ContextHandle,
+        support::Support,
+        DPE_PROFILE,
+    };
+    use platform::default::{DefaultPlatform, AUTO_INIT_LOCALITY};
+    use std::net::TcpListener;
+    use std::thread;
+
+    struct TestTypes;
+
+    impl DpeTypes for TestTypes {
+        type Crypto<'a> = OpensslCrypto;
+        type Platform<'a> = DefaultPlatform;
+    }
+
+    /// Serves MCTP requests the way the simulator does in `--mctp` mode.
+    fn serve_mctp(mut stream: UnixStream) {
+        let mut env = DpeEnv::<TestTypes> {
+            crypto: OpensslCrypto::new(),
+            platform: DefaultPlatform,
+            arena: &mut [],
+        };
+        let mut dpe = DpeInstance::new(&mut env, Support::AUTO_INIT | Support::X509).unwrap();
+        let mut reassembler = Box::new(Reassembler::<4096>::new());
+        let mut buf = [0u8; MAX_PACKET_SIZE];
+        while let Some(packet) = read_mctp_packet(&mut stream, &mut buf).unwrap() {
+            let Some(msg) = reassembler.push(packet).unwrap() else {
+                continue;
+            };
+            let response = dpe
+                .execute_serialized_command(&mut env, msg.info.src_eid.into(), msg.body)
+                .unwrap();
+            let mut fragmenter = Fragmenter::new(
+                msg.info.response(),
+                BASELINE_MTU,
+                MSG_TYPE_DPE,
+                response.as_bytes(),
+            )
+            .unwrap();
+            let mut out = [0u8; MAX_PACKET_SIZE];
+            while let Some(len) = fragmenter.next_packet(&mut out) {
+                write_mctp_packet(&mut stream, &out[..len]).unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn test_mctp() {
+        let (client_stream, server_stream) = UnixStream::pair().unwrap();
+        let server = thread::spawn(move || serve_mctp(server_stream));
+
+        let mut client = DpeClient::new(MctpTransport::new(client_stream, 8), AUTO_INIT_LOCALITY);
+        let profile = client.get_profile().unwrap();
+        assert_eq!(profile.resp_hdr.profile, DPE_PROFILE as u32);
+
+        // Both the command and the certificate span several packets.
+        let certified = client
+            .certify_key(&CertifyKeyCmd {
+                handle: ContextHandle::default(),
+                flags: CertifyKeyFlags::empty(),
+                label: [0; DPE_PROFILE.get_hash_size()],
+                format: CertifyKeyCmd::FORMAT_X509,
+            })
+            .unwrap();
+        assert_ne!(certified.cert_size, 0);
+
+        // Localities are sent as the source EID.
+        client.set_locality(0x100);
+        assert!(matches!(client.get_profile(), Err(ClientError::Io(_))));
+
+        drop(client);
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_tcp_framing() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let request = read_frame(&mut stream, MAX_REQUEST_SIZE).unwrap().unwrap();
+            write_frame(&mut stream, &[b"response"]).unwrap();
+            // Keep the connection open, so the client can only rely on the
+            // framing to find the end of the response.
+            assert!(read_frame(&mut stream, MAX_REQUEST_SIZE).unwrap().is_none());
+            request
+        });
+
+        let mut transport = TcpTransport::new(addr);
+        let response = transport.send(0x4142_4344, b"cmd").unwrap();
+        assert_eq!(response, b"response");
+        drop(transport);
+
+        let request = server.join().unwrap();
+        assert_eq!(&request[..4], &0x4142_4344u32.to_le_bytes());
+        assert_eq!(&request[4..], b"cmd");
+    }
+
+    #[test]
+    fn test_read_frame() {
+        let mut stream: &[u8] = &[3, 0, 0, 0, b'a', b'b', b'c', 1, 0];
+        assert_eq!(read_frame(&mut stream, 3).unwrap(), Some(b"abc".to_vec()));
+        // A truncated length is a closed connection, a truncated body an error.
+        assert!(read_frame(&mut stream, 3).unwrap().is_none());
+        let mut stream: &[u8] = &[3, 0, 0, 0, b'a'];
+        assert!(read_frame(&mut stream, 3).is_err());
+        let mut stream: &[u8] = &[4, 0, 0, 0, b'a', b'b', b'c', b'd'];
+        assert!(read_frame(&mut stream, 3).is_err());
+    }
+}
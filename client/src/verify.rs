@@ -0,0 +1,167 @@
+/*++
+Licensed under the Apache-2.0 license.
+
+Abstract:
+    Verification of the certificate chains a DPE issues.
+--*/
+use crate::ClientError;
+use openssl::x509::X509;
+use x509_parser::prelude::{FromDer, X509Certificate};
+
+/// tcg-dice-MultiTcbInfo
+const MULTI_TCBINFO_OID: &str = "2.23.133.5.4.5";
+/// tcg-dice-TcbInfo
+const TCBINFO_OID: &str = "2.23.133.5.4.1";
+/// tcg-dice-Ueid
+const UEID_OID: &str = "2.23.133.5.4.4";
+
+/// Something wrong with a certificate in a chain checked by `verify_chain`.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ChainProblem {
+    /// The issuer name is not the subject name of the certificate before it.
+    IssuerMismatch,
+    /// The signature does not verify with the key of the certificate before
+    /// it, or with its own key if it is a self-signed root.
+    BadSignature,
+    /// The certificate issues another one but its BasicConstraints don't
+    /// mark it as a CA.
+    NotCa,
+    /// More CA certificates follow it than its path length constraint allows.
+    PathLenExceeded,
+    /// The leaf has neither a MultiTcbInfo nor a TcbInfo extension.
+    MissingTcbInfo,
+}
+
+/// What `verify_chain` found out about one certificate.
+#[derive(Debug)]
+pub struct CertReport {
+    /// The subject name in its RFC 4514 string form.
+    pub subject: String,
+    /// Whether the signature was checked. It is not for a root certificate
+    /// that isn't self-signed, which is trusted as it is.
+    pub signature_checked: bool,
+    /// Whether BasicConstraints mark it as a CA.
+    pub is_ca: bool,
+    /// Whether it carries a MultiTcbInfo or TcbInfo extension.
+    pub has_tcb_info: bool,
+    /// Whether it carries a Ueid extension.
+    pub has_ueid: bool,
+    pub problems: Vec<ChainProblem>,
+}
+
+/// Outcome of `verify_chain`.
+#[derive(Debug)]
+pub struct ChainReport {
+    /// One report for each certificate, from the root down to the leaf.
+    pub certs: Vec<CertReport>,
+}
+
+impl ChainReport {
+    /// Returns whether no certificate has a problem.
+    pub fn is_valid(&self) -> bool {
+        self.certs.iter().all(|cert| cert.problems.is_empty())
+    }
+
+    /// Returns the report of the leaf certificate.
+    pub fn leaf(&self) -> &CertReport {
+        // verify_chain always reports on the leaf
+        &self.certs[self.certs.len() - 1]
+    }
+}
+
+/// Checks the chain from the first certificate of `chain` down to `leaf`.
+///
+/// `chain` is the concatenated DER certificates returned by
+/// GetCertificateChain, and `leaf` an X.509 certificate returned by
+/// CertifyKey. Each certificate must be issued and signed by the one before
+/// it, and every issuer must be a CA whose path length constraint allows the
+/// CAs below it. The leaf must carry the DICE TcbInfo of the certified
+/// context. The root is trusted, so its signature is only checked if it is
+/// self-signed.
+///
+/// Returns `ClientError::MalformedResponse` if a certificate can't be
+/// parsed. Everything else wrong with the chain is listed in the report.
+pub fn verify_chain(chain: &[u8], leaf: &[u8]) -> Result<ChainReport, ClientError> {
+    let mut ders = Vec::new();
+    let mut rem = chain;
+    while !rem.is_empty() {
+        let (next, _) =
+            X509Certificate::from_der(rem).map_err(|_| ClientError::MalformedResponse)?;
+        ders.push(&rem[..rem.len() - next.len()]);
+        rem = next;
+    }
+    ders.push(leaf);
+
+    let mut parsed = Vec::new();
+    let mut certs = Vec::new();
+    for der in &ders {
+        let (_, cert) =
+            X509Certificate::from_der(der).map_err(|_| ClientError::MalformedResponse)?;
+        parsed.push(cert);
+        certs.push(X509::from_der(der).map_err(|_| ClientError::MalformedResponse)?);
+    }
+
+    let mut reports = Vec::new();
+    for (i, cert) in parsed.iter().enumerate() {
+        let has_extension = |oid: &str| {
+            cert.extensions()
+                .iter()
+                .any(|ext| ext.oid.to_id_string() == oid)
+        };
+        let basic_constraints = cert
+            .basic_constraints()
+            .map_err(|_| ClientError::MalformedResponse)?
+            .map(|ext| ext.value);
+        let mut report = CertReport {
+            subject: cert.subject().to_string(),
+            signature_checked: false,
+            is_ca: basic_constraints.map_or(false, |bc| bc.ca),
+            has_tcb_info: has_extension(MULTI_TCBINFO_OID) || has_extension(TCBINFO_OID),
+            has_ueid: has_extension(UEID_OID),
+            problems: Vec::new(),
+        };
+
+        let issuer = match i {
+            0 if cert.issuer().as_raw() == cert.subject().as_raw() => Some(&certs[0]),
+            0 => None,
+            _ => {
+                if cert.issuer().as_raw() != parsed[i - 1].subject().as_raw() {
+                    report.problems.push(ChainProblem::IssuerMismatch);
+                }
+                Some(&certs[i - 1])
+            }
+        };
+        if let Some(issuer) = issuer {
+            report.signature_checked = true;
+            let verified = issuer
+                .public_key()
+                .and_then(|key| certs[i].verify(&key))
+                .unwrap_or(false);
+            if !verified {
+                report.problems.push(ChainProblem::BadSignature);
+            }
+        }
+
+        if i + 1 < parsed.len() {
+            if !report.is_ca {
+                report.problems.push(ChainProblem::NotCa);
+            }
+            // Self-issued certificates don't count towards the path length.
+            let cas_below = parsed[i + 1..parsed.len() - 1]
+                .iter()
+                .filter(|below| below.issuer().as_raw() != below.subject().as_raw())
+                .count();
+            if let Some(path_len) = basic_constraints.and_then(|bc| bc.path_len_constraint) {
+                if cas_below > path_len as usize {
+                    report.problems.push(ChainProblem::PathLenExceeded);
+                }
+            }
+        } else if !report.has_tcb_info {
+            report.problems.push(ChainProblem::MissingTcbInfo);
+        }
+
+        reports.push(report);
+    }
+
+    Ok(ChainReport { certs: reports })
+}
@@ -0,0 +1,466 @@
+/*++
+Licensed under the Apache-2.0 license.
+
+Abstract:
+    Spec conformance tests that run against any DPE reachable through a
+    client transport.
+--*/
+use dpe::{
+    commands::{
+        CertifyKeyCmd, CertifyKeyFlags, CommandHdr, DeriveContextCmd, DeriveContextFlags,
+        DestroyCtxCmd, ExtendTciCmd, InitCtxCmd, RotateCtxCmd, RotateCtxFlags, TagTciCmd,
+    },
+    context::ContextHandle,
+    response::{DpeErrorCode, ResponseHdr},
+    support::Support,
+    wire::WireEndian,
+    DPE_PROFILE,
+};
+use dpe_client::{ClientError, DpeClient, Transport};
+use zerocopy::{AsBytes, FromBytes};
+
+/// ID of a command no DPE implements, below the vendor range.
+const UNKNOWN_COMMAND: u32 = 0x7FFF_FFFF;
+
+/// Handle no context is expected to have.
+const BOGUS_HANDLE: ContextHandle = ContextHandle([0xA5; ContextHandle::SIZE]);
+
+/// Where the tests send commands from.
+#[derive(Debug, Clone, Copy)]
+pub struct Config {
+    /// Locality the tests run in. If the DPE auto-initializes, it must be
+    /// the locality that owns the default context.
+    pub locality: u32,
+    /// A second locality, for the tests that check contexts are isolated
+    /// between localities. They are skipped without one.
+    pub other_locality: Option<u32>,
+}
+
+/// Result of one test.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Outcome {
+    Passed,
+    /// The DPE doesn't support what the test needs.
+    Skipped(&'static str),
+    Failed(String),
+}
+
+impl From<ClientError> for Outcome {
+    fn from(err: ClientError) -> Self {
+        Outcome::Failed(format!("unexpected error: {err:?}"))
+    }
+}
+
+/// Report of one test, as returned by `run`.
+#[derive(Debug)]
+pub struct TestReport {
+    pub name: &'static str,
+    pub outcome: Outcome,
+}
+
+/// A test returns `Err` with the outcome unless it passed.
+type TestResult = Result<(), Outcome>;
+
+type TestFn<T> = fn(&mut Harness<T>) -> TestResult;
+
+/// Runs every test against the DPE behind `transport` and reports each of
+/// them in turn.
+///
+/// The tests only create simulation contexts, which they destroy again, so
+/// the DPE is left the way it was found unless a test fails halfway. Which
+/// tests run depends on the support flags the DPE reports in GetProfile.
+pub fn run<T: Transport>(transport: T, config: Config) -> Result<Vec<TestReport>, ClientError> {
+    let mut client = DpeClient::new(transport, config.locality);
+    let support = client.get_profile()?.support();
+    let mut harness = Harness {
+        client,
+        config,
+        support,
+    };
+
+    Ok(all_tests::<T>()
+        .into_iter()
+        .map(|(name, test)| {
+            harness.client.set_locality(config.locality);
+            let outcome = match test(&mut harness) {
+                Ok(()) => Outcome::Passed,
+                Err(outcome) => outcome,
+            };
+            TestReport { name, outcome }
+        })
+        .collect())
+}
+
+fn all_tests<T: Transport>() -> [(&'static str, TestFn<T>); 8] {
+    [
+        ("profile", test_profile),
+        ("unknown_command", test_unknown_command),
+        ("invalid_handle", test_invalid_handle),
+        ("simulation_lifecycle", test_simulation_lifecycle),
+        ("rotate_handle", test_rotate_handle),
+        ("locality_isolation", test_locality_isolation),
+        ("unsupported_commands", test_unsupported_commands),
+        ("unsupported_arguments", test_unsupported_arguments),
+    ]
+}
+
+/// State shared by the tests.
+pub struct Harness<T: Transport> {
+    client: DpeClient<T>,
+    config: Config,
+    support: Support,
+}
+
+impl<T: Transport> Harness<T> {
+    /// Skips the test unless the DPE supports all of `support`.
+    fn require(&self, support: Support, reason: &'static str) -> TestResult {
+        if self.support.contains(support) {
+            Ok(())
+        } else {
+            Err(Outcome::Skipped(reason))
+        }
+    }
+
+    /// Returns a context to run a test in: a new simulation context if the
+    /// DPE supports them, or else the default context. The test must pass
+    /// the context's last handle to `release`.
+    fn acquire(&mut self) -> Result<ContextHandle, Outcome> {
+        if self.support.contains(Support::SIMULATION) {
+            Ok(self
+                .client
+                .initialize_context(&InitCtxCmd::new_simulation())?
+                .handle)
+        } else if self.support.contains(Support::AUTO_INIT) {
+            Ok(ContextHandle::default())
+        } else {
+            Err(Outcome::Skipped("needs SIMULATION or AUTO_INIT"))
+        }
+    }
+
+    /// Destroys a context returned by `acquire` unless it is the default
+    /// context.
+    fn release(&mut self, handle: ContextHandle) -> TestResult {
+        if !handle.is_default() {
+            self.client.destroy_context(&DestroyCtxCmd { handle })?;
+        }
+        Ok(())
+    }
+}
+
+/// Fails the test with `msg` unless `cond` holds.
+fn check(cond: bool, msg: impl FnOnce() -> String) -> TestResult {
+    if cond {
+        Ok(())
+    } else {
+        Err(Outcome::Failed(msg()))
+    }
+}
+
+/// Fails the test unless `res` is an error with one of `codes`.
+fn expect_error<R>(what: &str, res: Result<R, ClientError>, codes: &[DpeErrorCode]) -> TestResult {
+    match res {
+        Err(err) if codes.iter().any(|&code| err.is_dpe_error(code)) => Ok(()),
+        Err(err) => Err(Outcome::Failed(format!(
+            "{what}: expected {codes:?}, got {err:?}"
+        ))),
+        Ok(_) => Err(Outcome::Failed(format!(
+            "{what}: expected {codes:?}, but it succeeded"
+        ))),
+    }
+}
+
+/// Errors a DPE may reject the handle of a context it doesn't know in a
+/// locality with. It can't tell the handle is unknown if the locality has no
+/// contexts at all.
+const UNKNOWN_HANDLE_ERRORS: &[DpeErrorCode] =
+    &[DpeErrorCode::InvalidHandle, DpeErrorCode::InvalidLocality];
+
+fn test_profile<T: Transport>(h: &mut Harness<T>) -> TestResult {
+    let profile = h.client.get_profile()?;
+    check(profile.resp_hdr.profile == DPE_PROFILE as u32, || {
+        format!(
+            "profile {:#x} is not {:#x}",
+            profile.resp_hdr.profile, DPE_PROFILE as u32
+        )
+    })?;
+    check(profile.max_tci_nodes > 0, || "max_tci_nodes is 0".into())?;
+    check(
+        !h.support.contains(Support::SIMULATION) || profile.max_simulation_contexts > 0,
+        || "SIMULATION is supported but max_simulation_contexts is 0".into(),
+    )?;
+    check(profile.max_cert_chain_depth > 0, || {
+        "max_cert_chain_depth is 0".into()
+    })
+}
+
+fn test_unknown_command<T: Transport>(h: &mut Harness<T>) -> TestResult {
+    let hdr = CommandHdr::new(UNKNOWN_COMMAND).to_le();
+    let locality = h.config.locality;
+    // Transports may return a DPE error either as a response or as an error.
+    let status = match h.client.transport().send(locality, hdr.as_bytes()) {
+        Ok(resp) => {
+            ResponseHdr::read_from_prefix(resp.as_slice())
+                .map(ResponseHdr::from_le)
+                .ok_or(ClientError::MalformedResponse)?
+                .status
+        }
+        Err(ClientError::Dpe(status)) => status,
+        Err(err) => return Err(err.into()),
+    };
+    let expected = DpeErrorCode::InvalidCommand.get_error_code();
+    check(status == expected, || {
+        format!("status {status:#x} is not InvalidCommand")
+    })
+}
+
+fn test_invalid_handle<T: Transport>(h: &mut Harness<T>) -> TestResult {
+    let handle = h.acquire()?;
+    let res = expect_error(
+        "destroying an unknown handle",
+        h.client.destroy_context(&DestroyCtxCmd {
+            handle: BOGUS_HANDLE,
+        }),
+        &[DpeErrorCode::InvalidHandle],
+    );
+    h.release(handle)?;
+    res
+}
+
+fn test_simulation_lifecycle<T: Transport>(h: &mut Harness<T>) -> TestResult {
+    h.require(Support::SIMULATION, "needs SIMULATION")?;
+    let parent = h
+        .client
+        .initialize_context(&InitCtxCmd::new_simulation())?
+        .handle;
+    check(!parent.is_default(), || {
+        "simulation context has the default handle".into()
+    })?;
+
+    // Deriving a child without RETAIN_PARENT_CONTEXT retires the parent.
+    let child = h
+        .client
+        .derive_context(&DeriveContextCmd {
+            handle: parent,
+            data: [0x11; DPE_PROFILE.get_hash_size()],
+            flags: DeriveContextFlags::empty(),
+            tci_type: 0,
+            target_locality: h.config.locality,
+        })?
+        .handle;
+    check(child != parent, || "child kept the parent's handle".into())?;
+    expect_error(
+        "using the parent after deriving a child",
+        h.client.destroy_context(&DestroyCtxCmd { handle: parent }),
+        UNKNOWN_HANDLE_ERRORS,
+    )?;
+
+    h.client.destroy_context(&DestroyCtxCmd { handle: child })?;
+    expect_error(
+        "using a destroyed context",
+        h.client.destroy_context(&DestroyCtxCmd { handle: child }),
+        UNKNOWN_HANDLE_ERRORS,
+    )
+}
+
+fn test_rotate_handle<T: Transport>(h: &mut Harness<T>) -> TestResult {
+    h.require(
+        Support::SIMULATION | Support::ROTATE_CONTEXT,
+        "needs SIMULATION and ROTATE_CONTEXT",
+    )?;
+    let old = h
+        .client
+        .initialize_context(&InitCtxCmd::new_simulation())?
+        .handle;
+    let new = h
+        .client
+        .rotate_context_handle(&RotateCtxCmd {
+            handle: old,
+            flags: RotateCtxFlags::empty(),
+        })?
+        .handle;
+    check(new != old, || "rotation kept the handle".into())?;
+    let res = expect_error(
+        "using a rotated-out handle",
+        h.client.destroy_context(&DestroyCtxCmd { handle: old }),
+        UNKNOWN_HANDLE_ERRORS,
+    );
+    h.release(new)?;
+    res
+}
+
+fn test_locality_isolation<T: Transport>(h: &mut Harness<T>) -> TestResult {
+    h.require(Support::SIMULATION, "needs SIMULATION")?;
+    let Some(other_locality) = h.config.other_locality else {
+        return Err(Outcome::Skipped("needs a second locality"));
+    };
+    let handle = h
+        .client
+        .initialize_context(&InitCtxCmd::new_simulation())?
+        .handle;
+
+    h.client.set_locality(other_locality);
+    let res = expect_error(
+        "using a context from another locality",
+        h.client.destroy_context(&DestroyCtxCmd { handle }),
+        UNKNOWN_HANDLE_ERRORS,
+    );
+    h.client.set_locality(h.config.locality);
+
+    h.release(handle)?;
+    res
+}
+
+fn test_unsupported_commands<T: Transport>(h: &mut Harness<T>) -> TestResult {
+    let handle = h.acquire()?;
+    let mut res = Ok(());
+    if !h.support.contains(Support::ROTATE_CONTEXT) {
+        res = res.and_then(|_| {
+            expect_error(
+                "RotateContextHandle without ROTATE_CONTEXT",
+                h.client.rotate_context_handle(&RotateCtxCmd {
+                    handle,
+                    flags: RotateCtxFlags::empty(),
+                }),
+                &[DpeErrorCode::InvalidCommand],
+            )
+        });
+    }
+    if !h.support.contains(Support::EXTEND_TCI) {
+        res = res.and_then(|_| {
+            expect_error(
+                "ExtendTci without EXTEND_TCI",
+                h.client.extend_tci(&ExtendTciCmd {
+                    handle,
+                    data: [0; DPE_PROFILE.get_hash_size()],
+                }),
+                &[DpeErrorCode::InvalidCommand],
+            )
+        });
+    }
+    if !h.support.contains(Support::TAGGING) {
+        res = res.and_then(|_| {
+            expect_error(
+                "TagTci without TAGGING",
+                h.client.tag_tci(&TagTciCmd { handle, tag: 0 }),
+                &[DpeErrorCode::InvalidCommand],
+            )
+        });
+    }
+    h.release(handle)?;
+    res
+}
+
+fn test_unsupported_arguments<T: Transport>(h: &mut Harness<T>) -> TestResult {
+    if !h.support.contains(Support::SIMULATION) {
+        expect_error(
+            "InitializeContext of a simulation context without SIMULATION",
+            h.client.initialize_context(&InitCtxCmd::new_simulation()),
+            &[DpeErrorCode::ArgumentNotSupported],
+        )?;
+    }
+
+    let handle = h.acquire()?;
+    let mut res = Ok(());
+    for (format, support, what) in [
+        (
+            CertifyKeyCmd::FORMAT_X509,
+            Support::X509,
+            "CertifyKey of an X.509 certificate without X509",
+        ),
+        (
+            CertifyKeyCmd::FORMAT_CSR,
+            Support::CSR,
+            "CertifyKey of a CSR without CSR",
+        ),
+    ] {
+        if !h.support.contains(support) {
+            res = res.and_then(|_| {
+                expect_error(
+                    what,
+                    h.client.certify_key(&CertifyKeyCmd {
+                        handle,
+                        flags: CertifyKeyFlags::empty(),
+                        label: [0; DPE_PROFILE.get_hash_size()],
+                        format,
+                    }),
+                    &[DpeErrorCode::ArgumentNotSupported],
+                )
+            });
+        }
+    }
+    h.release(handle)?;
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crypto::OpensslCrypto;
+    use dpe::{
+        dpe_instance::{DpeEnv, DpeTypes},
+        DpeInstance,
+    };
+    use dpe_client::InProcessTransport;
+    use platform::default::{DefaultPlatform, AUTO_INIT_LOCALITY};
+
+    struct TestTypes;
+
+    impl DpeTypes for TestTypes {
+        type Crypto<'a> = OpensslCrypto;
+        type Platform<'a> = DefaultPlatform;
+    }
+
+    fn run_against(support: Support) -> Vec<TestReport> {
+        let mut env = DpeEnv::<TestTypes> {
+            crypto: OpensslCrypto::new(),
+            platform: DefaultPlatform,
+            arena: &mut [],
+        };
+        let dpe = DpeInstance::new(&mut env, support).unwrap();
+        let config = Config {
+            locality: AUTO_INIT_LOCALITY,
+            other_locality: Some(AUTO_INIT_LOCALITY + 1),
+        };
+        run(InProcessTransport::new(dpe, env), config).unwrap()
+    }
+
+    #[test]
+    fn test_conformance() {
+        for support in [
+            Support::AUTO_INIT,
+            Support::SIMULATION,
+            Support::AUTO_INIT | Support::SIMULATION | Support::ROTATE_CONTEXT | Support::X509,
+            Support::all() - Support::COMMAND_AUTH,
+        ] {
+            let reports = run_against(support);
+            assert_eq!(
+                reports.len(),
+                all_tests::<InProcessTransport<TestTypes>>().len()
+            );
+            for report in reports {
+                assert!(
+                    !matches!(report.outcome, Outcome::Failed(_)),
+                    "{support:?}: {report:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_skipped() {
+        let reports = run_against(Support::AUTO_INIT);
+        let outcome = |name| {
+            &reports
+                .iter()
+                .find(|report| report.name == name)
+                .unwrap()
+                .outcome
+        };
+        assert_eq!(outcome("profile"), &Outcome::Passed);
+        assert_eq!(outcome("unsupported_commands"), &Outcome::Passed);
+        assert!(matches!(
+            outcome("simulation_lifecycle"),
+            Outcome::Skipped(_)
+        ));
+    }
+}
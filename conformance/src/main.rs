@@ -0,0 +1,94 @@
+// Licensed under the Apache-2.0 license
+
+//! Runs the conformance tests against a DPE and prints the outcome of each.
+//!
+//! Exits with a failure status if any test fails. Tests that need a feature
+//! the DPE doesn't report in GetProfile are skipped.
+//!
+//! Example:
+//!
+//!   dpe-conformance --other-locality 0x1234
+
+use clap::Parser;
+use dpe_client::{TcpTransport, UnixTransport, SIMULATOR_SOCKET_PATH};
+use dpe_conformance::{run, Config, Outcome};
+use std::{net::SocketAddr, path::PathBuf, process::ExitCode};
+
+/// Checks that a DPE behaves the way the spec requires.
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    /// Unix socket the DPE listens on.
+    #[arg(long, value_name = "PATH", default_value = SIMULATOR_SOCKET_PATH)]
+    socket: PathBuf,
+
+    /// Connect to a DPE listening on TCP instead of the Unix socket.
+    #[arg(long, value_name = "ADDR:PORT", conflicts_with = "socket")]
+    tcp: Option<SocketAddr>,
+
+    /// Locality the tests run in. It must own the default context if the DPE
+    /// auto-initializes.
+    #[arg(long, value_parser = parse_u32, default_value = "0")]
+    locality: u32,
+
+    /// A second locality, for the locality isolation tests.
+    #[arg(long, value_parser = parse_u32)]
+    other_locality: Option<u32>,
+}
+
+fn parse_u32(arg: &str) -> Result<u32, String> {
+    let result = match arg.strip_prefix("0x") {
+        Some(hex) => u32::from_str_radix(hex, 16),
+        None => arg.parse(),
+    };
+    result.map_err(|err| err.to_string())
+}
+
+fn main() -> ExitCode {
+    let args = Args::parse();
+    let config = Config {
+        locality: args.locality,
+        other_locality: args.other_locality,
+    };
+    let reports = match args.tcp {
+        Some(addr) => run(TcpTransport::new(addr), config),
+        None => run(UnixTransport::new(args.socket), config),
+    };
+    let reports = match reports {
+        Ok(reports) => reports,
+        Err(err) => {
+            eprintln!("GetProfile failed: {err:?}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let mut failed = 0;
+    for report in &reports {
+        match &report.outcome {
+            Outcome::Passed => println!("PASS {}", report.name),
+            Outcome::Skipped(reason) => println!("SKIP {} ({reason})", report.name),
+            Outcome::Failed(msg) => {
+                println!("FAIL {}: {msg}", report.name);
+                failed += 1;
+            }
+        }
+    }
+    println!("{} tests, {failed} failed", reports.len());
+
+    if failed == 0 {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
+
+    #[test]
+    fn test_args() {
+        Args::command().debug_assert();
+    }
+}
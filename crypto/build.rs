@@ -17,12 +17,15 @@ fn main() {
 
         const ALIAS_PRIV_256: &str = "../platform/src/test_data/key_256.pem";
         const ALIAS_PRIV_384: &str = "../platform/src/test_data/key_384.pem";
+        const ALIAS_PRIV_521: &str = "../platform/src/test_data/key_521.pem";
 
         const CURVE_ID_256: nid::Nid = nid::Nid::X9_62_PRIME256V1;
         const CURVE_ID_384: nid::Nid = nid::Nid::SECP384R1;
+        const CURVE_ID_521: nid::Nid = nid::Nid::SECP521R1;
 
         println!("cargo:rerun-if-changed={ALIAS_PRIV_256}");
         println!("cargo:rerun-if-changed={ALIAS_PRIV_384}");
+        println!("cargo:rerun-if-changed={ALIAS_PRIV_521}");
 
         let out_dir = env::var_os("OUT_DIR").unwrap();
 
@@ -50,6 +53,18 @@ fn main() {
             ec_key.private_key_to_pem().unwrap()
         };
 
+        // generate 521 bit private key in PEM format
+        let pem_521 = if Path::new(ALIAS_PRIV_521).exists() {
+            let input_pem = fs::read(ALIAS_PRIV_521).unwrap();
+            let ec_priv: ec::EcKey<pkey::Private> =
+                ec::EcKey::private_key_from_pem(&input_pem).unwrap();
+            ec_priv.private_key_to_pem().unwrap()
+        } else {
+            let group = ec::EcGroup::from_curve_name(CURVE_ID_521).unwrap();
+            let ec_key = ec::EcKey::generate(&group).unwrap();
+            ec_key.private_key_to_pem().unwrap()
+        };
+
         // write 256 bit private key to file
         let path_256 = Path::new(&out_dir).join("alias_priv_256.pem");
         let mut sample_alias_key_file_256 = File::create(path_256).unwrap();
@@ -59,6 +74,11 @@ fn main() {
         let path_384 = Path::new(&out_dir).join("alias_priv_384.pem");
         let mut sample_alias_key_file_384 = File::create(path_384).unwrap();
         sample_alias_key_file_384.write_all(&pem_384).unwrap();
+
+        // write 521 bit private key to file
+        let path_521 = Path::new(&out_dir).join("alias_priv_521.pem");
+        let mut sample_alias_key_file_521 = File::create(path_521).unwrap();
+        sample_alias_key_file_521.write_all(&pem_521).unwrap();
     }
     #[cfg(feature = "rustcrypto")]
     {
@@ -68,6 +88,7 @@ fn main() {
             fs::File,
             p256::NistP256,
             p384::NistP384,
+            p521::SecretKey as SecretKey521,
             rand::{rngs::StdRng, SeedableRng},
             sec1::{DecodeEcPrivateKey, EncodeEcPrivateKey},
             std::env,
@@ -79,9 +100,11 @@ fn main() {
 
         const ALIAS_PRIV_256: &str = "../platform/src/test_data/key_256.pem";
         const ALIAS_PRIV_384: &str = "../platform/src/test_data/key_384.pem";
+        const ALIAS_PRIV_521: &str = "../platform/src/test_data/key_521.pem";
 
         println!("cargo:rerun-if-changed={ALIAS_PRIV_256}");
         println!("cargo:rerun-if-changed={ALIAS_PRIV_384}");
+        println!("cargo:rerun-if-changed={ALIAS_PRIV_521}");
 
         let out_dir = env::var_os("OUT_DIR").unwrap();
 
@@ -103,6 +126,16 @@ fn main() {
             ec_secret.to_sec1_pem(LineEnding::default()).unwrap()
         };
 
+        // generate 521 bit private key in PEM format. p521 has its own
+        // signing key type, so go through the secret key.
+        let pem_521 = if Path::new(ALIAS_PRIV_521).exists() {
+            let ec_secret = SecretKey521::read_sec1_pem_file(ALIAS_PRIV_521).unwrap();
+            ec_secret.to_sec1_pem(LineEnding::default()).unwrap()
+        } else {
+            let ec_secret = SecretKey521::random(&mut StdRng::from_entropy());
+            ec_secret.to_sec1_pem(LineEnding::default()).unwrap()
+        };
+
         // write 256 bit private key to file
         let path_256 = Path::new(&out_dir).join("alias_priv_256.pem");
         let mut sample_alias_key_file_256 = File::create(path_256).unwrap();
@@ -116,5 +149,12 @@ fn main() {
         sample_alias_key_file_384
             .write_all(pem_384.deref().as_bytes())
             .unwrap();
+
+        // write 521 bit private key to file
+        let path_521 = Path::new(&out_dir).join("alias_priv_521.pem");
+        let mut sample_alias_key_file_521 = File::create(path_521).unwrap();
+        sample_alias_key_file_521
+            .write_all(pem_521.deref().as_bytes())
+            .unwrap();
     }
 }
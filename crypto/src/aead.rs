@@ -0,0 +1,14 @@
+// Licensed under the Apache-2.0 license
+
+use zeroize::ZeroizeOnDrop;
+
+/// Size of an AES-256-GCM key.
+pub const AEAD_KEY_SIZE: usize = 32;
+/// Size of an AES-256-GCM initialization vector.
+pub const AEAD_IV_SIZE: usize = 12;
+/// Size of an AES-256-GCM authentication tag.
+pub const AEAD_TAG_SIZE: usize = 16;
+
+/// A symmetric key used to encrypt session traffic
+#[derive(ZeroizeOnDrop)]
+pub struct AeadKey(pub [u8; AEAD_KEY_SIZE]);
@@ -0,0 +1,254 @@
+// Licensed under the Apache-2.0 license
+
+//! Software SHA-2 whose running state is kept in a `HashState` buffer so it
+//! can be saved between calls.
+//!
+//! Layout of the buffer:
+//!
+//! ```text
+//! algorithm:   u8    (1 = SHA-256, 2 = SHA-384, 3 = SHA-512)
+//! reserved:    [u8; 7]
+//! length:      u64   (little-endian, bytes hashed so far)
+//! words:       [u8; 64] (big-endian chaining words)
+//! block:       [u8; 128] (bytes of the partial block)
+//! ```
+
+use crate::{AlgLen, CryptoError, Digest, HashState, HASH_STATE_SIZE};
+use sha2::digest::generic_array::GenericArray;
+
+const ALG_OFFSET: usize = 0;
+const LEN_OFFSET: usize = 8;
+const WORDS_OFFSET: usize = 16;
+const BLOCK_OFFSET: usize = 80;
+
+const _: () = assert!(BLOCK_OFFSET + 128 == HASH_STATE_SIZE);
+
+const SHA256_IV: [u32; 8] = [
+    0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab, 0x5be0cd19,
+];
+
+const SHA384_IV: [u64; 8] = [
+    0xcbbb9d5dc1059ed8,
+    0x629a292a367cd507,
+    0x9159015a3070dd17,
+    0x152fecd8f70e5939,
+    0x67332667ffc00b31,
+    0x8eb44a8768581511,
+    0xdb0c2e0d64f98fa7,
+    0x47b5481dbefa4fa4,
+];
+
+const SHA512_IV: [u64; 8] = [
+    0x6a09e667f3bcc908,
+    0xbb67ae8584caa73b,
+    0x3c6ef372fe94f82b,
+    0xa54ff53a5f1d36f1,
+    0x510e527fade682d1,
+    0x9b05688c2b3e6c1f,
+    0x1f83d9abfb41bd6b,
+    0x5be0cd19137e2179,
+];
+
+const fn alg_id(algs: AlgLen) -> u8 {
+    match algs {
+        AlgLen::Bit256 => 1,
+        AlgLen::Bit384 => 2,
+        AlgLen::Bit512 => 3,
+    }
+}
+
+const fn block_size(algs: AlgLen) -> usize {
+    match algs {
+        AlgLen::Bit256 => 64,
+        AlgLen::Bit384 | AlgLen::Bit512 => 128,
+    }
+}
+
+/// Size of the message length field in the final block.
+const fn length_size(algs: AlgLen) -> usize {
+    match algs {
+        AlgLen::Bit256 => 8,
+        AlgLen::Bit384 | AlgLen::Bit512 => 16,
+    }
+}
+
+fn read_len(state: &HashState) -> u64 {
+    let mut len = [0u8; 8];
+    len.copy_from_slice(&state.0[LEN_OFFSET..WORDS_OFFSET]);
+    u64::from_le_bytes(len)
+}
+
+/// Runs the compression function over `blocks`, which must be a multiple of
+/// the block size, updating the chaining words in `state`.
+fn compress(algs: AlgLen, state: &mut HashState, blocks: &[u8]) {
+    let words = &mut state.0[WORDS_OFFSET..BLOCK_OFFSET];
+    match algs {
+        AlgLen::Bit256 => {
+            let mut h = [0u32; 8];
+            for (word, bytes) in h.iter_mut().zip(words.chunks_exact(4)) {
+                *word = u32::from_be_bytes(bytes.try_into().unwrap_or_default());
+            }
+            for block in blocks.chunks_exact(block_size(algs)) {
+                sha2::compress256(
+                    &mut h,
+                    core::slice::from_ref(GenericArray::from_slice(block)),
+                );
+            }
+            for (word, bytes) in h.iter().zip(words.chunks_exact_mut(4)) {
+                bytes.copy_from_slice(&word.to_be_bytes());
+            }
+        }
+        AlgLen::Bit384 | AlgLen::Bit512 => {
+            let mut h = [0u64; 8];
+            for (word, bytes) in h.iter_mut().zip(words.chunks_exact(8)) {
+                *word = u64::from_be_bytes(bytes.try_into().unwrap_or_default());
+            }
+            for block in blocks.chunks_exact(block_size(algs)) {
+                sha2::compress512(
+                    &mut h,
+                    core::slice::from_ref(GenericArray::from_slice(block)),
+                );
+            }
+            for (word, bytes) in h.iter().zip(words.chunks_exact_mut(8)) {
+                bytes.copy_from_slice(&word.to_be_bytes());
+            }
+        }
+    }
+}
+
+/// Returns a state that has not hashed anything yet.
+pub(crate) fn hash_state_initialize(algs: AlgLen) -> HashState {
+    let mut state = HashState([0; HASH_STATE_SIZE]);
+    state.0[ALG_OFFSET] = alg_id(algs);
+    let words = &mut state.0[WORDS_OFFSET..BLOCK_OFFSET];
+    match algs {
+        AlgLen::Bit256 => {
+            for (word, bytes) in SHA256_IV.iter().zip(words.chunks_exact_mut(4)) {
+                bytes.copy_from_slice(&word.to_be_bytes());
+            }
+        }
+        AlgLen::Bit384 => {
+            for (word, bytes) in SHA384_IV.iter().zip(words.chunks_exact_mut(8)) {
+                bytes.copy_from_slice(&word.to_be_bytes());
+            }
+        }
+        AlgLen::Bit512 => {
+            for (word, bytes) in SHA512_IV.iter().zip(words.chunks_exact_mut(8)) {
+                bytes.copy_from_slice(&word.to_be_bytes());
+            }
+        }
+    }
+    state
+}
+
+/// Adds `bytes` to the hash held in `state`.
+pub(crate) fn hash_state_update(
+    algs: AlgLen,
+    state: &mut HashState,
+    mut bytes: &[u8],
+) -> Result<(), CryptoError> {
+    if state.0[ALG_OFFSET] != alg_id(algs) {
+        return Err(CryptoError::HashError(0));
+    }
+    let bs = block_size(algs);
+    let len = read_len(state);
+    // Keep the bit length within the 64-bit field SHA-256 encodes.
+    let new_len = len
+        .checked_add(bytes.len() as u64)
+        .filter(|len| *len < (1 << 61))
+        .ok_or(CryptoError::Size)?;
+    state.0[LEN_OFFSET..WORDS_OFFSET].copy_from_slice(&new_len.to_le_bytes());
+
+    // Top up a partial block first.
+    let buffered = (len % bs as u64) as usize;
+    if buffered != 0 {
+        let take = bytes.len().min(bs - buffered);
+        let start = BLOCK_OFFSET + buffered;
+        state.0[start..start + take].copy_from_slice(&bytes[..take]);
+        bytes = &bytes[take..];
+        if buffered + take < bs {
+            return Ok(());
+        }
+        let mut block = [0u8; 128];
+        block[..bs].copy_from_slice(&state.0[BLOCK_OFFSET..BLOCK_OFFSET + bs]);
+        compress(algs, state, &block[..bs]);
+    }
+
+    let full = bytes.len() - bytes.len() % bs;
+    compress(algs, state, &bytes[..full]);
+    let rest = &bytes[full..];
+    state.0[BLOCK_OFFSET..BLOCK_OFFSET + rest.len()].copy_from_slice(rest);
+    Ok(())
+}
+
+/// Pads the message held in `state` and returns its digest.
+pub(crate) fn hash_state_finish(algs: AlgLen, state: &HashState) -> Result<Digest, CryptoError> {
+    if state.0[ALG_OFFSET] != alg_id(algs) {
+        return Err(CryptoError::HashError(0));
+    }
+    let bs = block_size(algs);
+    let len = read_len(state);
+    let buffered = (len % bs as u64) as usize;
+
+    // The padding takes one block, or two if the length doesn't fit after the
+    // buffered bytes.
+    let mut padding = [0u8; 256];
+    padding[..buffered].copy_from_slice(&state.0[BLOCK_OFFSET..BLOCK_OFFSET + buffered]);
+    padding[buffered] = 0x80;
+    let blocks = if buffered + 1 + length_size(algs) > bs {
+        2 * bs
+    } else {
+        bs
+    };
+    let bit_len = (len as u128) << 3;
+    let bit_len = bit_len.to_be_bytes();
+    padding[blocks - length_size(algs)..blocks]
+        .copy_from_slice(&bit_len[bit_len.len() - length_size(algs)..]);
+
+    let mut state = state.clone();
+    compress(algs, &mut state, &padding[..blocks]);
+    Digest::new(&state.0[WORDS_OFFSET..WORDS_OFFSET + algs.size()])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sha2::{Digest as _, Sha256, Sha384, Sha512};
+
+    fn expected(algs: AlgLen, data: &[u8]) -> Vec<u8> {
+        match algs {
+            AlgLen::Bit256 => Sha256::digest(data).to_vec(),
+            AlgLen::Bit384 => Sha384::digest(data).to_vec(),
+            AlgLen::Bit512 => Sha512::digest(data).to_vec(),
+        }
+    }
+
+    #[test]
+    fn test_matches_sha2() {
+        let data: Vec<u8> = (0..1000u32).map(|i| i as u8).collect();
+        for algs in [AlgLen::Bit256, AlgLen::Bit384, AlgLen::Bit512] {
+            // Cover lengths around the padding boundaries and splits that
+            // leave partial blocks behind.
+            for len in [0, 1, 55, 56, 63, 64, 111, 112, 127, 128, 129, 1000] {
+                for split in [1, 7, 64, 200] {
+                    let mut state = hash_state_initialize(algs);
+                    for chunk in data[..len].chunks(split) {
+                        hash_state_update(algs, &mut state, chunk).unwrap();
+                    }
+                    let digest = hash_state_finish(algs, &state).unwrap();
+                    assert_eq!(digest.bytes(), expected(algs, &data[..len]));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_wrong_algorithm() {
+        let mut state = hash_state_initialize(AlgLen::Bit256);
+        assert_eq!(
+            hash_state_update(AlgLen::Bit384, &mut state, b"data"),
+            Err(CryptoError::HashError(0))
+        );
+        assert!(hash_state_finish(AlgLen::Bit384, &state).is_err());
+    }
+}
@@ -1,8 +1,9 @@
 // Licensed under the Apache-2.0 license
 
-use crate::{AlgLen, CryptoBuf, CryptoError, Digest};
+use crate::{AeadKey, AlgLen, Cdi, CryptoBuf, CryptoError, Digest, AEAD_KEY_SIZE};
 use hkdf::Hkdf;
-use sha2::{Sha256, Sha384};
+use sha2::{Sha256, Sha384, Sha512};
+use zeroize::Zeroize;
 
 impl From<hkdf::InvalidLength> for CryptoError {
     fn from(_: hkdf::InvalidLength) -> Self {
@@ -10,27 +11,41 @@ impl From<hkdf::InvalidLength> for CryptoError {
     }
 }
 
-pub fn hkdf_derive_cdi(
+/// Fills `okm` with HKDF output keying material derived from `ikm`, `salt`
+/// and `info` as specified in RFC 5869. The other functions in this module
+/// are built on it.
+pub fn hkdf(
     algs: AlgLen,
-    measurement: &Digest,
+    salt: Option<&[u8]>,
+    ikm: &[u8],
     info: &[u8],
-) -> Result<Vec<u8>, CryptoError> {
+    okm: &mut [u8],
+) -> Result<(), CryptoError> {
     match algs {
-        AlgLen::Bit256 => {
-            let hk = Hkdf::<Sha256>::new(Some(info), measurement.bytes());
-            let mut cdi = [0u8; AlgLen::Bit256.size()];
-            hk.expand(measurement.bytes(), &mut cdi)?;
-
-            Ok(cdi.to_vec())
-        }
-        AlgLen::Bit384 => {
-            let hk = Hkdf::<Sha384>::new(Some(info), measurement.bytes());
-            let mut cdi = [0u8; AlgLen::Bit384.size()];
-            hk.expand(measurement.bytes(), &mut cdi)?;
-
-            Ok(cdi.to_vec())
-        }
+        AlgLen::Bit256 => Hkdf::<Sha256>::new(salt, ikm).expand(info, okm)?,
+        AlgLen::Bit384 => Hkdf::<Sha384>::new(salt, ikm).expand(info, okm)?,
+        AlgLen::Bit512 => Hkdf::<Sha512>::new(salt, ikm).expand(info, okm)?,
     }
+    Ok(())
+}
+
+pub fn hkdf_derive_cdi(
+    algs: AlgLen,
+    measurement: &Digest,
+    info: &[u8],
+) -> Result<Cdi, CryptoError> {
+    let mut cdi = [0u8; AlgLen::MAX_ALG_LEN_BYTES];
+    let cdi = &mut cdi[..algs.size()];
+    let res = hkdf(
+        algs,
+        Some(info),
+        measurement.bytes(),
+        measurement.bytes(),
+        cdi,
+    )
+    .and_then(|_| Cdi::new(cdi));
+    cdi.zeroize();
+    res
 }
 
 pub fn hkdf_get_priv_key(
@@ -39,20 +54,71 @@ pub fn hkdf_get_priv_key(
     label: &[u8],
     info: &[u8],
 ) -> Result<CryptoBuf, CryptoError> {
-    match algs {
-        AlgLen::Bit256 => {
-            let hk = Hkdf::<Sha256>::new(Some(info), cdi);
-            let mut priv_key = [0u8; AlgLen::Bit256.size()];
-            hk.expand(label, &mut priv_key)?;
-
-            Ok(CryptoBuf::new(&priv_key).unwrap())
+    let mut priv_key = [0u8; AlgLen::MAX_ECC_INT_BYTES];
+    let priv_key = &mut priv_key[..algs.ecc_int_size()];
+    let res = hkdf(algs, Some(info), cdi, label, priv_key).and_then(|_| {
+        if matches!(algs, AlgLen::Bit512) {
+            // Keep the scalar within the 521 bits of the curve order.
+            priv_key[0] &= 0x01;
         }
-        AlgLen::Bit384 => {
-            let hk = Hkdf::<Sha384>::new(Some(info), cdi);
-            let mut priv_key = [0u8; AlgLen::Bit384.size()];
-            hk.expand(label, &mut priv_key)?;
+        CryptoBuf::new(priv_key)
+    });
+    priv_key.zeroize();
+    res
+}
+
+pub fn hkdf_derive_aead_key(
+    algs: AlgLen,
+    shared_secret: &[u8],
+    info: &[u8],
+) -> Result<AeadKey, CryptoError> {
+    let mut key = AeadKey([0u8; AEAD_KEY_SIZE]);
+    hkdf(algs, None, shared_secret, info, &mut key.0)?;
+    Ok(key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wycheproof::{hkdf::TestName, hkdf::TestSet, TestResult};
 
-            Ok(CryptoBuf::new(&priv_key).unwrap())
+    /// Runs the Wycheproof HKDF vectors, which include output lengths right
+    /// at and just beyond the 255 blocks RFC 5869 allows.
+    fn check_wycheproof(algs: AlgLen, name: TestName) {
+        let test_set = TestSet::load(name).unwrap();
+        for group in test_set.test_groups {
+            for test in group.tests {
+                let mut okm = vec![0u8; test.size];
+                let res = hkdf(
+                    algs,
+                    Some(&test.salt[..]),
+                    &test.ikm[..],
+                    &test.info[..],
+                    &mut okm,
+                );
+                match test.result {
+                    TestResult::Invalid => assert!(res.is_err(), "tcId {}", test.tc_id),
+                    _ => {
+                        assert!(res.is_ok(), "tcId {}", test.tc_id);
+                        assert_eq!(okm[..], test.okm[..], "tcId {}", test.tc_id);
+                    }
+                }
+            }
         }
     }
+
+    #[test]
+    fn test_wycheproof_hkdf_sha256() {
+        check_wycheproof(AlgLen::Bit256, TestName::HkdfSha256);
+    }
+
+    #[test]
+    fn test_wycheproof_hkdf_sha384() {
+        check_wycheproof(AlgLen::Bit384, TestName::HkdfSha384);
+    }
+
+    #[test]
+    fn test_wycheproof_hkdf_sha512() {
+        check_wycheproof(AlgLen::Bit512, TestName::HkdfSha512);
+    }
 }
@@ -0,0 +1,401 @@
+// Licensed under the Apache-2.0 license
+
+//! Known-answer tests for CDI derivation, key derivation and HMAC.
+//!
+//! Certificates issued by the DPE only match what a verifier expects if every
+//! `Crypto` implementation derives exactly the same CDIs and keys as the
+//! OpenSSL backend. Implementations run these checks from their own tests
+//! with `crypto_kat_tests!`.
+
+use crate::{AlgLen, Cdi, Crypto, Digest, Kdf};
+
+/// Inputs and expected outputs of one CDI and key derivation.
+pub struct KatVector {
+    pub algs: AlgLen,
+    /// Measurement digest passed to `derive_cdi`.
+    pub measurement: &'static [u8],
+    /// Info passed to `derive_cdi`.
+    pub cdi_info: &'static [u8],
+    /// CDI `derive_cdi` must return.
+    pub cdi: &'static [u8],
+    /// Label passed to `derive_key_pair`.
+    pub label: &'static [u8],
+    /// Info passed to `derive_key_pair`.
+    pub key_info: &'static [u8],
+    /// Public key `derive_key_pair` must return for the derived CDI.
+    pub pub_x: &'static [u8],
+    pub pub_y: &'static [u8],
+}
+
+pub const KAT_VECTORS: &[KatVector] = &[
+    KatVector {
+        algs: AlgLen::Bit256,
+        measurement: &[
+            0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0a, 0x0b, 0x0c, 0x0d,
+            0x0e, 0x0f, 0x10, 0x11, 0x12, 0x13, 0x14, 0x15, 0x16, 0x17, 0x18, 0x19, 0x1a, 0x1b,
+            0x1c, 0x1d, 0x1e, 0x1f,
+        ],
+        cdi_info: b"DPE KAT CDI",
+        cdi: &[
+            0x62, 0x00, 0xdb, 0x7c, 0x0d, 0xb4, 0x9e, 0x92, 0xaa, 0xf1, 0xb3, 0x8f, 0x25, 0x6a,
+            0x19, 0x18, 0xb6, 0x97, 0x04, 0x9a, 0xa3, 0xa4, 0x5f, 0xa8, 0xfc, 0x7e, 0x71, 0x55,
+            0xd1, 0x47, 0x39, 0x2c,
+        ],
+        label: &[
+            0xa5, 0xa5, 0xa5, 0xa5, 0xa5, 0xa5, 0xa5, 0xa5, 0xa5, 0xa5, 0xa5, 0xa5, 0xa5, 0xa5,
+            0xa5, 0xa5, 0xa5, 0xa5, 0xa5, 0xa5, 0xa5, 0xa5, 0xa5, 0xa5, 0xa5, 0xa5, 0xa5, 0xa5,
+            0xa5, 0xa5, 0xa5, 0xa5,
+        ],
+        key_info: b"DPE KAT key",
+        pub_x: &[
+            0x9b, 0x2d, 0x91, 0x0f, 0x78, 0x11, 0xae, 0xfd, 0x30, 0xc6, 0x38, 0x14, 0x0c, 0x01,
+            0x5e, 0x2a, 0x6b, 0xa5, 0x7d, 0x93, 0xb2, 0x33, 0x2b, 0xb4, 0x2d, 0xe3, 0x6b, 0x73,
+            0xfb, 0xcb, 0xe4, 0xda,
+        ],
+        pub_y: &[
+            0x89, 0x76, 0xdd, 0xca, 0x35, 0x5a, 0x78, 0xa6, 0xf8, 0x43, 0x9c, 0xb7, 0xea, 0x70,
+            0xef, 0x88, 0xad, 0xcc, 0xac, 0x71, 0xe1, 0x7e, 0xd4, 0x1a, 0xd2, 0x2f, 0xb2, 0x92,
+            0x98, 0x94, 0xa7, 0x13,
+        ],
+    },
+    KatVector {
+        algs: AlgLen::Bit384,
+        measurement: &[
+            0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0a, 0x0b, 0x0c, 0x0d,
+            0x0e, 0x0f, 0x10, 0x11, 0x12, 0x13, 0x14, 0x15, 0x16, 0x17, 0x18, 0x19, 0x1a, 0x1b,
+            0x1c, 0x1d, 0x1e, 0x1f, 0x20, 0x21, 0x22, 0x23, 0x24, 0x25, 0x26, 0x27, 0x28, 0x29,
+            0x2a, 0x2b, 0x2c, 0x2d, 0x2e, 0x2f,
+        ],
+        cdi_info: b"DPE KAT CDI",
+        cdi: &[
+            0x50, 0x41, 0x3a, 0x03, 0xd9, 0xe5, 0x4c, 0x83, 0xc2, 0xa0, 0x56, 0xfe, 0x3d, 0xa4,
+            0x66, 0xba, 0xe9, 0x44, 0xf1, 0x35, 0x52, 0x1e, 0x30, 0x34, 0xac, 0xde, 0x7d, 0xdd,
+            0x1b, 0xa3, 0x40, 0xba, 0x9a, 0xbf, 0x91, 0x1b, 0x86, 0x77, 0x0b, 0xe4, 0x9c, 0x3b,
+            0x84, 0xb3, 0xf3, 0x33, 0xbc, 0x73,
+        ],
+        label: &[
+            0xa5, 0xa5, 0xa5, 0xa5, 0xa5, 0xa5, 0xa5, 0xa5, 0xa5, 0xa5, 0xa5, 0xa5, 0xa5, 0xa5,
+            0xa5, 0xa5, 0xa5, 0xa5, 0xa5, 0xa5, 0xa5, 0xa5, 0xa5, 0xa5, 0xa5, 0xa5, 0xa5, 0xa5,
+            0xa5, 0xa5, 0xa5, 0xa5, 0xa5, 0xa5, 0xa5, 0xa5, 0xa5, 0xa5, 0xa5, 0xa5, 0xa5, 0xa5,
+            0xa5, 0xa5, 0xa5, 0xa5, 0xa5, 0xa5,
+        ],
+        key_info: b"DPE KAT key",
+        pub_x: &[
+            0x22, 0xb8, 0x33, 0x13, 0x1f, 0x02, 0x4f, 0x06, 0xcc, 0x01, 0x66, 0x1e, 0x0f, 0x3e,
+            0x4b, 0x44, 0x7c, 0xbf, 0x19, 0x48, 0x27, 0xf9, 0x68, 0x03, 0x7e, 0xd8, 0x4b, 0x7e,
+            0xea, 0x7e, 0x29, 0x71, 0xc9, 0xce, 0xa3, 0x1c, 0xb9, 0xe5, 0x7d, 0x21, 0xa3, 0xf9,
+            0xc0, 0xa6, 0x7b, 0xd6, 0x96, 0xb8,
+        ],
+        pub_y: &[
+            0xcc, 0x1c, 0x08, 0x21, 0xe3, 0x72, 0xf9, 0x63, 0x81, 0x8c, 0x48, 0xa7, 0x72, 0xf1,
+            0xc3, 0x39, 0x97, 0x85, 0xac, 0x21, 0xd4, 0x86, 0xa6, 0xfe, 0xe5, 0xfe, 0xeb, 0x48,
+            0x3d, 0xe5, 0xe6, 0x95, 0x0f, 0x17, 0xbe, 0x50, 0xf6, 0x2f, 0xd9, 0x85, 0xe6, 0xbe,
+            0x71, 0xcf, 0x14, 0x8d, 0x6b, 0x8a,
+        ],
+    },
+    KatVector {
+        algs: AlgLen::Bit512,
+        measurement: &[
+            0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0a, 0x0b, 0x0c, 0x0d,
+            0x0e, 0x0f, 0x10, 0x11, 0x12, 0x13, 0x14, 0x15, 0x16, 0x17, 0x18, 0x19, 0x1a, 0x1b,
+            0x1c, 0x1d, 0x1e, 0x1f, 0x20, 0x21, 0x22, 0x23, 0x24, 0x25, 0x26, 0x27, 0x28, 0x29,
+            0x2a, 0x2b, 0x2c, 0x2d, 0x2e, 0x2f, 0x30, 0x31, 0x32, 0x33, 0x34, 0x35, 0x36, 0x37,
+            0x38, 0x39, 0x3a, 0x3b, 0x3c, 0x3d, 0x3e, 0x3f,
+        ],
+        cdi_info: b"DPE KAT CDI",
+        cdi: &[
+            0x12, 0xd8, 0x3f, 0x38, 0xa8, 0x0d, 0xd8, 0xbd, 0x37, 0xac, 0x0b, 0x34, 0xdb, 0x75,
+            0xbb, 0x80, 0xe6, 0xac, 0x3c, 0x44, 0x12, 0xc4, 0xdb, 0xa1, 0x8d, 0xb0, 0x4a, 0x15,
+            0xc4, 0x9a, 0xda, 0x9e, 0xa9, 0x54, 0x2c, 0x57, 0xd3, 0xba, 0x2d, 0x2a, 0x6e, 0x6b,
+            0x25, 0xf3, 0x13, 0x36, 0x89, 0x9e, 0x54, 0x35, 0x7e, 0x10, 0x68, 0xc7, 0x1b, 0xa8,
+            0xdb, 0x83, 0x03, 0x3a, 0x4d, 0x72, 0x45, 0x08,
+        ],
+        label: &[
+            0xa5, 0xa5, 0xa5, 0xa5, 0xa5, 0xa5, 0xa5, 0xa5, 0xa5, 0xa5, 0xa5, 0xa5, 0xa5, 0xa5,
+            0xa5, 0xa5, 0xa5, 0xa5, 0xa5, 0xa5, 0xa5, 0xa5, 0xa5, 0xa5, 0xa5, 0xa5, 0xa5, 0xa5,
+            0xa5, 0xa5, 0xa5, 0xa5, 0xa5, 0xa5, 0xa5, 0xa5, 0xa5, 0xa5, 0xa5, 0xa5, 0xa5, 0xa5,
+            0xa5, 0xa5, 0xa5, 0xa5, 0xa5, 0xa5, 0xa5, 0xa5, 0xa5, 0xa5, 0xa5, 0xa5, 0xa5, 0xa5,
+            0xa5, 0xa5, 0xa5, 0xa5, 0xa5, 0xa5, 0xa5, 0xa5,
+        ],
+        key_info: b"DPE KAT key",
+        pub_x: &[
+            0x01, 0xf3, 0x1c, 0xf9, 0x49, 0xe5, 0x43, 0xb2, 0x5a, 0x43, 0xde, 0xbc, 0x4d, 0x16,
+            0xe7, 0xb4, 0x53, 0xfe, 0xe3, 0xd3, 0xf5, 0x78, 0xcb, 0xd9, 0x37, 0xbf, 0x24, 0x32,
+            0xba, 0x9a, 0x39, 0x17, 0xa7, 0xc6, 0xb6, 0x6f, 0xfb, 0x8f, 0x67, 0x04, 0xca, 0xec,
+            0xa0, 0x5c, 0x57, 0x32, 0xdf, 0x00, 0x15, 0x75, 0xdc, 0xae, 0xec, 0x72, 0x81, 0x46,
+            0x6b, 0x8a, 0x1a, 0x7d, 0x80, 0xd2, 0x97, 0x2a, 0xe6, 0x03,
+        ],
+        pub_y: &[
+            0x00, 0x4c, 0xc4, 0x4c, 0xad, 0xda, 0x5a, 0x83, 0x1b, 0x99, 0x74, 0x8d, 0x8e, 0xd7,
+            0x1d, 0x4f, 0xa3, 0xc8, 0xbb, 0x8b, 0x65, 0x8e, 0x86, 0x83, 0x94, 0x89, 0x2b, 0x0a,
+            0x4d, 0xf8, 0xa2, 0x21, 0xe7, 0x35, 0xe4, 0x3d, 0xc9, 0xfe, 0xc8, 0xa1, 0x27, 0xbb,
+            0x0b, 0x54, 0x43, 0xde, 0x84, 0xf3, 0xf7, 0x52, 0xf0, 0xc1, 0xcb, 0xd4, 0x43, 0xca,
+            0xaf, 0x28, 0x40, 0x58, 0x20, 0xc2, 0x8d, 0x20, 0xa4, 0x02,
+        ],
+    },
+];
+
+/// Expected outputs of a `Kdf` for one entry of `KAT_VECTORS`.
+pub struct KdfVector {
+    /// CDI `Kdf::derive_cdi` must return for the entry's measurement and info.
+    pub cdi: &'static [u8],
+    /// Private key `Kdf::derive_priv_key` must return for `cdi` and the
+    /// entry's label and info.
+    pub priv_key: &'static [u8],
+}
+
+/// Outputs of `HkdfKdf` for the inputs of `KAT_VECTORS`, in the same order.
+pub const HKDF_VECTORS: &[KdfVector] = &[
+    KdfVector {
+        cdi: &[
+            0x62, 0x00, 0xdb, 0x7c, 0x0d, 0xb4, 0x9e, 0x92, 0xaa, 0xf1, 0xb3, 0x8f, 0x25, 0x6a,
+            0x19, 0x18, 0xb6, 0x97, 0x04, 0x9a, 0xa3, 0xa4, 0x5f, 0xa8, 0xfc, 0x7e, 0x71, 0x55,
+            0xd1, 0x47, 0x39, 0x2c,
+        ],
+        priv_key: &[
+            0x12, 0x1d, 0x43, 0xc1, 0xdb, 0xb5, 0xcd, 0x74, 0x55, 0x21, 0x99, 0x34, 0x46, 0xf9,
+            0x43, 0x97, 0x56, 0x78, 0x27, 0xdf, 0x84, 0x8b, 0x43, 0x8c, 0x73, 0x4b, 0x2b, 0x9e,
+            0x64, 0x9b, 0xe3, 0x50,
+        ],
+    },
+    KdfVector {
+        cdi: &[
+            0x50, 0x41, 0x3a, 0x03, 0xd9, 0xe5, 0x4c, 0x83, 0xc2, 0xa0, 0x56, 0xfe, 0x3d, 0xa4,
+            0x66, 0xba, 0xe9, 0x44, 0xf1, 0x35, 0x52, 0x1e, 0x30, 0x34, 0xac, 0xde, 0x7d, 0xdd,
+            0x1b, 0xa3, 0x40, 0xba, 0x9a, 0xbf, 0x91, 0x1b, 0x86, 0x77, 0x0b, 0xe4, 0x9c, 0x3b,
+            0x84, 0xb3, 0xf3, 0x33, 0xbc, 0x73,
+        ],
+        priv_key: &[
+            0xe8, 0x1a, 0xd9, 0x39, 0x6d, 0x4d, 0x74, 0xa2, 0xad, 0x4c, 0x02, 0x7f, 0x0c, 0xf1,
+            0x83, 0x42, 0xdb, 0x53, 0x92, 0x41, 0x15, 0x40, 0x8a, 0x51, 0x7a, 0x7e, 0x2e, 0x3f,
+            0x85, 0x40, 0xec, 0x78, 0x41, 0x40, 0x73, 0xd0, 0x31, 0xab, 0x24, 0x95, 0x98, 0xc3,
+            0x13, 0x42, 0x72, 0x63, 0xe1, 0xd5,
+        ],
+    },
+    KdfVector {
+        cdi: &[
+            0x12, 0xd8, 0x3f, 0x38, 0xa8, 0x0d, 0xd8, 0xbd, 0x37, 0xac, 0x0b, 0x34, 0xdb, 0x75,
+            0xbb, 0x80, 0xe6, 0xac, 0x3c, 0x44, 0x12, 0xc4, 0xdb, 0xa1, 0x8d, 0xb0, 0x4a, 0x15,
+            0xc4, 0x9a, 0xda, 0x9e, 0xa9, 0x54, 0x2c, 0x57, 0xd3, 0xba, 0x2d, 0x2a, 0x6e, 0x6b,
+            0x25, 0xf3, 0x13, 0x36, 0x89, 0x9e, 0x54, 0x35, 0x7e, 0x10, 0x68, 0xc7, 0x1b, 0xa8,
+            0xdb, 0x83, 0x03, 0x3a, 0x4d, 0x72, 0x45, 0x08,
+        ],
+        priv_key: &[
+            0x00, 0x8d, 0x50, 0x17, 0x4d, 0x30, 0x08, 0x88, 0x1f, 0x67, 0x2f, 0x4b, 0x6f, 0x3b,
+            0x10, 0x36, 0x7e, 0x85, 0x7e, 0xe7, 0xdb, 0xdd, 0x60, 0xc0, 0x7d, 0xe1, 0x25, 0x2d,
+            0x22, 0x5b, 0x5d, 0xa2, 0xef, 0x5b, 0x92, 0xf2, 0x38, 0xd5, 0x61, 0x1f, 0x6a, 0xfb,
+            0x74, 0xae, 0x80, 0x9a, 0x2d, 0x7c, 0x22, 0xc9, 0x7e, 0x1e, 0xcb, 0x6f, 0xbc, 0x19,
+            0xb0, 0xda, 0x5b, 0x7a, 0xbd, 0x30, 0x54, 0xb3, 0x7e, 0xfa,
+        ],
+    },
+];
+
+/// Outputs of `Sp800108CtrKdf` for the inputs of `KAT_VECTORS`, in the same order.
+pub const SP800_108_CTR_VECTORS: &[KdfVector] = &[
+    KdfVector {
+        cdi: &[
+            0x72, 0xf0, 0x95, 0x65, 0xbe, 0xee, 0x88, 0x7e, 0xa0, 0x28, 0x60, 0xe6, 0xf6, 0xe4,
+            0x1b, 0xae, 0x38, 0x9d, 0x16, 0xb5, 0x95, 0x84, 0xf6, 0x92, 0x88, 0xe3, 0x2b, 0x21,
+            0x87, 0xb3, 0xfa, 0x53,
+        ],
+        priv_key: &[
+            0xf5, 0x63, 0xf4, 0x9c, 0x28, 0x3e, 0x4b, 0x58, 0xdf, 0x14, 0x84, 0xb3, 0x4f, 0xfb,
+            0xec, 0xc2, 0xde, 0xb2, 0x6c, 0x1a, 0x22, 0xde, 0x29, 0x4b, 0xf5, 0x1b, 0x43, 0xaf,
+            0x20, 0x1d, 0x8c, 0x77,
+        ],
+    },
+    KdfVector {
+        cdi: &[
+            0xca, 0x93, 0xdc, 0x2e, 0x41, 0xa5, 0xf7, 0x07, 0xb1, 0x38, 0xbc, 0xc1, 0xe3, 0x5b,
+            0x66, 0xef, 0x16, 0xa3, 0xa5, 0xbb, 0xfe, 0x8d, 0xe5, 0xfe, 0x33, 0x3f, 0x84, 0xae,
+            0xd4, 0x78, 0x90, 0x8d, 0x97, 0x9f, 0xf0, 0xac, 0xa6, 0xac, 0x66, 0x61, 0xd5, 0xbc,
+            0xbc, 0xe0, 0x3d, 0xef, 0x12, 0xd5,
+        ],
+        priv_key: &[
+            0x07, 0x49, 0xe0, 0x53, 0x59, 0xd9, 0x8c, 0x86, 0xb9, 0x1b, 0x6e, 0x1b, 0x65, 0xb5,
+            0x7f, 0x23, 0xcf, 0xd2, 0x44, 0xb6, 0xa2, 0x69, 0x01, 0x89, 0xc4, 0x32, 0xe5, 0xe2,
+            0xfc, 0xe5, 0xfb, 0x4d, 0x25, 0xb7, 0xca, 0x37, 0x51, 0x9a, 0x47, 0x6d, 0x90, 0x9a,
+            0x92, 0x95, 0xad, 0x38, 0xf5, 0x9f,
+        ],
+    },
+    KdfVector {
+        cdi: &[
+            0xfe, 0x53, 0xaa, 0xea, 0xb6, 0x36, 0xd3, 0xb1, 0xf6, 0xb8, 0x44, 0x19, 0x61, 0x60,
+            0x5b, 0x08, 0x2a, 0x88, 0x47, 0x28, 0x3e, 0x0f, 0x6b, 0xaa, 0x0b, 0x61, 0xe4, 0xb1,
+            0x32, 0xfe, 0x28, 0xd4, 0x24, 0x0a, 0x97, 0x5c, 0x18, 0xbd, 0x08, 0xe2, 0x1b, 0x0f,
+            0x5c, 0xbd, 0x1e, 0x79, 0xf0, 0xd1, 0x82, 0xfc, 0x92, 0xbb, 0x9c, 0xd0, 0x01, 0xb1,
+            0x6c, 0x28, 0x69, 0xb4, 0x13, 0x22, 0xfb, 0x6e,
+        ],
+        priv_key: &[
+            0x00, 0x3c, 0x9e, 0xbb, 0x9c, 0x53, 0x82, 0xee, 0x25, 0x9b, 0x0a, 0x07, 0x1e, 0x2e,
+            0x16, 0x84, 0x0f, 0x88, 0x0d, 0x02, 0x4b, 0x62, 0x8b, 0xca, 0x44, 0x45, 0xb7, 0x2a,
+            0x42, 0x7d, 0x06, 0xd7, 0x17, 0xc1, 0x43, 0x7d, 0x8d, 0x4f, 0xdd, 0x2a, 0xae, 0x24,
+            0x9c, 0xc8, 0xcb, 0x18, 0x26, 0xb0, 0x5c, 0x4c, 0x0e, 0xc4, 0x0f, 0xba, 0xb1, 0x21,
+            0x88, 0x18, 0x26, 0x31, 0xa3, 0x38, 0x05, 0x8a, 0x06, 0xa2,
+        ],
+    },
+];
+
+/// Inputs and expected output of one HMAC.
+pub struct HmacVector {
+    pub algs: AlgLen,
+    pub key: &'static [u8],
+    pub data: &'static [u8],
+    /// HMAC `Crypto::hmac` must return.
+    pub mac: &'static [u8],
+}
+
+/// RFC 4231 test cases 2 and 6. The second uses a key longer than the hash block.
+pub const HMAC_VECTORS: &[HmacVector] = &[
+    HmacVector {
+        algs: AlgLen::Bit256,
+        key: b"Jefe",
+        data: b"what do ya want for nothing?",
+        mac: &[
+            0x5b, 0xdc, 0xc1, 0x46, 0xbf, 0x60, 0x75, 0x4e, 0x6a, 0x04, 0x24, 0x26, 0x08, 0x95,
+            0x75, 0xc7, 0x5a, 0x00, 0x3f, 0x08, 0x9d, 0x27, 0x39, 0x83, 0x9d, 0xec, 0x58, 0xb9,
+            0x64, 0xec, 0x38, 0x43,
+        ],
+    },
+    HmacVector {
+        algs: AlgLen::Bit384,
+        key: b"Jefe",
+        data: b"what do ya want for nothing?",
+        mac: &[
+            0xaf, 0x45, 0xd2, 0xe3, 0x76, 0x48, 0x40, 0x31, 0x61, 0x7f, 0x78, 0xd2, 0xb5, 0x8a,
+            0x6b, 0x1b, 0x9c, 0x7e, 0xf4, 0x64, 0xf5, 0xa0, 0x1b, 0x47, 0xe4, 0x2e, 0xc3, 0x73,
+            0x63, 0x22, 0x44, 0x5e, 0x8e, 0x22, 0x40, 0xca, 0x5e, 0x69, 0xe2, 0xc7, 0x8b, 0x32,
+            0x39, 0xec, 0xfa, 0xb2, 0x16, 0x49,
+        ],
+    },
+    HmacVector {
+        algs: AlgLen::Bit512,
+        key: b"Jefe",
+        data: b"what do ya want for nothing?",
+        mac: &[
+            0x16, 0x4b, 0x7a, 0x7b, 0xfc, 0xf8, 0x19, 0xe2, 0xe3, 0x95, 0xfb, 0xe7, 0x3b, 0x56,
+            0xe0, 0xa3, 0x87, 0xbd, 0x64, 0x22, 0x2e, 0x83, 0x1f, 0xd6, 0x10, 0x27, 0x0c, 0xd7,
+            0xea, 0x25, 0x05, 0x54, 0x97, 0x58, 0xbf, 0x75, 0xc0, 0x5a, 0x99, 0x4a, 0x6d, 0x03,
+            0x4f, 0x65, 0xf8, 0xf0, 0xe6, 0xfd, 0xca, 0xea, 0xb1, 0xa3, 0x4d, 0x4a, 0x6b, 0x4b,
+            0x63, 0x6e, 0x07, 0x0a, 0x38, 0xbc, 0xe7, 0x37,
+        ],
+    },
+    HmacVector {
+        algs: AlgLen::Bit256,
+        key: &[0xaa; 131],
+        data: b"Test Using Larger Than Block-Size Key - Hash Key First",
+        mac: &[
+            0x60, 0xe4, 0x31, 0x59, 0x1e, 0xe0, 0xb6, 0x7f, 0x0d, 0x8a, 0x26, 0xaa, 0xcb, 0xf5,
+            0xb7, 0x7f, 0x8e, 0x0b, 0xc6, 0x21, 0x37, 0x28, 0xc5, 0x14, 0x05, 0x46, 0x04, 0x0f,
+            0x0e, 0xe3, 0x7f, 0x54,
+        ],
+    },
+    HmacVector {
+        algs: AlgLen::Bit384,
+        key: &[0xaa; 131],
+        data: b"Test Using Larger Than Block-Size Key - Hash Key First",
+        mac: &[
+            0x4e, 0xce, 0x08, 0x44, 0x85, 0x81, 0x3e, 0x90, 0x88, 0xd2, 0xc6, 0x3a, 0x04, 0x1b,
+            0xc5, 0xb4, 0x4f, 0x9e, 0xf1, 0x01, 0x2a, 0x2b, 0x58, 0x8f, 0x3c, 0xd1, 0x1f, 0x05,
+            0x03, 0x3a, 0xc4, 0xc6, 0x0c, 0x2e, 0xf6, 0xab, 0x40, 0x30, 0xfe, 0x82, 0x96, 0x24,
+            0x8d, 0xf1, 0x63, 0xf4, 0x49, 0x52,
+        ],
+    },
+    HmacVector {
+        algs: AlgLen::Bit512,
+        key: &[0xaa; 131],
+        data: b"Test Using Larger Than Block-Size Key - Hash Key First",
+        mac: &[
+            0x80, 0xb2, 0x42, 0x63, 0xc7, 0xc1, 0xa3, 0xeb, 0xb7, 0x14, 0x93, 0xc1, 0xdd, 0x7b,
+            0xe8, 0xb4, 0x9b, 0x46, 0xd1, 0xf4, 0x1b, 0x4a, 0xee, 0xc1, 0x12, 0x1b, 0x01, 0x37,
+            0x83, 0xf8, 0xf3, 0x52, 0x6b, 0x56, 0xd0, 0x37, 0xe0, 0x5f, 0x25, 0x98, 0xbd, 0x0f,
+            0xd2, 0x21, 0x5d, 0x6a, 0x1e, 0x52, 0x95, 0xe6, 0x4f, 0x73, 0xf6, 0x3f, 0x0a, 0xec,
+            0x8b, 0x91, 0x5a, 0x98, 0x5d, 0x78, 0x65, 0x98,
+        ],
+    },
+];
+
+/// Checks that `crypto` derives the expected CDI for every vector.
+pub fn check_derive_cdi<C: Crypto<Cdi = Cdi>>(crypto: &mut C) {
+    for (i, vector) in KAT_VECTORS.iter().enumerate() {
+        let measurement = Digest::new(vector.measurement).unwrap();
+        let cdi = crypto
+            .derive_cdi(vector.algs, &measurement, vector.cdi_info)
+            .unwrap();
+        assert_eq!(vector.cdi, cdi.bytes(), "CDI of vector {i}");
+    }
+}
+
+/// Checks that `crypto` derives the expected public key for every vector,
+/// starting from the measurement. Unlike `check_derive_cdi` this never looks
+/// at the CDI, so it also covers implementations that keep CDIs out of reach.
+pub fn check_derive_key_pair<C: Crypto>(crypto: &mut C) {
+    for (i, vector) in KAT_VECTORS.iter().enumerate() {
+        let measurement = Digest::new(vector.measurement).unwrap();
+        let cdi = crypto
+            .derive_cdi(vector.algs, &measurement, vector.cdi_info)
+            .unwrap();
+        let (_, pub_key) = crypto
+            .derive_key_pair(vector.algs, &cdi, vector.label, vector.key_info)
+            .unwrap();
+        assert_eq!(vector.pub_x, pub_key.x.bytes(), "public x of vector {i}");
+        assert_eq!(vector.pub_y, pub_key.y.bytes(), "public y of vector {i}");
+    }
+}
+
+/// Checks that `kdf` derives the expected CDI and private key for every
+/// vector in `vectors`, whose inputs come from `KAT_VECTORS`.
+pub fn check_kdf<K: Kdf + ?Sized>(kdf: &K, vectors: &[KdfVector]) {
+    assert_eq!(vectors.len(), KAT_VECTORS.len());
+    for (i, (inputs, vector)) in KAT_VECTORS.iter().zip(vectors).enumerate() {
+        let measurement = Digest::new(inputs.measurement).unwrap();
+        let cdi = kdf
+            .derive_cdi(inputs.algs, &measurement, inputs.cdi_info)
+            .unwrap();
+        assert_eq!(vector.cdi, cdi.bytes(), "CDI of vector {i}");
+        let priv_key = kdf
+            .derive_priv_key(inputs.algs, cdi.bytes(), inputs.label, inputs.key_info)
+            .unwrap();
+        assert_eq!(
+            vector.priv_key,
+            priv_key.bytes(),
+            "private key of vector {i}"
+        );
+    }
+}
+
+/// Checks that `crypto` computes the expected HMAC for every vector.
+pub fn check_hmac<C: Crypto>(crypto: &mut C) {
+    for (i, vector) in HMAC_VECTORS.iter().enumerate() {
+        let mac = crypto.hmac(vector.algs, vector.key, vector.data).unwrap();
+        assert_eq!(vector.mac, mac.bytes(), "HMAC of vector {i}");
+    }
+}
+
+/// Generates tests which run the known-answer checks against the `Crypto`
+/// implementation built by `$crypto`.
+///
+/// Pass `opaque_cdi` as the second argument if the implementation's CDIs are
+/// not `Cdi` buffers. Only the derived public keys are checked then.
+#[macro_export]
+macro_rules! crypto_kat_tests {
+    ($crypto:expr, opaque_cdi) => {
+        #[test]
+        fn kat_derive_key_pair() {
+            $crate::kat::check_derive_key_pair(&mut $crypto);
+        }
+
+        #[test]
+        fn kat_hmac() {
+            $crate::kat::check_hmac(&mut $crypto);
+        }
+    };
+    ($crypto:expr) => {
+        $crate::crypto_kat_tests!($crypto, opaque_cdi);
+
+        #[test]
+        fn kat_derive_cdi() {
+            $crate::kat::check_derive_cdi(&mut $crypto);
+        }
+    };
+}
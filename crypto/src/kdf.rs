@@ -0,0 +1,274 @@
+// Licensed under the Apache-2.0 license
+
+//! Key derivation functions for CDIs and derived keys.
+//!
+//! Silicon KDF engines implement different constructions, so the software
+//! backends take the KDF as a `Kdf` chosen by the platform. Every KDF uses
+//! HMAC or HKDF with the hash selected by `algs` and produces the same output
+//! sizes: a CDI is `algs.size()` bytes and a private key is
+//! `algs.ecc_int_size()` bytes. The first byte of a P-521 key is masked to
+//! its lowest bit so the scalar stays within 521 bits.
+//!
+//! `HkdfKdf` (RFC 5869):
+//!
+//! * CDI: salt = `info`, IKM = `measurement`, info = `measurement`.
+//! * Private key: salt = `info`, IKM = `cdi`, info = `label`.
+//!
+//! `Sp800108CtrKdf` (NIST SP 800-108r1 counter mode with HMAC as the PRF).
+//! Block `i`, counting from 1, is
+//! `HMAC(K_I, [i]_32 || Label || 0x00 || Context || [L]_32)`, where the
+//! counter and the output length `L` in bits are big-endian:
+//!
+//! * CDI: K_I = `measurement`, Label = `info`, Context is empty.
+//! * Private key: K_I = `cdi`, Label = `label`, Context = `info`.
+//!
+//! Known answers for both are in `kat::HKDF_VECTORS` and
+//! `kat::SP800_108_CTR_VECTORS`.
+//!
+//! An AES-256-GCM key is the last `AEAD_KEY_SIZE` bytes of a private key
+//! derived the same way, which skips the masked first byte of a P-521 key.
+
+#[cfg(any(feature = "openssl", feature = "rustcrypto"))]
+use crate::hkdf::{hkdf_derive_cdi, hkdf_get_priv_key};
+use crate::{AeadKey, AlgLen, Cdi, CryptoBuf, CryptoError, Digest, AEAD_KEY_SIZE};
+#[cfg(any(feature = "openssl", feature = "rustcrypto"))]
+use hkdf::hmac::{digest::KeyInit, Hmac, Mac};
+#[cfg(any(feature = "openssl", feature = "rustcrypto"))]
+use sha2::{Sha256, Sha384, Sha512};
+#[cfg(any(feature = "openssl", feature = "rustcrypto"))]
+use zeroize::Zeroize;
+
+/// Derives CDIs and private keys for a `Crypto` implementation.
+///
+/// Implementations hold no state, so one instance can be shared by every
+/// `Crypto` instance on the platform.
+pub trait Kdf: Sync {
+    /// Derives a CDI from a digest of the measurements.
+    ///
+    /// # Arguments
+    ///
+    /// * `algs` - Which length of algorithms to use.
+    /// * `measurement` - A digest of the measurements which should be used for CDI derivation
+    /// * `info` - Caller-supplied info string to use in CDI derivation
+    fn derive_cdi(
+        &self,
+        algs: AlgLen,
+        measurement: &Digest,
+        info: &[u8],
+    ) -> Result<Cdi, CryptoError>;
+
+    /// Derives the private key of an asymmetric or symmetric key from a CDI.
+    ///
+    /// # Arguments
+    ///
+    /// * `algs` - Which length of algorithms to use.
+    /// * `cdi` - The CDI to derive the key from
+    /// * `label` - Caller-supplied label to use in key derivation
+    /// * `info` - Caller-supplied info string to use in key derivation
+    fn derive_priv_key(
+        &self,
+        algs: AlgLen,
+        cdi: &[u8],
+        label: &[u8],
+        info: &[u8],
+    ) -> Result<CryptoBuf, CryptoError>;
+
+    /// Derives an AES-256-GCM key from a CDI.
+    ///
+    /// # Arguments
+    ///
+    /// * `algs` - Which length of algorithms to use.
+    /// * `cdi` - The CDI to derive the key from
+    /// * `label` - Caller-supplied label to use in key derivation
+    /// * `info` - Caller-supplied info string to use in key derivation
+    fn derive_aead_key(
+        &self,
+        algs: AlgLen,
+        cdi: &[u8],
+        label: &[u8],
+        info: &[u8],
+    ) -> Result<AeadKey, CryptoError> {
+        let priv_key = self.derive_priv_key(algs, cdi, label, info)?;
+        let key = priv_key
+            .bytes()
+            .len()
+            .checked_sub(AEAD_KEY_SIZE)
+            .and_then(|start| priv_key.bytes().get(start..))
+            .ok_or(CryptoError::Size)?;
+        let mut aead_key = AeadKey([0; AEAD_KEY_SIZE]);
+        aead_key.0.copy_from_slice(key);
+        Ok(aead_key)
+    }
+}
+
+/// HKDF as described in RFC 5869.
+#[cfg(any(feature = "openssl", feature = "rustcrypto"))]
+pub struct HkdfKdf;
+
+#[cfg(any(feature = "openssl", feature = "rustcrypto"))]
+impl Kdf for HkdfKdf {
+    fn derive_cdi(
+        &self,
+        algs: AlgLen,
+        measurement: &Digest,
+        info: &[u8],
+    ) -> Result<Cdi, CryptoError> {
+        hkdf_derive_cdi(algs, measurement, info)
+    }
+
+    fn derive_priv_key(
+        &self,
+        algs: AlgLen,
+        cdi: &[u8],
+        label: &[u8],
+        info: &[u8],
+    ) -> Result<CryptoBuf, CryptoError> {
+        hkdf_get_priv_key(algs, cdi, label, info)
+    }
+}
+
+/// The counter mode KDF of NIST SP 800-108r1 with HMAC as the PRF.
+#[cfg(any(feature = "openssl", feature = "rustcrypto"))]
+pub struct Sp800108CtrKdf;
+
+#[cfg(any(feature = "openssl", feature = "rustcrypto"))]
+impl Sp800108CtrKdf {
+    fn expand(
+        algs: AlgLen,
+        key: &[u8],
+        label: &[u8],
+        context: &[u8],
+        out: &mut [u8],
+    ) -> Result<(), CryptoError> {
+        match algs {
+            AlgLen::Bit256 => counter_mode::<Hmac<Sha256>>(key, label, context, out),
+            AlgLen::Bit384 => counter_mode::<Hmac<Sha384>>(key, label, context, out),
+            AlgLen::Bit512 => counter_mode::<Hmac<Sha512>>(key, label, context, out),
+        }
+    }
+}
+
+#[cfg(any(feature = "openssl", feature = "rustcrypto"))]
+fn counter_mode<M: Mac + KeyInit + Clone>(
+    key: &[u8],
+    label: &[u8],
+    context: &[u8],
+    out: &mut [u8],
+) -> Result<(), CryptoError> {
+    let prf = <M as KeyInit>::new_from_slice(key).map_err(|_| CryptoError::Size)?;
+    let out_bits = u32::try_from(out.len() * 8).map_err(|_| CryptoError::Size)?;
+    let mut counter = 1u32;
+    let mut filled = 0;
+    while filled < out.len() {
+        let mut block = prf.clone();
+        block.update(&counter.to_be_bytes());
+        block.update(label);
+        block.update(&[0]);
+        block.update(context);
+        block.update(&out_bits.to_be_bytes());
+        let mut block = block.finalize().into_bytes();
+
+        let len = block.len().min(out.len() - filled);
+        out[filled..filled + len].copy_from_slice(&block[..len]);
+        block.zeroize();
+        filled += len;
+        counter += 1;
+    }
+    Ok(())
+}
+
+#[cfg(any(feature = "openssl", feature = "rustcrypto"))]
+impl Kdf for Sp800108CtrKdf {
+    fn derive_cdi(
+        &self,
+        algs: AlgLen,
+        measurement: &Digest,
+        info: &[u8],
+    ) -> Result<Cdi, CryptoError> {
+        let mut cdi = [0u8; AlgLen::MAX_ALG_LEN_BYTES];
+        let cdi = &mut cdi[..algs.size()];
+        Self::expand(algs, measurement.bytes(), info, &[], cdi)?;
+
+        let res = Cdi::new(cdi);
+        cdi.zeroize();
+        res
+    }
+
+    fn derive_priv_key(
+        &self,
+        algs: AlgLen,
+        cdi: &[u8],
+        label: &[u8],
+        info: &[u8],
+    ) -> Result<CryptoBuf, CryptoError> {
+        let mut priv_key = [0u8; AlgLen::MAX_ECC_INT_BYTES];
+        let priv_key = &mut priv_key[..algs.ecc_int_size()];
+        Self::expand(algs, cdi, label, info, priv_key)?;
+        if matches!(algs, AlgLen::Bit512) {
+            // Keep the scalar within the 521 bits of the curve order.
+            priv_key[0] &= 0x01;
+        }
+
+        let res = CryptoBuf::new(priv_key);
+        priv_key.zeroize();
+        res
+    }
+}
+
+#[cfg(all(test, any(feature = "openssl", feature = "rustcrypto")))]
+mod tests {
+    use super::*;
+    use crate::kat::{check_kdf, HKDF_VECTORS, SP800_108_CTR_VECTORS};
+
+    #[test]
+    fn test_hkdf_kat() {
+        check_kdf(&HkdfKdf, HKDF_VECTORS);
+    }
+
+    #[test]
+    fn test_sp800_108_ctr_kat() {
+        check_kdf(&Sp800108CtrKdf, SP800_108_CTR_VECTORS);
+    }
+
+    #[test]
+    fn test_sp800_108_ctr_multiple_blocks() {
+        // A P-521 key takes two SHA-512 blocks. The output length is part of
+        // every block, so a longer output does not extend a shorter one.
+        let mut short = [0u8; 64];
+        let mut long = [0u8; 128];
+        Sp800108CtrKdf::expand(AlgLen::Bit512, b"key", b"label", b"ctx", &mut short).unwrap();
+        Sp800108CtrKdf::expand(AlgLen::Bit512, b"key", b"label", b"ctx", &mut long).unwrap();
+        assert_ne!(short[..], long[..64]);
+        assert_ne!(long[..64], long[64..]);
+    }
+
+    #[test]
+    fn test_kdfs_differ() {
+        let measurement = Digest::new(&[0x11; 32]).unwrap();
+        let hkdf = HkdfKdf
+            .derive_cdi(AlgLen::Bit256, &measurement, b"info")
+            .unwrap();
+        let ctr = Sp800108CtrKdf
+            .derive_cdi(AlgLen::Bit256, &measurement, b"info")
+            .unwrap();
+        assert_ne!(hkdf.bytes(), ctr.bytes());
+    }
+
+    #[test]
+    fn test_derive_aead_key() {
+        for algs in [AlgLen::Bit256, AlgLen::Bit384, AlgLen::Bit512] {
+            let cdi = [0x22; AlgLen::MAX_ALG_LEN_BYTES];
+            let cdi = &cdi[..algs.size()];
+            let priv_key = HkdfKdf
+                .derive_priv_key(algs, cdi, b"label", b"info")
+                .unwrap();
+            let key = HkdfKdf
+                .derive_aead_key(algs, cdi, b"label", b"info")
+                .unwrap();
+            assert_eq!(
+                key.0[..],
+                priv_key.bytes()[algs.ecc_int_size() - AEAD_KEY_SIZE..]
+            );
+        }
+    }
+}
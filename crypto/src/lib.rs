@@ -7,6 +7,8 @@ Abstract:
 
 #[cfg(feature = "openssl")]
 pub use crate::openssl::*;
+pub use aead::*;
+pub use kdf::*;
 pub use signer::*;
 
 #[cfg(feature = "rustcrypto")]
@@ -18,28 +20,63 @@ pub mod openssl;
 #[cfg(feature = "rustcrypto")]
 pub mod rustcrypto;
 
+pub mod kat;
+
 #[cfg(feature = "deterministic_rand")]
 pub use rand::*;
 
+mod aead;
+#[cfg(any(feature = "openssl", feature = "rustcrypto"))]
+mod hash_state;
 #[cfg(any(feature = "openssl", feature = "rustcrypto"))]
 mod hkdf;
+mod kdf;
+#[cfg(feature = "openssl")]
+mod rfc6979;
 mod signer;
+#[cfg(all(test, any(feature = "openssl", feature = "rustcrypto")))]
+mod wycheproof_tests;
+
+use arrayvec::ArrayVec;
+use core::task::Poll;
+use zeroize::{Zeroize, ZeroizeOnDrop};
 
 #[derive(Debug, Clone, Copy)]
 #[cfg_attr(test, derive(strum_macros::EnumIter))]
 pub enum AlgLen {
     Bit256,
     Bit384,
+    /// SHA-512 with P-521
+    Bit512,
     // NOTE: If a larger length is added, MUST update AlgLen::MAX_ALG_LEN
 }
 
 impl AlgLen {
-    const MAX_ALG_LEN: Self = Self::Bit384;
+    const MAX_ALG_LEN: Self = Self::Bit512;
     pub(crate) const MAX_ALG_LEN_BYTES: usize = Self::MAX_ALG_LEN.size();
+    pub(crate) const MAX_ECC_INT_BYTES: usize = Self::MAX_ALG_LEN.ecc_int_size();
+    const MAX_HASH_BLOCK_BYTES: usize = Self::MAX_ALG_LEN.hash_block_size();
     pub const fn size(self) -> usize {
         match self {
             AlgLen::Bit256 => 256 / 8,
             AlgLen::Bit384 => 384 / 8,
+            AlgLen::Bit512 => 512 / 8,
+        }
+    }
+    /// Size of the block of the hash used with this length.
+    pub const fn hash_block_size(self) -> usize {
+        match self {
+            AlgLen::Bit256 => 64,
+            AlgLen::Bit384 | AlgLen::Bit512 => 128,
+        }
+    }
+    /// Size of a coordinate, scalar or signature component on the curve used
+    /// with this length. P-521 integers are wider than a SHA-512 digest.
+    pub const fn ecc_int_size(self) -> usize {
+        match self {
+            AlgLen::Bit256 => 256 / 8,
+            AlgLen::Bit384 => 384 / 8,
+            AlgLen::Bit512 => (521 + 7) / 8,
         }
     }
 }
@@ -54,6 +91,11 @@ pub enum CryptoError {
     Size = 0x3,
     NotImplemented = 0x4,
     HashError(u32) = 0x5,
+    /// An AEAD tag or a signature did not match the data it was meant to
+    /// authenticate.
+    AuthenticationFailed = 0x6,
+    /// Encoded data, such as a DER signature, was malformed.
+    InvalidEncoding = 0x7,
 }
 
 impl CryptoError {
@@ -71,10 +113,88 @@ impl CryptoError {
             CryptoError::Size => None,
             CryptoError::NotImplemented => None,
             CryptoError::HashError(code) => Some(*code),
+            CryptoError::AuthenticationFailed => None,
+            CryptoError::InvalidEncoding => None,
         }
     }
 }
 
+/// HMAC built on the running hash of `crypto`. This is the default
+/// `Crypto::hmac`.
+pub fn hmac_with_hasher<C: Crypto + ?Sized>(
+    crypto: &mut C,
+    algs: AlgLen,
+    key: &[u8],
+    data: &[u8],
+) -> Result<Digest, CryptoError> {
+    let block_size = algs.hash_block_size();
+    let mut block = [0u8; AlgLen::MAX_HASH_BLOCK_BYTES];
+    let block = &mut block[..block_size];
+    if key.len() > block_size {
+        let digest = crypto.hash(algs, key)?;
+        block[..digest.len()].copy_from_slice(digest.bytes());
+    } else {
+        block[..key.len()].copy_from_slice(key);
+    }
+
+    block.iter_mut().for_each(|b| *b ^= 0x36);
+    let mut hasher = crypto.hash_initialize(algs)?;
+    hasher.update(block)?;
+    hasher.update(data)?;
+    let inner = hasher.finish()?;
+
+    // Flip the block from the inner pad to the outer pad.
+    block.iter_mut().for_each(|b| *b ^= 0x36 ^ 0x5c);
+    let mut hasher = crypto.hash_initialize(algs)?;
+    hasher.update(block)?;
+    hasher.update(inner.bytes())?;
+    let result = hasher.finish();
+
+    block.zeroize();
+    result
+}
+
+/// Destroys `cdi` once `key_pair` has been derived from it. If that fails the
+/// key is destroyed as well, so neither outlives a failed call.
+pub fn destroy_cdi_after_derive<C: Crypto + ?Sized, P>(
+    crypto: &mut C,
+    cdi: C::Cdi,
+    key_pair: Result<(C::PrivKey, P), CryptoError>,
+) -> Result<(C::PrivKey, P), CryptoError> {
+    let destroyed = crypto.destroy_cdi(cdi);
+    let (priv_key, pub_key) = key_pair?;
+    if let Err(e) = destroyed {
+        // The CDI error is the one worth reporting.
+        let _ = crypto.destroy_key(priv_key);
+        return Err(e);
+    }
+    Ok((priv_key, pub_key))
+}
+
+/// Implements `Crypto::wrap_cdi` for implementations that hold the CDI in
+/// memory: encrypts `cdi` into `wrapped` under `key` with a random IV. The
+/// plaintext is not left in `wrapped` if encryption fails.
+pub fn wrap_cdi_bytes<C: Crypto + ?Sized>(
+    crypto: &mut C,
+    cdi: &[u8],
+    key: &AeadKey,
+    aad: &[u8],
+    wrapped: &mut [u8],
+) -> Result<([u8; AEAD_IV_SIZE], [u8; AEAD_TAG_SIZE]), CryptoError> {
+    if wrapped.len() != cdi.len() {
+        return Err(CryptoError::Size);
+    }
+    let mut iv = [0u8; AEAD_IV_SIZE];
+    crypto.rand_bytes(&mut iv)?;
+
+    wrapped.copy_from_slice(cdi);
+    let tag = crypto.aead_encrypt(key, &iv, aad, wrapped);
+    if tag.is_err() {
+        wrapped.zeroize();
+    }
+    Ok((iv, tag?))
+}
+
 pub trait Hasher: Sized {
     /// Adds a chunk to the running hash.
     ///
@@ -92,11 +212,50 @@ pub trait Hasher: Sized {
 
 pub type Digest = CryptoBuf;
 
+/// Size of a saved running hash, enough for a SHA-512 state and partial block.
+pub const HASH_STATE_SIZE: usize = 208;
+
+/// A running hash saved by `Crypto::hash_state_initialize`.
+///
+/// Unlike a `Hasher`, the state does not borrow the `Crypto` implementation,
+/// so it can be stored between commands and resumed by another instance. The
+/// layout is up to the implementation, which must reject states it cannot
+/// interpret.
+#[derive(Clone, Zeroize)]
+pub struct HashState(pub [u8; HASH_STATE_SIZE]);
+
+/// A Compound Device Identifier.
+///
+/// The buffer is cleared when the CDI is dropped. It intentionally does not
+/// implement `Debug` or `Clone` so the secret cannot be printed or copied by
+/// accident.
+#[derive(Zeroize, ZeroizeOnDrop)]
+pub struct Cdi(ArrayVec<u8, { AlgLen::MAX_ALG_LEN_BYTES }>);
+
+impl Cdi {
+    pub fn new(bytes: &[u8]) -> Result<Cdi, CryptoError> {
+        let mut vec = ArrayVec::new();
+        vec.try_extend_from_slice(bytes)
+            .map_err(|_| CryptoError::Size)?;
+        Ok(Cdi(vec))
+    }
+
+    pub fn bytes(&self) -> &[u8] {
+        self.0.as_slice()
+    }
+}
+
 pub trait Crypto {
+    /// CDI returned by `derive_cdi`. It may hold the secret itself or be an
+    /// opaque handle to a secret that never leaves hardware. Callers release
+    /// it with `destroy_cdi`.
     type Cdi;
     type Hasher<'c>: Hasher
     where
         Self: 'c;
+    /// Private key returned by `derive_key_pair` and the other key pair
+    /// derivations. Like `Cdi`, it may be an opaque handle. Callers release
+    /// it with `destroy_key`.
     type PrivKey;
 
     /// Fills the buffer with random values.
@@ -118,6 +277,21 @@ pub trait Crypto {
         hasher.finish()
     }
 
+    /// Computes HMAC (RFC 2104) over `data` with `key`, using the hash
+    /// selected by `algs`.
+    ///
+    /// The default builds the HMAC from `hash_initialize`. Implementations
+    /// with a native HMAC may override it.
+    ///
+    /// # Arguments
+    ///
+    /// * `algs` - Which length of algorithm to use.
+    /// * `key` - The HMAC key. Keys longer than the hash block are hashed first.
+    /// * `data` - Value to authenticate.
+    fn hmac(&mut self, algs: AlgLen, key: &[u8], data: &[u8]) -> Result<Digest, CryptoError> {
+        hmac_with_hasher(self, algs, key, data)
+    }
+
     /// Compute the serial number of an ECDSA public key by computing the hash
     /// over the point in uncompressed format.
     ///
@@ -156,6 +330,49 @@ pub trait Crypto {
     /// * `algs` - Which length of algorithm to use.
     fn hash_initialize(&mut self, algs: AlgLen) -> Result<Self::Hasher<'_>, CryptoError>;
 
+    /// Starts a running hash whose state is kept by the caller.
+    ///
+    /// Implementations that cannot save a running hash can rely on the
+    /// default, which returns `CryptoError::NotImplemented`.
+    ///
+    /// # Arguments
+    ///
+    /// * `algs` - Which length of algorithm to use.
+    fn hash_state_initialize(&mut self, _algs: AlgLen) -> Result<HashState, CryptoError> {
+        Err(CryptoError::NotImplemented)
+    }
+
+    /// Adds `bytes` to a running hash started by `hash_state_initialize`.
+    ///
+    /// # Arguments
+    ///
+    /// * `algs` - Which length of algorithm the hash was started with.
+    /// * `state` - Running hash to update.
+    /// * `bytes` - Value to add to hash.
+    fn hash_state_update(
+        &mut self,
+        _algs: AlgLen,
+        _state: &mut HashState,
+        _bytes: &[u8],
+    ) -> Result<(), CryptoError> {
+        Err(CryptoError::NotImplemented)
+    }
+
+    /// Finishes a running hash started by `hash_state_initialize` and returns
+    /// the result.
+    ///
+    /// # Arguments
+    ///
+    /// * `algs` - Which length of algorithm the hash was started with.
+    /// * `state` - Running hash to finish.
+    fn hash_state_finish(
+        &mut self,
+        _algs: AlgLen,
+        _state: &HashState,
+    ) -> Result<Digest, CryptoError> {
+        Err(CryptoError::NotImplemented)
+    }
+
     /// Derive a CDI based on the current base CDI and measurements
     ///
     /// # Arguments
@@ -212,6 +429,64 @@ pub trait Crypto {
         info: &[u8],
     ) -> Result<(Self::PrivKey, EcdsaPub), CryptoError>;
 
+    /// Releases a CDI returned by `derive_cdi`. It is not used afterwards.
+    ///
+    /// The default drops it, which is enough for implementations whose CDIs
+    /// clear themselves when dropped.
+    ///
+    /// # Arguments
+    ///
+    /// * `cdi` - The CDI to release
+    fn destroy_cdi(&mut self, _cdi: Self::Cdi) -> Result<(), CryptoError> {
+        Ok(())
+    }
+
+    /// Releases a private key returned by one of the key pair derivations. It
+    /// is not used afterwards.
+    ///
+    /// The default drops it, which is enough for implementations whose keys
+    /// clear themselves when dropped.
+    ///
+    /// # Arguments
+    ///
+    /// * `priv_key` - The private key to release
+    fn destroy_key(&mut self, _priv_key: Self::PrivKey) -> Result<(), CryptoError> {
+        Ok(())
+    }
+
+    /// Keeps a copy of `cdi` in cache slot `slot`, replacing whatever the
+    /// slot held, so that `cached_cdi` can return it without running the KDF
+    /// again. DPE uses one slot per context index and only reads a slot back
+    /// while the measurements it was derived from are unchanged.
+    ///
+    /// Implementations without a CDI cache can rely on the default, which
+    /// returns `CryptoError::NotImplemented`.
+    ///
+    /// # Arguments
+    ///
+    /// * `slot` - Index of the cache slot
+    /// * `cdi` - The CDI to keep
+    fn cache_cdi(&mut self, _slot: usize, _cdi: &Self::Cdi) -> Result<(), CryptoError> {
+        Err(CryptoError::NotImplemented)
+    }
+
+    /// Returns the CDI last stored in `slot` by `cache_cdi`, or an error if
+    /// the slot is empty. It is released with `destroy_cdi` like a freshly
+    /// derived one.
+    ///
+    /// The default returns `CryptoError::NotImplemented`.
+    ///
+    /// # Arguments
+    ///
+    /// * `slot` - Index of the cache slot
+    fn cached_cdi(&mut self, _slot: usize) -> Result<Self::Cdi, CryptoError> {
+        Err(CryptoError::NotImplemented)
+    }
+
+    /// Drops every CDI kept by `cache_cdi`. Called whenever a measurement
+    /// changes. The default does nothing.
+    fn clear_cdi_cache(&mut self) {}
+
     /// Sign `digest` with the platform Alias Key
     ///
     /// # Arguments
@@ -241,6 +516,75 @@ pub trait Crypto {
         pub_key: &EcdsaPub,
     ) -> Result<EcdsaSig, CryptoError>;
 
+    /// Starts signing `digest` like `ecdsa_sign_with_derived`, for hardware
+    /// that signs in the background.
+    ///
+    /// Returns `Poll::Pending` if the signature is not ready yet, in which
+    /// case it is collected with `ecdsa_sign_poll`. The caller may destroy
+    /// `priv_key` as soon as this returns. Only one signature is started at a
+    /// time. The default signs before returning.
+    ///
+    /// # Arguments
+    ///
+    /// * `algs` - Which length of algorithms to use.
+    /// * `digest` - Digest of data to be signed.
+    /// * `priv_key` - Caller-supplied private key to use in public key derivation
+    /// * `pub_key` - The public key corresponding to `priv_key`.
+    fn ecdsa_sign_with_derived_start(
+        &mut self,
+        algs: AlgLen,
+        digest: &Digest,
+        priv_key: &Self::PrivKey,
+        pub_key: &EcdsaPub,
+    ) -> Result<Poll<EcdsaSig>, CryptoError> {
+        self.ecdsa_sign_with_derived(algs, digest, priv_key, pub_key)
+            .map(Poll::Ready)
+    }
+
+    /// Checks on the signature `ecdsa_sign_with_derived_start` left pending.
+    ///
+    /// Implementations that always sign before returning from
+    /// `ecdsa_sign_with_derived_start` can rely on the default, which returns
+    /// `CryptoError::NotImplemented`.
+    ///
+    /// # Arguments
+    ///
+    /// * `algs` - Which length of algorithms to use.
+    fn ecdsa_sign_poll(&mut self, _algs: AlgLen) -> Result<Poll<EcdsaSig>, CryptoError> {
+        Err(CryptoError::NotImplemented)
+    }
+
+    /// Abandons the signature `ecdsa_sign_with_derived_start` left pending,
+    /// so that the next one can be started.
+    ///
+    /// Implementations that always sign before returning from
+    /// `ecdsa_sign_with_derived_start` can rely on the default, which does
+    /// nothing.
+    fn ecdsa_sign_cancel(&mut self) {}
+
+    /// Verifies that `sig` is a signature of `digest` by `pub_key`.
+    ///
+    /// Returns `CryptoError::AuthenticationFailed` if it is not, which
+    /// includes signatures whose `r` or `s` is not `algs.ecc_int_size()`
+    /// bytes long. Implementations that never check signatures can rely on
+    /// the default, which returns `CryptoError::NotImplemented`.
+    ///
+    /// # Arguments
+    ///
+    /// * `algs` - Which length of algorithms to use.
+    /// * `digest` - Digest of the signed data.
+    /// * `pub_key` - Public key of the signer
+    /// * `sig` - Signature to check
+    fn ecdsa_verify(
+        &mut self,
+        _algs: AlgLen,
+        _digest: &Digest,
+        _pub_key: &EcdsaPub,
+        _sig: &EcdsaSig,
+    ) -> Result<(), CryptoError> {
+        Err(CryptoError::NotImplemented)
+    }
+
     /// Sign `digest` with a derived HMAC key from the CDI.
     ///
     /// # Arguments
@@ -258,6 +602,236 @@ pub trait Crypto {
         info: &[u8],
         digest: &Digest,
     ) -> Result<HmacSig, CryptoError>;
+
+    /// Generates an ephemeral ECDH key pair, agrees on a shared secret with
+    /// `peer_pub` and derives an AEAD key from that secret.
+    ///
+    /// Returns the derived key and the ephemeral public key to hand back to the peer.
+    ///
+    /// # Arguments
+    ///
+    /// * `algs` - Which length of algorithms to use.
+    /// * `peer_pub` - The peer's ECDH public key
+    /// * `info` - Caller-supplied info string to use in key derivation
+    fn ecdh_derive_key(
+        &mut self,
+        algs: AlgLen,
+        peer_pub: &EcdsaPub,
+        info: &[u8],
+    ) -> Result<(AeadKey, EcdsaPub), CryptoError>;
+
+    /// Encrypts `data` in place with AES-256-GCM and returns the authentication tag.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The AEAD key
+    /// * `iv` - Initialization vector. Must never be reused with the same key.
+    /// * `aad` - Additional data to authenticate but not encrypt
+    /// * `data` - Plaintext to be replaced by ciphertext
+    fn aead_encrypt(
+        &mut self,
+        key: &AeadKey,
+        iv: &[u8; AEAD_IV_SIZE],
+        aad: &[u8],
+        data: &mut [u8],
+    ) -> Result<[u8; AEAD_TAG_SIZE], CryptoError>;
+
+    /// Authenticates and decrypts `data` in place with AES-256-GCM.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The AEAD key
+    /// * `iv` - Initialization vector used for encryption
+    /// * `aad` - Additional data that was authenticated with the ciphertext
+    /// * `data` - Ciphertext to be replaced by plaintext
+    /// * `tag` - The authentication tag
+    fn aead_decrypt(
+        &mut self,
+        key: &AeadKey,
+        iv: &[u8; AEAD_IV_SIZE],
+        aad: &[u8],
+        data: &mut [u8],
+        tag: &[u8; AEAD_TAG_SIZE],
+    ) -> Result<(), CryptoError>;
+
+    /// Derives an AES-256-GCM key from a CDI, so data can be sealed to the
+    /// measurements the CDI was derived from.
+    ///
+    /// # Arguments
+    ///
+    /// * `algs` - Which length of algorithms to use.
+    /// * `cdi` - CDI from which to derive the key
+    /// * `label` - Caller-supplied label to use in symmetric key derivation
+    /// * `info` - Caller-supplied info string to use in symmetric key derivation
+    fn derive_aead_key(
+        &mut self,
+        algs: AlgLen,
+        cdi: &Self::Cdi,
+        label: &[u8],
+        info: &[u8],
+    ) -> Result<AeadKey, CryptoError>;
+
+    /// Encrypts `cdi` with AES-256-GCM under `key` so it can be handed to
+    /// another secure element without exposing the plaintext.
+    ///
+    /// Returns the random IV and the authentication tag.
+    ///
+    /// # Arguments
+    ///
+    /// * `cdi` - The CDI to export
+    /// * `key` - The wrapping key
+    /// * `aad` - Additional data to authenticate but not encrypt
+    /// * `wrapped` - Output for the encrypted CDI. Must be as long as the CDI.
+    fn wrap_cdi(
+        &mut self,
+        cdi: &Self::Cdi,
+        key: &AeadKey,
+        aad: &[u8],
+        wrapped: &mut [u8],
+    ) -> Result<([u8; AEAD_IV_SIZE], [u8; AEAD_TAG_SIZE]), CryptoError>;
+
+    /// Derives an ML-DSA-87 key pair using a cryptographically secure KDF
+    ///
+    /// Implementations without post-quantum support can rely on the default,
+    /// which returns `CryptoError::NotImplemented`.
+    ///
+    /// # Arguments
+    ///
+    /// * `algs` - Which length of algorithms to use for the KDF.
+    /// * `cdi` - Caller-supplied private key to use in public key derivation
+    /// * `label` - Caller-supplied label to use in asymmetric key derivation
+    /// * `info` - Caller-supplied info string to use in asymmetric key derivation
+    #[cfg(feature = "ml_dsa")]
+    fn derive_mldsa_key_pair(
+        &mut self,
+        _algs: AlgLen,
+        _cdi: &Self::Cdi,
+        _label: &[u8],
+        _info: &[u8],
+    ) -> Result<(Self::PrivKey, MlDsaPub), CryptoError> {
+        Err(CryptoError::NotImplemented)
+    }
+
+    /// Sign `digest` with a derived ML-DSA-87 key-pair
+    ///
+    /// Implementations without post-quantum support can rely on the default,
+    /// which returns `CryptoError::NotImplemented`.
+    ///
+    /// # Arguments
+    ///
+    /// * `digest` - Digest of data to be signed.
+    /// * `priv_key` - Private key returned by `derive_mldsa_key_pair`
+    /// * `pub_key` - The public key corresponding to `priv_key`
+    #[cfg(feature = "ml_dsa")]
+    fn mldsa_sign_with_derived(
+        &mut self,
+        _digest: &Digest,
+        _priv_key: &Self::PrivKey,
+        _pub_key: &MlDsaPub,
+    ) -> Result<MlDsaSig, CryptoError> {
+        Err(CryptoError::NotImplemented)
+    }
+
+    /// Derives the public half of an RSA key pair from `cdi`
+    ///
+    /// The key is derived from the same inputs as `derive_key_pair`, so the
+    /// same CDI, label and info always give the same RSA key. Implementations
+    /// without RSA support can rely on the default, which returns
+    /// `CryptoError::NotImplemented`.
+    ///
+    /// # Arguments
+    ///
+    /// * `algs` - Which length of algorithms to use for the KDF.
+    /// * `cdi` - Caller-supplied private key to use in public key derivation
+    /// * `label` - Caller-supplied label to use in asymmetric key derivation
+    /// * `info` - Caller-supplied info string to use in asymmetric key derivation
+    /// * `size` - The size of the modulus
+    #[cfg(feature = "rsa")]
+    fn derive_rsa_pub(
+        &mut self,
+        _algs: AlgLen,
+        _cdi: &Self::Cdi,
+        _label: &[u8],
+        _info: &[u8],
+        _size: RsaKeySize,
+    ) -> Result<RsaPub, CryptoError> {
+        Err(CryptoError::NotImplemented)
+    }
+
+    /// Sign `digest` with the RSA key `derive_rsa_pub` returns for the same
+    /// arguments
+    ///
+    /// RSA keys are expensive to derive and large to hold, so unlike
+    /// `ecdsa_sign_with_derived` the private key is derived again for each
+    /// signature rather than handed to the caller.
+    ///
+    /// # Arguments
+    ///
+    /// * `algs` - Which length of algorithms to use for the KDF and the
+    ///   signature. `digest` must be of that length.
+    /// * `digest` - Digest of data to be signed.
+    /// * `cdi` - Caller-supplied private key to use in key derivation
+    /// * `label` - Caller-supplied label to use in asymmetric key derivation
+    /// * `info` - Caller-supplied info string to use in asymmetric key derivation
+    /// * `size` - The size of the modulus
+    /// * `padding` - The signature scheme
+    #[cfg(feature = "rsa")]
+    #[allow(clippy::too_many_arguments)]
+    fn rsa_sign_with_derived(
+        &mut self,
+        _algs: AlgLen,
+        _digest: &Digest,
+        _cdi: &Self::Cdi,
+        _label: &[u8],
+        _info: &[u8],
+        _size: RsaKeySize,
+        _padding: RsaPadding,
+    ) -> Result<RsaSig, CryptoError> {
+        Err(CryptoError::NotImplemented)
+    }
+
+    /// Derives an Ed25519 key pair using a cryptographically secure KDF
+    ///
+    /// The private key is the 32-byte seed from RFC 8032. Implementations
+    /// without Ed25519 support can rely on the default, which returns
+    /// `CryptoError::NotImplemented`.
+    ///
+    /// # Arguments
+    ///
+    /// * `algs` - Which length of algorithms to use for the KDF.
+    /// * `cdi` - Caller-supplied private key to use in public key derivation
+    /// * `label` - Caller-supplied label to use in asymmetric key derivation
+    /// * `info` - Caller-supplied info string to use in asymmetric key derivation
+    #[cfg(feature = "ed25519")]
+    fn derive_ed25519_key_pair(
+        &mut self,
+        _algs: AlgLen,
+        _cdi: &Self::Cdi,
+        _label: &[u8],
+        _info: &[u8],
+    ) -> Result<(Self::PrivKey, Ed25519Pub), CryptoError> {
+        Err(CryptoError::NotImplemented)
+    }
+
+    /// Sign `message` with a derived Ed25519 key pair
+    ///
+    /// This is PureEdDSA: Ed25519 hashes `message` itself with SHA-512, so
+    /// callers pass the data to be signed rather than a digest of it.
+    ///
+    /// # Arguments
+    ///
+    /// * `message` - Data to be signed.
+    /// * `priv_key` - Private key returned by `derive_ed25519_key_pair`
+    /// * `pub_key` - The public key corresponding to `priv_key`
+    #[cfg(feature = "ed25519")]
+    fn ed25519_sign_with_derived(
+        &mut self,
+        _message: &[u8],
+        _priv_key: &Self::PrivKey,
+        _pub_key: &Ed25519Pub,
+    ) -> Result<Ed25519Sig, CryptoError> {
+        Err(CryptoError::NotImplemented)
+    }
 }
 #[cfg(test)]
 mod tests {
@@ -269,4 +843,36 @@ mod tests {
         let max_len = AlgLen::iter().map(|x| x.size()).max().unwrap();
         assert_eq!(AlgLen::MAX_ALG_LEN_BYTES, max_len);
     }
+
+    #[test]
+    fn test_max_ecc_int_size() {
+        let max_len = AlgLen::iter().map(|x| x.ecc_int_size()).max().unwrap();
+        assert_eq!(AlgLen::MAX_ECC_INT_BYTES, max_len);
+        assert!(CryptoBuf::MAX_SIZE >= AlgLen::MAX_ALG_LEN_BYTES);
+
+        assert_eq!(AlgLen::Bit256.ecc_int_size(), 32);
+        assert_eq!(AlgLen::Bit384.ecc_int_size(), 48);
+        // P-521 coordinates don't fill a whole number of bytes.
+        assert_eq!(AlgLen::Bit512.ecc_int_size(), 66);
+    }
+
+    #[test]
+    fn test_max_hash_block_size() {
+        let max_len = AlgLen::iter().map(|x| x.hash_block_size()).max().unwrap();
+        assert_eq!(AlgLen::MAX_HASH_BLOCK_BYTES, max_len);
+    }
+
+    #[test]
+    fn test_cdi() {
+        let mut cdi = Cdi::new(&[0xaa; AlgLen::MAX_ALG_LEN_BYTES]).unwrap();
+        assert_eq!(cdi.bytes(), &[0xaa; AlgLen::MAX_ALG_LEN_BYTES]);
+
+        cdi.zeroize();
+        assert!(cdi.bytes().is_empty());
+
+        assert!(matches!(
+            Cdi::new(&[0; AlgLen::MAX_ALG_LEN_BYTES + 1]),
+            Err(CryptoError::Size)
+        ));
+    }
 }
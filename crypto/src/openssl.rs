@@ -1,20 +1,40 @@
 // Licensed under the Apache-2.0 license
 
-use crate::{hkdf::*, AlgLen, Crypto, CryptoBuf, CryptoError, Digest, EcdsaPub, Hasher, HmacSig};
+use crate::{
+    hash_state, hkdf::*, wrap_cdi_bytes, AeadKey, AlgLen, Cdi, Crypto, CryptoBuf, CryptoError,
+    Digest, EcdsaPub, HashState, Hasher, HkdfKdf, HmacSig, Kdf, AEAD_IV_SIZE, AEAD_TAG_SIZE,
+};
+#[cfg(feature = "ed25519")]
+use crate::{Ed25519Pub, Ed25519Sig};
+#[cfg(feature = "rsa")]
+use crate::{RsaKeySize, RsaPadding, RsaPub, RsaSig};
 #[cfg(not(feature = "no-cfi"))]
 use caliptra_cfi_derive_git::cfi_impl_fn;
+use core::task::Poll;
+#[cfg(feature = "ed25519")]
+use openssl::pkey::Id;
 use openssl::{
     bn::{BigNum, BigNumContext},
+    derive::Deriver,
     ec::{EcGroup, EcKey, EcPoint},
-    ecdsa::EcdsaSig,
     error::ErrorStack,
     hash::MessageDigest,
     nid::Nid,
     pkey::{PKey, Private},
     sign::Signer,
+    symm::{decrypt_aead, encrypt_aead, Cipher},
+};
+#[cfg(feature = "rsa")]
+use openssl::{
+    md::{Md, MdRef},
+    pkey_ctx::PkeyCtx,
+    rsa::{Padding, Rsa},
+    sign::RsaPssSaltlen,
 };
 #[cfg(feature = "deterministic_rand")]
 use rand::{rngs::StdRng, RngCore, SeedableRng};
+use std::collections::BTreeMap;
+use zeroize::Zeroize;
 
 impl From<ErrorStack> for CryptoError {
     fn from(e: ErrorStack) -> Self {
@@ -42,29 +62,48 @@ impl Hasher for OpensslHasher {
     }
 }
 
-#[cfg(feature = "deterministic_rand")]
-pub struct OpensslCrypto(StdRng);
-
-#[cfg(not(feature = "deterministic_rand"))]
-pub struct OpensslCrypto;
+pub struct OpensslCrypto {
+    #[cfg(feature = "deterministic_rand")]
+    rng: StdRng,
+    kdf: &'static dyn Kdf,
+    /// Whether signatures started with `ecdsa_sign_with_derived_start` are
+    /// held back until `ecdsa_sign_poll`.
+    defer_signing: bool,
+    deferred_sig: Option<super::EcdsaSig>,
+    /// CDIs kept by `cache_cdi`, by slot.
+    cdi_cache: BTreeMap<usize, Cdi>,
+}
 
 impl OpensslCrypto {
-    #[cfg(feature = "deterministic_rand")]
+    /// Creates an instance which derives CDIs and keys with HKDF.
     pub fn new() -> Self {
-        const SEED: [u8; 32] = [1; 32];
-        let seeded_rng = StdRng::from_seed(SEED);
-        OpensslCrypto(seeded_rng)
+        Self::with_kdf(&HkdfKdf)
     }
 
-    #[cfg(not(feature = "deterministic_rand"))]
-    pub fn new() -> Self {
-        Self {}
+    /// Creates an instance which derives CDIs and keys with `kdf`.
+    pub fn with_kdf(kdf: &'static dyn Kdf) -> Self {
+        Self {
+            #[cfg(feature = "deterministic_rand")]
+            rng: StdRng::from_seed([1; 32]),
+            kdf,
+            defer_signing: false,
+            deferred_sig: None,
+            cdi_cache: BTreeMap::new(),
+        }
+    }
+
+    /// Holds back signatures started with `ecdsa_sign_with_derived_start`
+    /// until the next `ecdsa_sign_poll`, like hardware that signs in the
+    /// background.
+    pub fn set_defer_signing(&mut self, defer: bool) {
+        self.defer_signing = defer;
     }
 
     fn get_digest(algs: AlgLen) -> MessageDigest {
         match algs {
             AlgLen::Bit256 => MessageDigest::sha256(),
             AlgLen::Bit384 => MessageDigest::sha384(),
+            AlgLen::Bit512 => MessageDigest::sha512(),
         }
     }
 
@@ -72,6 +111,7 @@ impl OpensslCrypto {
         match algs {
             AlgLen::Bit256 => Nid::X9_62_PRIME256V1,
             AlgLen::Bit384 => Nid::SECP384R1,
+            AlgLen::Bit512 => Nid::SECP521R1,
         }
     }
 
@@ -91,6 +131,122 @@ impl OpensslCrypto {
 
         EcKey::from_private_components(&group, priv_key_bn, &pub_point)
     }
+
+    /// Signs `digest` with `key`. With the `rfc6979` feature the nonce is
+    /// derived from the key and digest, so signatures are reproducible.
+    fn ecdsa_sign(
+        algs: AlgLen,
+        digest: &Digest,
+        key: &EcKey<Private>,
+    ) -> Result<super::EcdsaSig, CryptoError> {
+        #[cfg(feature = "rfc6979")]
+        let sig = crate::rfc6979::sign(Self::get_digest(algs), digest.bytes(), key)?;
+        #[cfg(not(feature = "rfc6979"))]
+        let sig = openssl::ecdsa::EcdsaSig::sign::<Private>(digest.bytes(), key)?;
+
+        let r =
+            CryptoBuf::new(&sig.r().to_vec_padded(algs.ecc_int_size() as i32).unwrap()).unwrap();
+        let s =
+            CryptoBuf::new(&sig.s().to_vec_padded(algs.ecc_int_size() as i32).unwrap()).unwrap();
+
+        Ok(super::EcdsaSig { r, s })
+    }
+
+    #[cfg(feature = "rsa")]
+    fn get_md(algs: AlgLen) -> &'static MdRef {
+        match algs {
+            AlgLen::Bit256 => Md::sha256(),
+            AlgLen::Bit384 => Md::sha384(),
+            AlgLen::Bit512 => Md::sha512(),
+        }
+    }
+
+    /// Derives an RSA key from the same seed `derive_key_pair` would use for
+    /// an ECDSA key.
+    ///
+    /// Each prime is searched for upwards from a starting point expanded from
+    /// the seed with HMAC, so the key only depends on the KDF inputs. The top
+    /// two bits of both starting points are set so that the modulus has
+    /// exactly `size.bits()` bits.
+    #[cfg(feature = "rsa")]
+    fn derive_rsa_key(
+        &mut self,
+        algs: AlgLen,
+        cdi: &Cdi,
+        label: &[u8],
+        info: &[u8],
+        size: RsaKeySize,
+    ) -> Result<Rsa<Private>, CryptoError> {
+        let seed = self.kdf.derive_priv_key(algs, cdi.bytes(), label, info)?;
+        let e = BigNum::from_u32(RsaPub::EXPONENT)?;
+        let p = self.derive_rsa_prime(algs, seed.bytes(), b'p', size, &e)?;
+        let q = self.derive_rsa_prime(algs, seed.bytes(), b'q', size, &e)?;
+
+        let mut ctx = BigNumContext::new()?;
+        let one = BigNum::from_u32(1)?;
+        let mut n = BigNum::new()?;
+        n.checked_mul(&p, &q, &mut ctx)?;
+        if n.num_bits() as usize != size.bits() {
+            return Err(CryptoError::Size);
+        }
+
+        let mut p1 = BigNum::new()?;
+        p1.checked_sub(&p, &one)?;
+        let mut q1 = BigNum::new()?;
+        q1.checked_sub(&q, &one)?;
+        let mut phi = BigNum::new()?;
+        phi.checked_mul(&p1, &q1, &mut ctx)?;
+
+        let mut d = BigNum::new()?;
+        d.mod_inverse(&e, &phi, &mut ctx)?;
+        let mut dp = BigNum::new()?;
+        dp.nnmod(&d, &p1, &mut ctx)?;
+        let mut dq = BigNum::new()?;
+        dq.nnmod(&d, &q1, &mut ctx)?;
+        let mut qinv = BigNum::new()?;
+        qinv.mod_inverse(&q, &p, &mut ctx)?;
+
+        Ok(Rsa::from_private_components(n, e, d, p, q, dp, dq, qinv)?)
+    }
+
+    /// Returns the first prime `p` at or above a starting point expanded from
+    /// `seed` and `label` for which `p - 1` is coprime with `e`.
+    #[cfg(feature = "rsa")]
+    fn derive_rsa_prime(
+        &mut self,
+        algs: AlgLen,
+        seed: &[u8],
+        label: u8,
+        size: RsaKeySize,
+        e: &BigNum,
+    ) -> Result<BigNum, CryptoError> {
+        let mut buf = [0u8; RsaPub::MAX_SIZE / 2];
+        let start = &mut buf[..size.modulus_size() / 2];
+        for (counter, chunk) in start.chunks_mut(algs.size()).enumerate() {
+            let mut data = [0u8; 5];
+            data[..4].copy_from_slice(&(counter as u32).to_be_bytes());
+            data[4] = label;
+            let block = self.hmac(algs, seed, &data)?;
+            chunk.copy_from_slice(&block.bytes()[..chunk.len()]);
+        }
+        start[0] |= 0xC0;
+        start[start.len() - 1] |= 1;
+        let mut p = BigNum::from_slice(start)?;
+        buf.zeroize();
+
+        let mut ctx = BigNumContext::new()?;
+        let one = BigNum::from_u32(1)?;
+        let mut p1 = BigNum::new()?;
+        let mut gcd = BigNum::new()?;
+        loop {
+            p1.checked_sub(&p, &one)?;
+            gcd.gcd(&p1, e, &mut ctx)?;
+            if gcd == one && p.is_prime_fasttest(64, &mut ctx, true)? {
+                return Ok(p);
+            }
+            p.add_word(2)?;
+        }
+    }
 }
 
 impl Default for OpensslCrypto {
@@ -99,7 +255,7 @@ impl Default for OpensslCrypto {
     }
 }
 
-type OpensslCdi = Vec<u8>;
+type OpensslCdi = Cdi;
 
 type OpensslPrivKey = CryptoBuf;
 
@@ -110,7 +266,7 @@ impl Crypto for OpensslCrypto {
 
     #[cfg(feature = "deterministic_rand")]
     fn rand_bytes(&mut self, dst: &mut [u8]) -> Result<(), CryptoError> {
-        StdRng::fill_bytes(&mut self.0, dst);
+        StdRng::fill_bytes(&mut self.rng, dst);
         Ok(())
     }
 
@@ -124,6 +280,34 @@ impl Crypto for OpensslCrypto {
         Ok(OpensslHasher(openssl::hash::Hasher::new(md)?))
     }
 
+    fn hmac(&mut self, algs: AlgLen, key: &[u8], data: &[u8]) -> Result<Digest, CryptoError> {
+        let hmac_key = PKey::hmac(key)?;
+        let mut signer = Signer::new(Self::get_digest(algs), &hmac_key)?;
+        signer.update(data)?;
+        Digest::new(&signer.sign_to_vec()?)
+    }
+
+    fn hash_state_initialize(&mut self, algs: AlgLen) -> Result<HashState, CryptoError> {
+        Ok(hash_state::hash_state_initialize(algs))
+    }
+
+    fn hash_state_update(
+        &mut self,
+        algs: AlgLen,
+        state: &mut HashState,
+        bytes: &[u8],
+    ) -> Result<(), CryptoError> {
+        hash_state::hash_state_update(algs, state, bytes)
+    }
+
+    fn hash_state_finish(
+        &mut self,
+        algs: AlgLen,
+        state: &HashState,
+    ) -> Result<Digest, CryptoError> {
+        hash_state::hash_state_finish(algs, state)
+    }
+
     #[cfg_attr(not(feature = "no-cfi"), cfi_impl_fn)]
     fn derive_cdi(
         &mut self,
@@ -131,7 +315,21 @@ impl Crypto for OpensslCrypto {
         measurement: &Digest,
         info: &[u8],
     ) -> Result<Self::Cdi, CryptoError> {
-        hkdf_derive_cdi(algs, measurement, info)
+        self.kdf.derive_cdi(algs, measurement, info)
+    }
+
+    fn cache_cdi(&mut self, slot: usize, cdi: &Self::Cdi) -> Result<(), CryptoError> {
+        self.cdi_cache.insert(slot, Cdi::new(cdi.bytes())?);
+        Ok(())
+    }
+
+    fn cached_cdi(&mut self, slot: usize) -> Result<Self::Cdi, CryptoError> {
+        let cdi = self.cdi_cache.get(&slot).ok_or(CryptoError::Size)?;
+        Cdi::new(cdi.bytes())
+    }
+
+    fn clear_cdi_cache(&mut self) {
+        self.cdi_cache.clear();
     }
 
     #[cfg_attr(not(feature = "no-cfi"), cfi_impl_fn)]
@@ -142,7 +340,7 @@ impl Crypto for OpensslCrypto {
         label: &[u8],
         info: &[u8],
     ) -> Result<(Self::PrivKey, EcdsaPub), CryptoError> {
-        let priv_key = hkdf_get_priv_key(algs, cdi, label, info)?;
+        let priv_key = self.kdf.derive_priv_key(algs, cdi.bytes(), label, info)?;
 
         let ec_priv_key = OpensslCrypto::ec_key_from_priv_key(algs, &priv_key)?;
         let nid = OpensslCrypto::get_curve(algs);
@@ -158,8 +356,8 @@ impl Crypto for OpensslCrypto {
             .affine_coordinates(&group, &mut x, &mut y, &mut bn_ctx)
             .unwrap();
 
-        let x = CryptoBuf::new(&x.to_vec_padded(algs.size() as i32).unwrap()).unwrap();
-        let y = CryptoBuf::new(&y.to_vec_padded(algs.size() as i32).unwrap()).unwrap();
+        let x = CryptoBuf::new(&x.to_vec_padded(algs.ecc_int_size() as i32).unwrap()).unwrap();
+        let y = CryptoBuf::new(&y.to_vec_padded(algs.ecc_int_size() as i32).unwrap()).unwrap();
 
         Ok((priv_key, EcdsaPub { x, y }))
     }
@@ -180,14 +378,14 @@ impl Crypto for OpensslCrypto {
                 "/alias_priv_384.pem"
             )))
             .unwrap(),
+            AlgLen::Bit512 => EcKey::private_key_from_pem(include_bytes!(concat!(
+                env!("OUT_DIR"),
+                "/alias_priv_521.pem"
+            )))
+            .unwrap(),
         };
 
-        let sig = EcdsaSig::sign::<Private>(digest.bytes(), &ec_priv)?;
-
-        let r = CryptoBuf::new(&sig.r().to_vec_padded(algs.size() as i32).unwrap()).unwrap();
-        let s = CryptoBuf::new(&sig.s().to_vec_padded(algs.size() as i32).unwrap()).unwrap();
-
-        Ok(super::EcdsaSig { r, s })
+        Self::ecdsa_sign(algs, digest, &ec_priv)
     }
 
     fn ecdsa_sign_with_derived(
@@ -198,12 +396,133 @@ impl Crypto for OpensslCrypto {
         _pub_key: &EcdsaPub,
     ) -> Result<super::EcdsaSig, CryptoError> {
         let ec_priv_key = OpensslCrypto::ec_key_from_priv_key(algs, priv_key)?;
-        let sig = EcdsaSig::sign::<Private>(digest.bytes(), &ec_priv_key).unwrap();
+        Self::ecdsa_sign(algs, digest, &ec_priv_key)
+    }
 
-        let r = CryptoBuf::new(&sig.r().to_vec_padded(algs.size() as i32).unwrap()).unwrap();
-        let s = CryptoBuf::new(&sig.s().to_vec_padded(algs.size() as i32).unwrap()).unwrap();
+    fn ecdsa_sign_with_derived_start(
+        &mut self,
+        algs: AlgLen,
+        digest: &Digest,
+        priv_key: &Self::PrivKey,
+        pub_key: &EcdsaPub,
+    ) -> Result<Poll<super::EcdsaSig>, CryptoError> {
+        let sig = self.ecdsa_sign_with_derived(algs, digest, priv_key, pub_key)?;
+        if !self.defer_signing {
+            return Ok(Poll::Ready(sig));
+        }
+        self.deferred_sig = Some(sig);
+        Ok(Poll::Pending)
+    }
 
-        Ok(super::EcdsaSig { r, s })
+    fn ecdsa_sign_poll(&mut self, _algs: AlgLen) -> Result<Poll<super::EcdsaSig>, CryptoError> {
+        self.deferred_sig
+            .take()
+            .map(Poll::Ready)
+            .ok_or(CryptoError::NotImplemented)
+    }
+
+    fn ecdsa_sign_cancel(&mut self) {
+        self.deferred_sig = None;
+    }
+
+    #[cfg(feature = "rsa")]
+    fn derive_rsa_pub(
+        &mut self,
+        algs: AlgLen,
+        cdi: &Self::Cdi,
+        label: &[u8],
+        info: &[u8],
+        size: RsaKeySize,
+    ) -> Result<RsaPub, CryptoError> {
+        let rsa = self.derive_rsa_key(algs, cdi, label, info, size)?;
+        RsaPub::new(&rsa.n().to_vec_padded(size.modulus_size() as i32)?)
+    }
+
+    #[cfg(feature = "rsa")]
+    fn rsa_sign_with_derived(
+        &mut self,
+        algs: AlgLen,
+        digest: &Digest,
+        cdi: &Self::Cdi,
+        label: &[u8],
+        info: &[u8],
+        size: RsaKeySize,
+        padding: RsaPadding,
+    ) -> Result<RsaSig, CryptoError> {
+        let rsa = self.derive_rsa_key(algs, cdi, label, info, size)?;
+        let pkey = PKey::from_rsa(rsa)?;
+        let md = Self::get_md(algs);
+
+        let mut ctx = PkeyCtx::new(&pkey)?;
+        ctx.sign_init()?;
+        ctx.set_signature_md(md)?;
+        match padding {
+            RsaPadding::Pkcs1v15 => ctx.set_rsa_padding(Padding::PKCS1)?,
+            RsaPadding::Pss => {
+                ctx.set_rsa_padding(Padding::PKCS1_PSS)?;
+                ctx.set_rsa_pss_saltlen(RsaPssSaltlen::DIGEST_LENGTH)?;
+                ctx.set_rsa_mgf1_md(md)?;
+            }
+        }
+
+        let mut sig = vec![];
+        ctx.sign_to_vec(digest.bytes(), &mut sig)?;
+        RsaSig::new(&sig)
+    }
+
+    #[cfg(feature = "ed25519")]
+    fn derive_ed25519_key_pair(
+        &mut self,
+        algs: AlgLen,
+        cdi: &Self::Cdi,
+        label: &[u8],
+        info: &[u8],
+    ) -> Result<(Self::PrivKey, Ed25519Pub), CryptoError> {
+        // Every profile's KDF output is at least as long as an Ed25519 seed.
+        let seed = self.kdf.derive_priv_key(algs, cdi.bytes(), label, info)?;
+        let priv_key = CryptoBuf::new(seed.bytes().get(..32).ok_or(CryptoError::Size)?)?;
+        let pkey = PKey::private_key_from_raw_bytes(priv_key.bytes(), Id::ED25519)?;
+        let pub_key = Ed25519Pub::new(&pkey.raw_public_key()?)?;
+        Ok((priv_key, pub_key))
+    }
+
+    #[cfg(feature = "ed25519")]
+    fn ed25519_sign_with_derived(
+        &mut self,
+        message: &[u8],
+        priv_key: &Self::PrivKey,
+        _pub_key: &Ed25519Pub,
+    ) -> Result<Ed25519Sig, CryptoError> {
+        let pkey = PKey::private_key_from_raw_bytes(priv_key.bytes(), Id::ED25519)?;
+        let mut signer = Signer::new_without_digest(&pkey)?;
+        Ed25519Sig::new(&signer.sign_oneshot_to_vec(message)?)
+    }
+
+    fn ecdsa_verify(
+        &mut self,
+        algs: AlgLen,
+        digest: &Digest,
+        pub_key: &EcdsaPub,
+        sig: &super::EcdsaSig,
+    ) -> Result<(), CryptoError> {
+        let size = algs.ecc_int_size();
+        if sig.r.len() != size || sig.s.len() != size {
+            return Err(CryptoError::AuthenticationFailed);
+        }
+        let group = EcGroup::from_curve_name(Self::get_curve(algs))?;
+        let x = BigNum::from_slice(pub_key.x.bytes())?;
+        let y = BigNum::from_slice(pub_key.y.bytes())?;
+        let key = EcKey::from_public_key_affine_coordinates(&group, &x, &y)?;
+        let sig = openssl::ecdsa::EcdsaSig::from_private_components(
+            BigNum::from_slice(sig.r.bytes())?,
+            BigNum::from_slice(sig.s.bytes())?,
+        )?;
+        // OpenSSL reports some malformed signatures as errors rather than
+        // mismatches. Either way the signature is no good.
+        match sig.verify(digest.bytes(), &key) {
+            Ok(true) => Ok(()),
+            _ => Err(CryptoError::AuthenticationFailed),
+        }
     }
 
     fn hmac_sign_with_derived(
@@ -224,4 +543,265 @@ impl Crypto for OpensslCrypto {
 
         Ok(HmacSig::new(&hmac).unwrap())
     }
+
+    fn ecdh_derive_key(
+        &mut self,
+        algs: AlgLen,
+        peer_pub: &EcdsaPub,
+        info: &[u8],
+    ) -> Result<(AeadKey, EcdsaPub), CryptoError> {
+        let group = EcGroup::from_curve_name(Self::get_curve(algs))?;
+        let mut bn_ctx = BigNumContext::new()?;
+
+        let peer_x = BigNum::from_slice(peer_pub.x.bytes())?;
+        let peer_y = BigNum::from_slice(peer_pub.y.bytes())?;
+        let peer_key = PKey::from_ec_key(EcKey::from_public_key_affine_coordinates(
+            &group, &peer_x, &peer_y,
+        )?)?;
+
+        let ephemeral = EcKey::generate(&group)?;
+        let mut x = BigNum::new()?;
+        let mut y = BigNum::new()?;
+        ephemeral
+            .public_key()
+            .affine_coordinates(&group, &mut x, &mut y, &mut bn_ctx)?;
+        let x = CryptoBuf::new(&x.to_vec_padded(algs.ecc_int_size() as i32)?)?;
+        let y = CryptoBuf::new(&y.to_vec_padded(algs.ecc_int_size() as i32)?)?;
+
+        let ephemeral = PKey::from_ec_key(ephemeral)?;
+        let mut deriver = Deriver::new(&ephemeral)?;
+        deriver.set_peer(&peer_key)?;
+        let shared_secret = deriver.derive_to_vec()?;
+
+        let key = hkdf_derive_aead_key(algs, &shared_secret, info)?;
+        Ok((key, EcdsaPub { x, y }))
+    }
+
+    fn aead_encrypt(
+        &mut self,
+        key: &AeadKey,
+        iv: &[u8; AEAD_IV_SIZE],
+        aad: &[u8],
+        data: &mut [u8],
+    ) -> Result<[u8; AEAD_TAG_SIZE], CryptoError> {
+        let mut tag = [0u8; AEAD_TAG_SIZE];
+        let ciphertext =
+            encrypt_aead(Cipher::aes_256_gcm(), &key.0, Some(iv), aad, data, &mut tag)?;
+        if ciphertext.len() != data.len() {
+            return Err(CryptoError::Size);
+        }
+        data.copy_from_slice(&ciphertext);
+        Ok(tag)
+    }
+
+    fn aead_decrypt(
+        &mut self,
+        key: &AeadKey,
+        iv: &[u8; AEAD_IV_SIZE],
+        aad: &[u8],
+        data: &mut [u8],
+        tag: &[u8; AEAD_TAG_SIZE],
+    ) -> Result<(), CryptoError> {
+        let plaintext = decrypt_aead(Cipher::aes_256_gcm(), &key.0, Some(iv), aad, data, tag)
+            .map_err(|_| CryptoError::AuthenticationFailed)?;
+        if plaintext.len() != data.len() {
+            return Err(CryptoError::Size);
+        }
+        data.copy_from_slice(&plaintext);
+        Ok(())
+    }
+
+    fn derive_aead_key(
+        &mut self,
+        algs: AlgLen,
+        cdi: &Self::Cdi,
+        label: &[u8],
+        info: &[u8],
+    ) -> Result<AeadKey, CryptoError> {
+        self.kdf.derive_aead_key(algs, cdi.bytes(), label, info)
+    }
+
+    fn wrap_cdi(
+        &mut self,
+        cdi: &Self::Cdi,
+        key: &AeadKey,
+        aad: &[u8],
+        wrapped: &mut [u8],
+    ) -> Result<([u8; AEAD_IV_SIZE], [u8; AEAD_TAG_SIZE]), CryptoError> {
+        wrap_cdi_bytes(self, cdi.bytes(), key, aad, wrapped)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    crate::crypto_kat_tests!(OpensslCrypto::new());
+
+    #[test]
+    fn test_wycheproof_ecdsa_verify() {
+        crate::wycheproof_tests::check_ecdsa_verify(&mut OpensslCrypto::new());
+    }
+
+    #[test]
+    fn test_with_kdf() {
+        let mut crypto = OpensslCrypto::with_kdf(&crate::Sp800108CtrKdf);
+        let vector = &crate::kat::KAT_VECTORS[0];
+        let measurement = Digest::new(vector.measurement).unwrap();
+        let cdi = crypto
+            .derive_cdi(vector.algs, &measurement, vector.cdi_info)
+            .unwrap();
+        assert_eq!(crate::kat::SP800_108_CTR_VECTORS[0].cdi, cdi.bytes());
+
+        let (priv_key, pub_key) = crypto
+            .derive_key_pair(vector.algs, &cdi, vector.label, vector.key_info)
+            .unwrap();
+        assert_eq!(
+            crate::kat::SP800_108_CTR_VECTORS[0].priv_key,
+            priv_key.bytes()
+        );
+        assert_ne!(vector.pub_x, pub_key.x.bytes());
+    }
+
+    #[test]
+    fn test_deferred_ecdsa_sign() {
+        let mut crypto = OpensslCrypto::new();
+        let algs = AlgLen::Bit384;
+        let measurement = Digest::new(&[0xAA; 48]).unwrap();
+        let cdi = crypto.derive_cdi(algs, &measurement, b"DPE").unwrap();
+        let (priv_key, pub_key) = crypto
+            .derive_key_pair(algs, &cdi, b"label", b"ECC")
+            .unwrap();
+        let digest = Digest::new(&[0x55; 48]).unwrap();
+
+        let Ok(Poll::Ready(sig)) =
+            crypto.ecdsa_sign_with_derived_start(algs, &digest, &priv_key, &pub_key)
+        else {
+            panic!("signature was deferred");
+        };
+        crypto.ecdsa_verify(algs, &digest, &pub_key, &sig).unwrap();
+
+        crypto.set_defer_signing(true);
+        assert!(matches!(
+            crypto.ecdsa_sign_with_derived_start(algs, &digest, &priv_key, &pub_key),
+            Ok(Poll::Pending)
+        ));
+        let Ok(Poll::Ready(sig)) = crypto.ecdsa_sign_poll(algs) else {
+            panic!("signature not ready");
+        };
+        crypto.ecdsa_verify(algs, &digest, &pub_key, &sig).unwrap();
+
+        // Nothing is left in flight.
+        assert!(matches!(
+            crypto.ecdsa_sign_poll(algs),
+            Err(CryptoError::NotImplemented)
+        ));
+
+        // A cancelled signature is never returned.
+        assert!(matches!(
+            crypto.ecdsa_sign_with_derived_start(algs, &digest, &priv_key, &pub_key),
+            Ok(Poll::Pending)
+        ));
+        crypto.ecdsa_sign_cancel();
+        assert!(matches!(
+            crypto.ecdsa_sign_poll(algs),
+            Err(CryptoError::NotImplemented)
+        ));
+    }
+
+    #[cfg(feature = "rsa")]
+    #[test]
+    fn test_rsa_sign_with_derived() {
+        let mut crypto = OpensslCrypto::new();
+        let algs = AlgLen::Bit384;
+        let measurement = Digest::new(&[0xAA; 48]).unwrap();
+        let cdi = crypto.derive_cdi(algs, &measurement, b"cdi").unwrap();
+        let size = RsaKeySize::Bit3072;
+
+        let pub_key = crypto
+            .derive_rsa_pub(algs, &cdi, b"label", b"RSA", size)
+            .unwrap();
+        assert_eq!(pub_key.modulus().len(), size.modulus_size());
+        // Derivation is deterministic, and depends on the label.
+        assert_eq!(
+            pub_key,
+            crypto
+                .derive_rsa_pub(algs, &cdi, b"label", b"RSA", size)
+                .unwrap()
+        );
+        assert_ne!(
+            pub_key,
+            crypto
+                .derive_rsa_pub(algs, &cdi, b"other", b"RSA", size)
+                .unwrap()
+        );
+
+        let rsa = Rsa::from_public_components(
+            BigNum::from_slice(pub_key.modulus()).unwrap(),
+            BigNum::from_u32(RsaPub::EXPONENT).unwrap(),
+        )
+        .unwrap();
+        let pkey = PKey::from_rsa(rsa).unwrap();
+        let digest = crypto.hash(algs, b"message").unwrap();
+        for padding in [RsaPadding::Pkcs1v15, RsaPadding::Pss] {
+            let sig = crypto
+                .rsa_sign_with_derived(algs, &digest, &cdi, b"label", b"RSA", size, padding)
+                .unwrap();
+            assert_eq!(sig.bytes().len(), size.modulus_size());
+
+            let mut ctx = PkeyCtx::new(&pkey).unwrap();
+            ctx.verify_init().unwrap();
+            ctx.set_signature_md(Md::sha384()).unwrap();
+            match padding {
+                RsaPadding::Pkcs1v15 => ctx.set_rsa_padding(Padding::PKCS1).unwrap(),
+                RsaPadding::Pss => {
+                    ctx.set_rsa_padding(Padding::PKCS1_PSS).unwrap();
+                    ctx.set_rsa_pss_saltlen(RsaPssSaltlen::DIGEST_LENGTH)
+                        .unwrap();
+                }
+            }
+            assert!(ctx.verify(digest.bytes(), sig.bytes()).unwrap());
+        }
+    }
+
+    #[cfg(feature = "ed25519")]
+    #[test]
+    fn test_ed25519_sign_with_derived() {
+        let mut crypto = OpensslCrypto::new();
+        let algs = AlgLen::Bit512;
+        let measurement = Digest::new(&[0xAA; 64]).unwrap();
+        let cdi = crypto.derive_cdi(algs, &measurement, b"cdi").unwrap();
+
+        let (priv_key, pub_key) = crypto
+            .derive_ed25519_key_pair(algs, &cdi, b"label", b"ED25519")
+            .unwrap();
+        let (_, other) = crypto
+            .derive_ed25519_key_pair(algs, &cdi, b"other", b"ED25519")
+            .unwrap();
+        assert_ne!(pub_key, other);
+
+        let sig = crypto
+            .ed25519_sign_with_derived(b"message", &priv_key, &pub_key)
+            .unwrap();
+        let pkey = PKey::public_key_from_raw_bytes(pub_key.bytes(), Id::ED25519).unwrap();
+        let mut verifier = openssl::sign::Verifier::new_without_digest(&pkey).unwrap();
+        assert!(verifier.verify_oneshot(sig.bytes(), b"message").unwrap());
+        // Ed25519 signatures are deterministic.
+        assert_eq!(
+            sig,
+            crypto
+                .ed25519_sign_with_derived(b"message", &priv_key, &pub_key)
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn test_hmac_with_hasher() {
+        let mut crypto = OpensslCrypto::new();
+        for (i, vector) in crate::kat::HMAC_VECTORS.iter().enumerate() {
+            let mac =
+                crate::hmac_with_hasher(&mut crypto, vector.algs, vector.key, vector.data).unwrap();
+            assert_eq!(vector.mac, mac.bytes(), "HMAC of vector {i}");
+        }
+    }
 }
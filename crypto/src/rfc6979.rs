@@ -0,0 +1,256 @@
+// Licensed under the Apache-2.0 license
+
+//! ECDSA signing with the deterministic nonces of RFC 6979, so that the same
+//! key and digest always produce the same signature.
+
+use openssl::{
+    bn::{BigNum, BigNumContext, BigNumContextRef, BigNumRef},
+    ec::{EcGroupRef, EcKeyRef, EcPoint},
+    ecdsa::EcdsaSig,
+    error::ErrorStack,
+    hash::MessageDigest,
+    pkey::{PKey, Private},
+    sign::Signer,
+};
+use zeroize::Zeroize;
+
+fn hmac(md: MessageDigest, key: &[u8], parts: &[&[u8]]) -> Result<Vec<u8>, ErrorStack> {
+    let key = PKey::hmac(key)?;
+    let mut signer = Signer::new(md, &key)?;
+    for part in parts {
+        signer.update(part)?;
+    }
+    signer.sign_to_vec()
+}
+
+/// Interprets the leftmost `qlen` bits of `bytes` as an integer (RFC 6979
+/// section 2.3.2).
+fn bits2int(bytes: &[u8], qlen: i32) -> Result<BigNum, ErrorStack> {
+    let value = BigNum::from_slice(bytes)?;
+    let blen = bytes.len() as i32 * 8;
+    if blen <= qlen {
+        return Ok(value);
+    }
+    let mut shifted = BigNum::new()?;
+    shifted.rshift(&value, blen - qlen)?;
+    Ok(shifted)
+}
+
+/// Computes r and s for nonce `k`, or returns None if either is zero and a
+/// new nonce is needed.
+fn sign_with_nonce(
+    group: &EcGroupRef,
+    order: &BigNumRef,
+    priv_key: &BigNumRef,
+    e: &BigNumRef,
+    k: &BigNumRef,
+    ctx: &mut BigNumContextRef,
+) -> Result<Option<EcdsaSig>, ErrorStack> {
+    let mut point = EcPoint::new(group)?;
+    point.mul_generator(group, k, ctx)?;
+    let mut x = BigNum::new()?;
+    let mut y = BigNum::new()?;
+    point.affine_coordinates(group, &mut x, &mut y, ctx)?;
+
+    let mut r = BigNum::new()?;
+    r.nnmod(&x, order, ctx)?;
+
+    // s = k^-1 * (e + r * priv_key) mod n
+    let mut r_priv = BigNum::new()?;
+    r_priv.mod_mul(&r, priv_key, order, ctx)?;
+    let mut sum = BigNum::new()?;
+    sum.mod_add(e, &r_priv, order, ctx)?;
+    let mut k_inv = BigNum::new()?;
+    k_inv.mod_inverse(k, order, ctx)?;
+    let mut s = BigNum::new()?;
+    s.mod_mul(&k_inv, &sum, order, ctx)?;
+
+    if r.num_bits() == 0 || s.num_bits() == 0 {
+        return Ok(None);
+    }
+    EcdsaSig::from_private_components(r, s).map(Some)
+}
+
+/// Signs `digest` with `key`, deriving the nonce from the key and digest as
+/// described in RFC 6979 section 3.2. `md` is the hash used by the HMAC DRBG
+/// and should be the one `digest` was computed with.
+pub(crate) fn sign(
+    md: MessageDigest,
+    digest: &[u8],
+    key: &EcKeyRef<Private>,
+) -> Result<EcdsaSig, ErrorStack> {
+    let group = key.group();
+    let mut ctx = BigNumContext::new()?;
+    let mut order = BigNum::new()?;
+    group.order(&mut order, &mut ctx)?;
+
+    let qlen = order.num_bits();
+    let rlen = (qlen + 7) / 8;
+    let hlen = md.size();
+
+    let e = bits2int(digest, qlen)?;
+    let mut h = BigNum::new()?;
+    h.nnmod(&e, &order, &mut ctx)?;
+    let h = h.to_vec_padded(rlen)?;
+    let mut x = key.private_key().to_vec_padded(rlen)?;
+
+    let mut v = vec![0x01; hlen];
+    let mut k = vec![0x00; hlen];
+    k = hmac(md, &k, &[&v[..], &[0x00], &x[..], &h[..]])?;
+    v = hmac(md, &k, &[&v[..]])?;
+    k = hmac(md, &k, &[&v[..], &[0x01], &x[..], &h[..]])?;
+    v = hmac(md, &k, &[&v[..]])?;
+    x.as_mut_slice().zeroize();
+
+    let result = loop {
+        let mut t = Vec::with_capacity(rlen as usize + hlen);
+        while t.len() < rlen as usize {
+            v = hmac(md, &k, &[&v[..]])?;
+            t.extend_from_slice(&v);
+        }
+        let mut nonce = bits2int(&t, qlen)?;
+        t.as_mut_slice().zeroize();
+        nonce.set_const_time();
+
+        if nonce.num_bits() > 0 && nonce.ucmp(&order).is_lt() {
+            let sig = sign_with_nonce(group, &order, key.private_key(), &e, &nonce, &mut ctx);
+            nonce.clear();
+            if let Some(sig) = sig? {
+                break sig;
+            }
+        }
+        k = hmac(md, &k, &[&v[..], &[0x00]])?;
+        v = hmac(md, &k, &[&v[..]])?;
+    };
+
+    k.as_mut_slice().zeroize();
+    v.as_mut_slice().zeroize();
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::AlgLen;
+    use openssl::{
+        ec::{EcGroup, EcKey},
+        nid::Nid,
+    };
+
+    struct Vector {
+        algs: AlgLen,
+        curve: Nid,
+        md: fn() -> MessageDigest,
+        priv_key: &'static str,
+        r: &'static str,
+        s: &'static str,
+    }
+
+    // RFC 6979 appendix A.2, message "sample".
+    const VECTORS: [Vector; 3] = [
+        Vector {
+            algs: AlgLen::Bit256,
+            curve: Nid::X9_62_PRIME256V1,
+            md: MessageDigest::sha256,
+            priv_key: "C9AFA9D845BA75166B5C215767B1D6934E50C3DB36E89B127B8A622B120F6721",
+            r: "EFD48B2AACB6A8FD1140DD9CD45E81D69D2C877B56AAF991C34D0EA84EAF3716",
+            s: "F7CB1C942D657C41D436C7A1B6E29F65F3E900DBB9AFF4064DC4AB2F843ACDA8",
+        },
+        Vector {
+            algs: AlgLen::Bit384,
+            curve: Nid::SECP384R1,
+            md: MessageDigest::sha384,
+            priv_key: "6B9D3DAD2E1B8C1C05B19875B6659F4DE23C3B667BF297BA9AA47740787137D8\
+                       96D5724E4C70A825F872C9EA60D2EDF5",
+            r: "94EDBB92A5ECB8AAD4736E56C691916B3F88140666CE9FA73D64C4EA95AD133C\
+                81A648152E44ACF96E36DD1E80FABE46",
+            s: "99EF4AEB15F178CEA1FE40DB2603138F130E740A19624526203B6351D0A3A94F\
+                A329C145786E679E7B82C71A38628AC8",
+        },
+        Vector {
+            algs: AlgLen::Bit512,
+            curve: Nid::SECP521R1,
+            md: MessageDigest::sha512,
+            priv_key: "0FAD06DAA62BA3B25D2FB40133DA757205DE67F5BB0018FEE8C86E1B68C7E75C\
+                       AA896EB32F1F47C70855836A6D16FCC1466F6D8FBEC67DB89EC0C08B0E996B83538",
+            r: "0C328FAFCBD79DD77850370C46325D987CB525569FB63C5D3BC53950E6D4C5F1\
+                74E25A1EE9017B5D450606ADD152B534931D7D4E8455CC91F9B15BF05EC36E377FA",
+            s: "0617CCE7CF5064806C467F678D3B4080D6F1CC50AF26CA209417308281B68AF2\
+                82623EAA63E5B5C0723D8B8C37FF0777B1A20F8CCB1DCCC43997F1EE0E44DA4A67A",
+        },
+    ];
+
+    fn test_key(curve: Nid, priv_key: &str) -> EcKey<Private> {
+        let group = EcGroup::from_curve_name(curve).unwrap();
+        let ctx = BigNumContext::new().unwrap();
+        let priv_key = BigNum::from_hex_str(priv_key).unwrap();
+        let mut pub_key = EcPoint::new(&group).unwrap();
+        pub_key.mul_generator(&group, &priv_key, &ctx).unwrap();
+        EcKey::from_private_components(&group, &priv_key, &pub_key).unwrap()
+    }
+
+    #[test]
+    fn test_known_answers() {
+        for vector in VECTORS {
+            let md = (vector.md)();
+            assert_eq!(vector.algs.size(), md.size());
+            let key = test_key(vector.curve, vector.priv_key);
+            let digest = openssl::hash::hash(md, b"sample").unwrap();
+
+            let sig = sign(md, &digest, &key).unwrap();
+            assert_eq!(BigNum::from_hex_str(vector.r).unwrap(), *sig.r());
+            assert_eq!(BigNum::from_hex_str(vector.s).unwrap(), *sig.s());
+            assert!(sig.verify(&digest, &key).unwrap());
+
+            // The same input always gives the same signature.
+            let again = sign(md, &digest, &key).unwrap();
+            assert_eq!(sig.r(), again.r());
+            assert_eq!(sig.s(), again.s());
+        }
+    }
+
+    /// Signs "sample" with each vector's key through `crypto` and checks the
+    /// signature matches the RFC.
+    #[cfg(any(feature = "rfc6979", feature = "rustcrypto"))]
+    fn check_crypto(crypto: &mut impl crate::Crypto<PrivKey = crate::CryptoBuf>) {
+        use crate::{CryptoBuf, Digest, EcdsaPub};
+
+        for vector in VECTORS {
+            let md = (vector.md)();
+            let key = test_key(vector.curve, vector.priv_key);
+            let digest = Digest::new(&openssl::hash::hash(md, b"sample").unwrap()).unwrap();
+            let size = vector.algs.ecc_int_size() as i32;
+            let priv_key = CryptoBuf::new(&key.private_key().to_vec_padded(size).unwrap()).unwrap();
+
+            let sig = crypto
+                .ecdsa_sign_with_derived(
+                    vector.algs,
+                    &digest,
+                    &priv_key,
+                    &EcdsaPub::default(vector.algs),
+                )
+                .unwrap();
+            assert_eq!(
+                BigNum::from_hex_str(vector.r).unwrap(),
+                BigNum::from_slice(sig.r.bytes()).unwrap()
+            );
+            assert_eq!(
+                BigNum::from_hex_str(vector.s).unwrap(),
+                BigNum::from_slice(sig.s.bytes()).unwrap()
+            );
+        }
+    }
+
+    #[cfg(feature = "rfc6979")]
+    #[test]
+    fn test_openssl_crypto() {
+        check_crypto(&mut crate::OpensslCrypto::new());
+    }
+
+    // RustCrypto always derives nonces as in RFC 6979, so both backends sign identically.
+    #[cfg(feature = "rustcrypto")]
+    #[test]
+    fn test_rustcrypto() {
+        check_crypto(&mut crate::RustCryptoImpl::new());
+    }
+}
@@ -1,20 +1,31 @@
 // Licensed under the Apache-2.0 license
 
 use crate::{
-    hkdf::*, AlgLen, Crypto, CryptoBuf, CryptoError, Digest, EcdsaPub, EcdsaSig, Hasher, HmacSig,
+    hash_state, hkdf::*, wrap_cdi_bytes, AeadKey, AlgLen, Cdi, Crypto, CryptoBuf, CryptoError,
+    Digest, EcdsaPub, EcdsaSig, HashState, Hasher, HkdfKdf, HmacSig, Kdf, AEAD_IV_SIZE,
+    AEAD_TAG_SIZE,
 };
+use aes_gcm::{aead::AeadInPlace, Aes256Gcm, Nonce, Tag};
 use core::ops::Deref;
-use ecdsa::{signature::hazmat::PrehashSigner, Signature};
+use ecdsa::{
+    signature::hazmat::{PrehashSigner, PrehashVerifier},
+    Signature,
+};
 use hmac::{Hmac, Mac};
-use p256::NistP256;
+use p256::{elliptic_curve::sec1::ToEncodedPoint, NistP256};
 use p384::NistP384;
+use p521::NistP521;
 use rand::{rngs::StdRng, RngCore, SeedableRng};
 use sec1::DecodeEcPrivateKey;
-use sha2::{digest::DynDigest, Sha256, Sha384};
+use sha2::{digest::DynDigest, Sha256, Sha384, Sha512};
 use std::boxed::Box;
+use std::collections::BTreeMap;
 
 const RUSTCRYPTO_ECDSA_ERROR: CryptoError = CryptoError::CryptoLibError(1);
 const RUSTCRYPTO_SEC_ERROR: CryptoError = CryptoError::CryptoLibError(2);
+const RUSTCRYPTO_ECDH_ERROR: CryptoError = CryptoError::CryptoLibError(3);
+const RUSTCRYPTO_AEAD_ERROR: CryptoError = CryptoError::CryptoLibError(4);
+const RUSTCRYPTO_KEY_ERROR: CryptoError = CryptoError::CryptoLibError(5);
 
 impl From<ecdsa::Error> for CryptoError {
     fn from(_value: ecdsa::Error) -> Self {
@@ -46,6 +57,15 @@ impl TryFrom<Signature<NistP384>> for EcdsaSig {
         Ok(EcdsaSig { r, s })
     }
 }
+impl TryFrom<Signature<NistP521>> for EcdsaSig {
+    type Error = CryptoError;
+
+    fn try_from(value: Signature<NistP521>) -> Result<Self, Self::Error> {
+        let r = CryptoBuf::new(&value.r().deref().to_bytes())?;
+        let s = CryptoBuf::new(&value.s().deref().to_bytes())?;
+        Ok(EcdsaSig { r, s })
+    }
+}
 
 pub struct RustCryptoHasher(Box<dyn DynDigest>);
 impl Hasher for RustCryptoHasher {
@@ -57,23 +77,43 @@ impl Hasher for RustCryptoHasher {
     }
 }
 
-pub struct RustCryptoImpl(StdRng);
+pub struct RustCryptoImpl {
+    rng: StdRng,
+    kdf: &'static dyn Kdf,
+    /// CDIs kept by `cache_cdi`, by slot.
+    cdi_cache: BTreeMap<usize, Cdi>,
+}
+
 impl RustCryptoImpl {
-    #[cfg(not(feature = "deterministic_rand"))]
+    /// Creates an instance which derives CDIs and keys with HKDF.
     pub fn new() -> Self {
-        RustCryptoImpl(StdRng::from_entropy())
+        Self::with_kdf(&HkdfKdf)
     }
 
+    /// Creates an instance which derives CDIs and keys with `kdf`.
+    #[cfg(not(feature = "deterministic_rand"))]
+    pub fn with_kdf(kdf: &'static dyn Kdf) -> Self {
+        RustCryptoImpl {
+            rng: StdRng::from_entropy(),
+            kdf,
+            cdi_cache: BTreeMap::new(),
+        }
+    }
+
+    /// Creates an instance which derives CDIs and keys with `kdf`.
     #[cfg(feature = "deterministic_rand")]
-    pub fn new() -> Self {
+    pub fn with_kdf(kdf: &'static dyn Kdf) -> Self {
         const SEED: [u8; 32] = [1; 32];
-        let seeded_rng = StdRng::from_seed(SEED);
-        RustCryptoImpl(seeded_rng)
+        RustCryptoImpl {
+            rng: StdRng::from_seed(SEED),
+            kdf,
+            cdi_cache: BTreeMap::new(),
+        }
     }
 }
 
 impl Crypto for RustCryptoImpl {
-    type Cdi = Vec<u8>;
+    type Cdi = Cdi;
     type Hasher<'c>  = RustCryptoHasher where Self: 'c;
     type PrivKey = CryptoBuf;
 
@@ -81,12 +121,54 @@ impl Crypto for RustCryptoImpl {
         let hasher = match algs {
             AlgLen::Bit256 => RustCryptoHasher(Box::new(Sha256::default())),
             AlgLen::Bit384 => RustCryptoHasher(Box::new(Sha384::default())),
+            AlgLen::Bit512 => RustCryptoHasher(Box::new(Sha512::default())),
         };
         Ok(hasher)
     }
 
+    fn hmac(&mut self, algs: AlgLen, key: &[u8], data: &[u8]) -> Result<Digest, CryptoError> {
+        match algs {
+            AlgLen::Bit256 => {
+                let mut hmac = Hmac::<Sha256>::new_from_slice(key).unwrap();
+                Mac::update(&mut hmac, data);
+                Digest::new(hmac.finalize().into_bytes().as_slice())
+            }
+            AlgLen::Bit384 => {
+                let mut hmac = Hmac::<Sha384>::new_from_slice(key).unwrap();
+                Mac::update(&mut hmac, data);
+                Digest::new(hmac.finalize().into_bytes().as_slice())
+            }
+            AlgLen::Bit512 => {
+                let mut hmac = Hmac::<Sha512>::new_from_slice(key).unwrap();
+                Mac::update(&mut hmac, data);
+                Digest::new(hmac.finalize().into_bytes().as_slice())
+            }
+        }
+    }
+
+    fn hash_state_initialize(&mut self, algs: AlgLen) -> Result<HashState, CryptoError> {
+        Ok(hash_state::hash_state_initialize(algs))
+    }
+
+    fn hash_state_update(
+        &mut self,
+        algs: AlgLen,
+        state: &mut HashState,
+        bytes: &[u8],
+    ) -> Result<(), CryptoError> {
+        hash_state::hash_state_update(algs, state, bytes)
+    }
+
+    fn hash_state_finish(
+        &mut self,
+        algs: AlgLen,
+        state: &HashState,
+    ) -> Result<Digest, CryptoError> {
+        hash_state::hash_state_finish(algs, state)
+    }
+
     fn rand_bytes(&mut self, dst: &mut [u8]) -> Result<(), CryptoError> {
-        StdRng::fill_bytes(&mut self.0, dst);
+        StdRng::fill_bytes(&mut self.rng, dst);
         Ok(())
     }
 
@@ -96,7 +178,21 @@ impl Crypto for RustCryptoImpl {
         measurement: &Digest,
         info: &[u8],
     ) -> Result<Self::Cdi, CryptoError> {
-        hkdf_derive_cdi(algs, measurement, info)
+        self.kdf.derive_cdi(algs, measurement, info)
+    }
+
+    fn cache_cdi(&mut self, slot: usize, cdi: &Self::Cdi) -> Result<(), CryptoError> {
+        self.cdi_cache.insert(slot, Cdi::new(cdi.bytes())?);
+        Ok(())
+    }
+
+    fn cached_cdi(&mut self, slot: usize) -> Result<Self::Cdi, CryptoError> {
+        let cdi = self.cdi_cache.get(&slot).ok_or(CryptoError::Size)?;
+        Cdi::new(cdi.bytes())
+    }
+
+    fn clear_cdi_cache(&mut self) {
+        self.cdi_cache.clear();
     }
 
     fn derive_key_pair(
@@ -106,7 +202,7 @@ impl Crypto for RustCryptoImpl {
         label: &[u8],
         info: &[u8],
     ) -> Result<(Self::PrivKey, EcdsaPub), CryptoError> {
-        let secret = hkdf_get_priv_key(algs, cdi, label, info)?;
+        let secret = self.kdf.derive_priv_key(algs, cdi.bytes(), label, info)?;
         match algs {
             AlgLen::Bit256 => {
                 let signing = p256::ecdsa::SigningKey::from_slice(&secret.bytes())?;
@@ -124,6 +220,16 @@ impl Crypto for RustCryptoImpl {
                 let y = CryptoBuf::new(point.y().ok_or(RUSTCRYPTO_ECDSA_ERROR)?.as_slice())?;
                 Ok((secret, EcdsaPub { x, y }))
             }
+            AlgLen::Bit512 => {
+                // p521 wraps its signing key, so derive the point from the
+                // secret key instead.
+                let secret_key = p521::SecretKey::from_slice(secret.bytes())
+                    .map_err(|_| RUSTCRYPTO_KEY_ERROR)?;
+                let point = secret_key.public_key().to_encoded_point(false);
+                let x = CryptoBuf::new(point.x().ok_or(RUSTCRYPTO_ECDSA_ERROR)?.as_slice())?;
+                let y = CryptoBuf::new(point.y().ok_or(RUSTCRYPTO_ECDSA_ERROR)?.as_slice())?;
+                Ok((secret, EcdsaPub { x, y }))
+            }
         }
     }
 
@@ -149,6 +255,16 @@ impl Crypto for RustCryptoImpl {
                 let sig: p384::ecdsa::Signature = signing_key.sign_prehash(digest.bytes())?;
                 sig.try_into()
             }
+            AlgLen::Bit512 => {
+                let secret_key = p521::SecretKey::from_sec1_pem(include_str!(concat!(
+                    env!("OUT_DIR"),
+                    "/alias_priv_521.pem"
+                )))
+                .map_err(|_| RUSTCRYPTO_KEY_ERROR)?;
+                let signing_key = p521::ecdsa::SigningKey::from_bytes(&secret_key.to_bytes())?;
+                let sig: p521::ecdsa::Signature = signing_key.sign_prehash(digest.bytes())?;
+                sig.try_into()
+            }
         }
     }
 
@@ -172,9 +288,62 @@ impl Crypto for RustCryptoImpl {
                         .sign_prehash(digest.bytes())?;
                 sig.try_into()
             }
+            AlgLen::Bit512 => {
+                let sig: p521::ecdsa::Signature =
+                    p521::ecdsa::SigningKey::from_slice(priv_key.bytes())?
+                        .sign_prehash(digest.bytes())?;
+                sig.try_into()
+            }
         }
     }
 
+    fn ecdsa_verify(
+        &mut self,
+        algs: AlgLen,
+        digest: &Digest,
+        pub_key: &EcdsaPub,
+        sig: &EcdsaSig,
+    ) -> Result<(), CryptoError> {
+        let size = algs.ecc_int_size();
+        if sig.r.len() != size || sig.s.len() != size {
+            return Err(CryptoError::AuthenticationFailed);
+        }
+        let mut sec1 = vec![0x04u8];
+        sec1.extend_from_slice(pub_key.x.bytes());
+        sec1.extend_from_slice(pub_key.y.bytes());
+        let mut rs = sig.r.bytes().to_vec();
+        rs.extend_from_slice(sig.s.bytes());
+        // Scalars that are zero or not below the curve order are rejected
+        // when the signature is parsed.
+        let valid = match algs {
+            AlgLen::Bit256 => {
+                let key = p256::ecdsa::VerifyingKey::from_sec1_bytes(&sec1)
+                    .map_err(|_| RUSTCRYPTO_KEY_ERROR)?;
+                p256::ecdsa::Signature::from_slice(&rs)
+                    .and_then(|sig| key.verify_prehash(digest.bytes(), &sig))
+                    .is_ok()
+            }
+            AlgLen::Bit384 => {
+                let key = p384::ecdsa::VerifyingKey::from_sec1_bytes(&sec1)
+                    .map_err(|_| RUSTCRYPTO_KEY_ERROR)?;
+                p384::ecdsa::Signature::from_slice(&rs)
+                    .and_then(|sig| key.verify_prehash(digest.bytes(), &sig))
+                    .is_ok()
+            }
+            AlgLen::Bit512 => {
+                let key = p521::ecdsa::VerifyingKey::from_sec1_bytes(&sec1)
+                    .map_err(|_| RUSTCRYPTO_KEY_ERROR)?;
+                p521::ecdsa::Signature::from_slice(&rs)
+                    .and_then(|sig| key.verify_prehash(digest.bytes(), &sig))
+                    .is_ok()
+            }
+        };
+        if !valid {
+            return Err(CryptoError::AuthenticationFailed);
+        }
+        Ok(())
+    }
+
     fn hmac_sign_with_derived(
         &mut self,
         algs: AlgLen,
@@ -195,6 +364,302 @@ impl Crypto for RustCryptoImpl {
                 Mac::update(&mut hmac, digest.bytes());
                 HmacSig::new(hmac.finalize().into_bytes().as_slice())
             }
+            AlgLen::Bit512 => {
+                let mut hmac = Hmac::<Sha512>::new_from_slice(symmetric_key.bytes()).unwrap();
+                Mac::update(&mut hmac, digest.bytes());
+                HmacSig::new(hmac.finalize().into_bytes().as_slice())
+            }
+        }
+    }
+
+    fn ecdh_derive_key(
+        &mut self,
+        algs: AlgLen,
+        peer_pub: &EcdsaPub,
+        info: &[u8],
+    ) -> Result<(AeadKey, EcdsaPub), CryptoError> {
+        let mut peer_sec1 = vec![0x04u8];
+        peer_sec1.extend_from_slice(peer_pub.x.bytes());
+        peer_sec1.extend_from_slice(peer_pub.y.bytes());
+        match algs {
+            AlgLen::Bit256 => {
+                let peer = p256::PublicKey::from_sec1_bytes(&peer_sec1)
+                    .map_err(|_| RUSTCRYPTO_ECDH_ERROR)?;
+                let ephemeral = p256::ecdh::EphemeralSecret::random(&mut self.rng);
+                let point = ephemeral.public_key().to_encoded_point(false);
+                let x = CryptoBuf::new(point.x().ok_or(RUSTCRYPTO_ECDH_ERROR)?.as_slice())?;
+                let y = CryptoBuf::new(point.y().ok_or(RUSTCRYPTO_ECDH_ERROR)?.as_slice())?;
+                let shared_secret = ephemeral.diffie_hellman(&peer);
+                let key =
+                    hkdf_derive_aead_key(algs, shared_secret.raw_secret_bytes().as_slice(), info)?;
+                Ok((key, EcdsaPub { x, y }))
+            }
+            AlgLen::Bit384 => {
+                let peer = p384::PublicKey::from_sec1_bytes(&peer_sec1)
+                    .map_err(|_| RUSTCRYPTO_ECDH_ERROR)?;
+                let ephemeral = p384::ecdh::EphemeralSecret::random(&mut self.rng);
+                let point = ephemeral.public_key().to_encoded_point(false);
+                let x = CryptoBuf::new(point.x().ok_or(RUSTCRYPTO_ECDH_ERROR)?.as_slice())?;
+                let y = CryptoBuf::new(point.y().ok_or(RUSTCRYPTO_ECDH_ERROR)?.as_slice())?;
+                let shared_secret = ephemeral.diffie_hellman(&peer);
+                let key =
+                    hkdf_derive_aead_key(algs, shared_secret.raw_secret_bytes().as_slice(), info)?;
+                Ok((key, EcdsaPub { x, y }))
+            }
+            AlgLen::Bit512 => {
+                let peer = p521::PublicKey::from_sec1_bytes(&peer_sec1)
+                    .map_err(|_| RUSTCRYPTO_ECDH_ERROR)?;
+                let ephemeral = p521::ecdh::EphemeralSecret::random(&mut self.rng);
+                let point = ephemeral.public_key().to_encoded_point(false);
+                let x = CryptoBuf::new(point.x().ok_or(RUSTCRYPTO_ECDH_ERROR)?.as_slice())?;
+                let y = CryptoBuf::new(point.y().ok_or(RUSTCRYPTO_ECDH_ERROR)?.as_slice())?;
+                let shared_secret = ephemeral.diffie_hellman(&peer);
+                let key =
+                    hkdf_derive_aead_key(algs, shared_secret.raw_secret_bytes().as_slice(), info)?;
+                Ok((key, EcdsaPub { x, y }))
+            }
         }
     }
+
+    fn aead_encrypt(
+        &mut self,
+        key: &AeadKey,
+        iv: &[u8; AEAD_IV_SIZE],
+        aad: &[u8],
+        data: &mut [u8],
+    ) -> Result<[u8; AEAD_TAG_SIZE], CryptoError> {
+        // Fully qualified since `Mac` also provides `new_from_slice`.
+        let cipher = <Aes256Gcm as aes_gcm::KeyInit>::new_from_slice(&key.0)
+            .map_err(|_| CryptoError::Size)?;
+        let tag = cipher
+            .encrypt_in_place_detached(Nonce::from_slice(iv), aad, data)
+            .map_err(|_| RUSTCRYPTO_AEAD_ERROR)?;
+        Ok(tag.into())
+    }
+
+    fn aead_decrypt(
+        &mut self,
+        key: &AeadKey,
+        iv: &[u8; AEAD_IV_SIZE],
+        aad: &[u8],
+        data: &mut [u8],
+        tag: &[u8; AEAD_TAG_SIZE],
+    ) -> Result<(), CryptoError> {
+        let cipher = <Aes256Gcm as aes_gcm::KeyInit>::new_from_slice(&key.0)
+            .map_err(|_| CryptoError::Size)?;
+        cipher
+            .decrypt_in_place_detached(Nonce::from_slice(iv), aad, data, Tag::from_slice(tag))
+            .map_err(|_| CryptoError::AuthenticationFailed)
+    }
+
+    fn derive_aead_key(
+        &mut self,
+        algs: AlgLen,
+        cdi: &Self::Cdi,
+        label: &[u8],
+        info: &[u8],
+    ) -> Result<AeadKey, CryptoError> {
+        self.kdf.derive_aead_key(algs, cdi.bytes(), label, info)
+    }
+
+    fn wrap_cdi(
+        &mut self,
+        cdi: &Self::Cdi,
+        key: &AeadKey,
+        aad: &[u8],
+        wrapped: &mut [u8],
+    ) -> Result<([u8; AEAD_IV_SIZE], [u8; AEAD_TAG_SIZE]), CryptoError> {
+        wrap_cdi_bytes(self, cdi.bytes(), key, aad, wrapped)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEST_CDI_INFO: &[u8] = b"cdi info";
+    const TEST_LABEL: &[u8] = b"label";
+    const TEST_INFO: &[u8] = b"info";
+
+    crate::crypto_kat_tests!(RustCryptoImpl::new());
+
+    #[test]
+    fn test_wycheproof_ecdsa_verify() {
+        crate::wycheproof_tests::check_ecdsa_verify(&mut RustCryptoImpl::new());
+    }
+
+    fn test_cdi(crypto: &mut RustCryptoImpl, algs: AlgLen) -> Cdi {
+        let measurement = Digest::new(&vec![0xaa; algs.size()]).unwrap();
+        crypto
+            .derive_cdi(algs, &measurement, TEST_CDI_INFO)
+            .unwrap()
+    }
+
+    fn sec1_bytes(pub_key: &EcdsaPub) -> Vec<u8> {
+        let mut sec1 = vec![0x04u8];
+        sec1.extend_from_slice(pub_key.x.bytes());
+        sec1.extend_from_slice(pub_key.y.bytes());
+        sec1
+    }
+
+    fn raw_sig(sig: &EcdsaSig) -> Vec<u8> {
+        let mut raw = sig.r.bytes().to_vec();
+        raw.extend_from_slice(sig.s.bytes());
+        raw
+    }
+
+    #[test]
+    fn test_derive_key_pair_is_deterministic() {
+        let mut crypto = RustCryptoImpl::new();
+        for algs in [AlgLen::Bit256, AlgLen::Bit384, AlgLen::Bit512] {
+            let cdi = test_cdi(&mut crypto, algs);
+            let (priv_1, pub_1) = crypto
+                .derive_key_pair(algs, &cdi, TEST_LABEL, TEST_INFO)
+                .unwrap();
+            let (priv_2, pub_2) = crypto
+                .derive_key_pair(algs, &cdi, TEST_LABEL, TEST_INFO)
+                .unwrap();
+            assert_eq!(priv_1.bytes(), priv_2.bytes());
+            assert_eq!(pub_1.x.bytes(), pub_2.x.bytes());
+            assert_eq!(pub_1.y.bytes(), pub_2.y.bytes());
+
+            let (_, pub_3) = crypto
+                .derive_key_pair(algs, &cdi, b"other label", TEST_INFO)
+                .unwrap();
+            assert_ne!(pub_1.x.bytes(), pub_3.x.bytes());
+        }
+    }
+
+    #[test]
+    fn test_sign_with_derived_verifies() {
+        let mut crypto = RustCryptoImpl::new();
+
+        let algs = AlgLen::Bit256;
+        let cdi = test_cdi(&mut crypto, algs);
+        let digest = Digest::new(&[0x55; 32]).unwrap();
+        let (priv_key, pub_key) = crypto
+            .derive_key_pair(algs, &cdi, TEST_LABEL, TEST_INFO)
+            .unwrap();
+        let sig = crypto
+            .ecdsa_sign_with_derived(algs, &digest, &priv_key, &pub_key)
+            .unwrap();
+        let verifying = p256::ecdsa::VerifyingKey::from_sec1_bytes(&sec1_bytes(&pub_key)).unwrap();
+        let sig = p256::ecdsa::Signature::from_slice(&raw_sig(&sig)).unwrap();
+        assert!(verifying.verify_prehash(digest.bytes(), &sig).is_ok());
+
+        let algs = AlgLen::Bit384;
+        let cdi = test_cdi(&mut crypto, algs);
+        let digest = Digest::new(&[0x55; 48]).unwrap();
+        let (priv_key, pub_key) = crypto
+            .derive_key_pair(algs, &cdi, TEST_LABEL, TEST_INFO)
+            .unwrap();
+        let sig = crypto
+            .ecdsa_sign_with_derived(algs, &digest, &priv_key, &pub_key)
+            .unwrap();
+        let verifying = p384::ecdsa::VerifyingKey::from_sec1_bytes(&sec1_bytes(&pub_key)).unwrap();
+        let sig = p384::ecdsa::Signature::from_slice(&raw_sig(&sig)).unwrap();
+        assert!(verifying.verify_prehash(digest.bytes(), &sig).is_ok());
+
+        let algs = AlgLen::Bit512;
+        let cdi = test_cdi(&mut crypto, algs);
+        let digest = Digest::new(&[0x55; 64]).unwrap();
+        let (priv_key, pub_key) = crypto
+            .derive_key_pair(algs, &cdi, TEST_LABEL, TEST_INFO)
+            .unwrap();
+        assert_eq!(pub_key.x.len(), algs.ecc_int_size());
+        let sig = crypto
+            .ecdsa_sign_with_derived(algs, &digest, &priv_key, &pub_key)
+            .unwrap();
+        let verifying = p521::ecdsa::VerifyingKey::from_sec1_bytes(&sec1_bytes(&pub_key)).unwrap();
+        let sig = p521::ecdsa::Signature::from_slice(&raw_sig(&sig)).unwrap();
+        assert!(verifying.verify_prehash(digest.bytes(), &sig).is_ok());
+    }
+
+    #[test]
+    fn test_hmac_sign_with_derived_is_deterministic() {
+        let mut crypto = RustCryptoImpl::new();
+        for algs in [AlgLen::Bit256, AlgLen::Bit384, AlgLen::Bit512] {
+            let cdi = test_cdi(&mut crypto, algs);
+            let digest = Digest::new(&vec![0x55; algs.size()]).unwrap();
+            let sig_1 = crypto
+                .hmac_sign_with_derived(algs, &cdi, TEST_LABEL, TEST_INFO, &digest)
+                .unwrap();
+            let sig_2 = crypto
+                .hmac_sign_with_derived(algs, &cdi, TEST_LABEL, TEST_INFO, &digest)
+                .unwrap();
+            assert_eq!(sig_1.bytes(), sig_2.bytes());
+            assert_eq!(sig_1.bytes().len(), algs.size());
+        }
+    }
+
+    #[test]
+    fn test_ecdh_derive_key_matches_peer() {
+        let mut crypto = RustCryptoImpl::new();
+        let peer_secret = p256::ecdh::EphemeralSecret::random(&mut crypto.0);
+        let point = peer_secret.public_key().to_encoded_point(false);
+        let peer_pub = EcdsaPub {
+            x: CryptoBuf::new(point.x().unwrap().as_slice()).unwrap(),
+            y: CryptoBuf::new(point.y().unwrap().as_slice()).unwrap(),
+        };
+
+        let (key, dpe_pub) = crypto
+            .ecdh_derive_key(AlgLen::Bit256, &peer_pub, TEST_INFO)
+            .unwrap();
+
+        let dpe_pub = p256::PublicKey::from_sec1_bytes(&sec1_bytes(&dpe_pub)).unwrap();
+        let shared_secret = peer_secret.diffie_hellman(&dpe_pub);
+        let peer_key = hkdf_derive_aead_key(
+            AlgLen::Bit256,
+            shared_secret.raw_secret_bytes().as_slice(),
+            TEST_INFO,
+        )
+        .unwrap();
+        assert_eq!(key.0, peer_key.0);
+    }
+
+    #[test]
+    fn test_aead_round_trip() {
+        let mut crypto = RustCryptoImpl::new();
+        let key = AeadKey([0x11; 32]);
+        let iv = [0x22; AEAD_IV_SIZE];
+        let plaintext = *b"plaintext";
+
+        let mut data = plaintext;
+        let tag = crypto.aead_encrypt(&key, &iv, b"aad", &mut data).unwrap();
+        assert_ne!(data, plaintext);
+
+        // Authentication fails if the additional data differs.
+        let mut tampered = data;
+        assert!(crypto
+            .aead_decrypt(&key, &iv, b"bad", &mut tampered, &tag)
+            .is_err());
+
+        crypto
+            .aead_decrypt(&key, &iv, b"aad", &mut data, &tag)
+            .unwrap();
+        assert_eq!(data, plaintext);
+    }
+
+    #[test]
+    fn test_wrap_cdi() {
+        let mut crypto = RustCryptoImpl::new();
+        let key = AeadKey([0x11; 32]);
+        let cdi = test_cdi(&mut crypto, AlgLen::Bit384);
+
+        let mut wrapped = [0u8; 48];
+        let (iv, tag) = crypto.wrap_cdi(&cdi, &key, b"aad", &mut wrapped).unwrap();
+        assert_ne!(wrapped, cdi.bytes());
+
+        crypto
+            .aead_decrypt(&key, &iv, b"aad", &mut wrapped, &tag)
+            .unwrap();
+        assert_eq!(wrapped, cdi.bytes());
+
+        // The output has to fit the CDI exactly.
+        let mut short = [0u8; 32];
+        assert_eq!(
+            crypto.wrap_cdi(&cdi, &key, b"aad", &mut short),
+            Err(CryptoError::Size)
+        );
+    }
 }
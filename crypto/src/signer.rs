@@ -2,7 +2,7 @@
 
 use crate::{AlgLen, CryptoError};
 use arrayvec::ArrayVec;
-use zeroize::ZeroizeOnDrop;
+use zeroize::{Zeroize, ZeroizeOnDrop};
 
 /// An ECDSA signature
 pub struct EcdsaSig {
@@ -10,6 +10,221 @@ pub struct EcdsaSig {
     pub s: CryptoBuf,
 }
 
+/// Encodings `EcdsaSig::serialize` can produce and `EcdsaSig::deserialize`
+/// accepts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EcdsaSigFormat {
+    /// The fixed-width integers as they appear in DPE responses, R || S.
+    Raw,
+    /// A DER ECDSA-Sig-Value, as carried in certificates and expected by
+    /// most verifiers.
+    ///
+    /// ECDSA-Sig-Value ::= SEQUENCE {
+    ///     r  INTEGER,
+    ///     s  INTEGER
+    ///   }
+    Der,
+}
+
+const DER_SEQUENCE: u8 = 0x30;
+const DER_INTEGER: u8 = 0x02;
+/// Prefix of a DER length that doesn't fit in the length byte itself.
+const DER_LONG_LENGTH_1: u8 = 0x81;
+
+/// Returns `int` without its leading zero bytes, keeping at least one byte.
+fn strip_leading_zeros(int: &[u8]) -> &[u8] {
+    let start = int
+        .iter()
+        .position(|b| *b != 0)
+        .unwrap_or(int.len().saturating_sub(1));
+    &int[start..]
+}
+
+/// Returns the size of the content of the minimal DER INTEGER holding the
+/// unsigned big-endian `int`.
+fn der_integer_content_size(int: &[u8]) -> usize {
+    let int = strip_leading_zeros(int);
+    match int.first() {
+        // A leading 1 bit would make the INTEGER negative.
+        Some(b) if b & 0x80 != 0 => int.len() + 1,
+        Some(_) => int.len(),
+        None => 1,
+    }
+}
+
+/// Returns the size of the DER length field for `len` content bytes.
+fn der_length_size(len: usize) -> usize {
+    if len < 0x80 {
+        1
+    } else {
+        2
+    }
+}
+
+impl EcdsaSig {
+    /// Largest encoding `serialize` can produce.
+    pub const MAX_SERIALIZED_SIZE: usize = 3 + 2 * (2 + 1 + CryptoBuf::MAX_SIZE);
+
+    /// Returns the size of the signature encoded as `format`.
+    pub fn serialized_size(&self, format: EcdsaSigFormat) -> Result<usize, CryptoError> {
+        Ok(match format {
+            EcdsaSigFormat::Raw => 2 * self.r.len().max(self.s.len()),
+            EcdsaSigFormat::Der => {
+                let seq_size = self.der_seq_content_size();
+                // The sequence holds at most two 67-byte integers, so its
+                // length always fits in the byte after 0x81.
+                1 + der_length_size(seq_size) + seq_size
+            }
+        })
+    }
+
+    fn der_seq_content_size(&self) -> usize {
+        [self.r.bytes(), self.s.bytes()]
+            .iter()
+            .map(|int| 2 + der_integer_content_size(int))
+            .sum()
+    }
+
+    /// Writes the signature to the start of `out` encoded as `format` and
+    /// returns the size of the encoding.
+    ///
+    /// `Raw` left-pads the shorter of `r` and `s` to the width of the other.
+    /// `Der` writes each as the shortest INTEGER that holds it.
+    pub fn serialize(&self, format: EcdsaSigFormat, out: &mut [u8]) -> Result<usize, CryptoError> {
+        let size = self.serialized_size(format)?;
+        let out = out.get_mut(..size).ok_or(CryptoError::Size)?;
+        let (r, s) = (self.r.bytes(), self.s.bytes());
+
+        match format {
+            EcdsaSigFormat::Raw => {
+                let (r_out, s_out) = out.split_at_mut(size / 2);
+                for (int, dst) in [(r, r_out), (s, s_out)] {
+                    let (pad, value) = dst.split_at_mut(dst.len() - int.len());
+                    pad.fill(0);
+                    value.copy_from_slice(int);
+                }
+            }
+            EcdsaSigFormat::Der => {
+                let mut writer = out.iter_mut();
+                let mut write = |bytes: &[u8]| {
+                    for (src, dst) in bytes.iter().zip(writer.by_ref()) {
+                        *dst = *src;
+                    }
+                };
+                let seq_size = self.der_seq_content_size();
+                write(&[DER_SEQUENCE]);
+                if der_length_size(seq_size) == 2 {
+                    write(&[DER_LONG_LENGTH_1]);
+                }
+                write(&[seq_size as u8]);
+                for int in [r, s] {
+                    let content_size = der_integer_content_size(int);
+                    let int = strip_leading_zeros(int);
+                    write(&[DER_INTEGER, content_size as u8]);
+                    if content_size > int.len() {
+                        write(&[0]);
+                    }
+                    write(int);
+                }
+            }
+        }
+        Ok(size)
+    }
+
+    /// Parses a signature on the curve used with `algs` from `bytes` encoded
+    /// as `format`.
+    ///
+    /// `r` and `s` of the result are always `algs.ecc_int_size()` bytes
+    /// long, with short integers padded with leading zeros. DER must be
+    /// minimal and not followed by anything. Returns
+    /// `CryptoError::InvalidEncoding` otherwise, or if an integer is negative
+    /// or too wide for the curve.
+    pub fn deserialize(
+        algs: AlgLen,
+        format: EcdsaSigFormat,
+        bytes: &[u8],
+    ) -> Result<EcdsaSig, CryptoError> {
+        let int_size = algs.ecc_int_size();
+        let (r, s) = match format {
+            EcdsaSigFormat::Raw => {
+                if bytes.len() != 2 * int_size {
+                    return Err(CryptoError::InvalidEncoding);
+                }
+                bytes.split_at(int_size)
+            }
+            EcdsaSigFormat::Der => {
+                let (&tag, rest) = bytes.split_first().ok_or(CryptoError::InvalidEncoding)?;
+                if tag != DER_SEQUENCE {
+                    return Err(CryptoError::InvalidEncoding);
+                }
+                let (seq, rest) = read_der_length(rest)?;
+                if !rest.is_empty() {
+                    return Err(CryptoError::InvalidEncoding);
+                }
+                let (r, seq) = read_der_integer(seq)?;
+                let (s, seq) = read_der_integer(seq)?;
+                if !seq.is_empty() {
+                    return Err(CryptoError::InvalidEncoding);
+                }
+                (r, s)
+            }
+        };
+        Ok(EcdsaSig {
+            r: left_pad(r, int_size)?,
+            s: left_pad(s, int_size)?,
+        })
+    }
+}
+
+/// Splits the content described by the DER length at the start of `bytes`
+/// from what follows it.
+fn read_der_length(bytes: &[u8]) -> Result<(&[u8], &[u8]), CryptoError> {
+    let (&first, rest) = bytes.split_first().ok_or(CryptoError::InvalidEncoding)?;
+    let (len, rest) = match first {
+        len if len < 0x80 => (usize::from(len), rest),
+        DER_LONG_LENGTH_1 => {
+            let (&len, rest) = rest.split_first().ok_or(CryptoError::InvalidEncoding)?;
+            // DER requires the short form wherever it fits.
+            if len < 0x80 {
+                return Err(CryptoError::InvalidEncoding);
+            }
+            (usize::from(len), rest)
+        }
+        _ => return Err(CryptoError::InvalidEncoding),
+    };
+    if len > rest.len() {
+        return Err(CryptoError::InvalidEncoding);
+    }
+    Ok(rest.split_at(len))
+}
+
+/// Reads a non-negative, minimally encoded DER INTEGER from the start of
+/// `bytes` and returns its magnitude and what follows it.
+fn read_der_integer(bytes: &[u8]) -> Result<(&[u8], &[u8]), CryptoError> {
+    let (&tag, rest) = bytes.split_first().ok_or(CryptoError::InvalidEncoding)?;
+    if tag != DER_INTEGER {
+        return Err(CryptoError::InvalidEncoding);
+    }
+    let (int, rest) = read_der_length(rest)?;
+    match int {
+        [] => Err(CryptoError::InvalidEncoding),
+        [first, ..] if first & 0x80 != 0 => Err(CryptoError::InvalidEncoding),
+        [0, second, ..] if second & 0x80 == 0 => Err(CryptoError::InvalidEncoding),
+        [0, magnitude @ ..] if !magnitude.is_empty() => Ok((magnitude, rest)),
+        _ => Ok((int, rest)),
+    }
+}
+
+/// Returns `int` padded with leading zeros to `width` bytes.
+fn left_pad(int: &[u8], width: usize) -> Result<CryptoBuf, CryptoError> {
+    let pad = width
+        .checked_sub(int.len())
+        .ok_or(CryptoError::InvalidEncoding)?;
+    let mut buf = CryptoBuf::zeroed(width);
+    buf.0[pad..].copy_from_slice(int);
+    Ok(buf)
+}
+
 /// An ECDSA public key
 #[derive(ZeroizeOnDrop)]
 pub struct EcdsaPub {
@@ -17,11 +232,101 @@ pub struct EcdsaPub {
     pub y: CryptoBuf,
 }
 
+/// Encodings `EcdsaPub::serialize` can produce.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EcdsaPubFormat {
+    /// The coordinates as they appear in DPE responses, X || Y.
+    Raw,
+    /// The SEC1 uncompressed point, 0x04 || X || Y.
+    Sec1Uncompressed,
+    /// The SEC1 compressed point, 0x02 or 0x03 for the parity of Y, then X.
+    Sec1Compressed,
+    /// A DER SubjectPublicKeyInfo holding the uncompressed point.
+    Spki,
+}
+
+const SEC1_UNCOMPRESSED: u8 = 0x04;
+const SEC1_COMPRESSED_EVEN: u8 = 0x02;
+
+// The DER of a SubjectPublicKeyInfo up to the uncompressed point, for each
+// curve: the id-ecPublicKey algorithm, the named curve and the BIT STRING
+// header.
+const SPKI_PREFIX_P256: &[u8] = &[
+    0x30, 0x59, 0x30, 0x13, 0x06, 0x07, 0x2a, 0x86, 0x48, 0xce, 0x3d, 0x02, 0x01, 0x06, 0x08, 0x2a,
+    0x86, 0x48, 0xce, 0x3d, 0x03, 0x01, 0x07, 0x03, 0x42, 0x00,
+];
+const SPKI_PREFIX_P384: &[u8] = &[
+    0x30, 0x76, 0x30, 0x10, 0x06, 0x07, 0x2a, 0x86, 0x48, 0xce, 0x3d, 0x02, 0x01, 0x06, 0x05, 0x2b,
+    0x81, 0x04, 0x00, 0x22, 0x03, 0x62, 0x00,
+];
+const SPKI_PREFIX_P521: &[u8] = &[
+    0x30, 0x81, 0x9b, 0x30, 0x10, 0x06, 0x07, 0x2a, 0x86, 0x48, 0xce, 0x3d, 0x02, 0x01, 0x06, 0x05,
+    0x2b, 0x81, 0x04, 0x00, 0x23, 0x03, 0x81, 0x86, 0x00,
+];
+
 impl EcdsaPub {
     pub fn default(alg: AlgLen) -> EcdsaPub {
         EcdsaPub {
-            x: CryptoBuf::default(alg),
-            y: CryptoBuf::default(alg),
+            x: CryptoBuf::zeroed(alg.ecc_int_size()),
+            y: CryptoBuf::zeroed(alg.ecc_int_size()),
+        }
+    }
+
+    /// Largest encoding `serialize` can produce.
+    pub const MAX_SERIALIZED_SIZE: usize = SPKI_PREFIX_P521.len() + 1 + 2 * CryptoBuf::MAX_SIZE;
+
+    /// Returns the size of the key encoded as `format`.
+    pub fn serialized_size(&self, format: EcdsaPubFormat) -> Result<usize, CryptoError> {
+        let int_size = self.x.len();
+        Ok(match format {
+            EcdsaPubFormat::Raw => 2 * int_size,
+            EcdsaPubFormat::Sec1Uncompressed => 1 + 2 * int_size,
+            EcdsaPubFormat::Sec1Compressed => 1 + int_size,
+            EcdsaPubFormat::Spki => self.spki_prefix()?.len() + 1 + 2 * int_size,
+        })
+    }
+
+    /// Writes the key to the start of `out` encoded as `format` and returns
+    /// the size of the encoding.
+    pub fn serialize(&self, format: EcdsaPubFormat, out: &mut [u8]) -> Result<usize, CryptoError> {
+        let (x, y) = (self.x.bytes(), self.y.bytes());
+        if x.len() != y.len() {
+            return Err(CryptoError::Size);
+        }
+        let size = self.serialized_size(format)?;
+        let out = out.get_mut(..size).ok_or(CryptoError::Size)?;
+
+        let mut writer = out.iter_mut();
+        let mut write = |bytes: &[u8]| {
+            for (src, dst) in bytes.iter().zip(writer.by_ref()) {
+                *dst = *src;
+            }
+        };
+        match format {
+            EcdsaPubFormat::Raw => {}
+            EcdsaPubFormat::Sec1Uncompressed => write(&[SEC1_UNCOMPRESSED]),
+            EcdsaPubFormat::Sec1Compressed => {
+                let odd = y.last().map_or(0, |b| b & 1);
+                write(&[SEC1_COMPRESSED_EVEN | odd]);
+                write(x);
+                return Ok(size);
+            }
+            EcdsaPubFormat::Spki => {
+                write(self.spki_prefix()?);
+                write(&[SEC1_UNCOMPRESSED]);
+            }
+        }
+        write(x);
+        write(y);
+        Ok(size)
+    }
+
+    fn spki_prefix(&self) -> Result<&'static [u8], CryptoError> {
+        match self.x.len() {
+            n if n == AlgLen::Bit256.ecc_int_size() => Ok(SPKI_PREFIX_P256),
+            n if n == AlgLen::Bit384.ecc_int_size() => Ok(SPKI_PREFIX_P384),
+            n if n == AlgLen::Bit512.ecc_int_size() => Ok(SPKI_PREFIX_P521),
+            _ => Err(CryptoError::Size),
         }
     }
 }
@@ -29,12 +334,173 @@ impl EcdsaPub {
 /// An HMAC Signature
 pub type HmacSig = CryptoBuf;
 
-/// A common base struct that can be used for all digests, signatures, and keys.
+/// An ML-DSA-87 public key
+#[cfg(feature = "ml_dsa")]
 #[derive(Debug, PartialEq, Eq, ZeroizeOnDrop)]
+pub struct MlDsaPub(ArrayVec<u8, { Self::SIZE }>);
+
+#[cfg(feature = "ml_dsa")]
+impl MlDsaPub {
+    pub const SIZE: usize = 2592;
+
+    pub fn new(bytes: &[u8]) -> Result<MlDsaPub, CryptoError> {
+        let mut vec = ArrayVec::new();
+        vec.try_extend_from_slice(bytes)
+            .map_err(|_| CryptoError::Size)?;
+        Ok(MlDsaPub(vec))
+    }
+
+    pub fn bytes(&self) -> &[u8] {
+        self.0.as_slice()
+    }
+}
+
+/// An ML-DSA-87 signature
+#[cfg(feature = "ml_dsa")]
+#[derive(Debug, PartialEq, Eq)]
+pub struct MlDsaSig(ArrayVec<u8, { Self::SIZE }>);
+
+#[cfg(feature = "ml_dsa")]
+impl MlDsaSig {
+    pub const SIZE: usize = 4627;
+
+    pub fn new(bytes: &[u8]) -> Result<MlDsaSig, CryptoError> {
+        let mut vec = ArrayVec::new();
+        vec.try_extend_from_slice(bytes)
+            .map_err(|_| CryptoError::Size)?;
+        Ok(MlDsaSig(vec))
+    }
+
+    pub fn bytes(&self) -> &[u8] {
+        self.0.as_slice()
+    }
+}
+
+/// The modulus sizes supported for derived RSA keys
+#[cfg(feature = "rsa")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RsaKeySize {
+    Bit3072,
+    Bit4096,
+}
+
+#[cfg(feature = "rsa")]
+impl RsaKeySize {
+    pub const fn bits(self) -> usize {
+        match self {
+            RsaKeySize::Bit3072 => 3072,
+            RsaKeySize::Bit4096 => 4096,
+        }
+    }
+
+    /// Size of the modulus, and of signatures, in bytes
+    pub const fn modulus_size(self) -> usize {
+        self.bits() / 8
+    }
+}
+
+/// The RSA signature schemes
+#[cfg(feature = "rsa")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RsaPadding {
+    /// RSASSA-PKCS1-v1_5
+    Pkcs1v15,
+    /// RSASSA-PSS with MGF1 over the same hash as the message and a salt as
+    /// long as the digest
+    Pss,
+}
+
+/// An RSA public key. Derived keys always use `RsaPub::EXPONENT` as public
+/// exponent, so only the big-endian modulus is held.
+#[cfg(feature = "rsa")]
+#[derive(Debug, PartialEq, Eq)]
+pub struct RsaPub(ArrayVec<u8, { Self::MAX_SIZE }>);
+
+#[cfg(feature = "rsa")]
+impl RsaPub {
+    pub const MAX_SIZE: usize = RsaKeySize::Bit4096.modulus_size();
+    pub const EXPONENT: u32 = 65537;
+
+    pub fn new(modulus: &[u8]) -> Result<RsaPub, CryptoError> {
+        let mut vec = ArrayVec::new();
+        vec.try_extend_from_slice(modulus)
+            .map_err(|_| CryptoError::Size)?;
+        Ok(RsaPub(vec))
+    }
+
+    pub fn modulus(&self) -> &[u8] {
+        self.0.as_slice()
+    }
+}
+
+/// An RSA signature
+#[cfg(feature = "rsa")]
+#[derive(Debug, PartialEq, Eq)]
+pub struct RsaSig(ArrayVec<u8, { Self::MAX_SIZE }>);
+
+#[cfg(feature = "rsa")]
+impl RsaSig {
+    pub const MAX_SIZE: usize = RsaPub::MAX_SIZE;
+
+    pub fn new(bytes: &[u8]) -> Result<RsaSig, CryptoError> {
+        let mut vec = ArrayVec::new();
+        vec.try_extend_from_slice(bytes)
+            .map_err(|_| CryptoError::Size)?;
+        Ok(RsaSig(vec))
+    }
+
+    pub fn bytes(&self) -> &[u8] {
+        self.0.as_slice()
+    }
+}
+
+/// An Ed25519 public key, the 32-byte encoding from RFC 8032
+#[cfg(feature = "ed25519")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Ed25519Pub(pub [u8; Self::SIZE]);
+
+#[cfg(feature = "ed25519")]
+impl Ed25519Pub {
+    pub const SIZE: usize = 32;
+
+    pub fn new(bytes: &[u8]) -> Result<Ed25519Pub, CryptoError> {
+        Ok(Ed25519Pub(bytes.try_into().map_err(|_| CryptoError::Size)?))
+    }
+
+    pub fn bytes(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+/// An Ed25519 signature, R || S
+#[cfg(feature = "ed25519")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Ed25519Sig(pub [u8; Self::SIZE]);
+
+#[cfg(feature = "ed25519")]
+impl Ed25519Sig {
+    pub const SIZE: usize = 64;
+
+    pub fn new(bytes: &[u8]) -> Result<Ed25519Sig, CryptoError> {
+        Ok(Ed25519Sig(bytes.try_into().map_err(|_| CryptoError::Size)?))
+    }
+
+    pub fn bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    /// Splits the signature into the encoded point R and the scalar S.
+    pub fn r_and_s(&self) -> (&[u8], &[u8]) {
+        self.0.split_at(Self::SIZE / 2)
+    }
+}
+
+/// A common base struct that can be used for all digests, signatures, and keys.
+#[derive(Debug, PartialEq, Eq, Zeroize, ZeroizeOnDrop)]
 pub struct CryptoBuf(ArrayVec<u8, { Self::MAX_SIZE }>);
 
 impl CryptoBuf {
-    pub const MAX_SIZE: usize = AlgLen::MAX_ALG_LEN_BYTES;
+    pub const MAX_SIZE: usize = AlgLen::MAX_ECC_INT_BYTES;
 
     pub fn new(bytes: &[u8]) -> Result<CryptoBuf, CryptoError> {
         let mut vec = ArrayVec::new();
@@ -44,8 +510,13 @@ impl CryptoBuf {
     }
 
     pub fn default(algs: AlgLen) -> CryptoBuf {
+        Self::zeroed(algs.size())
+    }
+
+    /// Returns `len` zero bytes. `len` must not exceed `MAX_SIZE`.
+    pub(crate) fn zeroed(len: usize) -> CryptoBuf {
         let mut vec = ArrayVec::new();
-        for _ in 0..algs.size() {
+        for _ in 0..len {
             vec.push(0);
         }
         CryptoBuf(vec)
@@ -115,5 +586,259 @@ mod tests {
         // test default
         let default_buf = CryptoBuf::default(AlgLen::Bit384);
         assert_eq!(default_buf.bytes(), [0; AlgLen::Bit384.size()]);
+
+        // P-521 coordinates are wider than the digest
+        let default_pub = EcdsaPub::default(AlgLen::Bit512);
+        assert_eq!(default_pub.x.bytes(), [0; AlgLen::Bit512.ecc_int_size()]);
+    }
+
+    fn sig(r: &[u8], s: &[u8]) -> EcdsaSig {
+        EcdsaSig {
+            r: CryptoBuf::new(r).unwrap(),
+            s: CryptoBuf::new(s).unwrap(),
+        }
+    }
+
+    fn serialize_sig(sig: &EcdsaSig, format: EcdsaSigFormat) -> Vec<u8> {
+        let mut out = [0u8; EcdsaSig::MAX_SERIALIZED_SIZE];
+        let len = sig.serialize(format, &mut out).unwrap();
+        assert_eq!(Ok(len), sig.serialized_size(format));
+        out[..len].to_vec()
+    }
+
+    #[test]
+    fn test_ecdsa_sig_der_integers() {
+        let mut r = [0u8; 32];
+        r[31] = 1;
+        let mut s = [0u8; 32];
+        s[1] = 0x80;
+        let der = serialize_sig(&sig(&r, &s), EcdsaSigFormat::Der);
+        // r loses its leading zeros and s gains a zero so it stays positive.
+        let mut expected = vec![0x30, 3 + 34, 0x02, 0x01, 0x01, 0x02, 32, 0x00, 0x80];
+        expected.extend_from_slice(&[0; 30]);
+        assert_eq!(der, expected);
+
+        let parsed = EcdsaSig::deserialize(AlgLen::Bit256, EcdsaSigFormat::Der, &der).unwrap();
+        assert_eq!(parsed.r.bytes(), r);
+        assert_eq!(parsed.s.bytes(), s);
+
+        // Zero is a single zero byte.
+        let der = serialize_sig(&sig(&[0; 32], &[0; 32]), EcdsaSigFormat::Der);
+        assert_eq!(der, [0x30, 6, 0x02, 1, 0, 0x02, 1, 0]);
+
+        // P-521 signatures need the long form of the sequence length.
+        let der = serialize_sig(&sig(&[0xff; 66], &[0xff; 66]), EcdsaSigFormat::Der);
+        assert_eq!(der[..3], [0x30, 0x81, 2 * 69]);
+        assert_eq!(der.len(), EcdsaSig::MAX_SERIALIZED_SIZE);
+        let parsed = EcdsaSig::deserialize(AlgLen::Bit512, EcdsaSigFormat::Der, &der).unwrap();
+        assert_eq!(parsed.r.bytes(), [0xff; 66]);
+    }
+
+    #[test]
+    fn test_ecdsa_sig_raw() {
+        let raw = serialize_sig(&sig(&[1; 48], &[0x80; 46]), EcdsaSigFormat::Raw);
+        assert_eq!(raw[..48], [1; 48]);
+        assert_eq!(raw[48..50], [0, 0]);
+        assert_eq!(raw[50..], [0x80; 46]);
+
+        let parsed = EcdsaSig::deserialize(AlgLen::Bit384, EcdsaSigFormat::Raw, &raw).unwrap();
+        assert_eq!(parsed.r.bytes(), [1; 48]);
+        assert_eq!(parsed.s.bytes(), &raw[48..]);
+        assert!(matches!(
+            EcdsaSig::deserialize(AlgLen::Bit256, EcdsaSigFormat::Raw, &raw),
+            Err(CryptoError::InvalidEncoding)
+        ));
+    }
+
+    #[test]
+    fn test_ecdsa_sig_malformed_der() {
+        let invalid: &[&[u8]] = &[
+            &[],
+            // Not a sequence.
+            &[0x31, 6, 0x02, 1, 1, 0x02, 1, 1],
+            // Trailing data, inside and after the sequence.
+            &[0x30, 7, 0x02, 1, 1, 0x02, 1, 1, 0],
+            &[0x30, 6, 0x02, 1, 1, 0x02, 1, 1, 0],
+            // Length longer than the data.
+            &[0x30, 7, 0x02, 1, 1, 0x02, 1, 1],
+            // Long form where the short form fits.
+            &[0x30, 0x81, 6, 0x02, 1, 1, 0x02, 1, 1],
+            // Empty, negative and non-minimal integers.
+            &[0x30, 5, 0x02, 0, 0x02, 1, 1],
+            &[0x30, 6, 0x02, 1, 0x80, 0x02, 1, 1],
+            &[0x30, 7, 0x02, 2, 0, 1, 0x02, 1, 1],
+            // Only one integer.
+            &[0x30, 3, 0x02, 1, 1],
+        ];
+        for der in invalid {
+            assert!(
+                matches!(
+                    EcdsaSig::deserialize(AlgLen::Bit256, EcdsaSigFormat::Der, der),
+                    Err(CryptoError::InvalidEncoding)
+                ),
+                "{der:02x?}"
+            );
+        }
+
+        // An integer wider than the curve.
+        let wide = serialize_sig(&sig(&[1; 33], &[1]), EcdsaSigFormat::Der);
+        assert!(matches!(
+            EcdsaSig::deserialize(AlgLen::Bit256, EcdsaSigFormat::Der, &wide),
+            Err(CryptoError::InvalidEncoding)
+        ));
+    }
+
+    #[cfg(feature = "openssl")]
+    #[test]
+    fn test_ecdsa_sig_openssl_interop() {
+        use openssl::{bn::BigNum, ec::EcGroup, ec::EcKey, ecdsa, nid::Nid};
+        use strum::IntoEnumIterator;
+
+        for alg in AlgLen::iter() {
+            let nid = match alg {
+                AlgLen::Bit256 => Nid::X9_62_PRIME256V1,
+                AlgLen::Bit384 => Nid::SECP384R1,
+                AlgLen::Bit512 => Nid::SECP521R1,
+            };
+            let group = EcGroup::from_curve_name(nid).unwrap();
+            let key = EcKey::generate(&group).unwrap();
+            let int_size = alg.ecc_int_size() as i32;
+            // Sign repeatedly so that integers with the top bit set, and
+            // likely some with leading zeros, come up.
+            for i in 0..64u8 {
+                let digest = [i; 32];
+                let expected = ecdsa::EcdsaSig::sign(&digest, &key).unwrap();
+                let expected_der = expected.to_der().unwrap();
+
+                let parsed =
+                    EcdsaSig::deserialize(alg, EcdsaSigFormat::Der, &expected_der).unwrap();
+                assert_eq!(
+                    parsed.r.bytes(),
+                    expected.r().to_vec_padded(int_size).unwrap()
+                );
+                assert_eq!(
+                    parsed.s.bytes(),
+                    expected.s().to_vec_padded(int_size).unwrap()
+                );
+                assert_eq!(serialize_sig(&parsed, EcdsaSigFormat::Der), expected_der);
+
+                let raw = serialize_sig(&parsed, EcdsaSigFormat::Raw);
+                let from_raw = EcdsaSig::deserialize(alg, EcdsaSigFormat::Raw, &raw).unwrap();
+                let der = serialize_sig(&from_raw, EcdsaSigFormat::Der);
+                let sig = ecdsa::EcdsaSig::from_der(&der).unwrap();
+                assert!(sig.verify(&digest, &key).unwrap());
+                assert_eq!(
+                    sig.r().to_owned().unwrap(),
+                    BigNum::from_slice(parsed.r.bytes()).unwrap()
+                );
+            }
+        }
+    }
+
+    #[cfg(feature = "openssl")]
+    #[test]
+    fn test_ecdsa_pub_serialize() {
+        use openssl::{
+            bn::BigNumContext,
+            ec::{EcGroup, EcKey, EcPoint, PointConversionForm},
+            nid::Nid,
+            pkey::PKey,
+        };
+        use strum::IntoEnumIterator;
+
+        for alg in AlgLen::iter() {
+            let nid = match alg {
+                AlgLen::Bit256 => Nid::X9_62_PRIME256V1,
+                AlgLen::Bit384 => Nid::SECP384R1,
+                AlgLen::Bit512 => Nid::SECP521R1,
+            };
+            let group = EcGroup::from_curve_name(nid).unwrap();
+            let key = EcKey::generate(&group).unwrap();
+            let mut ctx = BigNumContext::new().unwrap();
+            let mut encode = |form| key.public_key().to_bytes(&group, form, &mut ctx).unwrap();
+            let uncompressed = encode(PointConversionForm::UNCOMPRESSED);
+            let compressed = encode(PointConversionForm::COMPRESSED);
+
+            let int_size = alg.ecc_int_size();
+            let pub_key = EcdsaPub {
+                x: CryptoBuf::new(&uncompressed[1..1 + int_size]).unwrap(),
+                y: CryptoBuf::new(&uncompressed[1 + int_size..]).unwrap(),
+            };
+            let mut out = [0u8; EcdsaPub::MAX_SERIALIZED_SIZE];
+            let mut serialize = |format| {
+                let len = pub_key.serialize(format, &mut out).unwrap();
+                assert_eq!(Ok(len), pub_key.serialized_size(format));
+                out[..len].to_vec()
+            };
+
+            assert_eq!(serialize(EcdsaPubFormat::Raw), uncompressed[1..], "{alg:?}");
+            assert_eq!(serialize(EcdsaPubFormat::Sec1Uncompressed), uncompressed);
+            assert_eq!(serialize(EcdsaPubFormat::Sec1Compressed), compressed);
+            let point = EcPoint::from_bytes(&group, &compressed, &mut ctx).unwrap();
+            assert!(point.eq(&group, key.public_key(), &mut ctx).unwrap());
+
+            let spki = serialize(EcdsaPubFormat::Spki);
+            let expected = PKey::from_ec_key(key.clone()).unwrap();
+            assert_eq!(spki, expected.public_key_to_der().unwrap(), "{alg:?}");
+            let parsed = PKey::public_key_from_der(&spki).unwrap();
+            assert!(parsed.public_eq(&expected));
+        }
+
+        // The output must be large enough.
+        let pub_key = EcdsaPub::default(AlgLen::Bit256);
+        assert_eq!(
+            Err(CryptoError::Size),
+            pub_key.serialize(EcdsaPubFormat::Spki, &mut [0u8; 90])
+        );
+    }
+
+    #[cfg(feature = "ml_dsa")]
+    #[test]
+    fn test_ml_dsa_buf_init() {
+        assert_eq!(
+            MlDsaPub::new(&[1u8; MlDsaPub::SIZE + 1]),
+            Err(CryptoError::Size)
+        );
+        assert_eq!(
+            MlDsaSig::new(&[1u8; MlDsaSig::SIZE + 1]),
+            Err(CryptoError::Size)
+        );
+
+        let pub_key = MlDsaPub::new(&[2u8; MlDsaPub::SIZE]).unwrap();
+        assert_eq!(pub_key.bytes(), [2u8; MlDsaPub::SIZE]);
+        let sig = MlDsaSig::new(&[3u8; MlDsaSig::SIZE]).unwrap();
+        assert_eq!(sig.bytes(), [3u8; MlDsaSig::SIZE]);
+    }
+
+    #[cfg(feature = "rsa")]
+    #[test]
+    fn test_rsa_buf_init() {
+        assert_eq!(
+            RsaPub::new(&[1u8; RsaPub::MAX_SIZE + 1]),
+            Err(CryptoError::Size)
+        );
+        assert_eq!(
+            RsaSig::new(&[1u8; RsaSig::MAX_SIZE + 1]),
+            Err(CryptoError::Size)
+        );
+
+        let size = RsaKeySize::Bit3072;
+        let pub_key = RsaPub::new(&[2u8; RsaKeySize::Bit3072.modulus_size()]).unwrap();
+        assert_eq!(pub_key.modulus().len() * 8, size.bits());
+        assert_eq!(RsaKeySize::Bit4096.modulus_size(), RsaPub::MAX_SIZE);
+    }
+
+    #[cfg(feature = "ed25519")]
+    #[test]
+    fn test_ed25519_buf_init() {
+        assert_eq!(Ed25519Pub::new(&[1u8; 31]), Err(CryptoError::Size));
+        assert_eq!(Ed25519Sig::new(&[1u8; 65]), Err(CryptoError::Size));
+
+        let pub_key = Ed25519Pub::new(&[2u8; Ed25519Pub::SIZE]).unwrap();
+        assert_eq!(pub_key.bytes(), [2u8; Ed25519Pub::SIZE]);
+        let mut bytes = [3u8; Ed25519Sig::SIZE];
+        bytes[32..].fill(4);
+        let sig = Ed25519Sig::new(&bytes).unwrap();
+        assert_eq!(sig.r_and_s(), (&[3u8; 32][..], &[4u8; 32][..]));
     }
 }
@@ -0,0 +1,56 @@
+// Licensed under the Apache-2.0 license
+
+//! Negative tests for the `Crypto` backends, driven by Project Wycheproof.
+//!
+//! The vectors pair valid signatures with malformed ones: zero or
+//! out-of-range scalars, truncated and padded encodings, and digests at the
+//! edges of the curve order. Each backend runs them from its own tests so a
+//! bug in how one library checks signatures can't hide behind the other.
+
+use crate::{AlgLen, Crypto, CryptoBuf, EcdsaPub, EcdsaSig};
+use wycheproof::{ecdsa::TestName, ecdsa::TestSet, TestResult};
+
+const ECDSA_TEST_SETS: &[(AlgLen, TestName)] = &[
+    (AlgLen::Bit256, TestName::EcdsaSecp256r1Sha256P1363),
+    (AlgLen::Bit384, TestName::EcdsaSecp384r1Sha384P1363),
+    (AlgLen::Bit512, TestName::EcdsaSecp521r1Sha512P1363),
+];
+
+/// Checks that `crypto` accepts every valid Wycheproof ECDSA signature on
+/// the DPE curves and rejects every invalid one.
+///
+/// The signatures are in the fixed-width r || s form `EcdsaSig` holds.
+/// Vectors Wycheproof marks acceptable are skipped.
+pub fn check_ecdsa_verify<C: Crypto>(crypto: &mut C) {
+    for (algs, name) in ECDSA_TEST_SETS {
+        let algs = *algs;
+        let test_set = TestSet::load(*name).unwrap();
+        for group in test_set.test_groups {
+            let point = &group.key.key[..];
+            assert_eq!(point.len(), 1 + 2 * algs.ecc_int_size());
+            let (x, y) = point[1..].split_at(algs.ecc_int_size());
+            let pub_key = EcdsaPub {
+                x: CryptoBuf::new(x).unwrap(),
+                y: CryptoBuf::new(y).unwrap(),
+            };
+
+            for test in group.tests {
+                let tc_id = test.tc_id;
+                let (r, s) = test.sig.split_at(test.sig.len() / 2);
+                let (Ok(r), Ok(s)) = (CryptoBuf::new(r), CryptoBuf::new(s)) else {
+                    // Too long for any signature on the curve.
+                    assert!(matches!(test.result, TestResult::Invalid), "tcId {tc_id}");
+                    continue;
+                };
+                let sig = EcdsaSig { r, s };
+                let digest = crypto.hash(algs, &test.msg[..]).unwrap();
+                let res = crypto.ecdsa_verify(algs, &digest, &pub_key, &sig);
+                match test.result {
+                    TestResult::Valid => assert_eq!(res, Ok(()), "tcId {tc_id}"),
+                    TestResult::Invalid => assert!(res.is_err(), "tcId {tc_id}"),
+                    TestResult::Acceptable => (),
+                }
+            }
+        }
+    }
+}
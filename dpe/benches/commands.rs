@@ -0,0 +1,92 @@
+// Licensed under the Apache-2.0 license
+
+//! Throughput of DeriveContext, Sign and CertifyKey at the top of the
+//! context tree and at the bottom of a chain that uses every context slot.
+//! Commands the profile or crypto backend does not support are skipped.
+//!
+//! The profile and backend are fixed at build time, so run once per
+//! combination:
+//!
+//! ```text
+//! cargo bench -p dpe --bench commands
+//! cargo bench -p dpe --bench commands --features rustcrypto
+//! cargo bench -p dpe --bench commands --no-default-features --features dpe_profile_p384_sha384,no-cfi
+//! ```
+
+mod common;
+
+use common::{bench_env, chain, copy, profile_name, supported, BACKEND};
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion};
+use dpe::{
+    commands::{
+        CertifyKeyCmd, CertifyKeyFlags, CommandExecution, DeriveContextCmd, DeriveContextFlags,
+        SignCmd, SignFlags,
+    },
+    context::ContextHandle,
+    support::Support,
+    DPE_PROFILE, MAX_HANDLES,
+};
+use platform::default::AUTO_INIT_LOCALITY;
+
+const SUPPORT: Support = Support::X509;
+
+/// Chain depths to measure. DeriveContext adds one more context, so its
+/// deepest chain leaves room for it.
+const DEPTHS: [usize; 2] = [1, MAX_HANDLES];
+
+fn bench_commands(c: &mut Criterion) {
+    let mut env = bench_env();
+    let mut group = c.benchmark_group(format!("{}/{BACKEND}", profile_name()));
+
+    for depth in DEPTHS {
+        let derive_depth = depth.min(MAX_HANDLES - 1);
+        let dpe = chain(&mut env, SUPPORT, derive_depth);
+        let cmd = DeriveContextCmd {
+            handle: ContextHandle::default(),
+            data: [0xAA; DPE_PROFILE.get_hash_size()],
+            flags: DeriveContextFlags::MAKE_DEFAULT,
+            tci_type: 0,
+            target_locality: AUTO_INIT_LOCALITY,
+        };
+        group.bench_function(BenchmarkId::new("derive_context", derive_depth), |b| {
+            b.iter_batched(
+                || copy(&dpe),
+                |mut dpe| cmd.execute(&mut dpe, &mut env, AUTO_INIT_LOCALITY).unwrap(),
+                BatchSize::SmallInput,
+            )
+        });
+
+        let mut dpe = chain(&mut env, SUPPORT, depth);
+        let cmd = SignCmd {
+            handle: ContextHandle::default(),
+            label: [0; DPE_PROFILE.get_hash_size()],
+            flags: SignFlags::empty(),
+            digest: [0; DPE_PROFILE.get_hash_size()],
+        };
+        if supported("sign", cmd.execute(&mut dpe, &mut env, AUTO_INIT_LOCALITY)) {
+            group.bench_function(BenchmarkId::new("sign", depth), |b| {
+                b.iter(|| cmd.execute(&mut dpe, &mut env, AUTO_INIT_LOCALITY).unwrap())
+            });
+        }
+
+        let cmd = CertifyKeyCmd {
+            handle: ContextHandle::default(),
+            flags: CertifyKeyFlags::empty(),
+            format: CertifyKeyCmd::FORMAT_X509,
+            label: [0; DPE_PROFILE.get_hash_size()],
+        };
+        if supported(
+            "certify_key",
+            cmd.execute(&mut dpe, &mut env, AUTO_INIT_LOCALITY),
+        ) {
+            group.bench_function(BenchmarkId::new("certify_key", depth), |b| {
+                b.iter(|| cmd.execute(&mut dpe, &mut env, AUTO_INIT_LOCALITY).unwrap())
+            });
+        }
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_commands);
+criterion_main!(benches);
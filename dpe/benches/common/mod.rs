@@ -0,0 +1,93 @@
+// Licensed under the Apache-2.0 license
+
+//! Helpers shared by the benchmarks. Each bench is its own crate and uses a
+//! subset of them.
+#![allow(dead_code)]
+
+#[cfg(not(feature = "rustcrypto"))]
+use crypto::OpensslCrypto as BenchCrypto;
+#[cfg(feature = "rustcrypto")]
+use crypto::RustCryptoImpl as BenchCrypto;
+use dpe::{
+    commands::{CommandExecution, DeriveContextCmd, DeriveContextFlags},
+    context::ContextHandle,
+    dpe_instance::{DpeEnv, DpeTypes},
+    response::DpeErrorCode,
+    support::Support,
+    DpeInstance, DpeProfile, DPE_PROFILE,
+};
+use platform::default::{DefaultPlatform, AUTO_INIT_LOCALITY};
+use zerocopy::{AsBytes, FromBytes};
+
+/// Benchmarks run against OpenSSL unless the `rustcrypto` feature selects
+/// the RustCrypto backend.
+pub struct BenchTypes;
+
+impl DpeTypes for BenchTypes {
+    type Crypto<'a> = BenchCrypto;
+    type Platform<'a> = DefaultPlatform;
+}
+
+/// Name of the crypto backend, used in benchmark IDs.
+#[cfg(not(feature = "rustcrypto"))]
+pub const BACKEND: &str = "openssl";
+#[cfg(feature = "rustcrypto")]
+pub const BACKEND: &str = "rustcrypto";
+
+pub fn bench_env() -> DpeEnv<'static, BenchTypes> {
+    DpeEnv {
+        crypto: BenchCrypto::new(),
+        platform: DefaultPlatform,
+        arena: &mut [],
+    }
+}
+
+/// Name of the profile this build was compiled for, used in benchmark IDs.
+pub const fn profile_name() -> &'static str {
+    match DPE_PROFILE {
+        DpeProfile::P256Sha256 => "p256",
+        DpeProfile::P384Sha384 => "p384",
+        DpeProfile::Mldsa87Sha384 => "mldsa87",
+        DpeProfile::P521Sha512 => "p521",
+        DpeProfile::Ed25519Sha512 => "ed25519",
+    }
+}
+
+/// Returns an auto-initialized instance whose default context sits at the
+/// bottom of a chain `depth` contexts long.
+pub fn chain(env: &mut DpeEnv<impl DpeTypes>, support: Support, depth: usize) -> DpeInstance {
+    let mut dpe = DpeInstance::new(env, Support::AUTO_INIT | support).unwrap();
+    for i in 1..depth {
+        DeriveContextCmd {
+            handle: ContextHandle::default(),
+            data: [i as u8; DPE_PROFILE.get_hash_size()],
+            flags: DeriveContextFlags::MAKE_DEFAULT,
+            tci_type: i as u32,
+            target_locality: AUTO_INIT_LOCALITY,
+        }
+        .execute(&mut dpe, env, AUTO_INIT_LOCALITY)
+        .unwrap();
+    }
+    dpe
+}
+
+/// Copies an instance so commands that consume contexts can be measured
+/// against the same starting state.
+pub fn copy(dpe: &DpeInstance) -> DpeInstance {
+    DpeInstance::read_from(dpe.as_bytes()).unwrap()
+}
+
+/// Runs a command once and reports whether the profile and backend support
+/// it, so benchmarks can skip commands that would fail on every iteration.
+pub fn supported<T>(name: &str, result: Result<T, DpeErrorCode>) -> bool {
+    match result {
+        Ok(_) => true,
+        Err(err) => {
+            eprintln!(
+                "skipping {name}: not supported by {}/{BACKEND} ({err:?})",
+                profile_name()
+            );
+            false
+        }
+    }
+}
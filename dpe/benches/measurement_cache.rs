@@ -0,0 +1,48 @@
+// Licensed under the Apache-2.0 license
+
+//! Compares Sign latency at the bottom of a full context chain with and
+//! without the measurement cache. With the cache, OpenSSL keeps the CDI as
+//! well, so the cached case skips both the chain walk and the CDI KDF.
+//!
+//! Run with `cargo bench -p dpe --bench measurement_cache`, adding
+//! `--features rustcrypto` to measure the RustCrypto backend.
+
+mod common;
+
+use common::{bench_env, chain, supported};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use dpe::{
+    commands::{CommandExecution, SignCmd, SignFlags},
+    context::ContextHandle,
+    support::Support,
+    DPE_PROFILE, MAX_HANDLES,
+};
+use platform::default::AUTO_INIT_LOCALITY;
+
+fn bench_sign(c: &mut Criterion) {
+    let mut env = bench_env();
+    let cmd = SignCmd {
+        handle: ContextHandle::default(),
+        label: [0; DPE_PROFILE.get_hash_size()],
+        flags: SignFlags::empty(),
+        digest: [0; DPE_PROFILE.get_hash_size()],
+    };
+
+    let mut group = c.benchmark_group("sign_deep_chain");
+    for (name, support) in [
+        ("uncached", Support::empty()),
+        ("cached", Support::MEASUREMENT_CACHE),
+    ] {
+        let mut dpe = chain(&mut env, support, MAX_HANDLES);
+        if !supported("sign", cmd.execute(&mut dpe, &mut env, AUTO_INIT_LOCALITY)) {
+            break;
+        }
+        group.bench_function(BenchmarkId::from_parameter(name), |b| {
+            b.iter(|| cmd.execute(&mut dpe, &mut env, AUTO_INIT_LOCALITY).unwrap())
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_sign);
+criterion_main!(benches);
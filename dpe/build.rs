@@ -7,10 +7,20 @@ use std::io::Write;
 fn main() {
     let default_value: usize = 24;
 
-    let arbitrary_max_handles = env::var("ARBITRARY_MAX_HANDLES")
-        .ok()
-        .and_then(|value| value.parse().ok())
-        .unwrap_or(default_value);
+    // Context children are tracked in a u64 bitmap, so there can be at most 64 contexts.
+    let max_value: usize = 64;
+
+    let arbitrary_max_handles = match env::var("ARBITRARY_MAX_HANDLES") {
+        Ok(value) => value
+            .parse()
+            .unwrap_or_else(|_| panic!("ARBITRARY_MAX_HANDLES must be a number, got {value:?}")),
+        Err(_) => default_value,
+    };
+    if !(1..=max_value).contains(&arbitrary_max_handles) {
+        panic!(
+            "ARBITRARY_MAX_HANDLES must be between 1 and {max_value}, got {arbitrary_max_handles}"
+        );
+    }
 
     let out_dir = env::var("OUT_DIR").unwrap();
     let dest_path = format!("{}/arbitrary_max_handles.rs", out_dir);
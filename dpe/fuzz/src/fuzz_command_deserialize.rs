@@ -0,0 +1,110 @@
+// Licensed under the Apache-2.0 license
+
+#![cfg_attr(feature = "libfuzzer-sys", no_main)]
+
+#[cfg(all(not(feature = "libfuzzer-sys"), not(feature = "afl")))]
+compile_error!("Either feature \"libfuzzer-sys\" or \"afl\" must be enabled!");
+
+#[cfg(feature = "libfuzzer-sys")]
+use libfuzzer_sys::fuzz_target;
+
+#[cfg(feature = "afl")]
+use afl::fuzz;
+
+use dpe::commands::{Command, CommandHdr, SignFlags};
+use zerocopy::AsBytes;
+
+/// Returns the serialized parameters of `command`.
+fn command_body(command: &Command) -> Vec<u8> {
+    let body: &[u8] = match command {
+        Command::GetProfile => &[],
+        Command::InitCtx(cmd, seed) => {
+            let mut body = cmd.as_bytes().to_vec();
+            // Only commands with `InitCtxCmd::SEED_FLAG_MASK` carry one.
+            if let Some(seed) = seed {
+                body.extend_from_slice(seed);
+            }
+            return body;
+        }
+        Command::DeriveContext(cmd, svn) => {
+            let mut body = cmd.as_bytes().to_vec();
+            // Only commands with `DeriveContextFlags::INPUT_SVN` carry one.
+            if let Some(svn) = svn {
+                body.extend_from_slice(&svn.to_le_bytes());
+            }
+            return body;
+        }
+        Command::CertifyKey(cmd) => cmd.as_bytes(),
+        Command::Sign(cmd, message) => {
+            let mut body = cmd.as_bytes().to_vec();
+            if cmd.flags.contains(SignFlags::IS_MESSAGE) {
+                body.extend_from_slice(&(message.len() as u32).to_le_bytes());
+                body.extend_from_slice(message);
+            }
+            return body;
+        }
+        Command::RotateCtx(cmd) => cmd.as_bytes(),
+        Command::DestroyCtx(cmd) => cmd.as_bytes(),
+        Command::GetCertificateChain(cmd) => cmd.as_bytes(),
+        Command::ExtendTci(cmd) => cmd.as_bytes(),
+        Command::TagTci(cmd) => cmd.as_bytes(),
+        Command::GetTaggedTci(cmd) => cmd.as_bytes(),
+        Command::OpenSession(cmd) => cmd.as_bytes(),
+        Command::CloseSession(cmd) => cmd.as_bytes(),
+        Command::GetCertificateChunk(cmd) => cmd.as_bytes(),
+        Command::ExportCdi(cmd) => cmd.as_bytes(),
+        Command::GetCommandCounter(cmd) => cmd.as_bytes(),
+        Command::HashInitialize(_) => &[],
+        Command::HashUpdate(cmd, data) => {
+            let mut body = cmd.as_bytes().to_vec();
+            body.extend_from_slice(data);
+            return body;
+        }
+        Command::HashFinal(cmd) => cmd.as_bytes(),
+        Command::GetContextInfo(cmd) => cmd.as_bytes(),
+        Command::Attest(cmd) => cmd.as_bytes(),
+        Command::Seal(cmd, data) => {
+            let mut body = cmd.as_bytes().to_vec();
+            body.extend_from_slice(data);
+            return body;
+        }
+        Command::Unseal(cmd, sealed) => {
+            let mut body = cmd.as_bytes().to_vec();
+            body.extend_from_slice(sealed);
+            return body;
+        }
+        Command::Vendor(_, body) => body,
+    };
+    body.to_vec()
+}
+
+// Parsing must never panic, and anything it accepts must survive a
+// serialize/deserialize round trip unchanged.
+fn harness(data: &[u8]) {
+    let Ok(command) = Command::deserialize(data) else {
+        return;
+    };
+
+    let body = command_body(&command);
+    let hdr = CommandHdr::try_from(data).unwrap();
+    let mut serialized = hdr.as_bytes().to_vec();
+    serialized.extend_from_slice(&body);
+
+    // Trailing bytes are ignored, so the command is a prefix of the input.
+    assert!(data.starts_with(&serialized));
+    assert_eq!(Command::deserialize(&serialized).ok(), Some(command));
+}
+
+// cargo-fuzz target
+#[cfg(feature = "libfuzzer-sys")]
+fuzz_target!(|data: &[u8]| {
+    harness(data);
+});
+
+// cargo-afl target
+#[cfg(feature = "afl")]
+fn main() {
+    fuzz!(|data: &[u8]| {
+        harness(data);
+    });
+}
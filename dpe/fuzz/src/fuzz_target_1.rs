@@ -54,26 +54,38 @@ fn harness(data: &[u8]) {
     trace!("----------------------------------");
     if let Ok(command) = Command::deserialize(data) {
         trace!("| Fuzzer's locality requested {command:x?}");
-        trace!("|");
     } else {
         trace!("| Fuzzer's locality requested invalid command. {data:02x?}");
-        trace!("----------------------------------");
-        return;
     }
+    trace!("|");
 
     let mut env = DpeEnv::<SimTypes> {
         crypto: OpensslCrypto::new(),
         platform: DefaultPlatform,
+        arena: &mut [],
     };
     let mut dpe = DpeInstance::new(&mut env, SUPPORT).unwrap();
     let prev_contexts = dpe.contexts;
 
     // Hard-code working locality
-    let response = dpe
-        .execute_serialized_command(&mut env, AUTO_INIT_LOCALITY, data)
-        .unwrap();
+    let response_code = match dpe.execute_serialized_command(&mut env, AUTO_INIT_LOCALITY, data) {
+        Ok(response) => response_status(&response),
+        // Malformed commands are rejected before they are executed.
+        Err(err) => err.get_error_code(),
+    };
+    // There are a few vendor error codes starting at 0x1000, so this can be a 2 bytes.
+    trace!("| Response Code {response_code:#06x}");
+    if dpe.contexts != prev_contexts && response_code != 0 {
+        panic!("Error: DPE state changes upon a failed DPE command.");
+    }
+    if response_code == DpeErrorCode::InternalError.discriminant() {
+        panic!("Error: DPE reached a state that should be unreachable.");
+    }
+    trace!("----------------------------------");
+}
 
-    let response_code = match response {
+fn response_status(response: &Response) -> u32 {
+    match *response {
         Response::GetProfile(ref res) => res.resp_hdr.status,
         Response::InitCtx(ref res) => res.resp_hdr.status,
         Response::DeriveContext(ref res) => res.resp_hdr.status,
@@ -82,17 +94,25 @@ fn harness(data: &[u8]) {
         Response::Sign(ref res) => res.resp_hdr.status,
         Response::DestroyCtx(ref resp_hdr) => resp_hdr.status,
         Response::GetCertificateChain(ref res) => res.resp_hdr.status,
+        Response::ExtendTci(ref res) => res.resp_hdr.status,
+        Response::TagTci(ref res) => res.resp_hdr.status,
+        Response::GetTaggedTci(ref res) => res.resp_hdr.status,
+        Response::OpenSession(ref res) => res.resp_hdr.status,
+        Response::CloseSession(ref resp_hdr) => resp_hdr.status,
+        Response::GetCertificateChunk(ref res) => res.resp_hdr.status,
+        Response::ExportCdi(ref res) => res.resp_hdr.status,
+        Response::GetCommandCounter(ref res) => res.resp_hdr.status,
+        Response::HashInitialize(ref res) => res.resp_hdr.status,
+        Response::HashUpdate(ref resp_hdr) => resp_hdr.status,
+        Response::HashFinal(ref res) => res.resp_hdr.status,
+        Response::GetContextInfo(ref res) => res.resp_hdr.status,
+        Response::Attest(ref res) => res.resp_hdr.status,
+        Response::Seal(ref res) => res.resp_hdr.status,
+        Response::Unseal(ref res) => res.resp_hdr.status,
+        Response::Vendor(ref res) => res.resp_hdr.status,
+        Response::Session(_) => 0,
         Response::Error(ref resp_hdr) => resp_hdr.status,
-    };
-    // There are a few vendor error codes starting at 0x1000, so this can be a 2 bytes.
-    trace!("| Response Code {response_code:#06x}");
-    if dpe.contexts != prev_contexts && response_code != 0 {
-        panic!("Error: DPE state changes upon a failed DPE command.");
     }
-    if response_code == DpeErrorCode::InternalError.discriminant() {
-        panic!("Error: DPE reached a state that should be unreachable.");
-    }
-    trace!("----------------------------------");
 }
 
 // cargo-fuzz target
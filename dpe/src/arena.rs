@@ -0,0 +1,177 @@
+/*++
+Licensed under the Apache-2.0 license.
+
+Abstract:
+    Scratch buffers for certificate encoding and hashing.
+
+    By default the buffers live on the stack, which costs tens of KiB for
+    CertifyKey. With the `no-large-stack` feature they are split off the
+    arena in `DpeEnv` instead, so the arena can be placed in static memory
+    on targets with a small stack.
+--*/
+use crate::{response::DpeErrorCode, MAX_CERTIFY_KEY_SIZE};
+
+/// Size of the arena `DpeEnv` must provide with the `no-large-stack`
+/// feature. CertifyKey issues into three certificate-sized buffers at once,
+/// so that the pending certificate is only replaced once it succeeds.
+pub const ARENA_SIZE: usize = 3 * MAX_CERTIFY_KEY_SIZE;
+
+/// Hands out disjoint, zeroed buffers from an arena.
+///
+/// A `Scratch` borrows the arena for as long as its buffers are in use, so
+/// buffers are released by dropping it.
+#[cfg_attr(not(feature = "no-large-stack"), allow(dead_code))]
+pub(crate) struct Scratch<'s> {
+    free: &'s mut [u8],
+}
+
+#[cfg_attr(not(feature = "no-large-stack"), allow(dead_code))]
+impl<'s> Scratch<'s> {
+    pub fn new(arena: &'s mut [u8]) -> Self {
+        Self { free: arena }
+    }
+
+    /// Returns a zeroed buffer of `N` bytes, or `InternalError` if the arena
+    /// is exhausted. `DpeInstance::new` checks that the arena holds
+    /// `ARENA_SIZE` bytes, so that only happens if a command needs more than
+    /// `ARENA_SIZE` at once.
+    pub fn take<const N: usize>(&mut self) -> Result<&'s mut [u8; N], DpeErrorCode> {
+        if self.free.len() < N {
+            return Err(DpeErrorCode::InternalError);
+        }
+        let (buf, rest) = core::mem::take(&mut self.free).split_at_mut(N);
+        self.free = rest;
+        buf.fill(0);
+        buf.try_into().map_err(|_| DpeErrorCode::InternalError)
+    }
+}
+
+/// Declares `$name` as a `&mut [u8; $len]` of zeros. It points to the stack,
+/// or with the `no-large-stack` feature to a buffer taken from the `Scratch`
+/// named by `$scratch`, which is only evaluated with that feature.
+macro_rules! scratch_buffer {
+    ($scratch:ident, $name:ident, $len:expr) => {
+        #[cfg(not(feature = "no-large-stack"))]
+        let mut storage = [0u8; $len];
+        #[cfg(not(feature = "no-large-stack"))]
+        let $name = &mut storage;
+        #[cfg(feature = "no-large-stack")]
+        let $name = $scratch.take::<{ $len }>()?;
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(all(feature = "no-large-stack", not(debug_assertions)))]
+    use crate::{
+        commands::{CertifyKeyCmd, CertifyKeyFlags, CommandExecution, GetCertificateChainCmd},
+        context::ContextHandle,
+        DPE_PROFILE,
+    };
+    #[cfg(feature = "no-large-stack")]
+    use crate::{
+        dpe_instance::{
+            tests::{TestTypes, TEST_LOCALITIES},
+            DpeEnv,
+        },
+        support::Support,
+        DpeInstance,
+    };
+    #[cfg(feature = "no-large-stack")]
+    use crypto::OpensslCrypto;
+    #[cfg(feature = "no-large-stack")]
+    use platform::default::DefaultPlatform;
+    #[cfg(all(feature = "no-large-stack", not(debug_assertions)))]
+    use platform::MAX_CHUNK_SIZE;
+
+    #[test]
+    fn test_scratch_take() {
+        let mut arena = [0xAAu8; 8];
+        let mut scratch = Scratch::new(&mut arena);
+        let first = scratch.take::<3>().unwrap();
+        let second = scratch.take::<5>().unwrap();
+        assert_eq!(first, &[0; 3]);
+        assert_eq!(second, &[0; 5]);
+        first.fill(1);
+        second.fill(2);
+        assert_eq!(scratch.take::<1>(), Err(DpeErrorCode::InternalError));
+        drop(scratch);
+        assert_eq!(arena, [1, 1, 1, 2, 2, 2, 2, 2]);
+
+        // Dropping the scratch releases its buffers.
+        let mut scratch = Scratch::new(&mut arena);
+        assert_eq!(scratch.take::<8>().unwrap(), &[0; 8]);
+    }
+
+    #[cfg(feature = "no-large-stack")]
+    #[test]
+    fn test_new_rejects_small_arena() {
+        let mut arena = vec![0u8; ARENA_SIZE - 1];
+        let mut env = DpeEnv::<TestTypes> {
+            crypto: OpensslCrypto::new(),
+            platform: DefaultPlatform,
+            arena: &mut arena,
+        };
+        assert_eq!(
+            DpeInstance::new(&mut env, Support::X509).err(),
+            Some(DpeErrorCode::InvalidArgument)
+        );
+    }
+
+    /// Smoke test that runs every CertifyKey format and GetCertificateChain
+    /// on a host thread with the stack of a ROM. The test binary aborts if
+    /// the stack overflows. Host frames differ from the embedded target's,
+    /// so the bound that counts is the cargo-call-stack check in ci.sh.
+    /// Debug builds use far more stack, so this only runs in release builds:
+    ///
+    /// cargo test --release --features no-large-stack stack_usage
+    #[cfg(all(feature = "no-large-stack", not(debug_assertions)))]
+    #[test]
+    fn test_stack_usage() {
+        const STACK_SIZE: usize = 16 * 1024;
+
+        let mut arena = vec![0u8; ARENA_SIZE];
+        let mut env = DpeEnv::<TestTypes> {
+            crypto: OpensslCrypto::new(),
+            platform: DefaultPlatform,
+            arena: &mut arena,
+        };
+        // The instance itself lives in static memory on a ROM.
+        let support = Support::AUTO_INIT
+            | Support::X509
+            | Support::CSR
+            | Support::CWT
+            | Support::INTERNAL_DICE
+            | Support::DEVICE_ID_CERT;
+        let mut dpe = Box::new(DpeInstance::new(&mut env, support).unwrap());
+
+        std::thread::scope(|s| {
+            std::thread::Builder::new()
+                .stack_size(STACK_SIZE)
+                .spawn_scoped(s, || {
+                    for format in [
+                        CertifyKeyCmd::FORMAT_X509,
+                        CertifyKeyCmd::FORMAT_CSR,
+                        CertifyKeyCmd::FORMAT_CWT,
+                    ] {
+                        let cmd = CertifyKeyCmd {
+                            handle: ContextHandle::default(),
+                            flags: CertifyKeyFlags::empty(),
+                            label: [0; DPE_PROFILE.get_hash_size()],
+                            format,
+                        };
+                        cmd.execute(&mut dpe, &mut env, TEST_LOCALITIES[0]).unwrap();
+                    }
+                    let cmd = GetCertificateChainCmd {
+                        offset: 0,
+                        size: MAX_CHUNK_SIZE as u32,
+                    };
+                    cmd.execute(&mut dpe, &mut env, TEST_LOCALITIES[0]).unwrap();
+                })
+                .unwrap()
+                .join()
+                .unwrap();
+        });
+    }
+}
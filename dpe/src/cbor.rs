@@ -0,0 +1,275 @@
+// Licensed under the Apache-2.0 license
+
+//! Minimal CBOR writer
+//!
+//! `CborWriter` encodes RFC 8949 data items into a caller-provided buffer,
+//! so it can be used in a no_std environment. Only definite-length items are
+//! produced. Arrays and maps are written as a header holding the number of
+//! elements, followed by the elements themselves.
+
+use crate::response::DpeErrorCode;
+
+pub struct CborWriter<'a> {
+    buf: &'a mut [u8],
+    offset: usize,
+}
+
+impl<'a> CborWriter<'a> {
+    // Major types, already shifted into the top three bits of the initial byte
+    pub const UNSIGNED_INT: u8 = 0x00;
+    pub const NEGATIVE_INT: u8 = 0x20;
+    pub const BYTE_STRING: u8 = 0x40;
+    pub const TEXT_STRING: u8 = 0x60;
+    pub const ARRAY: u8 = 0x80;
+    pub const MAP: u8 = 0xA0;
+    pub const TAG: u8 = 0xC0;
+    pub const SIMPLE: u8 = 0xE0;
+
+    pub const FALSE: u8 = Self::SIMPLE | 20;
+    pub const TRUE: u8 = Self::SIMPLE | 21;
+
+    /// Build a new CborWriter that writes output to `buf`
+    pub fn new(buf: &'a mut [u8]) -> CborWriter<'a> {
+        CborWriter { buf, offset: 0 }
+    }
+
+    /// Number of bytes written so far
+    pub fn len(&self) -> usize {
+        self.offset
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.offset == 0
+    }
+
+    /// Number of bytes of the head encoding `value`
+    ///
+    /// Values below 24 fit in the initial byte. Larger values follow it in
+    /// the smallest of 1, 2, 4 or 8 big-endian bytes.
+    pub const fn head_size(value: u64) -> usize {
+        if value < 24 {
+            1
+        } else if value <= u8::MAX as u64 {
+            2
+        } else if value <= u16::MAX as u64 {
+            3
+        } else if value <= u32::MAX as u64 {
+            5
+        } else {
+            9
+        }
+    }
+
+    /// Number of bytes of a byte or text string holding `len` bytes
+    pub const fn string_size(len: usize) -> usize {
+        Self::head_size(len as u64) + len
+    }
+
+    /// Write all of `bytes` to the buffer
+    ///
+    /// Used to embed data items that were encoded separately.
+    pub fn write_bytes(&mut self, bytes: &[u8]) -> Result<usize, DpeErrorCode> {
+        let size = bytes.len();
+
+        if self.offset > self.buf.len() || self.offset + size > self.buf.len() {
+            return Err(DpeErrorCode::CertificateTooLarge);
+        }
+
+        self.buf
+            .get_mut(self.offset..self.offset + size)
+            .ok_or(DpeErrorCode::InternalError)?
+            .copy_from_slice(bytes);
+        self.offset += size;
+
+        Ok(size)
+    }
+
+    /// Write the head of a data item of type `major` with argument `value`
+    pub fn write_head(&mut self, major: u8, value: u64) -> Result<usize, DpeErrorCode> {
+        let bytes = value.to_be_bytes();
+        match Self::head_size(value) {
+            1 => self.write_bytes(&[major | value as u8]),
+            2 => Ok(self.write_bytes(&[major | 24])? + self.write_bytes(&bytes[7..])?),
+            3 => Ok(self.write_bytes(&[major | 25])? + self.write_bytes(&bytes[6..])?),
+            5 => Ok(self.write_bytes(&[major | 26])? + self.write_bytes(&bytes[4..])?),
+            _ => Ok(self.write_bytes(&[major | 27])? + self.write_bytes(&bytes)?),
+        }
+    }
+
+    /// CBOR-encodes an unsigned integer
+    pub fn write_uint(&mut self, value: u64) -> Result<usize, DpeErrorCode> {
+        self.write_head(Self::UNSIGNED_INT, value)
+    }
+
+    /// CBOR-encodes a signed integer, picking the major type from its sign
+    pub fn write_int(&mut self, value: i64) -> Result<usize, DpeErrorCode> {
+        if value >= 0 {
+            self.write_head(Self::UNSIGNED_INT, value as u64)
+        } else {
+            // Negative integers encode -1 - value, which can't overflow.
+            self.write_head(Self::NEGATIVE_INT, !(value as u64))
+        }
+    }
+
+    /// Write the head of a byte string whose `len` bytes are written separately
+    pub fn write_bstr_header(&mut self, len: usize) -> Result<usize, DpeErrorCode> {
+        self.write_head(Self::BYTE_STRING, len as u64)
+    }
+
+    /// CBOR-encodes `bytes` as a byte string
+    pub fn write_bstr(&mut self, bytes: &[u8]) -> Result<usize, DpeErrorCode> {
+        Ok(self.write_bstr_header(bytes.len())? + self.write_bytes(bytes)?)
+    }
+
+    /// CBOR-encodes `text` as a text string
+    ///
+    /// `text` must already be valid UTF-8.
+    pub fn write_tstr(&mut self, text: &[u8]) -> Result<usize, DpeErrorCode> {
+        Ok(self.write_head(Self::TEXT_STRING, text.len() as u64)? + self.write_bytes(text)?)
+    }
+
+    /// Write the head of an array of `count` elements
+    pub fn write_array_header(&mut self, count: usize) -> Result<usize, DpeErrorCode> {
+        self.write_head(Self::ARRAY, count as u64)
+    }
+
+    /// Write the head of a map of `count` key/value pairs
+    pub fn write_map_header(&mut self, count: usize) -> Result<usize, DpeErrorCode> {
+        self.write_head(Self::MAP, count as u64)
+    }
+
+    /// Write the tag `tag`, which applies to the data item written next
+    pub fn write_tag(&mut self, tag: u64) -> Result<usize, DpeErrorCode> {
+        self.write_head(Self::TAG, tag)
+    }
+
+    /// CBOR-encodes a boolean
+    pub fn write_bool(&mut self, val: bool) -> Result<usize, DpeErrorCode> {
+        self.write_bytes(&[if val { Self::TRUE } else { Self::FALSE }])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ciborium::value::{Integer, Value};
+
+    fn decode(bytes: &[u8]) -> Value {
+        ciborium::de::from_reader(bytes).unwrap()
+    }
+
+    #[test]
+    fn test_head_sizes() {
+        // Boundaries from RFC 8949 Appendix A.
+        let cases: [(u64, &[u8]); 8] = [
+            (0, &[0x00]),
+            (23, &[0x17]),
+            (24, &[0x18, 0x18]),
+            (255, &[0x18, 0xFF]),
+            (256, &[0x19, 0x01, 0x00]),
+            (65536, &[0x1A, 0x00, 0x01, 0x00, 0x00]),
+            (u32::MAX as u64 + 1, &[0x1B, 0, 0, 0, 1, 0, 0, 0, 0]),
+            (
+                u64::MAX,
+                &[0x1B, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF],
+            ),
+        ];
+
+        for (value, expected) in cases {
+            let mut buf = [0u8; 16];
+            let mut w = CborWriter::new(&mut buf);
+            let byte_count = w.write_uint(value).unwrap();
+            assert_eq!(&buf[..byte_count], expected);
+            assert_eq!(CborWriter::head_size(value), byte_count);
+        }
+    }
+
+    #[test]
+    fn test_integers() {
+        let cases = [
+            0i64,
+            1,
+            23,
+            24,
+            -1,
+            -24,
+            -25,
+            -256,
+            -257,
+            -4670552,
+            i64::MIN,
+            i64::MAX,
+        ];
+
+        for value in cases {
+            let mut buf = [0u8; 16];
+            let mut w = CborWriter::new(&mut buf);
+            let byte_count = w.write_int(value).unwrap();
+            assert_eq!(
+                decode(&buf[..byte_count]),
+                Value::Integer(Integer::from(value))
+            );
+        }
+    }
+
+    #[test]
+    fn test_strings() {
+        let mut buf = [0u8; 512];
+        let mut w = CborWriter::new(&mut buf);
+        let mut byte_count = w.write_array_header(3).unwrap();
+        byte_count += w.write_bstr(&[0xAA; 300]).unwrap();
+        byte_count += w.write_tstr(b"DPE").unwrap();
+        byte_count += w.write_bstr(&[]).unwrap();
+        assert_eq!(
+            byte_count,
+            1 + CborWriter::string_size(300) + CborWriter::string_size(3) + 1
+        );
+
+        assert_eq!(
+            decode(&buf[..byte_count]),
+            Value::Array(vec![
+                Value::Bytes(vec![0xAA; 300]),
+                Value::Text("DPE".into()),
+                Value::Bytes(vec![]),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_map_and_tag() {
+        let mut buf = [0u8; 64];
+        let mut w = CborWriter::new(&mut buf);
+        let mut byte_count = w.write_tag(18).unwrap();
+        byte_count += w.write_map_header(2).unwrap();
+        byte_count += w.write_int(1).unwrap();
+        byte_count += w.write_bool(true).unwrap();
+        byte_count += w.write_int(-2).unwrap();
+        byte_count += w.write_bool(false).unwrap();
+
+        assert_eq!(
+            decode(&buf[..byte_count]),
+            Value::Tag(
+                18,
+                Box::new(Value::Map(vec![
+                    (Value::Integer(1.into()), Value::Bool(true)),
+                    (Value::Integer((-2).into()), Value::Bool(false)),
+                ]))
+            )
+        );
+    }
+
+    #[test]
+    fn test_buffer_too_small() {
+        let mut buf = [0u8; 4];
+        let mut w = CborWriter::new(&mut buf);
+        assert_eq!(
+            w.write_bstr(&[0; 4]),
+            Err(DpeErrorCode::CertificateTooLarge)
+        );
+
+        let mut buf = [0u8; 0];
+        let mut w = CborWriter::new(&mut buf);
+        assert_eq!(w.write_uint(0), Err(DpeErrorCode::CertificateTooLarge));
+        assert!(w.is_empty());
+    }
+}
@@ -0,0 +1,102 @@
+/*++
+Licensed under the Apache-2.0 license.
+
+Abstract:
+    Replay state and message framing of HMAC-authenticated commands.
+--*/
+use crate::{wire::WireEndian, U8Bool, DPE_PROFILE};
+use platform::MAX_LOCALITIES;
+use zerocopy::{AsBytes, FromBytes};
+use zeroize::Zeroize;
+
+/// Number of localities that can send authenticated commands. A slot is
+/// never freed, since resetting a counter would let old commands be
+/// replayed, so there is one for every locality a platform's locality table
+/// can hold.
+pub const MAX_AUTH_LOCALITIES: usize = MAX_LOCALITIES;
+
+/// Size of the HMAC that follows an authenticated command.
+pub const COMMAND_AUTH_TAG_SIZE: usize = DPE_PROFILE.get_hash_size();
+
+/// Replay state of a locality that has sent authenticated commands.
+#[repr(C, align(4))]
+#[derive(AsBytes, FromBytes, Copy, Clone, Zeroize)]
+pub struct AuthCounter {
+    pub(crate) locality: u32,
+    /// Counter the next authenticated command from `locality` must carry.
+    pub(crate) counter: u32,
+    pub(crate) active: U8Bool,
+    reserved: [u8; 3],
+}
+
+impl Default for AuthCounter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AuthCounter {
+    pub const fn new() -> AuthCounter {
+        AuthCounter {
+            locality: 0,
+            counter: 0,
+            active: U8Bool::new(false),
+            reserved: [0; 3],
+        }
+    }
+
+    pub fn active(&self) -> bool {
+        self.active.get()
+    }
+}
+
+/// Header of a command authenticated with the key of the locality sending it.
+///
+/// The header is followed by the serialized command and then an HMAC over the
+/// header and command, using the hash of the DPE profile. Each locality's
+/// counter starts at 0 and each value is only accepted once.
+#[repr(C)]
+#[derive(Debug, PartialEq, Eq, Clone, Copy, AsBytes, FromBytes)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AuthCmdHdr {
+    pub magic: u32,
+    pub counter: u32,
+}
+
+impl_wire_endian!(AuthCmdHdr { magic, counter });
+
+impl AuthCmdHdr {
+    pub const AUTH_CMD_MAGIC: u32 = u32::from_be_bytes(*b"DPAC");
+
+    /// Returns whether `bytes` starts with an authenticated command header.
+    pub fn is_auth_cmd(bytes: &[u8]) -> bool {
+        match AuthCmdHdr::read_from_prefix(bytes).map(Self::from_le) {
+            Some(hdr) => hdr.magic == Self::AUTH_CMD_MAGIC,
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::session::SessionMsgHdr;
+
+    #[test]
+    fn test_is_auth_cmd() {
+        let hdr = AuthCmdHdr {
+            magic: AuthCmdHdr::AUTH_CMD_MAGIC,
+            counter: 0,
+        };
+        assert!(AuthCmdHdr::is_auth_cmd(hdr.to_le().as_bytes()));
+        assert!(!AuthCmdHdr::is_auth_cmd(&hdr.to_le().as_bytes()[..4]));
+
+        // Session messages are not mistaken for authenticated commands.
+        let hdr = SessionMsgHdr {
+            magic: SessionMsgHdr::SESSION_CMD_MAGIC,
+            session_id: 0,
+            counter: 0,
+        };
+        assert!(!AuthCmdHdr::is_auth_cmd(hdr.to_le().as_bytes()));
+    }
+}
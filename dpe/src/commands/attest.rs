@@ -0,0 +1,465 @@
+// Licensed under the Apache-2.0 license.
+use super::CommandExecution;
+#[cfg(feature = "no-large-stack")]
+use crate::arena::Scratch;
+use crate::{
+    context::{ContextHandle, ContextType},
+    cwt::CwtWriter,
+    dpe_instance::{DpeEnv, DpeInstance, DpeTypes},
+    response::{AttestResp, DpeErrorCode, Response, ResponseHdr, ResponseWriter},
+    tci::TciNodeData,
+    DPE_PROFILE, MAX_CERTIFY_KEY_SIZE, MAX_CERT_SIZE, MAX_HANDLES,
+};
+#[cfg(not(feature = "no-cfi"))]
+use caliptra_cfi_derive_git::cfi_impl_fn;
+use caliptra_cfi_lib_git::cfi_launder;
+#[cfg(not(feature = "no-cfi"))]
+use caliptra_cfi_lib_git::{cfi_assert, cfi_assert_ne};
+use cfg_if::cfg_if;
+use crypto::{destroy_cdi_after_derive, Crypto, EcdsaPub, Hasher};
+use platform::{Platform, MAX_UEID_SIZE};
+
+/// Returns an attestation token for a context: a COSE_Sign1 over the
+/// caller's nonce, the TCI nodes from the context up to the root, the DPE
+/// profile and the public key of the signing key.
+///
+/// The token is signed with a key the context derives from `label` for
+/// attestation only. It is not the key Sign uses, so Sign can't be used to
+/// forge a token. The public key is in the token, so a verifier can check
+/// it without parsing X.509. See `CwtWriter::encode_token_claims` for the
+/// claims.
+///
+/// Like a certificate, a token larger than the response is read with
+/// GetCertificateChunk.
+#[repr(C)]
+#[derive(Debug, PartialEq, Eq, zerocopy::FromBytes, zerocopy::AsBytes)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AttestCmd {
+    pub handle: ContextHandle,
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_hex"))]
+    pub label: [u8; DPE_PROFILE.get_hash_size()],
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_hex"))]
+    pub nonce: [u8; DPE_PROFILE.get_hash_size()],
+}
+
+impl_wire_endian!(AttestCmd {});
+
+impl AttestCmd {
+    /// Key derivation info for attestation keys, which keeps them apart from
+    /// the keys Sign and CertifyKey derive from the same label.
+    const KEY_INFO: &'static [u8] = b"ATTEST";
+}
+
+impl CommandExecution for AttestCmd {
+    #[cfg_attr(not(feature = "no-cfi"), cfi_impl_fn)]
+    fn execute(
+        &self,
+        dpe: &mut DpeInstance,
+        env: &mut DpeEnv<impl DpeTypes>,
+        locality: u32,
+    ) -> Result<Response, DpeErrorCode> {
+        dpe_trace!("Attest locality={:#x}", locality);
+
+        // Make sure this command is supported.
+        if !dpe.support.attest() {
+            return Err(DpeErrorCode::InvalidCommand);
+        }
+
+        // The token carries an EC2 COSE_Key and an ECDSA signature.
+        if DPE_PROFILE.is_pq() || DPE_PROFILE.is_eddsa() {
+            return Err(DpeErrorCode::ArgumentNotSupported);
+        }
+
+        let idx = dpe.get_active_context_pos(&self.handle, locality)?;
+        let context = &dpe.contexts[idx];
+
+        // Like Sign, simulation contexts don't sign anything.
+        if context.context_type == ContextType::Simulation {
+            return Err(DpeErrorCode::InvalidArgument);
+        }
+
+        cfg_if! {
+            if #[cfg(not(feature = "no-cfi"))] {
+                cfi_assert!(dpe.support.attest());
+                cfi_assert!(!DPE_PROFILE.is_pq());
+                cfi_assert!(!DPE_PROFILE.is_eddsa());
+                cfi_assert_ne(context.context_type, ContextType::Simulation);
+            }
+        }
+
+        let algs = DPE_PROFILE.alg_len();
+        let (measurement, cdi) = dpe.derive_cdi(env, idx)?;
+        let key_pair = env
+            .crypto
+            .derive_key_pair(algs, &cdi, &self.label, Self::KEY_INFO);
+        if cfi_launder(key_pair.is_ok()) {
+            #[cfg(not(feature = "no-cfi"))]
+            cfi_assert!(key_pair.is_ok());
+        } else {
+            #[cfg(not(feature = "no-cfi"))]
+            cfi_assert!(key_pair.is_err());
+        }
+        let (priv_key, pub_key) = destroy_cdi_after_derive(&mut env.crypto, cdi, key_pair)?;
+
+        // Pick the new handle up front, so that nothing changes if it can't be.
+        let new_handle = match dpe.next_onetime_use_handle(env, &dpe.contexts[idx]) {
+            Ok(handle) => handle,
+            Err(e) => {
+                env.crypto.destroy_key(priv_key)?;
+                return Err(e);
+            }
+        };
+
+        let written = self.write_token(dpe, env, idx, locality, &priv_key, &pub_key);
+        env.crypto.destroy_key(priv_key)?;
+        written?;
+
+        // Rotate the handle if it isn't the default context.
+        dpe.contexts[idx].handle = new_handle;
+
+        // Only the first chunk fits in the response. The rest is read with GetCertificateChunk.
+        let mut token = [0u8; MAX_CERT_SIZE];
+        ResponseWriter::new(&mut token).write_chunk(dpe.pending_cert.get())?;
+
+        Ok(Response::Attest(AttestResp {
+            resp_hdr: ResponseHdr::new(DpeErrorCode::NoError),
+            new_context_handle: new_handle,
+            token_size: dpe.pending_cert.size,
+            token,
+        }))
+    }
+}
+
+impl AttestCmd {
+    /// Signs a token for the context at `idx` with `priv_key` and writes it
+    /// to `dpe.pending_cert`, where GetCertificateChunk can read it. The
+    /// previous certificate or token is only replaced once the token is
+    /// signed.
+    fn write_token<'a, T: DpeTypes + 'a>(
+        &self,
+        dpe: &mut DpeInstance,
+        env: &mut DpeEnv<'a, T>,
+        idx: usize,
+        locality: u32,
+        priv_key: &<T::Crypto<'a> as Crypto>::PrivKey,
+        pub_key: &EcdsaPub,
+    ) -> Result<(), DpeErrorCode> {
+        const INITIALIZER: TciNodeData = TciNodeData::new();
+        let mut nodes = [INITIALIZER; MAX_HANDLES];
+        let tcb_count = dpe.get_tcb_nodes(idx, &mut nodes)?;
+
+        let mut ueid = [0u8; MAX_UEID_SIZE];
+        let ueid_len = env.platform.get_ueid(&mut ueid)?;
+
+        #[cfg(feature = "no-large-stack")]
+        let mut scratch = Scratch::new(env.arena);
+        scratch_buffer!(scratch, claims_buffer, MAX_CERTIFY_KEY_SIZE);
+        let claims_size = CwtWriter::new(&mut claims_buffer[..]).encode_token_claims(
+            &self.nonce,
+            ueid.get(..ueid_len).ok_or(DpeErrorCode::InternalError)?,
+            pub_key,
+            nodes.get(..tcb_count).ok_or(DpeErrorCode::InternalError)?,
+        )?;
+        let claims = claims_buffer
+            .get(..claims_size)
+            .ok_or(DpeErrorCode::CertificateTooLarge)?;
+
+        let mut prefix = [0u8; CwtWriter::MAX_SIG_STRUCTURE_PREFIX_SIZE];
+        let prefix_size = CwtWriter::new(&mut prefix).encode_sig_structure_prefix(claims_size)?;
+        let mut hasher = env.crypto.hash_initialize(DPE_PROFILE.alg_len())?;
+        hasher.update(&prefix[..prefix_size])?;
+        hasher.update(claims)?;
+        let sig_structure_digest = hasher.finish()?;
+        let sig = env.crypto.ecdsa_sign_with_derived(
+            DPE_PROFILE.alg_len(),
+            &sig_structure_digest,
+            priv_key,
+            pub_key,
+        )?;
+
+        scratch_buffer!(scratch, token_buffer, MAX_CERTIFY_KEY_SIZE);
+        let token_size = CwtWriter::new(&mut token_buffer[..]).encode_cose_sign1(claims, &sig)?;
+        let token = token_buffer
+            .get(..token_size)
+            .ok_or(DpeErrorCode::InternalError)?;
+        dpe.pending_cert.set(locality, token, &[], None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        commands::{
+            Command, CommandHdr, DeriveContextCmd, DeriveContextFlags, GetCertificateChunkCmd,
+            InitCtxCmd,
+        },
+        dpe_instance::tests::{TestTypes, SIMULATION_HANDLE, TEST_LOCALITIES},
+        support::Support,
+    };
+    use caliptra_cfi_lib_git::CfiCounter;
+    use ciborium::value::Value;
+    use crypto::{AlgLen, Digest, OpensslCrypto};
+    use openssl::{
+        bn::BigNum,
+        ec::{EcGroup, EcKey},
+        ecdsa::EcdsaSig,
+        nid::Nid,
+        pkey::Public,
+    };
+    use platform::{default::DefaultPlatform, MAX_CHUNK_SIZE};
+    use zerocopy::AsBytes;
+
+    const TEST_ATTEST_CMD: AttestCmd = AttestCmd {
+        handle: SIMULATION_HANDLE,
+        label: [0xaa; DPE_PROFILE.get_hash_size()],
+        nonce: [0x4e; DPE_PROFILE.get_hash_size()],
+    };
+
+    fn attest_cmd() -> AttestCmd {
+        AttestCmd {
+            handle: ContextHandle::default(),
+            ..TEST_ATTEST_CMD
+        }
+    }
+
+    fn claim(map: &Value, key: i64) -> &Value {
+        map.as_map()
+            .unwrap()
+            .iter()
+            .find(|(k, _)| *k == Value::Integer(key.into()))
+            .map(|(_, v)| v)
+            .unwrap()
+    }
+
+    /// Reads the whole token, including the part that didn't fit in the response.
+    fn read_token(
+        dpe: &mut DpeInstance,
+        env: &mut DpeEnv<TestTypes>,
+        resp: &AttestResp,
+    ) -> Vec<u8> {
+        let mut token = Vec::new();
+        while token.len() < resp.token_size as usize {
+            let chunk = match (GetCertificateChunkCmd {
+                offset: token.len() as u32,
+                size: MAX_CHUNK_SIZE as u32,
+            })
+            .execute(dpe, env, TEST_LOCALITIES[0])
+            .unwrap()
+            {
+                Response::GetCertificateChunk(chunk) => chunk,
+                _ => panic!("Wrong response type."),
+            };
+            token.extend_from_slice(&chunk.certificate_chain[..chunk.certificate_size as usize]);
+        }
+        token
+    }
+
+    /// Returns the key in the token's claims, the digest of its
+    /// Sig_structure and its signature.
+    fn token_signature(
+        env: &mut DpeEnv<TestTypes>,
+        token: &[u8],
+    ) -> (EcKey<Public>, Digest, EcdsaSig) {
+        let Value::Tag(18, sign1) = ciborium::de::from_reader(token).unwrap() else {
+            panic!("Token is not a tagged COSE_Sign1");
+        };
+        let sign1 = sign1.as_array().unwrap();
+        let claims: Value =
+            ciborium::de::from_reader(sign1[2].as_bytes().unwrap().as_slice()).unwrap();
+
+        let cose_key: Value =
+            ciborium::de::from_reader(claim(&claims, -4670552).as_bytes().unwrap().as_slice())
+                .unwrap();
+        let x = BigNum::from_slice(claim(&cose_key, -2).as_bytes().unwrap()).unwrap();
+        let y = BigNum::from_slice(claim(&cose_key, -3).as_bytes().unwrap()).unwrap();
+        let nid = match DPE_PROFILE.alg_len() {
+            AlgLen::Bit256 => Nid::X9_62_PRIME256V1,
+            AlgLen::Bit384 => Nid::SECP384R1,
+            AlgLen::Bit512 => Nid::SECP521R1,
+        };
+        let group = EcGroup::from_curve_name(nid).unwrap();
+        let key = EcKey::from_public_key_affine_coordinates(&group, &x, &y).unwrap();
+
+        let mut sig_structure = Vec::new();
+        ciborium::ser::into_writer(
+            &Value::Array(vec![
+                Value::Text("Signature1".into()),
+                sign1[0].clone(),
+                Value::Bytes(vec![]),
+                sign1[2].clone(),
+            ]),
+            &mut sig_structure,
+        )
+        .unwrap();
+        let digest = env
+            .crypto
+            .hash(DPE_PROFILE.alg_len(), &sig_structure)
+            .unwrap();
+        let sig = sign1[3].as_bytes().unwrap();
+        let (r, s) = sig.split_at(sig.len() / 2);
+        let sig = EcdsaSig::from_private_components(
+            BigNum::from_slice(r).unwrap(),
+            BigNum::from_slice(s).unwrap(),
+        )
+        .unwrap();
+        (key, digest, sig)
+    }
+
+    #[test]
+    fn test_deserialize_attest() {
+        CfiCounter::reset_for_test();
+        let mut command = CommandHdr::new_for_test(Command::ATTEST)
+            .as_bytes()
+            .to_vec();
+        command.extend(TEST_ATTEST_CMD.as_bytes());
+        assert_eq!(
+            Ok(Command::Attest(TEST_ATTEST_CMD)),
+            Command::deserialize(&command)
+        );
+    }
+
+    #[test]
+    fn test_attest_support() {
+        CfiCounter::reset_for_test();
+        let mut env = DpeEnv::<TestTypes> {
+            crypto: OpensslCrypto::new(),
+            platform: DefaultPlatform,
+            arena: &mut [],
+        };
+
+        let mut dpe = DpeInstance::new(&mut env, Support::AUTO_INIT).unwrap();
+        assert_eq!(
+            Err(DpeErrorCode::InvalidCommand),
+            attest_cmd().execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
+        );
+
+        // Simulation contexts can't be attested.
+        let mut dpe = DpeInstance::new(&mut env, Support::SIMULATION | Support::ATTEST).unwrap();
+        let handle = match InitCtxCmd::new_simulation()
+            .execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
+            .unwrap()
+        {
+            Response::InitCtx(resp) => resp.handle,
+            _ => panic!("Wrong response type."),
+        };
+        assert_eq!(
+            Err(DpeErrorCode::InvalidArgument),
+            AttestCmd {
+                handle,
+                ..TEST_ATTEST_CMD
+            }
+            .execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
+        );
+    }
+
+    #[test]
+    fn test_attest() {
+        CfiCounter::reset_for_test();
+        let mut env = DpeEnv::<TestTypes> {
+            crypto: OpensslCrypto::new(),
+            platform: DefaultPlatform,
+            arena: &mut [],
+        };
+        let mut dpe = DpeInstance::new(&mut env, Support::AUTO_INIT | Support::ATTEST).unwrap();
+
+        DeriveContextCmd {
+            handle: ContextHandle::default(),
+            data: [0x11; DPE_PROFILE.get_tci_size()],
+            flags: DeriveContextFlags::MAKE_DEFAULT,
+            tci_type: 0x7465_7374,
+            target_locality: TEST_LOCALITIES[0],
+        }
+        .execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
+        .unwrap();
+
+        let resp = match attest_cmd()
+            .execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
+            .unwrap()
+        {
+            Response::Attest(resp) => resp,
+            _ => panic!("Wrong response type."),
+        };
+        assert_eq!(resp.new_context_handle, ContextHandle::default());
+        let token = read_token(&mut dpe, &mut env, &resp);
+        let first_chunk = token.len().min(MAX_CERT_SIZE);
+        assert_eq!(&resp.token[..first_chunk], &token[..first_chunk]);
+
+        let Value::Tag(18, sign1) = ciborium::de::from_reader(token.as_slice()).unwrap() else {
+            panic!("Token is not a tagged COSE_Sign1");
+        };
+        let sign1 = sign1.as_array().unwrap();
+        let payload = sign1[2].as_bytes().unwrap();
+        let claims: Value = ciborium::de::from_reader(payload.as_slice()).unwrap();
+
+        assert_eq!(
+            claim(&claims, CwtWriter::NONCE_CLAIM),
+            &Value::Bytes(TEST_ATTEST_CMD.nonce.to_vec())
+        );
+        assert_eq!(
+            claim(&claims, CwtWriter::PROFILE_CLAIM),
+            &Value::Integer((DPE_PROFILE as u32).into())
+        );
+        // The derived context and the auto-initialized root.
+        let nodes = claim(&claims, CwtWriter::TCI_NODES_CLAIM)
+            .as_array()
+            .unwrap();
+        assert_eq!(nodes.len(), 2);
+        assert_eq!(
+            nodes[0].as_array().unwrap()[2],
+            Value::Bytes(vec![0x11; DPE_PROFILE.get_tci_size()])
+        );
+
+        // The token verifies with the key in its claims.
+        let (key, digest, sig) = token_signature(&mut env, &token);
+        assert!(sig.verify(digest.bytes(), &key).unwrap());
+    }
+
+    #[cfg(not(any(
+        feature = "dpe_profile_mldsa87_sha384",
+        feature = "dpe_profile_ed25519_sha512"
+    )))]
+    #[test]
+    fn test_attest_key_is_not_sign_key() {
+        use crate::commands::{SignCmd, SignFlags};
+
+        CfiCounter::reset_for_test();
+        let mut env = DpeEnv::<TestTypes> {
+            crypto: OpensslCrypto::new(),
+            platform: DefaultPlatform,
+            arena: &mut [],
+        };
+        let mut dpe = DpeInstance::new(&mut env, Support::AUTO_INIT | Support::ATTEST).unwrap();
+
+        let resp = match attest_cmd()
+            .execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
+            .unwrap()
+        {
+            Response::Attest(resp) => resp,
+            _ => panic!("Wrong response type."),
+        };
+        let token = read_token(&mut dpe, &mut env, &resp);
+        let (key, digest, _) = token_signature(&mut env, &token);
+
+        // Signing the token's Sig_structure with the same label doesn't
+        // produce a signature the attestation key verifies.
+        let resp = match (SignCmd {
+            handle: ContextHandle::default(),
+            label: TEST_ATTEST_CMD.label,
+            flags: SignFlags::empty(),
+            digest: digest.bytes().try_into().unwrap(),
+        })
+        .execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
+        .unwrap()
+        {
+            Response::Sign(resp) => resp,
+            _ => panic!("Wrong response type."),
+        };
+        let forged = EcdsaSig::from_private_components(
+            BigNum::from_slice(&resp.sig_r_or_hmac).unwrap(),
+            BigNum::from_slice(&resp.sig_s).unwrap(),
+        )
+        .unwrap();
+        assert!(!forged.verify(digest.bytes(), &key).unwrap());
+    }
+}
@@ -1,12 +1,20 @@
 // Licensed under the Apache-2.0 license.
 use super::CommandExecution;
+#[cfg(feature = "no-large-stack")]
+use crate::arena::Scratch;
 use crate::{
     context::ContextHandle,
+    cwt::CwtWriter,
     dpe_instance::{DpeEnv, DpeInstance, DpeTypes},
-    response::{CertifyKeyResp, DpeErrorCode, Response, ResponseHdr},
+    response::{
+        CertifyKeyResp, DpeErrorCode, GetCertificateChainResp, Response, ResponseHdr,
+        ResponseWriter,
+    },
     tci::TciNodeData,
-    x509::{CertWriter, DirectoryString, MeasurementData, Name},
-    DPE_PROFILE, MAX_CERT_SIZE, MAX_HANDLES,
+    x509::{
+        self, CertWriter, DirectoryString, MeasurementData, Name, SubjectPubKey, SERIAL_NUMBER_SIZE,
+    },
+    U8Bool, DPE_PROFILE, MAX_CERTIFY_KEY_SIZE, MAX_CERT_SIZE, MAX_HANDLES,
 };
 use bitflags::bitflags;
 #[cfg(not(feature = "no-cfi"))]
@@ -15,35 +23,842 @@ use caliptra_cfi_lib_git::cfi_launder;
 #[cfg(not(feature = "no-cfi"))]
 use caliptra_cfi_lib_git::{cfi_assert, cfi_assert_eq};
 use cfg_if::cfg_if;
-use crypto::{Crypto, Hasher};
-use platform::{Platform, MAX_ISSUER_NAME_SIZE, MAX_KEY_IDENTIFIER_SIZE};
+#[cfg(feature = "dpe_profile_ed25519_sha512")]
+use crypto::Ed25519Pub;
+use crypto::{destroy_cdi_after_derive, Crypto, Digest, EcdsaPub, EcdsaSig, Hasher};
+#[cfg(feature = "rsa")]
+use crypto::{RsaKeySize, RsaPadding, RsaPub};
+use platform::{
+    Fwid, Platform, PlatformError, SubjectName, MAX_ADDITIONAL_FWIDS, MAX_CHUNK_SIZE,
+    MAX_ISSUER_NAME_SIZE, MAX_KEY_IDENTIFIER_SIZE, MAX_UEID_SIZE,
+};
+use zerocopy::{AsBytes, FromBytes};
+use zeroize::Zeroize;
 
 #[repr(C)]
 #[derive(Debug, PartialEq, Eq, zerocopy::FromBytes, zerocopy::AsBytes)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct CertifyKeyFlags(u32);
 
 bitflags! {
     impl CertifyKeyFlags: u32 {
         const IS_CA = 1u32 << 30;
+        /// Pick the certificate serial number at random instead of deriving it
+        /// from the subject key, so re-certifying a key yields a new serial.
+        const RANDOM_SERIAL = 1u32 << 29;
+        /// Certify an RSA-3072 key derived from the context instead of an
+        /// ECDSA key. Requires the `rsa` feature and an X.509 or CSR format.
+        const RSA_3072 = 1u32 << 28;
+        /// Certify an RSA-4096 key, like `RSA_3072`.
+        const RSA_4096 = 1u32 << 27;
+        /// Self-sign the CSR of an RSA key with RSASSA-PSS rather than
+        /// RSASSA-PKCS1-v1_5.
+        const RSA_PSS = 1u32 << 26;
     }
 }
 
+impl_wire_endian!(flags CertifyKeyFlags);
+
 #[repr(C)]
 #[derive(Debug, PartialEq, Eq, zerocopy::FromBytes, zerocopy::AsBytes)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct CertifyKeyCmd {
     pub handle: ContextHandle,
     pub flags: CertifyKeyFlags,
     pub format: u32,
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_hex"))]
     pub label: [u8; DPE_PROFILE.get_hash_size()],
 }
 
+impl_wire_endian!(CertifyKeyCmd { flags, format });
+
 impl CertifyKeyCmd {
     pub const FORMAT_X509: u32 = 0;
     pub const FORMAT_CSR: u32 = 1;
+    pub const FORMAT_CWT: u32 = 2;
+    /// Returns the derived public key as an EC2 COSE_Key rather than a
+    /// certificate. Nothing is signed. Requires `Support::CWT`.
+    pub const FORMAT_COSE_KEY: u32 = 3;
 
     const fn uses_is_ca(&self) -> bool {
         self.flags.contains(CertifyKeyFlags::IS_CA)
     }
+
+    const fn uses_random_serial(&self) -> bool {
+        self.flags.contains(CertifyKeyFlags::RANDOM_SERIAL)
+    }
+
+    /// Whether the key is returned in a COSE structure, which only holds
+    /// EC2 keys.
+    const fn uses_cose(&self) -> bool {
+        self.format == Self::FORMAT_CWT || self.format == Self::FORMAT_COSE_KEY
+    }
+
+    const fn uses_rsa(&self) -> bool {
+        self.flags.intersects(
+            CertifyKeyFlags::RSA_3072
+                .union(CertifyKeyFlags::RSA_4096)
+                .union(CertifyKeyFlags::RSA_PSS),
+        )
+    }
+
+    /// The size of the RSA key to certify, or `None` for an ECDSA key.
+    #[cfg(feature = "rsa")]
+    fn rsa_key_size(&self) -> Result<Option<RsaKeySize>, DpeErrorCode> {
+        let rsa_3072 = self.flags.contains(CertifyKeyFlags::RSA_3072);
+        let rsa_4096 = self.flags.contains(CertifyKeyFlags::RSA_4096);
+        match (rsa_3072, rsa_4096) {
+            (true, true) => Err(DpeErrorCode::InvalidArgument),
+            (true, false) => Ok(Some(RsaKeySize::Bit3072)),
+            (false, true) => Ok(Some(RsaKeySize::Bit4096)),
+            (false, false) if self.flags.contains(CertifyKeyFlags::RSA_PSS) => {
+                Err(DpeErrorCode::InvalidArgument)
+            }
+            (false, false) => Ok(None),
+        }
+    }
+
+    #[cfg(feature = "rsa")]
+    const fn rsa_padding(&self) -> RsaPadding {
+        if self.flags.contains(CertifyKeyFlags::RSA_PSS) {
+            RsaPadding::Pss
+        } else {
+            RsaPadding::Pkcs1v15
+        }
+    }
+
+    /// Digest over what a certificate issued by this command depends on,
+    /// other than platform data that is fixed for the life of the instance:
+    /// the measurements of the context and its ancestors, the label, the
+    /// flags and the format, and the command counter when it is certified.
+    ///
+    /// # Arguments
+    ///
+    /// * `env` - DPE environment containing Crypto and Platform implementations
+    /// * `measurement` - Measurement digest of the certified context
+    /// * `command_counter` - Command counter that goes into the certificate
+    fn reuse_key(
+        &self,
+        env: &mut DpeEnv<impl DpeTypes>,
+        measurement: &Digest,
+        command_counter: Option<u32>,
+    ) -> Result<Digest, DpeErrorCode> {
+        let mut hasher = env.crypto.hash_initialize(DPE_PROFILE.alg_len())?;
+        hasher.update(measurement.bytes())?;
+        hasher.update(&self.label)?;
+        hasher.update(&self.flags.bits().to_le_bytes())?;
+        hasher.update(&self.format.to_le_bytes())?;
+        if let Some(counter) = command_counter {
+            hasher.update(&counter.to_le_bytes())?;
+        }
+        Ok(hasher.finish()?)
+    }
+
+    /// Builds the response for `issued`, the certificate that goes to
+    /// `dpe.pending_cert`. The caller moves the context to `new_handle`.
+    fn certify_key_resp(
+        issued: &[u8],
+        new_handle: ContextHandle,
+        subject_pub_key: SubjectPubKey,
+    ) -> Result<CertifyKeyResp, DpeErrorCode> {
+        // Only the first chunk fits in the response. The rest is read with GetCertificateChunk.
+        let mut cert = [0u8; MAX_CERT_SIZE];
+        let cert_size = ResponseWriter::new(&mut cert).write_chunk(issued)?;
+
+        let (derived_pubkey_x, derived_pubkey_y) = match subject_pub_key {
+            SubjectPubKey::Ecdsa(pub_key) => (
+                pub_key
+                    .x
+                    .bytes()
+                    .try_into()
+                    .map_err(|_| DpeErrorCode::InternalError)?,
+                pub_key
+                    .y
+                    .bytes()
+                    .try_into()
+                    .map_err(|_| DpeErrorCode::InternalError)?,
+            ),
+            // An RSA key doesn't fit in the response. It is only in the
+            // certificate.
+            #[cfg(feature = "rsa")]
+            SubjectPubKey::Rsa(_) => (
+                [0; DPE_PROFILE.get_ecc_int_size()],
+                [0; DPE_PROFILE.get_ecc_int_size()],
+            ),
+            // The fields are sized for the P-521 alias key. An Ed25519 key
+            // fills the start of X and the rest is zero.
+            #[cfg(feature = "dpe_profile_ed25519_sha512")]
+            SubjectPubKey::Ed25519(pub_key) => {
+                let mut x = [0; DPE_PROFILE.get_ecc_int_size()];
+                ResponseWriter::new(&mut x).write(pub_key.bytes())?;
+                (x, [0; DPE_PROFILE.get_ecc_int_size()])
+            }
+        };
+
+        Ok(CertifyKeyResp {
+            new_context_handle: new_handle,
+            derived_pubkey_x,
+            derived_pubkey_y,
+            cert_size: u32::try_from(cert_size).map_err(|_| DpeErrorCode::InternalError)?,
+            cert,
+            total_size: u32::try_from(issued.len()).map_err(|_| DpeErrorCode::InternalError)?,
+            resp_hdr: ResponseHdr::new(DpeErrorCode::NoError),
+        })
+    }
+}
+
+/// The output of the most recent CertifyKey or Attest command. It is kept so
+/// that certificates and tokens larger than the response buffer can be read
+/// back with GetCertificateChunk.
+///
+/// With `Support::CERT_CACHE`, a CertifyKey that repeats the request that
+/// issued `cert` gets it back without signing. There is only this one
+/// certificate, so any CertifyKey or Attest for another context or request
+/// replaces it and the next request for the first context signs again.
+#[repr(C, align(4))]
+#[derive(AsBytes, FromBytes, Zeroize)]
+pub(crate) struct PendingCert {
+    /// Locality that issued the CertifyKey command.
+    pub(crate) locality: u32,
+    /// Number of valid bytes in `cert`. Zero when there is no certificate.
+    pub(crate) size: u32,
+    pub(crate) cert: [u8; MAX_CERTIFY_KEY_SIZE],
+    /// Number of valid bytes in `chain`. Zero unless `cert` was issued by a
+    /// parent context's layer key.
+    pub(crate) chain_size: u32,
+    /// Certificates of the layer keys between the platform's alias key and
+    /// the issuer of `cert`, root first. At most `MAX_LAYER_CHAIN_DEPTH` of
+    /// them.
+    pub(crate) chain: [u8; MAX_CERTIFY_KEY_SIZE],
+    /// With `Support::CERT_CACHE`, the digest `CertifyKeyCmd::reuse_key`
+    /// returned for the request that issued `cert`. Only valid if `reusable`
+    /// is set.
+    pub(crate) reuse_key: [u8; DPE_PROFILE.get_hash_size()],
+    /// Index of the context `cert` was issued for. Only valid if `reusable`
+    /// is set.
+    pub(crate) reuse_idx: u8,
+    /// Whether a CertifyKey matching `reuse_key` may return `cert` as is.
+    pub(crate) reusable: U8Bool,
+    // unused buffer added to make PendingCert word aligned and remove padding
+    reserved: [u8; 2],
+}
+
+impl PendingCert {
+    pub const fn new() -> PendingCert {
+        PendingCert {
+            locality: 0,
+            size: 0,
+            cert: [0; MAX_CERTIFY_KEY_SIZE],
+            chain_size: 0,
+            chain: [0; MAX_CERTIFY_KEY_SIZE],
+            reuse_key: [0; DPE_PROFILE.get_hash_size()],
+            reuse_idx: 0,
+            reusable: U8Bool::new(false),
+            reserved: [0; 2],
+        }
+    }
+
+    /// Whether `cert` was issued to `locality` for context `idx` by a request
+    /// with reuse key `key`, so it can be returned again without signing.
+    pub(crate) fn can_reuse(&self, idx: usize, locality: u32, key: &Digest) -> bool {
+        self.reusable.get()
+            && self.size != 0
+            && self.locality == locality
+            && usize::from(self.reuse_idx) == idx
+            && key.bytes() == self.reuse_key
+    }
+
+    /// The certificate or token, or an empty slice if there is none.
+    pub(crate) fn get(&self) -> &[u8] {
+        self.cert.get(..self.size as usize).unwrap_or_default()
+    }
+
+    /// Replaces the certificate with `cert`, issued to `locality`, and the
+    /// layer chain with `chain`. With `reuse`, records the index of the
+    /// context `cert` was issued for and the reuse key of the request so
+    /// that repeating it returns `cert`; otherwise `cert` is not reused.
+    /// Nothing changes if this fails.
+    pub(crate) fn set(
+        &mut self,
+        locality: u32,
+        cert: &[u8],
+        chain: &[u8],
+        reuse: Option<(usize, &Digest)>,
+    ) -> Result<(), DpeErrorCode> {
+        let size = u32::try_from(cert.len()).map_err(|_| DpeErrorCode::InternalError)?;
+        let chain_size = u32::try_from(chain.len()).map_err(|_| DpeErrorCode::InternalError)?;
+        let reuse = match reuse {
+            Some((idx, key)) => Some((
+                u8::try_from(idx).map_err(|_| DpeErrorCode::InternalError)?,
+                key.bytes()
+                    .get(..DPE_PROFILE.get_hash_size())
+                    .ok_or(DpeErrorCode::InternalError)?,
+            )),
+            None => None,
+        };
+        let cert_out = self
+            .cert
+            .get_mut(..cert.len())
+            .ok_or(DpeErrorCode::CertificateTooLarge)?;
+        let chain_out = self
+            .chain
+            .get_mut(..chain.len())
+            .ok_or(DpeErrorCode::CertificateTooLarge)?;
+
+        cert_out.copy_from_slice(cert);
+        chain_out.copy_from_slice(chain);
+        self.size = size;
+        self.chain_size = chain_size;
+        self.locality = locality;
+        match reuse {
+            Some((idx, key)) => {
+                self.reuse_key.copy_from_slice(key);
+                self.reuse_idx = idx;
+                self.reusable = true.into();
+            }
+            None => self.forbid_reuse(),
+        }
+        Ok(())
+    }
+
+    /// Stops CertifyKey from returning `cert` without issuing a new one.
+    pub(crate) fn forbid_reuse(&mut self) {
+        self.reusable = false.into();
+    }
+
+    /// Layer certificates GetCertificateChain appends to the platform's chain
+    /// for `locality`. Empty unless `locality` issued the last CertifyKey and
+    /// it was signed by a layer key.
+    pub(crate) fn layer_chain(&self, locality: u32) -> &[u8] {
+        if self.size == 0 || self.locality != locality {
+            return &[];
+        }
+        self.chain
+            .get(..self.chain_size as usize)
+            .unwrap_or_default()
+    }
+}
+
+/// The self-signed DeviceID certificate DPE issues itself when the default
+/// context is initialized and DEVICE_ID_CERT is supported.
+#[repr(C, align(4))]
+#[derive(AsBytes, FromBytes, Zeroize)]
+pub(crate) struct DeviceIdCert {
+    /// Number of valid bytes in `cert`. Zero when there is no certificate.
+    pub(crate) size: u32,
+    pub(crate) cert: [u8; MAX_CERT_SIZE],
+}
+
+impl DeviceIdCert {
+    pub const fn new() -> DeviceIdCert {
+        DeviceIdCert {
+            size: 0,
+            cert: [0; MAX_CERT_SIZE],
+        }
+    }
+
+    /// The DER certificate, or an empty slice if none was issued.
+    pub(crate) fn get(&self) -> &[u8] {
+        self.cert.get(..self.size as usize).unwrap_or_default()
+    }
+}
+
+/// Label of the key each context issues its children's certificates with.
+const LAYER_KEY_LABEL: [u8; DPE_PROFILE.get_hash_size()] = [0; DPE_PROFILE.get_hash_size()];
+
+/// Info of the layer key derivation. CertifyKey and Sign derive with b"ECC",
+/// so clients can never sign with a layer key.
+const LAYER_KEY_INFO: &[u8] = b"DPE Layer";
+
+/// Most layer certificates `PendingCert::chain` holds, and so the most
+/// ancestors a context certified with LAYERED_CERTS may have. Each layer
+/// certificate is limited to `MAX_CERT_SIZE`, so a full chain always fits.
+pub const MAX_LAYER_CHAIN_DEPTH: usize = MAX_CERTIFY_KEY_SIZE / MAX_CERT_SIZE;
+
+const _: () = assert!(MAX_LAYER_CHAIN_DEPTH > 0);
+
+/// Info of RSA key derivations, which keeps them apart from the ECDSA key
+/// derived with the same label.
+#[cfg(feature = "rsa")]
+const RSA_KEY_INFO: &[u8] = b"RSA";
+
+/// Info of the DeviceID CDI derivation. It is derived without a measurement
+/// so the DeviceID key is the same on every boot.
+const DEVICE_ID_CDI_INFO: &[u8] = b"DPE DeviceID";
+
+/// Public key type of the keys CertifyKey certifies in this profile.
+#[cfg(not(feature = "dpe_profile_ed25519_sha512"))]
+type LeafPubKey = EcdsaPub;
+#[cfg(feature = "dpe_profile_ed25519_sha512")]
+type LeafPubKey = Ed25519Pub;
+
+/// Length of the hex serial in a layer certificate's subject name.
+const LAYER_SERIAL_SIZE: usize = 64;
+
+/// Computes the key identifier of `pub_key` as the hash of the key, truncated
+/// to 20 bytes.
+fn key_identifier(
+    crypto: &mut impl Crypto,
+    pub_key: SubjectPubKey,
+) -> Result<[u8; MAX_KEY_IDENTIFIER_SIZE], DpeErrorCode> {
+    let hashed_pub_key = pub_key.digest(crypto)?;
+    let mut key_identifier = [0u8; MAX_KEY_IDENTIFIER_SIZE];
+    key_identifier.copy_from_slice(
+        hashed_pub_key
+            .bytes()
+            .get(..MAX_KEY_IDENTIFIER_SIZE)
+            .ok_or(DpeErrorCode::InternalError)?,
+    );
+    Ok(key_identifier)
+}
+
+/// The key a context issues its children's X.509 certificates with when
+/// LAYERED_CERTS is supported.
+///
+/// Only the public half is kept. The private key is derived again by `sign`
+/// and released straight after, so no key handle outlives a single call.
+struct LayerKey {
+    /// Measurement hash the key's CDI is derived from.
+    measurement: [u8; DPE_PROFILE.get_hash_size()],
+    /// Info string the key's CDI is derived with.
+    cdi_info: &'static [u8],
+    /// Common name of the subject.
+    cn: &'static [u8],
+    pub_key: EcdsaPub,
+    /// Hex string of the public key, used in the subject name.
+    serial: [u8; LAYER_SERIAL_SIZE],
+    key_identifier: [u8; MAX_KEY_IDENTIFIER_SIZE],
+}
+
+impl LayerKey {
+    /// Derives the layer key of the context whose measurement hash is
+    /// `digest`.
+    fn derive(crypto: &mut impl Crypto, digest: &Digest) -> Result<Self, DpeErrorCode> {
+        Self::derive_with_info(crypto, digest, b"DPE", b"DPE Layer")
+    }
+
+    /// Derives the DeviceID key, which signs its own certificate and takes
+    /// the alias key's place as the issuer of root certificates.
+    fn derive_device_id(crypto: &mut impl Crypto) -> Result<Self, DpeErrorCode> {
+        let digest = Digest::new(&[0; DPE_PROFILE.get_hash_size()])?;
+        Self::derive_with_info(crypto, &digest, DEVICE_ID_CDI_INFO, b"DPE DeviceID")
+    }
+
+    fn derive_with_info(
+        crypto: &mut impl Crypto,
+        digest: &Digest,
+        cdi_info: &'static [u8],
+        cn: &'static [u8],
+    ) -> Result<Self, DpeErrorCode> {
+        let mut measurement = [0u8; DPE_PROFILE.get_hash_size()];
+        measurement.copy_from_slice(
+            digest
+                .bytes()
+                .get(..DPE_PROFILE.get_hash_size())
+                .ok_or(DpeErrorCode::InternalError)?,
+        );
+        let (priv_key, pub_key) = Self::derive_key_pair(crypto, digest, cdi_info)?;
+        crypto.destroy_key(priv_key)?;
+
+        let algs = DPE_PROFILE.alg_len();
+        let mut pub_key_serial = [0u8; DPE_PROFILE.get_hash_size() * 2];
+        crypto.get_pubkey_serial(algs, &pub_key, &mut pub_key_serial)?;
+        let mut serial = [0u8; LAYER_SERIAL_SIZE];
+        serial.copy_from_slice(
+            pub_key_serial
+                .get(..LAYER_SERIAL_SIZE)
+                .ok_or(DpeErrorCode::InternalError)?,
+        );
+
+        let key_identifier = key_identifier(crypto, SubjectPubKey::Ecdsa(&pub_key))?;
+        Ok(LayerKey {
+            measurement,
+            cdi_info,
+            cn,
+            pub_key,
+            serial,
+            key_identifier,
+        })
+    }
+
+    fn derive_key_pair<C: Crypto>(
+        crypto: &mut C,
+        digest: &Digest,
+        cdi_info: &[u8],
+    ) -> Result<(C::PrivKey, EcdsaPub), DpeErrorCode> {
+        let algs = DPE_PROFILE.alg_len();
+        let cdi = crypto.derive_cdi(algs, digest, cdi_info)?;
+        let key_pair = crypto.derive_key_pair(algs, &cdi, &LAYER_KEY_LABEL, LAYER_KEY_INFO);
+        Ok(destroy_cdi_after_derive(crypto, cdi, key_pair)?)
+    }
+
+    /// Signs `digest` with the layer key.
+    fn sign(&self, crypto: &mut impl Crypto, digest: &Digest) -> Result<EcdsaSig, DpeErrorCode> {
+        let measurement = Digest::new(&self.measurement)?;
+        let (priv_key, pub_key) = Self::derive_key_pair(crypto, &measurement, self.cdi_info)?;
+        let sig =
+            crypto.ecdsa_sign_with_derived(DPE_PROFILE.alg_len(), digest, &priv_key, &pub_key);
+        crypto.destroy_key(priv_key)?;
+        Ok(sig?)
+    }
+
+    fn subject_name(&self) -> Name {
+        Name {
+            cn: DirectoryString::PrintableString(self.cn),
+            serial: DirectoryString::PrintableString(&self.serial),
+            org: None,
+            org_unit: None,
+        }
+    }
+
+    /// Writes the DER subject name of the layer certificate to `out`.
+    fn encode_name(&self, out: &mut [u8; MAX_ISSUER_NAME_SIZE]) -> Result<usize, DpeErrorCode> {
+        CertWriter::new(out, true).encode_rdn(&self.subject_name())
+    }
+}
+
+/// Writes the certificate of the layer key `subject` to `out`. It is signed by
+/// `issuer`, or by the alias key if there is none. Passing the subject as its
+/// own issuer writes a self-signed certificate.
+///
+/// # Arguments
+///
+/// * `tci_nodes` - TCIs from the root down to the subject's context
+/// * `supports_recursive` - whether the RECURSIVE support flag is set
+/// * `tbs_buffer` - scratch space for the TBSCertificate, `MAX_CERT_SIZE`
+///   bytes since the certificate can't be larger
+#[allow(clippy::too_many_arguments)]
+fn encode_layer_cert<C: Crypto>(
+    crypto: &mut C,
+    platform: &mut impl Platform,
+    tci_nodes: &[TciNodeData],
+    supports_recursive: bool,
+    subject: &LayerKey,
+    issuer: Option<&LayerKey>,
+    tbs_buffer: &mut [u8],
+    out: &mut [u8],
+) -> Result<usize, DpeErrorCode> {
+    let mut issuer_name = [0u8; MAX_ISSUER_NAME_SIZE];
+    let mut authority_key_identifier = [0u8; MAX_KEY_IDENTIFIER_SIZE];
+    let issuer_len = match issuer {
+        Some(issuer) => {
+            authority_key_identifier = issuer.key_identifier;
+            issuer.encode_name(&mut issuer_name)?
+        }
+        None => {
+            platform.get_issuer_key_identifier(&mut authority_key_identifier)?;
+            platform.get_issuer_name(&mut issuer_name)?
+        }
+    };
+
+    let mut ueid = [0u8; MAX_UEID_SIZE];
+    let ueid_len = platform.get_ueid(&mut ueid)?;
+
+    let measurements = MeasurementData {
+        ueid: ueid.get(..ueid_len).ok_or(DpeErrorCode::InternalError)?,
+        tci_nodes,
+        is_ca: true,
+        supports_recursive,
+        subject_key_identifier: subject.key_identifier,
+        authority_key_identifier,
+        // Counters change with every command. Leave them out so the layer
+        // certificates stay the same while the TCIs do.
+        command_counter: None,
+        label: None,
+        additional_fwids: &[],
+    };
+
+    let serial_number =
+        x509::serial_number_from_pubkey(crypto, SubjectPubKey::Ecdsa(&subject.pub_key))?;
+    let cert_validity = platform.get_cert_validity()?;
+    let mut tbs_writer = CertWriter::new(tbs_buffer, true);
+    let tbs_size = tbs_writer.encode_ecdsa_tbs(
+        &serial_number,
+        issuer_name
+            .get(..issuer_len)
+            .ok_or(DpeErrorCode::InternalError)?,
+        &subject.subject_name(),
+        SubjectPubKey::Ecdsa(&subject.pub_key),
+        &measurements,
+        &cert_validity,
+    )?;
+    let tbs = tbs_buffer
+        .get(..tbs_size)
+        .ok_or(DpeErrorCode::InternalError)?;
+
+    let algs = DPE_PROFILE.alg_len();
+    let tbs_digest = crypto.hash(algs, tbs)?;
+    let sig = match issuer {
+        Some(issuer) => issuer.sign(crypto, &tbs_digest)?,
+        None => crypto.ecdsa_sign_with_alias(algs, &tbs_digest)?,
+    };
+    CertWriter::new(out, true).encode_ecdsa_certificate(tbs, &sig)
+}
+
+/// Start of a leaf certificate's commonName, followed by the TCI type.
+const LEAF_CN_PREFIX: &[u8] = b"DPE Leaf ";
+
+/// Length of the hex cumulative TCI in a leaf certificate's subject name.
+const LEAF_SERIAL_SIZE: usize = 64;
+
+/// Subject commonName and serialNumber of a leaf certificate.
+///
+/// They identify the firmware layer of the certified context: the commonName
+/// holds its TCI type and the serialNumber its cumulative TCI, both in hex,
+/// unless the platform names the subject itself with
+/// `Platform::get_leaf_subject_name`.
+struct LeafName {
+    name: SubjectName,
+    /// Whether the platform named the subject, in which case the attributes
+    /// are any UTF-8 rather than hex.
+    from_platform: bool,
+}
+
+impl LeafName {
+    fn new(platform: &mut impl Platform, tci: &TciNodeData) -> Result<Self, DpeErrorCode> {
+        match platform.get_leaf_subject_name(tci.tci_type, &tci.tci_cumulative.0) {
+            Ok(name) => {
+                let valid = |attr: &[u8]| !attr.is_empty() && core::str::from_utf8(attr).is_ok();
+                if !valid(&name.cn) || !valid(&name.serial) {
+                    return Err(DpeErrorCode::Platform(PlatformError::SubjectNameError(0)));
+                }
+                return Ok(LeafName {
+                    name,
+                    from_platform: true,
+                });
+            }
+            Err(PlatformError::NotImplemented) => (),
+            Err(e) => return Err(e.into()),
+        }
+
+        let mut cn = [0u8; LEAF_CN_PREFIX.len() + 8];
+        cn[..LEAF_CN_PREFIX.len()].copy_from_slice(LEAF_CN_PREFIX);
+        cn[LEAF_CN_PREFIX.len()..].copy_from_slice(&x509::u32_hex_string(tci.tci_type));
+        let mut tci_hex = [0u8; DPE_PROFILE.get_tci_size() * 2];
+        Digest::new(&tci.tci_cumulative.0)?.write_hex_str(&mut tci_hex)?;
+        let serial = tci_hex
+            .get(..LEAF_SERIAL_SIZE)
+            .ok_or(DpeErrorCode::InternalError)?;
+
+        let mut name = SubjectName::default();
+        name.cn
+            .try_extend_from_slice(&cn)
+            .map_err(|_| DpeErrorCode::InternalError)?;
+        name.serial
+            .try_extend_from_slice(serial)
+            .map_err(|_| DpeErrorCode::InternalError)?;
+        Ok(LeafName {
+            name,
+            from_platform: false,
+        })
+    }
+
+    fn subject_name(&self) -> Name {
+        let (cn, serial) = (self.name.cn.as_slice(), self.name.serial.as_slice());
+        let (cn, serial) = if self.from_platform {
+            (
+                DirectoryString::Utf8String(cn),
+                DirectoryString::Utf8String(serial),
+            )
+        } else {
+            (
+                DirectoryString::PrintableString(cn),
+                DirectoryString::PrintableString(serial),
+            )
+        };
+        Name {
+            cn,
+            serial,
+            org: None,
+            org_unit: None,
+        }
+    }
+}
+
+/// Reads the FWIDs the platform adds to leaf certificates. None are added if
+/// the platform doesn't implement `Platform::get_additional_fwids`.
+fn get_additional_fwids(
+    platform: &mut impl Platform,
+    out: &mut [Fwid; MAX_ADDITIONAL_FWIDS],
+) -> Result<usize, DpeErrorCode> {
+    match platform.get_additional_fwids(out) {
+        Ok(count) if count <= MAX_ADDITIONAL_FWIDS => Ok(count),
+        Ok(_) => Err(DpeErrorCode::Platform(PlatformError::AdditionalFwidError(
+            0,
+        ))),
+        Err(PlatformError::NotImplemented) => Ok(0),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Returns the DeviceID key if DPE issued itself a DeviceID certificate. It
+/// then issues root certificates instead of the alias key.
+fn root_issuer<C: Crypto>(
+    dpe: &DpeInstance,
+    crypto: &mut C,
+) -> Result<Option<LayerKey>, DpeErrorCode> {
+    if dpe.device_id_cert.size == 0 {
+        return Ok(None);
+    }
+    LayerKey::derive_device_id(crypto).map(Some)
+}
+
+/// Writes the self-signed DeviceID certificate into `dpe.device_id_cert`.
+/// Its TCIs are those of the newly initialized default context at `idx`.
+pub(crate) fn write_device_id_cert<'a, T: DpeTypes + 'a>(
+    dpe: &mut DpeInstance,
+    env: &mut DpeEnv<'a, T>,
+    idx: usize,
+) -> Result<(), DpeErrorCode> {
+    const INITIALIZER: TciNodeData = TciNodeData::new();
+    let mut nodes = [INITIALIZER; MAX_HANDLES];
+    let tcb_count = dpe.get_tcb_nodes(idx, &mut nodes)?;
+    let device_id = LayerKey::derive_device_id(&mut env.crypto)?;
+
+    dpe.device_id_cert.size = 0;
+    #[cfg(feature = "no-large-stack")]
+    let mut scratch = Scratch::new(env.arena);
+    scratch_buffer!(scratch, tbs_buffer, MAX_CERT_SIZE);
+    let size = encode_layer_cert(
+        &mut env.crypto,
+        &mut env.platform,
+        nodes.get(..tcb_count).ok_or(DpeErrorCode::InternalError)?,
+        dpe.support.recursive(),
+        &device_id,
+        Some(&device_id),
+        tbs_buffer,
+        &mut dpe.device_id_cert.cert,
+    )?;
+    dpe.device_id_cert.size = u32::try_from(size).map_err(|_| DpeErrorCode::InternalError)?;
+    Ok(())
+}
+
+/// The contexts from the root down to the issuer of a certificate issued
+/// with LAYERED_CERTS, and their measurement hashes.
+struct LayerPath {
+    /// Indices of the contexts, root first.
+    contexts: [usize; MAX_LAYER_CHAIN_DEPTH],
+    measurements: [[u8; DPE_PROFILE.get_hash_size()]; MAX_LAYER_CHAIN_DEPTH],
+    depth: usize,
+}
+
+impl LayerPath {
+    /// Collects the path from the root down to the context at `idx`.
+    ///
+    /// Returns `MaxTcis` if the context at `idx` is deeper than
+    /// `MAX_LAYER_CHAIN_DEPTH`.
+    fn new(
+        dpe: &mut DpeInstance,
+        env: &mut DpeEnv<impl DpeTypes>,
+        idx: usize,
+    ) -> Result<Self, DpeErrorCode> {
+        let mut contexts = [0usize; MAX_LAYER_CHAIN_DEPTH];
+        let mut depth = 0;
+        let mut next = Some(idx);
+        while let Some(pos) = next {
+            *contexts.get_mut(depth).ok_or(DpeErrorCode::MaxTcis)? = pos;
+            depth += 1;
+            next = dpe
+                .contexts
+                .get(pos)
+                .ok_or(DpeErrorCode::InternalError)?
+                .parent_pos();
+        }
+        contexts[..depth].reverse();
+
+        let mut measurements = [[0u8; DPE_PROFILE.get_hash_size()]; MAX_LAYER_CHAIN_DEPTH];
+        for (&pos, measurement) in contexts[..depth].iter().zip(measurements.iter_mut()) {
+            let digest = dpe.compute_measurement_hash(env, pos)?;
+            measurement.copy_from_slice(
+                digest
+                    .bytes()
+                    .get(..DPE_PROFILE.get_hash_size())
+                    .ok_or(DpeErrorCode::InternalError)?,
+            );
+        }
+        Ok(LayerPath {
+            contexts,
+            measurements,
+            depth,
+        })
+    }
+
+    /// Writes the layer certificates of the path into `out`, root first.
+    /// Returns their size and the layer key of the last context, which
+    /// issues the certificate below it.
+    ///
+    /// `tbs_buffer` is scratch space for the TBSCertificate of each layer
+    /// certificate.
+    fn write_chain<C: Crypto>(
+        &self,
+        dpe: &DpeInstance,
+        crypto: &mut C,
+        platform: &mut impl Platform,
+        tbs_buffer: &mut [u8],
+        out: &mut [u8],
+    ) -> Result<(usize, LayerKey), DpeErrorCode> {
+        let contexts = self
+            .contexts
+            .get(..self.depth)
+            .ok_or(DpeErrorCode::InternalError)?;
+
+        const INITIALIZER: TciNodeData = TciNodeData::new();
+        let mut nodes = [INITIALIZER; MAX_HANDLES];
+        let mut chain_size = 0;
+        let mut issuer = root_issuer(dpe, crypto)?;
+        for (&pos, measurement) in contexts.iter().zip(&self.measurements) {
+            let subject = LayerKey::derive(crypto, &Digest::new(measurement)?)?;
+            let tcb_count = dpe.get_tcb_nodes(pos, &mut nodes)?;
+            chain_size += encode_layer_cert(
+                crypto,
+                platform,
+                nodes.get(..tcb_count).ok_or(DpeErrorCode::InternalError)?,
+                dpe.support.recursive(),
+                &subject,
+                issuer.as_ref(),
+                tbs_buffer,
+                out.get_mut(chain_size..chain_size + MAX_CERT_SIZE)
+                    .ok_or(DpeErrorCode::InternalError)?,
+            )?;
+            issuer = Some(subject);
+        }
+        Ok((chain_size, issuer.ok_or(DpeErrorCode::InternalError)?))
+    }
+}
+
+/// What CertifyKey works out with the whole `DpeEnv`, before its scratch
+/// buffers take the arena.
+struct PreparedCert {
+    /// With CERT_CACHE, the reuse key of the request.
+    reuse_key: Option<Digest>,
+    /// Whether `dpe.pending_cert` already holds the certificate the request
+    /// would issue.
+    reuse: bool,
+    new_handle: ContextHandle,
+    /// With LAYERED_CERTS, the path down to the issuer of an X.509
+    /// certificate for a derived context.
+    layer_path: Option<LayerPath>,
+    #[cfg(feature = "rsa")]
+    rsa_key_size: Option<RsaKeySize>,
+    #[cfg(feature = "rsa")]
+    rsa_pub_key: Option<RsaPub>,
+}
+
+impl PreparedCert {
+    /// The key the certificate is issued for: the RSA key if one was
+    /// requested, `pub_key` otherwise.
+    fn subject_pub_key<'k>(&'k self, pub_key: &'k LeafPubKey) -> SubjectPubKey<'k> {
+        #[cfg(not(feature = "dpe_profile_ed25519_sha512"))]
+        let subject_pub_key = SubjectPubKey::Ecdsa(pub_key);
+        #[cfg(feature = "dpe_profile_ed25519_sha512")]
+        let subject_pub_key = SubjectPubKey::Ed25519(pub_key);
+        #[cfg(feature = "rsa")]
+        let subject_pub_key = match &self.rsa_pub_key {
+            Some(rsa_pub_key) => SubjectPubKey::Rsa(rsa_pub_key),
+            None => subject_pub_key,
+        };
+        subject_pub_key
+    }
 }
 
 impl CommandExecution for CertifyKeyCmd {
@@ -54,6 +869,13 @@ impl CommandExecution for CertifyKeyCmd {
         env: &mut DpeEnv<impl DpeTypes>,
         locality: u32,
     ) -> Result<Response, DpeErrorCode> {
+        dpe_trace!(
+            "CertifyKey flags={:#x} format={} locality={:#x}",
+            self.flags.bits(),
+            self.format,
+            locality
+        );
+
         let idx = dpe.get_active_context_pos(&self.handle, locality)?;
         let context = &dpe.contexts[idx];
 
@@ -71,8 +893,25 @@ impl CommandExecution for CertifyKeyCmd {
             if !context.allow_x509() {
                 return Err(DpeErrorCode::InvalidArgument);
             }
-        } else if self.format == Self::FORMAT_CSR && !dpe.support.csr() {
-            return Err(DpeErrorCode::ArgumentNotSupported);
+        } else if self.format == Self::FORMAT_CSR {
+            if !dpe.support.csr() {
+                return Err(DpeErrorCode::ArgumentNotSupported);
+            }
+        } else if self.format == Self::FORMAT_CWT {
+            if !dpe.support.cwt() {
+                return Err(DpeErrorCode::ArgumentNotSupported);
+            }
+            // A CWT is a certificate issued by DPE, like an X.509 certificate.
+            if !context.allow_x509() {
+                return Err(DpeErrorCode::InvalidArgument);
+            }
+        } else if self.format == Self::FORMAT_COSE_KEY {
+            if !dpe.support.cwt() {
+                return Err(DpeErrorCode::ArgumentNotSupported);
+            }
+        } else {
+            // Reject unknown formats before doing any key derivation.
+            return Err(DpeErrorCode::InvalidArgument);
         }
 
         // Make sure the command is coming from the right locality.
@@ -80,23 +919,68 @@ impl CommandExecution for CertifyKeyCmd {
             return Err(DpeErrorCode::InvalidLocality);
         }
 
+        // RSA keys can only be certified in X.509 certificates and CSRs, and
+        // only the CSR is signed with the RSA key itself.
+        if self.uses_rsa() && (!cfg!(feature = "rsa") || self.uses_cose()) {
+            return Err(DpeErrorCode::ArgumentNotSupported);
+        }
+        #[cfg(feature = "rsa")]
+        self.rsa_key_size()?;
+        #[cfg(feature = "rsa")]
+        if self.rsa_padding() == RsaPadding::Pss && self.format != Self::FORMAT_CSR {
+            return Err(DpeErrorCode::InvalidArgument);
+        }
+
+        // The certificate encoders only support classical subject keys so far,
+        // so post-quantum profiles can sign but not certify their keys.
+        if DPE_PROFILE.is_pq() {
+            return Err(DpeErrorCode::ArgumentNotSupported);
+        }
+        // A CWT carries the key as an EC2 COSE_Key, which has no room for an
+        // Ed25519 key.
+        if DPE_PROFILE.is_eddsa() && self.uses_cose() {
+            return Err(DpeErrorCode::ArgumentNotSupported);
+        }
+
         cfg_if! {
             if #[cfg(not(feature = "no-cfi"))] {
+                cfi_assert!(!DPE_PROFILE.is_pq());
+                cfi_assert!(!DPE_PROFILE.is_eddsa() || !self.uses_cose());
+                cfi_assert!(!self.uses_rsa() || cfg!(feature = "rsa"));
+                cfi_assert!(!self.uses_rsa() || !self.uses_cose());
                 cfi_assert!(!self.uses_is_ca() || dpe.support.is_ca());
                 cfi_assert!(!self.uses_is_ca() || context.allow_ca());
                 cfi_assert!(self.format != Self::FORMAT_X509 || dpe.support.x509());
                 cfi_assert!(self.format != Self::FORMAT_X509 || context.allow_x509());
                 cfi_assert!(self.format != Self::FORMAT_CSR || dpe.support.csr());
+                cfi_assert!(self.format != Self::FORMAT_CWT || dpe.support.cwt());
+                cfi_assert!(self.format != Self::FORMAT_CWT || context.allow_x509());
+                cfi_assert!(self.format != Self::FORMAT_COSE_KEY || dpe.support.cwt());
+                cfi_assert!(
+                    self.format == Self::FORMAT_X509
+                        || self.format == Self::FORMAT_CSR
+                        || self.format == Self::FORMAT_CWT
+                        || self.format == Self::FORMAT_COSE_KEY
+                );
                 cfi_assert_eq(context.locality, locality);
             }
         }
 
         let algs = DPE_PROFILE.alg_len();
-        let digest = dpe.compute_measurement_hash(env, idx)?;
-        let cdi = env
-            .crypto
-            .derive_cdi(DPE_PROFILE.alg_len(), &digest, b"DPE")?;
+        let (digest, cdi) = dpe.derive_cdi(env, idx)?;
+        let prepared = match self.prepare_cert(dpe, env, idx, locality, &digest, &cdi) {
+            Ok(prepared) => prepared,
+            Err(e) => {
+                env.crypto.destroy_cdi(cdi)?;
+                return Err(e);
+            }
+        };
+        #[cfg(not(feature = "dpe_profile_ed25519_sha512"))]
         let key_pair = env.crypto.derive_key_pair(algs, &cdi, &self.label, b"ECC");
+        #[cfg(feature = "dpe_profile_ed25519_sha512")]
+        let key_pair = env
+            .crypto
+            .derive_ed25519_key_pair(algs, &cdi, &self.label, b"ED25519");
         if cfi_launder(key_pair.is_ok()) {
             #[cfg(not(feature = "no-cfi"))]
             cfi_assert!(key_pair.is_ok());
@@ -104,17 +988,176 @@ impl CommandExecution for CertifyKeyCmd {
             #[cfg(not(feature = "no-cfi"))]
             cfi_assert!(key_pair.is_err());
         }
-        let (priv_key, pub_key) = key_pair?;
+        let (priv_key, pub_key) = match key_pair {
+            Ok(key_pair) => key_pair,
+            Err(e) => {
+                env.crypto.destroy_cdi(cdi)?;
+                return Err(e.into());
+            }
+        };
+        let subject_pub_key = prepared.subject_pub_key(&pub_key);
+
+        // The certificate is issued into scratch buffers and only copied to
+        // `dpe.pending_cert` once nothing else can fail.
+        #[cfg(feature = "no-large-stack")]
+        let mut scratch = Scratch::new(env.arena);
+        scratch_buffer!(scratch, cert_buffer, MAX_CERTIFY_KEY_SIZE);
+        scratch_buffer!(scratch, chain_buffer, MAX_CERTIFY_KEY_SIZE);
+        scratch_buffer!(scratch, tbs_buffer, MAX_CERTIFY_KEY_SIZE);
+        let issued = if prepared.reuse {
+            Ok(None)
+        } else {
+            self.certify_derived_key(
+                dpe,
+                &mut env.crypto,
+                &mut env.platform,
+                idx,
+                &prepared,
+                &cdi,
+                &priv_key,
+                subject_pub_key,
+                cert_buffer,
+                chain_buffer,
+                tbs_buffer,
+            )
+            .map(Some)
+        };
+        // Release the keys before anything is committed, so that a failure
+        // leaves the context and the pending certificate as they were.
+        let destroyed = env.crypto.destroy_key(priv_key);
+        env.crypto.destroy_cdi(cdi)?;
+        destroyed?;
+
+        let resp = match issued? {
+            Some((cert_size, chain_size)) => {
+                let cert = cert_buffer
+                    .get(..cert_size)
+                    .ok_or(DpeErrorCode::InternalError)?;
+                let chain = chain_buffer
+                    .get(..chain_size)
+                    .ok_or(DpeErrorCode::InternalError)?;
+                let resp = Self::certify_key_resp(cert, prepared.new_handle, subject_pub_key)?;
+                let reuse = prepared.reuse_key.as_ref().map(|key| (idx, key));
+                dpe.pending_cert.set(locality, cert, chain, reuse)?;
+                resp
+            }
+            // The request would issue the certificate that is already pending.
+            None => Self::certify_key_resp(
+                dpe.pending_cert.get(),
+                prepared.new_handle,
+                subject_pub_key,
+            )?,
+        };
+
+        // Rotate handle if it isn't the default
+        dpe.contexts[idx].handle = resp.new_context_handle;
+
+        Ok(Response::CertifyKey(resp))
+    }
+}
+
+impl CertifyKeyCmd {
+    /// Works out everything issuing the certificate needs the whole `env`
+    /// for. Only the measurement cache in `dpe` may change.
+    #[cfg_attr(not(feature = "rsa"), allow(unused_variables))]
+    fn prepare_cert<'a, T: DpeTypes + 'a>(
+        &self,
+        dpe: &mut DpeInstance,
+        env: &mut DpeEnv<'a, T>,
+        idx: usize,
+        locality: u32,
+        digest: &Digest,
+        cdi: &<T::Crypto<'a> as Crypto>::Cdi,
+    ) -> Result<PreparedCert, DpeErrorCode> {
+        // The key pair is derived either way: it is cheap next to an RSA key
+        // and keeps the path to it the same.
+        #[cfg(feature = "rsa")]
+        let rsa_key_size = self.rsa_key_size()?;
+        #[cfg(feature = "rsa")]
+        let rsa_pub_key = match rsa_key_size {
+            Some(size) => Some(env.crypto.derive_rsa_pub(
+                DPE_PROFILE.alg_len(),
+                cdi,
+                &self.label,
+                RSA_KEY_INFO,
+                size,
+            )?),
+            None => None,
+        };
+
+        // With CERT_CACHE, a request that would issue the same certificate
+        // as the last one gets it back without signing anything.
+        let reuse_key = if dpe.support.cert_cache() {
+            let command_counter = dpe
+                .support
+                .command_counter()
+                .then_some(dpe.contexts[idx].command_counter);
+            Some(self.reuse_key(env, digest, command_counter)?)
+        } else {
+            None
+        };
+        let reuse = reuse_key
+            .as_ref()
+            .is_some_and(|key| dpe.pending_cert.can_reuse(idx, locality, key));
+
+        // Pick the new handle up front and only store it once the certificate
+        // is written, so that a failure leaves the context untouched.
+        let new_handle = dpe.next_onetime_use_handle(env, &dpe.contexts[idx])?;
+
+        // With LAYERED_CERTS, the X.509 certificate of a derived context is
+        // issued by its parent's layer key instead of the alias key.
+        let layer_path = match dpe.contexts[idx].parent_pos() {
+            Some(parent)
+                if !reuse && dpe.support.layered_certs() && self.format == Self::FORMAT_X509 =>
+            {
+                Some(LayerPath::new(dpe, env, parent)?)
+            }
+            _ => None,
+        };
 
-        let mut subj_serial = [0u8; DPE_PROFILE.get_hash_size() * 2];
-        env.crypto
-            .get_pubkey_serial(DPE_PROFILE.alg_len(), &pub_key, &mut subj_serial)?;
-        // The serial number of the subject can be at most 64 bytes
-        let truncated_subj_serial = &subj_serial[..64];
+        Ok(PreparedCert {
+            reuse_key,
+            reuse,
+            new_handle,
+            layer_path,
+            #[cfg(feature = "rsa")]
+            rsa_key_size,
+            #[cfg(feature = "rsa")]
+            rsa_pub_key,
+        })
+    }
 
+    /// Issues a certificate for `subject_pub_key`, the key derived from
+    /// `cdi`, into `cert_buffer`, and its layer chain into `chain_buffer`.
+    /// Returns the size of each. Nothing in `dpe` changes.
+    ///
+    /// `tbs_buffer` is scratch space for what the certificate signs. A CSR,
+    /// which never has a layer chain, is built in `chain_buffer`.
+    #[allow(clippy::too_many_arguments)]
+    #[cfg_attr(not(feature = "rsa"), allow(unused_variables))]
+    fn certify_derived_key<C: Crypto>(
+        &self,
+        dpe: &DpeInstance,
+        crypto: &mut C,
+        platform: &mut impl Platform,
+        idx: usize,
+        prepared: &PreparedCert,
+        cdi: &C::Cdi,
+        priv_key: &C::PrivKey,
+        subject_pub_key: SubjectPubKey,
+        cert_buffer: &mut [u8; MAX_CERTIFY_KEY_SIZE],
+        chain_buffer: &mut [u8; MAX_CERTIFY_KEY_SIZE],
+        tbs_buffer: &mut [u8; MAX_CERTIFY_KEY_SIZE],
+    ) -> Result<(usize, usize), DpeErrorCode> {
+        // The vendor ID and SKU come from the platform, so one build can tell
+        // the SKUs it runs on apart.
+        let vendor_id = x509::u32_hex_string(platform.get_vendor_id()?);
+        let vendor_sku = x509::u32_hex_string(platform.get_vendor_sku()?);
+        let leaf_name = LeafName::new(platform, &dpe.contexts[idx].tci)?;
         let subject_name = Name {
-            cn: DirectoryString::PrintableString(b"DPE Leaf"),
-            serial: DirectoryString::PrintableString(truncated_subj_serial),
+            org: Some(DirectoryString::PrintableString(&vendor_id)),
+            org_unit: Some(DirectoryString::PrintableString(&vendor_sku)),
+            ..leaf_name.subject_name()
         };
 
         // Get TCI Nodes
@@ -125,175 +1168,333 @@ impl CommandExecution for CertifyKeyCmd {
             return Err(DpeErrorCode::InternalError);
         }
 
-        let mut subject_key_identifier = [0u8; MAX_KEY_IDENTIFIER_SIZE];
-        // compute key identifier as SHA hash of the DER encoded subject public key
-        let mut hasher = env.crypto.hash_initialize(DPE_PROFILE.alg_len())?;
-        hasher.update(&[0x04])?;
-        hasher.update(pub_key.x.bytes())?;
-        hasher.update(pub_key.y.bytes())?;
-        let hashed_pub_key = hasher.finish()?;
-        if hashed_pub_key.len() < MAX_KEY_IDENTIFIER_SIZE {
-            return Err(DpeErrorCode::InternalError);
-        }
-        // truncate key identifier to 20 bytes
-        subject_key_identifier.copy_from_slice(&hashed_pub_key.bytes()[..MAX_KEY_IDENTIFIER_SIZE]);
+        let subject_key_identifier = key_identifier(crypto, subject_pub_key)?;
+
+        // The layer chain is written first, while `tbs_buffer` is free. With
+        // DEVICE_ID_CERT, the DeviceID key stands in for the alias key.
+        let (chain_size, layer_issuer) = match &prepared.layer_path {
+            Some(path) => {
+                let (size, issuer) = path.write_chain(
+                    dpe,
+                    crypto,
+                    platform,
+                    &mut tbs_buffer[..MAX_CERT_SIZE],
+                    chain_buffer,
+                )?;
+                (size, Some(issuer))
+            }
+            None if self.format == Self::FORMAT_X509 => (0, root_issuer(dpe, crypto)?),
+            None => (0, None),
+        };
 
         let mut authority_key_identifier = [0u8; MAX_KEY_IDENTIFIER_SIZE];
-        env.platform
-            .get_issuer_key_identifier(&mut authority_key_identifier)?;
+        match &layer_issuer {
+            Some(issuer) => authority_key_identifier = issuer.key_identifier,
+            None => platform.get_issuer_key_identifier(&mut authority_key_identifier)?,
+        }
+
+        let mut ueid = [0u8; MAX_UEID_SIZE];
+        let ueid_len = platform.get_ueid(&mut ueid)?;
+
+        let mut additional_fwids = [Fwid::default(); MAX_ADDITIONAL_FWIDS];
+        let additional_fwids_len = get_additional_fwids(platform, &mut additional_fwids)?;
 
         let measurements = MeasurementData {
-            label: &self.label,
-            tci_nodes: &nodes[..tcb_count],
+            ueid: ueid.get(..ueid_len).ok_or(DpeErrorCode::InternalError)?,
+            tci_nodes: nodes.get(..tcb_count).ok_or(DpeErrorCode::InternalError)?,
             is_ca: self.uses_is_ca(),
             supports_recursive: dpe.support.recursive(),
             subject_key_identifier,
             authority_key_identifier,
+            command_counter: dpe
+                .support
+                .command_counter()
+                .then_some(dpe.contexts[idx].command_counter),
+            label: Some(&self.label),
+            additional_fwids: additional_fwids
+                .get(..additional_fwids_len)
+                .ok_or(DpeErrorCode::InternalError)?,
         };
 
         let mut issuer_name = [0u8; MAX_ISSUER_NAME_SIZE];
-        let issuer_len = env.platform.get_issuer_name(&mut issuer_name)?;
+        let issuer_len = match &layer_issuer {
+            Some(issuer) => issuer.encode_name(&mut issuer_name)?,
+            None => platform.get_issuer_name(&mut issuer_name)?,
+        };
 
-        let mut cert = [0u8; MAX_CERT_SIZE];
         let cert_size = match self.format {
             Self::FORMAT_X509 => {
                 #[cfg(not(feature = "no-cfi"))]
                 cfi_assert_eq(self.format, Self::FORMAT_X509);
-                let mut tbs_buffer = [0u8; MAX_CERT_SIZE];
-                let mut tbs_writer = CertWriter::new(&mut tbs_buffer, true);
+                let mut tbs_writer = CertWriter::new(&mut tbs_buffer[..], true);
                 if issuer_len > MAX_ISSUER_NAME_SIZE {
                     return Err(DpeErrorCode::InternalError);
                 }
-                let cert_validity = env.platform.get_cert_validity()?;
-                let mut bytes_written = tbs_writer.encode_ecdsa_tbs(
-                    /*serial=*/
-                    &subject_name.serial.bytes()[..20], // Serial number must be truncated to 20 bytes
+                let cert_validity = platform.get_cert_validity()?;
+                let serial_number = if self.uses_random_serial() {
+                    let mut serial_number = [0u8; SERIAL_NUMBER_SIZE];
+                    crypto.rand_bytes(&mut serial_number)?;
+                    // Keep the INTEGER positive.
+                    serial_number[0] &= 0x7F;
+                    serial_number
+                } else {
+                    x509::serial_number_from_pubkey(crypto, subject_pub_key)?
+                };
+                let bytes_written = tbs_writer.encode_ecdsa_tbs(
+                    &serial_number,
                     &issuer_name[..issuer_len],
                     &subject_name,
-                    &pub_key,
+                    subject_pub_key,
                     &measurements,
                     &cert_validity,
                 )?;
-                if bytes_written > MAX_CERT_SIZE {
-                    return Err(DpeErrorCode::InternalError);
+                if bytes_written > MAX_CERTIFY_KEY_SIZE {
+                    return Err(DpeErrorCode::CertificateTooLarge);
                 }
 
-                let tbs_digest = env
-                    .crypto
-                    .hash(DPE_PROFILE.alg_len(), &tbs_buffer[..bytes_written])?;
-                let sig = env
-                    .crypto
-                    .ecdsa_sign_with_alias(DPE_PROFILE.alg_len(), &tbs_digest)?;
+                let tbs_digest =
+                    crypto.hash(DPE_PROFILE.alg_len(), &tbs_buffer[..bytes_written])?;
+                let sig = match &layer_issuer {
+                    Some(issuer) => issuer.sign(crypto, &tbs_digest)?,
+                    None => crypto.ecdsa_sign_with_alias(DPE_PROFILE.alg_len(), &tbs_digest)?,
+                };
 
-                let mut cert_writer = CertWriter::new(&mut cert, true);
-                bytes_written =
-                    cert_writer.encode_ecdsa_certificate(&tbs_buffer[..bytes_written], &sig)?;
-                u32::try_from(bytes_written).map_err(|_| DpeErrorCode::InternalError)?
+                let mut cert_writer = CertWriter::new(&mut cert_buffer[..], true);
+                cert_writer.encode_ecdsa_certificate(&tbs_buffer[..bytes_written], &sig)?
             }
             Self::FORMAT_CSR => {
                 #[cfg(not(feature = "no-cfi"))]
                 cfi_assert_eq(self.format, Self::FORMAT_CSR);
-                let mut cert_req_info_buffer = [0u8; MAX_CERT_SIZE];
-                let mut cert_req_info_writer = CertWriter::new(&mut cert_req_info_buffer, true);
+                let cert_req_info_buffer = tbs_buffer;
+                let mut cert_req_info_writer = CertWriter::new(&mut cert_req_info_buffer[..], true);
                 let mut bytes_written = cert_req_info_writer.encode_certification_request_info(
-                    &pub_key,
+                    subject_pub_key,
                     &subject_name,
                     &measurements,
                 )?;
-                if bytes_written > MAX_CERT_SIZE {
-                    return Err(DpeErrorCode::InternalError);
+                if bytes_written > MAX_CERTIFY_KEY_SIZE {
+                    return Err(DpeErrorCode::CertificateTooLarge);
                 }
 
-                let cert_req_info_digest = env.crypto.hash(
+                let cert_req_info_digest = crypto.hash(
                     DPE_PROFILE.alg_len(),
                     &cert_req_info_buffer[..bytes_written],
                 )?;
+                let csr_buffer = chain_buffer;
+                let mut csr_writer = CertWriter::new(&mut csr_buffer[..], true);
                 // The PKCS#10 CSR is self-signed so the private key signs it instead of the alias key.
-                let cert_req_info_sig = env.crypto.ecdsa_sign_with_derived(
-                    DPE_PROFILE.alg_len(),
-                    &cert_req_info_digest,
-                    &priv_key,
-                    &pub_key,
-                )?;
-
-                let mut csr_buffer = [0u8; MAX_CERT_SIZE];
-                let mut csr_writer = CertWriter::new(&mut csr_buffer, true);
-                bytes_written = csr_writer
-                    .encode_csr(&cert_req_info_buffer[..bytes_written], &cert_req_info_sig)?;
-                if bytes_written > MAX_CERT_SIZE {
-                    return Err(DpeErrorCode::InternalError);
+                bytes_written = match subject_pub_key {
+                    SubjectPubKey::Ecdsa(pub_key) => {
+                        let cert_req_info_sig = crypto.ecdsa_sign_with_derived(
+                            DPE_PROFILE.alg_len(),
+                            &cert_req_info_digest,
+                            priv_key,
+                            pub_key,
+                        )?;
+                        csr_writer.encode_csr(
+                            &cert_req_info_buffer[..bytes_written],
+                            &cert_req_info_sig,
+                        )?
+                    }
+                    #[cfg(feature = "rsa")]
+                    SubjectPubKey::Rsa(_) => {
+                        let padding = self.rsa_padding();
+                        let cert_req_info_sig = crypto.rsa_sign_with_derived(
+                            DPE_PROFILE.alg_len(),
+                            &cert_req_info_digest,
+                            cdi,
+                            &self.label,
+                            RSA_KEY_INFO,
+                            prepared.rsa_key_size.ok_or(DpeErrorCode::InternalError)?,
+                            padding,
+                        )?;
+                        csr_writer.encode_rsa_csr(
+                            &cert_req_info_buffer[..bytes_written],
+                            &cert_req_info_sig,
+                            padding,
+                        )?
+                    }
+                    // Ed25519 signs the CertificationRequestInfo itself
+                    // rather than its digest.
+                    #[cfg(feature = "dpe_profile_ed25519_sha512")]
+                    SubjectPubKey::Ed25519(pub_key) => {
+                        let cert_req_info_sig = crypto.ed25519_sign_with_derived(
+                            &cert_req_info_buffer[..bytes_written],
+                            priv_key,
+                            pub_key,
+                        )?;
+                        csr_writer.encode_ed25519_csr(
+                            &cert_req_info_buffer[..bytes_written],
+                            &cert_req_info_sig,
+                        )?
+                    }
+                };
+                if bytes_written > MAX_CERTIFY_KEY_SIZE {
+                    return Err(DpeErrorCode::CertificateTooLarge);
                 }
 
-                let csr_digest = env
-                    .crypto
-                    .hash(DPE_PROFILE.alg_len(), &csr_buffer[..bytes_written])?;
-                let csr_sig = env
-                    .crypto
-                    .ecdsa_sign_with_alias(DPE_PROFILE.alg_len(), &csr_digest)?;
-                let sid = env.platform.get_signer_identifier()?;
+                let csr_digest =
+                    crypto.hash(DPE_PROFILE.alg_len(), &csr_buffer[..bytes_written])?;
+                let csr_sig = crypto.ecdsa_sign_with_alias(DPE_PROFILE.alg_len(), &csr_digest)?;
+                let sid = platform.get_signer_identifier()?;
 
-                let mut cms_writer = CertWriter::new(&mut cert, true);
-                bytes_written =
-                    cms_writer.encode_cms(&csr_buffer[..bytes_written], &csr_sig, &sid)?;
-                u32::try_from(bytes_written).map_err(|_| DpeErrorCode::InternalError)?
+                let mut cms_writer = CertWriter::new(&mut cert_buffer[..], true);
+                cms_writer.encode_cms(&csr_buffer[..bytes_written], &csr_sig, &sid)?
+            }
+            Self::FORMAT_CWT => {
+                #[cfg(not(feature = "no-cfi"))]
+                cfi_assert_eq(self.format, Self::FORMAT_CWT);
+                let claims_buffer = tbs_buffer;
+                let mut claims_writer = CwtWriter::new(&mut claims_buffer[..]);
+                let pub_key = match subject_pub_key {
+                    SubjectPubKey::Ecdsa(pub_key) => pub_key,
+                    // Other keys are rejected before getting here.
+                    #[cfg(any(feature = "rsa", feature = "dpe_profile_ed25519_sha512"))]
+                    _ => return Err(DpeErrorCode::InternalError),
+                };
+                let claims_size = claims_writer.encode_claims(pub_key, &measurements)?;
+                if claims_size > MAX_CERTIFY_KEY_SIZE {
+                    return Err(DpeErrorCode::CertificateTooLarge);
+                }
+                let claims = &claims_buffer[..claims_size];
+
+                // The alias key signs the COSE Sig_structure, which embeds the claims.
+                let mut prefix = [0u8; CwtWriter::MAX_SIG_STRUCTURE_PREFIX_SIZE];
+                let mut prefix_writer = CwtWriter::new(&mut prefix);
+                let prefix_size = prefix_writer.encode_sig_structure_prefix(claims_size)?;
+                let mut hasher = crypto.hash_initialize(DPE_PROFILE.alg_len())?;
+                hasher.update(&prefix[..prefix_size])?;
+                hasher.update(claims)?;
+                let sig_structure_digest = hasher.finish()?;
+                let sig =
+                    crypto.ecdsa_sign_with_alias(DPE_PROFILE.alg_len(), &sig_structure_digest)?;
+
+                let mut cwt_writer = CwtWriter::new(&mut cert_buffer[..]);
+                cwt_writer.encode_cose_sign1(claims, &sig)?
+            }
+            Self::FORMAT_COSE_KEY => {
+                #[cfg(not(feature = "no-cfi"))]
+                cfi_assert_eq(self.format, Self::FORMAT_COSE_KEY);
+                let pub_key = match subject_pub_key {
+                    SubjectPubKey::Ecdsa(pub_key) => pub_key,
+                    // Other keys are rejected before getting here.
+                    #[cfg(any(feature = "rsa", feature = "dpe_profile_ed25519_sha512"))]
+                    _ => return Err(DpeErrorCode::InternalError),
+                };
+                let mut cwt_writer = CwtWriter::new(&mut cert_buffer[..]);
+                cwt_writer.encode_ec2_cose_key(pub_key)?
             }
             _ => return Err(DpeErrorCode::InvalidArgument),
         };
 
-        let derived_pubkey_x: [u8; DPE_PROFILE.get_ecc_int_size()] =
-            pub_key
-                .x
-                .bytes()
-                .try_into()
-                .map_err(|_| DpeErrorCode::InternalError)?;
-        let derived_pubkey_y: [u8; DPE_PROFILE.get_ecc_int_size()] =
-            pub_key
-                .y
-                .bytes()
-                .try_into()
-                .map_err(|_| DpeErrorCode::InternalError)?;
-
-        // Rotate handle if it isn't the default
-        dpe.roll_onetime_use_handle(env, idx)?;
-
-        Ok(Response::CertifyKey(CertifyKeyResp {
-            new_context_handle: dpe.contexts[idx].handle,
-            derived_pubkey_x,
-            derived_pubkey_y,
-            cert_size,
-            cert,
-            resp_hdr: ResponseHdr::new(DpeErrorCode::NoError),
-        }))
+        Ok((cert_size, chain_size))
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::{
-        commands::{Command, CommandHdr, DeriveContextCmd, DeriveContextFlags, InitCtxCmd},
-        dpe_instance::tests::{TestTypes, SIMULATION_HANDLE, TEST_LOCALITIES},
-        support::Support,
-        x509::tests::TcbInfo,
-        DpeProfile,
-    };
-    use caliptra_cfi_lib_git::CfiCounter;
-    use cms::{
-        content_info::{CmsVersion, ContentInfo},
-        signed_data::{SignedData, SignerIdentifier},
-    };
-    use crypto::{AlgLen, CryptoBuf, EcdsaPub, OpensslCrypto};
-    use der::{Decode, Encode};
-    use openssl::{
-        bn::BigNum,
-        ec::{EcGroup, EcKey},
-        ecdsa::EcdsaSig,
-        hash::{Hasher, MessageDigest},
+#[repr(C)]
+#[derive(Debug, PartialEq, Eq, zerocopy::FromBytes, zerocopy::AsBytes)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct GetCertificateChunkCmd {
+    pub offset: u32,
+    pub size: u32,
+}
+
+impl_wire_endian!(GetCertificateChunkCmd { offset, size });
+
+impl CommandExecution for GetCertificateChunkCmd {
+    #[cfg_attr(not(feature = "no-cfi"), cfi_impl_fn)]
+    fn execute(
+        &self,
+        dpe: &mut DpeInstance,
+        _env: &mut DpeEnv<impl DpeTypes>,
+        locality: u32,
+    ) -> Result<Response, DpeErrorCode> {
+        dpe_trace!(
+            "GetCertificateChunk offset={} size={} locality={:#x}",
+            self.offset,
+            self.size,
+            locality
+        );
+
+        let pending_cert = &dpe.pending_cert;
+        if pending_cert.size == 0 {
+            return Err(DpeErrorCode::InvalidArgument);
+        }
+
+        // Only the locality that requested the certificate may read it.
+        if pending_cert.locality != locality {
+            return Err(DpeErrorCode::InvalidLocality);
+        } else {
+            #[cfg(not(feature = "no-cfi"))]
+            cfi_assert_eq(pending_cert.locality, locality);
+        }
+
+        if self.size > MAX_CHUNK_SIZE as u32 || self.offset >= pending_cert.size {
+            return Err(DpeErrorCode::InvalidArgument);
+        } else {
+            cfg_if! {
+                if #[cfg(not(feature = "no-cfi"))] {
+                    cfi_assert!(self.size <= MAX_CHUNK_SIZE as u32);
+                    cfi_assert!(self.offset < pending_cert.size);
+                }
+            }
+        }
+
+        let mut certificate_chain = [0u8; MAX_CERT_SIZE];
+        let mut out = ResponseWriter::with_offset(
+            certificate_chain
+                .get_mut(..self.size as usize)
+                .ok_or(DpeErrorCode::InternalError)?,
+            self.offset as usize,
+        );
+        let len = out.write_chunk(
+            pending_cert
+                .cert
+                .get(..pending_cert.size as usize)
+                .ok_or(DpeErrorCode::InternalError)?,
+        )? as u32;
+
+        Ok(Response::GetCertificateChunk(GetCertificateChainResp {
+            certificate_chain,
+            certificate_size: len,
+            resp_hdr: ResponseHdr::new(DpeErrorCode::NoError),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        commands::{
+            Command, CommandHdr, DeriveContextCmd, DeriveContextFlags, ExtendTciCmd,
+            GetCertificateChainCmd, InitCtxCmd,
+        },
+        dpe_instance::tests::{TestTypes, SIMULATION_HANDLE, TEST_LOCALITIES},
+        support::Support,
+        x509::tests::{TcbFreshness, TcbInfo, Ueid},
+        DpeProfile,
+    };
+    use caliptra_cfi_lib_git::CfiCounter;
+    use ciborium::value::Value;
+    use cms::{
+        content_info::{CmsVersion, ContentInfo},
+        signed_data::{SignedData, SignerIdentifier},
+    };
+    use core::cmp::min;
+    use crypto::{AlgLen, OpensslCrypto};
+    use der::{Decode, Encode};
+    use openssl::{
+        bn::BigNum,
+        ec::{EcGroup, EcKey},
+        ecdsa::EcdsaSig,
+        hash::{Hasher, MessageDigest},
         nid::*,
+        pkey::PKey,
+        x509::X509,
     };
-    use platform::default::DefaultPlatform;
+    use platform::default::{DefaultPlatform, TEST_CERT_CHAIN};
     use spki::ObjectIdentifier;
-    use std::str;
     use x509_parser::nom::Parser;
     use x509_parser::oid_registry::asn1_rs::oid;
     use x509_parser::prelude::public_key::PublicKey;
@@ -328,6 +1529,7 @@ mod tests {
         let mut env = DpeEnv::<TestTypes> {
             crypto: OpensslCrypto::new(),
             platform: DefaultPlatform,
+            arena: &mut [],
         };
         let mut dpe = DpeInstance::new(&mut env, Support::X509).unwrap();
 
@@ -358,6 +1560,14 @@ mod tests {
         match parser.parse(&certify_resp.cert[..certify_resp.cert_size.try_into().unwrap()]) {
             Ok((_, cert)) => {
                 assert_eq!(cert.version(), X509Version::V3);
+
+                // The UEID comes from the platform.
+                let ueid = cert
+                    .get_extension_unique(&oid!(2.23.133 .5 .4 .4))
+                    .unwrap()
+                    .unwrap();
+                let parsed_ueid = asn1::parse_single::<Ueid>(ueid.value).unwrap();
+                assert_eq!(parsed_ueid.ueid, platform::default::UEID);
             }
             Err(e) => panic!("x509 parsing failed: {:?}", e),
         };
@@ -369,6 +1579,7 @@ mod tests {
         let mut env = DpeEnv::<TestTypes> {
             crypto: OpensslCrypto::new(),
             platform: DefaultPlatform,
+            arena: &mut [],
         };
         let mut dpe = DpeInstance::new(&mut env, Support::X509 | Support::IS_CA).unwrap();
 
@@ -394,6 +1605,11 @@ mod tests {
             _ => panic!("Wrong response type."),
         };
 
+        let mut expected_aki = [0u8; MAX_KEY_IDENTIFIER_SIZE];
+        env.platform
+            .get_issuer_key_identifier(&mut expected_aki)
+            .unwrap();
+
         let mut parser = X509CertificateParser::new().with_deep_parse_extensions(true);
         match parser.parse(&certify_resp_ca.cert[..certify_resp_ca.cert_size.try_into().unwrap()]) {
             Ok((_, cert)) => {
@@ -408,7 +1624,12 @@ mod tests {
                 let pub_key = &cert.tbs_certificate.subject_pki.subject_public_key.data;
                 let mut hasher = match DPE_PROFILE {
                     DpeProfile::P256Sha256 => Hasher::new(MessageDigest::sha256()).unwrap(),
-                    DpeProfile::P384Sha384 => Hasher::new(MessageDigest::sha384()).unwrap(),
+                    DpeProfile::P384Sha384 | DpeProfile::Mldsa87Sha384 => {
+                        Hasher::new(MessageDigest::sha384()).unwrap()
+                    }
+                    DpeProfile::P521Sha512 | DpeProfile::Ed25519Sha512 => {
+                        Hasher::new(MessageDigest::sha512()).unwrap()
+                    }
                 };
                 hasher.update(pub_key).unwrap();
                 let expected_ski: &[u8] = &hasher.finish().unwrap();
@@ -426,18 +1647,13 @@ mod tests {
                     Err(_) => panic!("multiple subject key identifier extensions found"),
                 }
 
-                let mut expected_aki = [0u8; MAX_KEY_IDENTIFIER_SIZE];
-                env.platform
-                    .get_issuer_key_identifier(&mut expected_aki)
-                    .unwrap();
                 match cert.get_extension_unique(&oid!(2.5.29 .35)) {
                     Ok(Some(extension)) => {
                         if let ParsedExtension::AuthorityKeyIdentifier(aki) =
                             extension.parsed_extension()
                         {
                             let key_identifier = aki.key_identifier.clone().unwrap();
-                            // skip first two bytes - first byte is 0x04 der encoding byte and second byte is size byte
-                            assert_eq!(&key_identifier.0[2..], &expected_aki,);
+                            assert_eq!(key_identifier.0, &expected_aki);
                         } else {
                             panic!("Extension has wrong type");
                         }
@@ -467,13 +1683,36 @@ mod tests {
         match parser
             .parse(&certify_resp_non_ca.cert[..certify_resp_non_ca.cert_size.try_into().unwrap()])
         {
-            Ok((_, cert)) => match cert.basic_constraints() {
-                Ok(Some(basic_constraints)) => {
-                    assert!(!basic_constraints.value.ca);
+            Ok((_, cert)) => {
+                match cert.basic_constraints() {
+                    Ok(Some(basic_constraints)) => {
+                        assert!(!basic_constraints.value.ca);
+                    }
+                    Ok(None) => panic!("basic constraints extension not found"),
+                    Err(_) => panic!("multiple basic constraints extensions found"),
+                }
+
+                // Leaf certificates carry key identifiers too so that they
+                // can be chained to their issuer.
+                assert!(matches!(
+                    cert.get_extension_unique(&oid!(2.5.29 .14)),
+                    Ok(Some(_))
+                ));
+                match cert.get_extension_unique(&oid!(2.5.29 .35)) {
+                    Ok(Some(extension)) => {
+                        if let ParsedExtension::AuthorityKeyIdentifier(aki) =
+                            extension.parsed_extension()
+                        {
+                            let key_identifier = aki.key_identifier.clone().unwrap();
+                            assert_eq!(key_identifier.0, &expected_aki);
+                        } else {
+                            panic!("Extension has wrong type");
+                        }
+                    }
+                    Ok(None) => panic!("authority key identifier extension not found"),
+                    Err(_) => panic!("multiple authority key identifier extensions found"),
                 }
-                Ok(None) => panic!("basic constraints extension not found"),
-                Err(_) => panic!("multiple basic constraints extensions found"),
-            },
+            }
             Err(e) => panic!("x509 parsing failed: {:?}", e),
         };
     }
@@ -484,6 +1723,7 @@ mod tests {
         let mut env = DpeEnv::<TestTypes> {
             crypto: OpensslCrypto::new(),
             platform: DefaultPlatform,
+            arena: &mut [],
         };
         let mut dpe = DpeInstance::new(&mut env, Support::CSR).unwrap();
 
@@ -530,6 +1770,7 @@ mod tests {
         let hash_alg_oid = match DPE_PROFILE.alg_len() {
             AlgLen::Bit256 => "2.16.840.1.101.3.4.2.1",
             AlgLen::Bit384 => "2.16.840.1.101.3.4.2.2",
+            AlgLen::Bit512 => "2.16.840.1.101.3.4.2.3",
         };
         assert!(digest_alg
             .assert_algorithm_oid(ObjectIdentifier::new_unwrap(hash_alg_oid))
@@ -558,6 +1799,7 @@ mod tests {
         let sig_alg_oid = match DPE_PROFILE.alg_len() {
             AlgLen::Bit256 => "1.2.840.10045.4.3.2",
             AlgLen::Bit384 => "1.2.840.10045.4.3.3",
+            AlgLen::Bit512 => "1.2.840.10045.4.3.4",
         };
         assert!(signer_info
             .signature_algorithm
@@ -599,11 +1841,15 @@ mod tests {
             AlgLen::Bit384 => EcKey::private_key_from_der(include_bytes!(
                 "../../../platform/src/test_data/key_384.der"
             )),
+            AlgLen::Bit512 => EcKey::private_key_from_der(include_bytes!(
+                "../../../platform/src/test_data/key_521.der"
+            )),
         }
         .unwrap();
         let curve = match DPE_PROFILE.alg_len() {
             AlgLen::Bit256 => Nid::X9_62_PRIME256V1,
             AlgLen::Bit384 => Nid::SECP384R1,
+            AlgLen::Bit512 => Nid::SECP521R1,
         };
         let group = &EcGroup::from_curve_name(curve).unwrap();
         let alias_key = EcKey::from_public_key(group, priv_key.public_key()).unwrap();
@@ -636,23 +1882,19 @@ mod tests {
         assert!(cri_sig.verify(cri_digest.bytes(), &pub_key).unwrap());
 
         // validate subject_name
-        let mut subj_serial = [0u8; DPE_PROFILE.get_hash_size() * 2];
-        let pub_key = EcdsaPub {
-            x: CryptoBuf::new(&certify_resp.derived_pubkey_x).unwrap(),
-            y: CryptoBuf::new(&certify_resp.derived_pubkey_y).unwrap(),
-        };
-        env.crypto
-            .get_pubkey_serial(DPE_PROFILE.alg_len(), &pub_key, &mut subj_serial)
-            .unwrap();
-        let truncated_subj_serial = &subj_serial[..64];
-        let subject_name = Name {
-            cn: DirectoryString::PrintableString(b"DPE Leaf"),
-            serial: DirectoryString::PrintableString(truncated_subj_serial),
-        };
+        let tci = &dpe.contexts[0].tci;
+        let tci_hex: String = tci
+            .tci_cumulative
+            .0
+            .iter()
+            .map(|b| format!("{b:02X}"))
+            .collect();
         let expected_subject_name = format!(
-            "CN={}, serialNumber={}",
-            str::from_utf8(subject_name.cn.bytes()).unwrap(),
-            str::from_utf8(&subject_name.serial.bytes()).unwrap()
+            "CN=DPE Leaf {:08X}, serialNumber={}, O={:08X}, OU={:08X}",
+            tci.tci_type,
+            &tci_hex[..LEAF_SERIAL_SIZE],
+            env.platform.get_vendor_id().unwrap(),
+            env.platform.get_vendor_sku().unwrap(),
         );
         let actual_subject_name = cri.subject.to_string_with_registry(oid_registry()).unwrap();
         assert_eq!(expected_subject_name, actual_subject_name);
@@ -695,12 +1937,297 @@ mod tests {
         }
     }
 
+    #[cfg(feature = "rsa")]
+    #[test]
+    fn test_certify_key_rsa() {
+        use openssl::x509::X509Req;
+
+        CfiCounter::reset_for_test();
+        let mut env = DpeEnv::<TestTypes> {
+            crypto: OpensslCrypto::new(),
+            platform: DefaultPlatform,
+            arena: &mut [],
+        };
+        let mut dpe =
+            DpeInstance::new(&mut env, Support::X509 | Support::CSR | Support::CWT).unwrap();
+        let handle = match InitCtxCmd::new_use_default()
+            .execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
+            .unwrap()
+        {
+            Response::InitCtx(resp) => resp.handle,
+            _ => panic!("Incorrect return type."),
+        };
+        let certify_cmd = |flags, format| CertifyKeyCmd {
+            handle,
+            flags,
+            label: [0; DPE_PROFILE.get_hash_size()],
+            format,
+        };
+
+        for (flags, format, err) in [
+            (
+                CertifyKeyFlags::RSA_3072 | CertifyKeyFlags::RSA_4096,
+                CertifyKeyCmd::FORMAT_X509,
+                DpeErrorCode::InvalidArgument,
+            ),
+            (
+                CertifyKeyFlags::RSA_PSS,
+                CertifyKeyCmd::FORMAT_CSR,
+                DpeErrorCode::InvalidArgument,
+            ),
+            // PSS only applies to the self-signed CSR.
+            (
+                CertifyKeyFlags::RSA_3072 | CertifyKeyFlags::RSA_PSS,
+                CertifyKeyCmd::FORMAT_X509,
+                DpeErrorCode::InvalidArgument,
+            ),
+            (
+                CertifyKeyFlags::RSA_3072,
+                CertifyKeyCmd::FORMAT_CWT,
+                DpeErrorCode::ArgumentNotSupported,
+            ),
+            (
+                CertifyKeyFlags::RSA_3072,
+                CertifyKeyCmd::FORMAT_COSE_KEY,
+                DpeErrorCode::ArgumentNotSupported,
+            ),
+        ] {
+            assert_eq!(
+                Err(err),
+                certify_cmd(flags, format).execute(&mut dpe, &mut env, TEST_LOCALITIES[0]),
+                "{flags:?}"
+            );
+        }
+
+        // The X.509 certificate holds the RSA key. It is not returned in the
+        // response.
+        let resp = match certify_cmd(CertifyKeyFlags::RSA_3072, CertifyKeyCmd::FORMAT_X509)
+            .execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
+            .unwrap()
+        {
+            Response::CertifyKey(resp) => resp,
+            _ => panic!("Wrong response type."),
+        };
+        assert_eq!(resp.derived_pubkey_x, [0; DPE_PROFILE.get_ecc_int_size()]);
+        assert_eq!(resp.derived_pubkey_y, [0; DPE_PROFILE.get_ecc_int_size()]);
+        let cert = X509::from_der(dpe.pending_cert.get()).unwrap();
+        let rsa = cert.public_key().unwrap().rsa().unwrap();
+        assert_eq!(rsa.n().num_bits(), 3072);
+        assert_eq!(rsa.e(), &*BigNum::from_u32(65537).unwrap());
+
+        // The subject key identifier is the hash of the modulus.
+        let digest = env
+            .crypto
+            .hash(DPE_PROFILE.alg_len(), &rsa.n().to_vec())
+            .unwrap();
+        let (_, parsed) = X509Certificate::from_der(dpe.pending_cert.get()).unwrap();
+        let Ok(Some(ski)) = parsed.get_extension_unique(&oid!(2.5.29 .14)) else {
+            panic!("subject key identifier extension not found");
+        };
+        let ParsedExtension::SubjectKeyIdentifier(ski) = ski.parsed_extension() else {
+            panic!("Extension has wrong type");
+        };
+        assert_eq!(ski.0, &digest.bytes()[..MAX_KEY_IDENTIFIER_SIZE]);
+
+        // The CSR is self-signed with the RSA key, in either scheme.
+        for (flags, bits) in [
+            (CertifyKeyFlags::RSA_3072, 3072),
+            (CertifyKeyFlags::RSA_4096 | CertifyKeyFlags::RSA_PSS, 4096),
+        ] {
+            assert!(matches!(
+                certify_cmd(flags, CertifyKeyCmd::FORMAT_CSR).execute(
+                    &mut dpe,
+                    &mut env,
+                    TEST_LOCALITIES[0]
+                ),
+                Ok(Response::CertifyKey(_))
+            ));
+            let content_info = ContentInfo::from_der(dpe.pending_cert.get()).unwrap();
+            let mut signed_data =
+                SignedData::from_der(&content_info.content.to_der().unwrap()).unwrap();
+            // skip first 4 explicit encoding bytes
+            let econtent = &signed_data
+                .encap_content_info
+                .econtent
+                .as_mut()
+                .unwrap()
+                .to_der()
+                .unwrap()[4..];
+
+            let csr = X509Req::from_der(econtent).unwrap();
+            let pub_key = csr.public_key().unwrap();
+            assert_eq!(pub_key.rsa().unwrap().n().num_bits(), bits);
+            assert!(csr.verify(&pub_key).unwrap(), "{flags:?}");
+        }
+    }
+
+    #[cfg(feature = "dpe_profile_ed25519_sha512")]
+    #[test]
+    fn test_certify_key_ed25519() {
+        use openssl::{pkey::Id, x509::X509Req};
+
+        CfiCounter::reset_for_test();
+        let mut env = DpeEnv::<TestTypes> {
+            crypto: OpensslCrypto::new(),
+            platform: DefaultPlatform,
+            arena: &mut [],
+        };
+        let mut dpe =
+            DpeInstance::new(&mut env, Support::X509 | Support::CSR | Support::CWT).unwrap();
+        let handle = match InitCtxCmd::new_use_default()
+            .execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
+            .unwrap()
+        {
+            Response::InitCtx(resp) => resp.handle,
+            _ => panic!("Incorrect return type."),
+        };
+        let certify_cmd = |format| CertifyKeyCmd {
+            handle,
+            flags: CertifyKeyFlags::empty(),
+            label: [0; DPE_PROFILE.get_hash_size()],
+            format,
+        };
+
+        assert_eq!(
+            Err(DpeErrorCode::ArgumentNotSupported),
+            certify_cmd(CertifyKeyCmd::FORMAT_CWT).execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
+        );
+        assert_eq!(
+            Err(DpeErrorCode::ArgumentNotSupported),
+            certify_cmd(CertifyKeyCmd::FORMAT_COSE_KEY).execute(
+                &mut dpe,
+                &mut env,
+                TEST_LOCALITIES[0]
+            )
+        );
+
+        // The certificate holds the Ed25519 key, and is signed by the P-521
+        // alias key.
+        let resp = match certify_cmd(CertifyKeyCmd::FORMAT_X509)
+            .execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
+            .unwrap()
+        {
+            Response::CertifyKey(resp) => resp,
+            _ => panic!("Wrong response type."),
+        };
+        let cert = X509::from_der(dpe.pending_cert.get()).unwrap();
+        let pub_key = cert.public_key().unwrap();
+        assert_eq!(pub_key.id(), Id::ED25519);
+        assert_eq!(
+            resp.derived_pubkey_x[..32],
+            pub_key.raw_public_key().unwrap()
+        );
+        assert!(resp.derived_pubkey_x[32..].iter().all(|&b| b == 0));
+        assert_eq!(resp.derived_pubkey_y, [0; DPE_PROFILE.get_ecc_int_size()]);
+        let alias_key = EcKey::private_key_from_der(include_bytes!(
+            "../../../platform/src/test_data/key_521.der"
+        ))
+        .unwrap();
+        assert!(cert.verify(&PKey::from_ec_key(alias_key).unwrap()).unwrap());
+
+        // The CSR is self-signed with the Ed25519 key.
+        assert!(matches!(
+            certify_cmd(CertifyKeyCmd::FORMAT_CSR).execute(&mut dpe, &mut env, TEST_LOCALITIES[0]),
+            Ok(Response::CertifyKey(_))
+        ));
+        let content_info = ContentInfo::from_der(dpe.pending_cert.get()).unwrap();
+        let mut signed_data =
+            SignedData::from_der(&content_info.content.to_der().unwrap()).unwrap();
+        // skip first 4 explicit encoding bytes
+        let econtent = &signed_data
+            .encap_content_info
+            .econtent
+            .as_mut()
+            .unwrap()
+            .to_der()
+            .unwrap()[4..];
+
+        let csr = X509Req::from_der(econtent).unwrap();
+        let csr_key = csr.public_key().unwrap();
+        assert!(csr_key.public_eq(&pub_key));
+        assert!(csr.verify(&csr_key).unwrap());
+    }
+
+    #[test]
+    fn test_cert_cache() {
+        CfiCounter::reset_for_test();
+        let mut env = DpeEnv::<TestTypes> {
+            crypto: OpensslCrypto::new(),
+            platform: DefaultPlatform,
+            arena: &mut [],
+        };
+        let certify = |dpe: &mut DpeInstance, env: &mut DpeEnv<TestTypes>, label| {
+            let cmd = CertifyKeyCmd {
+                handle: ContextHandle::default(),
+                flags: CertifyKeyFlags::RANDOM_SERIAL,
+                label: [label; DPE_PROFILE.get_hash_size()],
+                format: CertifyKeyCmd::FORMAT_X509,
+            };
+            assert!(matches!(
+                cmd.execute(dpe, env, TEST_LOCALITIES[0]),
+                Ok(Response::CertifyKey(_))
+            ));
+            dpe.pending_cert.get().to_vec()
+        };
+
+        // Serial numbers are random, so without the cache every certificate
+        // is new.
+        let mut dpe = DpeInstance::new(
+            &mut env,
+            Support::X509 | Support::AUTO_INIT | Support::EXTEND_TCI,
+        )
+        .unwrap();
+        let first = certify(&mut dpe, &mut env, 0);
+        assert_ne!(first, certify(&mut dpe, &mut env, 0));
+
+        let mut dpe = DpeInstance::new(
+            &mut env,
+            Support::X509 | Support::AUTO_INIT | Support::EXTEND_TCI | Support::CERT_CACHE,
+        )
+        .unwrap();
+        let first = certify(&mut dpe, &mut env, 0);
+        assert_eq!(first, certify(&mut dpe, &mut env, 0));
+
+        // Another label is another key.
+        let other_label = certify(&mut dpe, &mut env, 1);
+        assert_ne!(first, other_label);
+        assert_eq!(other_label, certify(&mut dpe, &mut env, 1));
+
+        // Only the last certificate is kept, so the first label signs again.
+        assert_ne!(first, certify(&mut dpe, &mut env, 0));
+
+        // Extending the TCI stops the last certificate from being reused.
+        ExtendTciCmd {
+            handle: ContextHandle::default(),
+            data: [0xAA; DPE_PROFILE.get_hash_size()],
+        }
+        .execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
+        .unwrap();
+        assert!(!dpe.pending_cert.reusable.get());
+        let extended = certify(&mut dpe, &mut env, 1);
+        assert_ne!(other_label, extended);
+        assert_eq!(extended, certify(&mut dpe, &mut env, 1));
+
+        // So does deriving a child.
+        DeriveContextCmd {
+            handle: ContextHandle::default(),
+            data: [0; DPE_PROFILE.get_hash_size()],
+            flags: DeriveContextFlags::MAKE_DEFAULT | DeriveContextFlags::INPUT_ALLOW_X509,
+            tci_type: 0,
+            target_locality: TEST_LOCALITIES[0],
+        }
+        .execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
+        .unwrap();
+        assert!(!dpe.pending_cert.reusable.get());
+    }
+
     #[test]
     fn test_certify_key_order() {
         CfiCounter::reset_for_test();
         let mut env = DpeEnv::<TestTypes> {
             crypto: OpensslCrypto::new(),
             platform: DefaultPlatform,
+            arena: &mut [],
         };
         let mut dpe = DpeInstance::new(&mut env, Support::X509 | Support::AUTO_INIT).unwrap();
 
@@ -751,4 +2278,1040 @@ mod tests {
         assert_eq!(second.tci_type.unwrap(), &[0, 0, 0, 1]);
         assert!(parsed_tcb_infos.next().is_none());
     }
+
+    #[test]
+    fn test_certify_key_multi_tcb_info_fwids() {
+        CfiCounter::reset_for_test();
+        let mut env = DpeEnv::<TestTypes> {
+            crypto: OpensslCrypto::new(),
+            platform: DefaultPlatform,
+            arena: &mut [],
+        };
+        let mut dpe = DpeInstance::new(
+            &mut env,
+            Support::X509 | Support::AUTO_INIT | Support::RECURSIVE,
+        )
+        .unwrap();
+
+        DeriveContextCmd {
+            handle: ContextHandle::default(),
+            data: [1; DPE_PROFILE.get_tci_size()],
+            flags: DeriveContextFlags::MAKE_DEFAULT | DeriveContextFlags::INPUT_ALLOW_X509,
+            tci_type: 1,
+            target_locality: 0,
+        }
+        .execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
+        .unwrap();
+
+        let certify_resp = match (CertifyKeyCmd {
+            handle: ContextHandle::default(),
+            flags: CertifyKeyFlags(0),
+            label: [0; DPE_PROFILE.get_hash_size()],
+            format: CertifyKeyCmd::FORMAT_X509,
+        })
+        .execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
+        .unwrap()
+        {
+            Response::CertifyKey(resp) => resp,
+            _ => panic!("Wrong response type."),
+        };
+
+        let mut parser = X509CertificateParser::new().with_deep_parse_extensions(true);
+        let (_, cert) = parser
+            .parse(&certify_resp.cert[..certify_resp.cert_size.try_into().unwrap()])
+            .unwrap();
+        let multi_tcb_info = cert
+            .get_extension_unique(&oid!(2.23.133 .5 .4 .5))
+            .unwrap()
+            .unwrap();
+        let parsed_tcb_infos: Vec<TcbInfo> =
+            asn1::parse_single::<asn1::SequenceOf<TcbInfo>>(multi_tcb_info.value)
+                .unwrap()
+                .collect();
+
+        // The TcbInfos are ordered from the root of the chain to the leaf.
+        let leaf_idx = dpe
+            .get_active_context_pos(&ContextHandle::default(), TEST_LOCALITIES[0])
+            .unwrap();
+        let root_idx = dpe.contexts[leaf_idx].parent_idx as usize;
+        let expected = [&dpe.contexts[root_idx].tci, &dpe.contexts[leaf_idx].tci];
+
+        assert_eq!(parsed_tcb_infos.len(), expected.len());
+        for (tcb_info, node) in parsed_tcb_infos.into_iter().zip(expected) {
+            let mut fwids = tcb_info.fwids.unwrap();
+            assert_eq!(fwids.next().unwrap().digest, node.tci_current.0);
+            assert_eq!(fwids.next().unwrap().digest, node.tci_cumulative.0);
+            assert!(fwids.next().is_none());
+        }
+    }
+
+    #[test]
+    fn test_certify_key_cwt() {
+        CfiCounter::reset_for_test();
+        let mut env = DpeEnv::<TestTypes> {
+            crypto: OpensslCrypto::new(),
+            platform: DefaultPlatform,
+            arena: &mut [],
+        };
+        let certify_cmd = CertifyKeyCmd {
+            handle: ContextHandle::default(),
+            flags: CertifyKeyFlags::empty(),
+            label: [0; DPE_PROFILE.get_hash_size()],
+            format: CertifyKeyCmd::FORMAT_CWT,
+        };
+
+        // The format must be supported.
+        let mut dpe = DpeInstance::new(&mut env, Support::X509 | Support::AUTO_INIT).unwrap();
+        assert_eq!(
+            certify_cmd.execute(&mut dpe, &mut env, TEST_LOCALITIES[0]),
+            Err(DpeErrorCode::ArgumentNotSupported)
+        );
+
+        let mut dpe = DpeInstance::new(&mut env, Support::CWT | Support::AUTO_INIT).unwrap();
+        let Ok(Response::CertifyKey(certify_resp)) =
+            certify_cmd.execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
+        else {
+            panic!("CertifyKey failed");
+        };
+        let cwt = &certify_resp.cert[..certify_resp.cert_size as usize];
+
+        let cwt: Value = ciborium::de::from_reader(cwt).unwrap();
+        let Value::Tag(18, sign1) = cwt else {
+            panic!("CWT is not a tagged COSE_Sign1");
+        };
+        let sign1 = sign1.as_array().unwrap();
+        let protected = sign1[0].as_bytes().unwrap();
+        let payload = sign1[2].as_bytes().unwrap();
+        let signature = sign1[3].as_bytes().unwrap();
+
+        // The subject public key claim holds the derived key.
+        let claims: Value = ciborium::de::from_reader(&payload[..]).unwrap();
+        let claim = |map: &Value, key: i64| {
+            map.as_map()
+                .unwrap()
+                .iter()
+                .find(|(k, _)| *k == Value::Integer(key.into()))
+                .map(|(_, v)| v.clone())
+                .unwrap()
+        };
+        let cose_key = claim(&claims, -4670552);
+        let cose_key: Value = ciborium::de::from_reader(&cose_key.as_bytes().unwrap()[..]).unwrap();
+        assert_eq!(
+            claim(&cose_key, -2).as_bytes().unwrap(),
+            &certify_resp.derived_pubkey_x
+        );
+        assert_eq!(
+            claim(&cose_key, -3).as_bytes().unwrap(),
+            &certify_resp.derived_pubkey_y
+        );
+
+        // The auto-initialized context is the only node in the TCB.
+        let tci_nodes = claim(&claims, CwtWriter::TCI_NODES_CLAIM);
+        assert_eq!(tci_nodes.as_array().unwrap().len(), 1);
+
+        // The alias key signed the Sig_structure.
+        let sig_structure = Value::Array(vec![
+            Value::Text("Signature1".into()),
+            Value::Bytes(protected.clone()),
+            Value::Bytes(vec![]),
+            Value::Bytes(payload.clone()),
+        ]);
+        let mut tbs = vec![];
+        ciborium::ser::into_writer(&sig_structure, &mut tbs).unwrap();
+        let digest = env.crypto.hash(DPE_PROFILE.alg_len(), &tbs).unwrap();
+
+        let priv_key = match DPE_PROFILE.alg_len() {
+            AlgLen::Bit256 => EcKey::private_key_from_der(include_bytes!(
+                "../../../platform/src/test_data/key_256.der"
+            )),
+            AlgLen::Bit384 => EcKey::private_key_from_der(include_bytes!(
+                "../../../platform/src/test_data/key_384.der"
+            )),
+            AlgLen::Bit512 => EcKey::private_key_from_der(include_bytes!(
+                "../../../platform/src/test_data/key_521.der"
+            )),
+        }
+        .unwrap();
+        let alias_key = EcKey::from_public_key(priv_key.group(), priv_key.public_key()).unwrap();
+        let (r, s) = signature.split_at(DPE_PROFILE.get_ecc_int_size());
+        let sig = EcdsaSig::from_private_components(
+            BigNum::from_slice(r).unwrap(),
+            BigNum::from_slice(s).unwrap(),
+        )
+        .unwrap();
+        assert!(sig.verify(digest.bytes(), &alias_key).unwrap());
+    }
+
+    #[test]
+    fn test_certify_key_cose_key() {
+        CfiCounter::reset_for_test();
+        let mut env = DpeEnv::<TestTypes> {
+            crypto: OpensslCrypto::new(),
+            platform: DefaultPlatform,
+            arena: &mut [],
+        };
+        let certify_cmd = |format| CertifyKeyCmd {
+            handle: ContextHandle::default(),
+            flags: CertifyKeyFlags::empty(),
+            label: [0; DPE_PROFILE.get_hash_size()],
+            format,
+        };
+
+        // The format needs CWT support.
+        let mut dpe = DpeInstance::new(&mut env, Support::X509 | Support::AUTO_INIT).unwrap();
+        assert_eq!(
+            certify_cmd(CertifyKeyCmd::FORMAT_COSE_KEY).execute(
+                &mut dpe,
+                &mut env,
+                TEST_LOCALITIES[0]
+            ),
+            Err(DpeErrorCode::ArgumentNotSupported)
+        );
+
+        let mut dpe = DpeInstance::new(&mut env, Support::CWT | Support::AUTO_INIT).unwrap();
+        let Ok(Response::CertifyKey(resp)) = certify_cmd(CertifyKeyCmd::FORMAT_COSE_KEY).execute(
+            &mut dpe,
+            &mut env,
+            TEST_LOCALITIES[0],
+        ) else {
+            panic!("CertifyKey failed");
+        };
+        let cose_key = &resp.cert[..resp.cert_size as usize];
+        assert_eq!(cose_key.len(), CwtWriter::COSE_KEY_SIZE);
+
+        // It is the same COSE_Key a CWT carries in its subject public key
+        // claim, with the raw coordinates from the response.
+        let cose_key: Value = ciborium::de::from_reader(cose_key).unwrap();
+        let label = |key: i64| {
+            cose_key
+                .as_map()
+                .unwrap()
+                .iter()
+                .find(|(k, _)| *k == Value::Integer(key.into()))
+                .map(|(_, v)| v.clone())
+                .unwrap()
+        };
+        // kty: EC2
+        assert_eq!(label(1), Value::Integer(2.into()));
+        assert_eq!(label(-2).as_bytes().unwrap(), &resp.derived_pubkey_x);
+        assert_eq!(label(-3).as_bytes().unwrap(), &resp.derived_pubkey_y);
+
+        let Ok(Response::CertifyKey(cwt_resp)) =
+            certify_cmd(CertifyKeyCmd::FORMAT_CWT).execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
+        else {
+            panic!("CertifyKey failed");
+        };
+        assert_eq!(cwt_resp.derived_pubkey_x, resp.derived_pubkey_x);
+        assert_eq!(cwt_resp.derived_pubkey_y, resp.derived_pubkey_y);
+    }
+
+    #[test]
+    fn test_command_counter_in_certificate() {
+        CfiCounter::reset_for_test();
+        let mut env = DpeEnv::<TestTypes> {
+            crypto: OpensslCrypto::new(),
+            platform: DefaultPlatform,
+            arena: &mut [],
+        };
+        let mut dpe = DpeInstance::new(
+            &mut env,
+            Support::X509 | Support::AUTO_INIT | Support::COMMAND_COUNTER,
+        )
+        .unwrap();
+
+        let mut command = CommandHdr::new_for_test(Command::CERTIFY_KEY)
+            .as_bytes()
+            .to_vec();
+        command.extend(
+            CertifyKeyCmd {
+                handle: ContextHandle::default(),
+                flags: CertifyKeyFlags::empty(),
+                label: [0; DPE_PROFILE.get_hash_size()],
+                format: CertifyKeyCmd::FORMAT_X509,
+            }
+            .as_bytes(),
+        );
+
+        // Each certificate carries the number of commands that came before it.
+        for expected in 0u32..2 {
+            let Ok(Response::CertifyKey(certify_resp)) =
+                dpe.execute_serialized_command(&mut env, TEST_LOCALITIES[0], &command)
+            else {
+                panic!("CertifyKey failed");
+            };
+            let (_, cert) = X509CertificateParser::new()
+                .with_deep_parse_extensions(false)
+                .parse(&certify_resp.cert[..certify_resp.cert_size as usize])
+                .unwrap();
+            let tcb_freshness = cert
+                .get_extension_unique(&oid!(2.23.133 .5 .4 .11))
+                .unwrap()
+                .unwrap();
+            let parsed = asn1::parse_single::<TcbFreshness>(tcb_freshness.value).unwrap();
+            assert_eq!(parsed.nonce, expected.to_be_bytes());
+        }
+    }
+
+    #[test]
+    fn test_label_collisions() {
+        CfiCounter::reset_for_test();
+        let mut env = DpeEnv::<TestTypes> {
+            crypto: OpensslCrypto::new(),
+            platform: DefaultPlatform,
+            arena: &mut [],
+        };
+        let mut dpe =
+            DpeInstance::new(&mut env, Support::X509 | Support::CWT | Support::AUTO_INIT).unwrap();
+
+        let mut certify = |label: [u8; DPE_PROFILE.get_hash_size()], format| {
+            let cmd = CertifyKeyCmd {
+                handle: ContextHandle::default(),
+                flags: CertifyKeyFlags::empty(),
+                label,
+                format,
+            };
+            match cmd.execute(&mut dpe, &mut env, TEST_LOCALITIES[0]) {
+                Ok(Response::CertifyKey(resp)) => resp,
+                _ => panic!("CertifyKey failed"),
+            }
+        };
+
+        // Labels that only differ in their first or last byte, or in a
+        // single bit, still give unrelated keys.
+        let mut labels = vec![
+            [0; DPE_PROFILE.get_hash_size()],
+            [0xFF; DPE_PROFILE.get_hash_size()],
+        ];
+        for (i, bit) in [
+            (0, 0x01),
+            (DPE_PROFILE.get_hash_size() - 1, 0x01),
+            (1, 0x80),
+        ] {
+            let mut label = [0; DPE_PROFILE.get_hash_size()];
+            label[i] = bit;
+            labels.push(label);
+        }
+
+        let mut keys = Vec::new();
+        for label in &labels {
+            let resp = certify(*label, CertifyKeyCmd::FORMAT_X509);
+            let key = (resp.derived_pubkey_x, resp.derived_pubkey_y);
+            assert!(!keys.contains(&key));
+            keys.push(key);
+
+            // The label follows the locality in the certified context's
+            // TcbInfo.
+            let (_, cert) = X509CertificateParser::new()
+                .with_deep_parse_extensions(false)
+                .parse(&resp.cert[..resp.cert_size as usize])
+                .unwrap();
+            let multi_tcb_info = cert
+                .get_extension_unique(&oid!(2.23.133 .5 .4 .5))
+                .unwrap()
+                .unwrap();
+            let tcb_info = asn1::parse_single::<asn1::SequenceOf<TcbInfo>>(multi_tcb_info.value)
+                .unwrap()
+                .last()
+                .unwrap();
+            let vendor_info = tcb_info.vendor_info.unwrap();
+            assert_eq!(vendor_info[..4], TEST_LOCALITIES[0].to_be_bytes());
+            assert_eq!(vendor_info[4..], label[..]);
+
+            // Certifying the same label again, in any format, gives the same
+            // key.
+            let resp = certify(*label, CertifyKeyCmd::FORMAT_CWT);
+            assert_eq!((resp.derived_pubkey_x, resp.derived_pubkey_y), key);
+        }
+    }
+
+    #[test]
+    fn test_unknown_format() {
+        CfiCounter::reset_for_test();
+        let mut env = DpeEnv::<TestTypes> {
+            crypto: OpensslCrypto::new(),
+            platform: DefaultPlatform,
+            arena: &mut [],
+        };
+        let mut dpe =
+            DpeInstance::new(&mut env, Support::X509 | Support::CSR | Support::AUTO_INIT).unwrap();
+
+        let certify_cmd = CertifyKeyCmd {
+            handle: ContextHandle::default(),
+            flags: CertifyKeyFlags::empty(),
+            label: [0; DPE_PROFILE.get_hash_size()],
+            format: CertifyKeyCmd::FORMAT_COSE_KEY + 1,
+        };
+        assert_eq!(
+            certify_cmd.execute(&mut dpe, &mut env, TEST_LOCALITIES[0]),
+            Err(DpeErrorCode::InvalidArgument)
+        );
+    }
+
+    #[test]
+    fn test_issuer_and_validity_from_platform() {
+        CfiCounter::reset_for_test();
+        let mut env = DpeEnv::<TestTypes> {
+            crypto: OpensslCrypto::new(),
+            platform: DefaultPlatform,
+            arena: &mut [],
+        };
+        let mut dpe = DpeInstance::new(&mut env, Support::X509 | Support::AUTO_INIT).unwrap();
+
+        let certify_cmd = CertifyKeyCmd {
+            handle: ContextHandle::default(),
+            flags: CertifyKeyFlags::empty(),
+            label: [0; DPE_PROFILE.get_hash_size()],
+            format: CertifyKeyCmd::FORMAT_X509,
+        };
+        let certify_resp = match certify_cmd
+            .execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
+            .unwrap()
+        {
+            Response::CertifyKey(resp) => resp,
+            _ => panic!("Wrong response type."),
+        };
+        let cert = &certify_resp.cert[..certify_resp.cert_size.try_into().unwrap()];
+        let (_, parsed) = X509CertificateParser::new().parse(cert).unwrap();
+
+        let mut issuer_name = [0u8; MAX_ISSUER_NAME_SIZE];
+        let issuer_len = env.platform.get_issuer_name(&mut issuer_name).unwrap();
+        assert_eq!(parsed.issuer().as_raw(), &issuer_name[..issuer_len]);
+
+        // Both validity bounds are GeneralizedTimes taken verbatim from the platform.
+        let validity = env.platform.get_cert_validity().unwrap();
+        for time in [validity.not_before, validity.not_after] {
+            let mut encoded = vec![0x18, time.len() as u8];
+            encoded.extend_from_slice(&time);
+            assert!(cert.windows(encoded.len()).any(|w| w == encoded));
+        }
+    }
+
+    #[test]
+    fn test_leaf_subject_name() {
+        CfiCounter::reset_for_test();
+        let mut env = DpeEnv::<TestTypes> {
+            crypto: OpensslCrypto::new(),
+            platform: DefaultPlatform,
+            arena: &mut [],
+        };
+        let mut dpe = DpeInstance::new(&mut env, Support::X509 | Support::AUTO_INIT).unwrap();
+
+        let tci_type = u32::from_be_bytes(*b"FMC_");
+        DeriveContextCmd {
+            handle: ContextHandle::default(),
+            data: [0x5a; DPE_PROFILE.get_hash_size()],
+            flags: DeriveContextFlags::MAKE_DEFAULT | DeriveContextFlags::INPUT_ALLOW_X509,
+            tci_type,
+            target_locality: TEST_LOCALITIES[0],
+        }
+        .execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
+        .unwrap();
+
+        let Response::CertifyKey(certify_resp) = CertifyKeyCmd {
+            handle: ContextHandle::default(),
+            flags: CertifyKeyFlags::empty(),
+            label: [0; DPE_PROFILE.get_hash_size()],
+            format: CertifyKeyCmd::FORMAT_X509,
+        }
+        .execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
+        .unwrap() else {
+            panic!("Wrong response type.");
+        };
+        let cert = &certify_resp.cert[..certify_resp.cert_size as usize];
+        let (_, parsed) = X509Certificate::from_der(cert).unwrap();
+
+        // The subject names the context's TCI type and cumulative TCI.
+        let idx = dpe
+            .get_active_context_pos(&ContextHandle::default(), TEST_LOCALITIES[0])
+            .unwrap();
+        let tci_hex: String = dpe.contexts[idx]
+            .tci
+            .tci_cumulative
+            .0
+            .iter()
+            .map(|b| format!("{b:02X}"))
+            .collect();
+        let subject = parsed.subject();
+        let cn = subject.iter_common_name().next().unwrap();
+        assert_eq!(cn.as_str().unwrap(), "DPE Leaf 464D435F");
+        let serial = subject
+            .iter_by_oid(&oid!(2.5.4 .5))
+            .next()
+            .unwrap()
+            .as_str()
+            .unwrap();
+        assert_eq!(serial, &tci_hex[..LEAF_SERIAL_SIZE]);
+
+        // The platform may name the subject itself.
+        let mut platform = NamedPlatform(DefaultPlatform);
+        let leaf_name = LeafName::new(&mut platform, &dpe.contexts[idx].tci).unwrap();
+        assert!(leaf_name.from_platform);
+        assert_eq!(leaf_name.name.cn.as_slice(), b"First mutable code");
+        assert_eq!(leaf_name.name.serial.as_slice(), b"FMC_");
+    }
+
+    /// Platform that names leaf certificate subjects after their TCI type.
+    struct NamedPlatform(DefaultPlatform);
+
+    impl Platform for NamedPlatform {
+        fn get_certificate_chain(
+            &mut self,
+            offset: u32,
+            size: u32,
+            out: &mut [u8; MAX_CHUNK_SIZE],
+        ) -> Result<u32, PlatformError> {
+            self.0.get_certificate_chain(offset, size, out)
+        }
+
+        fn get_issuer_name(
+            &mut self,
+            out: &mut [u8; MAX_ISSUER_NAME_SIZE],
+        ) -> Result<usize, PlatformError> {
+            self.0.get_issuer_name(out)
+        }
+
+        fn get_signer_identifier(&mut self) -> Result<platform::SignerIdentifier, PlatformError> {
+            self.0.get_signer_identifier()
+        }
+
+        fn get_issuer_key_identifier(
+            &mut self,
+            out: &mut [u8; MAX_KEY_IDENTIFIER_SIZE],
+        ) -> Result<(), PlatformError> {
+            self.0.get_issuer_key_identifier(out)
+        }
+
+        fn get_ueid(&mut self, out: &mut [u8; MAX_UEID_SIZE]) -> Result<usize, PlatformError> {
+            self.0.get_ueid(out)
+        }
+
+        fn get_vendor_id(&mut self) -> Result<u32, PlatformError> {
+            self.0.get_vendor_id()
+        }
+
+        fn get_vendor_sku(&mut self) -> Result<u32, PlatformError> {
+            self.0.get_vendor_sku()
+        }
+
+        fn get_auto_init_locality(&mut self) -> Result<u32, PlatformError> {
+            self.0.get_auto_init_locality()
+        }
+
+        fn get_leaf_subject_name(
+            &mut self,
+            tci_type: u32,
+            _tci_cumulative: &[u8],
+        ) -> Result<SubjectName, PlatformError> {
+            let mut name = SubjectName::default();
+            name.cn
+                .try_extend_from_slice(b"First mutable code")
+                .unwrap();
+            name.serial
+                .try_extend_from_slice(&tci_type.to_be_bytes())
+                .unwrap();
+            Ok(name)
+        }
+
+        fn write_str(&mut self, str: &str) -> Result<(), PlatformError> {
+            self.0.write_str(str)
+        }
+
+        fn get_cert_validity(&mut self) -> Result<platform::CertValidity, PlatformError> {
+            self.0.get_cert_validity()
+        }
+    }
+
+    #[test]
+    fn test_serial_number() {
+        CfiCounter::reset_for_test();
+        let mut env = DpeEnv::<TestTypes> {
+            crypto: OpensslCrypto::new(),
+            platform: DefaultPlatform,
+            arena: &mut [],
+        };
+        let mut dpe = DpeInstance::new(&mut env, Support::X509 | Support::AUTO_INIT).unwrap();
+
+        let mut certify = |flags| {
+            let certify_cmd = CertifyKeyCmd {
+                handle: ContextHandle::default(),
+                flags,
+                label: [0; DPE_PROFILE.get_hash_size()],
+                format: CertifyKeyCmd::FORMAT_X509,
+            };
+            match certify_cmd
+                .execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
+                .unwrap()
+            {
+                Response::CertifyKey(resp) => {
+                    resp.cert[..resp.cert_size.try_into().unwrap()].to_vec()
+                }
+                _ => panic!("Wrong response type."),
+            }
+        };
+
+        // Random serials differ between otherwise identical requests.
+        let first = certify(CertifyKeyFlags::RANDOM_SERIAL);
+        let second = certify(CertifyKeyFlags::RANDOM_SERIAL);
+        let (_, first) = X509CertificateParser::new().parse(&first).unwrap();
+        let (_, second) = X509CertificateParser::new().parse(&second).unwrap();
+        assert_ne!(first.raw_serial(), second.raw_serial());
+
+        // By default, the serial is derived from the key and stays the same.
+        let first = certify(CertifyKeyFlags::empty());
+        let second = certify(CertifyKeyFlags::empty());
+        let (_, first) = X509CertificateParser::new().parse(&first).unwrap();
+        let (_, second) = X509CertificateParser::new().parse(&second).unwrap();
+        assert_eq!(first.raw_serial(), second.raw_serial());
+
+        // The serial is the truncated hash of the uncompressed subject key.
+        let pub_key = &first.tbs_certificate.subject_pki.subject_public_key.data;
+        let mut hasher = match DPE_PROFILE {
+            DpeProfile::P256Sha256 => Hasher::new(MessageDigest::sha256()).unwrap(),
+            DpeProfile::P384Sha384 | DpeProfile::Mldsa87Sha384 => {
+                Hasher::new(MessageDigest::sha384()).unwrap()
+            }
+            DpeProfile::P521Sha512 | DpeProfile::Ed25519Sha512 => {
+                Hasher::new(MessageDigest::sha512()).unwrap()
+            }
+        };
+        hasher.update(pub_key).unwrap();
+        let mut expected = hasher.finish().unwrap()[..SERIAL_NUMBER_SIZE].to_vec();
+        expected[0] &= 0x7F;
+        let trim = |serial: &[u8]| -> Vec<u8> {
+            serial.iter().skip_while(|&&b| b == 0).copied().collect()
+        };
+        assert_eq!(trim(first.raw_serial()), trim(&expected));
+    }
+
+    /// Reads the pending certificate back in chunks of `chunk_size` bytes.
+    fn read_cert_chunks(
+        dpe: &mut DpeInstance,
+        env: &mut DpeEnv<TestTypes>,
+        cert_size: u32,
+        chunk_size: u32,
+    ) -> Vec<u8> {
+        let mut cert = Vec::new();
+        while cert.len() < cert_size as usize {
+            let resp = match (GetCertificateChunkCmd {
+                offset: cert.len() as u32,
+                size: chunk_size,
+            })
+            .execute(dpe, env, TEST_LOCALITIES[0])
+            .unwrap()
+            {
+                Response::GetCertificateChunk(resp) => resp,
+                _ => panic!("Wrong response type."),
+            };
+            assert!(resp.certificate_size > 0 && resp.certificate_size <= chunk_size);
+            cert.extend_from_slice(&resp.certificate_chain[..resp.certificate_size as usize]);
+        }
+        cert
+    }
+
+    #[test]
+    fn test_deserialize_get_certificate_chunk() {
+        CfiCounter::reset_for_test();
+        let cmd = GetCertificateChunkCmd {
+            offset: 0x1234,
+            size: 0x100,
+        };
+        let mut command = CommandHdr::new_for_test(Command::GET_CERTIFICATE_CHUNK)
+            .as_bytes()
+            .to_vec();
+        command.extend(cmd.as_bytes());
+        assert_eq!(
+            Ok(Command::GetCertificateChunk(cmd)),
+            Command::deserialize(&command)
+        );
+    }
+
+    #[test]
+    fn test_get_certificate_chunk() {
+        CfiCounter::reset_for_test();
+        let mut env = DpeEnv::<TestTypes> {
+            crypto: OpensslCrypto::new(),
+            platform: DefaultPlatform,
+            arena: &mut [],
+        };
+        let mut dpe = DpeInstance::new(&mut env, Support::X509 | Support::AUTO_INIT).unwrap();
+
+        // Nothing to read before a certificate has been requested.
+        assert_eq!(
+            Err(DpeErrorCode::InvalidArgument),
+            GetCertificateChunkCmd {
+                offset: 0,
+                size: MAX_CHUNK_SIZE as u32,
+            }
+            .execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
+        );
+
+        let certify_resp = match (CertifyKeyCmd {
+            handle: ContextHandle::default(),
+            flags: CertifyKeyFlags::empty(),
+            label: [0; DPE_PROFILE.get_hash_size()],
+            format: CertifyKeyCmd::FORMAT_X509,
+        })
+        .execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
+        .unwrap()
+        {
+            Response::CertifyKey(resp) => resp,
+            _ => panic!("Wrong response type."),
+        };
+        let cert_size = certify_resp.total_size;
+        assert_eq!(certify_resp.cert_size, cert_size);
+
+        // The chunks put back together match the certificate in the response.
+        assert_eq!(
+            read_cert_chunks(&mut dpe, &mut env, cert_size, 100),
+            &certify_resp.cert[..cert_size as usize]
+        );
+
+        // Only the locality that certified the key can read it back.
+        assert_eq!(
+            Err(DpeErrorCode::InvalidLocality),
+            GetCertificateChunkCmd {
+                offset: 0,
+                size: MAX_CHUNK_SIZE as u32,
+            }
+            .execute(&mut dpe, &mut env, TEST_LOCALITIES[1])
+        );
+
+        // Chunks can't be larger than a response.
+        assert_eq!(
+            Err(DpeErrorCode::InvalidArgument),
+            GetCertificateChunkCmd {
+                offset: 0,
+                size: MAX_CHUNK_SIZE as u32 + 1,
+            }
+            .execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
+        );
+
+        // Reading past the end fails.
+        assert_eq!(
+            Err(DpeErrorCode::InvalidArgument),
+            GetCertificateChunkCmd {
+                offset: cert_size,
+                size: MAX_CHUNK_SIZE as u32,
+            }
+            .execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
+        );
+    }
+
+    #[test]
+    fn test_certify_key_larger_than_response() {
+        CfiCounter::reset_for_test();
+        let mut env = DpeEnv::<TestTypes> {
+            crypto: OpensslCrypto::new(),
+            platform: DefaultPlatform,
+            arena: &mut [],
+        };
+        let mut dpe = DpeInstance::new(
+            &mut env,
+            Support::X509 | Support::AUTO_INIT | Support::RECURSIVE,
+        )
+        .unwrap();
+
+        // Fill every context slot with a single chain so the MultiTcbInfo is as large as it gets.
+        for i in 1..MAX_HANDLES {
+            DeriveContextCmd {
+                handle: ContextHandle::default(),
+                data: [i as u8; DPE_PROFILE.get_tci_size()],
+                flags: DeriveContextFlags::MAKE_DEFAULT | DeriveContextFlags::INPUT_ALLOW_X509,
+                tci_type: i as u32,
+                target_locality: 0,
+            }
+            .execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
+            .unwrap();
+        }
+
+        let certify_resp = match (CertifyKeyCmd {
+            handle: ContextHandle::default(),
+            flags: CertifyKeyFlags::empty(),
+            label: [0; DPE_PROFILE.get_hash_size()],
+            format: CertifyKeyCmd::FORMAT_X509,
+        })
+        .execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
+        .unwrap()
+        {
+            Response::CertifyKey(resp) => resp,
+            _ => panic!("Wrong response type."),
+        };
+        let cert_size = certify_resp.total_size as usize;
+
+        // With the default number of handles the certificate doesn't fit in one response.
+        if MAX_HANDLES >= 24 {
+            assert!(cert_size > MAX_CERT_SIZE);
+        }
+
+        // The response only counts the bytes it holds.
+        let first_chunk_size = min(cert_size, MAX_CERT_SIZE);
+        assert_eq!(certify_resp.cert_size as usize, first_chunk_size);
+
+        let cert = read_cert_chunks(
+            &mut dpe,
+            &mut env,
+            certify_resp.total_size,
+            MAX_CHUNK_SIZE as u32,
+        );
+        assert_eq!(cert.len(), cert_size);
+        assert_eq!(
+            &cert[..first_chunk_size],
+            &certify_resp.cert[..first_chunk_size]
+        );
+
+        let mut parser = X509CertificateParser::new().with_deep_parse_extensions(true);
+        let (_, parsed) = parser.parse(&cert).unwrap();
+        let multi_tcb_info = parsed
+            .get_extension_unique(&oid!(2.23.133 .5 .4 .5))
+            .unwrap()
+            .unwrap();
+        let tcb_info_count = asn1::parse_single::<asn1::SequenceOf<TcbInfo>>(multi_tcb_info.value)
+            .unwrap()
+            .count();
+        assert_eq!(tcb_info_count, MAX_HANDLES);
+    }
+
+    #[test]
+    fn test_layered_certs_max_depth() {
+        CfiCounter::reset_for_test();
+        let mut env = DpeEnv::<TestTypes> {
+            crypto: OpensslCrypto::new(),
+            platform: DefaultPlatform,
+            arena: &mut [],
+        };
+        let mut dpe = DpeInstance::new(
+            &mut env,
+            Support::AUTO_INIT | Support::X509 | Support::LAYERED_CERTS,
+        )
+        .unwrap();
+
+        let certify = |dpe: &mut DpeInstance, env: &mut DpeEnv<TestTypes>| {
+            CertifyKeyCmd {
+                handle: ContextHandle::default(),
+                flags: CertifyKeyFlags::empty(),
+                label: [0; DPE_PROFILE.get_hash_size()],
+                format: CertifyKeyCmd::FORMAT_X509,
+            }
+            .execute(dpe, env, TEST_LOCALITIES[0])
+        };
+        let derive = |dpe: &mut DpeInstance, env: &mut DpeEnv<TestTypes>, i: usize| {
+            DeriveContextCmd {
+                handle: ContextHandle::default(),
+                data: [i as u8; DPE_PROFILE.get_hash_size()],
+                flags: DeriveContextFlags::MAKE_DEFAULT | DeriveContextFlags::INPUT_ALLOW_X509,
+                tci_type: i as u32,
+                target_locality: TEST_LOCALITIES[0],
+            }
+            .execute(dpe, env, TEST_LOCALITIES[0])
+            .unwrap();
+        };
+
+        // Give the certified context as many ancestors as the chain holds.
+        for i in 0..MAX_LAYER_CHAIN_DEPTH {
+            derive(&mut dpe, &mut env, i);
+        }
+        assert!(certify(&mut dpe, &mut env).is_ok());
+
+        let mut chain = vec![];
+        while let Ok(Response::GetCertificateChain(resp)) = (GetCertificateChainCmd {
+            offset: chain.len() as u32,
+            size: MAX_CHUNK_SIZE as u32,
+        })
+        .execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
+        {
+            chain.extend_from_slice(&resp.certificate_chain[..resp.certificate_size as usize]);
+        }
+        let mut layers = 0;
+        let mut rem = &chain[TEST_CERT_CHAIN.len()..];
+        while !rem.is_empty() {
+            let (next, _) = X509Certificate::from_der(rem).unwrap();
+            rem = next;
+            layers += 1;
+        }
+        assert_eq!(layers, MAX_LAYER_CHAIN_DEPTH);
+
+        // One more layer doesn't fit.
+        if MAX_HANDLES > MAX_LAYER_CHAIN_DEPTH + 1 {
+            derive(&mut dpe, &mut env, MAX_LAYER_CHAIN_DEPTH);
+            assert_eq!(
+                Err(DpeErrorCode::MaxTcis),
+                certify(&mut dpe, &mut env).map(|_| ())
+            );
+        }
+    }
+
+    #[test]
+    fn test_layered_certs() {
+        CfiCounter::reset_for_test();
+        let mut env = DpeEnv::<TestTypes> {
+            crypto: OpensslCrypto::new(),
+            platform: DefaultPlatform,
+            arena: &mut [],
+        };
+        let mut dpe = DpeInstance::new(
+            &mut env,
+            Support::AUTO_INIT | Support::X509 | Support::LAYERED_CERTS,
+        )
+        .unwrap();
+
+        // Derive two layers below the auto-initialized context.
+        for i in 0..2 {
+            DeriveContextCmd {
+                handle: ContextHandle::default(),
+                data: [i; DPE_PROFILE.get_hash_size()],
+                flags: DeriveContextFlags::MAKE_DEFAULT | DeriveContextFlags::INPUT_ALLOW_X509,
+                tci_type: i as u32,
+                target_locality: TEST_LOCALITIES[0],
+            }
+            .execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
+            .unwrap();
+        }
+
+        let Response::CertifyKey(certify_resp) = CertifyKeyCmd {
+            handle: ContextHandle::default(),
+            flags: CertifyKeyFlags::empty(),
+            label: [0; DPE_PROFILE.get_hash_size()],
+            format: CertifyKeyCmd::FORMAT_X509,
+        }
+        .execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
+        .unwrap() else {
+            panic!("Wrong response type.");
+        };
+        assert_eq!(certify_resp.cert_size, certify_resp.total_size);
+        let leaf = &certify_resp.cert[..certify_resp.cert_size as usize];
+
+        // Read the whole chain back.
+        let mut chain = vec![];
+        while let Ok(Response::GetCertificateChain(resp)) = (GetCertificateChainCmd {
+            offset: chain.len() as u32,
+            size: 1000,
+        })
+        .execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
+        {
+            chain.extend_from_slice(&resp.certificate_chain[..resp.certificate_size as usize]);
+        }
+        assert!(chain.starts_with(TEST_CERT_CHAIN));
+
+        // One layer certificate for each ancestor of the certified context.
+        let mut layers = vec![];
+        let mut rem = &chain[TEST_CERT_CHAIN.len()..];
+        while !rem.is_empty() {
+            let (next, _) = X509Certificate::from_der(rem).unwrap();
+            layers.push(&rem[..rem.len() - next.len()]);
+            rem = next;
+        }
+        assert_eq!(layers.len(), 2);
+
+        // Each certificate is signed by the key of the one before it, starting
+        // with the alias key.
+        let priv_key = match DPE_PROFILE.alg_len() {
+            AlgLen::Bit256 => EcKey::private_key_from_der(include_bytes!(
+                "../../../platform/src/test_data/key_256.der"
+            )),
+            AlgLen::Bit384 => EcKey::private_key_from_der(include_bytes!(
+                "../../../platform/src/test_data/key_384.der"
+            )),
+            AlgLen::Bit512 => EcKey::private_key_from_der(include_bytes!(
+                "../../../platform/src/test_data/key_521.der"
+            )),
+        }
+        .unwrap();
+        let mut issuer_key = PKey::from_ec_key(priv_key).unwrap();
+        let mut issuer_name = None;
+        for der in layers.iter().chain([&leaf]) {
+            let cert = X509::from_der(der).unwrap();
+            assert!(cert.verify(&issuer_key).unwrap());
+            issuer_key = cert.public_key().unwrap();
+
+            let (_, parsed) = X509Certificate::from_der(der).unwrap();
+            if let Some(issuer_name) = issuer_name {
+                assert_eq!(parsed.issuer().as_raw(), issuer_name);
+            }
+            issuer_name = Some(parsed.subject().as_raw());
+        }
+
+        // Other localities only see the platform's chain.
+        assert!(GetCertificateChainCmd {
+            offset: TEST_CERT_CHAIN.len() as u32,
+            size: 1000,
+        }
+        .execute(&mut dpe, &mut env, TEST_LOCALITIES[1])
+        .is_err());
+    }
+
+    #[test]
+    fn test_device_id_cert() {
+        CfiCounter::reset_for_test();
+        let mut env = DpeEnv::<TestTypes> {
+            crypto: OpensslCrypto::new(),
+            platform: DefaultPlatform,
+            arena: &mut [],
+        };
+        let support =
+            Support::AUTO_INIT | Support::X509 | Support::LAYERED_CERTS | Support::DEVICE_ID_CERT;
+        let mut dpe = DpeInstance::new(&mut env, support).unwrap();
+        dpe.self_test(&mut env, TEST_LOCALITIES[0]).unwrap();
+
+        DeriveContextCmd {
+            handle: ContextHandle::default(),
+            data: [1; DPE_PROFILE.get_hash_size()],
+            flags: DeriveContextFlags::MAKE_DEFAULT | DeriveContextFlags::INPUT_ALLOW_X509,
+            tci_type: 1,
+            target_locality: TEST_LOCALITIES[0],
+        }
+        .execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
+        .unwrap();
+
+        let Response::CertifyKey(certify_resp) = CertifyKeyCmd {
+            handle: ContextHandle::default(),
+            flags: CertifyKeyFlags::empty(),
+            label: [0; DPE_PROFILE.get_hash_size()],
+            format: CertifyKeyCmd::FORMAT_X509,
+        }
+        .execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
+        .unwrap() else {
+            panic!("Wrong response type.");
+        };
+        assert_eq!(certify_resp.cert_size, certify_resp.total_size);
+        let leaf = &certify_resp.cert[..certify_resp.cert_size as usize];
+
+        // The chain starts at the DeviceID certificate instead of the
+        // platform's chain.
+        let mut chain = vec![];
+        while let Ok(Response::GetCertificateChain(resp)) = (GetCertificateChainCmd {
+            offset: chain.len() as u32,
+            size: 1000,
+        })
+        .execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
+        {
+            chain.extend_from_slice(&resp.certificate_chain[..resp.certificate_size as usize]);
+        }
+        let device_id = dpe.device_id_cert.get();
+        assert!(chain.starts_with(device_id));
+        assert!(!chain.starts_with(TEST_CERT_CHAIN));
+
+        // The DeviceID certificate is self-signed.
+        let device_id_cert = X509::from_der(device_id).unwrap();
+        let device_id_key = device_id_cert.public_key().unwrap();
+        assert!(device_id_cert.verify(&device_id_key).unwrap());
+        let (_, parsed) = X509Certificate::from_der(device_id).unwrap();
+        assert_eq!(parsed.issuer().as_raw(), parsed.subject().as_raw());
+
+        // The layer certificate is issued by the DeviceID key and the leaf by
+        // the layer key.
+        let layer = &chain[device_id.len()..];
+        let layer_cert = X509::from_der(layer).unwrap();
+        assert!(layer_cert.verify(&device_id_key).unwrap());
+        let leaf_cert = X509::from_der(leaf).unwrap();
+        assert!(leaf_cert.verify(&layer_cert.public_key().unwrap()).unwrap());
+
+        // The DeviceID key doesn't depend on any measurement.
+        let dpe = DpeInstance::new(&mut env, support).unwrap();
+        let other = X509::from_der(dpe.device_id_cert.get()).unwrap();
+        assert!(other.public_key().unwrap().public_eq(&device_id_key));
+
+        // Without the flag there is no DeviceID certificate.
+        let dpe = DpeInstance::new(&mut env, Support::AUTO_INIT | Support::X509).unwrap();
+        assert!(dpe.device_id_cert.get().is_empty());
+    }
 }
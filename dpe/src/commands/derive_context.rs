@@ -1,5 +1,5 @@
 // Licensed under the Apache-2.0 license.
-use super::CommandExecution;
+use super::{read_prefix, CommandExecution};
 use crate::{
     context::{ActiveContextArgs, Context, ContextHandle, ContextState},
     dpe_instance::{DpeEnv, DpeInstance, DpeTypes},
@@ -16,6 +16,7 @@ use cfg_if::cfg_if;
 
 #[repr(C)]
 #[derive(Debug, PartialEq, Eq, zerocopy::FromBytes, zerocopy::AsBytes)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct DeriveContextFlags(u32);
 
 bitflags! {
@@ -28,19 +29,36 @@ bitflags! {
         const INPUT_ALLOW_CA = 1u32 << 26;
         const INPUT_ALLOW_X509 = 1u32 << 25;
         const RECURSIVE = 1u32 << 24;
+        const INPUT_SVN = 1u32 << 23;
     }
 }
 
+impl_wire_endian!(flags DeriveContextFlags);
+
+/// Derives a child context, or extends the parent in place if
+/// `DeriveContextFlags::RECURSIVE` is set.
+///
+/// When `DeriveContextFlags::INPUT_SVN` is set, the command is followed by a
+/// little-endian `u32` security version number. It may not be lower than the
+/// parent's and is reported in the `svn` field of the context's TcbInfo.
 #[repr(C)]
 #[derive(Debug, PartialEq, Eq, zerocopy::FromBytes, zerocopy::AsBytes)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct DeriveContextCmd {
     pub handle: ContextHandle,
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_hex"))]
     pub data: [u8; DPE_PROFILE.get_hash_size()],
     pub flags: DeriveContextFlags,
     pub tci_type: u32,
     pub target_locality: u32,
 }
 
+impl_wire_endian!(DeriveContextCmd {
+    flags,
+    tci_type,
+    target_locality
+});
+
 impl DeriveContextCmd {
     const fn uses_internal_info_input(&self) -> bool {
         self.flags.contains(DeriveContextFlags::INTERNAL_INPUT_INFO)
@@ -75,6 +93,38 @@ impl DeriveContextCmd {
         self.flags.contains(DeriveContextFlags::RECURSIVE)
     }
 
+    const fn has_svn(&self) -> bool {
+        self.flags.contains(DeriveContextFlags::INPUT_SVN)
+    }
+
+    /// Returns the SVN carried in `trailer`, the bytes that follow the
+    /// command, or `None` if the command doesn't set
+    /// `DeriveContextFlags::INPUT_SVN`.
+    ///
+    /// # Arguments
+    ///
+    /// * `trailer` - Serialized bytes following the command
+    pub(crate) fn svn(&self, trailer: &[u8]) -> Result<Option<u32>, DpeErrorCode> {
+        if !self.has_svn() {
+            return Ok(None);
+        }
+        let (svn, _) = read_prefix::<u32>(trailer).ok_or(DpeErrorCode::InvalidArgument)?;
+        Ok(Some(svn))
+    }
+
+    /// Checks that `svn` doesn't roll back the SVN of the context it is
+    /// derived from.
+    fn check_svn(svn: Option<u32>, parent_svn: u32) -> Result<(), DpeErrorCode> {
+        let rolls_back = svn.map_or(false, |svn| svn < parent_svn);
+        if rolls_back {
+            Err(DpeErrorCode::SvnRollback)
+        } else {
+            #[cfg(not(feature = "no-cfi"))]
+            cfi_assert!(!rolls_back);
+            Ok(())
+        }
+    }
+
     /// Whether it is okay to make a default context.
     ///
     /// When a default context is in a locality, it MUST be the only context in the locality. This
@@ -175,23 +225,76 @@ impl CommandExecution for DeriveContextCmd {
         env: &mut DpeEnv<impl DpeTypes>,
         locality: u32,
     ) -> Result<Response, DpeErrorCode> {
+        self.execute_with_svn(dpe, env, locality, None)
+    }
+}
+
+impl DeriveContextCmd {
+    /// Executes the command, recording `svn` in the derived context if
+    /// `DeriveContextFlags::INPUT_SVN` is set.
+    ///
+    /// # Arguments
+    ///
+    /// * `dpe` - DPE instance
+    /// * `env` - DPE environment containing Crypto and Platform implementations
+    /// * `locality` - which hardware locality is making the request
+    /// * `svn` - SVN that followed the command
+    #[cfg_attr(not(feature = "no-cfi"), cfi_impl_fn)]
+    pub(crate) fn execute_with_svn(
+        &self,
+        dpe: &mut DpeInstance,
+        env: &mut DpeEnv<impl DpeTypes>,
+        locality: u32,
+        svn: Option<u32>,
+    ) -> Result<Response, DpeErrorCode> {
+        dpe_trace!(
+            "DeriveContext flags={:#x} tci_type={:#x} target_locality={:#x} svn={:?} locality={:#x}",
+            self.flags.bits(),
+            self.tci_type,
+            self.target_locality,
+            svn,
+            locality
+        );
+
         // Make sure the operation is supported.
         if (!dpe.support.internal_info() && self.uses_internal_info_input())
             || (!dpe.support.internal_dice() && self.uses_internal_dice_input())
             || (!dpe.support.retain_parent_context() && self.retains_parent())
             || (!dpe.support.is_ca() && self.allows_ca())
             || (!dpe.support.x509() && self.allows_x509())
+            || (!dpe.support.svn() && self.has_svn())
         {
             return Err(DpeErrorCode::ArgumentNotSupported);
         }
 
+        // Only keep the SVN if the command asks for it to be recorded.
+        let svn = if self.has_svn() {
+            Some(svn.ok_or(DpeErrorCode::InvalidArgument)?)
+        } else {
+            None
+        };
+
         let parent_idx = dpe.get_active_context_pos(&self.handle, locality)?;
         if (!dpe.contexts[parent_idx].allow_ca() && self.allows_ca())
             || (!dpe.contexts[parent_idx].allow_x509() && self.allows_x509())
             || (self.is_recursive() && self.retains_parent())
+            // A recursive derivation extends the parent in place, so it can't move to another locality.
+            || (self.is_recursive() && self.changes_locality())
         {
             return Err(DpeErrorCode::InvalidArgument);
         }
+        Self::check_svn(svn, dpe.contexts[parent_idx].tci.svn)?;
+
+        // Let the integrator enforce which TCI types may be layered on which.
+        let tci_type_allowed = env
+            .platform
+            .validate_tci_type(dpe.contexts[parent_idx].tci.tci_type, self.tci_type);
+        if !tci_type_allowed {
+            return Err(DpeErrorCode::TciTypeNotAllowed);
+        } else {
+            #[cfg(not(feature = "no-cfi"))]
+            cfi_assert!(tci_type_allowed);
+        }
 
         let target_locality = if !self.changes_locality() {
             #[cfg(not(feature = "no-cfi"))]
@@ -202,6 +305,7 @@ impl CommandExecution for DeriveContextCmd {
             cfi_assert!(self.changes_locality());
             self.target_locality
         };
+        dpe.localities.check_known(target_locality)?;
 
         cfg_if! {
             if #[cfg(not(feature = "no-cfi"))] {
@@ -210,9 +314,11 @@ impl CommandExecution for DeriveContextCmd {
                 cfi_assert!(dpe.support.retain_parent_context() || !self.retains_parent());
                 cfi_assert!(dpe.support.is_ca() || !self.allows_ca());
                 cfi_assert!(dpe.support.x509() || !self.allows_x509());
+                cfi_assert!(dpe.support.svn() || !self.has_svn());
                 cfi_assert!(dpe.contexts[parent_idx].allow_ca() || !self.allows_ca());
                 cfi_assert!(dpe.contexts[parent_idx].allow_x509() || !self.allows_x509());
                 cfi_assert!(!self.is_recursive() || !self.retains_parent());
+                cfi_assert!(!self.is_recursive() || !self.changes_locality());
             }
         }
 
@@ -230,14 +336,18 @@ impl CommandExecution for DeriveContextCmd {
                 &TciMeasurement(self.data),
                 target_locality,
             )?;
+            if let Some(svn) = svn {
+                tmp_context.tci.svn = svn;
+                tmp_context.tci.has_svn = true.into();
+            }
 
             // Rotate the handle if it isn't the default context.
-            dpe.roll_onetime_use_handle(env, parent_idx)?;
+            tmp_context.handle = dpe.next_onetime_use_handle(env, &tmp_context)?;
 
-            dpe.contexts[parent_idx] = Context {
-                handle: dpe.contexts[parent_idx].handle,
-                ..tmp_context
-            };
+            // Storing the context is the last step that can fail, and it changes nothing if it does.
+            dpe.commit_tci_update(env, parent_idx, tmp_context)?;
+            dpe.invalidate_measurement_cache(&mut env.crypto);
+            dpe.pending_cert.forbid_reuse();
 
             // No child context created so handle is unmeaningful
             Ok(Response::DeriveContext(DeriveContextResp {
@@ -258,6 +368,15 @@ impl CommandExecution for DeriveContextCmd {
                 cfi_assert!(safe_to_make_child);
             }
 
+            // The child makes the parent's chain one context longer.
+            let child_depth = dpe.context_depth(parent_idx)? + 1;
+            if child_depth > dpe.max_cert_chain_depth() as usize {
+                return Err(DpeErrorCode::MaxCertChainDepth);
+            } else {
+                #[cfg(not(feature = "no-cfi"))]
+                cfi_assert!(child_depth <= dpe.max_cert_chain_depth() as usize);
+            }
+
             let child_handle = if self.makes_default() {
                 #[cfg(not(feature = "no-cfi"))]
                 cfi_assert!(self.makes_default());
@@ -265,7 +384,7 @@ impl CommandExecution for DeriveContextCmd {
             } else {
                 #[cfg(not(feature = "no-cfi"))]
                 cfi_assert!(!self.makes_default());
-                dpe.generate_new_handle(env)?
+                dpe.generate_new_handle(env, target_locality)?
             };
 
             let allow_ca = self.allows_ca();
@@ -286,6 +405,10 @@ impl CommandExecution for DeriveContextCmd {
                 uses_internal_input_info,
                 uses_internal_input_dice,
             });
+            // Children that don't give an SVN carry the parent's, so that
+            // their descendants can't roll it back either.
+            tmp_child_context.tci.svn = svn.unwrap_or(dpe.contexts[parent_idx].tci.svn);
+            tmp_child_context.tci.has_svn = svn.is_some().into();
 
             dpe.add_tci_measurement(
                 env,
@@ -299,10 +422,9 @@ impl CommandExecution for DeriveContextCmd {
             if !self.retains_parent() {
                 #[cfg(not(feature = "no-cfi"))]
                 cfi_assert!(!self.retains_parent());
-                tmp_parent_context.state = ContextState::Retired;
-                tmp_parent_context.handle = ContextHandle([0xff; ContextHandle::SIZE]);
+                tmp_parent_context.retire();
             } else if !tmp_parent_context.handle.is_default() {
-                tmp_parent_context.handle = dpe.generate_new_handle(env)?;
+                tmp_parent_context.handle = dpe.generate_new_handle(env, locality)?;
             } else {
                 cfg_if! {
                     if #[cfg(not(feature = "no-cfi"))] {
@@ -319,10 +441,16 @@ impl CommandExecution for DeriveContextCmd {
             // At this point we cannot error out anymore, so it is safe to set the updated child and parent contexts.
             dpe.contexts[child_idx] = tmp_child_context;
             dpe.contexts[parent_idx] = tmp_parent_context;
+            dpe.pending_cert.forbid_reuse();
 
+            let parent_handle = if self.retains_parent() {
+                dpe.contexts[parent_idx].handle
+            } else {
+                ContextHandle::INVALID
+            };
             Ok(Response::DeriveContext(DeriveContextResp {
                 handle: child_handle,
-                parent_handle: dpe.contexts[parent_idx].handle,
+                parent_handle,
                 resp_hdr: ResponseHdr::new(DpeErrorCode::NoError),
             }))
         }
@@ -339,7 +467,9 @@ mod tests {
             CertifyKeyCmd, CertifyKeyFlags, Command, CommandHdr, InitCtxCmd, SignCmd, SignFlags,
         },
         context::ContextType,
-        dpe_instance::tests::{TestTypes, RANDOM_HANDLE, SIMULATION_HANDLE, TEST_LOCALITIES},
+        dpe_instance::tests::{
+            TestTypes, RANDOM_HANDLE, SIMULATION_HANDLE, TEST_HANDLE, TEST_LOCALITIES,
+        },
         support::Support,
         MAX_HANDLES,
     };
@@ -366,7 +496,36 @@ mod tests {
             .to_vec();
         command.extend(TEST_DERIVE_CONTEXT_CMD.as_bytes());
         assert_eq!(
-            Ok(Command::DeriveContext(TEST_DERIVE_CONTEXT_CMD)),
+            Ok(Command::DeriveContext(TEST_DERIVE_CONTEXT_CMD, None)),
+            Command::deserialize(&command)
+        );
+    }
+
+    #[test]
+    fn test_deserialize_derive_context_with_svn() {
+        CfiCounter::reset_for_test();
+        let cmd = DeriveContextCmd {
+            flags: DeriveContextFlags::INPUT_SVN,
+            ..TEST_DERIVE_CONTEXT_CMD
+        };
+        let mut command = CommandHdr::new_for_test(Command::DERIVE_CONTEXT)
+            .as_bytes()
+            .to_vec();
+        command.extend(cmd.as_bytes());
+        assert_eq!(
+            Err(DpeErrorCode::InvalidArgument),
+            Command::deserialize(&command)
+        );
+
+        command.extend(7u32.to_le_bytes());
+        assert_eq!(
+            Ok(Command::DeriveContext(
+                DeriveContextCmd {
+                    flags: DeriveContextFlags::INPUT_SVN,
+                    ..TEST_DERIVE_CONTEXT_CMD
+                },
+                Some(7)
+            )),
             Command::deserialize(&command)
         );
     }
@@ -377,6 +536,7 @@ mod tests {
         let mut env = DpeEnv::<TestTypes> {
             crypto: OpensslCrypto::new(),
             platform: DefaultPlatform,
+            arena: &mut [],
         };
         let mut dpe = DpeInstance::new(
             &mut env,
@@ -439,6 +599,7 @@ mod tests {
         let mut env = DpeEnv::<TestTypes> {
             crypto: OpensslCrypto::new(),
             platform: DefaultPlatform,
+            arena: &mut [],
         };
         let mut dpe = DpeInstance::new(&mut env, Support::default()).unwrap();
 
@@ -466,6 +627,7 @@ mod tests {
         let mut env = DpeEnv::<TestTypes> {
             crypto: OpensslCrypto::new(),
             platform: DefaultPlatform,
+            arena: &mut [],
         };
         let mut dpe = DpeInstance::new(&mut env, Support::AUTO_INIT).unwrap();
 
@@ -502,6 +664,7 @@ mod tests {
         let mut env = DpeEnv::<TestTypes> {
             crypto: OpensslCrypto::new(),
             platform: DefaultPlatform,
+            arena: &mut [],
         };
         let mut dpe = DpeInstance::new(&mut env, Support::AUTO_INIT).unwrap();
 
@@ -537,6 +700,7 @@ mod tests {
         let mut env = DpeEnv::<TestTypes> {
             crypto: OpensslCrypto::new(),
             platform: DefaultPlatform,
+            arena: &mut [],
         };
         let mut dpe = DpeInstance::new(&mut env, Support::AUTO_INIT).unwrap();
 
@@ -565,6 +729,7 @@ mod tests {
         let mut env = DpeEnv::<TestTypes> {
             crypto: OpensslCrypto::new(),
             platform: DefaultPlatform,
+            arena: &mut [],
         };
         let mut dpe = DpeInstance::new(&mut env, Support::AUTO_INIT).unwrap();
 
@@ -609,6 +774,7 @@ mod tests {
         let mut env = DpeEnv::<TestTypes> {
             crypto: OpensslCrypto::new(),
             platform: DefaultPlatform,
+            arena: &mut [],
         };
         let mut dpe = DpeInstance::new(
             &mut env,
@@ -706,6 +872,7 @@ mod tests {
         let mut env = DpeEnv::<TestTypes> {
             crypto: OpensslCrypto::new(),
             platform: DefaultPlatform,
+            arena: &mut [],
         };
         let mut dpe = DpeInstance::new(
             &mut env,
@@ -845,6 +1012,7 @@ mod tests {
         let mut env = DpeEnv::<TestTypes> {
             crypto: OpensslCrypto::new(),
             platform: DefaultPlatform,
+            arena: &mut [],
         };
         let mut dpe = DpeInstance::new(
             &mut env,
@@ -871,6 +1039,7 @@ mod tests {
         let mut env = DpeEnv::<TestTypes> {
             crypto: OpensslCrypto::new(),
             platform: DefaultPlatform,
+            arena: &mut [],
         };
         let mut dpe = DpeInstance::new(
             &mut env,
@@ -904,12 +1073,61 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_make_default_in_other_locality_that_has_default() {
+        CfiCounter::reset_for_test();
+        let mut env = DpeEnv::<TestTypes> {
+            crypto: OpensslCrypto::new(),
+            platform: DefaultPlatform,
+            arena: &mut [],
+        };
+        let mut dpe = DpeInstance::new(
+            &mut env,
+            Support::AUTO_INIT | Support::RETAIN_PARENT_CONTEXT,
+        )
+        .unwrap();
+
+        let make_default_in_other = DeriveContextCmd {
+            handle: ContextHandle::default(),
+            data: [0; DPE_PROFILE.get_tci_size()],
+            flags: DeriveContextFlags::RETAIN_PARENT_CONTEXT
+                | DeriveContextFlags::MAKE_DEFAULT
+                | DeriveContextFlags::CHANGE_LOCALITY,
+            tci_type: 7,
+            target_locality: TEST_LOCALITIES[1],
+        };
+        make_default_in_other
+            .execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
+            .unwrap();
+        let num_active = dpe
+            .count_contexts(|c: &Context| c.state == ContextState::Active)
+            .unwrap();
+
+        // Only one default context is allowed per locality.
+        assert_eq!(
+            make_default_in_other.execute(&mut dpe, &mut env, TEST_LOCALITIES[0]),
+            Err(DpeErrorCode::InvalidArgument)
+        );
+
+        // The failed command must not have touched either locality.
+        assert_eq!(
+            num_active,
+            dpe.count_contexts(|c: &Context| c.state == ContextState::Active)
+                .unwrap()
+        );
+        dpe.get_active_context_pos(&ContextHandle::default(), TEST_LOCALITIES[0])
+            .unwrap();
+        dpe.get_active_context_pos(&ContextHandle::default(), TEST_LOCALITIES[1])
+            .unwrap();
+    }
+
     #[test]
     fn test_recursive() {
         CfiCounter::reset_for_test();
         let mut env = DpeEnv::<TestTypes> {
             crypto: OpensslCrypto::new(),
             platform: DefaultPlatform,
+            arena: &mut [],
         };
         let mut dpe = DpeInstance::new(
             &mut env,
@@ -974,4 +1192,234 @@ mod tests {
         let digest = hasher_2.finish().unwrap();
         assert_eq!(digest.bytes(), dpe.contexts[child_idx].tci.tci_cumulative.0);
     }
+
+    #[test]
+    fn test_change_locality() {
+        CfiCounter::reset_for_test();
+        let mut env = DpeEnv::<TestTypes> {
+            crypto: OpensslCrypto::new(),
+            platform: DefaultPlatform,
+            arena: &mut [],
+        };
+        let mut dpe = DpeInstance::new(
+            &mut env,
+            Support::AUTO_INIT | Support::RETAIN_PARENT_CONTEXT | Support::RECURSIVE,
+        )
+        .unwrap();
+        let root_idx = dpe
+            .get_active_context_pos(&ContextHandle::default(), TEST_LOCALITIES[0])
+            .unwrap();
+        dpe.contexts[root_idx].handle = TEST_HANDLE;
+
+        // Without CHANGE_LOCALITY the target locality is ignored.
+        let Response::DeriveContext(resp) = DeriveContextCmd {
+            handle: TEST_HANDLE,
+            data: [0; DPE_PROFILE.get_tci_size()],
+            flags: DeriveContextFlags::RETAIN_PARENT_CONTEXT,
+            tci_type: 0,
+            target_locality: TEST_LOCALITIES[1],
+        }
+        .execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
+        .unwrap() else {
+            panic!("Derive Child Failed");
+        };
+        let child_idx = dpe
+            .get_active_context_pos(&resp.handle, TEST_LOCALITIES[0])
+            .unwrap();
+        assert_eq!(TEST_LOCALITIES[0], dpe.contexts[child_idx].locality);
+
+        // With CHANGE_LOCALITY the child is only usable from the target locality.
+        let Response::DeriveContext(resp) = DeriveContextCmd {
+            handle: resp.parent_handle,
+            data: [0; DPE_PROFILE.get_tci_size()],
+            flags: DeriveContextFlags::RETAIN_PARENT_CONTEXT | DeriveContextFlags::CHANGE_LOCALITY,
+            tci_type: 0,
+            target_locality: TEST_LOCALITIES[1],
+        }
+        .execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
+        .unwrap() else {
+            panic!("Derive Child Failed");
+        };
+        assert_eq!(
+            Err(DpeErrorCode::InvalidHandle),
+            dpe.get_active_context_pos(&resp.handle, TEST_LOCALITIES[0])
+        );
+        let child_idx = dpe
+            .get_active_context_pos(&resp.handle, TEST_LOCALITIES[1])
+            .unwrap();
+        assert_eq!(TEST_LOCALITIES[1], dpe.contexts[child_idx].locality);
+
+        // A recursive derivation can't change the locality of the context it extends.
+        assert_eq!(
+            Err(DpeErrorCode::InvalidArgument),
+            DeriveContextCmd {
+                handle: resp.parent_handle,
+                data: [0; DPE_PROFILE.get_tci_size()],
+                flags: DeriveContextFlags::RECURSIVE | DeriveContextFlags::CHANGE_LOCALITY,
+                tci_type: 0,
+                target_locality: TEST_LOCALITIES[1],
+            }
+            .execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
+        );
+    }
+
+    #[test]
+    fn test_svn() {
+        CfiCounter::reset_for_test();
+        let mut env = DpeEnv::<TestTypes> {
+            crypto: OpensslCrypto::new(),
+            platform: DefaultPlatform,
+            arena: &mut [],
+        };
+        let derive = |svn: Option<u32>, flags: DeriveContextFlags| DeriveContextCmd {
+            handle: ContextHandle::default(),
+            data: [0; DPE_PROFILE.get_tci_size()],
+            flags: flags
+                | DeriveContextFlags::MAKE_DEFAULT
+                | if svn.is_some() {
+                    DeriveContextFlags::INPUT_SVN
+                } else {
+                    DeriveContextFlags::empty()
+                },
+            tci_type: 0,
+            target_locality: 0,
+        };
+
+        let mut dpe = DpeInstance::new(&mut env, Support::AUTO_INIT).unwrap();
+        assert_eq!(
+            Err(DpeErrorCode::ArgumentNotSupported),
+            derive(Some(1), DeriveContextFlags::empty()).execute_with_svn(
+                &mut dpe,
+                &mut env,
+                TEST_LOCALITIES[0],
+                Some(1)
+            )
+        );
+
+        let mut dpe = DpeInstance::new(
+            &mut env,
+            Support::AUTO_INIT | Support::SVN | Support::RECURSIVE,
+        )
+        .unwrap();
+        let default_ctx = |dpe: &DpeInstance| {
+            dpe.contexts[dpe
+                .get_active_context_pos(&ContextHandle::default(), TEST_LOCALITIES[0])
+                .unwrap()]
+            .tci
+        };
+
+        // The flag without an SVN following the command is malformed.
+        assert_eq!(
+            Err(DpeErrorCode::InvalidArgument),
+            derive(Some(1), DeriveContextFlags::empty()).execute(
+                &mut dpe,
+                &mut env,
+                TEST_LOCALITIES[0]
+            )
+        );
+
+        derive(Some(3), DeriveContextFlags::empty())
+            .execute_with_svn(&mut dpe, &mut env, TEST_LOCALITIES[0], Some(3))
+            .unwrap();
+        assert_eq!(3, default_ctx(&dpe).svn);
+        assert!(default_ctx(&dpe).has_svn.get());
+
+        assert_eq!(
+            Err(DpeErrorCode::SvnRollback),
+            derive(Some(2), DeriveContextFlags::empty()).execute_with_svn(
+                &mut dpe,
+                &mut env,
+                TEST_LOCALITIES[0],
+                Some(2)
+            )
+        );
+
+        // A child without an SVN inherits the parent's but doesn't report it.
+        derive(None, DeriveContextFlags::empty())
+            .execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
+            .unwrap();
+        assert_eq!(3, default_ctx(&dpe).svn);
+        assert!(!default_ctx(&dpe).has_svn.get());
+        assert_eq!(
+            Err(DpeErrorCode::SvnRollback),
+            derive(Some(2), DeriveContextFlags::empty()).execute_with_svn(
+                &mut dpe,
+                &mut env,
+                TEST_LOCALITIES[0],
+                Some(2)
+            )
+        );
+
+        // Extending a context in place can't roll back its SVN either.
+        assert_eq!(
+            Err(DpeErrorCode::SvnRollback),
+            derive(Some(2), DeriveContextFlags::RECURSIVE).execute_with_svn(
+                &mut dpe,
+                &mut env,
+                TEST_LOCALITIES[0],
+                Some(2)
+            )
+        );
+        derive(Some(5), DeriveContextFlags::RECURSIVE)
+            .execute_with_svn(&mut dpe, &mut env, TEST_LOCALITIES[0], Some(5))
+            .unwrap();
+        assert_eq!(5, default_ctx(&dpe).svn);
+        assert!(default_ctx(&dpe).has_svn.get());
+    }
+
+    #[test]
+    fn test_max_cert_chain_depth() {
+        CfiCounter::reset_for_test();
+        let mut env = DpeEnv::<TestTypes> {
+            crypto: OpensslCrypto::new(),
+            platform: DefaultPlatform,
+            arena: &mut [],
+        };
+        let mut dpe = DpeInstance::new(&mut env, Support::AUTO_INIT).unwrap();
+        assert_eq!(MAX_HANDLES as u32, dpe.max_cert_chain_depth());
+        assert_eq!(
+            Err(DpeErrorCode::InvalidArgument),
+            dpe.set_max_cert_chain_depth(0)
+        );
+        assert_eq!(
+            Err(DpeErrorCode::InvalidArgument),
+            dpe.set_max_cert_chain_depth(MAX_HANDLES as u32 + 1)
+        );
+        dpe.set_max_cert_chain_depth(3).unwrap();
+        assert_eq!(
+            3,
+            dpe.get_profile(&mut env.platform)
+                .unwrap()
+                .max_cert_chain_depth
+        );
+
+        let derive_cmd = DeriveContextCmd {
+            handle: ContextHandle::default(),
+            data: [0; DPE_PROFILE.get_tci_size()],
+            flags: DeriveContextFlags::MAKE_DEFAULT,
+            tci_type: 0,
+            target_locality: TEST_LOCALITIES[0],
+        };
+
+        // The auto-initialized context is at depth 1, so two more fit.
+        for depth in 2..=3 {
+            derive_cmd
+                .execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
+                .unwrap();
+            let idx = dpe
+                .get_active_context_pos(&ContextHandle::default(), TEST_LOCALITIES[0])
+                .unwrap();
+            assert_eq!(depth, dpe.context_depth(idx).unwrap());
+        }
+
+        assert_eq!(
+            Err(DpeErrorCode::MaxCertChainDepth),
+            derive_cmd.execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
+        );
+        assert_eq!(
+            3,
+            dpe.count_contexts(|c: &Context| c.state != ContextState::Inactive)
+                .unwrap()
+        );
+    }
 }
@@ -14,10 +14,13 @@ use caliptra_cfi_lib_git::{cfi_assert, cfi_assert_eq};
 
 #[repr(C)]
 #[derive(Debug, PartialEq, Eq, zerocopy::FromBytes, zerocopy::AsBytes)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct DestroyCtxCmd {
     pub handle: ContextHandle,
 }
 
+impl_wire_endian!(DestroyCtxCmd {});
+
 impl CommandExecution for DestroyCtxCmd {
     #[cfg_attr(not(feature = "no-cfi"), cfi_impl_fn)]
     fn execute(
@@ -26,7 +29,15 @@ impl CommandExecution for DestroyCtxCmd {
         _env: &mut DpeEnv<impl DpeTypes>,
         locality: u32,
     ) -> Result<Response, DpeErrorCode> {
-        let idx = dpe.get_active_context_pos(&self.handle, locality)?;
+        dpe_trace!("DestroyContext locality={:#x}", locality);
+
+        // Retired contexts reject every other command, but can still be destroyed.
+        let (idx, retired) = match dpe.get_active_context_pos(&self.handle, locality) {
+            Err(DpeErrorCode::RetiredContext) => {
+                (dpe.get_retired_context_pos(&self.handle, locality)?, true)
+            }
+            idx => (idx?, false),
+        };
         let context = &dpe.contexts[idx];
         // Make sure the command is coming from the right locality.
         if context.locality != locality {
@@ -36,8 +47,25 @@ impl CommandExecution for DestroyCtxCmd {
             cfi_assert_eq(context.locality, locality);
         }
 
+        // The children of a retired context may have been handed to other
+        // localities, which the caller must not be able to tear down.
+        if retired {
+            let descendants = dpe.get_descendants(context)?;
+            let other_locality =
+                flags_iter(descendants, MAX_HANDLES).any(|i| dpe.contexts[i].locality != locality);
+            if cfi_launder(other_locality) {
+                return Err(DpeErrorCode::InvalidLocality);
+            } else {
+                #[cfg(not(feature = "no-cfi"))]
+                cfi_assert!(!other_locality);
+            }
+        } else {
+            #[cfg(not(feature = "no-cfi"))]
+            cfi_assert!(!retired);
+        }
+
         // mark consecutive retired parent contexts without active children to be destroyed
-        let mut retired_contexts = 0u32;
+        let mut retired_contexts = 0u64;
         let mut parent_idx = context.parent_idx as usize;
         loop {
             if parent_idx == Context::ROOT_INDEX as usize {
@@ -88,7 +116,9 @@ impl CommandExecution for DestroyCtxCmd {
 mod tests {
     use super::*;
     use crate::{
-        commands::{Command, CommandHdr, DeriveContextCmd, DeriveContextFlags, InitCtxCmd},
+        commands::{
+            Command, CommandHdr, DeriveContextCmd, DeriveContextFlags, InitCtxCmd, TagTciCmd,
+        },
         context::{Context, ContextState},
         dpe_instance::tests::{TestTypes, SIMULATION_HANDLE, TEST_HANDLE, TEST_LOCALITIES},
         support::{test::SUPPORT, Support},
@@ -122,6 +152,7 @@ mod tests {
         let mut env = DpeEnv::<TestTypes> {
             crypto: OpensslCrypto::new(),
             platform: DefaultPlatform,
+            arena: &mut [],
         };
         let mut dpe = DpeInstance::new(&mut env, Support::default()).unwrap();
 
@@ -279,6 +310,7 @@ mod tests {
         let mut env = DpeEnv::<TestTypes> {
             crypto: OpensslCrypto::new(),
             platform: DefaultPlatform,
+            arena: &mut [],
         };
         let mut dpe = DpeInstance::new(&mut env, SUPPORT).unwrap();
 
@@ -348,6 +380,7 @@ mod tests {
         let mut env = DpeEnv::<TestTypes> {
             crypto: OpensslCrypto::new(),
             platform: DefaultPlatform,
+            arena: &mut [],
         };
         let mut dpe = DpeInstance::new(&mut env, SUPPORT).unwrap();
 
@@ -410,6 +443,132 @@ mod tests {
         assert_eq!(dpe.contexts[1].state, ContextState::Retired);
     }
 
+    #[test]
+    fn test_destroy_deep_tree() {
+        CfiCounter::reset_for_test();
+        let mut env = DpeEnv::<TestTypes> {
+            crypto: OpensslCrypto::new(),
+            platform: DefaultPlatform,
+            arena: &mut [],
+        };
+        let mut dpe = DpeInstance::new(&mut env, Support::default()).unwrap();
+
+        // Chain every slot together: context[i] is the only child of context[i - 1].
+        for idx in 0..MAX_HANDLES {
+            let parent_idx = if idx == 0 {
+                Context::ROOT_INDEX
+            } else {
+                (idx - 1) as u8
+            };
+            let children: &[u8] = if idx + 1 < MAX_HANDLES {
+                &[(idx + 1) as u8]
+            } else {
+                &[]
+            };
+            activate_dummy_context(
+                &mut dpe,
+                idx,
+                parent_idx,
+                &ContextHandle([idx as u8 + 1; ContextHandle::SIZE]),
+                children,
+            );
+            dpe.contexts[idx].locality = TEST_LOCALITIES[0];
+        }
+
+        // Destroying the middle of the chain releases everything beneath it.
+        let mid = MAX_HANDLES / 2;
+        DestroyCtxCmd {
+            handle: ContextHandle([mid as u8 + 1; ContextHandle::SIZE]),
+        }
+        .execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
+        .unwrap();
+        for (idx, context) in dpe.contexts.iter().enumerate() {
+            if idx < mid {
+                assert_eq!(context.state, ContextState::Active);
+            } else {
+                assert_eq!(context.state, ContextState::Inactive);
+                assert_eq!(context.handle, ContextHandle::default());
+                assert_eq!(context.children, 0);
+            }
+        }
+        if mid > 0 {
+            assert_eq!(dpe.contexts[mid - 1].children, 0);
+        }
+
+        // Destroying the root releases the rest.
+        DestroyCtxCmd {
+            handle: ContextHandle([1; ContextHandle::SIZE]),
+        }
+        .execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
+        .unwrap();
+        assert_eq!(
+            dpe.count_contexts(|ctx| ctx.state != ContextState::Inactive)
+                .unwrap(),
+            0
+        );
+    }
+
+    #[test]
+    fn test_destroy_wide_tree() {
+        CfiCounter::reset_for_test();
+        let mut env = DpeEnv::<TestTypes> {
+            crypto: OpensslCrypto::new(),
+            platform: DefaultPlatform,
+            arena: &mut [],
+        };
+        let mut dpe = DpeInstance::new(&mut env, Support::default()).unwrap();
+
+        // context[0] is the parent of every other slot.
+        let children: Vec<u8> = (1..MAX_HANDLES as u8).collect();
+        activate_dummy_context(
+            &mut dpe,
+            0,
+            Context::ROOT_INDEX,
+            &ContextHandle::default(),
+            &children,
+        );
+        dpe.contexts[0].locality = TEST_LOCALITIES[0];
+        for idx in 1..MAX_HANDLES {
+            activate_dummy_context(
+                &mut dpe,
+                idx,
+                0,
+                &ContextHandle([idx as u8; ContextHandle::SIZE]),
+                &[],
+            );
+            dpe.contexts[idx].locality = TEST_LOCALITIES[0];
+        }
+
+        // Destroying a leaf only releases that leaf.
+        let leaf = MAX_HANDLES - 1;
+        if leaf > 0 {
+            DestroyCtxCmd {
+                handle: ContextHandle([leaf as u8; ContextHandle::SIZE]),
+            }
+            .execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
+            .unwrap();
+            assert_eq!(dpe.contexts[leaf].state, ContextState::Inactive);
+            assert_eq!(dpe.contexts[0].children & (1 << leaf), 0);
+            assert_eq!(
+                dpe.count_contexts(|ctx| ctx.state != ContextState::Inactive)
+                    .unwrap(),
+                MAX_HANDLES - 1
+            );
+        }
+
+        // Destroying the root releases all remaining children.
+        DestroyCtxCmd {
+            handle: ContextHandle::default(),
+        }
+        .execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
+        .unwrap();
+        for context in dpe.contexts.iter() {
+            assert_eq!(context.state, ContextState::Inactive);
+            assert_eq!(context.children, 0);
+            assert_eq!(context.parent_idx, Context::ROOT_INDEX);
+        }
+    }
+
     fn activate_dummy_context(
         dpe: &mut DpeInstance,
         idx: usize,
@@ -425,4 +584,248 @@ mod tests {
             dpe.contexts[idx].children = children;
         }
     }
+
+    #[test]
+    fn test_destroyed_slot_is_scrubbed() {
+        CfiCounter::reset_for_test();
+        let mut env = DpeEnv::<TestTypes> {
+            crypto: OpensslCrypto::new(),
+            platform: DefaultPlatform,
+            arena: &mut [],
+        };
+        let mut dpe = DpeInstance::new(
+            &mut env,
+            Support::AUTO_INIT | Support::TAGGING | Support::RETAIN_PARENT_CONTEXT,
+        )
+        .unwrap();
+
+        let derive =
+            |dpe: &mut DpeInstance, env: &mut DpeEnv<TestTypes>, data: u8, tci_type: u32| {
+                match (DeriveContextCmd {
+                    handle: ContextHandle::default(),
+                    data: [data; DPE_PROFILE.get_hash_size()],
+                    flags: DeriveContextFlags::RETAIN_PARENT_CONTEXT,
+                    tci_type,
+                    target_locality: TEST_LOCALITIES[0],
+                })
+                .execute(dpe, env, TEST_LOCALITIES[0])
+                {
+                    Ok(Response::DeriveContext(resp)) => resp.handle,
+                    resp => panic!("Unexpected response {resp:?}"),
+                }
+            };
+
+        let handle = derive(&mut dpe, &mut env, 0xaa, 0x1234);
+        let handle =
+            match (TagTciCmd { handle, tag: 7 }).execute(&mut dpe, &mut env, TEST_LOCALITIES[0]) {
+                Ok(Response::TagTci(resp)) => resp.handle,
+                resp => panic!("Unexpected response {resp:?}"),
+            };
+        assert_eq!(ContextState::Active, dpe.contexts[1].state);
+        assert!(dpe.contexts[1].has_tag());
+
+        DestroyCtxCmd { handle }
+            .execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
+            .unwrap();
+        assert_eq!(Context::new().as_bytes(), dpe.contexts[1].as_bytes());
+
+        // The freed slot is reused first and nothing from the old context shows up in it.
+        derive(&mut dpe, &mut env, 0x55, 0x5678);
+        let context = &dpe.contexts[1];
+        assert_eq!(ContextState::Active, context.state);
+        assert!(!context.has_tag());
+        assert_eq!(0, context.tag);
+        assert_eq!(0x5678, context.tci.tci_type);
+        assert_eq!(
+            [0x55; DPE_PROFILE.get_tci_size()],
+            context.tci.tci_current.0
+        );
+
+        // Destroying the default context scrubs its slot too, but the default context still
+        // can't be initialized a second time.
+        DestroyCtxCmd {
+            handle: ContextHandle::default(),
+        }
+        .execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
+        .unwrap();
+        assert_eq!(Context::new().as_bytes(), dpe.contexts[0].as_bytes());
+        assert_eq!(Context::new().as_bytes(), dpe.contexts[1].as_bytes());
+        assert!(dpe.has_initialized());
+        assert_eq!(
+            Err(DpeErrorCode::ArgumentNotSupported),
+            InitCtxCmd::new_use_default().execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
+        );
+    }
+
+    #[test]
+    fn test_destroy_retired_context() {
+        CfiCounter::reset_for_test();
+        let mut env = DpeEnv::<TestTypes> {
+            crypto: OpensslCrypto::new(),
+            platform: DefaultPlatform,
+            arena: &mut [],
+        };
+        let mut dpe = DpeInstance::new(&mut env, Support::SIMULATION).unwrap();
+        let Ok(Response::InitCtx(resp)) =
+            InitCtxCmd::new_simulation().execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
+        else {
+            panic!("Initialize Context Failed");
+        };
+        let parent_handle = resp.handle;
+
+        // Deriving a child without retaining the parent retires the parent.
+        let derive_cmd = DeriveContextCmd {
+            handle: parent_handle,
+            data: [0; DPE_PROFILE.get_tci_size()],
+            flags: DeriveContextFlags::empty(),
+            tci_type: 0,
+            target_locality: TEST_LOCALITIES[0],
+        };
+        let Ok(Response::DeriveContext(resp)) =
+            derive_cmd.execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
+        else {
+            panic!("Derive Child Failed");
+        };
+        assert_eq!(ContextHandle::INVALID, resp.parent_handle);
+        let parent_idx = dpe
+            .get_retired_context_pos(&parent_handle, TEST_LOCALITIES[0])
+            .unwrap();
+        let child_idx = dpe
+            .get_active_context_pos(&resp.handle, TEST_LOCALITIES[0])
+            .unwrap();
+        assert_eq!(ContextState::Retired, dpe.contexts[parent_idx].state);
+
+        // The retired parent rejects commands, but its handle stays bound to its locality.
+        assert_eq!(
+            Err(DpeErrorCode::RetiredContext),
+            derive_cmd.execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
+        );
+        assert_eq!(
+            Err(DpeErrorCode::InvalidLocality),
+            DestroyCtxCmd {
+                handle: parent_handle,
+            }
+            .execute(&mut dpe, &mut env, TEST_LOCALITIES[1])
+        );
+
+        // Destroying the retired parent destroys its child as well.
+        DestroyCtxCmd {
+            handle: parent_handle,
+        }
+        .execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
+        .unwrap();
+        assert_eq!(ContextState::Inactive, dpe.contexts[parent_idx].state);
+        assert_eq!(ContextState::Inactive, dpe.contexts[child_idx].state);
+    }
+
+    #[test]
+    fn test_destroy_retired_context_keeps_other_localities() {
+        CfiCounter::reset_for_test();
+        let mut env = DpeEnv::<TestTypes> {
+            crypto: OpensslCrypto::new(),
+            platform: DefaultPlatform,
+            arena: &mut [],
+        };
+        let mut dpe = DpeInstance::new(&mut env, Support::SIMULATION).unwrap();
+        let Ok(Response::InitCtx(resp)) =
+            InitCtxCmd::new_simulation().execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
+        else {
+            panic!("Initialize Context Failed");
+        };
+        let parent_handle = resp.handle;
+
+        // Hand the child to another locality and retire the parent.
+        let Ok(Response::DeriveContext(resp)) = (DeriveContextCmd {
+            handle: parent_handle,
+            data: [0; DPE_PROFILE.get_tci_size()],
+            flags: DeriveContextFlags::CHANGE_LOCALITY,
+            tci_type: 0,
+            target_locality: TEST_LOCALITIES[1],
+        })
+        .execute(&mut dpe, &mut env, TEST_LOCALITIES[0]) else {
+            panic!("Derive Child Failed");
+        };
+        let child_idx = dpe
+            .get_active_context_pos(&resp.handle, TEST_LOCALITIES[1])
+            .unwrap();
+
+        // The parent's locality can't tear down the child through the retired parent.
+        assert_eq!(
+            Err(DpeErrorCode::InvalidLocality),
+            DestroyCtxCmd {
+                handle: parent_handle,
+            }
+            .execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
+        );
+        assert_eq!(ContextState::Active, dpe.contexts[child_idx].state);
+
+        // The child's own locality can, which takes the retired parent with it.
+        DestroyCtxCmd {
+            handle: resp.handle,
+        }
+        .execute(&mut dpe, &mut env, TEST_LOCALITIES[1])
+        .unwrap();
+        assert_eq!(
+            0,
+            dpe.count_contexts(|ctx| ctx.state != ContextState::Inactive)
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn test_retired_default_context_releases_handle() {
+        CfiCounter::reset_for_test();
+        let mut env = DpeEnv::<TestTypes> {
+            crypto: OpensslCrypto::new(),
+            platform: DefaultPlatform,
+            arena: &mut [],
+        };
+        let mut dpe = DpeInstance::new(&mut env, Support::default()).unwrap();
+        InitCtxCmd::new_use_default()
+            .execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
+            .unwrap();
+
+        // Handing a default child to another locality retires the parent, which gives up the
+        // default handle of its locality.
+        DeriveContextCmd {
+            handle: ContextHandle::default(),
+            data: [0; DPE_PROFILE.get_tci_size()],
+            flags: DeriveContextFlags::MAKE_DEFAULT | DeriveContextFlags::CHANGE_LOCALITY,
+            tci_type: 0,
+            target_locality: TEST_LOCALITIES[1],
+        }
+        .execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
+        .unwrap();
+        let child_idx = dpe
+            .get_active_context_pos(&ContextHandle::default(), TEST_LOCALITIES[1])
+            .unwrap();
+        let parent_idx = dpe.contexts[child_idx].parent_idx as usize;
+        assert_eq!(ContextState::Retired, dpe.contexts[parent_idx].state);
+        assert_eq!(ContextHandle::INVALID, dpe.contexts[parent_idx].handle);
+        assert_eq!(
+            Err(DpeErrorCode::InvalidHandle),
+            dpe.get_active_context_pos(&ContextHandle::default(), TEST_LOCALITIES[0])
+        );
+
+        // The parent can't be reached through the invalid handle.
+        assert_eq!(
+            Err(DpeErrorCode::InvalidHandle),
+            DestroyCtxCmd {
+                handle: ContextHandle::INVALID,
+            }
+            .execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
+        );
+
+        // It goes away with its only child.
+        DestroyCtxCmd {
+            handle: ContextHandle::default(),
+        }
+        .execute(&mut dpe, &mut env, TEST_LOCALITIES[1])
+        .unwrap();
+        assert_eq!(
+            0,
+            dpe.count_contexts(|ctx| ctx.state != ContextState::Inactive)
+                .unwrap()
+        );
+    }
 }
@@ -0,0 +1,231 @@
+// Licensed under the Apache-2.0 license.
+use super::CommandExecution;
+use crate::{
+    context::ContextHandle,
+    dpe_instance::{DpeEnv, DpeInstance, DpeTypes},
+    response::{DpeErrorCode, ExportCdiResp, Response, ResponseHdr},
+    DPE_PROFILE,
+};
+#[cfg(not(feature = "no-cfi"))]
+use caliptra_cfi_derive_git::cfi_impl_fn;
+#[cfg(not(feature = "no-cfi"))]
+use caliptra_cfi_lib_git::cfi_assert;
+use crypto::{AeadKey, Crypto, AEAD_KEY_SIZE};
+use platform::Platform;
+
+#[repr(C)]
+#[derive(Debug, PartialEq, Eq, zerocopy::FromBytes, zerocopy::AsBytes)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ExportCdiCmd {
+    pub handle: ContextHandle,
+}
+
+impl_wire_endian!(ExportCdiCmd {});
+
+impl ExportCdiCmd {
+    /// Additional authenticated data bound to every wrapped CDI, so the
+    /// ciphertext can't be passed off as some other blob sealed under the
+    /// same platform key.
+    pub const AAD: &'static [u8] = b"DPE_EXPORT_CDI";
+
+    /// Label the exported CDI is derived with. It differs from the label of
+    /// the CDI that CertifyKey and Sign derive keys from, so the exported
+    /// CDI can't be used to recompute the context's keys.
+    pub const CDI_LABEL: &'static [u8] = b"DPE_EXPORT";
+}
+
+impl CommandExecution for ExportCdiCmd {
+    #[cfg_attr(not(feature = "no-cfi"), cfi_impl_fn)]
+    fn execute(
+        &self,
+        dpe: &mut DpeInstance,
+        env: &mut DpeEnv<impl DpeTypes>,
+        locality: u32,
+    ) -> Result<Response, DpeErrorCode> {
+        dpe_trace!("ExportCdi locality={:#x}", locality);
+
+        // Make sure this command is supported.
+        if !dpe.support.export_cdi() {
+            return Err(DpeErrorCode::InvalidCommand);
+        } else {
+            #[cfg(not(feature = "no-cfi"))]
+            cfi_assert!(dpe.support.export_cdi());
+        }
+
+        let idx = dpe.get_active_context_pos(&self.handle, locality)?;
+
+        let digest = dpe.compute_measurement_hash(env, idx)?;
+        let mut key = AeadKey([0; AEAD_KEY_SIZE]);
+        env.platform.get_cdi_wrapping_key(&mut key.0)?;
+
+        let cdi = env
+            .crypto
+            .derive_cdi(DPE_PROFILE.alg_len(), &digest, Self::CDI_LABEL)?;
+        let mut wrapped_cdi = [0; DPE_PROFILE.get_hash_size()];
+        let wrapped = env.crypto.wrap_cdi(&cdi, &key, Self::AAD, &mut wrapped_cdi);
+        env.crypto.destroy_cdi(cdi)?;
+        let (iv, tag) = wrapped?;
+
+        // Rotate the handle if it isn't the default context.
+        dpe.roll_onetime_use_handle(env, idx)?;
+
+        Ok(Response::ExportCdi(ExportCdiResp {
+            resp_hdr: ResponseHdr::new(DpeErrorCode::NoError),
+            new_context_handle: dpe.contexts[idx].handle,
+            iv,
+            tag,
+            wrapped_cdi,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        commands::{Command, CommandHdr, InitCtxCmd},
+        dpe_instance::tests::{TestTypes, SIMULATION_HANDLE, TEST_LOCALITIES},
+        support::Support,
+    };
+    use caliptra_cfi_lib_git::CfiCounter;
+    use crypto::OpensslCrypto;
+    use platform::default::{DefaultPlatform, TEST_CDI_WRAPPING_KEY};
+    use zerocopy::AsBytes;
+
+    const TEST_EXPORT_CDI_CMD: ExportCdiCmd = ExportCdiCmd {
+        handle: SIMULATION_HANDLE,
+    };
+
+    #[test]
+    fn test_deserialize_export_cdi() {
+        CfiCounter::reset_for_test();
+        let mut command = CommandHdr::new_for_test(Command::EXPORT_CDI)
+            .as_bytes()
+            .to_vec();
+        command.extend(TEST_EXPORT_CDI_CMD.as_bytes());
+        assert_eq!(
+            Ok(Command::ExportCdi(TEST_EXPORT_CDI_CMD)),
+            Command::deserialize(&command)
+        );
+    }
+
+    #[test]
+    fn test_export_cdi() {
+        CfiCounter::reset_for_test();
+        let mut env = DpeEnv::<TestTypes> {
+            crypto: OpensslCrypto::new(),
+            platform: DefaultPlatform,
+            arena: &mut [],
+        };
+        let mut dpe = DpeInstance::new(&mut env, Support::default()).unwrap();
+
+        // Make sure it returns an error if the command is marked unsupported.
+        assert_eq!(
+            Err(DpeErrorCode::InvalidCommand),
+            ExportCdiCmd {
+                handle: ContextHandle::default(),
+            }
+            .execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
+        );
+
+        let mut dpe = DpeInstance::new(&mut env, Support::EXPORT_CDI).unwrap();
+        InitCtxCmd::new_use_default()
+            .execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
+            .unwrap();
+
+        // Wrong locality.
+        assert_eq!(
+            Err(DpeErrorCode::InvalidLocality),
+            ExportCdiCmd {
+                handle: ContextHandle::default(),
+            }
+            .execute(&mut dpe, &mut env, TEST_LOCALITIES[1])
+        );
+
+        let Ok(Response::ExportCdi(resp)) = (ExportCdiCmd {
+            handle: ContextHandle::default(),
+        })
+        .execute(&mut dpe, &mut env, TEST_LOCALITIES[0]) else {
+            panic!("ExportCdi failed");
+        };
+        assert_eq!(resp.new_context_handle, ContextHandle::default());
+
+        // Unwrapping with the platform key must recover the context's CDI.
+        let mut unwrapped = resp.wrapped_cdi;
+        env.crypto
+            .aead_decrypt(
+                &AeadKey(TEST_CDI_WRAPPING_KEY),
+                &resp.iv,
+                ExportCdiCmd::AAD,
+                &mut unwrapped,
+                &resp.tag,
+            )
+            .unwrap();
+
+        let digest = dpe.compute_measurement_hash(&mut env, 0).unwrap();
+        let cdi = env
+            .crypto
+            .derive_cdi(DPE_PROFILE.alg_len(), &digest, ExportCdiCmd::CDI_LABEL)
+            .unwrap();
+        assert_eq!(cdi.bytes(), unwrapped);
+        assert_ne!(resp.wrapped_cdi, unwrapped);
+
+        // The exported CDI is not the one CertifyKey and Sign derive keys
+        // from.
+        let signing_cdi = env
+            .crypto
+            .derive_cdi(DPE_PROFILE.alg_len(), &digest, b"DPE")
+            .unwrap();
+        assert_ne!(signing_cdi.bytes(), unwrapped);
+
+        // A different AAD must not authenticate.
+        let mut unwrapped = resp.wrapped_cdi;
+        assert!(env
+            .crypto
+            .aead_decrypt(
+                &AeadKey(TEST_CDI_WRAPPING_KEY),
+                &resp.iv,
+                b"DPE",
+                &mut unwrapped,
+                &resp.tag,
+            )
+            .is_err());
+    }
+
+    #[test]
+    fn test_export_cdi_rotates_handle() {
+        CfiCounter::reset_for_test();
+        let mut env = DpeEnv::<TestTypes> {
+            crypto: OpensslCrypto::new(),
+            platform: DefaultPlatform,
+            arena: &mut [],
+        };
+        let mut dpe =
+            DpeInstance::new(&mut env, Support::SIMULATION | Support::EXPORT_CDI).unwrap();
+        let handle = match InitCtxCmd::new_simulation()
+            .execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
+            .unwrap()
+        {
+            Response::InitCtx(resp) => resp.handle,
+            _ => panic!("Wrong response type."),
+        };
+
+        let Ok(Response::ExportCdi(resp)) =
+            ExportCdiCmd { handle }.execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
+        else {
+            panic!("ExportCdi failed");
+        };
+        assert_ne!(resp.new_context_handle, handle);
+
+        // The old handle is no longer valid.
+        assert_eq!(
+            Err(DpeErrorCode::InvalidHandle),
+            ExportCdiCmd { handle }.execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
+        );
+        assert!(ExportCdiCmd {
+            handle: resp.new_context_handle,
+        }
+        .execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
+        .is_ok());
+    }
+}
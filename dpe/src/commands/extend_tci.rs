@@ -0,0 +1,176 @@
+// Licensed under the Apache-2.0 license.
+use super::CommandExecution;
+use crate::{
+    context::ContextHandle,
+    dpe_instance::{DpeEnv, DpeInstance, DpeTypes},
+    response::{DpeErrorCode, NewHandleResp, Response, ResponseHdr},
+    tci::TciMeasurement,
+    DPE_PROFILE,
+};
+#[cfg(not(feature = "no-cfi"))]
+use caliptra_cfi_derive_git::cfi_impl_fn;
+#[cfg(not(feature = "no-cfi"))]
+use caliptra_cfi_lib_git::cfi_assert;
+
+#[repr(C)]
+#[derive(Debug, PartialEq, Eq, zerocopy::FromBytes, zerocopy::AsBytes)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ExtendTciCmd {
+    pub handle: ContextHandle,
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_hex"))]
+    pub data: [u8; DPE_PROFILE.get_hash_size()],
+}
+
+impl_wire_endian!(ExtendTciCmd {});
+
+impl CommandExecution for ExtendTciCmd {
+    #[cfg_attr(not(feature = "no-cfi"), cfi_impl_fn)]
+    fn execute(
+        &self,
+        dpe: &mut DpeInstance,
+        env: &mut DpeEnv<impl DpeTypes>,
+        locality: u32,
+    ) -> Result<Response, DpeErrorCode> {
+        dpe_trace!("ExtendTci locality={:#x}", locality);
+
+        // Make sure this command is supported.
+        if !dpe.support.extend_tci() {
+            return Err(DpeErrorCode::InvalidCommand);
+        } else {
+            #[cfg(not(feature = "no-cfi"))]
+            cfi_assert!(dpe.support.extend_tci());
+        }
+
+        let idx = dpe.get_active_context_pos(&self.handle, locality)?;
+
+        // Extend a copy of the context so a failure leaves the instance untouched.
+        let mut tmp_context = dpe.contexts[idx];
+        dpe.add_tci_measurement(env, &mut tmp_context, &TciMeasurement(self.data), locality)?;
+
+        // Rotate the handle if it isn't the default context.
+        tmp_context.handle = dpe.next_onetime_use_handle(env, &tmp_context)?;
+
+        // Storing the context is the last step that can fail, and it changes nothing if it does.
+        dpe.commit_tci_update(env, idx, tmp_context)?;
+        dpe.invalidate_measurement_cache(&mut env.crypto);
+        dpe.pending_cert.forbid_reuse();
+
+        Ok(Response::ExtendTci(NewHandleResp {
+            handle: dpe.contexts[idx].handle,
+            resp_hdr: ResponseHdr::new(DpeErrorCode::NoError),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        commands::{Command, CommandHdr, InitCtxCmd},
+        dpe_instance::tests::{TestTypes, RANDOM_HANDLE, SIMULATION_HANDLE, TEST_LOCALITIES},
+        support::Support,
+    };
+    use caliptra_cfi_lib_git::CfiCounter;
+    use crypto::{Crypto, Hasher, OpensslCrypto};
+    use platform::default::DefaultPlatform;
+    use zerocopy::AsBytes;
+
+    const TEST_EXTEND_TCI_CMD: ExtendTciCmd = ExtendTciCmd {
+        handle: SIMULATION_HANDLE,
+        data: [0; DPE_PROFILE.get_hash_size()],
+    };
+
+    #[test]
+    fn test_deserialize_extend_tci() {
+        CfiCounter::reset_for_test();
+        let mut command = CommandHdr::new_for_test(Command::EXTEND_TCI)
+            .as_bytes()
+            .to_vec();
+        command.extend(TEST_EXTEND_TCI_CMD.as_bytes());
+        assert_eq!(
+            Ok(Command::ExtendTci(TEST_EXTEND_TCI_CMD)),
+            Command::deserialize(&command)
+        );
+    }
+
+    #[test]
+    fn test_extend_tci() {
+        CfiCounter::reset_for_test();
+        let mut env = DpeEnv::<TestTypes> {
+            crypto: OpensslCrypto::new(),
+            platform: DefaultPlatform,
+            arena: &mut [],
+        };
+        let mut dpe = DpeInstance::new(&mut env, Support::default()).unwrap();
+        // Make sure it returns an error if the command is marked unsupported.
+        assert_eq!(
+            Err(DpeErrorCode::InvalidCommand),
+            ExtendTciCmd {
+                handle: ContextHandle::default(),
+                data: [0; DPE_PROFILE.get_hash_size()],
+            }
+            .execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
+        );
+
+        // Turn on support.
+        let mut dpe =
+            DpeInstance::new(&mut env, Support::EXTEND_TCI | Support::SIMULATION).unwrap();
+        InitCtxCmd::new_use_default()
+            .execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
+            .unwrap();
+
+        // Wrong locality.
+        assert_eq!(
+            Err(DpeErrorCode::InvalidLocality),
+            ExtendTciCmd {
+                handle: ContextHandle::default(),
+                data: [0; DPE_PROFILE.get_hash_size()],
+            }
+            .execute(&mut dpe, &mut env, TEST_LOCALITIES[1])
+        );
+
+        let extension_data = [1; DPE_PROFILE.get_hash_size()];
+        assert_eq!(
+            Ok(Response::ExtendTci(NewHandleResp {
+                handle: ContextHandle::default(),
+                resp_hdr: ResponseHdr::new(DpeErrorCode::NoError),
+            })),
+            ExtendTciCmd {
+                handle: ContextHandle::default(),
+                data: extension_data,
+            }
+            .execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
+        );
+
+        // Make sure extending the default TCI doesn't change the handle.
+        let default_handle = ContextHandle::default();
+        let handle = &dpe.contexts[0].handle;
+        assert_eq!(default_handle, *handle);
+        // Make sure the current TCI was updated correctly.
+        let context = &dpe.contexts[0];
+        let mut hasher = env.crypto.hash_initialize(DPE_PROFILE.alg_len()).unwrap();
+        hasher.update(&[0u8; DPE_PROFILE.get_hash_size()]).unwrap();
+        hasher.update(&extension_data).unwrap();
+        let first_cumulative = hasher.finish().unwrap();
+        assert_eq!(first_cumulative.bytes(), context.tci.tci_cumulative.0);
+        assert_eq!(extension_data, context.tci.tci_current.0);
+
+        // A non-default handle should be rotated after it is extended.
+        let sim_local = TEST_LOCALITIES[1];
+        InitCtxCmd::new_simulation()
+            .execute(&mut dpe, &mut env, sim_local)
+            .unwrap();
+        let resp = ExtendTciCmd {
+            handle: RANDOM_HANDLE,
+            data: extension_data,
+        }
+        .execute(&mut dpe, &mut env, sim_local)
+        .unwrap();
+        let new_handle = match resp {
+            Response::ExtendTci(resp) => resp.handle,
+            _ => panic!("Incorrect response type"),
+        };
+        assert_ne!(new_handle, RANDOM_HANDLE);
+        assert!(dpe.get_active_context_pos(&new_handle, sim_local).is_ok());
+    }
+}
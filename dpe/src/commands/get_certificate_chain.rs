@@ -1,37 +1,136 @@
 // Licensed under the Apache-2.0 license.
 use super::CommandExecution;
+#[cfg(feature = "no-large-stack")]
+use crate::arena::Scratch;
 use crate::{
     dpe_instance::{DpeEnv, DpeInstance, DpeTypes},
-    response::{DpeErrorCode, GetCertificateChainResp, Response, ResponseHdr},
+    response::{DpeErrorCode, GetCertificateChainResp, Response, ResponseHdr, ResponseWriter},
 };
 #[cfg(not(feature = "no-cfi"))]
 use caliptra_cfi_derive_git::cfi_impl_fn;
-use platform::{Platform, MAX_CHUNK_SIZE};
+use platform::{Platform, PlatformError, MAX_CHUNK_SIZE};
 
+/// Reads a chunk of the certificate chain.
+///
+/// If the last certificate CertifyKey issued for the caller's locality was
+/// signed by a parent context's layer key, the certificates of the layer keys
+/// follow the platform's chain so that it ends at the leaf's issuer.
+///
+/// With DEVICE_ID_CERT, the chain starts at the self-signed DeviceID
+/// certificate instead of the platform's chain.
 #[repr(C)]
 #[derive(Debug, PartialEq, Eq, zerocopy::FromBytes, zerocopy::AsBytes)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct GetCertificateChainCmd {
     pub offset: u32,
     pub size: u32,
 }
 
+impl_wire_endian!(GetCertificateChainCmd { offset, size });
+
+/// Returns the length of the platform's certificate chain.
+fn platform_chain_size(
+    platform: &mut impl Platform,
+    chunk: &mut [u8; MAX_CHUNK_SIZE],
+) -> Result<u32, DpeErrorCode> {
+    let mut size = 0u32;
+    loop {
+        // A short read marks the end of the chain. Reading past the end
+        // fails when its length is a nonzero multiple of the chunk size.
+        let len = match platform.get_certificate_chain(size, MAX_CHUNK_SIZE as u32, chunk) {
+            Ok(len) => len,
+            Err(PlatformError::CertificateChainError)
+                if size != 0 && size % MAX_CHUNK_SIZE as u32 == 0 =>
+            {
+                break;
+            }
+            Err(e) => return Err(e.into()),
+        };
+        size = size.checked_add(len).ok_or(DpeErrorCode::InternalError)?;
+        if len < MAX_CHUNK_SIZE as u32 {
+            break;
+        }
+    }
+    Ok(size)
+}
+
 impl CommandExecution for GetCertificateChainCmd {
     #[cfg_attr(not(feature = "no-cfi"), cfi_impl_fn)]
     fn execute(
         &self,
-        _dpe: &mut DpeInstance,
+        dpe: &mut DpeInstance,
         env: &mut DpeEnv<impl DpeTypes>,
-        _locality: u32,
+        locality: u32,
     ) -> Result<Response, DpeErrorCode> {
+        dpe_trace!(
+            "GetCertificateChain offset={} size={}",
+            self.offset,
+            self.size
+        );
+
         // Make sure the operation is supported.
         if self.size > MAX_CHUNK_SIZE as u32 {
             return Err(DpeErrorCode::InvalidArgument);
         }
 
         let mut cert_chunk = [0u8; MAX_CHUNK_SIZE];
-        let len = env
-            .platform
-            .get_certificate_chain(self.offset, self.size, &mut cert_chunk)?;
+        let device_id_cert = dpe.device_id_cert.get();
+        let layer_chain = dpe.pending_cert.layer_chain(locality);
+        if device_id_cert.is_empty() && layer_chain.is_empty() {
+            let len =
+                env.platform
+                    .get_certificate_chain(self.offset, self.size, &mut cert_chunk)?;
+            return Ok(Response::GetCertificateChain(GetCertificateChainResp {
+                certificate_chain: cert_chunk,
+                certificate_size: len,
+                resp_hdr: ResponseHdr::new(DpeErrorCode::NoError),
+            }));
+        }
+
+        // The platform's chain is read through a separate buffer, since it
+        // comes in whole chunks.
+        #[cfg(feature = "no-large-stack")]
+        let mut scratch = Scratch::new(env.arena);
+        scratch_buffer!(scratch, chunk, MAX_CHUNK_SIZE);
+
+        // The self-signed DeviceID certificate takes the place of the
+        // platform's chain.
+        let root_size = if device_id_cert.is_empty() {
+            platform_chain_size(&mut env.platform, chunk)?
+        } else {
+            device_id_cert.len() as u32
+        };
+        let layer_size = layer_chain.len() as u32;
+        let total_size = root_size
+            .checked_add(layer_size)
+            .ok_or(DpeErrorCode::InternalError)?;
+        if self.offset >= total_size {
+            return Err(DpeErrorCode::InvalidArgument);
+        }
+
+        let mut out = ResponseWriter::with_offset(
+            cert_chunk
+                .get_mut(..self.size as usize)
+                .ok_or(DpeErrorCode::InternalError)?,
+            self.offset as usize,
+        );
+        if device_id_cert.is_empty() {
+            out.write_from(root_size as usize, |offset, buf| {
+                let len =
+                    env.platform
+                        .get_certificate_chain(offset as u32, buf.len() as u32, chunk)?
+                        as usize;
+                buf.get_mut(..len)
+                    .ok_or(DpeErrorCode::InternalError)?
+                    .copy_from_slice(chunk.get(..len).ok_or(DpeErrorCode::InternalError)?);
+                Ok(len)
+            })?;
+        } else {
+            out.write_chunk(device_id_cert)?;
+        }
+        out.write_chunk(layer_chain)?;
+        let len = out.len() as u32;
+
         Ok(Response::GetCertificateChain(GetCertificateChainResp {
             certificate_chain: cert_chunk,
             certificate_size: len,
@@ -50,7 +149,7 @@ mod tests {
     };
     use caliptra_cfi_lib_git::CfiCounter;
     use crypto::OpensslCrypto;
-    use platform::default::DefaultPlatform;
+    use platform::default::{DefaultPlatform, TEST_CERT_CHAIN};
     use zerocopy::AsBytes;
 
     const TEST_GET_CERTIFICATE_CHAIN_CMD: GetCertificateChainCmd = GetCertificateChainCmd {
@@ -77,6 +176,7 @@ mod tests {
         let mut env = DpeEnv::<TestTypes> {
             crypto: OpensslCrypto::new(),
             platform: DefaultPlatform,
+            arena: &mut [],
         };
         let mut dpe = DpeInstance::new(&mut env, SUPPORT).unwrap();
 
@@ -89,4 +189,37 @@ mod tests {
             .execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
         );
     }
+
+    #[test]
+    fn test_chunked_retrieval() {
+        CfiCounter::reset_for_test();
+        let mut env = DpeEnv::<TestTypes> {
+            crypto: OpensslCrypto::new(),
+            platform: DefaultPlatform,
+            arena: &mut [],
+        };
+        let mut dpe = DpeInstance::new(&mut env, SUPPORT).unwrap();
+
+        // Read the chain back in small windows and make sure it reassembles.
+        const CHUNK_SIZE: u32 = 100;
+        let mut chain = vec![];
+        loop {
+            let resp = GetCertificateChainCmd {
+                offset: chain.len() as u32,
+                size: CHUNK_SIZE,
+            }
+            .execute(&mut dpe, &mut env, TEST_LOCALITIES[0]);
+            match resp {
+                Ok(Response::GetCertificateChain(resp)) => {
+                    assert!(resp.certificate_size <= CHUNK_SIZE);
+                    chain.extend_from_slice(
+                        &resp.certificate_chain[..resp.certificate_size as usize],
+                    );
+                }
+                Err(_) => break,
+                _ => panic!("Incorrect response type"),
+            }
+        }
+        assert_eq!(chain, TEST_CERT_CHAIN);
+    }
 }
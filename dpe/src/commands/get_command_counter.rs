@@ -0,0 +1,175 @@
+// Licensed under the Apache-2.0 license.
+use super::CommandExecution;
+use crate::{
+    context::ContextHandle,
+    dpe_instance::{DpeEnv, DpeInstance, DpeTypes},
+    response::{DpeErrorCode, GetCommandCounterResp, Response, ResponseHdr},
+};
+#[cfg(not(feature = "no-cfi"))]
+use caliptra_cfi_derive_git::cfi_impl_fn;
+#[cfg(not(feature = "no-cfi"))]
+use caliptra_cfi_lib_git::cfi_assert;
+
+/// Reports how many commands completed successfully on a context before this
+/// one. The query is counted as well and does not rotate the handle, so two
+/// queries in a row return consecutive values.
+#[repr(C)]
+#[derive(Debug, PartialEq, Eq, zerocopy::FromBytes, zerocopy::AsBytes)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct GetCommandCounterCmd {
+    pub handle: ContextHandle,
+}
+
+impl_wire_endian!(GetCommandCounterCmd {});
+
+impl CommandExecution for GetCommandCounterCmd {
+    #[cfg_attr(not(feature = "no-cfi"), cfi_impl_fn)]
+    fn execute(
+        &self,
+        dpe: &mut DpeInstance,
+        _env: &mut DpeEnv<impl DpeTypes>,
+        locality: u32,
+    ) -> Result<Response, DpeErrorCode> {
+        dpe_trace!("GetCommandCounter locality={:#x}", locality);
+
+        // Make sure this command is supported.
+        if !dpe.support.command_counter() {
+            return Err(DpeErrorCode::InvalidCommand);
+        } else {
+            #[cfg(not(feature = "no-cfi"))]
+            cfi_assert!(dpe.support.command_counter());
+        }
+
+        let idx = dpe.get_active_context_pos(&self.handle, locality)?;
+
+        Ok(Response::GetCommandCounter(GetCommandCounterResp {
+            resp_hdr: ResponseHdr::new(DpeErrorCode::NoError),
+            counter: dpe.contexts[idx].command_counter,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        commands::{Command, CommandHdr, ExtendTciCmd, RotateCtxCmd, RotateCtxFlags},
+        dpe_instance::tests::{TestTypes, SIMULATION_HANDLE, TEST_HANDLE, TEST_LOCALITIES},
+        support::Support,
+        DPE_PROFILE,
+    };
+    use caliptra_cfi_lib_git::CfiCounter;
+    use crypto::OpensslCrypto;
+    use platform::default::DefaultPlatform;
+    use zerocopy::AsBytes;
+
+    const TEST_GET_COMMAND_COUNTER_CMD: GetCommandCounterCmd = GetCommandCounterCmd {
+        handle: SIMULATION_HANDLE,
+    };
+
+    fn get_counter(
+        dpe: &mut DpeInstance,
+        env: &mut DpeEnv<TestTypes>,
+        handle: ContextHandle,
+    ) -> u32 {
+        let mut serialized = CommandHdr::new_for_test(Command::GET_COMMAND_COUNTER)
+            .as_bytes()
+            .to_vec();
+        serialized.extend(GetCommandCounterCmd { handle }.as_bytes());
+
+        match dpe
+            .execute_serialized_command(env, TEST_LOCALITIES[0], &serialized)
+            .unwrap()
+        {
+            Response::GetCommandCounter(resp) => resp.counter,
+            resp => panic!("Unexpected response {:?}", resp.as_bytes()),
+        }
+    }
+
+    #[test]
+    fn test_deserialize_get_command_counter() {
+        CfiCounter::reset_for_test();
+        let mut command = CommandHdr::new_for_test(Command::GET_COMMAND_COUNTER)
+            .as_bytes()
+            .to_vec();
+        command.extend(TEST_GET_COMMAND_COUNTER_CMD.as_bytes());
+        assert_eq!(
+            Ok(Command::GetCommandCounter(TEST_GET_COMMAND_COUNTER_CMD)),
+            Command::deserialize(&command)
+        );
+    }
+
+    #[test]
+    fn test_get_command_counter() {
+        CfiCounter::reset_for_test();
+        let mut env = DpeEnv::<TestTypes> {
+            crypto: OpensslCrypto::new(),
+            platform: DefaultPlatform,
+            arena: &mut [],
+        };
+
+        // Make sure it returns an error if the command is marked unsupported.
+        let mut dpe = DpeInstance::new(&mut env, Support::AUTO_INIT).unwrap();
+        assert_eq!(
+            Err(DpeErrorCode::InvalidCommand),
+            GetCommandCounterCmd {
+                handle: ContextHandle::default(),
+            }
+            .execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
+        );
+
+        let mut dpe = DpeInstance::new(
+            &mut env,
+            Support::AUTO_INIT | Support::COMMAND_COUNTER | Support::ROTATE_CONTEXT,
+        )
+        .unwrap();
+        let default = ContextHandle::default();
+
+        // Each query counts as a command on the context.
+        assert_eq!(0, get_counter(&mut dpe, &mut env, default));
+        assert_eq!(1, get_counter(&mut dpe, &mut env, default));
+
+        // The counter stays with the context when its handle changes.
+        let cmd = RotateCtxCmd {
+            handle: default,
+            flags: RotateCtxFlags::empty(),
+        };
+        let mut serialized = CommandHdr::new_for_test(Command::ROTATE_CONTEXT_HANDLE)
+            .as_bytes()
+            .to_vec();
+        serialized.extend(cmd.as_bytes());
+        let Response::RotateCtx(resp) = dpe
+            .execute_serialized_command(&mut env, TEST_LOCALITIES[0], &serialized)
+            .unwrap()
+        else {
+            panic!("RotateContextHandle failed");
+        };
+        assert_eq!(3, get_counter(&mut dpe, &mut env, resp.handle));
+
+        // Failed commands are not counted. ExtendTci is not supported.
+        let cmd = ExtendTciCmd {
+            handle: resp.handle,
+            data: [0; DPE_PROFILE.get_hash_size()],
+        };
+        let mut serialized = CommandHdr::new_for_test(Command::EXTEND_TCI)
+            .as_bytes()
+            .to_vec();
+        serialized.extend(cmd.as_bytes());
+        let Response::Error(_) = dpe
+            .execute_serialized_command(&mut env, TEST_LOCALITIES[0], &serialized)
+            .unwrap()
+        else {
+            panic!("ExtendTci unexpectedly succeeded");
+        };
+        assert_eq!(4, get_counter(&mut dpe, &mut env, resp.handle));
+
+        // Unknown handles are rejected.
+        assert_eq!(
+            Err(DpeErrorCode::InvalidHandle),
+            GetCommandCounterCmd {
+                handle: TEST_HANDLE,
+            }
+            .execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
+        );
+    }
+}
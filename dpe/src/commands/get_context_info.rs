@@ -0,0 +1,214 @@
+// Licensed under the Apache-2.0 license.
+use super::CommandExecution;
+use crate::{
+    context::{ChildIter, ContextHandle},
+    dpe_instance::{DpeEnv, DpeInstance, DpeTypes},
+    response::{DpeErrorCode, GetContextInfoResp, Response, ResponseHdr},
+};
+#[cfg(not(feature = "no-cfi"))]
+use caliptra_cfi_derive_git::cfi_impl_fn;
+#[cfg(not(feature = "no-cfi"))]
+use caliptra_cfi_lib_git::cfi_assert;
+
+/// Reports where a context sits in the derivation tree: its index and its
+/// parent's, how many children it has, its TCI type, and its locality. Meant
+/// for debugging, so it does not rotate the handle. The parent's handle is
+/// never returned, since it would let any holder of a child handle use the
+/// parent.
+#[repr(C)]
+#[derive(Debug, PartialEq, Eq, zerocopy::FromBytes, zerocopy::AsBytes)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct GetContextInfoCmd {
+    pub handle: ContextHandle,
+}
+
+impl_wire_endian!(GetContextInfoCmd {});
+
+impl CommandExecution for GetContextInfoCmd {
+    #[cfg_attr(not(feature = "no-cfi"), cfi_impl_fn)]
+    fn execute(
+        &self,
+        dpe: &mut DpeInstance,
+        _env: &mut DpeEnv<impl DpeTypes>,
+        locality: u32,
+    ) -> Result<Response, DpeErrorCode> {
+        dpe_trace!("GetContextInfo locality={:#x}", locality);
+
+        // Make sure this command is supported.
+        if !dpe.support.context_info() {
+            return Err(DpeErrorCode::InvalidCommand);
+        } else {
+            #[cfg(not(feature = "no-cfi"))]
+            cfi_assert!(dpe.support.context_info());
+        }
+
+        let idx = dpe.get_active_context_pos(&self.handle, locality)?;
+        let context = &dpe.contexts[idx];
+
+        let (parent_index, flags) = match context.parent_pos() {
+            Some(parent_idx) => (parent_idx as u32, GetContextInfoResp::HAS_PARENT),
+            None => (0, 0),
+        };
+
+        let mut child_count = 0;
+        for child in ChildIter::new(context, &dpe.contexts) {
+            child?;
+            child_count += 1;
+        }
+
+        Ok(Response::GetContextInfo(GetContextInfoResp {
+            resp_hdr: ResponseHdr::new(DpeErrorCode::NoError),
+            index: idx as u32,
+            parent_index,
+            flags,
+            child_count,
+            tci_type: context.tci.tci_type,
+            locality: context.locality,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        commands::{Command, CommandHdr, DeriveContextCmd, DeriveContextFlags},
+        dpe_instance::tests::{TestTypes, SIMULATION_HANDLE, TEST_HANDLE, TEST_LOCALITIES},
+        support::Support,
+        DPE_PROFILE,
+    };
+    use caliptra_cfi_lib_git::CfiCounter;
+    use crypto::OpensslCrypto;
+    use platform::default::DefaultPlatform;
+    use zerocopy::AsBytes;
+
+    const TEST_GET_CONTEXT_INFO_CMD: GetContextInfoCmd = GetContextInfoCmd {
+        handle: SIMULATION_HANDLE,
+    };
+
+    fn get_info(
+        dpe: &mut DpeInstance,
+        env: &mut DpeEnv<TestTypes>,
+        handle: ContextHandle,
+    ) -> GetContextInfoResp {
+        match (GetContextInfoCmd { handle })
+            .execute(dpe, env, TEST_LOCALITIES[0])
+            .unwrap()
+        {
+            Response::GetContextInfo(resp) => resp,
+            resp => panic!("Unexpected response {:?}", resp.as_bytes()),
+        }
+    }
+
+    #[test]
+    fn test_deserialize_get_context_info() {
+        CfiCounter::reset_for_test();
+        let mut command = CommandHdr::new_for_test(Command::GET_CONTEXT_INFO)
+            .as_bytes()
+            .to_vec();
+        command.extend(TEST_GET_CONTEXT_INFO_CMD.as_bytes());
+        assert_eq!(
+            Ok(Command::GetContextInfo(TEST_GET_CONTEXT_INFO_CMD)),
+            Command::deserialize(&command)
+        );
+    }
+
+    #[test]
+    fn test_get_context_info() {
+        CfiCounter::reset_for_test();
+        let mut env = DpeEnv::<TestTypes> {
+            crypto: OpensslCrypto::new(),
+            platform: DefaultPlatform,
+            arena: &mut [],
+        };
+
+        // Make sure it returns an error if the command is marked unsupported.
+        let mut dpe = DpeInstance::new(&mut env, Support::AUTO_INIT).unwrap();
+        assert_eq!(
+            Err(DpeErrorCode::InvalidCommand),
+            GetContextInfoCmd {
+                handle: ContextHandle::default(),
+            }
+            .execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
+        );
+
+        let mut dpe = DpeInstance::new(
+            &mut env,
+            Support::AUTO_INIT | Support::CONTEXT_INFO | Support::RETAIN_PARENT_CONTEXT,
+        )
+        .unwrap();
+        let default = ContextHandle::default();
+
+        // The auto-initialized context is a root with no children.
+        let root_idx = dpe
+            .get_active_context_pos(&default, TEST_LOCALITIES[0])
+            .unwrap();
+        let resp = get_info(&mut dpe, &mut env, default);
+        assert_eq!(root_idx as u32, resp.index);
+        assert_eq!(0, resp.flags);
+        assert_eq!(0, resp.child_count);
+        assert_eq!(dpe.contexts[root_idx].tci.tci_type, resp.tci_type);
+        assert_eq!(TEST_LOCALITIES[0], resp.locality);
+
+        let Response::DeriveContext(derive_resp) = DeriveContextCmd {
+            handle: default,
+            data: [0; DPE_PROFILE.get_hash_size()],
+            flags: DeriveContextFlags::RETAIN_PARENT_CONTEXT,
+            tci_type: 7,
+            target_locality: TEST_LOCALITIES[0],
+        }
+        .execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
+        .unwrap() else {
+            panic!("DeriveContext failed");
+        };
+
+        // The parent now has one child.
+        let resp = get_info(&mut dpe, &mut env, derive_resp.parent_handle);
+        assert_eq!(1, resp.child_count);
+
+        // The child points back at its parent.
+        let child_idx = dpe
+            .get_active_context_pos(&derive_resp.handle, TEST_LOCALITIES[0])
+            .unwrap();
+        let resp = get_info(&mut dpe, &mut env, derive_resp.handle);
+        assert_eq!(child_idx as u32, resp.index);
+        assert_eq!(GetContextInfoResp::HAS_PARENT, resp.flags);
+        assert_eq!(root_idx as u32, resp.parent_index);
+        assert_eq!(0, resp.child_count);
+        assert_eq!(7, resp.tci_type);
+        assert_eq!(TEST_LOCALITIES[0], resp.locality);
+
+        // A grandchild doesn't give away the handle of its non-default parent.
+        let Response::DeriveContext(grandchild_resp) = DeriveContextCmd {
+            handle: derive_resp.handle,
+            data: [0; DPE_PROFILE.get_hash_size()],
+            flags: DeriveContextFlags::RETAIN_PARENT_CONTEXT,
+            tci_type: 8,
+            target_locality: TEST_LOCALITIES[0],
+        }
+        .execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
+        .unwrap() else {
+            panic!("DeriveContext failed");
+        };
+        let parent_handle = grandchild_resp.parent_handle;
+        assert!(!parent_handle.is_default());
+        let resp = get_info(&mut dpe, &mut env, grandchild_resp.handle);
+        assert_eq!(child_idx as u32, resp.parent_index);
+        assert!(!resp
+            .as_bytes()
+            .windows(ContextHandle::SIZE)
+            .any(|window| window == parent_handle.0));
+
+        // The handle is not rotated.
+        get_info(&mut dpe, &mut env, grandchild_resp.handle);
+
+        // Unknown handles are rejected.
+        assert_eq!(
+            Err(DpeErrorCode::InvalidHandle),
+            GetContextInfoCmd {
+                handle: TEST_HANDLE,
+            }
+            .execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
+        );
+    }
+}
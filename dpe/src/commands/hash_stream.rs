@@ -0,0 +1,394 @@
+// Licensed under the Apache-2.0 license.
+use super::CommandExecution;
+use crate::{
+    dpe_instance::{DpeEnv, DpeInstance, DpeTypes},
+    hash_stream::{HashStream, MAX_HASH_STREAMS_PER_LOCALITY},
+    response::{DpeErrorCode, HashFinalResp, HashInitializeResp, Response, ResponseHdr},
+    DPE_PROFILE,
+};
+#[cfg(not(feature = "no-cfi"))]
+use caliptra_cfi_derive_git::cfi_impl_fn;
+#[cfg(not(feature = "no-cfi"))]
+use caliptra_cfi_lib_git::cfi_assert;
+use crypto::Crypto;
+use zeroize::Zeroize;
+
+/// Starts a hash that is fed over several HashUpdate commands, so data too
+/// large for one command can be hashed by the DPE before it is signed.
+#[derive(Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct HashInitializeCmd;
+
+/// Adds data to a hash started with HashInitialize.
+///
+/// The command is followed by `size` bytes of data.
+#[repr(C)]
+#[derive(Debug, PartialEq, Eq, zerocopy::FromBytes, zerocopy::AsBytes)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct HashUpdateCmd {
+    pub stream_id: u32,
+    pub size: u32,
+}
+
+impl_wire_endian!(HashUpdateCmd { stream_id, size });
+
+/// Finishes a hash started with HashInitialize and returns the digest. The
+/// stream is freed even if finishing the hash fails.
+#[repr(C)]
+#[derive(Debug, PartialEq, Eq, zerocopy::FromBytes, zerocopy::AsBytes)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct HashFinalCmd {
+    pub stream_id: u32,
+}
+
+impl_wire_endian!(HashFinalCmd { stream_id });
+
+/// Returns the active stream `stream_id` if `locality` started it.
+fn get_stream(
+    dpe: &mut DpeInstance,
+    stream_id: u32,
+    locality: u32,
+) -> Result<&mut HashStream, DpeErrorCode> {
+    let stream = dpe
+        .hash_streams
+        .get_mut(stream_id as usize)
+        .filter(|s| s.active())
+        .ok_or(DpeErrorCode::InvalidArgument)?;
+
+    // Only the locality that started the hash may use it.
+    if stream.locality != locality {
+        return Err(DpeErrorCode::InvalidLocality);
+    } else {
+        #[cfg(not(feature = "no-cfi"))]
+        cfi_assert!(stream.locality == locality);
+    }
+    Ok(stream)
+}
+
+impl CommandExecution for HashInitializeCmd {
+    #[cfg_attr(not(feature = "no-cfi"), cfi_impl_fn)]
+    fn execute(
+        &self,
+        dpe: &mut DpeInstance,
+        env: &mut DpeEnv<impl DpeTypes>,
+        locality: u32,
+    ) -> Result<Response, DpeErrorCode> {
+        dpe_trace!("HashInitialize locality={:#x}", locality);
+
+        // Make sure this command is supported.
+        if !dpe.support.hash_stream() {
+            return Err(DpeErrorCode::InvalidCommand);
+        } else {
+            #[cfg(not(feature = "no-cfi"))]
+            cfi_assert!(dpe.support.hash_stream());
+        }
+
+        let open_in_locality = dpe
+            .hash_streams
+            .iter()
+            .filter(|s| s.active() && s.locality == locality)
+            .count();
+        if open_in_locality >= MAX_HASH_STREAMS_PER_LOCALITY {
+            return Err(DpeErrorCode::MaxHashStreams);
+        } else {
+            #[cfg(not(feature = "no-cfi"))]
+            cfi_assert!(open_in_locality < MAX_HASH_STREAMS_PER_LOCALITY);
+        }
+
+        let stream_id = dpe
+            .hash_streams
+            .iter()
+            .position(|s| !s.active())
+            .ok_or(DpeErrorCode::MaxHashStreams)?;
+
+        let state = env.crypto.hash_state_initialize(DPE_PROFILE.alg_len())?;
+
+        let stream = &mut dpe.hash_streams[stream_id];
+        stream.state = state.0;
+        stream.locality = locality;
+        stream.active = true.into();
+
+        Ok(Response::HashInitialize(HashInitializeResp {
+            resp_hdr: ResponseHdr::new(DpeErrorCode::NoError),
+            stream_id: stream_id as u32,
+        }))
+    }
+}
+
+impl HashUpdateCmd {
+    /// Returns the data carried in `trailer`, the bytes that follow the
+    /// command.
+    ///
+    /// # Arguments
+    ///
+    /// * `trailer` - Serialized bytes following the command
+    pub(crate) fn data<'a>(&self, trailer: &'a [u8]) -> Result<&'a [u8], DpeErrorCode> {
+        trailer
+            .get(..self.size as usize)
+            .ok_or(DpeErrorCode::InvalidArgument)
+    }
+
+    /// Adds `data` to the stream.
+    ///
+    /// # Arguments
+    ///
+    /// * `dpe` - DPE instance
+    /// * `env` - DPE environment containing Crypto and Platform implementations
+    /// * `locality` - which hardware locality is making the request
+    /// * `data` - Data that followed the command
+    #[cfg_attr(not(feature = "no-cfi"), cfi_impl_fn)]
+    pub(crate) fn execute_with_data(
+        &self,
+        dpe: &mut DpeInstance,
+        env: &mut DpeEnv<impl DpeTypes>,
+        locality: u32,
+        data: &[u8],
+    ) -> Result<Response, DpeErrorCode> {
+        dpe_trace!(
+            "HashUpdate stream_id={} size={} locality={:#x}",
+            self.stream_id,
+            data.len(),
+            locality
+        );
+
+        // Make sure this command is supported.
+        if !dpe.support.hash_stream() {
+            return Err(DpeErrorCode::InvalidCommand);
+        } else {
+            #[cfg(not(feature = "no-cfi"))]
+            cfi_assert!(dpe.support.hash_stream());
+        }
+
+        let stream = get_stream(dpe, self.stream_id, locality)?;
+        let mut state = stream.hash_state();
+        env.crypto
+            .hash_state_update(DPE_PROFILE.alg_len(), &mut state, data)?;
+        stream.state = state.0;
+
+        Ok(Response::HashUpdate(ResponseHdr::new(
+            DpeErrorCode::NoError,
+        )))
+    }
+}
+
+impl CommandExecution for HashUpdateCmd {
+    #[cfg_attr(not(feature = "no-cfi"), cfi_impl_fn)]
+    fn execute(
+        &self,
+        dpe: &mut DpeInstance,
+        env: &mut DpeEnv<impl DpeTypes>,
+        locality: u32,
+    ) -> Result<Response, DpeErrorCode> {
+        self.execute_with_data(dpe, env, locality, &[])
+    }
+}
+
+impl CommandExecution for HashFinalCmd {
+    #[cfg_attr(not(feature = "no-cfi"), cfi_impl_fn)]
+    fn execute(
+        &self,
+        dpe: &mut DpeInstance,
+        env: &mut DpeEnv<impl DpeTypes>,
+        locality: u32,
+    ) -> Result<Response, DpeErrorCode> {
+        dpe_trace!(
+            "HashFinal stream_id={} locality={:#x}",
+            self.stream_id,
+            locality
+        );
+
+        // Make sure this command is supported.
+        if !dpe.support.hash_stream() {
+            return Err(DpeErrorCode::InvalidCommand);
+        } else {
+            #[cfg(not(feature = "no-cfi"))]
+            cfi_assert!(dpe.support.hash_stream());
+        }
+
+        let stream = get_stream(dpe, self.stream_id, locality)?;
+        let state = stream.hash_state();
+        stream.zeroize();
+        let digest = env
+            .crypto
+            .hash_state_finish(DPE_PROFILE.alg_len(), &state)?;
+
+        Ok(Response::HashFinal(HashFinalResp {
+            resp_hdr: ResponseHdr::new(DpeErrorCode::NoError),
+            digest: digest
+                .bytes()
+                .try_into()
+                .map_err(|_| DpeErrorCode::InternalError)?,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        commands::{Command, CommandHdr},
+        dpe_instance::tests::{TestTypes, TEST_LOCALITIES},
+        hash_stream::{MAX_HASH_STREAMS, MAX_HASH_STREAMS_PER_LOCALITY},
+        support::{test::SUPPORT, Support},
+    };
+    use caliptra_cfi_lib_git::CfiCounter;
+    use platform::default::DefaultPlatform;
+    use zerocopy::AsBytes;
+
+    const TEST_HASH_UPDATE_CMD: HashUpdateCmd = HashUpdateCmd {
+        stream_id: 1,
+        size: 4,
+    };
+
+    const TEST_HASH_FINAL_CMD: HashFinalCmd = HashFinalCmd { stream_id: 1 };
+
+    fn new_env() -> DpeEnv<'static, TestTypes> {
+        DpeEnv::<TestTypes> {
+            crypto: crypto::OpensslCrypto::new(),
+            platform: DefaultPlatform,
+            arena: &mut [],
+        }
+    }
+
+    fn initialize(dpe: &mut DpeInstance, env: &mut DpeEnv<TestTypes>, locality: u32) -> u32 {
+        match HashInitializeCmd.execute(dpe, env, locality).unwrap() {
+            Response::HashInitialize(resp) => resp.stream_id,
+            _ => panic!("Incorrect response type"),
+        }
+    }
+
+    #[test]
+    fn test_deserialize_hash_stream() {
+        CfiCounter::reset_for_test();
+        let command = CommandHdr::new_for_test(Command::HASH_INITIALIZE)
+            .as_bytes()
+            .to_vec();
+        assert_eq!(
+            Ok(Command::HashInitialize(HashInitializeCmd)),
+            Command::deserialize(&command)
+        );
+
+        let mut command = CommandHdr::new_for_test(Command::HASH_UPDATE)
+            .as_bytes()
+            .to_vec();
+        command.extend(TEST_HASH_UPDATE_CMD.as_bytes());
+        command.extend(b"data");
+        assert_eq!(
+            Ok(Command::HashUpdate(TEST_HASH_UPDATE_CMD, b"data")),
+            Command::deserialize(&command)
+        );
+
+        // The data must be as long as the command says.
+        command.pop();
+        assert_eq!(
+            Err(DpeErrorCode::InvalidArgument),
+            Command::deserialize(&command)
+        );
+
+        let mut command = CommandHdr::new_for_test(Command::HASH_FINAL)
+            .as_bytes()
+            .to_vec();
+        command.extend(TEST_HASH_FINAL_CMD.as_bytes());
+        assert_eq!(
+            Ok(Command::HashFinal(TEST_HASH_FINAL_CMD)),
+            Command::deserialize(&command)
+        );
+    }
+
+    #[test]
+    fn test_hash_stream() {
+        CfiCounter::reset_for_test();
+        let mut env = new_env();
+
+        // Make sure the commands are rejected if unsupported.
+        let mut dpe = DpeInstance::new(&mut env, SUPPORT).unwrap();
+        assert_eq!(
+            Err(DpeErrorCode::InvalidCommand),
+            HashInitializeCmd.execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
+        );
+        assert_eq!(
+            Err(DpeErrorCode::InvalidCommand),
+            TEST_HASH_UPDATE_CMD.execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
+        );
+        assert_eq!(
+            Err(DpeErrorCode::InvalidCommand),
+            TEST_HASH_FINAL_CMD.execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
+        );
+
+        let mut dpe = DpeInstance::new(&mut env, SUPPORT | Support::HASH_STREAM).unwrap();
+        let data: Vec<u8> = (0..5000u32).map(|i| i as u8).collect();
+
+        let stream_id = initialize(&mut dpe, &mut env, TEST_LOCALITIES[0]);
+        for chunk in data.chunks(333) {
+            let mut serialized = CommandHdr::new_for_test(Command::HASH_UPDATE)
+                .as_bytes()
+                .to_vec();
+            serialized.extend(
+                HashUpdateCmd {
+                    stream_id,
+                    size: chunk.len() as u32,
+                }
+                .as_bytes(),
+            );
+            serialized.extend(chunk);
+            assert_eq!(
+                Ok(Response::HashUpdate(ResponseHdr::new(
+                    DpeErrorCode::NoError
+                ))),
+                dpe.execute_serialized_command(&mut env, TEST_LOCALITIES[0], &serialized)
+            );
+        }
+
+        // Other localities can't use the stream.
+        assert_eq!(
+            Err(DpeErrorCode::InvalidLocality),
+            HashFinalCmd { stream_id }.execute(&mut dpe, &mut env, TEST_LOCALITIES[1])
+        );
+
+        let resp = match (HashFinalCmd { stream_id })
+            .execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
+            .unwrap()
+        {
+            Response::HashFinal(resp) => resp,
+            _ => panic!("Incorrect response type"),
+        };
+        assert_eq!(
+            env.crypto
+                .hash(DPE_PROFILE.alg_len(), &data)
+                .unwrap()
+                .bytes(),
+            resp.digest
+        );
+
+        // The stream is gone once it is finished.
+        assert_eq!(
+            Err(DpeErrorCode::InvalidArgument),
+            HashFinalCmd { stream_id }.execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
+        );
+    }
+
+    #[test]
+    fn test_max_hash_streams() {
+        CfiCounter::reset_for_test();
+        let mut env = new_env();
+        let mut dpe = DpeInstance::new(&mut env, SUPPORT | Support::HASH_STREAM).unwrap();
+
+        // Each locality can only take its share of the streams.
+        for i in 0..MAX_HASH_STREAMS {
+            let locality = TEST_LOCALITIES[i / MAX_HASH_STREAMS_PER_LOCALITY];
+            initialize(&mut dpe, &mut env, locality);
+            if (i + 1) % MAX_HASH_STREAMS_PER_LOCALITY == 0 {
+                assert_eq!(
+                    Err(DpeErrorCode::MaxHashStreams),
+                    HashInitializeCmd.execute(&mut dpe, &mut env, locality)
+                );
+            }
+        }
+
+        // Finishing a stream frees its slot.
+        HashFinalCmd { stream_id: 0 }
+            .execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
+            .unwrap();
+        assert_eq!(0, initialize(&mut dpe, &mut env, TEST_LOCALITIES[0]));
+    }
+}
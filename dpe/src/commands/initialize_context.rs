@@ -1,9 +1,10 @@
 // Licensed under the Apache-2.0 license.
-use super::CommandExecution;
+use super::{write_device_id_cert, CommandExecution};
 use crate::{
     context::{ActiveContextArgs, Context, ContextHandle, ContextType},
     dpe_instance::{DpeEnv, DpeInstance, DpeTypes},
     response::{DpeErrorCode, NewHandleResp, Response, ResponseHdr},
+    DPE_PROFILE,
 };
 use bitflags::bitflags;
 #[cfg(not(feature = "no-cfi"))]
@@ -11,18 +12,29 @@ use caliptra_cfi_derive_git::cfi_impl_fn;
 #[cfg(not(feature = "no-cfi"))]
 use caliptra_cfi_lib_git::{cfi_assert, cfi_assert_eq};
 use cfg_if::cfg_if;
+use platform::LocalityDescriptor;
 
+/// Initializes the default context or a simulation context.
+///
+/// When `InitCtxCmd::SEED_FLAG_MASK` is set, the command is followed by a
+/// seed the size of a digest. The seed stands in for the parent of the new
+/// simulation context when its chain digest is computed, so every key derived
+/// from the context or its children depends only on the seed and the TCIs.
 #[repr(C)]
 #[derive(Debug, PartialEq, Eq, zerocopy::FromBytes, zerocopy::AsBytes)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct InitCtxCmd(u32);
 
 bitflags! {
     impl InitCtxCmd: u32 {
         const SIMULATION_FLAG_MASK = 1u32 << 31;
         const DEFAULT_FLAG_MASK = 1u32 << 30;
+        const SEED_FLAG_MASK = 1u32 << 29;
     }
 }
 
+impl_wire_endian!(flags InitCtxCmd);
+
 impl InitCtxCmd {
     pub const fn new_use_default() -> InitCtxCmd {
         Self::DEFAULT_FLAG_MASK
@@ -39,6 +51,38 @@ impl InitCtxCmd {
     pub const fn new_simulation() -> InitCtxCmd {
         Self::SIMULATION_FLAG_MASK
     }
+
+    /// Initializes a simulation context whose keys are derived from a seed
+    /// sent after the command.
+    pub const fn new_seeded_simulation() -> InitCtxCmd {
+        Self::SIMULATION_FLAG_MASK.union(Self::SEED_FLAG_MASK)
+    }
+
+    const fn has_seed(&self) -> bool {
+        self.contains(Self::SEED_FLAG_MASK)
+    }
+
+    /// Returns the seed carried in `trailer`, the bytes that follow the
+    /// command, or `None` if the command doesn't set
+    /// `InitCtxCmd::SEED_FLAG_MASK`.
+    ///
+    /// # Arguments
+    ///
+    /// * `trailer` - Serialized bytes following the command
+    pub(crate) fn seed(
+        &self,
+        trailer: &[u8],
+    ) -> Result<Option<[u8; DPE_PROFILE.get_hash_size()]>, DpeErrorCode> {
+        if !self.has_seed() {
+            return Ok(None);
+        }
+        let seed = trailer
+            .get(..DPE_PROFILE.get_hash_size())
+            .ok_or(DpeErrorCode::InvalidArgument)?;
+        Ok(Some(
+            seed.try_into().map_err(|_| DpeErrorCode::InvalidArgument)?,
+        ))
+    }
 }
 
 impl CommandExecution for InitCtxCmd {
@@ -49,17 +93,50 @@ impl CommandExecution for InitCtxCmd {
         env: &mut DpeEnv<impl DpeTypes>,
         locality: u32,
     ) -> Result<Response, DpeErrorCode> {
+        self.execute_with_seed(dpe, env, locality, None)
+    }
+}
+
+impl InitCtxCmd {
+    /// Executes the command, deriving the new simulation context from `seed`
+    /// if `InitCtxCmd::SEED_FLAG_MASK` is set.
+    ///
+    /// # Arguments
+    ///
+    /// * `dpe` - DPE instance
+    /// * `env` - DPE environment containing Crypto and Platform implementations
+    /// * `locality` - which hardware locality is making the request
+    /// * `seed` - seed that followed the command
+    #[cfg_attr(not(feature = "no-cfi"), cfi_impl_fn)]
+    pub(crate) fn execute_with_seed(
+        &self,
+        dpe: &mut DpeInstance,
+        env: &mut DpeEnv<impl DpeTypes>,
+        locality: u32,
+        seed: Option<&[u8; DPE_PROFILE.get_hash_size()]>,
+    ) -> Result<Response, DpeErrorCode> {
+        dpe_trace!(
+            "InitializeContext flags={:#x} seeded={} locality={:#x}",
+            self.bits(),
+            seed.is_some(),
+            locality
+        );
+
         // This function can only be called once for non-simulation contexts.
         if (self.flag_is_default() && dpe.has_initialized())
             || (self.flag_is_simulation() && !dpe.support.simulation())
+            || (self.has_seed() && !dpe.support.simulation_seed())
         {
             return Err(DpeErrorCode::ArgumentNotSupported);
         }
 
         // A flag must be set, but it can't be both flags. The base DPE CDI is locked for
         // non-simulation contexts once it is used once to prevent later software from accessing the
-        // CDI.
-        if !(self.flag_is_default() ^ self.flag_is_simulation()) {
+        // CDI. Only simulation contexts can be seeded, and a seed must follow the flag.
+        if !(self.flag_is_default() ^ self.flag_is_simulation())
+            || (self.has_seed() && !self.flag_is_simulation())
+            || self.has_seed() != seed.is_some()
+        {
             return Err(DpeErrorCode::InvalidArgument);
         }
 
@@ -67,22 +144,46 @@ impl CommandExecution for InitCtxCmd {
             if #[cfg(not(feature = "no-cfi"))] {
                 cfi_assert!(!self.flag_is_default() || !dpe.has_initialized());
                 cfi_assert!(!self.flag_is_simulation() || dpe.support.simulation());
+                cfi_assert!(!self.has_seed() || dpe.support.simulation_seed());
                 cfi_assert!(self.flag_is_default() ^ self.flag_is_simulation());
+                cfi_assert!(!self.has_seed() || self.flag_is_simulation());
+                cfi_assert!(self.has_seed() == seed.is_some());
             }
         }
 
+        // Simulation contexts are capped separately so they can't use up the whole context table.
+        if self.flag_is_simulation()
+            && dpe.count_simulation_contexts() >= dpe.max_simulation_contexts() as usize
+        {
+            return Err(DpeErrorCode::MaxTcis);
+        } else {
+            #[cfg(not(feature = "no-cfi"))]
+            cfi_assert!(
+                !self.flag_is_simulation()
+                    || dpe.count_simulation_contexts() < dpe.max_simulation_contexts() as usize
+            );
+        }
+
+        if self.flag_is_simulation() {
+            dpe.localities
+                .check(locality, LocalityDescriptor::SIMULATION)?;
+        }
+
         let idx = dpe
             .get_next_inactive_context_pos()
             .ok_or(DpeErrorCode::MaxTcis)?;
         let (context_type, handle) = if self.flag_is_default() {
-            dpe.has_initialized = true.into();
             (ContextType::Normal, ContextHandle::default())
         } else {
             // Simulation.
-            (ContextType::Simulation, dpe.generate_new_handle(env)?)
+            (
+                ContextType::Simulation,
+                dpe.generate_new_handle(env, locality)?,
+            )
         };
 
-        dpe.contexts[idx].activate(&ActiveContextArgs {
+        let mut context = Context::new();
+        context.activate(&ActiveContextArgs {
             context_type,
             locality,
             handle: &handle,
@@ -93,6 +194,23 @@ impl CommandExecution for InitCtxCmd {
             uses_internal_input_info: false,
             uses_internal_input_dice: false,
         });
+        if let Some(seed) = seed {
+            context.seed = *seed;
+        }
+        context.chain_digest = dpe.compute_chain_digest(env, &context)?;
+        dpe.contexts[idx] = context;
+        if self.flag_is_default() {
+            dpe.has_initialized = true.into();
+        }
+
+        if self.flag_is_default() && dpe.support.device_id_cert() {
+            if let Err(err) = write_device_id_cert(dpe, env, idx) {
+                // Leave DPE uninitialized so that initialization can be retried.
+                dpe.contexts[idx].destroy();
+                dpe.has_initialized = false.into();
+                return Err(err);
+            }
+        }
         Ok(Response::InitCtx(NewHandleResp {
             handle,
             resp_hdr: ResponseHdr::new(DpeErrorCode::NoError),
@@ -104,10 +222,11 @@ impl CommandExecution for InitCtxCmd {
 mod tests {
     use super::*;
     use crate::{
-        commands::{Command, CommandHdr},
+        commands::{CertifyKeyCmd, CertifyKeyFlags, Command, CommandHdr, DestroyCtxCmd},
         context::ContextState,
         dpe_instance::tests::{TestTypes, TEST_LOCALITIES},
         support::Support,
+        MAX_HANDLES,
     };
     use caliptra_cfi_lib_git::CfiCounter;
     use crypto::OpensslCrypto;
@@ -124,7 +243,30 @@ mod tests {
             .to_vec();
         command.extend(TEST_INIT_CTX_CMD.as_bytes());
         assert_eq!(
-            Ok(Command::InitCtx(TEST_INIT_CTX_CMD)),
+            Ok(Command::InitCtx(TEST_INIT_CTX_CMD, None)),
+            Command::deserialize(&command)
+        );
+    }
+
+    #[test]
+    fn test_deserialize_init_ctx_with_seed() {
+        CfiCounter::reset_for_test();
+        let mut command = CommandHdr::new_for_test(Command::INITIALIZE_CONTEXT)
+            .as_bytes()
+            .to_vec();
+        command.extend(InitCtxCmd::new_seeded_simulation().as_bytes());
+        assert_eq!(
+            Err(DpeErrorCode::InvalidArgument),
+            Command::deserialize(&command)
+        );
+
+        let seed = [0x5a; DPE_PROFILE.get_hash_size()];
+        command.extend(seed);
+        assert_eq!(
+            Ok(Command::InitCtx(
+                InitCtxCmd::new_seeded_simulation(),
+                Some(seed)
+            )),
             Command::deserialize(&command)
         );
     }
@@ -135,6 +277,7 @@ mod tests {
         let mut env = DpeEnv::<TestTypes> {
             crypto: OpensslCrypto::new(),
             platform: DefaultPlatform,
+            arena: &mut [],
         };
         let mut dpe = DpeInstance::new(&mut env, Support::default()).unwrap();
 
@@ -190,4 +333,157 @@ mod tests {
             InitCtxCmd::new_simulation().execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
         );
     }
+
+    #[test]
+    fn test_max_simulation_contexts() {
+        CfiCounter::reset_for_test();
+        let mut env = DpeEnv::<TestTypes> {
+            crypto: OpensslCrypto::new(),
+            platform: DefaultPlatform,
+            arena: &mut [],
+        };
+        let mut dpe = DpeInstance::new(&mut env, Support::SIMULATION).unwrap();
+        assert_eq!(MAX_HANDLES as u32, dpe.max_simulation_contexts());
+
+        assert_eq!(
+            Err(DpeErrorCode::InvalidArgument),
+            dpe.set_max_simulation_contexts(MAX_HANDLES as u32 + 1)
+        );
+        dpe.set_max_simulation_contexts(2).unwrap();
+        assert_eq!(
+            2,
+            dpe.get_profile(&mut env.platform)
+                .unwrap()
+                .max_simulation_contexts
+        );
+
+        let mut handles = [ContextHandle::default(); 2];
+        for handle in handles.iter_mut() {
+            *handle = match InitCtxCmd::new_simulation()
+                .execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
+                .unwrap()
+            {
+                Response::InitCtx(resp) => resp.handle,
+                _ => panic!("Wrong response type."),
+            };
+        }
+
+        // The cap is reached even though the context table has room.
+        assert_eq!(
+            Err(DpeErrorCode::MaxTcis),
+            InitCtxCmd::new_simulation().execute(&mut dpe, &mut env, TEST_LOCALITIES[1])
+        );
+
+        // The default context doesn't count against the cap.
+        InitCtxCmd::new_use_default()
+            .execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
+            .unwrap();
+
+        // Destroying a simulation context makes room for another.
+        DestroyCtxCmd { handle: handles[0] }
+            .execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
+            .unwrap();
+        assert!(InitCtxCmd::new_simulation()
+            .execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
+            .is_ok());
+    }
+
+    /// Initializes a simulation context in a new DPE instance, seeded with
+    /// `seed` if given, and returns the public key it certifies.
+    fn simulation_pub_key(seed: Option<&[u8; DPE_PROFILE.get_hash_size()]>) -> Vec<u8> {
+        let mut env = DpeEnv::<TestTypes> {
+            crypto: OpensslCrypto::new(),
+            platform: DefaultPlatform,
+            arena: &mut [],
+        };
+        let mut dpe = DpeInstance::new(
+            &mut env,
+            Support::SIMULATION | Support::SIMULATION_SEED | Support::X509,
+        )
+        .unwrap();
+        let cmd = if seed.is_some() {
+            InitCtxCmd::new_seeded_simulation()
+        } else {
+            InitCtxCmd::new_simulation()
+        };
+        let handle = match cmd
+            .execute_with_seed(&mut dpe, &mut env, TEST_LOCALITIES[0], seed)
+            .unwrap()
+        {
+            Response::InitCtx(resp) => resp.handle,
+            _ => panic!("Wrong response type."),
+        };
+        match (CertifyKeyCmd {
+            handle,
+            flags: CertifyKeyFlags::empty(),
+            label: [0; DPE_PROFILE.get_hash_size()],
+            format: CertifyKeyCmd::FORMAT_X509,
+        })
+        .execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
+        .unwrap()
+        {
+            Response::CertifyKey(resp) => resp.derived_pubkey_x.to_vec(),
+            _ => panic!("Wrong response type."),
+        }
+    }
+
+    #[test]
+    fn test_simulation_seed() {
+        CfiCounter::reset_for_test();
+        let seed = [0x11; DPE_PROFILE.get_hash_size()];
+        let other_seed = [0x22; DPE_PROFILE.get_hash_size()];
+
+        // The same seed derives the same keys in separate instances.
+        let seeded = simulation_pub_key(Some(&seed));
+        assert_eq!(seeded, simulation_pub_key(Some(&seed)));
+        assert_ne!(seeded, simulation_pub_key(Some(&other_seed)));
+        assert_ne!(seeded, simulation_pub_key(None));
+
+        let mut env = DpeEnv::<TestTypes> {
+            crypto: OpensslCrypto::new(),
+            platform: DefaultPlatform,
+            arena: &mut [],
+        };
+
+        // Seeds need their own support flag.
+        let mut dpe = DpeInstance::new(&mut env, Support::SIMULATION).unwrap();
+        assert_eq!(
+            Err(DpeErrorCode::ArgumentNotSupported),
+            InitCtxCmd::new_seeded_simulation().execute_with_seed(
+                &mut dpe,
+                &mut env,
+                TEST_LOCALITIES[0],
+                Some(&seed)
+            )
+        );
+
+        let mut dpe =
+            DpeInstance::new(&mut env, Support::SIMULATION | Support::SIMULATION_SEED).unwrap();
+
+        // Only simulation contexts can be seeded.
+        assert_eq!(
+            Err(DpeErrorCode::InvalidArgument),
+            (InitCtxCmd::DEFAULT_FLAG_MASK | InitCtxCmd::SEED_FLAG_MASK).execute_with_seed(
+                &mut dpe,
+                &mut env,
+                TEST_LOCALITIES[0],
+                Some(&seed)
+            )
+        );
+
+        // The seed flag and the seed must come together.
+        assert_eq!(
+            Err(DpeErrorCode::InvalidArgument),
+            InitCtxCmd::new_seeded_simulation().execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
+        );
+        assert_eq!(
+            Err(DpeErrorCode::InvalidArgument),
+            InitCtxCmd::new_simulation().execute_with_seed(
+                &mut dpe,
+                &mut env,
+                TEST_LOCALITIES[0],
+                Some(&seed)
+            )
+        );
+    }
 }
@@ -4,99 +4,329 @@ Licensed under the Apache-2.0 license.
 Abstract:
     DPE Commands and deserialization.
 --*/
+pub use self::attest::AttestCmd;
 pub use self::derive_context::{DeriveContextCmd, DeriveContextFlags};
 pub use self::destroy_context::DestroyCtxCmd;
+pub use self::export_cdi::ExportCdiCmd;
+pub use self::extend_tci::ExtendTciCmd;
 pub use self::get_certificate_chain::GetCertificateChainCmd;
+pub use self::get_command_counter::GetCommandCounterCmd;
+pub use self::get_context_info::GetContextInfoCmd;
+pub use self::hash_stream::{HashFinalCmd, HashInitializeCmd, HashUpdateCmd};
 pub use self::initialize_context::InitCtxCmd;
 
-pub use self::certify_key::{CertifyKeyCmd, CertifyKeyFlags};
+pub(crate) use self::certify_key::{write_device_id_cert, DeviceIdCert, PendingCert};
+pub use self::certify_key::{CertifyKeyCmd, CertifyKeyFlags, GetCertificateChunkCmd};
+pub use self::session::{CloseSessionCmd, OpenSessionCmd};
 
 pub use self::rotate_context::{RotateCtxCmd, RotateCtxFlags};
+pub use self::seal::{SealCmd, SealFlags, UnsealCmd};
 pub use self::sign::{SignCmd, SignFlags};
+pub use self::tag_tci::{GetTaggedTciCmd, TagTciCmd};
+pub use self::vendor::{NoVendorCommands, VendorCommandHandler};
 
 use crate::{
+    context::ContextHandle,
     dpe_instance::{DpeEnv, DpeInstance, DpeTypes},
     response::{DpeErrorCode, Response},
+    wire::WireEndian,
     DPE_PROFILE,
 };
 use core::mem::size_of;
 use zerocopy::FromBytes;
 
+mod attest;
 mod certify_key;
 mod derive_context;
 mod destroy_context;
+mod export_cdi;
+mod extend_tci;
 mod get_certificate_chain;
+mod get_command_counter;
+mod get_context_info;
+mod hash_stream;
 mod initialize_context;
 mod rotate_context;
+mod seal;
+mod session;
 mod sign;
+mod tag_tci;
+mod vendor;
 
 #[derive(Debug, PartialEq, Eq)]
-pub enum Command {
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum Command<'a> {
     GetProfile,
-    InitCtx(InitCtxCmd),
-    DeriveContext(DeriveContextCmd),
+    /// The seed follows the command when it sets
+    /// `InitCtxCmd::SEED_FLAG_MASK`.
+    InitCtx(
+        InitCtxCmd,
+        #[cfg_attr(
+            feature = "serde",
+            serde(serialize_with = "crate::serde_hex::serialize_option")
+        )]
+        Option<[u8; DPE_PROFILE.get_hash_size()]>,
+    ),
+    /// The SVN follows the command when it sets
+    /// `DeriveContextFlags::INPUT_SVN`.
+    DeriveContext(DeriveContextCmd, Option<u32>),
     CertifyKey(CertifyKeyCmd),
-    Sign(SignCmd),
+    /// The slice holds the message to hash when the command sets
+    /// `SignFlags::IS_MESSAGE` and is empty otherwise.
+    Sign(
+        SignCmd,
+        #[cfg_attr(
+            feature = "serde",
+            serde(serialize_with = "crate::serde_hex::serialize")
+        )]
+        &'a [u8],
+    ),
     RotateCtx(RotateCtxCmd),
     DestroyCtx(DestroyCtxCmd),
     GetCertificateChain(GetCertificateChainCmd),
+    ExtendTci(ExtendTciCmd),
+    TagTci(TagTciCmd),
+    GetTaggedTci(GetTaggedTciCmd),
+    OpenSession(OpenSessionCmd),
+    CloseSession(CloseSessionCmd),
+    GetCertificateChunk(GetCertificateChunkCmd),
+    ExportCdi(ExportCdiCmd),
+    GetCommandCounter(GetCommandCounterCmd),
+    HashInitialize(HashInitializeCmd),
+    /// The slice holds the data to add to the hash.
+    HashUpdate(
+        HashUpdateCmd,
+        #[cfg_attr(
+            feature = "serde",
+            serde(serialize_with = "crate::serde_hex::serialize")
+        )]
+        &'a [u8],
+    ),
+    HashFinal(HashFinalCmd),
+    GetContextInfo(GetContextInfoCmd),
+    Attest(AttestCmd),
+    /// The slice holds the data to seal.
+    Seal(
+        SealCmd,
+        #[cfg_attr(
+            feature = "serde",
+            serde(serialize_with = "crate::serde_hex::serialize")
+        )]
+        &'a [u8],
+    ),
+    /// The slice holds the sealed data.
+    Unseal(
+        UnsealCmd,
+        #[cfg_attr(
+            feature = "serde",
+            serde(serialize_with = "crate::serde_hex::serialize")
+        )]
+        &'a [u8],
+    ),
+    /// A command in the vendor range, with the bytes that follow its header.
+    Vendor(
+        u32,
+        #[cfg_attr(
+            feature = "serde",
+            serde(serialize_with = "crate::serde_hex::serialize")
+        )]
+        &'a [u8],
+    ),
 }
 
-impl Command {
+impl<'a> Command<'a> {
     pub const GET_PROFILE: u32 = 0x01;
+    pub const OPEN_SESSION: u32 = 0x02;
+    pub const CLOSE_SESSION: u32 = 0x03;
     pub const INITIALIZE_CONTEXT: u32 = 0x07;
     pub const DERIVE_CONTEXT: u32 = 0x08;
     pub const CERTIFY_KEY: u32 = 0x09;
     pub const SIGN: u32 = 0x0A;
+    pub const SEAL: u32 = 0x0B;
+    pub const UNSEAL: u32 = 0x0C;
     pub const ROTATE_CONTEXT_HANDLE: u32 = 0x0e;
     pub const DESTROY_CONTEXT: u32 = 0x0f;
     pub const GET_CERTIFICATE_CHAIN: u32 = 0x10;
+    pub const EXTEND_TCI: u32 = 0x11;
+    pub const TAG_TCI: u32 = 0x12;
+    pub const GET_TAGGED_TCI: u32 = 0x13;
+    pub const GET_CERTIFICATE_CHUNK: u32 = 0x16;
+    pub const EXPORT_CDI: u32 = 0x17;
+    pub const GET_COMMAND_COUNTER: u32 = 0x18;
+    pub const HASH_INITIALIZE: u32 = 0x19;
+    pub const HASH_UPDATE: u32 = 0x1A;
+    pub const HASH_FINAL: u32 = 0x1B;
+    pub const GET_CONTEXT_INFO: u32 = 0x1C;
+    pub const ATTEST: u32 = 0x1D;
+
+    /// First command ID reserved for integrators. Commands at or above it are
+    /// handed to a `VendorCommandHandler`.
+    pub const VENDOR_COMMAND_BASE: u32 = 0x8000_0000;
 
     /// Returns the command with its parameters given a slice of bytes.
     ///
     /// # Arguments
     ///
     /// * `bytes` - serialized command
-    pub fn deserialize(bytes: &[u8]) -> Result<Command, DpeErrorCode> {
-        let header = CommandHdr::try_from(bytes)?;
-        let bytes = &bytes[size_of::<CommandHdr>()..];
+    pub fn deserialize(bytes: &'a [u8]) -> Result<Command<'a>, DpeErrorCode> {
+        let (header, bytes) =
+            read_prefix::<CommandHdr>(bytes).ok_or(DpeErrorCode::InvalidCommand)?;
+        header.validate()?;
 
         match header.cmd_id {
             Command::GET_PROFILE => Ok(Command::GetProfile),
-            Command::INITIALIZE_CONTEXT => Self::parse_command(Command::InitCtx, bytes),
-            Command::DERIVE_CONTEXT => Self::parse_command(Command::DeriveContext, bytes),
+            Command::INITIALIZE_CONTEXT => Self::parse_init_ctx(bytes),
+            Command::DERIVE_CONTEXT => Self::parse_derive_context(bytes),
             Command::CERTIFY_KEY => Self::parse_command(Command::CertifyKey, bytes),
-            Command::SIGN => Self::parse_command(Command::Sign, bytes),
+            Command::SIGN => Self::parse_sign(bytes),
             Command::ROTATE_CONTEXT_HANDLE => Self::parse_command(Command::RotateCtx, bytes),
             Command::DESTROY_CONTEXT => Self::parse_command(Command::DestroyCtx, bytes),
             Command::GET_CERTIFICATE_CHAIN => {
                 Self::parse_command(Command::GetCertificateChain, bytes)
             }
+            Command::EXTEND_TCI => Self::parse_command(Command::ExtendTci, bytes),
+            Command::TAG_TCI => Self::parse_command(Command::TagTci, bytes),
+            Command::GET_TAGGED_TCI => Self::parse_command(Command::GetTaggedTci, bytes),
+            Command::OPEN_SESSION => Self::parse_command(Command::OpenSession, bytes),
+            Command::CLOSE_SESSION => Self::parse_command(Command::CloseSession, bytes),
+            Command::GET_CERTIFICATE_CHUNK => {
+                Self::parse_command(Command::GetCertificateChunk, bytes)
+            }
+            Command::EXPORT_CDI => Self::parse_command(Command::ExportCdi, bytes),
+            Command::GET_COMMAND_COUNTER => Self::parse_command(Command::GetCommandCounter, bytes),
+            Command::HASH_INITIALIZE => Ok(Command::HashInitialize(HashInitializeCmd)),
+            Command::HASH_UPDATE => Self::parse_hash_update(bytes),
+            Command::HASH_FINAL => Self::parse_command(Command::HashFinal, bytes),
+            Command::GET_CONTEXT_INFO => Self::parse_command(Command::GetContextInfo, bytes),
+            Command::ATTEST => Self::parse_command(Command::Attest, bytes),
+            Command::SEAL => Self::parse_seal(bytes),
+            Command::UNSEAL => Self::parse_unseal(bytes),
+            id if id >= Command::VENDOR_COMMAND_BASE => Ok(Command::Vendor(id, bytes)),
             _ => Err(DpeErrorCode::InvalidCommand),
         }
     }
 
-    fn parse_command<T: FromBytes>(
-        build: impl FnOnce(T) -> Command,
+    /// Returns the handle of the context the command operates on, if any.
+    pub fn handle(&self) -> Option<&ContextHandle> {
+        match self {
+            Command::DeriveContext(cmd, _) => Some(&cmd.handle),
+            Command::CertifyKey(cmd) => Some(&cmd.handle),
+            Command::Sign(cmd, _) => Some(&cmd.handle),
+            Command::RotateCtx(cmd) => Some(&cmd.handle),
+            Command::DestroyCtx(cmd) => Some(&cmd.handle),
+            Command::ExtendTci(cmd) => Some(&cmd.handle),
+            Command::TagTci(cmd) => Some(&cmd.handle),
+            Command::ExportCdi(cmd) => Some(&cmd.handle),
+            Command::GetCommandCounter(cmd) => Some(&cmd.handle),
+            Command::GetContextInfo(cmd) => Some(&cmd.handle),
+            Command::Attest(cmd) => Some(&cmd.handle),
+            Command::Seal(cmd, _) => Some(&cmd.handle),
+            Command::Unseal(cmd, _) => Some(&cmd.handle),
+            Command::GetProfile
+            | Command::InitCtx(..)
+            | Command::GetCertificateChain(_)
+            | Command::GetTaggedTci(_)
+            | Command::OpenSession(_)
+            | Command::CloseSession(_)
+            | Command::GetCertificateChunk(_)
+            | Command::HashInitialize(_)
+            | Command::HashUpdate(..)
+            | Command::HashFinal(_)
+            | Command::Vendor(..) => None,
+        }
+    }
+
+    fn parse_command<T: FromBytes + WireEndian>(
+        build: impl FnOnce(T) -> Command<'a>,
         bytes: &[u8],
-    ) -> Result<Command, DpeErrorCode> {
-        Ok(build(
-            T::read_from_prefix(bytes).ok_or(DpeErrorCode::InvalidArgument)?,
-        ))
+    ) -> Result<Command<'a>, DpeErrorCode> {
+        let (cmd, _) = read_prefix(bytes).ok_or(DpeErrorCode::InvalidArgument)?;
+        Ok(build(cmd))
+    }
+
+    fn parse_init_ctx(bytes: &'a [u8]) -> Result<Command<'a>, DpeErrorCode> {
+        let (cmd, trailer) =
+            read_prefix::<InitCtxCmd>(bytes).ok_or(DpeErrorCode::InvalidArgument)?;
+        let seed = cmd.seed(trailer)?;
+        Ok(Command::InitCtx(cmd, seed))
+    }
+
+    fn parse_derive_context(bytes: &'a [u8]) -> Result<Command<'a>, DpeErrorCode> {
+        let (cmd, trailer) =
+            read_prefix::<DeriveContextCmd>(bytes).ok_or(DpeErrorCode::InvalidArgument)?;
+        let svn = cmd.svn(trailer)?;
+        Ok(Command::DeriveContext(cmd, svn))
+    }
+
+    fn parse_sign(bytes: &'a [u8]) -> Result<Command<'a>, DpeErrorCode> {
+        let (cmd, trailer) = read_prefix::<SignCmd>(bytes).ok_or(DpeErrorCode::InvalidArgument)?;
+        let message = cmd.message(trailer)?;
+        Ok(Command::Sign(cmd, message))
+    }
+
+    fn parse_hash_update(bytes: &'a [u8]) -> Result<Command<'a>, DpeErrorCode> {
+        let (cmd, trailer) =
+            read_prefix::<HashUpdateCmd>(bytes).ok_or(DpeErrorCode::InvalidArgument)?;
+        let data = cmd.data(trailer)?;
+        Ok(Command::HashUpdate(cmd, data))
+    }
+
+    fn parse_seal(bytes: &'a [u8]) -> Result<Command<'a>, DpeErrorCode> {
+        let (cmd, trailer) = read_prefix::<SealCmd>(bytes).ok_or(DpeErrorCode::InvalidArgument)?;
+        let data = cmd.data(trailer)?;
+        Ok(Command::Seal(cmd, data))
     }
+
+    fn parse_unseal(bytes: &'a [u8]) -> Result<Command<'a>, DpeErrorCode> {
+        let (cmd, trailer) =
+            read_prefix::<UnsealCmd>(bytes).ok_or(DpeErrorCode::InvalidArgument)?;
+        let sealed = cmd.data(trailer)?;
+        Ok(Command::Unseal(cmd, sealed))
+    }
+}
+
+/// Reads a `T` from the front of `bytes` and returns it in host byte order
+/// along with the bytes that follow it, or `None` if `bytes` is too short.
+///
+/// The value is copied out, so `bytes` doesn't need to be aligned for `T`.
+pub(crate) fn read_prefix<T: FromBytes + WireEndian>(bytes: &[u8]) -> Option<(T, &[u8])> {
+    let rest = bytes.get(size_of::<T>()..)?;
+    Some((T::from_le(T::read_from_prefix(bytes)?), rest))
 }
 
-impl From<Command> for u32 {
+impl From<Command<'_>> for u32 {
     fn from(cmd: Command) -> u32 {
+        u32::from(&cmd)
+    }
+}
+
+impl From<&Command<'_>> for u32 {
+    fn from(cmd: &Command) -> u32 {
         match cmd {
             Command::GetProfile => Command::GET_PROFILE,
-            Command::InitCtx(_) => Command::INITIALIZE_CONTEXT,
-            Command::DeriveContext(_) => Command::DERIVE_CONTEXT,
+            Command::InitCtx(..) => Command::INITIALIZE_CONTEXT,
+            Command::DeriveContext(..) => Command::DERIVE_CONTEXT,
             Command::CertifyKey(_) => Command::CERTIFY_KEY,
-            Command::Sign(_) => Command::SIGN,
+            Command::Sign(..) => Command::SIGN,
             Command::RotateCtx(_) => Command::ROTATE_CONTEXT_HANDLE,
             Command::DestroyCtx(_) => Command::DESTROY_CONTEXT,
             Command::GetCertificateChain(_) => Command::GET_CERTIFICATE_CHAIN,
+            Command::ExtendTci(_) => Command::EXTEND_TCI,
+            Command::TagTci(_) => Command::TAG_TCI,
+            Command::GetTaggedTci(_) => Command::GET_TAGGED_TCI,
+            Command::OpenSession(_) => Command::OPEN_SESSION,
+            Command::CloseSession(_) => Command::CLOSE_SESSION,
+            Command::GetCertificateChunk(_) => Command::GET_CERTIFICATE_CHUNK,
+            Command::ExportCdi(_) => Command::EXPORT_CDI,
+            Command::GetCommandCounter(_) => Command::GET_COMMAND_COUNTER,
+            Command::HashInitialize(_) => Command::HASH_INITIALIZE,
+            Command::HashUpdate(..) => Command::HASH_UPDATE,
+            Command::HashFinal(_) => Command::HASH_FINAL,
+            Command::GetContextInfo(_) => Command::GET_CONTEXT_INFO,
+            Command::Attest(_) => Command::ATTEST,
+            Command::Seal(..) => Command::SEAL,
+            Command::Unseal(..) => Command::UNSEAL,
+            Command::Vendor(id, _) => *id,
         }
     }
 }
@@ -126,37 +356,57 @@ pub trait CommandExecution {
 
 #[repr(C)]
 #[derive(Debug, PartialEq, Eq, zerocopy::FromBytes, zerocopy::AsBytes)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct CommandHdr {
     pub magic: u32,
     pub cmd_id: u32,
     pub profile: u32,
 }
 
+impl_wire_endian!(CommandHdr {
+    magic,
+    cmd_id,
+    profile
+});
+
+// The header is the same on every profile, so pin its wire size.
+const _: () = assert!(size_of::<CommandHdr>() == 12);
+
 impl CommandHdr {
     const DPE_COMMAND_MAGIC: u32 = u32::from_be_bytes(*b"DPEC");
 
-    pub fn new_for_test(cmd_id: u32) -> CommandHdr {
+    /// Returns a header for `cmd_id` directed at the profile this crate was built for.
+    pub const fn new(cmd_id: u32) -> CommandHdr {
         CommandHdr {
             magic: Self::DPE_COMMAND_MAGIC,
             cmd_id,
             profile: DPE_PROFILE as u32,
         }
     }
-}
 
-impl TryFrom<&[u8]> for CommandHdr {
-    type Error = DpeErrorCode;
+    pub fn new_for_test(cmd_id: u32) -> CommandHdr {
+        Self::new(cmd_id)
+    }
 
-    fn try_from(raw: &[u8]) -> Result<Self, Self::Error> {
-        let header = CommandHdr::read_from_prefix(raw).ok_or(DpeErrorCode::InvalidCommand)?;
-        if header.magic != Self::DPE_COMMAND_MAGIC {
+    fn validate(&self) -> Result<(), DpeErrorCode> {
+        if self.magic != Self::DPE_COMMAND_MAGIC {
             return Err(DpeErrorCode::InvalidCommand);
         }
         // The client doesn't know what profile is implemented when calling the `GetProfile`
         // command. But, all other commands should be directed towards the correct profile.
-        if header.cmd_id != Command::GET_PROFILE && header.profile != DPE_PROFILE as u32 {
+        if self.cmd_id != Command::GET_PROFILE && self.profile != DPE_PROFILE as u32 {
             return Err(DpeErrorCode::InvalidCommand);
         }
+        Ok(())
+    }
+}
+
+impl TryFrom<&[u8]> for CommandHdr {
+    type Error = DpeErrorCode;
+
+    fn try_from(raw: &[u8]) -> Result<Self, Self::Error> {
+        let (header, _) = read_prefix::<CommandHdr>(raw).ok_or(DpeErrorCode::InvalidCommand)?;
+        header.validate()?;
         Ok(header)
     }
 }
@@ -173,22 +423,46 @@ pub mod tests {
         1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20, 21, 22, 23, 24, 25,
         26, 27, 28, 29, 30, 31, 32,
     ];
-    #[cfg(feature = "dpe_profile_p384_sha384")]
+    #[cfg(any(
+        feature = "dpe_profile_p384_sha384",
+        feature = "dpe_profile_mldsa87_sha384"
+    ))]
     pub const TEST_DIGEST: [u8; DPE_PROFILE.get_hash_size()] = [
         1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20, 21, 22, 23, 24, 25,
         26, 27, 28, 29, 30, 31, 32, 33, 34, 35, 36, 37, 38, 39, 40, 41, 42, 43, 44, 45, 46, 47, 48,
     ];
+    #[cfg(any(
+        feature = "dpe_profile_p521_sha512",
+        feature = "dpe_profile_ed25519_sha512"
+    ))]
+    pub const TEST_DIGEST: [u8; DPE_PROFILE.get_hash_size()] = [
+        1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20, 21, 22, 23, 24, 25,
+        26, 27, 28, 29, 30, 31, 32, 33, 34, 35, 36, 37, 38, 39, 40, 41, 42, 43, 44, 45, 46, 47, 48,
+        49, 50, 51, 52, 53, 54, 55, 56, 57, 58, 59, 60, 61, 62, 63, 64,
+    ];
 
     #[cfg(feature = "dpe_profile_p256_sha256")]
     pub const TEST_LABEL: [u8; DPE_PROFILE.get_hash_size()] = [
         32, 31, 30, 29, 28, 27, 26, 25, 24, 23, 22, 21, 20, 19, 18, 17, 16, 15, 14, 13, 12, 11, 10,
         9, 8, 7, 6, 5, 4, 3, 2, 1,
     ];
-    #[cfg(feature = "dpe_profile_p384_sha384")]
+    #[cfg(any(
+        feature = "dpe_profile_p384_sha384",
+        feature = "dpe_profile_mldsa87_sha384"
+    ))]
     pub const TEST_LABEL: [u8; DPE_PROFILE.get_hash_size()] = [
         48, 47, 46, 45, 44, 43, 42, 41, 40, 39, 38, 37, 36, 35, 34, 33, 32, 31, 30, 29, 28, 27, 26,
         25, 24, 23, 22, 21, 20, 19, 18, 17, 16, 15, 14, 13, 12, 11, 10, 9, 8, 7, 6, 5, 4, 3, 2, 1,
     ];
+    #[cfg(any(
+        feature = "dpe_profile_p521_sha512",
+        feature = "dpe_profile_ed25519_sha512"
+    ))]
+    pub const TEST_LABEL: [u8; DPE_PROFILE.get_hash_size()] = [
+        64, 63, 62, 61, 60, 59, 58, 57, 56, 55, 54, 53, 52, 51, 50, 49, 48, 47, 46, 45, 44, 43, 42,
+        41, 40, 39, 38, 37, 36, 35, 34, 33, 32, 31, 30, 29, 28, 27, 26, 25, 24, 23, 22, 21, 20, 19,
+        18, 17, 16, 15, 14, 13, 12, 11, 10, 9, 8, 7, 6, 5, 4, 3, 2, 1,
+    ];
 
     const DEFAULT_COMMAND: CommandHdr = CommandHdr {
         magic: CommandHdr::DPE_COMMAND_MAGIC,
@@ -232,7 +506,12 @@ pub mod tests {
         // Test wrong profile.
         #[cfg(feature = "dpe_profile_p256_sha256")]
         let wrong_profile = DpeProfile::P384Sha384 as u32;
-        #[cfg(feature = "dpe_profile_p384_sha384")]
+        #[cfg(any(
+            feature = "dpe_profile_p384_sha384",
+            feature = "dpe_profile_mldsa87_sha384",
+            feature = "dpe_profile_p521_sha512",
+            feature = "dpe_profile_ed25519_sha512"
+        ))]
         let wrong_profile = DpeProfile::P256Sha256 as u32;
 
         // All commands should check the profile except GetProfile.
@@ -268,4 +547,45 @@ pub mod tests {
             CommandHdr::try_from(GOOD_HEADER.as_bytes()).unwrap()
         );
     }
+
+    #[test]
+    fn test_read_prefix() {
+        let bytes = [1, 0, 0, 0, 2, 3];
+        assert_eq!(Some((1u32, &bytes[4..])), read_prefix::<u32>(&bytes));
+        assert_eq!(Some((1u32, &[][..])), read_prefix::<u32>(&bytes[..4]));
+        assert_eq!(None, read_prefix::<u32>(&bytes[..3]));
+    }
+
+    /// Runs `Command::deserialize` over every input in the fuzz corpus.
+    fn for_each_corpus_input(mut f: impl FnMut(&[u8])) {
+        let corpus = concat!(env!("CARGO_MANIFEST_DIR"), "/fuzz/common_corpus");
+        for entry in std::fs::read_dir(corpus).unwrap() {
+            f(&std::fs::read(entry.unwrap().path()).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_corpus_truncated() {
+        CfiCounter::reset_for_test();
+        for_each_corpus_input(|input| {
+            let full = Command::deserialize(input);
+            for len in 0..input.len() {
+                // Truncated commands must be rejected, never read out of bounds.
+                if let Ok(cmd) = Command::deserialize(&input[..len]) {
+                    assert_eq!(Ok(&cmd), full.as_ref());
+                }
+            }
+        });
+    }
+
+    #[test]
+    fn test_corpus_misaligned() {
+        CfiCounter::reset_for_test();
+        for_each_corpus_input(|input| {
+            // Commands arrive in byte buffers with no alignment guarantee.
+            let mut buf = vec![0u8; input.len() + 1];
+            buf[1..].copy_from_slice(input);
+            assert_eq!(Command::deserialize(input), Command::deserialize(&buf[1..]));
+        });
+    }
 }
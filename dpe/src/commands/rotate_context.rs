@@ -14,21 +14,34 @@ use caliptra_cfi_lib_git::{cfi_assert, cfi_assert_eq};
 
 #[repr(C)]
 #[derive(Debug, PartialEq, Eq, zerocopy::FromBytes, zerocopy::AsBytes)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct RotateCtxFlags(u32);
 
 bitflags! {
     impl RotateCtxFlags: u32 {
+        /// Give the context the default handle instead of a new random one.
+        ///
+        /// Without it, rotating the default context moves it to a random
+        /// handle, which keeps other callers in the locality from using it.
+        /// With it, a context on a random handle becomes the default again,
+        /// but only if the locality has no default context and no other
+        /// context on a random handle.
         const TARGET_IS_DEFAULT = 1u32 << 31;
     }
 }
 
+impl_wire_endian!(flags RotateCtxFlags);
+
 #[repr(C)]
 #[derive(Debug, PartialEq, Eq, zerocopy::FromBytes, zerocopy::AsBytes)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct RotateCtxCmd {
     pub handle: ContextHandle,
     pub flags: RotateCtxFlags,
 }
 
+impl_wire_endian!(RotateCtxCmd { flags });
+
 impl RotateCtxCmd {
     pub const TARGET_IS_DEFAULT: u32 = 1 << 31;
 
@@ -68,6 +81,12 @@ impl CommandExecution for RotateCtxCmd {
         env: &mut DpeEnv<impl DpeTypes>,
         locality: u32,
     ) -> Result<Response, DpeErrorCode> {
+        dpe_trace!(
+            "RotateContext flags={:#x} locality={:#x}",
+            self.flags.bits(),
+            locality
+        );
+
         if !dpe.support.rotate_context() {
             return Err(DpeErrorCode::InvalidCommand);
         } else {
@@ -96,7 +115,7 @@ impl CommandExecution for RotateCtxCmd {
         let new_handle = if self.uses_target_is_default() {
             ContextHandle::default()
         } else {
-            dpe.generate_new_handle(env)?
+            dpe.generate_new_handle(env, locality)?
         };
         dpe.contexts[idx].handle = new_handle;
 
@@ -111,11 +130,14 @@ impl CommandExecution for RotateCtxCmd {
 mod tests {
     use super::*;
     use crate::{
-        commands::{Command, CommandHdr, InitCtxCmd},
+        commands::{
+            Command, CommandHdr, DeriveContextCmd, DeriveContextFlags, DestroyCtxCmd, InitCtxCmd,
+        },
         dpe_instance::tests::{
             TestTypes, RANDOM_HANDLE, SIMULATION_HANDLE, TEST_HANDLE, TEST_LOCALITIES,
         },
         support::Support,
+        DPE_PROFILE,
     };
     use caliptra_cfi_lib_git::CfiCounter;
     use crypto::OpensslCrypto;
@@ -146,6 +168,7 @@ mod tests {
         let mut env = DpeEnv::<TestTypes> {
             crypto: OpensslCrypto::new(),
             platform: DefaultPlatform,
+            arena: &mut [],
         };
         let mut dpe = DpeInstance::new(&mut env, Support::default()).unwrap();
         // Make sure it returns an error if the command is marked unsupported.
@@ -235,4 +258,211 @@ mod tests {
             .execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
         );
     }
+
+    #[test]
+    fn test_protect_and_restore_default_context() {
+        CfiCounter::reset_for_test();
+        let mut env = DpeEnv::<TestTypes> {
+            crypto: OpensslCrypto::new(),
+            platform: DefaultPlatform,
+            arena: &mut [],
+        };
+        let mut dpe = DpeInstance::new(&mut env, Support::ROTATE_CONTEXT).unwrap();
+        InitCtxCmd::new_use_default()
+            .execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
+            .unwrap();
+
+        // Protect the default context behind a random handle.
+        let resp = RotateCtxCmd {
+            handle: ContextHandle::default(),
+            flags: RotateCtxFlags::empty(),
+        }
+        .execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
+        .unwrap();
+        let protected = match resp {
+            Response::RotateCtx(resp) => resp.handle,
+            _ => panic!("Incorrect response type"),
+        };
+        assert!(!protected.is_default());
+        let idx = dpe
+            .get_active_context_pos(&protected, TEST_LOCALITIES[0])
+            .unwrap();
+
+        // Callers that only know the default handle can no longer reach it.
+        assert_eq!(
+            Err(DpeErrorCode::InvalidHandle),
+            dpe.get_active_context_pos(&ContextHandle::default(), TEST_LOCALITIES[0])
+        );
+
+        // Another context on a random handle in the same locality blocks the
+        // restore.
+        dpe.contexts[1].state = ContextState::Active;
+        dpe.contexts[1].locality = TEST_LOCALITIES[0];
+        dpe.contexts[1].handle = SIMULATION_HANDLE;
+        assert_eq!(
+            Err(DpeErrorCode::InvalidArgument),
+            RotateCtxCmd {
+                handle: protected,
+                flags: RotateCtxFlags::TARGET_IS_DEFAULT,
+            }
+            .execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
+        );
+
+        // So does a default context that appeared in the meantime.
+        dpe.contexts[1].handle = ContextHandle::default();
+        assert_eq!(
+            Err(DpeErrorCode::InvalidArgument),
+            RotateCtxCmd {
+                handle: protected,
+                flags: RotateCtxFlags::TARGET_IS_DEFAULT,
+            }
+            .execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
+        );
+
+        // A context in another locality doesn't.
+        dpe.contexts[1].locality = TEST_LOCALITIES[1];
+        assert_eq!(
+            Ok(Response::RotateCtx(NewHandleResp {
+                handle: ContextHandle::default(),
+                resp_hdr: ResponseHdr::new(DpeErrorCode::NoError),
+            })),
+            RotateCtxCmd {
+                handle: protected,
+                flags: RotateCtxFlags::TARGET_IS_DEFAULT,
+            }
+            .execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
+        );
+        assert_eq!(
+            Ok(idx),
+            dpe.get_active_context_pos(&ContextHandle::default(), TEST_LOCALITIES[0])
+        );
+
+        // The old random handle is gone.
+        assert_eq!(
+            Err(DpeErrorCode::InvalidHandle),
+            dpe.get_active_context_pos(&protected, TEST_LOCALITIES[0])
+        );
+    }
+
+    #[test]
+    fn test_rotate_to_default_after_destroying_simulation_contexts() {
+        CfiCounter::reset_for_test();
+        let mut env = DpeEnv::<TestTypes> {
+            crypto: OpensslCrypto::new(),
+            platform: DefaultPlatform,
+            arena: &mut [],
+        };
+        let mut dpe =
+            DpeInstance::new(&mut env, Support::ROTATE_CONTEXT | Support::SIMULATION).unwrap();
+        InitCtxCmd::new_use_default()
+            .execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
+            .unwrap();
+
+        // Move the normal context off of the default handle.
+        let resp = RotateCtxCmd {
+            handle: ContextHandle::default(),
+            flags: RotateCtxFlags::empty(),
+        }
+        .execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
+        .unwrap();
+        let normal_handle = match resp {
+            Response::RotateCtx(resp) => resp.handle,
+            _ => panic!("Incorrect response type"),
+        };
+
+        // Open a simulation context in the same locality.
+        let resp = InitCtxCmd::new_simulation()
+            .execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
+            .unwrap();
+        let sim_handle = match resp {
+            Response::InitCtx(resp) => resp.handle,
+            _ => panic!("Incorrect response type"),
+        };
+
+        // The simulation context still holds a non-default handle.
+        assert_eq!(
+            Err(DpeErrorCode::InvalidArgument),
+            RotateCtxCmd {
+                handle: normal_handle,
+                flags: RotateCtxFlags::TARGET_IS_DEFAULT,
+            }
+            .execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
+        );
+
+        DestroyCtxCmd { handle: sim_handle }
+            .execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
+            .unwrap();
+
+        // With the simulation context gone, the remaining context can become the default.
+        assert_eq!(
+            Ok(Response::RotateCtx(NewHandleResp {
+                handle: ContextHandle::default(),
+                resp_hdr: ResponseHdr::new(DpeErrorCode::NoError),
+            })),
+            RotateCtxCmd {
+                handle: normal_handle,
+                flags: RotateCtxFlags::TARGET_IS_DEFAULT,
+            }
+            .execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
+        );
+        assert!(dpe
+            .get_active_context_pos(&ContextHandle::default(), TEST_LOCALITIES[0])
+            .is_ok());
+    }
+
+    #[test]
+    fn test_rotate_to_default_after_retiring_default_parent() {
+        CfiCounter::reset_for_test();
+        let mut env = DpeEnv::<TestTypes> {
+            crypto: OpensslCrypto::new(),
+            platform: DefaultPlatform,
+            arena: &mut [],
+        };
+        let mut dpe = DpeInstance::new(&mut env, Support::ROTATE_CONTEXT).unwrap();
+        InitCtxCmd::new_use_default()
+            .execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
+            .unwrap();
+
+        // Retire the default context by deriving a non-default child from it.
+        let child_handle = match (DeriveContextCmd {
+            handle: ContextHandle::default(),
+            data: [0u8; DPE_PROFILE.get_tci_size()],
+            flags: DeriveContextFlags::empty(),
+            tci_type: 0,
+            target_locality: TEST_LOCALITIES[0],
+        })
+        .execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
+        {
+            Ok(Response::DeriveContext(resp)) => resp.handle,
+            Ok(_) => panic!("Invalid response type"),
+            Err(e) => panic!("{:?}", e),
+        };
+        let parent_idx = dpe
+            .contexts
+            .iter()
+            .position(|c| c.state == ContextState::Retired)
+            .unwrap();
+        assert_eq!(ContextHandle::INVALID, dpe.contexts[parent_idx].handle);
+
+        assert_eq!(
+            Ok(Response::RotateCtx(NewHandleResp {
+                handle: ContextHandle::default(),
+                resp_hdr: ResponseHdr::new(DpeErrorCode::NoError),
+            })),
+            RotateCtxCmd {
+                handle: child_handle,
+                flags: RotateCtxFlags::TARGET_IS_DEFAULT,
+            }
+            .execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
+        );
+
+        // Only the child holds the default handle.
+        assert_eq!(
+            1,
+            dpe.contexts
+                .iter()
+                .filter(|c| c.locality == TEST_LOCALITIES[0] && c.handle.is_default())
+                .count()
+        );
+    }
 }
@@ -0,0 +1,820 @@
+// Licensed under the Apache-2.0 license.
+use super::CommandExecution;
+use crate::{
+    context::{ContextHandle, ContextType},
+    dpe_instance::{DpeEnv, DpeInstance, DpeTypes},
+    response::{DpeErrorCode, Response, ResponseHdr, SealResp, UnsealResp},
+    DPE_PROFILE, MAX_SEALED_SIZE, MAX_SEAL_DATA_SIZE, SEAL_HEADER_SIZE,
+};
+use bitflags::bitflags;
+#[cfg(not(feature = "no-cfi"))]
+use caliptra_cfi_derive_git::cfi_impl_fn;
+#[cfg(not(feature = "no-cfi"))]
+use caliptra_cfi_lib_git::{cfi_assert, cfi_assert_ne};
+use cfg_if::cfg_if;
+use constant_time_eq::constant_time_eq;
+use crypto::{AeadKey, Crypto, CryptoError, AEAD_IV_SIZE, AEAD_TAG_SIZE};
+use zeroize::Zeroize;
+
+#[repr(C)]
+#[derive(Debug, PartialEq, Eq, zerocopy::FromBytes, zerocopy::AsBytes)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SealFlags(u32);
+
+bitflags! {
+    impl SealFlags: u32 {
+        /// Seal to a child of the context whose cumulative TCI will be `tci`,
+        /// rather than to the context itself.
+        const EXPECTED_TCI = 1u32 << 31;
+    }
+}
+
+impl_wire_endian!(flags SealFlags);
+
+/// Encrypts data so that only a context with the same measurements, or a
+/// child of it with an expected TCI, can decrypt it with Unseal.
+///
+/// The command is followed by `size` bytes of data, at most
+/// `MAX_SEAL_DATA_SIZE`. The data is encrypted with AES-256-GCM under a key
+/// derived from the context's CDI and `label`, which binds it to the
+/// cumulative TCIs of the context and its ancestors.
+///
+/// With `SealFlags::EXPECTED_TCI`, Unseal instead succeeds only in a direct
+/// child of the context whose cumulative TCI is `tci`, so data sealed in one
+/// layer can be released to the next once it has been measured. `tci` is
+/// ignored otherwise.
+///
+/// The sealed data is a `SEAL_HEADER_SIZE` byte header recording the policy,
+/// the IV and the tag followed by the ciphertext. The header is
+/// authenticated along with the ciphertext.
+#[repr(C)]
+#[derive(Debug, PartialEq, Eq, zerocopy::FromBytes, zerocopy::AsBytes)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SealCmd {
+    pub handle: ContextHandle,
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_hex"))]
+    pub label: [u8; DPE_PROFILE.get_hash_size()],
+    pub flags: SealFlags,
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_hex"))]
+    pub tci: [u8; DPE_PROFILE.get_tci_size()],
+    pub size: u32,
+}
+
+impl_wire_endian!(SealCmd { flags, size });
+
+/// Decrypts data sealed with Seal. It fails with `DpeErrorCode::UnsealFailed`
+/// unless `label` and the context's measurements satisfy the policy the data
+/// was sealed with.
+///
+/// The command is followed by `size` bytes of sealed data.
+#[repr(C)]
+#[derive(Debug, PartialEq, Eq, zerocopy::FromBytes, zerocopy::AsBytes)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct UnsealCmd {
+    pub handle: ContextHandle,
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_hex"))]
+    pub label: [u8; DPE_PROFILE.get_hash_size()],
+    pub size: u32,
+}
+
+impl_wire_endian!(UnsealCmd { size });
+
+/// Unseal policy for data sealed to the measurements of the sealing context.
+const POLICY_CURRENT: u32 = 0;
+/// Unseal policy for data sealed to a child of the sealing context with the
+/// TCI in the header.
+const POLICY_EXPECTED_TCI: u32 = 1;
+
+/// Additional authenticated data bound to all sealed data, so it can't be
+/// passed off as some other blob encrypted under the same key. The sealed
+/// data header follows it.
+const AAD: &[u8] = b"DPE_SEAL";
+
+/// Returns the `size` bytes at the start of `trailer`, the bytes that follow
+/// a Seal or Unseal command.
+fn trailer_data(size: u32, trailer: &[u8]) -> Result<&[u8], DpeErrorCode> {
+    trailer
+        .get(..size as usize)
+        .ok_or(DpeErrorCode::InvalidArgument)
+}
+
+/// Returns the additional authenticated data for sealed data with `header`.
+fn aad(header: &[u8]) -> Result<[u8; AAD.len() + SEAL_HEADER_SIZE], DpeErrorCode> {
+    let mut aad = [0u8; AAD.len() + SEAL_HEADER_SIZE];
+    let (prefix, aad_header) = aad.split_at_mut(AAD.len());
+    prefix.copy_from_slice(AAD);
+    aad_header.copy_from_slice(
+        header
+            .get(..SEAL_HEADER_SIZE)
+            .ok_or(DpeErrorCode::InternalError)?,
+    );
+    Ok(aad)
+}
+
+/// Returns the index of the context `handle` names, if sealing is supported
+/// and the context can seal.
+fn sealing_context(
+    dpe: &DpeInstance,
+    handle: &ContextHandle,
+    locality: u32,
+) -> Result<usize, DpeErrorCode> {
+    // Make sure this command is supported.
+    if !dpe.support.seal() {
+        return Err(DpeErrorCode::InvalidCommand);
+    }
+
+    let idx = dpe.get_active_context_pos(handle, locality)?;
+    let context = &dpe.contexts[idx];
+
+    // Like signing keys, simulation contexts have no sealing key.
+    if context.context_type == ContextType::Simulation {
+        return Err(DpeErrorCode::InvalidArgument);
+    }
+
+    cfg_if! {
+        if #[cfg(not(feature = "no-cfi"))] {
+            cfi_assert!(dpe.support.seal());
+            cfi_assert_ne(context.context_type, ContextType::Simulation);
+        }
+    }
+    Ok(idx)
+}
+
+/// Derives the key that data sealed by the context at `idx` with `label` is
+/// encrypted under. `info` separates the keys for each unseal policy.
+fn derive_seal_key(
+    dpe: &mut DpeInstance,
+    env: &mut DpeEnv<impl DpeTypes>,
+    idx: usize,
+    label: &[u8],
+    info: &[u8],
+) -> Result<AeadKey, DpeErrorCode> {
+    // The CDI is the same one CertifyKey and Sign derive keys from.
+    let digest = dpe.compute_measurement_hash(env, idx)?;
+    let cdi = env
+        .crypto
+        .derive_cdi(DPE_PROFILE.alg_len(), &digest, b"DPE")?;
+    let key = env
+        .crypto
+        .derive_aead_key(DPE_PROFILE.alg_len(), &cdi, label, info);
+    env.crypto.destroy_cdi(cdi)?;
+    Ok(key?)
+}
+
+/// Key derivation info for each unseal policy.
+fn policy_info(policy: u32) -> &'static [u8] {
+    if policy == POLICY_EXPECTED_TCI {
+        b"SEAL_TCI"
+    } else {
+        b"SEAL"
+    }
+}
+
+impl SealCmd {
+    /// Returns the data carried in `trailer`, the bytes that follow the
+    /// command.
+    ///
+    /// # Arguments
+    ///
+    /// * `trailer` - Serialized bytes following the command
+    pub(crate) fn data<'a>(&self, trailer: &'a [u8]) -> Result<&'a [u8], DpeErrorCode> {
+        trailer_data(self.size, trailer)
+    }
+
+    /// Seals `data` to the context, or to its child with the expected TCI.
+    ///
+    /// # Arguments
+    ///
+    /// * `dpe` - DPE instance
+    /// * `env` - DPE environment containing Crypto and Platform implementations
+    /// * `locality` - which hardware locality is making the request
+    /// * `data` - Data that followed the command
+    #[cfg_attr(not(feature = "no-cfi"), cfi_impl_fn)]
+    pub(crate) fn execute_with_data(
+        &self,
+        dpe: &mut DpeInstance,
+        env: &mut DpeEnv<impl DpeTypes>,
+        locality: u32,
+        data: &[u8],
+    ) -> Result<Response, DpeErrorCode> {
+        dpe_trace!(
+            "Seal flags={:#x} size={} locality={:#x}",
+            self.flags.bits(),
+            data.len(),
+            locality
+        );
+
+        let idx = sealing_context(dpe, &self.handle, locality)?;
+        if data.len() > MAX_SEAL_DATA_SIZE {
+            return Err(DpeErrorCode::InvalidArgument);
+        } else {
+            #[cfg(not(feature = "no-cfi"))]
+            cfi_assert!(data.len() <= MAX_SEAL_DATA_SIZE);
+        }
+
+        let mut sealed = [0u8; MAX_SEALED_SIZE];
+        let (header, rest) = sealed.split_at_mut(SEAL_HEADER_SIZE);
+        let (policy, expected_tci) = header.split_at_mut(core::mem::size_of::<u32>());
+        let policy_value = if self.flags.contains(SealFlags::EXPECTED_TCI) {
+            expected_tci.copy_from_slice(&self.tci);
+            POLICY_EXPECTED_TCI
+        } else {
+            POLICY_CURRENT
+        };
+        policy.copy_from_slice(&policy_value.to_le_bytes());
+        let aad = aad(header)?;
+
+        let key = derive_seal_key(dpe, env, idx, &self.label, policy_info(policy_value))?;
+        let mut iv = [0u8; AEAD_IV_SIZE];
+        env.crypto.rand_bytes(&mut iv)?;
+
+        let sealed_size = SEAL_HEADER_SIZE + AEAD_IV_SIZE + AEAD_TAG_SIZE + data.len();
+        let (iv_tag, ciphertext) = rest.split_at_mut(AEAD_IV_SIZE + AEAD_TAG_SIZE);
+        let ciphertext = ciphertext
+            .get_mut(..data.len())
+            .ok_or(DpeErrorCode::InternalError)?;
+        ciphertext.copy_from_slice(data);
+        let tag = env.crypto.aead_encrypt(&key, &iv, &aad, ciphertext)?;
+        let (header_iv, header_tag) = iv_tag.split_at_mut(AEAD_IV_SIZE);
+        header_iv.copy_from_slice(&iv);
+        header_tag.copy_from_slice(&tag);
+
+        // Rotate the handle if it isn't the default context.
+        dpe.roll_onetime_use_handle(env, idx)?;
+
+        Ok(Response::Seal(SealResp {
+            resp_hdr: ResponseHdr::new(DpeErrorCode::NoError),
+            new_context_handle: dpe.contexts[idx].handle,
+            sealed_size: sealed_size as u32,
+            sealed,
+        }))
+    }
+}
+
+impl CommandExecution for SealCmd {
+    #[cfg_attr(not(feature = "no-cfi"), cfi_impl_fn)]
+    fn execute(
+        &self,
+        dpe: &mut DpeInstance,
+        env: &mut DpeEnv<impl DpeTypes>,
+        locality: u32,
+    ) -> Result<Response, DpeErrorCode> {
+        self.execute_with_data(dpe, env, locality, &[])
+    }
+}
+
+impl UnsealCmd {
+    /// Returns the sealed data carried in `trailer`, the bytes that follow
+    /// the command.
+    ///
+    /// # Arguments
+    ///
+    /// * `trailer` - Serialized bytes following the command
+    pub(crate) fn data<'a>(&self, trailer: &'a [u8]) -> Result<&'a [u8], DpeErrorCode> {
+        trailer_data(self.size, trailer)
+    }
+
+    /// Returns the index of the context whose sealing key `sealed` was
+    /// encrypted under, if the context at `idx` satisfies the policy in the
+    /// header.
+    fn sealer(
+        dpe: &DpeInstance,
+        idx: usize,
+        policy: u32,
+        expected_tci: &[u8],
+    ) -> Result<usize, DpeErrorCode> {
+        match policy {
+            POLICY_CURRENT => Ok(idx),
+            POLICY_EXPECTED_TCI => {
+                let context = &dpe.contexts[idx];
+                if !constant_time_eq(&context.tci.tci_cumulative.0, expected_tci) {
+                    return Err(DpeErrorCode::UnsealFailed);
+                }
+                context.parent_pos().ok_or(DpeErrorCode::UnsealFailed)
+            }
+            _ => Err(DpeErrorCode::InvalidArgument),
+        }
+    }
+
+    /// Unseals `sealed` with the sealing key its policy selects.
+    ///
+    /// # Arguments
+    ///
+    /// * `dpe` - DPE instance
+    /// * `env` - DPE environment containing Crypto and Platform implementations
+    /// * `locality` - which hardware locality is making the request
+    /// * `sealed` - Sealed data that followed the command
+    #[cfg_attr(not(feature = "no-cfi"), cfi_impl_fn)]
+    pub(crate) fn execute_with_data(
+        &self,
+        dpe: &mut DpeInstance,
+        env: &mut DpeEnv<impl DpeTypes>,
+        locality: u32,
+        sealed: &[u8],
+    ) -> Result<Response, DpeErrorCode> {
+        dpe_trace!("Unseal size={} locality={:#x}", sealed.len(), locality);
+
+        const MIN_SEALED_SIZE: usize = SEAL_HEADER_SIZE + AEAD_IV_SIZE + AEAD_TAG_SIZE;
+        let idx = sealing_context(dpe, &self.handle, locality)?;
+        if sealed.len() < MIN_SEALED_SIZE || sealed.len() > MAX_SEALED_SIZE {
+            return Err(DpeErrorCode::InvalidArgument);
+        } else {
+            cfg_if! {
+                if #[cfg(not(feature = "no-cfi"))] {
+                    cfi_assert!(sealed.len() >= MIN_SEALED_SIZE);
+                    cfi_assert!(sealed.len() <= MAX_SEALED_SIZE);
+                }
+            }
+        }
+
+        let (header, rest) = sealed.split_at(SEAL_HEADER_SIZE);
+        let (policy, expected_tci) = header.split_at(core::mem::size_of::<u32>());
+        let policy =
+            u32::from_le_bytes(policy.try_into().map_err(|_| DpeErrorCode::InternalError)?);
+        let (iv, rest) = rest.split_at(AEAD_IV_SIZE);
+        let (tag, ciphertext) = rest.split_at(AEAD_TAG_SIZE);
+        let iv: &[u8; AEAD_IV_SIZE] = iv.try_into().map_err(|_| DpeErrorCode::InternalError)?;
+        let tag: &[u8; AEAD_TAG_SIZE] = tag.try_into().map_err(|_| DpeErrorCode::InternalError)?;
+
+        let sealer = Self::sealer(dpe, idx, policy, expected_tci)?;
+        let key = derive_seal_key(dpe, env, sealer, &self.label, policy_info(policy))?;
+        let mut data = [0u8; MAX_SEAL_DATA_SIZE];
+        let plaintext = data
+            .get_mut(..ciphertext.len())
+            .ok_or(DpeErrorCode::InternalError)?;
+        plaintext.copy_from_slice(ciphertext);
+        match env
+            .crypto
+            .aead_decrypt(&key, iv, &aad(header)?, plaintext, tag)
+        {
+            Ok(()) => (),
+            Err(err) => {
+                // Don't leave anything the backend decrypted before it
+                // checked the tag.
+                data.zeroize();
+                return Err(match err {
+                    CryptoError::AuthenticationFailed => DpeErrorCode::UnsealFailed,
+                    err => err.into(),
+                });
+            }
+        }
+
+        // Rotate the handle if it isn't the default context.
+        dpe.roll_onetime_use_handle(env, idx)?;
+
+        Ok(Response::Unseal(UnsealResp {
+            resp_hdr: ResponseHdr::new(DpeErrorCode::NoError),
+            new_context_handle: dpe.contexts[idx].handle,
+            data_size: ciphertext.len() as u32,
+            data,
+        }))
+    }
+}
+
+impl CommandExecution for UnsealCmd {
+    #[cfg_attr(not(feature = "no-cfi"), cfi_impl_fn)]
+    fn execute(
+        &self,
+        dpe: &mut DpeInstance,
+        env: &mut DpeEnv<impl DpeTypes>,
+        locality: u32,
+    ) -> Result<Response, DpeErrorCode> {
+        self.execute_with_data(dpe, env, locality, &[])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        commands::{Command, CommandHdr, DeriveContextCmd, DeriveContextFlags, ExtendTciCmd},
+        dpe_instance::tests::{TestTypes, TEST_LOCALITIES},
+        support::Support,
+    };
+    use caliptra_cfi_lib_git::CfiCounter;
+    use crypto::{Hasher, OpensslCrypto};
+    use platform::default::DefaultPlatform;
+    use zerocopy::AsBytes;
+
+    const TEST_LABEL: [u8; DPE_PROFILE.get_hash_size()] = [0x5E; DPE_PROFILE.get_hash_size()];
+
+    fn seal(
+        dpe: &mut DpeInstance,
+        env: &mut DpeEnv<TestTypes>,
+        handle: ContextHandle,
+        data: &[u8],
+    ) -> Result<SealResp, DpeErrorCode> {
+        seal_in(dpe, env, handle, TEST_LOCALITIES[0], data)
+    }
+
+    fn seal_in(
+        dpe: &mut DpeInstance,
+        env: &mut DpeEnv<TestTypes>,
+        handle: ContextHandle,
+        locality: u32,
+        data: &[u8],
+    ) -> Result<SealResp, DpeErrorCode> {
+        let cmd = SealCmd {
+            handle,
+            label: TEST_LABEL,
+            flags: SealFlags::empty(),
+            tci: [0; DPE_PROFILE.get_tci_size()],
+            size: data.len() as u32,
+        };
+        match cmd.execute_with_data(dpe, env, locality, data)? {
+            Response::Seal(resp) => Ok(resp),
+            _ => panic!("Wrong response type."),
+        }
+    }
+
+    fn unseal(
+        dpe: &mut DpeInstance,
+        env: &mut DpeEnv<TestTypes>,
+        handle: ContextHandle,
+        label: [u8; DPE_PROFILE.get_hash_size()],
+        sealed: &[u8],
+    ) -> Result<UnsealResp, DpeErrorCode> {
+        unseal_in(dpe, env, handle, TEST_LOCALITIES[0], label, sealed)
+    }
+
+    fn unseal_in(
+        dpe: &mut DpeInstance,
+        env: &mut DpeEnv<TestTypes>,
+        handle: ContextHandle,
+        locality: u32,
+        label: [u8; DPE_PROFILE.get_hash_size()],
+        sealed: &[u8],
+    ) -> Result<UnsealResp, DpeErrorCode> {
+        let cmd = UnsealCmd {
+            handle,
+            label,
+            size: sealed.len() as u32,
+        };
+        match cmd.execute_with_data(dpe, env, locality, sealed)? {
+            Response::Unseal(resp) => Ok(resp),
+            _ => panic!("Wrong response type."),
+        }
+    }
+
+    #[test]
+    fn test_deserialize_seal() {
+        CfiCounter::reset_for_test();
+        let cmd = SealCmd {
+            handle: ContextHandle::default(),
+            label: TEST_LABEL,
+            flags: SealFlags::EXPECTED_TCI,
+            tci: [0x7C; DPE_PROFILE.get_tci_size()],
+            size: 4,
+        };
+        let mut command = CommandHdr::new_for_test(Command::SEAL).as_bytes().to_vec();
+        command.extend(cmd.as_bytes());
+        command.extend(b"DPE!");
+        assert_eq!(
+            Ok(Command::Seal(
+                SealCmd {
+                    handle: ContextHandle::default(),
+                    label: TEST_LABEL,
+                    flags: SealFlags::EXPECTED_TCI,
+                    tci: [0x7C; DPE_PROFILE.get_tci_size()],
+                    size: 4,
+                },
+                b"DPE!"
+            )),
+            Command::deserialize(&command)
+        );
+
+        // The data must all be there.
+        command.pop();
+        assert_eq!(
+            Err(DpeErrorCode::InvalidArgument),
+            Command::deserialize(&command)
+        );
+
+        let cmd = UnsealCmd {
+            handle: ContextHandle::default(),
+            label: TEST_LABEL,
+            size: 2,
+        };
+        let mut command = CommandHdr::new_for_test(Command::UNSEAL)
+            .as_bytes()
+            .to_vec();
+        command.extend(cmd.as_bytes());
+        command.extend(b"DPE!");
+        assert_eq!(
+            Ok(Command::Unseal(
+                UnsealCmd {
+                    handle: ContextHandle::default(),
+                    label: TEST_LABEL,
+                    size: 2,
+                },
+                b"DP"
+            )),
+            Command::deserialize(&command)
+        );
+    }
+
+    #[test]
+    fn test_seal_unseal() {
+        CfiCounter::reset_for_test();
+        let mut env = DpeEnv::<TestTypes> {
+            crypto: OpensslCrypto::new(),
+            platform: DefaultPlatform,
+            arena: &mut [],
+        };
+
+        // Make sure it returns an error if the command is marked unsupported.
+        let mut dpe = DpeInstance::new(&mut env, Support::AUTO_INIT).unwrap();
+        assert_eq!(
+            Err(DpeErrorCode::InvalidCommand),
+            seal(&mut dpe, &mut env, ContextHandle::default(), b"secret")
+        );
+
+        let mut dpe = DpeInstance::new(
+            &mut env,
+            Support::AUTO_INIT | Support::SEAL | Support::EXTEND_TCI,
+        )
+        .unwrap();
+        let sealed = seal(&mut dpe, &mut env, ContextHandle::default(), b"secret").unwrap();
+        let sealed = &sealed.sealed[..sealed.sealed_size as usize];
+        assert_eq!(
+            sealed.len(),
+            SEAL_HEADER_SIZE + AEAD_IV_SIZE + AEAD_TAG_SIZE + 6
+        );
+        assert_ne!(
+            &sealed[SEAL_HEADER_SIZE + AEAD_IV_SIZE + AEAD_TAG_SIZE..],
+            b"secret"
+        );
+
+        let resp = unseal(
+            &mut dpe,
+            &mut env,
+            ContextHandle::default(),
+            TEST_LABEL,
+            sealed,
+        )
+        .unwrap();
+        assert_eq!(&resp.data[..resp.data_size as usize], b"secret");
+
+        // The key depends on the label.
+        assert_eq!(
+            Err(DpeErrorCode::UnsealFailed),
+            unseal(
+                &mut dpe,
+                &mut env,
+                ContextHandle::default(),
+                [0; DPE_PROFILE.get_hash_size()],
+                sealed
+            )
+        );
+
+        // Tampering with the sealed data is detected.
+        let mut tampered = sealed.to_vec();
+        *tampered.last_mut().unwrap() ^= 1;
+        assert_eq!(
+            Err(DpeErrorCode::UnsealFailed),
+            unseal(
+                &mut dpe,
+                &mut env,
+                ContextHandle::default(),
+                TEST_LABEL,
+                &tampered
+            )
+        );
+
+        // Sealed data must at least hold the header, IV and tag, and data to
+        // seal must fit in the response.
+        assert_eq!(
+            Err(DpeErrorCode::InvalidArgument),
+            unseal(
+                &mut dpe,
+                &mut env,
+                ContextHandle::default(),
+                TEST_LABEL,
+                &sealed[..SEAL_HEADER_SIZE + AEAD_IV_SIZE + AEAD_TAG_SIZE - 1]
+            )
+        );
+        assert_eq!(
+            Err(DpeErrorCode::InvalidArgument),
+            seal(
+                &mut dpe,
+                &mut env,
+                ContextHandle::default(),
+                &[0; MAX_SEAL_DATA_SIZE + 1]
+            )
+        );
+        let resp = seal(
+            &mut dpe,
+            &mut env,
+            ContextHandle::default(),
+            &[0xA5; MAX_SEAL_DATA_SIZE],
+        )
+        .unwrap();
+        assert_eq!(resp.sealed_size as usize, MAX_SEALED_SIZE);
+
+        // Once the TCI changes, the data no longer unseals.
+        ExtendTciCmd {
+            handle: ContextHandle::default(),
+            data: [1; DPE_PROFILE.get_hash_size()],
+        }
+        .execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
+        .unwrap();
+        assert_eq!(
+            Err(DpeErrorCode::UnsealFailed),
+            unseal(
+                &mut dpe,
+                &mut env,
+                ContextHandle::default(),
+                TEST_LABEL,
+                sealed
+            )
+        );
+    }
+
+    #[test]
+    fn test_seal_rotates_handle() {
+        CfiCounter::reset_for_test();
+        let mut env = DpeEnv::<TestTypes> {
+            crypto: OpensslCrypto::new(),
+            platform: DefaultPlatform,
+            arena: &mut [],
+        };
+        let mut dpe = DpeInstance::new(
+            &mut env,
+            Support::AUTO_INIT | Support::SEAL | Support::RETAIN_PARENT_CONTEXT,
+        )
+        .unwrap();
+
+        // A non-default child in another locality.
+        let Ok(Response::DeriveContext(resp)) = (DeriveContextCmd {
+            handle: ContextHandle::default(),
+            data: [2; DPE_PROFILE.get_hash_size()],
+            flags: DeriveContextFlags::RETAIN_PARENT_CONTEXT
+                | DeriveContextFlags::CHANGE_LOCALITY,
+            tci_type: 0,
+            target_locality: TEST_LOCALITIES[1],
+        })
+        .execute(&mut dpe, &mut env, TEST_LOCALITIES[0]) else {
+            panic!("DeriveContext failed");
+        };
+        let child = resp.handle;
+
+        let sealed = seal_in(&mut dpe, &mut env, child, TEST_LOCALITIES[1], b"child").unwrap();
+        assert_ne!(sealed.new_context_handle, child);
+        let sealed_data = &sealed.sealed[..sealed.sealed_size as usize];
+
+        // The parent has different measurements, so it can't unseal the
+        // child's data.
+        assert_eq!(
+            Err(DpeErrorCode::UnsealFailed),
+            unseal(
+                &mut dpe,
+                &mut env,
+                ContextHandle::default(),
+                TEST_LABEL,
+                sealed_data
+            )
+        );
+
+        // A failed Unseal leaves the handle alone.
+        let handle = sealed.new_context_handle;
+        let mut tampered = sealed_data.to_vec();
+        *tampered.last_mut().unwrap() ^= 1;
+        assert_eq!(
+            Err(DpeErrorCode::UnsealFailed),
+            unseal_in(
+                &mut dpe,
+                &mut env,
+                handle,
+                TEST_LOCALITIES[1],
+                TEST_LABEL,
+                &tampered
+            )
+        );
+        let resp = unseal_in(
+            &mut dpe,
+            &mut env,
+            handle,
+            TEST_LOCALITIES[1],
+            TEST_LABEL,
+            sealed_data,
+        )
+        .unwrap();
+        assert_eq!(&resp.data[..resp.data_size as usize], b"child");
+        assert_ne!(resp.new_context_handle, handle);
+    }
+
+    #[test]
+    fn test_seal_to_expected_tci() {
+        CfiCounter::reset_for_test();
+        let mut env = DpeEnv::<TestTypes> {
+            crypto: OpensslCrypto::new(),
+            platform: DefaultPlatform,
+            arena: &mut [],
+        };
+        let mut dpe = DpeInstance::new(
+            &mut env,
+            Support::AUTO_INIT | Support::SEAL | Support::EXTEND_TCI,
+        )
+        .unwrap();
+
+        // The cumulative TCI a child measured with `data` will start with.
+        let data = [3; DPE_PROFILE.get_tci_size()];
+        let mut hasher = env.crypto.hash_initialize(DPE_PROFILE.alg_len()).unwrap();
+        hasher.update(&[0; DPE_PROFILE.get_tci_size()]).unwrap();
+        hasher.update(&data).unwrap();
+        let mut expected_tci = [0; DPE_PROFILE.get_tci_size()];
+        expected_tci.copy_from_slice(hasher.finish().unwrap().bytes());
+
+        let Ok(Response::Seal(sealed)) = (SealCmd {
+            handle: ContextHandle::default(),
+            label: TEST_LABEL,
+            flags: SealFlags::EXPECTED_TCI,
+            tci: expected_tci,
+            size: 4,
+        })
+        .execute_with_data(&mut dpe, &mut env, TEST_LOCALITIES[0], b"next") else {
+            panic!("Seal failed");
+        };
+        let sealed = &sealed.sealed[..sealed.sealed_size as usize];
+        assert_eq!(
+            &sealed[..SEAL_HEADER_SIZE],
+            [&POLICY_EXPECTED_TCI.to_le_bytes()[..], &expected_tci[..]].concat()
+        );
+
+        // The sealing context doesn't have the expected TCI.
+        assert_eq!(
+            Err(DpeErrorCode::UnsealFailed),
+            unseal(
+                &mut dpe,
+                &mut env,
+                ContextHandle::default(),
+                TEST_LABEL,
+                sealed
+            )
+        );
+
+        DeriveContextCmd {
+            handle: ContextHandle::default(),
+            data,
+            flags: DeriveContextFlags::empty(),
+            tci_type: 0,
+            target_locality: TEST_LOCALITIES[0],
+        }
+        .execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
+        .unwrap();
+
+        // The expected TCI is authenticated along with the data.
+        let mut tampered = sealed.to_vec();
+        tampered[..SEAL_HEADER_SIZE].copy_from_slice(&[0; SEAL_HEADER_SIZE]);
+        assert_eq!(
+            Err(DpeErrorCode::UnsealFailed),
+            unseal(
+                &mut dpe,
+                &mut env,
+                ContextHandle::default(),
+                TEST_LABEL,
+                &tampered
+            )
+        );
+        let mut tampered = sealed.to_vec();
+        tampered[..4].copy_from_slice(&2u32.to_le_bytes());
+        assert_eq!(
+            Err(DpeErrorCode::InvalidArgument),
+            unseal(
+                &mut dpe,
+                &mut env,
+                ContextHandle::default(),
+                TEST_LABEL,
+                &tampered
+            )
+        );
+
+        let resp = unseal(
+            &mut dpe,
+            &mut env,
+            ContextHandle::default(),
+            TEST_LABEL,
+            sealed,
+        )
+        .unwrap();
+        assert_eq!(&resp.data[..resp.data_size as usize], b"next");
+
+        // Once the child extends its TCI, the policy no longer holds.
+        ExtendTciCmd {
+            handle: ContextHandle::default(),
+            data: [1; DPE_PROFILE.get_hash_size()],
+        }
+        .execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
+        .unwrap();
+        assert_eq!(
+            Err(DpeErrorCode::UnsealFailed),
+            unseal(
+                &mut dpe,
+                &mut env,
+                ContextHandle::default(),
+                TEST_LABEL,
+                sealed
+            )
+        );
+    }
+}
@@ -0,0 +1,556 @@
+// Licensed under the Apache-2.0 license.
+use super::CommandExecution;
+use crate::{
+    dpe_instance::{DpeEnv, DpeInstance, DpeTypes},
+    response::{DpeErrorCode, OpenSessionResp, Response, ResponseHdr},
+    session::{MAX_SESSIONS_PER_LOCALITY, SESSION_KEY_INFO, SESSION_SIGN_LABEL},
+    DPE_PROFILE,
+};
+#[cfg(not(feature = "no-cfi"))]
+use caliptra_cfi_derive_git::cfi_impl_fn;
+#[cfg(not(feature = "no-cfi"))]
+use caliptra_cfi_lib_git::cfi_assert;
+use crypto::{Crypto, CryptoBuf, EcdsaPub, EcdsaSig, Hasher};
+use zeroize::Zeroize;
+
+#[repr(C)]
+#[derive(Debug, PartialEq, Eq, zerocopy::FromBytes, zerocopy::AsBytes)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct OpenSessionCmd {
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_hex"))]
+    pub pub_key_x: [u8; DPE_PROFILE.get_ecc_int_size()],
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_hex"))]
+    pub pub_key_y: [u8; DPE_PROFILE.get_ecc_int_size()],
+}
+
+impl_wire_endian!(OpenSessionCmd {});
+
+impl CommandExecution for OpenSessionCmd {
+    #[cfg_attr(not(feature = "no-cfi"), cfi_impl_fn)]
+    fn execute(
+        &self,
+        dpe: &mut DpeInstance,
+        env: &mut DpeEnv<impl DpeTypes>,
+        locality: u32,
+    ) -> Result<Response, DpeErrorCode> {
+        dpe_trace!("OpenSession locality={:#x}", locality);
+
+        // Make sure this command is supported.
+        if !dpe.support.sessions() {
+            return Err(DpeErrorCode::InvalidCommand);
+        } else {
+            #[cfg(not(feature = "no-cfi"))]
+            cfi_assert!(dpe.support.sessions());
+        }
+
+        let open_in_locality = dpe
+            .sessions
+            .iter()
+            .filter(|s| s.active() && s.locality == locality)
+            .count();
+        if open_in_locality >= MAX_SESSIONS_PER_LOCALITY {
+            return Err(DpeErrorCode::MaxSessions);
+        } else {
+            #[cfg(not(feature = "no-cfi"))]
+            cfi_assert!(open_in_locality < MAX_SESSIONS_PER_LOCALITY);
+        }
+
+        let session_id = dpe
+            .sessions
+            .iter()
+            .position(|s| !s.active())
+            .ok_or(DpeErrorCode::MaxSessions)?;
+
+        let peer_pub = EcdsaPub {
+            x: CryptoBuf::new(&self.pub_key_x)?,
+            y: CryptoBuf::new(&self.pub_key_y)?,
+        };
+        let (key, pub_key) =
+            env.crypto
+                .ecdh_derive_key(DPE_PROFILE.alg_len(), &peer_pub, SESSION_KEY_INFO)?;
+
+        let pub_key_x: [u8; DPE_PROFILE.get_ecc_int_size()] = pub_key
+            .x
+            .bytes()
+            .try_into()
+            .map_err(|_| DpeErrorCode::InternalError)?;
+        let pub_key_y: [u8; DPE_PROFILE.get_ecc_int_size()] = pub_key
+            .y
+            .bytes()
+            .try_into()
+            .map_err(|_| DpeErrorCode::InternalError)?;
+
+        // Sign both public keys so the client knows it is talking to this
+        // DPE and not to something in the middle.
+        let mut hasher = env.crypto.hash_initialize(DPE_PROFILE.alg_len())?;
+        hasher.update(SESSION_SIGN_LABEL)?;
+        hasher.update(&self.pub_key_x)?;
+        hasher.update(&self.pub_key_y)?;
+        hasher.update(&pub_key_x)?;
+        hasher.update(&pub_key_y)?;
+        hasher.update(&(session_id as u32).to_le_bytes())?;
+        let digest = hasher.finish()?;
+        let EcdsaSig { r, s } = env
+            .crypto
+            .ecdsa_sign_with_alias(DPE_PROFILE.alg_len(), &digest)?;
+        let sig_r: [u8; DPE_PROFILE.get_ecc_int_size()] = r
+            .bytes()
+            .try_into()
+            .map_err(|_| DpeErrorCode::InternalError)?;
+        let sig_s: [u8; DPE_PROFILE.get_ecc_int_size()] = s
+            .bytes()
+            .try_into()
+            .map_err(|_| DpeErrorCode::InternalError)?;
+
+        let session = &mut dpe.sessions[session_id];
+        session.key = key.0;
+        session.locality = locality;
+        session.counter = 0;
+        session.active = true.into();
+
+        Ok(Response::OpenSession(OpenSessionResp {
+            session_id: session_id as u32,
+            pub_key_x,
+            pub_key_y,
+            sig_r,
+            sig_s,
+            resp_hdr: ResponseHdr::new(DpeErrorCode::NoError),
+        }))
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, PartialEq, Eq, zerocopy::FromBytes, zerocopy::AsBytes)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CloseSessionCmd {
+    pub session_id: u32,
+}
+
+impl_wire_endian!(CloseSessionCmd { session_id });
+
+impl CommandExecution for CloseSessionCmd {
+    #[cfg_attr(not(feature = "no-cfi"), cfi_impl_fn)]
+    fn execute(
+        &self,
+        dpe: &mut DpeInstance,
+        _env: &mut DpeEnv<impl DpeTypes>,
+        locality: u32,
+    ) -> Result<Response, DpeErrorCode> {
+        dpe_trace!(
+            "CloseSession session_id={} locality={:#x}",
+            self.session_id,
+            locality
+        );
+
+        // Make sure this command is supported.
+        if !dpe.support.sessions() {
+            return Err(DpeErrorCode::InvalidCommand);
+        } else {
+            #[cfg(not(feature = "no-cfi"))]
+            cfi_assert!(dpe.support.sessions());
+        }
+
+        let session = dpe
+            .sessions
+            .get_mut(self.session_id as usize)
+            .filter(|s| s.active())
+            .ok_or(DpeErrorCode::InvalidSession)?;
+
+        // Only the locality that opened the session may close it.
+        if session.locality != locality {
+            return Err(DpeErrorCode::InvalidLocality);
+        } else {
+            #[cfg(not(feature = "no-cfi"))]
+            cfi_assert!(session.locality == locality);
+        }
+
+        session.zeroize();
+
+        Ok(Response::CloseSession(ResponseHdr::new(
+            DpeErrorCode::NoError,
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        commands::{Command, CommandHdr},
+        dpe_instance::tests::{TestTypes, TEST_LOCALITIES},
+        response::{GetProfileResp, SessionResp},
+        session::{SessionMsgHdr, MAX_SESSIONS},
+        support::{test::SUPPORT, Support},
+        MAX_HANDLES,
+    };
+    use caliptra_cfi_lib_git::CfiCounter;
+    use core::mem::size_of;
+    use crypto::{AeadKey, AlgLen, CryptoError, OpensslCrypto, AEAD_KEY_SIZE, AEAD_TAG_SIZE};
+    use hkdf::Hkdf;
+    use openssl::{
+        bn::{BigNum, BigNumContext},
+        derive::Deriver,
+        ec::{EcGroup, EcKey},
+        nid::Nid,
+        pkey::{PKey, Private},
+    };
+    use platform::{default::DefaultPlatform, Platform};
+    use sha2::{Sha256, Sha384, Sha512};
+    use zerocopy::{AsBytes, FromBytes};
+
+    const TEST_OPEN_SESSION_CMD: OpenSessionCmd = OpenSessionCmd {
+        pub_key_x: [1; DPE_PROFILE.get_ecc_int_size()],
+        pub_key_y: [2; DPE_PROFILE.get_ecc_int_size()],
+    };
+
+    const TEST_CLOSE_SESSION_CMD: CloseSessionCmd = CloseSessionCmd { session_id: 1 };
+
+    /// Generates a client ECDH key and the OpenSession command carrying its public key.
+    fn new_client_key() -> (EcKey<Private>, OpenSessionCmd) {
+        let nid = match DPE_PROFILE.alg_len() {
+            AlgLen::Bit256 => Nid::X9_62_PRIME256V1,
+            AlgLen::Bit384 => Nid::SECP384R1,
+            AlgLen::Bit512 => Nid::SECP521R1,
+        };
+        let group = EcGroup::from_curve_name(nid).unwrap();
+        let key = EcKey::generate(&group).unwrap();
+
+        let mut x = BigNum::new().unwrap();
+        let mut y = BigNum::new().unwrap();
+        let mut bn_ctx = BigNumContext::new().unwrap();
+        key.public_key()
+            .affine_coordinates(&group, &mut x, &mut y, &mut bn_ctx)
+            .unwrap();
+
+        let size = DPE_PROFILE.get_ecc_int_size() as i32;
+        let cmd = OpenSessionCmd {
+            pub_key_x: x.to_vec_padded(size).unwrap().try_into().unwrap(),
+            pub_key_y: y.to_vec_padded(size).unwrap().try_into().unwrap(),
+        };
+        (key, cmd)
+    }
+
+    /// Derives the session key on the client side.
+    fn client_session_key(client: &EcKey<Private>, resp: &OpenSessionResp) -> AeadKey {
+        let x = BigNum::from_slice(&resp.pub_key_x).unwrap();
+        let y = BigNum::from_slice(&resp.pub_key_y).unwrap();
+        let server = EcKey::from_public_key_affine_coordinates(client.group(), &x, &y).unwrap();
+        let server = PKey::from_ec_key(server).unwrap();
+        let client = PKey::from_ec_key(client.clone()).unwrap();
+
+        let mut deriver = Deriver::new(&client).unwrap();
+        deriver.set_peer(&server).unwrap();
+        let shared_secret = deriver.derive_to_vec().unwrap();
+
+        let mut key = AeadKey([0; AEAD_KEY_SIZE]);
+        match DPE_PROFILE.alg_len() {
+            AlgLen::Bit256 => Hkdf::<Sha256>::new(None, &shared_secret)
+                .expand(SESSION_KEY_INFO, &mut key.0)
+                .unwrap(),
+            AlgLen::Bit384 => Hkdf::<Sha384>::new(None, &shared_secret)
+                .expand(SESSION_KEY_INFO, &mut key.0)
+                .unwrap(),
+            AlgLen::Bit512 => Hkdf::<Sha512>::new(None, &shared_secret)
+                .expand(SESSION_KEY_INFO, &mut key.0)
+                .unwrap(),
+        }
+        key
+    }
+
+    fn seal_cmd(
+        env: &mut DpeEnv<TestTypes>,
+        key: &AeadKey,
+        session_id: u32,
+        counter: u32,
+        cmd: &[u8],
+    ) -> Vec<u8> {
+        let hdr = SessionMsgHdr {
+            magic: SessionMsgHdr::SESSION_CMD_MAGIC,
+            session_id,
+            counter,
+        };
+        let mut data = cmd.to_vec();
+        let tag = env
+            .crypto
+            .aead_encrypt(key, &hdr.iv(), hdr.as_bytes(), &mut data)
+            .unwrap();
+
+        let mut msg = hdr.as_bytes().to_vec();
+        msg.extend(data);
+        msg.extend(tag);
+        msg
+    }
+
+    fn open_resp(env: &mut DpeEnv<TestTypes>, key: &AeadKey, resp: &SessionResp) -> Vec<u8> {
+        let bytes = resp.msg_bytes();
+        let hdr = SessionMsgHdr::read_from_prefix(bytes).unwrap();
+        assert_eq!(hdr.magic, SessionMsgHdr::SESSION_RESP_MAGIC);
+
+        let body = &bytes[size_of::<SessionMsgHdr>()..];
+        let (ciphertext, tag) = body.split_at(body.len() - AEAD_TAG_SIZE);
+        let mut data = ciphertext.to_vec();
+        env.crypto
+            .aead_decrypt(
+                key,
+                &hdr.iv(),
+                hdr.as_bytes(),
+                &mut data,
+                tag.try_into().unwrap(),
+            )
+            .unwrap();
+        data
+    }
+
+    #[test]
+    fn test_deserialize_open_session() {
+        CfiCounter::reset_for_test();
+        let mut command = CommandHdr::new_for_test(Command::OPEN_SESSION)
+            .as_bytes()
+            .to_vec();
+        command.extend(TEST_OPEN_SESSION_CMD.as_bytes());
+        assert_eq!(
+            Ok(Command::OpenSession(TEST_OPEN_SESSION_CMD)),
+            Command::deserialize(&command)
+        );
+    }
+
+    #[test]
+    fn test_deserialize_close_session() {
+        CfiCounter::reset_for_test();
+        let mut command = CommandHdr::new_for_test(Command::CLOSE_SESSION)
+            .as_bytes()
+            .to_vec();
+        command.extend(TEST_CLOSE_SESSION_CMD.as_bytes());
+        assert_eq!(
+            Ok(Command::CloseSession(TEST_CLOSE_SESSION_CMD)),
+            Command::deserialize(&command)
+        );
+    }
+
+    #[test]
+    fn test_open_close_session() {
+        CfiCounter::reset_for_test();
+        let mut env = DpeEnv::<TestTypes> {
+            crypto: OpensslCrypto::new(),
+            platform: DefaultPlatform,
+            arena: &mut [],
+        };
+        let mut dpe = DpeInstance::new(&mut env, SUPPORT).unwrap();
+        let (_, open_cmd) = new_client_key();
+
+        // Make sure it returns an error if the command is marked unsupported.
+        assert_eq!(
+            Err(DpeErrorCode::InvalidCommand),
+            open_cmd.execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
+        );
+        assert_eq!(
+            Err(DpeErrorCode::InvalidCommand),
+            CloseSessionCmd { session_id: 0 }.execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
+        );
+
+        let mut dpe = DpeInstance::new(&mut env, SUPPORT | Support::SESSIONS).unwrap();
+
+        // A public key that is not on the curve is rejected.
+        assert!(TEST_OPEN_SESSION_CMD
+            .execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
+            .is_err());
+        assert!(!dpe.sessions[0].active());
+
+        // Each locality can only take its share of the session slots.
+        for i in 0..MAX_SESSIONS {
+            let locality = TEST_LOCALITIES[i / MAX_SESSIONS_PER_LOCALITY];
+            match open_cmd.execute(&mut dpe, &mut env, locality).unwrap() {
+                Response::OpenSession(resp) => assert_eq!(resp.session_id, i as u32),
+                _ => panic!("Incorrect response type"),
+            }
+            if (i + 1) % MAX_SESSIONS_PER_LOCALITY == 0 {
+                assert_eq!(
+                    Err(DpeErrorCode::MaxSessions),
+                    open_cmd.execute(&mut dpe, &mut env, locality)
+                );
+            }
+        }
+
+        // Only the locality that opened a session can close it.
+        let session_id = MAX_SESSIONS_PER_LOCALITY - 1;
+        assert_eq!(
+            Err(DpeErrorCode::InvalidLocality),
+            CloseSessionCmd {
+                session_id: session_id as u32
+            }
+            .execute(&mut dpe, &mut env, TEST_LOCALITIES[1])
+        );
+        assert_eq!(
+            Ok(Response::CloseSession(ResponseHdr::new(
+                DpeErrorCode::NoError
+            ))),
+            CloseSessionCmd {
+                session_id: session_id as u32
+            }
+            .execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
+        );
+        assert!(!dpe.sessions[session_id].active());
+        assert_eq!(dpe.sessions[session_id].key, [0; AEAD_KEY_SIZE]);
+
+        // Closed and out of range sessions can't be closed.
+        assert_eq!(
+            Err(DpeErrorCode::InvalidSession),
+            CloseSessionCmd {
+                session_id: session_id as u32
+            }
+            .execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
+        );
+        assert_eq!(
+            Err(DpeErrorCode::InvalidSession),
+            CloseSessionCmd {
+                session_id: MAX_SESSIONS as u32
+            }
+            .execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
+        );
+
+        // The freed slot can be reused.
+        match open_cmd
+            .execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
+            .unwrap()
+        {
+            Response::OpenSession(resp) => assert_eq!(resp.session_id, session_id as u32),
+            _ => panic!("Incorrect response type"),
+        }
+    }
+
+    #[test]
+    fn test_session_round_trip() {
+        CfiCounter::reset_for_test();
+        let mut env = DpeEnv::<TestTypes> {
+            crypto: OpensslCrypto::new(),
+            platform: DefaultPlatform,
+            arena: &mut [],
+        };
+        let mut dpe = DpeInstance::new(&mut env, SUPPORT | Support::SESSIONS).unwrap();
+        let locality = TEST_LOCALITIES[0];
+
+        let (client, open_cmd) = new_client_key();
+        let mut command = CommandHdr::new_for_test(Command::OPEN_SESSION)
+            .as_bytes()
+            .to_vec();
+        command.extend(open_cmd.as_bytes());
+        let open_resp_msg = match dpe
+            .execute_serialized_command(&mut env, locality, &command)
+            .unwrap()
+        {
+            Response::OpenSession(resp) => resp,
+            _ => panic!("Incorrect response type"),
+        };
+        let session_id = open_resp_msg.session_id;
+        let key = client_session_key(&client, &open_resp_msg);
+
+        // The DPE's public key is signed with the alias key.
+        let mut signed = SESSION_SIGN_LABEL.to_vec();
+        signed.extend(open_cmd.pub_key_x);
+        signed.extend(open_cmd.pub_key_y);
+        signed.extend(open_resp_msg.pub_key_x);
+        signed.extend(open_resp_msg.pub_key_y);
+        signed.extend(session_id.to_le_bytes());
+        let digest = env.crypto.hash(DPE_PROFILE.alg_len(), &signed).unwrap();
+        let priv_key = match DPE_PROFILE.alg_len() {
+            AlgLen::Bit256 => EcKey::private_key_from_der(include_bytes!(
+                "../../../platform/src/test_data/key_256.der"
+            )),
+            AlgLen::Bit384 => EcKey::private_key_from_der(include_bytes!(
+                "../../../platform/src/test_data/key_384.der"
+            )),
+            AlgLen::Bit512 => EcKey::private_key_from_der(include_bytes!(
+                "../../../platform/src/test_data/key_521.der"
+            )),
+        }
+        .unwrap();
+        let alias_key = EcKey::from_public_key(priv_key.group(), priv_key.public_key()).unwrap();
+        let sig = openssl::ecdsa::EcdsaSig::from_private_components(
+            BigNum::from_slice(&open_resp_msg.sig_r).unwrap(),
+            BigNum::from_slice(&open_resp_msg.sig_s).unwrap(),
+        )
+        .unwrap();
+        assert!(sig.verify(digest.bytes(), &alias_key).unwrap());
+
+        // Send GetProfile over the session.
+        let get_profile = CommandHdr::new_for_test(Command::GET_PROFILE);
+        let msg = seal_cmd(&mut env, &key, session_id, 0, get_profile.as_bytes());
+        let resp = match dpe
+            .execute_serialized_command(&mut env, locality, &msg)
+            .unwrap()
+        {
+            Response::Session(resp) => resp,
+            _ => panic!("Incorrect response type"),
+        };
+        assert_eq!(resp.hdr.session_id, session_id);
+        assert_eq!(resp.hdr.counter, 0);
+        let expected = GetProfileResp::new(
+            SUPPORT | Support::SESSIONS,
+            env.platform.get_vendor_id().unwrap(),
+            env.platform.get_vendor_sku().unwrap(),
+            MAX_HANDLES as u32,
+            MAX_HANDLES as u32,
+        );
+        assert_eq!(expected.as_bytes(), open_resp(&mut env, &key, &resp));
+
+        // A message can't be replayed.
+        assert_eq!(
+            Response::Error(ResponseHdr::new(DpeErrorCode::InvalidSession)),
+            dpe.execute_serialized_command(&mut env, locality, &msg)
+                .unwrap()
+        );
+
+        // Messages from another locality are rejected.
+        let msg = seal_cmd(&mut env, &key, session_id, 1, get_profile.as_bytes());
+        assert_eq!(
+            Response::Error(ResponseHdr::new(DpeErrorCode::InvalidLocality)),
+            dpe.execute_serialized_command(&mut env, TEST_LOCALITIES[1], &msg)
+                .unwrap()
+        );
+
+        // Tampered messages fail authentication.
+        let mut tampered = msg.clone();
+        let last = tampered.len() - 1;
+        tampered[last] ^= 1;
+        assert_eq!(
+            Response::Error(ResponseHdr::new(DpeErrorCode::Crypto(
+                CryptoError::AuthenticationFailed
+            ))),
+            dpe.execute_serialized_command(&mut env, locality, &tampered)
+                .unwrap()
+        );
+
+        // Rejected messages don't consume the counter, so the original is still accepted.
+        assert!(matches!(
+            dpe.execute_serialized_command(&mut env, locality, &msg)
+                .unwrap(),
+            Response::Session(_)
+        ));
+
+        // Close the session from inside the session.
+        let mut close = CommandHdr::new_for_test(Command::CLOSE_SESSION)
+            .as_bytes()
+            .to_vec();
+        close.extend(CloseSessionCmd { session_id }.as_bytes());
+        let msg = seal_cmd(&mut env, &key, session_id, 2, &close);
+        let resp = match dpe
+            .execute_serialized_command(&mut env, locality, &msg)
+            .unwrap()
+        {
+            Response::Session(resp) => resp,
+            _ => panic!("Incorrect response type"),
+        };
+        assert_eq!(
+            ResponseHdr::new(DpeErrorCode::NoError).as_bytes(),
+            open_resp(&mut env, &key, &resp)
+        );
+
+        // The session is gone.
+        let msg = seal_cmd(&mut env, &key, session_id, 3, get_profile.as_bytes());
+        assert_eq!(
+            Response::Error(ResponseHdr::new(DpeErrorCode::InvalidSession)),
+            dpe.execute_serialized_command(&mut env, locality, &msg)
+                .unwrap()
+        );
+    }
+}
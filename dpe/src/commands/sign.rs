@@ -1,9 +1,9 @@
 // Licensed under the Apache-2.0 license.
-use super::CommandExecution;
+use super::{read_prefix, CommandExecution};
 use crate::{
     context::{ContextHandle, ContextType},
     dpe_instance::{DpeEnv, DpeInstance, DpeTypes},
-    response::{DpeErrorCode, Response, ResponseHdr, SignResp},
+    response::{DpeErrorCode, Response, ResponseHdr, ResponseWriter, SignResp},
     DPE_PROFILE,
 };
 use bitflags::bitflags;
@@ -13,32 +13,96 @@ use caliptra_cfi_lib_git::cfi_launder;
 #[cfg(not(feature = "no-cfi"))]
 use caliptra_cfi_lib_git::{cfi_assert, cfi_assert_eq, cfi_assert_ne};
 use cfg_if::cfg_if;
-use crypto::{Crypto, CryptoBuf, Digest, EcdsaSig, HmacSig};
+#[cfg(not(any(
+    feature = "dpe_profile_mldsa87_sha384",
+    feature = "dpe_profile_ed25519_sha512"
+)))]
+use core::task::Poll;
+#[cfg(not(any(
+    feature = "dpe_profile_mldsa87_sha384",
+    feature = "dpe_profile_ed25519_sha512"
+)))]
+use crypto::EcdsaSig;
+#[cfg(feature = "dpe_profile_ed25519_sha512")]
+use crypto::Ed25519Sig;
+#[cfg(not(feature = "dpe_profile_mldsa87_sha384"))]
+use crypto::HmacSig;
+#[cfg(feature = "dpe_profile_mldsa87_sha384")]
+use crypto::MlDsaSig;
+use crypto::{destroy_cdi_after_derive, Crypto, Digest, Hasher};
 
 #[repr(C)]
 #[derive(Debug, PartialEq, Eq, zerocopy::AsBytes, zerocopy::FromBytes)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct SignFlags(u32);
 
 bitflags! {
     impl SignFlags: u32 {
         const IS_SYMMETRIC = 1u32 << 30;
+        const IS_MESSAGE = 1u32 << 29;
     }
 }
 
+impl_wire_endian!(flags SignFlags);
+
+/// Signs a digest with a key derived from the context's CDI.
+///
+/// When `SignFlags::IS_MESSAGE` is set, `digest` is ignored and the command is
+/// followed by a little-endian `u32` length and that many bytes of message,
+/// which the DPE hashes with the profile's hash algorithm before signing.
 #[repr(C)]
 #[derive(Debug, PartialEq, Eq, zerocopy::AsBytes, zerocopy::FromBytes)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct SignCmd {
     pub handle: ContextHandle,
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_hex"))]
     pub label: [u8; DPE_PROFILE.get_hash_size()],
     pub flags: SignFlags,
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_hex"))]
     pub digest: [u8; DPE_PROFILE.get_hash_size()],
 }
 
+impl_wire_endian!(SignCmd { flags });
+
 impl SignCmd {
     const fn uses_symmetric(&self) -> bool {
         self.flags.contains(SignFlags::IS_SYMMETRIC)
     }
 
+    const fn uses_message(&self) -> bool {
+        self.flags.contains(SignFlags::IS_MESSAGE)
+    }
+
+    /// Returns the message carried in `trailer`, the bytes that follow the
+    /// command. Digest-mode commands carry no message and ignore the trailer.
+    ///
+    /// # Arguments
+    ///
+    /// * `trailer` - Serialized bytes following the command
+    pub(crate) fn message<'a>(&self, trailer: &'a [u8]) -> Result<&'a [u8], DpeErrorCode> {
+        if !self.uses_message() {
+            return Ok(&[]);
+        }
+        let (size, message) = read_prefix::<u32>(trailer).ok_or(DpeErrorCode::InvalidArgument)?;
+        message
+            .get(..size as usize)
+            .ok_or(DpeErrorCode::InvalidArgument)
+    }
+
+    /// Returns the digest to sign, hashing `message` if the command carries one.
+    fn digest_to_sign(
+        &self,
+        env: &mut DpeEnv<impl DpeTypes>,
+        message: &[u8],
+    ) -> Result<Digest, DpeErrorCode> {
+        if !self.uses_message() {
+            return Ok(Digest::new(&self.digest)?);
+        }
+        let mut hasher = env.crypto.hash_initialize(DPE_PROFILE.alg_len())?;
+        hasher.update(message)?;
+        Ok(hasher.finish()?)
+    }
+
     /// Signs `digest` using ECDSA
     ///
     /// # Arguments
@@ -47,6 +111,11 @@ impl SignCmd {
     /// * `env` - DPE environment containing Crypto and Platform implementations
     /// * `idx` - The index of the context where the measurement hash is computed from
     /// * `digest` - The data to be signed
+    /// * `defer` - Whether the crypto implementation may finish the signature later
+    #[cfg(not(any(
+        feature = "dpe_profile_mldsa87_sha384",
+        feature = "dpe_profile_ed25519_sha512"
+    )))]
     #[cfg_attr(not(feature = "no-cfi"), cfi_impl_fn)]
     fn ecdsa_sign(
         &self,
@@ -54,12 +123,10 @@ impl SignCmd {
         env: &mut DpeEnv<impl DpeTypes>,
         idx: usize,
         digest: &Digest,
-    ) -> Result<EcdsaSig, DpeErrorCode> {
+        defer: bool,
+    ) -> Result<Poll<EcdsaSig>, DpeErrorCode> {
         let algs = DPE_PROFILE.alg_len();
-        let cdi_digest = dpe.compute_measurement_hash(env, idx)?;
-        let cdi = env
-            .crypto
-            .derive_cdi(DPE_PROFILE.alg_len(), &cdi_digest, b"DPE")?;
+        let (_, cdi) = dpe.derive_cdi(env, idx)?;
         let key_pair = env.crypto.derive_key_pair(algs, &cdi, &self.label, b"ECC");
         if cfi_launder(key_pair.is_ok()) {
             #[cfg(not(feature = "no-cfi"))]
@@ -68,13 +135,175 @@ impl SignCmd {
             #[cfg(not(feature = "no-cfi"))]
             cfi_assert!(key_pair.is_err());
         }
-        let (priv_key, pub_key) = key_pair?;
+        let (priv_key, pub_key) = destroy_cdi_after_derive(&mut env.crypto, cdi, key_pair)?;
+
+        let sig = if defer {
+            env.crypto
+                .ecdsa_sign_with_derived_start(algs, digest, &priv_key, &pub_key)
+        } else {
+            env.crypto
+                .ecdsa_sign_with_derived(algs, digest, &priv_key, &pub_key)
+                .map(Poll::Ready)
+        };
+        env.crypto.destroy_key(priv_key)?;
+
+        Ok(sig?)
+    }
+
+    /// Splits an ECDSA signature into the R and S fields of the response.
+    #[cfg(not(any(
+        feature = "dpe_profile_mldsa87_sha384",
+        feature = "dpe_profile_ed25519_sha512"
+    )))]
+    fn ecdsa_sig_fields(
+        sig: EcdsaSig,
+    ) -> Result<
+        (
+            [u8; DPE_PROFILE.get_ecc_int_size()],
+            [u8; DPE_PROFILE.get_ecc_int_size()],
+        ),
+        DpeErrorCode,
+    > {
+        let EcdsaSig { r, s } = sig;
+
+        let sig_r: [u8; DPE_PROFILE.get_ecc_int_size()] = r
+            .bytes()
+            .try_into()
+            .map_err(|_| DpeErrorCode::InternalError)?;
+
+        let sig_s: [u8; DPE_PROFILE.get_ecc_int_size()] = s
+            .bytes()
+            .try_into()
+            .map_err(|_| DpeErrorCode::InternalError)?;
+
+        Ok((sig_r, sig_s))
+    }
+
+    /// Finishes a Sign command left pending by `start_with_message` once the
+    /// crypto implementation has the signature, returning `None` while it
+    /// is still being computed.
+    ///
+    /// # Arguments
+    ///
+    /// * `dpe` - DPE instance
+    /// * `env` - DPE environment containing Crypto and Platform implementations
+    /// * `idx` - The index of the context the signature is for
+    #[cfg(not(any(
+        feature = "dpe_profile_mldsa87_sha384",
+        feature = "dpe_profile_ed25519_sha512"
+    )))]
+    pub(crate) fn poll(
+        dpe: &mut DpeInstance,
+        env: &mut DpeEnv<impl DpeTypes>,
+        idx: usize,
+    ) -> Result<Option<Response>, DpeErrorCode> {
+        let sig = match env.crypto.ecdsa_sign_poll(DPE_PROFILE.alg_len())? {
+            Poll::Ready(sig) => sig,
+            Poll::Pending => return Ok(None),
+        };
+        let (sig_r, sig_s) = Self::ecdsa_sig_fields(sig)?;
+
+        // Rotate the handle if it isn't the default context.
+        dpe.roll_onetime_use_handle(env, idx)?;
+
+        Ok(Some(Response::Sign(Self::sign_resp(
+            dpe, idx, sig_r, sig_s,
+        ))))
+    }
+
+    /// Only ECDSA signatures are ever left pending, so there is nothing to
+    /// finish with other profiles.
+    #[cfg(any(
+        feature = "dpe_profile_mldsa87_sha384",
+        feature = "dpe_profile_ed25519_sha512"
+    ))]
+    pub(crate) fn poll(
+        _dpe: &mut DpeInstance,
+        _env: &mut DpeEnv<impl DpeTypes>,
+        _idx: usize,
+    ) -> Result<Option<Response>, DpeErrorCode> {
+        Err(DpeErrorCode::InternalError)
+    }
+
+    /// Signs `digest` using Ed25519
+    ///
+    /// Ed25519 is used without prehashing, so the digest bytes are the
+    /// message that gets signed.
+    ///
+    /// # Arguments
+    ///
+    /// * `dpe` - DPE instance
+    /// * `env` - DPE environment containing Crypto and Platform implementations
+    /// * `idx` - The index of the context where the measurement hash is computed from
+    /// * `digest` - The data to be signed
+    #[cfg(feature = "dpe_profile_ed25519_sha512")]
+    #[cfg_attr(not(feature = "no-cfi"), cfi_impl_fn)]
+    fn ed25519_sign(
+        &self,
+        dpe: &mut DpeInstance,
+        env: &mut DpeEnv<impl DpeTypes>,
+        idx: usize,
+        digest: &Digest,
+    ) -> Result<Ed25519Sig, DpeErrorCode> {
+        let algs = DPE_PROFILE.alg_len();
+        let (_, cdi) = dpe.derive_cdi(env, idx)?;
+        let key_pair = env
+            .crypto
+            .derive_ed25519_key_pair(algs, &cdi, &self.label, b"ED25519");
+        if cfi_launder(key_pair.is_ok()) {
+            #[cfg(not(feature = "no-cfi"))]
+            cfi_assert!(key_pair.is_ok());
+        } else {
+            #[cfg(not(feature = "no-cfi"))]
+            cfi_assert!(key_pair.is_err());
+        }
+        let (priv_key, pub_key) = destroy_cdi_after_derive(&mut env.crypto, cdi, key_pair)?;
 
         let sig = env
             .crypto
-            .ecdsa_sign_with_derived(algs, digest, &priv_key, &pub_key)?;
+            .ed25519_sign_with_derived(digest.bytes(), &priv_key, &pub_key);
+        env.crypto.destroy_key(priv_key)?;
 
-        Ok(sig)
+        Ok(sig?)
+    }
+
+    /// Signs `digest` using ML-DSA-87
+    ///
+    /// # Arguments
+    ///
+    /// * `dpe` - DPE instance
+    /// * `env` - DPE environment containing Crypto and Platform implementations
+    /// * `idx` - The index of the context where the measurement hash is computed from
+    /// * `digest` - The data to be signed
+    #[cfg(feature = "dpe_profile_mldsa87_sha384")]
+    #[cfg_attr(not(feature = "no-cfi"), cfi_impl_fn)]
+    fn mldsa_sign(
+        &self,
+        dpe: &mut DpeInstance,
+        env: &mut DpeEnv<impl DpeTypes>,
+        idx: usize,
+        digest: &Digest,
+    ) -> Result<MlDsaSig, DpeErrorCode> {
+        let algs = DPE_PROFILE.alg_len();
+        let (_, cdi) = dpe.derive_cdi(env, idx)?;
+        let key_pair = env
+            .crypto
+            .derive_mldsa_key_pair(algs, &cdi, &self.label, b"MLDSA");
+        if cfi_launder(key_pair.is_ok()) {
+            #[cfg(not(feature = "no-cfi"))]
+            cfi_assert!(key_pair.is_ok());
+        } else {
+            #[cfg(not(feature = "no-cfi"))]
+            cfi_assert!(key_pair.is_err());
+        }
+        let (priv_key, pub_key) = destroy_cdi_after_derive(&mut env.crypto, cdi, key_pair)?;
+
+        let sig = env
+            .crypto
+            .mldsa_sign_with_derived(digest, &priv_key, &pub_key);
+        env.crypto.destroy_key(priv_key)?;
+
+        Ok(sig?)
     }
 
     /// Signs `digest` using an HMAC
@@ -85,6 +314,7 @@ impl SignCmd {
     /// * `env` - DPE environment containing Crypto and Platform implementations
     /// * `idx` - The index of the context where the measurement hash is computed from
     /// * `digest` - The data to be signed
+    #[cfg(not(feature = "dpe_profile_mldsa87_sha384"))]
     #[cfg_attr(not(feature = "no-cfi"), cfi_impl_fn)]
     fn hmac_sign(
         &self,
@@ -94,10 +324,7 @@ impl SignCmd {
         digest: &Digest,
     ) -> Result<HmacSig, DpeErrorCode> {
         let algs = DPE_PROFILE.alg_len();
-        let cdi_digest = dpe.compute_measurement_hash(env, idx)?;
-        let cdi = env
-            .crypto
-            .derive_cdi(DPE_PROFILE.alg_len(), &cdi_digest, b"DPE");
+        let cdi = dpe.derive_cdi(env, idx).map(|(_, cdi)| cdi);
         if cfi_launder(cdi.is_ok()) {
             #[cfg(not(feature = "no-cfi"))]
             cfi_assert!(cdi.is_ok());
@@ -105,9 +332,14 @@ impl SignCmd {
             #[cfg(not(feature = "no-cfi"))]
             cfi_assert!(cdi.is_err());
         }
-        Ok(env
+        let cdi = cdi?;
+
+        let sig = env
             .crypto
-            .hmac_sign_with_derived(algs, &cdi?, &self.label, b"HMAC", digest)?)
+            .hmac_sign_with_derived(algs, &cdi, &self.label, b"HMAC", digest);
+        env.crypto.destroy_cdi(cdi)?;
+
+        Ok(sig?)
     }
 }
 
@@ -119,6 +351,86 @@ impl CommandExecution for SignCmd {
         env: &mut DpeEnv<impl DpeTypes>,
         locality: u32,
     ) -> Result<Response, DpeErrorCode> {
+        // The command alone has no message to hash. It only comes with
+        // `execute_with_message`.
+        if self.uses_message() {
+            return Err(DpeErrorCode::InvalidArgument);
+        } else {
+            #[cfg(not(feature = "no-cfi"))]
+            cfi_assert!(!self.uses_message());
+        }
+        self.execute_with_message(dpe, env, locality, &[])
+    }
+}
+
+impl SignCmd {
+    /// Executes the command, signing the hash of `message` instead of
+    /// `digest` if `SignFlags::IS_MESSAGE` is set.
+    ///
+    /// # Arguments
+    ///
+    /// * `dpe` - DPE instance
+    /// * `env` - DPE environment containing Crypto and Platform implementations
+    /// * `locality` - which hardware locality is making the request
+    /// * `message` - Message that followed the command
+    #[cfg_attr(not(feature = "no-cfi"), cfi_impl_fn)]
+    pub(crate) fn execute_with_message(
+        &self,
+        dpe: &mut DpeInstance,
+        env: &mut DpeEnv<impl DpeTypes>,
+        locality: u32,
+        message: &[u8],
+    ) -> Result<Response, DpeErrorCode> {
+        self.sign(dpe, env, locality, message, false)
+    }
+
+    /// Executes the command like `execute_with_message`, but lets the crypto
+    /// implementation finish an ECDSA signature in the background.
+    ///
+    /// If it does, the command is recorded as pending and this fails with
+    /// `DpeErrorCode::Busy`. The response comes from `SignCmd::poll` once the
+    /// signature is ready.
+    ///
+    /// # Arguments
+    ///
+    /// * `dpe` - DPE instance
+    /// * `env` - DPE environment containing Crypto and Platform implementations
+    /// * `locality` - which hardware locality is making the request
+    /// * `message` - Message that followed the command
+    #[cfg_attr(not(feature = "no-cfi"), cfi_impl_fn)]
+    pub(crate) fn start_with_message(
+        &self,
+        dpe: &mut DpeInstance,
+        env: &mut DpeEnv<impl DpeTypes>,
+        locality: u32,
+        message: &[u8],
+    ) -> Result<Response, DpeErrorCode> {
+        self.sign(dpe, env, locality, message, true)
+    }
+
+    #[cfg_attr(not(feature = "no-cfi"), cfi_impl_fn)]
+    #[cfg_attr(
+        any(
+            feature = "dpe_profile_mldsa87_sha384",
+            feature = "dpe_profile_ed25519_sha512"
+        ),
+        allow(unused_variables)
+    )]
+    fn sign(
+        &self,
+        dpe: &mut DpeInstance,
+        env: &mut DpeEnv<impl DpeTypes>,
+        locality: u32,
+        message: &[u8],
+        defer: bool,
+    ) -> Result<Response, DpeErrorCode> {
+        dpe_trace!(
+            "Sign flags={:#x} message_size={} locality={:#x}",
+            self.flags.bits(),
+            message.len(),
+            locality
+        );
+
         // Make sure the operation is supported.
         if !dpe.support.is_symmetric() && self.uses_symmetric() {
             return Err(DpeErrorCode::ArgumentNotSupported);
@@ -138,36 +450,96 @@ impl CommandExecution for SignCmd {
             }
         }
 
-        let algs = DPE_PROFILE.alg_len();
-        let digest = Digest::new(&self.digest)?;
+        let digest = self.digest_to_sign(env, message)?;
+
+        #[cfg(not(feature = "dpe_profile_mldsa87_sha384"))]
+        let (sig_r_or_hmac, sig_s) = if !self.uses_symmetric() {
+            #[cfg(not(feature = "dpe_profile_ed25519_sha512"))]
+            let (sig_r, sig_s) = match self.ecdsa_sign(dpe, env, idx, &digest, defer)? {
+                Poll::Ready(sig) => Self::ecdsa_sig_fields(sig)?,
+                Poll::Pending => {
+                    // The DPE is busy until the signature is collected.
+                    let mut token = [0u8; 4];
+                    if let Err(err) = env.crypto.rand_bytes(&mut token) {
+                        env.crypto.ecdsa_sign_cancel();
+                        return Err(err.into());
+                    }
+                    dpe.pending_op
+                        .start(idx, locality, u32::from_le_bytes(token));
+                    return Err(DpeErrorCode::Busy);
+                }
+            };
+
+            // R and S are 32 bytes, shorter than the fields, which are sized
+            // for the P-521 alias key. Like an HMAC, each fills the start of
+            // its field and the rest is zero.
+            #[cfg(feature = "dpe_profile_ed25519_sha512")]
+            let (sig_r, sig_s) = {
+                let sig = self.ed25519_sign(dpe, env, idx, &digest)?;
+                let (r, s) = sig.r_and_s();
+                let mut sig_r = [0u8; DPE_PROFILE.get_ecc_int_size()];
+                let mut sig_s = [0u8; DPE_PROFILE.get_ecc_int_size()];
+                ResponseWriter::new(&mut sig_r).write(r)?;
+                ResponseWriter::new(&mut sig_s).write(s)?;
 
-        let EcdsaSig { r, s } = if !self.uses_symmetric() {
-            self.ecdsa_sign(dpe, env, idx, &digest)?
+                (sig_r, sig_s)
+            };
+
+            (sig_r, sig_s)
         } else {
-            let r = self.hmac_sign(dpe, env, idx, &digest)?;
-            let s = CryptoBuf::default(algs);
-            EcdsaSig { r, s }
-        };
+            // The HMAC is as long as the digest, which is shorter than the
+            // field for P-521. It fills the start of the field and the rest
+            // is zero.
+            let hmac = self.hmac_sign(dpe, env, idx, &digest)?;
+            let mut sig_r_or_hmac = [0u8; DPE_PROFILE.get_ecc_int_size()];
+            ResponseWriter::new(&mut sig_r_or_hmac).write(hmac.bytes())?;
 
-        let sig_r_or_hmac: [u8; DPE_PROFILE.get_ecc_int_size()] = r
-            .bytes()
-            .try_into()
-            .map_err(|_| DpeErrorCode::InternalError)?;
+            (sig_r_or_hmac, [0u8; DPE_PROFILE.get_ecc_int_size()])
+        };
 
-        let sig_s: [u8; DPE_PROFILE.get_ecc_int_size()] = s
-            .bytes()
-            .try_into()
-            .map_err(|_| DpeErrorCode::InternalError)?;
+        #[cfg(feature = "dpe_profile_mldsa87_sha384")]
+        let sig: [u8; MlDsaSig::SIZE] = {
+            // An HMAC does not fit the ML-DSA response layout.
+            if self.uses_symmetric() {
+                return Err(DpeErrorCode::ArgumentNotSupported);
+            }
+            self.mldsa_sign(dpe, env, idx, &digest)?
+                .bytes()
+                .try_into()
+                .map_err(|_| DpeErrorCode::InternalError)?
+        };
 
         // Rotate the handle if it isn't the default context.
         dpe.roll_onetime_use_handle(env, idx)?;
 
-        Ok(Response::Sign(SignResp {
+        #[cfg(not(feature = "dpe_profile_mldsa87_sha384"))]
+        let resp = Self::sign_resp(dpe, idx, sig_r_or_hmac, sig_s);
+
+        #[cfg(feature = "dpe_profile_mldsa87_sha384")]
+        let resp = SignResp {
+            new_context_handle: dpe.contexts[idx].handle,
+            sig,
+            reserved: [0; 1],
+            resp_hdr: ResponseHdr::new(DpeErrorCode::NoError),
+        };
+
+        Ok(Response::Sign(resp))
+    }
+
+    /// Returns the response carrying the signature for the context at `idx`.
+    #[cfg(not(feature = "dpe_profile_mldsa87_sha384"))]
+    fn sign_resp(
+        dpe: &DpeInstance,
+        idx: usize,
+        sig_r_or_hmac: [u8; DPE_PROFILE.get_ecc_int_size()],
+        sig_s: [u8; DPE_PROFILE.get_ecc_int_size()],
+    ) -> SignResp {
+        SignResp {
             new_context_handle: dpe.contexts[idx].handle,
             sig_r_or_hmac,
             sig_s,
             resp_hdr: ResponseHdr::new(DpeErrorCode::NoError),
-        }))
+        }
     }
 }
 
@@ -186,8 +558,10 @@ mod tests {
         support::{test::SUPPORT, Support},
     };
     use caliptra_cfi_lib_git::CfiCounter;
+    use core::mem::size_of;
     use crypto::OpensslCrypto;
     use openssl::x509::X509;
+    #[cfg(not(feature = "dpe_profile_ed25519_sha512"))]
     use openssl::{bn::BigNum, ecdsa::EcdsaSig};
     use platform::default::DefaultPlatform;
     use zerocopy::AsBytes;
@@ -205,7 +579,45 @@ mod tests {
         let mut command = CommandHdr::new_for_test(Command::SIGN).as_bytes().to_vec();
         command.extend(TEST_SIGN_CMD.as_bytes());
         assert_eq!(
-            Ok(Command::Sign(TEST_SIGN_CMD)),
+            Ok(Command::Sign(TEST_SIGN_CMD, &[])),
+            Command::deserialize(&command)
+        );
+    }
+
+    #[test]
+    fn test_deserialize_sign_message() {
+        CfiCounter::reset_for_test();
+        let cmd = SignCmd {
+            flags: SignFlags::IS_MESSAGE,
+            ..TEST_SIGN_CMD
+        };
+        let message = b"message to sign";
+        let mut command = CommandHdr::new_for_test(Command::SIGN).as_bytes().to_vec();
+        command.extend(cmd.as_bytes());
+        command.extend((message.len() as u32).to_le_bytes());
+        command.extend(message);
+        assert_eq!(
+            Ok(Command::Sign(
+                SignCmd {
+                    flags: SignFlags::IS_MESSAGE,
+                    ..TEST_SIGN_CMD
+                },
+                &message[..]
+            )),
+            Command::deserialize(&command)
+        );
+
+        // The message must be as long as its size says.
+        command.pop();
+        assert_eq!(
+            Err(DpeErrorCode::InvalidArgument),
+            Command::deserialize(&command)
+        );
+
+        // And the size itself must be present.
+        command.truncate(size_of::<CommandHdr>() + size_of::<SignCmd>() + 2);
+        assert_eq!(
+            Err(DpeErrorCode::InvalidArgument),
             Command::deserialize(&command)
         );
     }
@@ -228,12 +640,31 @@ mod tests {
         .uses_symmetric());
     }
 
+    #[test]
+    fn test_uses_message() {
+        CfiCounter::reset_for_test();
+        // No flags set.
+        assert!(!SignCmd {
+            flags: SignFlags::empty(),
+            ..TEST_SIGN_CMD
+        }
+        .uses_message());
+
+        // Just is-message flag set.
+        assert!(SignCmd {
+            flags: SignFlags::IS_MESSAGE,
+            ..TEST_SIGN_CMD
+        }
+        .uses_message());
+    }
+
     #[test]
     fn test_bad_command_inputs() {
         CfiCounter::reset_for_test();
         let mut env = DpeEnv::<TestTypes> {
             crypto: OpensslCrypto::new(),
             platform: DefaultPlatform,
+            arena: &mut [],
         };
         let mut dpe = DpeInstance::new(&mut env, SUPPORT).unwrap();
 
@@ -249,6 +680,18 @@ mod tests {
             .execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
         );
 
+        // A message without the bytes that carry it.
+        assert_eq!(
+            Err(DpeErrorCode::InvalidArgument),
+            SignCmd {
+                handle: ContextHandle::default(),
+                label: TEST_LABEL,
+                flags: SignFlags::IS_MESSAGE,
+                digest: TEST_DIGEST
+            }
+            .execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
+        );
+
         // Bad handle.
         assert_eq!(
             Err(DpeErrorCode::InvalidHandle),
@@ -295,12 +738,14 @@ mod tests {
         );
     }
 
+    #[cfg(not(feature = "dpe_profile_ed25519_sha512"))]
     #[test]
     fn test_asymmetric() {
         CfiCounter::reset_for_test();
         let mut env = DpeEnv::<TestTypes> {
             crypto: OpensslCrypto::new(),
             platform: DefaultPlatform,
+            arena: &mut [],
         };
         let mut dpe = DpeInstance::new(&mut env, SUPPORT).unwrap();
 
@@ -355,12 +800,147 @@ mod tests {
         assert!(sig.verify(&TEST_DIGEST, &ec_pub_key).unwrap());
     }
 
+    #[cfg(feature = "dpe_profile_ed25519_sha512")]
+    #[test]
+    fn test_ed25519() {
+        CfiCounter::reset_for_test();
+        let mut env = DpeEnv::<TestTypes> {
+            crypto: OpensslCrypto::new(),
+            platform: DefaultPlatform,
+            arena: &mut [],
+        };
+        let mut dpe = DpeInstance::new(&mut env, SUPPORT).unwrap();
+
+        let cmd = SignCmd {
+            handle: ContextHandle::default(),
+            label: TEST_LABEL,
+            flags: SignFlags::empty(),
+            digest: TEST_DIGEST,
+        };
+        let resp = match cmd.execute(&mut dpe, &mut env, TEST_LOCALITIES[0]).unwrap() {
+            Response::Sign(resp) => resp,
+            _ => panic!("Incorrect response type"),
+        };
+        // R and S are zero-padded to the width of the fields.
+        assert!(resp.sig_r_or_hmac[32..].iter().all(|&b| b == 0));
+        assert!(resp.sig_s[32..].iter().all(|&b| b == 0));
+        let mut sig = resp.sig_r_or_hmac[..32].to_vec();
+        sig.extend_from_slice(&resp.sig_s[..32]);
+
+        let pub_key = {
+            let cmd = CertifyKeyCmd {
+                handle: ContextHandle::default(),
+                flags: CertifyKeyFlags::empty(),
+                label: TEST_LABEL,
+                format: CertifyKeyCmd::FORMAT_X509,
+            };
+            let certify_resp = match cmd.execute(&mut dpe, &mut env, TEST_LOCALITIES[0]).unwrap() {
+                Response::CertifyKey(resp) => resp,
+                _ => panic!("Incorrect response type"),
+            };
+            let x509 =
+                X509::from_der(&certify_resp.cert[..certify_resp.cert_size.try_into().unwrap()])
+                    .unwrap();
+            x509.public_key().unwrap()
+        };
+
+        // The digest is signed as the message, without prehashing.
+        let mut verifier = openssl::sign::Verifier::new_without_digest(&pub_key).unwrap();
+        assert!(verifier.verify_oneshot(&sig, &TEST_DIGEST).unwrap());
+    }
+
+    #[cfg(not(any(
+        feature = "dpe_profile_mldsa87_sha384",
+        feature = "dpe_profile_ed25519_sha512"
+    )))]
+    #[test]
+    fn test_derived_key_is_per_context() {
+        CfiCounter::reset_for_test();
+        let mut env = DpeEnv::<TestTypes> {
+            crypto: OpensslCrypto::new(),
+            platform: DefaultPlatform,
+            arena: &mut [],
+        };
+        let mut dpe = DpeInstance::new(&mut env, SUPPORT).unwrap();
+
+        /// Signs with the default context and returns the signature along
+        /// with the public key derived from that context's CDI.
+        fn sign(
+            dpe: &mut DpeInstance,
+            env: &mut DpeEnv<TestTypes>,
+        ) -> (crypto::EcdsaPub, crypto::EcdsaSig) {
+            let cmd = SignCmd {
+                handle: ContextHandle::default(),
+                label: TEST_LABEL,
+                flags: SignFlags::empty(),
+                digest: TEST_DIGEST,
+            };
+            let resp = match cmd.execute(dpe, env, TEST_LOCALITIES[0]) {
+                Ok(Response::Sign(resp)) => resp,
+                _ => panic!("Incorrect response type"),
+            };
+            let idx = dpe
+                .get_active_context_pos(&ContextHandle::default(), TEST_LOCALITIES[0])
+                .unwrap();
+            let (_, cdi) = dpe.derive_cdi(env, idx).unwrap();
+            let (_, pub_key) = env
+                .crypto
+                .derive_key_pair(DPE_PROFILE.alg_len(), &cdi, &TEST_LABEL, b"ECC")
+                .unwrap();
+            let sig = crypto::EcdsaSig {
+                r: crypto::CryptoBuf::new(&resp.sig_r_or_hmac).unwrap(),
+                s: crypto::CryptoBuf::new(&resp.sig_s).unwrap(),
+            };
+            (pub_key, sig)
+        }
+
+        DeriveContextCmd {
+            handle: ContextHandle::default(),
+            data: [0; DPE_PROFILE.get_hash_size()],
+            flags: DeriveContextFlags::MAKE_DEFAULT | DeriveContextFlags::INPUT_ALLOW_X509,
+            tci_type: 0,
+            target_locality: 0,
+        }
+        .execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
+        .unwrap();
+        let (parent_key, parent_sig) = sign(&mut dpe, &mut env);
+
+        DeriveContextCmd {
+            handle: ContextHandle::default(),
+            data: [1; DPE_PROFILE.get_hash_size()],
+            flags: DeriveContextFlags::MAKE_DEFAULT | DeriveContextFlags::INPUT_ALLOW_X509,
+            tci_type: 1,
+            target_locality: 0,
+        }
+        .execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
+        .unwrap();
+        let (child_key, child_sig) = sign(&mut dpe, &mut env);
+
+        // Each context signs with a key derived from its own CDI.
+        assert_ne!(parent_key.x.bytes(), child_key.x.bytes());
+        let algs = DPE_PROFILE.alg_len();
+        let digest = Digest::new(&TEST_DIGEST).unwrap();
+        assert!(env
+            .crypto
+            .ecdsa_verify(algs, &digest, &parent_key, &parent_sig)
+            .is_ok());
+        assert!(env
+            .crypto
+            .ecdsa_verify(algs, &digest, &child_key, &child_sig)
+            .is_ok());
+        assert!(env
+            .crypto
+            .ecdsa_verify(algs, &digest, &child_key, &parent_sig)
+            .is_err());
+    }
+
     #[test]
     fn test_symmetric() {
         CfiCounter::reset_for_test();
         let mut env = DpeEnv::<TestTypes> {
             crypto: OpensslCrypto::new(),
             platform: DefaultPlatform,
+            arena: &mut [],
         };
         let mut dpe =
             DpeInstance::new(&mut env, Support::AUTO_INIT | Support::IS_SYMMETRIC).unwrap();
@@ -379,14 +959,69 @@ mod tests {
         let idx = dpe
             .get_active_context_pos(&ContextHandle::default(), TEST_LOCALITIES[0])
             .unwrap();
-        // Check that r is equal to the HMAC over the digest
-        assert_eq!(
-            resp.sig_r_or_hmac,
-            cmd.hmac_sign(&mut dpe, &mut env, idx, &Digest::new(&TEST_DIGEST).unwrap(),)
-                .unwrap()
-                .bytes()
-        );
+        // Check that r starts with the HMAC over the digest and is padded
+        // with 0s
+        let hmac = cmd
+            .hmac_sign(&mut dpe, &mut env, idx, &Digest::new(&TEST_DIGEST).unwrap())
+            .unwrap();
+        assert_eq!(&resp.sig_r_or_hmac[..hmac.len()], hmac.bytes());
+        assert!(resp.sig_r_or_hmac[hmac.len()..].iter().all(|&b| b == 0x0));
         // Check that s is a buffer of all 0s
         assert!(&resp.sig_s.iter().all(|&b| b == 0x0));
     }
+
+    #[test]
+    fn test_sign_message() {
+        CfiCounter::reset_for_test();
+        let mut env = DpeEnv::<TestTypes> {
+            crypto: OpensslCrypto::new(),
+            platform: DefaultPlatform,
+            arena: &mut [],
+        };
+        let mut dpe =
+            DpeInstance::new(&mut env, Support::AUTO_INIT | Support::IS_SYMMETRIC).unwrap();
+        let message = b"a message longer than a single digest, hashed by the DPE itself";
+
+        let mut serialized = CommandHdr::new_for_test(Command::SIGN).as_bytes().to_vec();
+        serialized.extend(
+            SignCmd {
+                handle: ContextHandle::default(),
+                label: TEST_LABEL,
+                flags: SignFlags::IS_SYMMETRIC | SignFlags::IS_MESSAGE,
+                digest: TEST_DIGEST,
+            }
+            .as_bytes(),
+        );
+        serialized.extend((message.len() as u32).to_le_bytes());
+        serialized.extend(message);
+        let resp = match dpe
+            .execute_serialized_command(&mut env, TEST_LOCALITIES[0], &serialized)
+            .unwrap()
+        {
+            Response::Sign(resp) => resp,
+            resp => panic!("Unexpected response {:?}", resp.as_bytes()),
+        };
+
+        // The HMAC is the same as signing the message's digest directly.
+        let digest = env
+            .crypto
+            .hash(DPE_PROFILE.alg_len(), message)
+            .unwrap()
+            .bytes()
+            .try_into()
+            .unwrap();
+        let expected = match (SignCmd {
+            handle: ContextHandle::default(),
+            label: TEST_LABEL,
+            flags: SignFlags::IS_SYMMETRIC,
+            digest,
+        })
+        .execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
+        .unwrap()
+        {
+            Response::Sign(resp) => resp,
+            _ => panic!("Incorrect response type"),
+        };
+        assert_eq!(expected.sig_r_or_hmac, resp.sig_r_or_hmac);
+    }
 }
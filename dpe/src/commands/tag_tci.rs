@@ -0,0 +1,286 @@
+// Licensed under the Apache-2.0 license.
+use super::CommandExecution;
+use crate::{
+    context::{ContextHandle, ContextState},
+    dpe_instance::{DpeEnv, DpeInstance, DpeTypes},
+    response::{DpeErrorCode, GetTaggedTciResp, NewHandleResp, Response, ResponseHdr},
+};
+#[cfg(not(feature = "no-cfi"))]
+use caliptra_cfi_derive_git::cfi_impl_fn;
+#[cfg(not(feature = "no-cfi"))]
+use caliptra_cfi_lib_git::cfi_assert;
+use caliptra_cfi_lib_git::cfi_launder;
+
+#[repr(C)]
+#[derive(Debug, PartialEq, Eq, zerocopy::FromBytes, zerocopy::AsBytes)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TagTciCmd {
+    pub handle: ContextHandle,
+    pub tag: u32,
+}
+
+impl_wire_endian!(TagTciCmd { tag });
+
+impl CommandExecution for TagTciCmd {
+    #[cfg_attr(not(feature = "no-cfi"), cfi_impl_fn)]
+    fn execute(
+        &self,
+        dpe: &mut DpeInstance,
+        env: &mut DpeEnv<impl DpeTypes>,
+        locality: u32,
+    ) -> Result<Response, DpeErrorCode> {
+        dpe_trace!("TagTci tag={:#x} locality={:#x}", self.tag, locality);
+
+        // Make sure this command is supported.
+        if !dpe.support.tagging() {
+            return Err(DpeErrorCode::InvalidCommand);
+        } else {
+            #[cfg(not(feature = "no-cfi"))]
+            cfi_assert!(dpe.support.tagging());
+        }
+
+        // Make sure the tag isn't used by any other contexts.
+        let tag_in_use = dpe
+            .contexts
+            .iter()
+            .any(|c| c.state != ContextState::Inactive && c.has_tag() && c.tag == self.tag);
+        if cfi_launder(tag_in_use) {
+            return Err(DpeErrorCode::BadTag);
+        } else {
+            #[cfg(not(feature = "no-cfi"))]
+            cfi_assert!(!tag_in_use);
+        }
+
+        let idx = dpe.get_active_context_pos(&self.handle, locality)?;
+
+        // A context can only be tagged once.
+        if dpe.contexts[idx].has_tag() {
+            return Err(DpeErrorCode::BadTag);
+        } else {
+            #[cfg(not(feature = "no-cfi"))]
+            cfi_assert!(!dpe.contexts[idx].has_tag());
+        }
+
+        // Because handles are one-time use, rotate the handle if it isn't the default.
+        dpe.roll_onetime_use_handle(env, idx)?;
+
+        let context = &mut dpe.contexts[idx];
+        context.has_tag = true.into();
+        context.tag = self.tag;
+
+        Ok(Response::TagTci(NewHandleResp {
+            handle: context.handle,
+            resp_hdr: ResponseHdr::new(DpeErrorCode::NoError),
+        }))
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, PartialEq, Eq, zerocopy::FromBytes, zerocopy::AsBytes)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct GetTaggedTciCmd {
+    pub tag: u32,
+}
+
+impl_wire_endian!(GetTaggedTciCmd { tag });
+
+impl CommandExecution for GetTaggedTciCmd {
+    #[cfg_attr(not(feature = "no-cfi"), cfi_impl_fn)]
+    fn execute(
+        &self,
+        dpe: &mut DpeInstance,
+        _env: &mut DpeEnv<impl DpeTypes>,
+        _locality: u32,
+    ) -> Result<Response, DpeErrorCode> {
+        dpe_trace!("GetTaggedTci tag={:#x}", self.tag);
+
+        // Make sure this command is supported.
+        if !dpe.support.tagging() {
+            return Err(DpeErrorCode::InvalidCommand);
+        } else {
+            #[cfg(not(feature = "no-cfi"))]
+            cfi_assert!(dpe.support.tagging());
+        }
+
+        // Tags are not locality-bound, so search every context that still holds TCI data.
+        let context = dpe
+            .contexts
+            .iter()
+            .find(|c| c.state != ContextState::Inactive && c.has_tag() && c.tag == self.tag)
+            .ok_or(DpeErrorCode::BadTag)?;
+
+        Ok(Response::GetTaggedTci(GetTaggedTciResp {
+            tci_cumulative: context.tci.tci_cumulative,
+            tci_current: context.tci.tci_current,
+            resp_hdr: ResponseHdr::new(DpeErrorCode::NoError),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        commands::{Command, CommandHdr, DestroyCtxCmd, InitCtxCmd},
+        dpe_instance::tests::{TestTypes, RANDOM_HANDLE, SIMULATION_HANDLE, TEST_LOCALITIES},
+        support::Support,
+        tci::TciMeasurement,
+        DPE_PROFILE,
+    };
+    use caliptra_cfi_lib_git::CfiCounter;
+    use crypto::OpensslCrypto;
+    use platform::default::DefaultPlatform;
+    use zerocopy::AsBytes;
+
+    const TEST_TAG_TCI_CMD: TagTciCmd = TagTciCmd {
+        handle: SIMULATION_HANDLE,
+        tag: 0x1234_5678,
+    };
+
+    const TEST_GET_TAGGED_TCI_CMD: GetTaggedTciCmd = GetTaggedTciCmd { tag: 0x1234_5678 };
+
+    #[test]
+    fn test_deserialize_tag_tci() {
+        CfiCounter::reset_for_test();
+        let mut command = CommandHdr::new_for_test(Command::TAG_TCI)
+            .as_bytes()
+            .to_vec();
+        command.extend(TEST_TAG_TCI_CMD.as_bytes());
+        assert_eq!(
+            Ok(Command::TagTci(TEST_TAG_TCI_CMD)),
+            Command::deserialize(&command)
+        );
+    }
+
+    #[test]
+    fn test_deserialize_get_tagged_tci() {
+        CfiCounter::reset_for_test();
+        let mut command = CommandHdr::new_for_test(Command::GET_TAGGED_TCI)
+            .as_bytes()
+            .to_vec();
+        command.extend(TEST_GET_TAGGED_TCI_CMD.as_bytes());
+        assert_eq!(
+            Ok(Command::GetTaggedTci(TEST_GET_TAGGED_TCI_CMD)),
+            Command::deserialize(&command)
+        );
+    }
+
+    #[test]
+    fn test_tag_tci() {
+        CfiCounter::reset_for_test();
+        let mut env = DpeEnv::<TestTypes> {
+            crypto: OpensslCrypto::new(),
+            platform: DefaultPlatform,
+            arena: &mut [],
+        };
+        let mut dpe = DpeInstance::new(&mut env, Support::default()).unwrap();
+        // Make sure it returns an error if the command is marked unsupported.
+        assert_eq!(
+            Err(DpeErrorCode::InvalidCommand),
+            TagTciCmd {
+                handle: ContextHandle::default(),
+                tag: 0,
+            }
+            .execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
+        );
+        assert_eq!(
+            Err(DpeErrorCode::InvalidCommand),
+            GetTaggedTciCmd { tag: 0 }.execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
+        );
+
+        // Make a new instance that supports tagging.
+        let mut dpe = DpeInstance::new(&mut env, Support::TAGGING | Support::SIMULATION).unwrap();
+        InitCtxCmd::new_use_default()
+            .execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
+            .unwrap();
+        let sim_local = TEST_LOCALITIES[1];
+        InitCtxCmd::new_simulation()
+            .execute(&mut dpe, &mut env, sim_local)
+            .unwrap();
+
+        // Invalid handle.
+        assert_eq!(
+            Err(DpeErrorCode::InvalidHandle),
+            TagTciCmd {
+                handle: SIMULATION_HANDLE,
+                tag: 0,
+            }
+            .execute(&mut dpe, &mut env, sim_local)
+        );
+
+        // Tag default handle.
+        assert_eq!(
+            Ok(Response::TagTci(NewHandleResp {
+                handle: ContextHandle::default(),
+                resp_hdr: ResponseHdr::new(DpeErrorCode::NoError),
+            })),
+            TagTciCmd {
+                handle: ContextHandle::default(),
+                tag: 0,
+            }
+            .execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
+        );
+
+        // Try to re-tag the default context.
+        assert_eq!(
+            Err(DpeErrorCode::BadTag),
+            TagTciCmd {
+                handle: ContextHandle::default(),
+                tag: 1,
+            }
+            .execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
+        );
+
+        // Try same tag on a different context.
+        assert_eq!(
+            Err(DpeErrorCode::BadTag),
+            TagTciCmd {
+                handle: RANDOM_HANDLE,
+                tag: 0,
+            }
+            .execute(&mut dpe, &mut env, sim_local)
+        );
+
+        // Tag the simulation context. Its handle should be rotated.
+        let resp = TagTciCmd {
+            handle: RANDOM_HANDLE,
+            tag: 1,
+        }
+        .execute(&mut dpe, &mut env, sim_local)
+        .unwrap();
+        let new_handle = match resp {
+            Response::TagTci(resp) => resp.handle,
+            _ => panic!("Incorrect response type"),
+        };
+        assert_ne!(new_handle, RANDOM_HANDLE);
+
+        // Tag doesn't exist.
+        assert_eq!(
+            Err(DpeErrorCode::BadTag),
+            GetTaggedTciCmd { tag: 2 }.execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
+        );
+
+        // Get the tagged TCI of the default context.
+        let idx = dpe
+            .get_active_context_pos(&ContextHandle::default(), TEST_LOCALITIES[0])
+            .unwrap();
+        dpe.contexts[idx].tci.tci_current = TciMeasurement([0xaa; DPE_PROFILE.get_tci_size()]);
+        assert_eq!(
+            Ok(Response::GetTaggedTci(GetTaggedTciResp {
+                tci_cumulative: dpe.contexts[idx].tci.tci_cumulative,
+                tci_current: TciMeasurement([0xaa; DPE_PROFILE.get_tci_size()]),
+                resp_hdr: ResponseHdr::new(DpeErrorCode::NoError),
+            })),
+            GetTaggedTciCmd { tag: 0 }.execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
+        );
+
+        // Destroying a context releases its tag.
+        DestroyCtxCmd { handle: new_handle }
+            .execute(&mut dpe, &mut env, sim_local)
+            .unwrap();
+        assert_eq!(
+            Err(DpeErrorCode::BadTag),
+            GetTaggedTciCmd { tag: 1 }.execute(&mut dpe, &mut env, sim_local)
+        );
+    }
+}
@@ -0,0 +1,225 @@
+// Licensed under the Apache-2.0 license.
+use crate::{
+    dpe_instance::{DpeEnv, DpeInstance, DpeTypes},
+    response::DpeErrorCode,
+};
+
+/// Executes commands in the vendor range, `Command::VENDOR_COMMAND_BASE` and
+/// up, on behalf of the integrator.
+///
+/// The dispatcher checks the command header, command authentication and
+/// `Platform::authorize` before handing a vendor command over. Handles in the
+/// command body are not checked: look them up with
+/// `DpeInstance::verify_handle_mac` and `DpeInstance::get_active_context_pos`
+/// so they are held to the same rules as those of standard commands.
+pub trait VendorCommandHandler {
+    /// Executes vendor command `cmd_id`, whose parameters are `cmd`, and
+    /// writes its response payload to `resp`.
+    ///
+    /// Returns the number of bytes written. The payload is sent after a
+    /// response header carrying `NoError`; on error only a header carrying the
+    /// error is sent. Return `InvalidCommand` for commands the handler doesn't
+    /// know.
+    ///
+    /// # Arguments
+    ///
+    /// * `dpe` - instance the command was sent to
+    /// * `env` - DPE environment containing Crypto and Platform implementations
+    /// * `locality` - which hardware locality is making the request
+    /// * `cmd_id` - command ID from the command header
+    /// * `cmd` - bytes following the command header
+    /// * `resp` - buffer for the response payload
+    fn execute(
+        &mut self,
+        dpe: &mut DpeInstance,
+        env: &mut DpeEnv<impl DpeTypes>,
+        locality: u32,
+        cmd_id: u32,
+        cmd: &[u8],
+        resp: &mut [u8],
+    ) -> Result<usize, DpeErrorCode>;
+}
+
+/// Rejects every vendor command. Used by
+/// `DpeInstance::execute_serialized_command`.
+pub struct NoVendorCommands;
+
+impl VendorCommandHandler for NoVendorCommands {
+    fn execute(
+        &mut self,
+        _dpe: &mut DpeInstance,
+        _env: &mut DpeEnv<impl DpeTypes>,
+        _locality: u32,
+        _cmd_id: u32,
+        _cmd: &[u8],
+        _resp: &mut [u8],
+    ) -> Result<usize, DpeErrorCode> {
+        Err(DpeErrorCode::InvalidCommand)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        commands::{Command, CommandHdr},
+        context::{ContextHandle, ContextState},
+        dpe_instance::tests::{TestTypes, TEST_LOCALITIES},
+        response::{Response, ResponseHdr},
+        support::test::SUPPORT,
+    };
+    use caliptra_cfi_lib_git::CfiCounter;
+    use core::mem::size_of;
+    use crypto::OpensslCrypto;
+    use platform::default::DefaultPlatform;
+    use zerocopy::AsBytes;
+
+    const COUNT_CONTEXTS: u32 = Command::VENDOR_COMMAND_BASE;
+    const FAIL: u32 = Command::VENDOR_COMMAND_BASE + 1;
+
+    /// Answers `COUNT_CONTEXTS` with the number of active contexts in the
+    /// caller's locality followed by the command parameters.
+    struct TestHandler {
+        calls: usize,
+    }
+
+    impl VendorCommandHandler for TestHandler {
+        fn execute(
+            &mut self,
+            dpe: &mut DpeInstance,
+            _env: &mut DpeEnv<impl DpeTypes>,
+            locality: u32,
+            cmd_id: u32,
+            cmd: &[u8],
+            resp: &mut [u8],
+        ) -> Result<usize, DpeErrorCode> {
+            self.calls += 1;
+            match cmd_id {
+                COUNT_CONTEXTS => {
+                    let count = dpe
+                        .contexts
+                        .iter()
+                        .filter(|c| c.state == ContextState::Active && c.locality == locality)
+                        .count() as u32;
+                    let len = 4 + cmd.len();
+                    let out = resp.get_mut(..len).ok_or(DpeErrorCode::InternalError)?;
+                    out[..4].copy_from_slice(&count.to_le_bytes());
+                    out[4..].copy_from_slice(cmd);
+                    Ok(len)
+                }
+                FAIL => Err(DpeErrorCode::InvalidArgument),
+                _ => Err(DpeErrorCode::InvalidCommand),
+            }
+        }
+    }
+
+    fn vendor_cmd(cmd_id: u32, params: &[u8]) -> Vec<u8> {
+        let mut cmd = CommandHdr::new_for_test(cmd_id).as_bytes().to_vec();
+        cmd.extend_from_slice(params);
+        cmd
+    }
+
+    fn new_env() -> DpeEnv<'static, TestTypes> {
+        DpeEnv::<TestTypes> {
+            crypto: OpensslCrypto::new(),
+            platform: DefaultPlatform,
+            arena: &mut [],
+        }
+    }
+
+    #[test]
+    fn test_deserialize_vendor_command() {
+        CfiCounter::reset_for_test();
+        let cmd = vendor_cmd(COUNT_CONTEXTS, &[1, 2, 3]);
+        let command = Command::deserialize(&cmd).unwrap();
+        assert_eq!(command, Command::Vendor(COUNT_CONTEXTS, &[1, 2, 3]));
+        assert_eq!(u32::from(&command), COUNT_CONTEXTS);
+        assert_eq!(command.handle(), None);
+
+        // IDs below the vendor range that DPE doesn't define stay invalid.
+        assert_eq!(
+            Command::deserialize(&vendor_cmd(Command::VENDOR_COMMAND_BASE - 1, &[])),
+            Err(DpeErrorCode::InvalidCommand)
+        );
+    }
+
+    #[test]
+    fn test_vendor_command() {
+        CfiCounter::reset_for_test();
+        let mut env = new_env();
+        let mut dpe = DpeInstance::new(&mut env, SUPPORT).unwrap();
+        let mut handler = TestHandler { calls: 0 };
+
+        let resp = dpe
+            .execute_serialized_command_with_vendor(
+                &mut env,
+                TEST_LOCALITIES[0],
+                &vendor_cmd(COUNT_CONTEXTS, &[0xaa, 0xbb]),
+                &mut handler,
+            )
+            .unwrap();
+        let mut expected = ResponseHdr::new(DpeErrorCode::NoError).as_bytes().to_vec();
+        expected.extend_from_slice(&[1, 0, 0, 0, 0xaa, 0xbb]);
+        assert!(matches!(resp, Response::Vendor(_)));
+        assert_eq!(resp.as_bytes(), expected);
+
+        // A locality without contexts sees none of them.
+        let resp = dpe
+            .execute_serialized_command_with_vendor(
+                &mut env,
+                TEST_LOCALITIES[1],
+                &vendor_cmd(COUNT_CONTEXTS, &[]),
+                &mut handler,
+            )
+            .unwrap();
+        assert_eq!(&resp.as_bytes()[size_of::<ResponseHdr>()..], &[0, 0, 0, 0]);
+
+        assert_eq!(
+            dpe.execute_serialized_command_with_vendor(
+                &mut env,
+                TEST_LOCALITIES[0],
+                &vendor_cmd(FAIL, &[]),
+                &mut handler,
+            ),
+            Ok(Response::Error(ResponseHdr::new(
+                DpeErrorCode::InvalidArgument
+            )))
+        );
+        assert_eq!(handler.calls, 3);
+
+        // Standard commands never reach the handler.
+        let get_profile = CommandHdr::new_for_test(Command::GET_PROFILE);
+        assert!(matches!(
+            dpe.execute_serialized_command_with_vendor(
+                &mut env,
+                TEST_LOCALITIES[0],
+                get_profile.as_bytes(),
+                &mut handler,
+            ),
+            Ok(Response::GetProfile(_))
+        ));
+        assert_eq!(handler.calls, 3);
+    }
+
+    #[test]
+    fn test_vendor_command_without_handler() {
+        CfiCounter::reset_for_test();
+        let mut env = new_env();
+        let mut dpe = DpeInstance::new(&mut env, SUPPORT).unwrap();
+
+        assert_eq!(
+            dpe.execute_serialized_command(
+                &mut env,
+                TEST_LOCALITIES[0],
+                &vendor_cmd(COUNT_CONTEXTS, &[])
+            ),
+            Ok(Response::Error(ResponseHdr::new(
+                DpeErrorCode::InvalidCommand
+            )))
+        );
+        // The default context is untouched.
+        assert!(dpe
+            .get_active_context_pos(&ContextHandle::default(), TEST_LOCALITIES[0])
+            .is_ok());
+    }
+}
@@ -1,5 +1,10 @@
 // Licensed under the Apache-2.0 license.
-use crate::{response::DpeErrorCode, tci::TciNodeData, U8Bool, MAX_HANDLES};
+use crate::{
+    dpe_instance::{flags_iter, FlagsIter},
+    response::DpeErrorCode,
+    tci::TciNodeData,
+    U8Bool, DPE_PROFILE, MAX_HANDLES,
+};
 use constant_time_eq::constant_time_eq;
 use zerocopy::{AsBytes, FromBytes};
 use zeroize::Zeroize;
@@ -10,11 +15,34 @@ pub struct Context {
     pub handle: ContextHandle,
     pub tci: TciNodeData,
     /// Bitmap of the node indices that are children of this node
-    pub children: u32,
+    pub children: u64,
 
     /// Which hardware locality owns the context.
     pub locality: u32,
 
+    /// Optional tag assigned to the context by TagTci. Only valid if `has_tag` is set.
+    pub tag: u32,
+
+    /// Number of commands that completed successfully on this context's handle.
+    /// Only maintained when the COMMAND_COUNTER support flag is set.
+    pub command_counter: u32,
+
+    /// Digest of the measurements from this context up to the root, which
+    /// keys are derived from. Only valid if `measurement_cached` is set.
+    pub cached_measurement: [u8; DPE_PROFILE.get_hash_size()],
+
+    /// Digest of the TCIs from the root down to this context, computed as
+    /// HASH(PARENT_CHAIN_DIGEST || TCI) with `seed` in place of the parent's
+    /// digest for a root. Kept up to date whenever a TCI changes, so that with
+    /// the `chain_digest_measurement` feature key derivation does not need to
+    /// hash every ancestor.
+    pub chain_digest: [u8; DPE_PROFILE.get_hash_size()],
+
+    /// Value a root's chain digest starts from in place of zeros. Only
+    /// nonzero for simulation contexts initialized with a caller-supplied
+    /// seed, so that their keys are reproducible.
+    pub seed: [u8; DPE_PROFILE.get_hash_size()],
+
     /// Index in DPE instance of the parent context. 0xFF if this node is the root
     pub parent_idx: u8,
 
@@ -31,7 +59,16 @@ pub struct Context {
     pub allow_ca: U8Bool,
     /// Whether this context can emit certificates in X.509 format
     pub allow_x509: U8Bool,
-    pub reserved: [u8; 1],
+    /// Whether a tag has been assigned to this context
+    pub has_tag: U8Bool,
+    /// Whether `cached_measurement` holds the current measurement digest
+    pub measurement_cached: U8Bool,
+    /// Whether the crypto implementation holds the current CDI of this
+    /// context in the cache slot of its index
+    pub cdi_cached: U8Bool,
+
+    // unused buffer added to make Context word aligned and remove padding
+    pub(crate) reserved: [u8; 2],
 }
 
 impl Context {
@@ -46,11 +83,19 @@ impl Context {
             context_type: ContextType::Normal,
             state: ContextState::Inactive,
             locality: 0,
+            tag: 0,
+            command_counter: 0,
+            cached_measurement: [0; DPE_PROFILE.get_hash_size()],
+            chain_digest: [0; DPE_PROFILE.get_hash_size()],
+            seed: [0; DPE_PROFILE.get_hash_size()],
             uses_internal_input_info: U8Bool::new(false),
             uses_internal_input_dice: U8Bool::new(false),
             allow_ca: U8Bool::new(false),
             allow_x509: U8Bool::new(false),
-            reserved: [0; 1],
+            has_tag: U8Bool::new(false),
+            measurement_cached: U8Bool::new(false),
+            cdi_cached: U8Bool::new(false),
+            reserved: [0; 2],
         }
     }
 
@@ -66,6 +111,24 @@ impl Context {
     pub fn allow_x509(&self) -> bool {
         self.allow_x509.get()
     }
+    pub fn has_tag(&self) -> bool {
+        self.has_tag.get()
+    }
+    pub fn measurement_cached(&self) -> bool {
+        self.measurement_cached.get()
+    }
+    pub fn cdi_cached(&self) -> bool {
+        self.cdi_cached.get()
+    }
+
+    /// Index of the parent context, or `None` if this is a root node.
+    pub fn parent_pos(&self) -> Option<usize> {
+        if self.parent_idx == Self::ROOT_INDEX {
+            None
+        } else {
+            Some(self.parent_idx as usize)
+        }
+    }
 
     /// Sets all values to an initialized state according to ActiveContextArgs
     pub fn activate(&mut self, args: &ActiveContextArgs) {
@@ -82,23 +145,39 @@ impl Context {
         self.allow_x509 = args.allow_x509.into();
         self.uses_internal_input_info = args.uses_internal_input_info.into();
         self.uses_internal_input_dice = args.uses_internal_input_dice.into();
+        self.tag = 0;
+        self.has_tag = false.into();
+        self.command_counter = 0;
+        self.measurement_cached = false.into();
+        self.cdi_cached = false.into();
+        self.seed = [0; DPE_PROFILE.get_hash_size()];
+    }
+
+    /// Retire this context after a child was derived from it without retaining it. The TCI data
+    /// is kept for the child's measurements. A non-default handle stays with the context, but
+    /// every command on it other than DestroyContext fails with `DpeErrorCode::RetiredContext`.
+    /// The default handle is released, so the locality can get a new default context; the
+    /// context then goes away with its last child.
+    pub fn retire(&mut self) {
+        self.state = ContextState::Retired;
+        if self.handle.is_default() {
+            self.handle = ContextHandle::INVALID;
+        }
     }
 
     /// Destroy this context so it can no longer be used until it is re-initialized. The default
     /// context cannot be re-initialized.
+    ///
+    /// Every field is scrubbed back to its `Context::new` value, so nothing about the old
+    /// context, such as its measurements, tag or locality, can show up in the context that
+    /// reuses the slot.
     pub fn destroy(&mut self) {
-        self.tci = TciNodeData::new();
-        self.state = ContextState::Inactive;
-        self.uses_internal_input_info = false.into();
-        self.uses_internal_input_dice = false.into();
-        self.allow_ca = false.into();
-        self.allow_x509 = false.into();
-        self.parent_idx = Self::ROOT_INDEX;
+        *self = Context::new();
     }
 
     /// Return the list of children of the context with idx added.
     /// This function does not mutate DPE state.
-    pub fn add_child(&mut self, idx: usize) -> Result<u32, DpeErrorCode> {
+    pub fn add_child(&mut self, idx: usize) -> Result<u64, DpeErrorCode> {
         if idx >= MAX_HANDLES {
             return Err(DpeErrorCode::InternalError);
         }
@@ -113,7 +192,14 @@ pub struct ContextHandle(pub [u8; ContextHandle::SIZE]);
 
 impl ContextHandle {
     pub const SIZE: usize = 16;
+    /// Bytes at the end of the handle that hold its MAC when
+    /// `Support::HANDLE_MAC` is set.
+    pub const MAC_SIZE: usize = 8;
+    pub(crate) const NONCE_SIZE: usize = Self::SIZE - Self::MAC_SIZE;
     const DEFAULT: [u8; Self::SIZE] = [0; Self::SIZE];
+    /// Returned in place of the parent handle when DeriveContext retires the
+    /// parent, and given to a retired parent whose child took over its handle.
+    pub const INVALID: ContextHandle = ContextHandle([0xff; Self::SIZE]);
 
     /// Returns the default context handle.
     pub const fn default() -> ContextHandle {
@@ -130,14 +216,19 @@ impl ContextHandle {
 #[repr(u8, align(1))]
 #[rustfmt::skip]
 pub enum ContextState {
-    /// Inactive or uninitialized.
+    /// Inactive or uninitialized. Every field of the context holds its `Context::new` value.
+    ///
+    /// Slots move from `Inactive` to `Active` when a context is initialized or derived, from
+    /// `Active` to `Retired` when a child is derived without retaining the parent, and from
+    /// either of those back to `Inactive` when the context is destroyed.
     Inactive,
     /// Context is initialized and ready to be used.
     Active,
     /// A child was derived from this context, but it was not retained. This will need to be
     /// destroyed automatically if all of it's children have been destroyed. It is preserved for its
-    /// TCI data, but the handle is no longer valid. Because the handle is no longer valid, a client
-    /// cannot command it to be destroyed.
+    /// TCI data. Commands on a non-default handle fail with `DpeErrorCode::RetiredContext`,
+    /// except for DestroyContext, which destroys it along with its descendants in the same
+    /// locality.
     Retired,
     // These are unused values to allow AsBytes and FromBytes to be able to use the enum.
     _03, _04, _05, _06, _07, _08, _09, _0a, _0b, _0c, _0d, _0e, _0f,
@@ -251,6 +342,117 @@ impl<'a> Iterator for ChildToRootIter<'a> {
     }
 }
 
+/// Iterates over the direct children of a context, yielding each child's
+/// index along with the child.
+pub(crate) struct ChildIter<'a> {
+    children: FlagsIter,
+    contexts: &'a [Context],
+}
+
+impl ChildIter<'_> {
+    /// Create a new iterator over the children of `parent`.
+    pub fn new<'a>(parent: &Context, contexts: &'a [Context]) -> ChildIter<'a> {
+        ChildIter {
+            children: flags_iter(parent.children, MAX_HANDLES),
+            contexts,
+        }
+    }
+}
+
+impl<'a> Iterator for ChildIter<'a> {
+    type Item = Result<(usize, &'a Context), DpeErrorCode>;
+
+    fn next(&mut self) -> Option<Result<(usize, &'a Context), DpeErrorCode>> {
+        let idx = self.children.next()?;
+        match self.contexts.get(idx) {
+            Some(context) => Some(Ok((idx, context))),
+            None => Some(Err(DpeErrorCode::InternalError)),
+        }
+    }
+}
+
+/// Walks every context that is still part of the derivation tree, visiting
+/// each parent before its children. Trees are visited in the order of their
+/// root's index and children in the order of their index.
+pub(crate) struct TreeIter<'a> {
+    contexts: &'a [Context],
+    stack: [u8; MAX_HANDLES],
+    stack_len: usize,
+    next_root: usize,
+    count: usize,
+    done: bool,
+}
+
+impl TreeIter<'_> {
+    /// Create a new iterator over the active and retired nodes of `contexts`.
+    pub fn new(contexts: &[Context]) -> TreeIter {
+        TreeIter {
+            contexts,
+            stack: [0; MAX_HANDLES],
+            stack_len: 0,
+            next_root: 0,
+            count: 0,
+            done: false,
+        }
+    }
+
+    fn fail<T>(&mut self, err: DpeErrorCode) -> Option<Result<T, DpeErrorCode>> {
+        self.done = true;
+        Some(Err(err))
+    }
+}
+
+impl<'a> Iterator for TreeIter<'a> {
+    type Item = Result<(usize, &'a Context), DpeErrorCode>;
+
+    fn next(&mut self) -> Option<Result<(usize, &'a Context), DpeErrorCode>> {
+        if self.done {
+            return None;
+        }
+        if self.stack_len == 0 {
+            let contexts = self.contexts;
+            let root = (self.next_root..contexts.len()).find(|&idx| {
+                contexts[idx].state != ContextState::Inactive
+                    && contexts[idx].parent_idx == Context::ROOT_INDEX
+            });
+            let Some(root) = root else {
+                self.done = true;
+                return None;
+            };
+            self.next_root = root + 1;
+            self.stack[0] = root as u8;
+            self.stack_len = 1;
+        }
+
+        self.stack_len -= 1;
+        let idx = self.stack[self.stack_len] as usize;
+        if self.count >= MAX_HANDLES {
+            return self.fail(DpeErrorCode::MaxTcis);
+        }
+        self.count += 1;
+        let contexts = self.contexts;
+        let Some(context) = contexts.get(idx) else {
+            return self.fail(DpeErrorCode::InternalError);
+        };
+        if context.state == ContextState::Inactive {
+            return self.fail(DpeErrorCode::InvalidHandle);
+        }
+
+        // Push the children highest index first so they are popped in order.
+        for child in (0..MAX_HANDLES).rev() {
+            if context.children & (1 << child) == 0 {
+                continue;
+            }
+            if self.stack_len >= self.stack.len() {
+                return self.fail(DpeErrorCode::MaxTcis);
+            }
+            self.stack[self.stack_len] = child as u8;
+            self.stack_len += 1;
+        }
+        Some(Ok((idx, context)))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -354,6 +556,81 @@ mod tests {
         assert!(iter.next().unwrap().is_ok());
     }
 
+    #[test]
+    fn test_child_iter() {
+        let mut contexts = [CONTEXT_INITIALIZER; MAX_HANDLES];
+        contexts[3].children = (1 << 0) | (1 << 5) | (1 << (MAX_HANDLES - 1));
+        for idx in [0, 5, MAX_HANDLES - 1] {
+            contexts[idx].parent_idx = 3;
+            contexts[idx].handle = ContextHandle([idx as u8; ContextHandle::SIZE]);
+        }
+
+        let children: Vec<usize> = ChildIter::new(&contexts[3], &contexts)
+            .map(|child| {
+                let (idx, context) = child.unwrap();
+                assert_eq!([idx as u8; ContextHandle::SIZE], context.handle.0);
+                assert_eq!(Some(3), context.parent_pos());
+                idx
+            })
+            .collect();
+        assert_eq!(vec![0, 5, MAX_HANDLES - 1], children);
+
+        // Leaf nodes have no children.
+        assert_eq!(0, ChildIter::new(&contexts[0], &contexts).count());
+        assert_eq!(None, contexts[3].parent_pos());
+
+        // Children outside of the context array are an error.
+        let parent = &contexts[3];
+        let mut iter = ChildIter::new(parent, &contexts[..1]);
+        assert!(iter.next().unwrap().is_ok());
+        assert_eq!(
+            DpeErrorCode::InternalError,
+            iter.next().unwrap().err().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_tree_iter() {
+        let mut contexts = [CONTEXT_INITIALIZER; MAX_HANDLES];
+        // Two trees: 4 -> {2 -> {7}, 9} and 1, with an inactive context at 0.
+        for idx in [1, 2, 4, 7, 9] {
+            contexts[idx].state = ContextState::Active;
+        }
+        contexts[4].state = ContextState::Retired;
+        contexts[4].children = (1 << 2) | (1 << 9);
+        contexts[2].parent_idx = 4;
+        contexts[9].parent_idx = 4;
+        contexts[2].children = 1 << 7;
+        contexts[7].parent_idx = 2;
+
+        let order: Vec<usize> = TreeIter::new(&contexts)
+            .map(|node| node.unwrap().0)
+            .collect();
+        assert_eq!(vec![1, 4, 2, 7, 9], order);
+
+        // An empty instance has no nodes.
+        let contexts = [CONTEXT_INITIALIZER; MAX_HANDLES];
+        assert_eq!(0, TreeIter::new(&contexts).count());
+    }
+
+    #[test]
+    fn test_tree_iter_errors() {
+        // A child pointing back at its parent must not loop forever.
+        let mut contexts = [CONTEXT_INITIALIZER; MAX_HANDLES];
+        contexts[0].state = ContextState::Active;
+        contexts[0].children = 1 << 1;
+        contexts[1].state = ContextState::Active;
+        contexts[1].parent_idx = 0;
+        contexts[1].children = 1 << 0;
+        let result: Result<Vec<_>, _> = TreeIter::new(&contexts).collect();
+        assert_eq!(DpeErrorCode::MaxTcis, result.err().unwrap());
+
+        // Children must be in use.
+        contexts[1].state = ContextState::Inactive;
+        let result: Result<Vec<_>, _> = TreeIter::new(&contexts).collect();
+        assert_eq!(DpeErrorCode::InvalidHandle, result.err().unwrap());
+    }
+
     #[test]
     fn test_child_to_root_iter_infinite_loop() {
         let contexts = [CONTEXT_INITIALIZER; MAX_HANDLES];
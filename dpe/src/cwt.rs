@@ -0,0 +1,575 @@
+// Licensed under the Apache-2.0 license
+
+//! CBOR Web Token encoding routines for DPE
+//!
+//! A CWT certificate is a COSE_Sign1 (RFC 9052) whose payload is a CWT
+//! claims set (RFC 8392). Subject, issuer, key and key usage claims follow
+//! the Open Profile for DICE so existing CBOR DICE verifiers can read them.
+//! The TCB's TCI nodes are carried in a private-use claim.
+//!
+//! The same COSE_Sign1 envelope carries attestation tokens, which sign a
+//! verifier's nonce along with the TCI nodes of a context.
+
+use crate::{
+    cbor::CborWriter, response::DpeErrorCode, tci::TciNodeData, x509::MeasurementData, DpeProfile,
+    DPE_PROFILE,
+};
+use crypto::{EcdsaPub, EcdsaSig};
+
+pub struct CwtWriter<'a> {
+    cbor: CborWriter<'a>,
+}
+
+impl<'a> CwtWriter<'a> {
+    // CWT claim keys
+    const ISS_CLAIM: i64 = 1;
+    const SUB_CLAIM: i64 = 2;
+    /// Entity Attestation Token nonce claim
+    pub const NONCE_CLAIM: i64 = 10;
+    /// Entity Attestation Token UEID claim
+    const UEID_CLAIM: i64 = 256;
+    const SUBJECT_PUBLIC_KEY_CLAIM: i64 = -4670552;
+    const KEY_USAGE_CLAIM: i64 = -4670553;
+    /// Private-use claim holding an array of TCI nodes. Each node is an
+    /// array of [tci_type, locality, tci_current, tci_cumulative].
+    pub const TCI_NODES_CLAIM: i64 = -70001;
+    /// Private-use claim holding the certified context's command counter.
+    pub const COMMAND_COUNTER_CLAIM: i64 = -70002;
+    /// Private-use claim holding the label the subject key was derived with.
+    pub const KEY_LABEL_CLAIM: i64 = -70003;
+    /// Private-use claim holding the DPE profile of an attestation token.
+    pub const PROFILE_CLAIM: i64 = -70004;
+
+    // Key usage bits, as a little-endian bit string
+    const KEY_USAGE_DIGITAL_SIGNATURE: u8 = 1 << 0;
+    const KEY_USAGE_KEY_CERT_SIGN: u8 = 1 << 5;
+
+    const COSE_SIGN1_TAG: u64 = 18;
+    const SIG_CONTEXT: &'static [u8] = b"Signature1";
+
+    // COSE header and key labels
+    const ALG_LABEL: i64 = 1;
+    const KTY_LABEL: i64 = 1;
+    const KEY_ALG_LABEL: i64 = 3;
+    const CRV_LABEL: i64 = -1;
+    const X_LABEL: i64 = -2;
+    const Y_LABEL: i64 = -3;
+    const KTY_EC2: i64 = 2;
+
+    const ALG: i64 = match DPE_PROFILE {
+        // ES256
+        DpeProfile::P256Sha256 => -7,
+        // ES384. The ML-DSA profile keeps a P-384 alias key.
+        DpeProfile::P384Sha384 | DpeProfile::Mldsa87Sha384 => -35,
+        // ES512. The Ed25519 profile keeps a P-521 alias key.
+        DpeProfile::P521Sha512 | DpeProfile::Ed25519Sha512 => -36,
+    };
+
+    const CRV: i64 = match DPE_PROFILE {
+        // P-256
+        DpeProfile::P256Sha256 => 1,
+        // P-384
+        DpeProfile::P384Sha384 | DpeProfile::Mldsa87Sha384 => 2,
+        // P-521
+        DpeProfile::P521Sha512 | DpeProfile::Ed25519Sha512 => 3,
+    };
+
+    /// Largest encoding of the Sig_structure fields that precede the payload
+    pub const MAX_SIG_STRUCTURE_PREFIX_SIZE: usize = 32;
+
+    /// Size of the encoded COSE_Key holding a public key
+    pub const COSE_KEY_SIZE: usize = 1 // map header
+        + 2 // kty
+        + 1 + Self::ALG_SIZE // alg
+        + 2 // crv
+        + 2 * (1 + CborWriter::string_size(DPE_PROFILE.get_ecc_int_size()));
+
+    /// Size of the encoded protected header
+    const PROTECTED_SIZE: usize = 2 + Self::ALG_SIZE;
+
+    /// Size of the encoded algorithm identifier, a negative integer
+    const ALG_SIZE: usize = CborWriter::head_size(!(Self::ALG as u64));
+
+    /// Build a new CwtWriter that writes output to `buf`
+    pub fn new(buf: &'a mut [u8]) -> CwtWriter<'a> {
+        CwtWriter {
+            cbor: CborWriter::new(buf),
+        }
+    }
+
+    /// Write `bytes` as an upper-case hex text string
+    fn encode_hex_tstr(&mut self, bytes: &[u8]) -> Result<usize, DpeErrorCode> {
+        const HEX_CHARS: &[u8; 16] = b"0123456789ABCDEF";
+        let mut bytes_written = self
+            .cbor
+            .write_head(CborWriter::TEXT_STRING, 2 * bytes.len() as u64)?;
+        for &b in bytes {
+            bytes_written += self
+                .cbor
+                .write_bytes(&[HEX_CHARS[(b >> 4) as usize], HEX_CHARS[(b & 0xF) as usize]])?;
+        }
+        Ok(bytes_written)
+    }
+
+    /// Encode the COSE protected header, a map holding the signing algorithm
+    fn encode_protected(&mut self) -> Result<usize, DpeErrorCode> {
+        let mut bytes_written = self.cbor.write_bstr_header(Self::PROTECTED_SIZE)?;
+        bytes_written += self.cbor.write_map_header(1)?;
+        bytes_written += self.cbor.write_int(Self::ALG_LABEL)?;
+        bytes_written += self.cbor.write_int(Self::ALG)?;
+        Ok(bytes_written)
+    }
+
+    /// Encode an EC2 COSE_Key wrapped in a byte string
+    fn encode_cose_key(&mut self, pubkey: &EcdsaPub) -> Result<usize, DpeErrorCode> {
+        let bytes_written = self.cbor.write_bstr_header(Self::COSE_KEY_SIZE)?;
+        Ok(bytes_written + self.encode_ec2_cose_key(pubkey)?)
+    }
+
+    /// Encode an EC2 COSE_Key (RFC 9053) for `pubkey`
+    ///
+    /// COSE_Key = {
+    ///     1 => 2,        ; kty: EC2
+    ///     3 => alg,
+    ///     -1 => crv,
+    ///     -2 => bstr,    ; x
+    ///     -3 => bstr,    ; y
+    /// }
+    pub fn encode_ec2_cose_key(&mut self, pubkey: &EcdsaPub) -> Result<usize, DpeErrorCode> {
+        let mut bytes_written = self.cbor.write_map_header(5)?;
+        bytes_written += self.cbor.write_int(Self::KTY_LABEL)?;
+        bytes_written += self.cbor.write_int(Self::KTY_EC2)?;
+        bytes_written += self.cbor.write_int(Self::KEY_ALG_LABEL)?;
+        bytes_written += self.cbor.write_int(Self::ALG)?;
+        bytes_written += self.cbor.write_int(Self::CRV_LABEL)?;
+        bytes_written += self.cbor.write_int(Self::CRV)?;
+        bytes_written += self.cbor.write_int(Self::X_LABEL)?;
+        bytes_written += self.cbor.write_bstr(pubkey.x.bytes())?;
+        bytes_written += self.cbor.write_int(Self::Y_LABEL)?;
+        bytes_written += self.cbor.write_bstr(pubkey.y.bytes())?;
+
+        // A key of the wrong size would not match the bstr header.
+        if bytes_written != Self::COSE_KEY_SIZE {
+            return Err(DpeErrorCode::InternalError);
+        }
+        Ok(bytes_written)
+    }
+
+    /// Encode TCI nodes as an array of [tci_type, locality, tci_current, tci_cumulative]
+    fn encode_tci_nodes(&mut self, tci_nodes: &[TciNodeData]) -> Result<usize, DpeErrorCode> {
+        let mut bytes_written = self.cbor.write_array_header(tci_nodes.len())?;
+        for node in tci_nodes {
+            bytes_written += self.cbor.write_array_header(4)?;
+            bytes_written += self.cbor.write_uint(node.tci_type.into())?;
+            bytes_written += self.cbor.write_uint(node.locality.into())?;
+            bytes_written += self.cbor.write_bstr(&node.tci_current.0)?;
+            bytes_written += self.cbor.write_bstr(&node.tci_cumulative.0)?;
+        }
+        Ok(bytes_written)
+    }
+
+    /// Encode the claims set describing the certified key
+    ///
+    /// Claims = {
+    ///     1 => tstr,              ; iss: hex authority key identifier
+    ///     2 => tstr,              ; sub: hex subject key identifier
+    ///     256 => bstr,            ; ueid
+    ///     -4670552 => bstr,       ; subject public key (COSE_Key)
+    ///     -4670553 => bstr,       ; key usage
+    ///     -70001 => [* TciNode],  ; TCB measurements
+    ///     ? -70002 => uint,       ; command counter
+    ///     ? -70003 => bstr,       ; key label
+    /// }
+    ///
+    /// TciNode = [tci_type: uint, locality: uint, tci_current: bstr, tci_cumulative: bstr]
+    ///
+    /// # Arguments
+    ///
+    /// * `pubkey` - Subject public key.
+    /// * `measurements` - DPE measurement data.
+    pub fn encode_claims(
+        &mut self,
+        pubkey: &EcdsaPub,
+        measurements: &MeasurementData,
+    ) -> Result<usize, DpeErrorCode> {
+        let claim_count = 6
+            + usize::from(measurements.command_counter.is_some())
+            + usize::from(measurements.label.is_some());
+        let mut bytes_written = self.cbor.write_map_header(claim_count)?;
+
+        bytes_written += self.cbor.write_int(Self::ISS_CLAIM)?;
+        bytes_written += self.encode_hex_tstr(&measurements.authority_key_identifier)?;
+
+        bytes_written += self.cbor.write_int(Self::SUB_CLAIM)?;
+        bytes_written += self.encode_hex_tstr(&measurements.subject_key_identifier)?;
+
+        bytes_written += self.cbor.write_int(Self::UEID_CLAIM)?;
+        bytes_written += self.cbor.write_bstr(measurements.ueid)?;
+
+        bytes_written += self.cbor.write_int(Self::SUBJECT_PUBLIC_KEY_CLAIM)?;
+        bytes_written += self.encode_cose_key(pubkey)?;
+
+        let key_usage = if measurements.is_ca {
+            Self::KEY_USAGE_KEY_CERT_SIGN
+        } else {
+            Self::KEY_USAGE_DIGITAL_SIGNATURE
+        };
+        bytes_written += self.cbor.write_int(Self::KEY_USAGE_CLAIM)?;
+        bytes_written += self.cbor.write_bstr(&[key_usage])?;
+
+        bytes_written += self.cbor.write_int(Self::TCI_NODES_CLAIM)?;
+        bytes_written += self.encode_tci_nodes(measurements.tci_nodes)?;
+
+        if let Some(command_counter) = measurements.command_counter {
+            bytes_written += self.cbor.write_int(Self::COMMAND_COUNTER_CLAIM)?;
+            bytes_written += self.cbor.write_uint(command_counter.into())?;
+        }
+
+        if let Some(label) = measurements.label {
+            bytes_written += self.cbor.write_int(Self::KEY_LABEL_CLAIM)?;
+            bytes_written += self.cbor.write_bstr(label)?;
+        }
+
+        Ok(bytes_written)
+    }
+
+    /// Encode the claims set of an attestation token
+    ///
+    /// Claims = {
+    ///     10 => bstr,             ; nonce
+    ///     256 => bstr,            ; ueid
+    ///     -4670552 => bstr,       ; public key of the signing key (COSE_Key)
+    ///     -70001 => [* TciNode],  ; TCB measurements
+    ///     -70004 => uint,         ; DPE profile
+    /// }
+    ///
+    /// # Arguments
+    ///
+    /// * `nonce` - Verifier-supplied nonce.
+    /// * `ueid` - Universal entity ID of the device.
+    /// * `pubkey` - Public key of the key that signs the token.
+    /// * `tci_nodes` - TCI nodes of the attested context, leaf first.
+    pub fn encode_token_claims(
+        &mut self,
+        nonce: &[u8],
+        ueid: &[u8],
+        pubkey: &EcdsaPub,
+        tci_nodes: &[TciNodeData],
+    ) -> Result<usize, DpeErrorCode> {
+        let mut bytes_written = self.cbor.write_map_header(5)?;
+
+        bytes_written += self.cbor.write_int(Self::NONCE_CLAIM)?;
+        bytes_written += self.cbor.write_bstr(nonce)?;
+
+        bytes_written += self.cbor.write_int(Self::UEID_CLAIM)?;
+        bytes_written += self.cbor.write_bstr(ueid)?;
+
+        bytes_written += self.cbor.write_int(Self::SUBJECT_PUBLIC_KEY_CLAIM)?;
+        bytes_written += self.encode_cose_key(pubkey)?;
+
+        bytes_written += self.cbor.write_int(Self::TCI_NODES_CLAIM)?;
+        bytes_written += self.encode_tci_nodes(tci_nodes)?;
+
+        bytes_written += self.cbor.write_int(Self::PROFILE_CLAIM)?;
+        bytes_written += self.cbor.write_uint(DPE_PROFILE as u64)?;
+
+        Ok(bytes_written)
+    }
+
+    /// Encode the fields of the COSE Sig_structure that precede the payload
+    ///
+    /// Sig_structure = [
+    ///     context: "Signature1",
+    ///     body_protected: bstr,
+    ///     external_aad: bstr,
+    ///     payload: bstr,
+    /// ]
+    ///
+    /// The payload bytes themselves are not written, so the caller can hash
+    /// this prefix followed by the payload without copying it.
+    ///
+    /// # Arguments
+    ///
+    /// * `payload_len` - Length of the encoded claims.
+    pub fn encode_sig_structure_prefix(
+        &mut self,
+        payload_len: usize,
+    ) -> Result<usize, DpeErrorCode> {
+        let mut bytes_written = self.cbor.write_array_header(4)?;
+        bytes_written += self.cbor.write_tstr(Self::SIG_CONTEXT)?;
+        bytes_written += self.encode_protected()?;
+        bytes_written += self.cbor.write_bstr(&[])?;
+        bytes_written += self.cbor.write_bstr_header(payload_len)?;
+        Ok(bytes_written)
+    }
+
+    /// Encode a CWT as a tagged COSE_Sign1
+    ///
+    /// COSE_Sign1 = #6.18([
+    ///     protected: bstr,
+    ///     unprotected: {},
+    ///     payload: bstr,
+    ///     signature: bstr,   ; r || s
+    /// ])
+    ///
+    /// # Arguments
+    ///
+    /// * `claims` - Claims set encoded with `encode_claims`.
+    /// * `sig` - Alias key signature over the Sig_structure.
+    pub fn encode_cose_sign1(
+        &mut self,
+        claims: &[u8],
+        sig: &EcdsaSig,
+    ) -> Result<usize, DpeErrorCode> {
+        let int_size = DPE_PROFILE.get_ecc_int_size();
+        if sig.r.len() != int_size || sig.s.len() != int_size {
+            return Err(DpeErrorCode::InternalError);
+        }
+
+        let mut bytes_written = self.cbor.write_tag(Self::COSE_SIGN1_TAG)?;
+        bytes_written += self.cbor.write_array_header(4)?;
+        bytes_written += self.encode_protected()?;
+        bytes_written += self.cbor.write_map_header(0)?;
+        bytes_written += self.cbor.write_bstr(claims)?;
+        bytes_written += self.cbor.write_bstr_header(2 * int_size)?;
+        bytes_written += self.cbor.write_bytes(sig.r.bytes())?;
+        bytes_written += self.cbor.write_bytes(sig.s.bytes())?;
+        Ok(bytes_written)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tci::TciMeasurement;
+    use ciborium::value::Value;
+    use crypto::CryptoBuf;
+    use platform::MAX_KEY_IDENTIFIER_SIZE;
+
+    fn decode(bytes: &[u8]) -> Value {
+        ciborium::de::from_reader(bytes).unwrap()
+    }
+
+    fn claim<'v>(map: &'v Value, key: i64) -> &'v Value {
+        map.as_map()
+            .unwrap()
+            .iter()
+            .find(|(k, _)| *k == Value::Integer(key.into()))
+            .map(|(_, v)| v)
+            .unwrap()
+    }
+
+    fn test_pub_key() -> EcdsaPub {
+        EcdsaPub {
+            x: CryptoBuf::new(&[0xAA; DPE_PROFILE.get_ecc_int_size()]).unwrap(),
+            y: CryptoBuf::new(&[0xBB; DPE_PROFILE.get_ecc_int_size()]).unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_claims() {
+        let mut node = TciNodeData::new();
+        node.tci_type = 0x11223344;
+        node.locality = 7;
+        node.tci_current = TciMeasurement([0x01; DPE_PROFILE.get_tci_size()]);
+        node.tci_cumulative = TciMeasurement([0x02; DPE_PROFILE.get_tci_size()]);
+        let measurements = MeasurementData {
+            ueid: &[0xCC; 17],
+            tci_nodes: &[node],
+            is_ca: false,
+            supports_recursive: false,
+            subject_key_identifier: [0x5A; MAX_KEY_IDENTIFIER_SIZE],
+            authority_key_identifier: [0xA5; MAX_KEY_IDENTIFIER_SIZE],
+            command_counter: Some(42),
+            label: Some(&[0x4C; 16]),
+            additional_fwids: &[],
+        };
+
+        let mut buf = [0u8; 1024];
+        let mut w = CwtWriter::new(&mut buf);
+        let bytes_written = w.encode_claims(&test_pub_key(), &measurements).unwrap();
+        let claims = decode(&buf[..bytes_written]);
+
+        assert_eq!(
+            claim(&claims, CwtWriter::ISS_CLAIM),
+            &Value::Text("A5".repeat(MAX_KEY_IDENTIFIER_SIZE))
+        );
+        assert_eq!(
+            claim(&claims, CwtWriter::SUB_CLAIM),
+            &Value::Text("5A".repeat(MAX_KEY_IDENTIFIER_SIZE))
+        );
+        assert_eq!(
+            claim(&claims, CwtWriter::UEID_CLAIM),
+            &Value::Bytes(vec![0xCC; 17])
+        );
+        assert_eq!(
+            claim(&claims, CwtWriter::KEY_USAGE_CLAIM),
+            &Value::Bytes(vec![CwtWriter::KEY_USAGE_DIGITAL_SIGNATURE])
+        );
+
+        let cose_key = claim(&claims, CwtWriter::SUBJECT_PUBLIC_KEY_CLAIM)
+            .as_bytes()
+            .unwrap();
+        let cose_key = decode(cose_key);
+        assert_eq!(
+            claim(&cose_key, CwtWriter::KTY_LABEL),
+            &Value::Integer(CwtWriter::KTY_EC2.into())
+        );
+        assert_eq!(
+            claim(&cose_key, CwtWriter::KEY_ALG_LABEL),
+            &Value::Integer(CwtWriter::ALG.into())
+        );
+        assert_eq!(
+            claim(&cose_key, CwtWriter::CRV_LABEL),
+            &Value::Integer(CwtWriter::CRV.into())
+        );
+        assert_eq!(
+            claim(&cose_key, CwtWriter::X_LABEL),
+            &Value::Bytes(vec![0xAA; DPE_PROFILE.get_ecc_int_size()])
+        );
+        assert_eq!(
+            claim(&cose_key, CwtWriter::Y_LABEL),
+            &Value::Bytes(vec![0xBB; DPE_PROFILE.get_ecc_int_size()])
+        );
+
+        assert_eq!(
+            claim(&claims, CwtWriter::TCI_NODES_CLAIM),
+            &Value::Array(vec![Value::Array(vec![
+                Value::Integer(0x11223344.into()),
+                Value::Integer(7.into()),
+                Value::Bytes(vec![0x01; DPE_PROFILE.get_tci_size()]),
+                Value::Bytes(vec![0x02; DPE_PROFILE.get_tci_size()]),
+            ])])
+        );
+        assert_eq!(
+            claim(&claims, CwtWriter::COMMAND_COUNTER_CLAIM),
+            &Value::Integer(42.into())
+        );
+        assert_eq!(
+            claim(&claims, CwtWriter::KEY_LABEL_CLAIM),
+            &Value::Bytes(vec![0x4C; 16])
+        );
+    }
+
+    #[test]
+    fn test_token_claims() {
+        let mut node = TciNodeData::new();
+        node.tci_type = 0x44332211;
+        node.tci_current = TciMeasurement([0x03; DPE_PROFILE.get_tci_size()]);
+
+        let mut buf = [0u8; 1024];
+        let mut w = CwtWriter::new(&mut buf);
+        let bytes_written = w
+            .encode_token_claims(&[0x4E; 32], &[0xCC; 17], &test_pub_key(), &[node])
+            .unwrap();
+        let claims = decode(&buf[..bytes_written]);
+
+        assert_eq!(claims.as_map().unwrap().len(), 5);
+        assert_eq!(
+            claim(&claims, CwtWriter::NONCE_CLAIM),
+            &Value::Bytes(vec![0x4E; 32])
+        );
+        assert_eq!(
+            claim(&claims, CwtWriter::UEID_CLAIM),
+            &Value::Bytes(vec![0xCC; 17])
+        );
+        let cose_key = decode(
+            claim(&claims, CwtWriter::SUBJECT_PUBLIC_KEY_CLAIM)
+                .as_bytes()
+                .unwrap(),
+        );
+        assert_eq!(
+            claim(&cose_key, CwtWriter::X_LABEL),
+            &Value::Bytes(vec![0xAA; DPE_PROFILE.get_ecc_int_size()])
+        );
+        assert_eq!(
+            claim(&claims, CwtWriter::TCI_NODES_CLAIM),
+            &Value::Array(vec![Value::Array(vec![
+                Value::Integer(0x44332211.into()),
+                Value::Integer(0.into()),
+                Value::Bytes(vec![0x03; DPE_PROFILE.get_tci_size()]),
+                Value::Bytes(vec![0; DPE_PROFILE.get_tci_size()]),
+            ])])
+        );
+        assert_eq!(
+            claim(&claims, CwtWriter::PROFILE_CLAIM),
+            &Value::Integer((DPE_PROFILE as u32).into())
+        );
+    }
+
+    #[test]
+    fn test_ec2_cose_key() {
+        let mut buf = [0u8; 256];
+        let mut w = CwtWriter::new(&mut buf);
+        let bytes_written = w.encode_ec2_cose_key(&test_pub_key()).unwrap();
+        assert_eq!(bytes_written, CwtWriter::COSE_KEY_SIZE);
+
+        let cose_key = decode(&buf[..bytes_written]);
+        assert_eq!(cose_key.as_map().unwrap().len(), 5);
+        assert_eq!(
+            claim(&cose_key, CwtWriter::KTY_LABEL),
+            &Value::Integer(CwtWriter::KTY_EC2.into())
+        );
+        assert_eq!(
+            claim(&cose_key, CwtWriter::Y_LABEL),
+            &Value::Bytes(vec![0xBB; DPE_PROFILE.get_ecc_int_size()])
+        );
+
+        // A key of the wrong size is rejected rather than mis-encoded.
+        let short_key = EcdsaPub {
+            x: CryptoBuf::new(&[0xAA; 8]).unwrap(),
+            y: CryptoBuf::new(&[0xBB; 8]).unwrap(),
+        };
+        let mut w = CwtWriter::new(&mut buf);
+        assert_eq!(
+            w.encode_ec2_cose_key(&short_key),
+            Err(DpeErrorCode::InternalError)
+        );
+    }
+
+    #[test]
+    fn test_cose_sign1() {
+        let claims = [0xA0]; // empty map
+        let sig = EcdsaSig {
+            r: CryptoBuf::new(&[0x11; DPE_PROFILE.get_ecc_int_size()]).unwrap(),
+            s: CryptoBuf::new(&[0x22; DPE_PROFILE.get_ecc_int_size()]).unwrap(),
+        };
+
+        let mut buf = [0u8; 256];
+        let mut w = CwtWriter::new(&mut buf);
+        let bytes_written = w.encode_cose_sign1(&claims, &sig).unwrap();
+        let Value::Tag(tag, sign1) = decode(&buf[..bytes_written]) else {
+            panic!("COSE_Sign1 is not tagged");
+        };
+        assert_eq!(tag, CwtWriter::COSE_SIGN1_TAG);
+
+        let sign1 = sign1.as_array().unwrap();
+        assert_eq!(sign1.len(), 4);
+        let protected = decode(sign1[0].as_bytes().unwrap());
+        assert_eq!(
+            claim(&protected, CwtWriter::ALG_LABEL),
+            &Value::Integer(CwtWriter::ALG.into())
+        );
+        assert_eq!(sign1[1], Value::Map(vec![]));
+        assert_eq!(sign1[2], Value::Bytes(claims.to_vec()));
+        let mut expected_sig = vec![0x11; DPE_PROFILE.get_ecc_int_size()];
+        expected_sig.extend_from_slice(&[0x22; DPE_PROFILE.get_ecc_int_size()]);
+        assert_eq!(sign1[3], Value::Bytes(expected_sig));
+    }
+
+    #[test]
+    fn test_sig_structure_prefix() {
+        let payload = [0xA0; 300];
+        let mut buf = [0u8; 512];
+        let mut w = CwtWriter::new(&mut buf);
+        let prefix_len = w.encode_sig_structure_prefix(payload.len()).unwrap();
+        assert!(prefix_len <= CwtWriter::MAX_SIG_STRUCTURE_PREFIX_SIZE);
+        buf[prefix_len..prefix_len + payload.len()].copy_from_slice(&payload);
+
+        let sig_structure = decode(&buf[..prefix_len + payload.len()]);
+        let sig_structure = sig_structure.as_array().unwrap();
+        assert_eq!(sig_structure[0], Value::Text("Signature1".into()));
+        assert_eq!(sig_structure[2], Value::Bytes(vec![]));
+        assert_eq!(sig_structure[3], Value::Bytes(payload.to_vec()));
+    }
+}
@@ -0,0 +1,689 @@
+// Licensed under the Apache-2.0 license
+
+//! Minimal ASN.1 DER writer and reader
+//!
+//! `DerWriter` encodes into a caller-provided buffer, so it can be used in a
+//! no_std environment. DER needs the length of a value before its contents,
+//! so callers first compute the size of each constructed value with the
+//! `*_size` helpers and then write its header followed by its contents.
+//!
+//! `DerReader` walks encoded values in place without allocating. It only
+//! understands single-byte tags and definite lengths, which is all DPE
+//! emits.
+
+use crate::response::DpeErrorCode;
+
+pub struct DerWriter<'a> {
+    buf: &'a mut [u8],
+    offset: usize,
+}
+
+impl<'a> DerWriter<'a> {
+    pub const BOOL_TAG: u8 = 0x1;
+    pub const INTEGER_TAG: u8 = 0x2;
+    pub const BIT_STRING_TAG: u8 = 0x3;
+    pub const OCTET_STRING_TAG: u8 = 0x4;
+    pub const NULL_TAG: u8 = 0x5;
+    pub const OID_TAG: u8 = 0x6;
+    pub const UTF8_STRING_TAG: u8 = 0xC;
+    pub const PRINTABLE_STRING_TAG: u8 = 0x13;
+    pub const GENERALIZED_TIME_TAG: u8 = 0x18;
+    pub const SEQUENCE_TAG: u8 = 0x30;
+    pub const SEQUENCE_OF_TAG: u8 = 0x30;
+    pub const SET_OF_TAG: u8 = 0x31;
+
+    pub const BOOL_SIZE: usize = 1;
+    /// Size of an encoded NULL, including the tag and size fields
+    pub const NULL_SIZE: usize = 2;
+
+    /// Largest number of bytes in a long-form length
+    pub const MAX_SIZE_BYTES: usize = 4;
+
+    // Constants for setting tag bits
+    pub const CONTEXT_SPECIFIC: u8 = 0x80; // Used for Implicit/Explicit tags
+    pub const CONSTRUCTED: u8 = 0x20; // SET{OF} and SEQUENCE{OF} have this bit set
+
+    /// Build a new DerWriter that writes output to `buf`
+    pub fn new(buf: &'a mut [u8]) -> DerWriter<'a> {
+        DerWriter { buf, offset: 0 }
+    }
+
+    /// Number of bytes written so far
+    pub fn len(&self) -> usize {
+        self.offset
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.offset == 0
+    }
+
+    /// Tag of a context-specific field `[number]`
+    ///
+    /// Set `constructed` for SEQUENCE/SET values and for EXPLICIT tags.
+    pub const fn context_tag(number: u8, constructed: bool) -> u8 {
+        if constructed {
+            Self::CONTEXT_SPECIFIC | Self::CONSTRUCTED | number
+        } else {
+            Self::CONTEXT_SPECIFIC | number
+        }
+    }
+
+    /// Calculate the number of bytes the ASN.1 size field will be
+    ///
+    /// Sizes up to 127 use the short form. Larger sizes use the long form: a
+    /// byte holding the number of length bytes followed by the big-endian
+    /// length, which is limited to `MAX_SIZE_BYTES`.
+    pub fn size_width(size: usize) -> Result<usize, DpeErrorCode> {
+        if size <= 127 {
+            return Ok(1);
+        }
+
+        let len_bytes = ((usize::BITS - size.leading_zeros() + 7) / 8) as usize;
+        if len_bytes > Self::MAX_SIZE_BYTES {
+            return Err(DpeErrorCode::CertificateTooLarge);
+        }
+
+        Ok(1 + len_bytes)
+    }
+
+    /// Get the size of an ASN.1 structure
+    /// If tagged, includes the tag and size
+    pub fn structure_size(data_size: usize, tagged: bool) -> Result<usize, DpeErrorCode> {
+        let size = if tagged {
+            1 + Self::size_width(data_size)? + data_size
+        } else {
+            data_size
+        };
+
+        Ok(size)
+    }
+
+    /// Calculate the number of bytes the ASN.1 INTEGER will be
+    /// If `tagged`, include the tag and size fields
+    pub fn integer_bytes_size(integer: &[u8], tagged: bool) -> Result<usize, DpeErrorCode> {
+        let mut len = integer.len();
+        for (i, &byte) in integer.iter().enumerate() {
+            if byte == 0 && i != integer.len() - 1 {
+                len -= 1;
+            } else if (byte & 0x80) != 0 {
+                len += 1;
+                break;
+            } else {
+                break;
+            }
+        }
+
+        Self::structure_size(len, tagged)
+    }
+
+    /// Calculate the number of bytes the ASN.1 INTEGER will be
+    /// If `tagged`, include the tag and size fields
+    pub fn integer_size(integer: u64, tagged: bool) -> Result<usize, DpeErrorCode> {
+        let bytes = integer.to_be_bytes();
+        Self::integer_bytes_size(&bytes, tagged)
+    }
+
+    /// Calculate the number of bytes an ASN.1 raw bytes field will be.
+    /// Can be used for OCTET STRING, OID, UTF8 STRING, etc.
+    /// If `tagged`, include the tag and size fields
+    pub fn bytes_size(bytes: &[u8], tagged: bool) -> Result<usize, DpeErrorCode> {
+        Self::structure_size(bytes.len(), tagged)
+    }
+
+    /// Calculate the number of bytes an ASN.1 BIT STRING holding `bytes` will be.
+    /// If `tagged`, include the tag and size fields
+    pub fn bit_string_size(bytes: &[u8], tagged: bool) -> Result<usize, DpeErrorCode> {
+        // Leading byte holds the number of unused bits.
+        Self::structure_size(1 + bytes.len(), tagged)
+    }
+
+    /// Write all of `bytes` to the buffer
+    pub fn write_bytes(&mut self, bytes: &[u8]) -> Result<usize, DpeErrorCode> {
+        let size = bytes.len();
+
+        if self.offset >= self.buf.len() || self.offset + size > self.buf.len() {
+            return Err(DpeErrorCode::CertificateTooLarge);
+        }
+
+        self.buf
+            .get_mut(self.offset..self.offset + size)
+            .ok_or(DpeErrorCode::InternalError)?
+            .copy_from_slice(bytes);
+        self.offset += size;
+
+        Ok(size)
+    }
+
+    /// Write a single `byte` to the buffer
+    pub fn write_byte(&mut self, byte: u8) -> Result<usize, DpeErrorCode> {
+        if self.offset >= self.buf.len() {
+            return Err(DpeErrorCode::CertificateTooLarge);
+        }
+
+        self.buf[self.offset] = byte;
+        self.offset += 1;
+        Ok(1)
+    }
+
+    /// DER-encodes the tag field of an ASN.1 type
+    pub fn write_tag(&mut self, tag: u8) -> Result<usize, DpeErrorCode> {
+        self.write_byte(tag)
+    }
+
+    /// DER-encodes the size field of an ASN.1 type
+    pub fn write_size(&mut self, size: usize) -> Result<usize, DpeErrorCode> {
+        let size_width = Self::size_width(size)?;
+
+        if size_width == 1 {
+            self.write_byte(size as u8)?;
+        } else {
+            let rem = size_width - 1;
+            self.write_byte(0x80 | rem as u8)?;
+
+            for i in (0..rem).rev() {
+                self.write_byte((size >> (i * 8)) as u8)?;
+            }
+        }
+
+        Ok(size_width)
+    }
+
+    /// DER-encodes the tag and size of a value whose contents are `size` bytes
+    pub fn write_header(&mut self, tag: u8, size: usize) -> Result<usize, DpeErrorCode> {
+        let bytes_written = self.write_tag(tag)?;
+        Ok(bytes_written + self.write_size(size)?)
+    }
+
+    /// DER-encodes the header of a SEQUENCE whose contents are `size` bytes
+    pub fn write_sequence_header(&mut self, size: usize) -> Result<usize, DpeErrorCode> {
+        self.write_header(Self::SEQUENCE_TAG, size)
+    }
+
+    /// DER-encodes the header of a SET whose contents are `size` bytes
+    pub fn write_set_header(&mut self, size: usize) -> Result<usize, DpeErrorCode> {
+        self.write_header(Self::SET_OF_TAG, size)
+    }
+
+    /// DER-encodes the header of a context-specific field `[number]` whose
+    /// contents are `size` bytes
+    pub fn write_context_header(
+        &mut self,
+        number: u8,
+        constructed: bool,
+        size: usize,
+    ) -> Result<usize, DpeErrorCode> {
+        self.write_header(Self::context_tag(number, constructed), size)
+    }
+
+    /// DER-encodes a big-endian integer buffer as an ASN.1 INTEGER
+    pub fn write_integer_bytes(&mut self, integer: &[u8]) -> Result<usize, DpeErrorCode> {
+        let mut bytes_written = self.write_tag(Self::INTEGER_TAG)?;
+
+        let size = Self::integer_bytes_size(integer, false)?;
+        bytes_written += self.write_size(size)?;
+
+        // Compute where to start reading from integer (strips leading zeros)
+        let integer_offset = integer.len().saturating_sub(size);
+
+        // If size got larger it is because a null byte needs to be prepended
+        if size > integer.len() {
+            bytes_written += self.write_byte(0)?;
+        }
+
+        if integer_offset >= integer.len() {
+            return Err(DpeErrorCode::InternalError);
+        }
+        bytes_written += self.write_bytes(&integer[integer_offset..])?;
+
+        Ok(bytes_written)
+    }
+
+    /// DER-encodes `integer` as an ASN.1 INTEGER
+    pub fn write_integer(&mut self, integer: u64) -> Result<usize, DpeErrorCode> {
+        self.write_integer_bytes(&integer.to_be_bytes())
+    }
+
+    /// DER-encodes `oid` as an ASN.1 ObjectIdentifier
+    ///
+    /// `oid` holds the already encoded arcs, without tag or size.
+    pub fn write_oid(&mut self, oid: &[u8]) -> Result<usize, DpeErrorCode> {
+        let bytes_written = self.write_header(Self::OID_TAG, oid.len())?;
+        Ok(bytes_written + self.write_bytes(oid)?)
+    }
+
+    /// DER-encodes `bytes` as an ASN.1 OCTET STRING
+    pub fn write_octet_string(&mut self, bytes: &[u8]) -> Result<usize, DpeErrorCode> {
+        let bytes_written = self.write_header(Self::OCTET_STRING_TAG, bytes.len())?;
+        Ok(bytes_written + self.write_bytes(bytes)?)
+    }
+
+    /// DER-encodes `bytes` as an ASN.1 BIT STRING with no unused bits
+    pub fn write_bit_string(&mut self, bytes: &[u8]) -> Result<usize, DpeErrorCode> {
+        let mut bytes_written =
+            self.write_header(Self::BIT_STRING_TAG, Self::bit_string_size(bytes, false)?)?;
+        bytes_written += self.write_byte(0)?;
+        Ok(bytes_written + self.write_bytes(bytes)?)
+    }
+
+    /// DER-encodes `val` as an ASN.1 BOOLEAN
+    pub fn write_bool(&mut self, val: bool) -> Result<usize, DpeErrorCode> {
+        let bytes_written = self.write_header(Self::BOOL_TAG, Self::BOOL_SIZE)?;
+        Ok(bytes_written + self.write_byte(if val { 0xFF } else { 0x00 })?)
+    }
+
+    /// DER-encodes an ASN.1 NULL
+    pub fn write_null(&mut self) -> Result<usize, DpeErrorCode> {
+        self.write_header(Self::NULL_TAG, 0)
+    }
+}
+
+pub struct DerReader<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> DerReader<'a> {
+    /// Build a new DerReader that reads the values in `buf`
+    pub fn new(buf: &'a [u8]) -> DerReader<'a> {
+        DerReader { buf }
+    }
+
+    /// Whether all values have been read
+    pub fn is_empty(&self) -> bool {
+        self.buf.is_empty()
+    }
+
+    /// Tag of the next value, without consuming it
+    pub fn peek_tag(&self) -> Option<u8> {
+        self.buf.first().copied()
+    }
+
+    /// Splits the next value into its tag, its contents and the size of its
+    /// whole encoding
+    ///
+    /// Lengths must use the shortest form, as DER requires.
+    fn next_value(&self) -> Result<(u8, &'a [u8], usize), DpeErrorCode> {
+        let (&tag, rest) = self
+            .buf
+            .split_first()
+            .ok_or(DpeErrorCode::InvalidCertificate)?;
+        // High tag numbers take more than one byte.
+        if tag & 0x1F == 0x1F {
+            return Err(DpeErrorCode::InvalidCertificate);
+        }
+
+        let (&first, rest) = rest.split_first().ok_or(DpeErrorCode::InvalidCertificate)?;
+        let (size, rest) = if first & 0x80 == 0 {
+            (first as usize, rest)
+        } else {
+            let len_bytes = (first & 0x7F) as usize;
+            if len_bytes == 0 || len_bytes > DerWriter::MAX_SIZE_BYTES || len_bytes > rest.len() {
+                return Err(DpeErrorCode::InvalidCertificate);
+            }
+            let (size_bytes, rest) = rest.split_at(len_bytes);
+            let size = size_bytes
+                .iter()
+                .fold(0usize, |size, byte| (size << 8) | *byte as usize);
+            if DerWriter::size_width(size)? != 1 + len_bytes {
+                return Err(DpeErrorCode::InvalidCertificate);
+            }
+            (size, rest)
+        };
+
+        let contents = rest.get(..size).ok_or(DpeErrorCode::InvalidCertificate)?;
+        let header_size = self.buf.len() - rest.len();
+        Ok((tag, contents, header_size + size))
+    }
+
+    /// Reads the next value, returning its tag and contents
+    pub fn read_any(&mut self) -> Result<(u8, &'a [u8]), DpeErrorCode> {
+        let (tag, contents, size) = self.next_value()?;
+        self.buf = &self.buf[size..];
+        Ok((tag, contents))
+    }
+
+    /// Reads the next value, which must have `tag`, and returns its contents
+    pub fn read(&mut self, tag: u8) -> Result<&'a [u8], DpeErrorCode> {
+        match self.read_any()? {
+            (t, contents) if t == tag => Ok(contents),
+            _ => Err(DpeErrorCode::InvalidCertificate),
+        }
+    }
+
+    /// Reads the next value, which must have `tag`, and returns its whole
+    /// encoding including tag and size
+    pub fn read_raw(&mut self, tag: u8) -> Result<&'a [u8], DpeErrorCode> {
+        let (t, _, size) = self.next_value()?;
+        if t != tag {
+            return Err(DpeErrorCode::InvalidCertificate);
+        }
+        let (raw, rest) = self.buf.split_at(size);
+        self.buf = rest;
+        Ok(raw)
+    }
+
+    /// Reads the next value if it has `tag`
+    pub fn read_optional(&mut self, tag: u8) -> Result<Option<&'a [u8]>, DpeErrorCode> {
+        if self.peek_tag() != Some(tag) {
+            return Ok(None);
+        }
+        self.read(tag).map(Some)
+    }
+
+    /// Reads a SEQUENCE and returns a reader over its contents
+    pub fn read_sequence(&mut self) -> Result<DerReader<'a>, DpeErrorCode> {
+        Ok(DerReader::new(self.read(DerWriter::SEQUENCE_TAG)?))
+    }
+
+    /// Reads a non-negative INTEGER and returns its big-endian bytes without
+    /// the leading zero DER adds to keep the sign bit clear
+    pub fn read_integer_bytes(&mut self) -> Result<&'a [u8], DpeErrorCode> {
+        let integer = self.read(DerWriter::INTEGER_TAG)?;
+        match integer {
+            [] => Err(DpeErrorCode::InvalidCertificate),
+            [first, ..] if first & 0x80 != 0 => Err(DpeErrorCode::InvalidCertificate),
+            [0, second, ..] if second & 0x80 == 0 => Err(DpeErrorCode::InvalidCertificate),
+            [0, rest @ ..] if !rest.is_empty() => Ok(rest),
+            _ => Ok(integer),
+        }
+    }
+
+    /// Reads an OBJECT IDENTIFIER and returns its encoded arcs
+    pub fn read_oid(&mut self) -> Result<&'a [u8], DpeErrorCode> {
+        self.read(DerWriter::OID_TAG)
+    }
+
+    /// Reads a BIT STRING with no unused bits and returns its bytes
+    pub fn read_bit_string(&mut self) -> Result<&'a [u8], DpeErrorCode> {
+        match self.read(DerWriter::BIT_STRING_TAG)? {
+            [0, bytes @ ..] => Ok(bytes),
+            _ => Err(DpeErrorCode::InvalidCertificate),
+        }
+    }
+
+    /// Fails if there are values left to read
+    pub fn finish(&self) -> Result<(), DpeErrorCode> {
+        if self.is_empty() {
+            Ok(())
+        } else {
+            Err(DpeErrorCode::InvalidCertificate)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use openssl::asn1::Asn1Object;
+    use openssl::bn::BigNum;
+
+    #[derive(asn1::Asn1Read)]
+    struct Tagged<'a> {
+        #[implicit(2)]
+        value: Option<&'a [u8]>,
+        #[explicit(3)]
+        flag: Option<bool>,
+    }
+
+    #[test]
+    fn test_integers() {
+        let buffer_cases = [
+            [0; 8],
+            [0xFF, 0xFF, 0xFF, 0xFF, 0x00, 0x00, 0x00, 0x00],
+            [0x01, 0x02, 0x03, 0x04, 0x00, 0x00, 0x00, 0x00],
+            [0x00, 0x00, 0xFF, 0x04, 0x00, 0x00, 0x00, 0x00],
+            [0x01, 0x02, 0x03, 0x04, 0x00, 0x00, 0x00, 0x00],
+        ];
+
+        for c in buffer_cases {
+            let mut buf = [0u8; 128];
+            let mut w = DerWriter::new(&mut buf);
+            let byte_count = w.write_integer_bytes(&c).unwrap();
+            let n = asn1::parse_single::<u64>(&buf[..byte_count]).unwrap();
+            assert_eq!(n, u64::from_be_bytes(c));
+            assert_eq!(DerWriter::integer_bytes_size(&c, true).unwrap(), byte_count);
+        }
+
+        let integer_cases = [0xFFFFFFFF00000000, 0x0102030405060708, 0x2];
+
+        for c in integer_cases {
+            let mut buf = [0; 128];
+            let mut w = DerWriter::new(&mut buf);
+            let byte_count = w.write_integer(c).unwrap();
+            let n = asn1::parse_single::<u64>(&buf[..byte_count]).unwrap();
+            assert_eq!(n, c);
+            assert_eq!(DerWriter::integer_size(c, true).unwrap(), byte_count);
+        }
+    }
+
+    #[test]
+    fn test_big_integer() {
+        // Leading 1 bit so a zero byte has to be prepended.
+        let integer = [0xA5; 48];
+        let mut buf = [0u8; 128];
+        let mut w = DerWriter::new(&mut buf);
+        let byte_count = w.write_integer_bytes(&integer).unwrap();
+
+        let parsed = asn1::parse_single::<asn1::BigUint>(&buf[..byte_count]).unwrap();
+        let parsed = BigNum::from_slice(parsed.as_bytes()).unwrap();
+        assert_eq!(parsed, BigNum::from_slice(&integer).unwrap());
+        assert_eq!(
+            DerWriter::integer_bytes_size(&integer, true).unwrap(),
+            byte_count
+        );
+    }
+
+    #[test]
+    fn test_size_field() {
+        for size in [0, 1, 127, 128, 255, 256, 4096, 65535, 65536, 0x12_3456] {
+            let contents = vec![0xaa; size];
+            let mut buf = vec![0u8; size + 8];
+            let mut w = DerWriter::new(&mut buf);
+            let byte_count = w.write_octet_string(&contents).unwrap();
+
+            assert_eq!(DerWriter::bytes_size(&contents, true).unwrap(), byte_count);
+            assert_eq!(1 + DerWriter::size_width(size).unwrap() + size, byte_count);
+            assert_eq!(
+                asn1::parse_single::<&[u8]>(&buf[..byte_count]).unwrap(),
+                contents
+            );
+        }
+    }
+
+    #[test]
+    fn test_size_width() {
+        assert_eq!(DerWriter::size_width(0), Ok(1));
+        assert_eq!(DerWriter::size_width(127), Ok(1));
+        assert_eq!(DerWriter::size_width(128), Ok(2));
+        assert_eq!(DerWriter::size_width(0xFF), Ok(2));
+        assert_eq!(DerWriter::size_width(0x100), Ok(3));
+        assert_eq!(DerWriter::size_width(0xFFFF), Ok(3));
+        assert_eq!(DerWriter::size_width(0x1_0000), Ok(4));
+        assert_eq!(DerWriter::size_width(0xFF_FFFF), Ok(4));
+        assert_eq!(DerWriter::size_width(0x100_0000), Ok(5));
+        assert_eq!(DerWriter::size_width(u32::MAX as usize), Ok(5));
+        #[cfg(target_pointer_width = "64")]
+        assert_eq!(
+            DerWriter::size_width(u32::MAX as usize + 1),
+            Err(DpeErrorCode::CertificateTooLarge)
+        );
+
+        let mut buf = [0u8; 5];
+        let mut w = DerWriter::new(&mut buf);
+        assert_eq!(w.write_size(0x0102_0304), Ok(5));
+        assert_eq!(buf, [0x84, 0x01, 0x02, 0x03, 0x04]);
+    }
+
+    #[test]
+    fn test_oid() {
+        for oid in ["2.23.133.5.4.5", "1.2.840.10045.4.3.3", "2.5.29.19"] {
+            let obj = Asn1Object::from_str(oid).unwrap();
+            let mut buf = [0u8; 32];
+            let mut w = DerWriter::new(&mut buf);
+            let byte_count = w.write_oid(obj.as_slice()).unwrap();
+
+            assert_eq!(
+                asn1::parse_single::<asn1::ObjectIdentifier>(&buf[..byte_count]).unwrap(),
+                asn1::ObjectIdentifier::from_string(oid).unwrap()
+            );
+            assert_eq!(
+                DerWriter::bytes_size(obj.as_slice(), true).unwrap(),
+                byte_count
+            );
+        }
+    }
+
+    #[test]
+    fn test_bit_string() {
+        let bits = [0x04, 0xaa, 0xbb, 0xcc];
+        let mut buf = [0u8; 16];
+        let mut w = DerWriter::new(&mut buf);
+        let byte_count = w.write_bit_string(&bits).unwrap();
+
+        let parsed = asn1::parse_single::<asn1::BitString>(&buf[..byte_count]).unwrap();
+        assert_eq!(parsed.as_bytes(), bits);
+        assert_eq!(parsed.padding_bits(), 0);
+        assert_eq!(DerWriter::bit_string_size(&bits, true).unwrap(), byte_count);
+    }
+
+    #[test]
+    fn test_bool() {
+        for val in [true, false] {
+            let mut buf = [0u8; 3];
+            let mut w = DerWriter::new(&mut buf);
+            let byte_count = w.write_bool(val).unwrap();
+            assert_eq!(byte_count, buf.len());
+            assert_eq!(asn1::parse_single::<bool>(&buf).unwrap(), val);
+        }
+    }
+
+    #[test]
+    fn test_null() {
+        let mut buf = [0u8; DerWriter::NULL_SIZE];
+        let mut w = DerWriter::new(&mut buf);
+        assert_eq!(w.write_null().unwrap(), DerWriter::NULL_SIZE);
+        asn1::parse_single::<asn1::Null>(&buf).unwrap();
+    }
+
+    #[test]
+    fn test_context_tags() {
+        let value = [0x11, 0x22, 0x33];
+        let value_size = DerWriter::bytes_size(&value, true).unwrap();
+        let flag_size = DerWriter::structure_size(DerWriter::BOOL_SIZE, true).unwrap();
+        let explicit_size = DerWriter::structure_size(flag_size, true).unwrap();
+        let seq_size = value_size + explicit_size;
+
+        let mut buf = [0u8; 32];
+        let mut w = DerWriter::new(&mut buf);
+        let mut byte_count = w.write_sequence_header(seq_size).unwrap();
+        // [2] IMPLICIT OCTET STRING
+        byte_count += w.write_context_header(2, false, value.len()).unwrap();
+        byte_count += w.write_bytes(&value).unwrap();
+        // [3] EXPLICIT BOOLEAN
+        byte_count += w.write_context_header(3, true, flag_size).unwrap();
+        byte_count += w.write_bool(true).unwrap();
+        assert_eq!(w.len(), byte_count);
+        assert_eq!(
+            DerWriter::structure_size(seq_size, true).unwrap(),
+            byte_count
+        );
+
+        let parsed = asn1::parse_single::<Tagged>(&buf[..byte_count]).unwrap();
+        assert_eq!(parsed.value, Some(&value[..]));
+        assert_eq!(parsed.flag, Some(true));
+    }
+
+    #[test]
+    fn test_reader_round_trip() {
+        let integer = [0x80, 0x01];
+        let oid = [0x55, 0x04, 0x03];
+        let bits = [0x04, 0x05];
+        let big = [0xAB; 300];
+        let seq_size = DerWriter::integer_bytes_size(&integer, true).unwrap()
+            + DerWriter::bytes_size(&oid, true).unwrap()
+            + DerWriter::bit_string_size(&bits, true).unwrap()
+            + DerWriter::bytes_size(&big, true).unwrap()
+            + DerWriter::structure_size(DerWriter::BOOL_SIZE, true).unwrap();
+
+        let mut buf = [0u8; 512];
+        let mut w = DerWriter::new(&mut buf);
+        w.write_sequence_header(seq_size).unwrap();
+        w.write_integer_bytes(&integer).unwrap();
+        w.write_oid(&oid).unwrap();
+        w.write_bit_string(&bits).unwrap();
+        w.write_octet_string(&big).unwrap();
+        w.write_bool(true).unwrap();
+        let len = w.len();
+
+        let mut r = DerReader::new(&buf[..len]);
+        let mut seq = r.read_sequence().unwrap();
+        r.finish().unwrap();
+        assert_eq!(seq.read_integer_bytes().unwrap(), integer);
+        assert_eq!(seq.read_oid().unwrap(), oid);
+        assert_eq!(seq.read_bit_string().unwrap(), bits);
+        assert_eq!(seq.read_optional(DerWriter::INTEGER_TAG).unwrap(), None);
+        assert_eq!(
+            seq.read_optional(DerWriter::OCTET_STRING_TAG).unwrap(),
+            Some(&big[..])
+        );
+        let raw = seq.read_raw(DerWriter::BOOL_TAG).unwrap();
+        assert_eq!(raw, [DerWriter::BOOL_TAG, 1, 0xFF]);
+        seq.finish().unwrap();
+    }
+
+    #[test]
+    fn test_reader_rejects_non_der() {
+        let cases: [&[u8]; 7] = [
+            // Truncated contents.
+            &[0x04, 0x02, 0x00],
+            // Indefinite length.
+            &[0x30, 0x80, 0x00, 0x00],
+            // Long form for a short length.
+            &[0x04, 0x81, 0x01, 0x00],
+            // Leading zero in a long-form length.
+            &[0x04, 0x82, 0x00, 0x80],
+            // Multi-byte tag.
+            &[0x1F, 0x81, 0x01, 0x00],
+            // Negative INTEGER.
+            &[0x02, 0x01, 0x80],
+            // Unneeded leading zero in an INTEGER.
+            &[0x02, 0x02, 0x00, 0x01],
+        ];
+        for case in cases {
+            let mut r = DerReader::new(case);
+            let result = if case[0] == DerWriter::INTEGER_TAG {
+                r.read_integer_bytes()
+            } else {
+                r.read_any().map(|(_, contents)| contents)
+            };
+            assert_eq!(result, Err(DpeErrorCode::InvalidCertificate), "{case:x?}");
+        }
+
+        // Wrong tag.
+        let mut r = DerReader::new(&[0x05, 0x00]);
+        assert!(r.read(DerWriter::SEQUENCE_TAG).is_err());
+
+        // Unused bits in a BIT STRING.
+        let mut r = DerReader::new(&[0x03, 0x02, 0x01, 0xFE]);
+        assert!(r.read_bit_string().is_err());
+
+        // Trailing data.
+        let mut r = DerReader::new(&[0x05, 0x00, 0x05, 0x00]);
+        r.read_any().unwrap();
+        assert!(r.finish().is_err());
+    }
+
+    #[test]
+    fn test_buffer_too_small() {
+        let mut buf = [0u8; 4];
+        let mut w = DerWriter::new(&mut buf);
+        assert_eq!(
+            w.write_octet_string(&[0; 3]),
+            Err(DpeErrorCode::CertificateTooLarge)
+        );
+
+        let mut buf = [0u8; 0];
+        let mut w = DerWriter::new(&mut buf);
+        assert_eq!(w.write_byte(0), Err(DpeErrorCode::CertificateTooLarge));
+        assert!(w.is_empty());
+    }
+}
@@ -4,13 +4,30 @@ Licensed under the Apache-2.0 license.
 Abstract:
     Defines an instance of DPE and all of its contexts.
 --*/
+#[cfg(feature = "no-large-stack")]
+use crate::arena::{Scratch, ARENA_SIZE};
 use crate::{
-    commands::{Command, CommandExecution, InitCtxCmd},
-    context::{ChildToRootIter, Context, ContextHandle, ContextState},
-    response::{DpeErrorCode, GetProfileResp, Response, ResponseHdr},
+    command_auth::{AuthCmdHdr, AuthCounter, COMMAND_AUTH_TAG_SIZE, MAX_AUTH_LOCALITIES},
+    commands::{
+        read_prefix, CertifyKeyCmd, CertifyKeyFlags, Command, CommandExecution, DeviceIdCert,
+        InitCtxCmd, NoVendorCommands, PendingCert, SignCmd, VendorCommandHandler,
+    },
+    context::{ChildIter, ChildToRootIter, Context, ContextHandle, ContextState, ContextType},
+    hash_stream::{HashStream, MAX_HASH_STREAMS},
+    locality::LocalityTable,
+    pending::{CommandStatus, PendingOp, PendingToken},
+    response::{
+        DpeErrorCode, GetProfileResp, Response, ResponseHdr, ResponseWriter, SessionResp,
+        VendorResp,
+    },
+    session::{Session, SessionMsgHdr, MAX_SESSIONS, MAX_SESSION_CMD_SIZE},
+    spdm,
     support::Support,
     tci::{TciMeasurement, TciNodeData},
-    U8Bool, DPE_PROFILE, INTERNAL_INPUT_INFO_SIZE, MAX_HANDLES,
+    validation::DpeValidator,
+    wire::WireEndian,
+    x509::EcdsaCertificate,
+    DpeProfile, U8Bool, DPE_PROFILE, INTERNAL_INPUT_INFO_SIZE, MAX_HANDLES,
 };
 #[cfg(not(feature = "no-cfi"))]
 use caliptra_cfi_derive_git::cfi_impl_fn;
@@ -19,8 +36,12 @@ use caliptra_cfi_lib_git::cfi_launder;
 use caliptra_cfi_lib_git::{cfi_assert, cfi_assert_eq};
 use cfg_if::cfg_if;
 use constant_time_eq::constant_time_eq;
-use crypto::{Crypto, Digest, Hasher};
-use platform::{Platform, MAX_CHUNK_SIZE};
+use core::mem::size_of;
+use crypto::{AeadKey, Crypto, Digest, Hasher, AEAD_IV_SIZE, AEAD_KEY_SIZE, AEAD_TAG_SIZE};
+use platform::{
+    LocalityDescriptor, Platform, PlatformError, COMMAND_AUTH_KEY_SIZE, MAX_CHUNK_SIZE,
+    MAX_ISSUER_NAME_SIZE,
+};
 use zerocopy::{AsBytes, FromBytes};
 use zeroize::Zeroize;
 
@@ -36,48 +57,176 @@ pub trait DpeTypes {
 pub struct DpeEnv<'a, T: DpeTypes + 'a> {
     pub crypto: T::Crypto<'a>,
     pub platform: T::Platform<'a>,
+    /// Memory for the scratch buffers of commands with the `no-large-stack`
+    /// feature, which must then hold at least `arena::ARENA_SIZE` bytes.
+    /// Without the feature it is unused and may be empty.
+    pub arena: &'a mut [u8],
 }
 
 #[repr(C, align(4))]
 #[derive(AsBytes, FromBytes, Zeroize)]
 pub struct DpeInstance {
     pub contexts: [Context; MAX_HANDLES],
+    pub(crate) sessions: [Session; MAX_SESSIONS],
+    /// Replay state of the localities that sent authenticated commands.
+    pub(crate) auth_counters: [AuthCounter; MAX_AUTH_LOCALITIES],
+    /// Localities the platform lets send commands.
+    pub(crate) localities: LocalityTable,
+    pub(crate) hash_streams: [HashStream; MAX_HASH_STREAMS],
+    pub(crate) pending_cert: PendingCert,
+    /// Self-signed certificate issued at initialization when
+    /// `Support::DEVICE_ID_CERT` is set.
+    pub(crate) device_id_cert: DeviceIdCert,
+    /// Sign command `start_command` left for the crypto implementation to
+    /// finish.
+    pub(crate) pending_op: PendingOp,
     pub(crate) support: Support,
 
+    /// Per-boot secret used to MAC context handles when `Support::HANDLE_MAC` is set.
+    pub(crate) handle_mac_key: [u8; DpeInstance::HANDLE_MAC_KEY_SIZE],
+
     /// Can only successfully execute the initialize context command for non-simulation (i.e.
     /// `InitializeContext(simulation=false)`) once per reset cycle.
     pub(crate) has_initialized: U8Bool,
 
+    /// Number of simulation contexts InitializeContext may have open at once.
+    pub(crate) max_simulation_contexts: u8,
+
+    /// Number of contexts, counted from a root, that DeriveContext may chain
+    /// together.
+    pub(crate) max_cert_chain_depth: u8,
+
     // unused buffer added to make DpeInstance word aligned and remove padding
-    reserved: [u8; 3],
+    reserved: [u8; 1],
+}
+
+/// Header prepended to a serialized `DpeInstance`. It allows the state to be
+/// checked for compatibility and authenticity before it is restored.
+#[repr(C)]
+#[derive(AsBytes, FromBytes, Clone, Copy)]
+pub struct PersistedStateHdr {
+    pub magic: u32,
+    pub version: u32,
+    pub profile: u32,
+    pub max_handles: u32,
+    pub state_size: u32,
+    /// IV the `DpeInstance` that follows the header is encrypted with.
+    pub iv: [u8; AEAD_IV_SIZE],
+    /// AES-256-GCM tag over the encrypted `DpeInstance`, with this header,
+    /// tag zeroed, as additional data.
+    pub tag: [u8; AEAD_TAG_SIZE],
+}
+
+impl PersistedStateHdr {
+    pub const MAGIC: u32 = u32::from_be_bytes(*b"DPES");
+    pub const VERSION: u32 = 24;
+
+    /// Returns the header as it is authenticated, with the tag zeroed.
+    fn aad(&self) -> PersistedStateHdr {
+        PersistedStateHdr {
+            tag: [0; AEAD_TAG_SIZE],
+            ..*self
+        }
+    }
 }
 
 impl DpeInstance {
     const MAX_NEW_HANDLE_ATTEMPTS: usize = 8;
+    const HANDLE_MAC_KEY_SIZE: usize = 32;
+
+    /// Number of bytes needed to hold the output of `DpeInstance::serialize`.
+    pub const SERIALIZED_SIZE: usize = size_of::<PersistedStateHdr>() + size_of::<DpeInstance>();
 
-    /// Create a new DPE instance.
+    /// Create a new DPE instance running `DPE_PROFILE`.
     ///
     /// # Arguments
     ///
     /// * `env` - DPE environment containing Crypto and Platform implementations
     /// * `support` - optional functionality the instance supports
-    #[cfg_attr(not(feature = "no-cfi"), cfi_impl_fn)]
     pub fn new(
         env: &mut DpeEnv<impl DpeTypes>,
         support: Support,
     ) -> Result<DpeInstance, DpeErrorCode> {
+        Self::new_for_profile(env, support, DPE_PROFILE)
+    }
+
+    /// Create a new DPE instance running `profile`.
+    ///
+    /// With `Support::AUTO_INIT`, the default context is initialized for the
+    /// platform's auto-init locality and extended with the platform's boot
+    /// measurement, if it provides one.
+    ///
+    /// Returns `ArgumentNotSupported` if this build cannot serve `profile`,
+    /// and `InvalidArgument` if the build has the `no-large-stack` feature
+    /// and `env.arena` is smaller than `arena::ARENA_SIZE`.
+    ///
+    /// # Arguments
+    ///
+    /// * `env` - DPE environment containing Crypto and Platform implementations
+    /// * `support` - optional functionality the instance supports
+    /// * `profile` - DPE profile the instance implements
+    #[cfg_attr(not(feature = "no-cfi"), cfi_impl_fn)]
+    pub fn new_for_profile(
+        env: &mut DpeEnv<impl DpeTypes>,
+        support: Support,
+        profile: DpeProfile,
+    ) -> Result<DpeInstance, DpeErrorCode> {
+        if !profile.is_supported() {
+            return Err(DpeErrorCode::ArgumentNotSupported);
+        } else {
+            #[cfg(not(feature = "no-cfi"))]
+            cfi_assert!(profile.is_supported());
+        }
+
+        #[cfg(feature = "no-large-stack")]
+        if env.arena.len() < ARENA_SIZE {
+            return Err(DpeErrorCode::InvalidArgument);
+        }
+
         let updated_support = support.preprocess_support();
+        let localities = LocalityTable::from_platform(&mut env.platform)?;
         const CONTEXT_INITIALIZER: Context = Context::new();
+        const SESSION_INITIALIZER: Session = Session::new();
+        const AUTH_COUNTER_INITIALIZER: AuthCounter = AuthCounter::new();
+        const HASH_STREAM_INITIALIZER: HashStream = HashStream::new();
         let mut dpe = DpeInstance {
             contexts: [CONTEXT_INITIALIZER; MAX_HANDLES],
+            sessions: [SESSION_INITIALIZER; MAX_SESSIONS],
+            auth_counters: [AUTH_COUNTER_INITIALIZER; MAX_AUTH_LOCALITIES],
+            localities,
+            hash_streams: [HASH_STREAM_INITIALIZER; MAX_HASH_STREAMS],
+            pending_cert: PendingCert::new(),
+            device_id_cert: DeviceIdCert::new(),
+            pending_op: PendingOp::new(),
             support: updated_support,
+            handle_mac_key: [0u8; Self::HANDLE_MAC_KEY_SIZE],
             has_initialized: false.into(),
-            reserved: [0u8; 3],
+            max_simulation_contexts: MAX_HANDLES as u8,
+            max_cert_chain_depth: MAX_HANDLES as u8,
+            reserved: [0u8; 1],
         };
 
+        if dpe.support.handle_mac() {
+            env.crypto.rand_bytes(&mut dpe.handle_mac_key)?;
+        } else {
+            #[cfg(not(feature = "no-cfi"))]
+            cfi_assert!(!dpe.support.handle_mac());
+        }
+
         if dpe.support.auto_init() {
             let locality = env.platform.get_auto_init_locality()?;
+            dpe.localities
+                .check(locality, LocalityDescriptor::AUTO_INIT)?;
             InitCtxCmd::new_use_default().execute(&mut dpe, env, locality)?;
+
+            let mut measurement = TciMeasurement::default();
+            match env.platform.get_boot_measurement(&mut measurement.0) {
+                Ok(tci_type) => {
+                    dpe.measure_auto_init_context(env, locality, tci_type, &measurement)?
+                }
+                Err(PlatformError::NotImplemented) => (),
+                Err(e) => return Err(e.into()),
+            }
         } else {
             #[cfg(not(feature = "no-cfi"))]
             cfi_assert!(!dpe.support.auto_init());
@@ -111,248 +260,1089 @@ impl DpeInstance {
         let mut dpe = Self::new(env, updated_support)?;
 
         let locality = env.platform.get_auto_init_locality()?;
-        let idx = dpe.get_active_context_pos(&ContextHandle::default(), locality)?;
-        let mut tmp_context = dpe.contexts[idx];
-        // add measurement to auto-initialized context
-        dpe.add_tci_measurement(
+        dpe.measure_auto_init_context(
             env,
-            &mut tmp_context,
-            &TciMeasurement(auto_init_measurement),
             locality,
+            tci_type,
+            &TciMeasurement(auto_init_measurement),
         )?;
-        dpe.contexts[idx] = tmp_context;
-        dpe.contexts[idx].tci.tci_type = tci_type;
         Ok(dpe)
     }
 
-    pub fn has_initialized(&self) -> bool {
-        self.has_initialized.get()
+    /// Extends the auto-initialized default context with `measurement` and
+    /// sets its TCI type.
+    fn measure_auto_init_context(
+        &mut self,
+        env: &mut DpeEnv<impl DpeTypes>,
+        locality: u32,
+        tci_type: u32,
+        measurement: &TciMeasurement,
+    ) -> Result<(), DpeErrorCode> {
+        let idx = self.get_active_context_pos(&ContextHandle::default(), locality)?;
+        let mut tmp_context = self.contexts[idx];
+        tmp_context.tci.tci_type = tci_type;
+        self.add_tci_measurement(env, &mut tmp_context, measurement, locality)?;
+        self.commit_tci_update(env, idx, tmp_context)
     }
 
-    pub fn get_profile(
-        &self,
-        platform: &mut impl Platform,
-    ) -> Result<GetProfileResp, DpeErrorCode> {
-        let vendor_id = platform.get_vendor_id()?;
-        let vendor_sku = platform.get_vendor_sku()?;
-        Ok(GetProfileResp::new(
-            self.support.bits(),
-            vendor_id,
-            vendor_sku,
-        ))
+    pub fn has_initialized(&self) -> bool {
+        self.has_initialized.get()
     }
 
-    /// Deserializes the command and executes it.
+    /// Limits how many simulation contexts may be open at once. By default
+    /// the only limit is the size of the context table.
+    ///
+    /// Contexts that are already open are not affected; InitializeContext
+    /// fails with `MaxTcis` until enough of them have been destroyed.
     ///
     /// # Arguments
     ///
-    /// * `env` - DPE environment containing Crypto and Platform implementations
-    /// * `locality` - which hardware locality is making the request
-    /// * `cmd` - serialized command
-    #[cfg_attr(not(feature = "no-cfi"), cfi_impl_fn)]
-    pub fn execute_serialized_command(
-        &mut self,
-        env: &mut DpeEnv<impl DpeTypes>,
-        locality: u32,
-        cmd: &[u8],
-    ) -> Result<Response, DpeErrorCode> {
-        let command = Command::deserialize(cmd)?;
-        let resp = match cfi_launder(command) {
-            Command::GetProfile => Ok(Response::GetProfile(self.get_profile(&mut env.platform)?)),
-            Command::InitCtx(cmd) => cmd.execute(self, env, locality),
-            Command::DeriveContext(cmd) => cmd.execute(self, env, locality),
-            Command::CertifyKey(cmd) => cmd.execute(self, env, locality),
-            Command::Sign(cmd) => cmd.execute(self, env, locality),
-            Command::RotateCtx(cmd) => cmd.execute(self, env, locality),
-            Command::DestroyCtx(cmd) => cmd.execute(self, env, locality),
-            Command::GetCertificateChain(cmd) => cmd.execute(self, env, locality),
-        };
-
-        match resp {
-            Ok(resp) => Ok(resp),
-            Err(err_code) => Ok(Response::Error(ResponseHdr::new(err_code))),
+    /// * `max` - Maximum number of simulation contexts, at most `MAX_HANDLES`
+    pub fn set_max_simulation_contexts(&mut self, max: u32) -> Result<(), DpeErrorCode> {
+        if max as usize > MAX_HANDLES {
+            return Err(DpeErrorCode::InvalidArgument);
         }
+        self.max_simulation_contexts = max as u8;
+        Ok(())
     }
 
-    /// Finds the index of the context having `handle` in `locality`
-    /// Inlined so the callsite optimizer knows that idx < self.contexts.len()
-    /// and won't insert possible call to panic.
+    pub fn max_simulation_contexts(&self) -> u32 {
+        self.max_simulation_contexts.into()
+    }
+
+    /// Limits how deep DeriveContext may chain contexts, and so how many
+    /// certificates the chain of a context's key can hold. A root context has
+    /// depth 1. By default the only limit is the size of the context table.
+    ///
+    /// Contexts that are already deeper are not affected, but DeriveContext
+    /// can't make children of them.
     ///
     /// # Arguments
     ///
-    /// * `handle` - handle to search
-    /// * `locality` - locality to search
-    #[inline(always)]
-    pub fn get_active_context_pos(
-        &self,
-        handle: &ContextHandle,
-        locality: u32,
-    ) -> Result<usize, DpeErrorCode> {
-        let idx = self.get_active_context_pos_internal(handle, locality)?;
-        if idx >= self.contexts.len() {
-            return Err(DpeErrorCode::InternalError);
+    /// * `max` - Maximum depth, from 1 up to `MAX_HANDLES`
+    pub fn set_max_cert_chain_depth(&mut self, max: u32) -> Result<(), DpeErrorCode> {
+        if max == 0 || max as usize > MAX_HANDLES {
+            return Err(DpeErrorCode::InvalidArgument);
         }
-        Ok(idx)
+        self.max_cert_chain_depth = max as u8;
+        Ok(())
     }
 
-    fn get_active_context_pos_internal(
-        &self,
-        handle: &ContextHandle,
-        locality: u32,
-    ) -> Result<usize, DpeErrorCode> {
-        // find all active contexts whose localities match the locality parameter
-        let mut valid_localities = self
-            .contexts
-            .iter()
-            .enumerate()
-            .filter(|(_, context)| {
-                context.state == ContextState::Active && context.locality == locality
-            })
-            .peekable();
-        if valid_localities.peek().is_none() {
-            return Err(DpeErrorCode::InvalidLocality);
-        }
+    pub fn max_cert_chain_depth(&self) -> u32 {
+        self.max_cert_chain_depth.into()
+    }
 
-        // filter down the contexts with valid localities based on their context handle matching the input context handle
-        // the locality and handle filters are separated so that we can return InvalidHandle or InvalidLocality upon getting no valid contexts accordingly
-        let mut valid_handles_and_localities = valid_localities
-            .filter(|(_, context)| constant_time_eq(&context.handle.0, &handle.0))
-            .peekable();
-        if valid_handles_and_localities.peek().is_none() {
-            return Err(DpeErrorCode::InvalidHandle);
+    /// Counts the contexts from the one at `idx` up to its root, both
+    /// included.
+    pub(crate) fn context_depth(&self, idx: usize) -> Result<usize, DpeErrorCode> {
+        let mut depth = 0;
+        for status in ChildToRootIter::new(idx, &self.contexts) {
+            status?;
+            depth += 1;
         }
-        let (i, _) = valid_handles_and_localities
-            .find(|(_, context)| {
-                context.state == ContextState::Active
-                    && constant_time_eq(&context.handle.0, &handle.0)
-                    && context.locality == locality
-            })
-            .ok_or(DpeErrorCode::InternalError)?;
-        Ok(i)
+        Ok(depth)
     }
 
-    pub(crate) fn get_next_inactive_context_pos(&self) -> Option<usize> {
+    /// Counts the simulation contexts opened by InitializeContext which have
+    /// not been destroyed yet.
+    pub(crate) fn count_simulation_contexts(&self) -> usize {
         self.contexts
             .iter()
-            .position(|context| context.state == ContextState::Inactive)
+            .filter(|c| {
+                c.state != ContextState::Inactive
+                    && c.context_type == ContextType::Simulation
+                    && c.parent_idx == Context::ROOT_INDEX
+            })
+            .count()
     }
 
-    /// Recursive function that will return all of `context`'s descendants
-    ///
-    /// # Arguments
-    ///
-    /// * `context` - context to get descendants for
+    /// Serializes the instance so it can be persisted across a warm reset.
     ///
-    /// Returns a u32 representing a bitmap of the node indices.
-    pub(crate) fn get_descendants(&self, context: &Context) -> Result<u32, DpeErrorCode> {
-        if context.state == ContextState::Inactive {
-            return Err(DpeErrorCode::InvalidHandle);
-        }
-
-        let mut descendants = context.children;
-        for idx in flags_iter(context.children, MAX_HANDLES) {
-            if idx >= self.contexts.len() {
-                return Err(DpeErrorCode::InternalError);
-            }
-            descendants |= cfi_launder(self.get_descendants(&self.contexts[idx])?);
-        }
-        Ok(descendants)
-    }
-
-    /// Generates a random context handle that is unique from all other context handles
+    /// The output is a `PersistedStateHdr` followed by the instance state,
+    /// encrypted and authenticated under the platform's state protection
+    /// key since it holds secrets such as the handle MAC key and session
+    /// keys. Returns the number of bytes written to `out`.
     ///
     /// # Arguments
     ///
     /// * `env` - DPE environment containing Crypto and Platform implementations
-    pub(crate) fn generate_new_handle(
+    /// * `out` - Buffer of at least `DpeInstance::SERIALIZED_SIZE` bytes
+    pub fn serialize(
         &self,
         env: &mut DpeEnv<impl DpeTypes>,
-    ) -> Result<ContextHandle, DpeErrorCode> {
-        for _ in 0..Self::MAX_NEW_HANDLE_ATTEMPTS {
-            let mut handle = ContextHandle::default();
-            env.crypto.rand_bytes(&mut handle.0)?;
-            if !handle.is_default()
-                && !self
-                    .contexts
-                    .iter()
-                    .any(|c| constant_time_eq(&c.handle.0, &handle.0))
-            {
-                return Ok(handle);
+        out: &mut [u8],
+    ) -> Result<usize, DpeErrorCode> {
+        let out = out
+            .get_mut(..Self::SERIALIZED_SIZE)
+            .ok_or(DpeErrorCode::InvalidArgument)?;
+        let (hdr_bytes, state_bytes) = out.split_at_mut(size_of::<PersistedStateHdr>());
+
+        let mut key = AeadKey([0; AEAD_KEY_SIZE]);
+        env.platform.get_state_protection_key(&mut key.0)?;
+        let mut hdr = PersistedStateHdr {
+            magic: PersistedStateHdr::MAGIC,
+            version: PersistedStateHdr::VERSION,
+            profile: DPE_PROFILE as u32,
+            max_handles: MAX_HANDLES as u32,
+            state_size: size_of::<DpeInstance>() as u32,
+            iv: [0; AEAD_IV_SIZE],
+            tag: [0; AEAD_TAG_SIZE],
+        };
+        env.crypto.rand_bytes(&mut hdr.iv)?;
+
+        state_bytes.copy_from_slice(self.as_bytes());
+        match env
+            .crypto
+            .aead_encrypt(&key, &hdr.iv, hdr.aad().as_bytes(), state_bytes)
+        {
+            Ok(tag) => hdr.tag = tag,
+            Err(e) => {
+                state_bytes.zeroize();
+                return Err(e.into());
             }
         }
-        Err(DpeErrorCode::InternalError)
+        hdr_bytes.copy_from_slice(hdr.as_bytes());
+        Ok(Self::SERIALIZED_SIZE)
     }
 
-    /// Rolls the context handle if the context is not the default context.
+    /// Restores an instance previously written by `DpeInstance::serialize`.
+    ///
+    /// The header must match this build of the DPE, the state must decrypt
+    /// and authenticate under the platform's state protection key and the
+    /// restored state must pass validation.
     ///
     /// # Arguments
     ///
     /// * `env` - DPE environment containing Crypto and Platform implementations
-    /// * `idx` - the index of the context
-    pub fn roll_onetime_use_handle(
-        &mut self,
+    /// * `bytes` - Serialized instance
+    pub fn deserialize(
         env: &mut DpeEnv<impl DpeTypes>,
-        idx: usize,
-    ) -> Result<(), DpeErrorCode> {
-        if idx >= MAX_HANDLES {
-            return Err(DpeErrorCode::MaxTcis);
+        bytes: &[u8],
+    ) -> Result<DpeInstance, DpeErrorCode> {
+        let hdr =
+            PersistedStateHdr::read_from_prefix(bytes).ok_or(DpeErrorCode::InvalidArgument)?;
+        if hdr.magic != PersistedStateHdr::MAGIC
+            || hdr.version != PersistedStateHdr::VERSION
+            || hdr.profile != DPE_PROFILE as u32
+            || hdr.max_handles != MAX_HANDLES as u32
+            || hdr.state_size as usize != size_of::<DpeInstance>()
+        {
+            return Err(DpeErrorCode::InvalidArgument);
         }
-        if !self.contexts[idx].handle.is_default() {
-            self.contexts[idx].handle = self.generate_new_handle(env)?;
-        } else {
-            #[cfg(not(feature = "no-cfi"))]
-            cfi_assert!(self.contexts[idx].handle.is_default());
+
+        let state = bytes
+            .get(size_of::<PersistedStateHdr>()..Self::SERIALIZED_SIZE)
+            .ok_or(DpeErrorCode::InvalidArgument)?;
+        let mut key = AeadKey([0; AEAD_KEY_SIZE]);
+        env.platform.get_state_protection_key(&mut key.0)?;
+
+        let mut dpe = DpeInstance::read_from(state).ok_or(DpeErrorCode::InvalidArgument)?;
+        let decrypted = env.crypto.aead_decrypt(
+            &key,
+            &hdr.iv,
+            hdr.aad().as_bytes(),
+            dpe.as_bytes_mut(),
+            &hdr.tag,
+        );
+        if decrypted.is_err() {
+            dpe.zeroize();
+            return Err(DpeErrorCode::InvalidArgument);
         }
-        Ok(())
+        if let Err(e) = (DpeValidator { dpe: &mut dpe }).validate_dpe() {
+            dpe.zeroize();
+            return Err(e);
+        }
+        // A signature in flight and the cached CDIs are lost with the crypto
+        // implementation's state, so don't wait on or look for them.
+        dpe.pending_op = PendingOp::new();
+        for context in dpe.contexts.iter_mut() {
+            context.cdi_cached = false.into();
+        }
+        Ok(dpe)
     }
 
-    /// Get the TCI nodes from the context at `start_idx` to the root node following parent
-    /// links. These are the nodes that should contribute to CDI and key
-    /// derivation for the context at `start_idx`.
+    pub fn get_profile(
+        &self,
+        platform: &mut impl Platform,
+    ) -> Result<GetProfileResp, DpeErrorCode> {
+        let vendor_id = platform.get_vendor_id()?;
+        let vendor_sku = platform.get_vendor_sku()?;
+        Ok(GetProfileResp::new(
+            self.support,
+            vendor_id,
+            vendor_sku,
+            self.max_simulation_contexts(),
+            self.max_cert_chain_depth(),
+        ))
+    }
+
+    /// Issues an X.509 certificate for the default context in `locality` and
+    /// checks that it parses and certifies the key CertifyKey returned.
     ///
-    /// # Arguments
+    /// This exercises key derivation and certificate encoding without a
+    /// full X.509 library, so it can run on the target. The signature is not
+    /// verified since `Crypto` cannot verify signatures. The certificate
+    /// replaces any certificate still being read with GetCertificateChunk.
     ///
-    /// * `start_idx` - Index into context array
-    /// * `nodes` - Array to write TCI nodes to
+    /// # Arguments
     ///
-    /// Returns the number of TCIs written to `nodes`
+    /// * `env` - DPE environment containing Crypto and Platform implementations
+    /// * `locality` - locality of the default context to certify
     #[cfg_attr(not(feature = "no-cfi"), cfi_impl_fn)]
-    pub(crate) fn get_tcb_nodes(
-        &self,
-        start_idx: usize,
-        nodes: &mut [TciNodeData],
-    ) -> Result<usize, DpeErrorCode> {
-        let mut out_idx = 0;
-
-        for status in ChildToRootIter::new(start_idx, &self.contexts) {
-            let curr = status?;
-            if out_idx >= nodes.len() {
-                return Err(DpeErrorCode::InternalError);
-            }
+    pub fn self_test(
+        &mut self,
+        env: &mut DpeEnv<impl DpeTypes>,
+        locality: u32,
+    ) -> Result<(), DpeErrorCode> {
+        let cmd = CertifyKeyCmd {
+            handle: ContextHandle::default(),
+            flags: CertifyKeyFlags::empty(),
+            format: CertifyKeyCmd::FORMAT_X509,
+            label: [0; DPE_PROFILE.get_hash_size()],
+        };
+        let Response::CertifyKey(resp) = cmd.execute(self, env, locality)? else {
+            return Err(DpeErrorCode::InternalError);
+        };
 
-            nodes[out_idx] = curr.tci;
-            out_idx += 1;
-        }
+        let cert_bytes = self
+            .pending_cert
+            .cert
+            .get(..resp.total_size as usize)
+            .ok_or(DpeErrorCode::InternalError)?;
+        let cert = EcdsaCertificate::parse(cert_bytes)?;
+
+        // The DeviceID certificate is self-signed, so its subject is the issuer.
+        let mut issuer_name = [0u8; MAX_ISSUER_NAME_SIZE];
+        let device_id_cert = self.device_id_cert.get();
+        let issuer_name = if device_id_cert.is_empty() {
+            let issuer_len = env.platform.get_issuer_name(&mut issuer_name)?;
+            issuer_name
+                .get(..issuer_len)
+                .ok_or(DpeErrorCode::InternalError)?
+        } else {
+            EcdsaCertificate::parse(device_id_cert)?.subject
+        };
 
-        if out_idx > nodes.len() {
+        let public_key_matches = match cert.subject_public_key {
+            [0x04, point @ ..] => {
+                let (x, y) = point.split_at(point.len() / 2);
+                x == resp.derived_pubkey_x && y == resp.derived_pubkey_y
+            }
+            _ => false,
+        };
+        let ecc_int_size = DPE_PROFILE.get_ecc_int_size();
+        if !public_key_matches
+            || cert.issuer != issuer_name
+            || cert.signature_r.len() > ecc_int_size
+            || cert.signature_s.len() > ecc_int_size
+        {
             return Err(DpeErrorCode::InternalError);
         }
-        nodes[..out_idx].reverse();
-
-        Ok(out_idx)
+        Ok(())
     }
 
-    /// Adds `measurement` to `context`. The current TCI is the measurement and
-    /// the cumulative TCI is the hash of the old cumulative TCI and the measurement.
+    /// Deserializes the command and executes it.
+    ///
+    /// Commands wrapped in an encrypted session message are decrypted first and
+    /// their response is encrypted with the same session. Commands wrapped in
+    /// an authenticated command message have their HMAC checked first. The
+    /// response is returned in wire byte order, ready to be sent with
+    /// `Response::as_bytes`.
+    ///
+    /// With `Support::COMMAND_AUTH`, every command other than GetProfile must
+    /// be sent in one of those two wrappers and fails with
+    /// `CommandNotAllowed` otherwise.
     ///
     /// # Arguments
     ///
     /// * `env` - DPE environment containing Crypto and Platform implementations
-    /// * `context` - context to add `measurement`` to
-    /// * `measurement` - measurement to add to `context``
-    /// * `locality` - locality that `context`'s locality must match
-    #[cfg_attr(not(feature = "no-cfi"), cfi_impl_fn)]
-    pub(crate) fn add_tci_measurement(
+    /// * `locality` - which hardware locality is making the request
+    /// * `cmd` - serialized command
+    pub fn execute_serialized_command(
+        &mut self,
+        env: &mut DpeEnv<impl DpeTypes>,
+        locality: u32,
+        cmd: &[u8],
+    ) -> Result<Response, DpeErrorCode> {
+        self.execute_serialized_command_with_vendor(env, locality, cmd, &mut NoVendorCommands)
+    }
+
+    /// Deserializes the command and executes it like
+    /// `execute_serialized_command`, handing commands in the vendor range to
+    /// `vendor`.
+    ///
+    /// # Arguments
+    ///
+    /// * `env` - DPE environment containing Crypto and Platform implementations
+    /// * `locality` - which hardware locality is making the request
+    /// * `cmd` - serialized command
+    /// * `vendor` - executes commands from `Command::VENDOR_COMMAND_BASE` up
+    #[cfg_attr(not(feature = "no-cfi"), cfi_impl_fn)]
+    pub fn execute_serialized_command_with_vendor(
+        &mut self,
+        env: &mut DpeEnv<impl DpeTypes>,
+        locality: u32,
+        cmd: &[u8],
+        vendor: &mut impl VendorCommandHandler,
+    ) -> Result<Response, DpeErrorCode> {
+        self.execute_serialized(env, locality, cmd, false, vendor)
+    }
+
+    /// Starts executing a serialized command like
+    /// `execute_serialized_command`, without waiting for crypto
+    /// implementations that sign in the background.
+    ///
+    /// Returns `CommandStatus::Pending` if the crypto implementation left an
+    /// ECDSA signature for a plaintext Sign command pending. Its response then
+    /// comes from `poll_command`, called from the same locality, and every
+    /// other command fails with `DpeErrorCode::Busy` until it does, until
+    /// `cancel_command` abandons it, or until it has turned away
+    /// `MAX_TURNED_AWAY` commands and expires. Other commands, and commands
+    /// in session or authenticated command messages, always complete.
+    ///
+    /// # Arguments
+    ///
+    /// * `env` - DPE environment containing Crypto and Platform implementations
+    /// * `locality` - which hardware locality is making the request
+    /// * `cmd` - serialized command
+    pub fn start_command(
+        &mut self,
+        env: &mut DpeEnv<impl DpeTypes>,
+        locality: u32,
+        cmd: &[u8],
+    ) -> Result<CommandStatus, DpeErrorCode> {
+        // An expired command may be replaced by this one.
+        let was_pending = self.pending_op.active().then(|| self.pending_op.token());
+        let resp = self.execute_serialized(env, locality, cmd, true, &mut NoVendorCommands)?;
+        if self.pending_op.active() && Some(self.pending_op.token()) != was_pending {
+            return Ok(CommandStatus::Pending(self.pending_op.token()));
+        }
+        Ok(CommandStatus::Complete(resp))
+    }
+
+    /// Checks on the command `start_command` left pending, returning its
+    /// response once the crypto implementation has finished.
+    ///
+    /// Fails with `InvalidArgument` if `token` is not the pending command's,
+    /// and with `InvalidLocality` if `locality` did not start it.
+    ///
+    /// # Arguments
+    ///
+    /// * `env` - DPE environment containing Crypto and Platform implementations
+    /// * `locality` - which hardware locality is making the request
+    /// * `token` - token `start_command` returned for the command
+    pub fn poll_command(
+        &mut self,
+        env: &mut DpeEnv<impl DpeTypes>,
+        locality: u32,
+        token: PendingToken,
+    ) -> Result<CommandStatus, DpeErrorCode> {
+        self.pending_op.check_owner(token, locality)?;
+
+        let idx = self.pending_op.context_idx as usize;
+        let resp = match SignCmd::poll(self, env, idx) {
+            Ok(None) => return Ok(CommandStatus::Pending(token)),
+            Ok(Some(resp)) => {
+                if self.support.command_counter() {
+                    self.count_command(idx);
+                }
+                resp
+            }
+            Err(err_code) => Response::Error(ResponseHdr::new(err_code)),
+        };
+        self.pending_op.active = false.into();
+        Ok(CommandStatus::Complete(resp.to_le()))
+    }
+
+    /// Abandons the command `start_command` left pending, so that other
+    /// commands run again. The context it was signing for is left as it was.
+    ///
+    /// Fails like `poll_command` if `locality` may not poll the command.
+    ///
+    /// # Arguments
+    ///
+    /// * `env` - DPE environment containing Crypto and Platform implementations
+    /// * `locality` - which hardware locality is making the request
+    /// * `token` - token `start_command` returned for the command
+    pub fn cancel_command(
+        &mut self,
+        env: &mut DpeEnv<impl DpeTypes>,
+        locality: u32,
+        token: PendingToken,
+    ) -> Result<(), DpeErrorCode> {
+        self.pending_op.check_owner(token, locality)?;
+        self.abandon_pending_op(env);
+        Ok(())
+    }
+
+    /// Drops the pending command along with the signature the crypto
+    /// implementation is working on.
+    fn abandon_pending_op(&mut self, env: &mut DpeEnv<impl DpeTypes>) {
+        env.crypto.ecdsa_sign_cancel();
+        self.pending_op.active = false.into();
+    }
+
+    /// Executes a serialized command for `execute_serialized_command_with_vendor`
+    /// and `start_command`.
+    ///
+    /// # Arguments
+    ///
+    /// * `env` - DPE environment containing Crypto and Platform implementations
+    /// * `locality` - which hardware locality is making the request
+    /// * `cmd` - serialized command
+    /// * `defer` - whether a plaintext command may be left pending
+    /// * `vendor` - executes commands from `Command::VENDOR_COMMAND_BASE` up
+    #[cfg_attr(not(feature = "no-cfi"), cfi_impl_fn)]
+    fn execute_serialized(
+        &mut self,
+        env: &mut DpeEnv<impl DpeTypes>,
+        locality: u32,
+        cmd: &[u8],
+        defer: bool,
+        vendor: &mut impl VendorCommandHandler,
+    ) -> Result<Response, DpeErrorCode> {
+        // Turn away localities the platform doesn't know before looking at
+        // the message, whatever its kind.
+        if let Err(err_code) = self.localities.check_known(locality) {
+            dpe_debug!("unknown locality {:#x}", locality);
+            return Ok(Response::Error(ResponseHdr::new(err_code)).to_le());
+        }
+        // Nothing may change the context a pending signature is for, until
+        // the signature has been waited on for too long.
+        if self.pending_op.active() {
+            if !self.pending_op.turn_away() {
+                return Ok(Response::Error(ResponseHdr::new(DpeErrorCode::Busy)).to_le());
+            }
+            dpe_debug!("pending command expired");
+            self.abandon_pending_op(env);
+        }
+        if SessionMsgHdr::is_session_cmd(cmd) {
+            return match self.execute_session_command(env, locality, cmd, vendor) {
+                Ok(resp) => Ok(resp.to_le()),
+                Err(err_code) => Ok(Response::Error(ResponseHdr::new(err_code)).to_le()),
+            };
+        }
+        if AuthCmdHdr::is_auth_cmd(cmd) {
+            return match self.execute_auth_command(env, locality, cmd, vendor) {
+                Ok(resp) => Ok(resp.to_le()),
+                Err(err_code) => Ok(Response::Error(ResponseHdr::new(err_code)).to_le()),
+            };
+        }
+        self.execute_command(env, locality, cmd, false, defer, vendor)
+            .map(Response::to_le)
+    }
+
+    /// Checks the HMAC and counter of an authenticated command and executes
+    /// it.
+    ///
+    /// # Arguments
+    ///
+    /// * `env` - DPE environment containing Crypto and Platform implementations
+    /// * `locality` - which hardware locality is making the request
+    /// * `msg` - authenticated command message
+    /// * `vendor` - executes commands in the vendor range
+    fn execute_auth_command(
+        &mut self,
+        env: &mut DpeEnv<impl DpeTypes>,
+        locality: u32,
+        msg: &[u8],
+        vendor: &mut impl VendorCommandHandler,
+    ) -> Result<Response, DpeErrorCode> {
+        if !self.support.command_auth() {
+            return Err(DpeErrorCode::InvalidCommand);
+        } else {
+            #[cfg(not(feature = "no-cfi"))]
+            cfi_assert!(self.support.command_auth());
+        }
+
+        let (hdr, _) = read_prefix::<AuthCmdHdr>(msg).ok_or(DpeErrorCode::InvalidCommand)?;
+        let mac_len = msg
+            .len()
+            .checked_sub(COMMAND_AUTH_TAG_SIZE)
+            .filter(|len| *len >= size_of::<AuthCmdHdr>())
+            .ok_or(DpeErrorCode::InvalidCommand)?;
+        let (authenticated, tag) = msg.split_at(mac_len);
+        let cmd = &authenticated[size_of::<AuthCmdHdr>()..];
+
+        let mut key = [0u8; COMMAND_AUTH_KEY_SIZE];
+        let mac = match env.platform.get_command_auth_key(locality, &mut key) {
+            Ok(()) => env
+                .crypto
+                .hmac(DPE_PROFILE.alg_len(), &key, authenticated)
+                .map_err(DpeErrorCode::from),
+            Err(e) => Err(e.into()),
+        };
+        key.zeroize();
+        if !constant_time_eq(mac?.bytes(), tag) {
+            return Err(DpeErrorCode::AuthenticationFailed);
+        }
+
+        // Each counter value is only accepted once so captured commands can't
+        // be replayed. The last value is never accepted so the counter can't
+        // wrap around.
+        let idx = self.auth_counter_pos(locality)?;
+        let auth = &mut self.auth_counters[idx];
+        let expected = if auth.active() { auth.counter } else { 0 };
+        if hdr.counter != expected || hdr.counter == u32::MAX {
+            return Err(DpeErrorCode::AuthenticationFailed);
+        }
+        auth.locality = locality;
+        auth.counter = expected + 1;
+        auth.active = true.into();
+
+        self.execute_command(env, locality, cmd, true, false, vendor)
+    }
+
+    /// Returns the index of the replay state of `locality`, or of a free slot
+    /// for it. Fails with `InvalidLocality` if every slot is taken by other
+    /// localities.
+    fn auth_counter_pos(&self, locality: u32) -> Result<usize, DpeErrorCode> {
+        self.auth_counters
+            .iter()
+            .position(|auth| auth.active() && auth.locality == locality)
+            .or_else(|| self.auth_counters.iter().position(|auth| !auth.active()))
+            .ok_or(DpeErrorCode::InvalidLocality)
+    }
+
+    /// Decrypts a command sent over an encrypted session, executes it and
+    /// encrypts the response.
+    ///
+    /// # Arguments
+    ///
+    /// * `env` - DPE environment containing Crypto and Platform implementations
+    /// * `locality` - which hardware locality is making the request
+    /// * `msg` - session message holding the encrypted command
+    /// * `vendor` - executes commands in the vendor range
+    fn execute_session_command(
+        &mut self,
+        env: &mut DpeEnv<impl DpeTypes>,
+        locality: u32,
+        msg: &[u8],
+        vendor: &mut impl VendorCommandHandler,
+    ) -> Result<Response, DpeErrorCode> {
+        if !self.support.sessions() {
+            return Err(DpeErrorCode::InvalidCommand);
+        } else {
+            #[cfg(not(feature = "no-cfi"))]
+            cfi_assert!(self.support.sessions());
+        }
+
+        let (hdr, body) = read_prefix::<SessionMsgHdr>(msg).ok_or(DpeErrorCode::InvalidCommand)?;
+        let cmd_len = body
+            .len()
+            .checked_sub(AEAD_TAG_SIZE)
+            .ok_or(DpeErrorCode::InvalidCommand)?;
+        if cmd_len > MAX_SESSION_CMD_SIZE {
+            return Err(DpeErrorCode::InvalidCommand);
+        }
+        let (ciphertext, tag) = body.split_at(cmd_len);
+        let tag: &[u8; AEAD_TAG_SIZE] = tag.try_into().map_err(|_| DpeErrorCode::InternalError)?;
+
+        let session_idx = hdr.session_id as usize;
+        let session = self
+            .sessions
+            .get(session_idx)
+            .filter(|s| s.active())
+            .ok_or(DpeErrorCode::InvalidSession)?;
+        if session.locality != locality {
+            return Err(DpeErrorCode::InvalidLocality);
+        }
+        // Each counter value is only accepted once so captured messages can't be replayed.
+        if session.counter != hdr.counter {
+            return Err(DpeErrorCode::InvalidSession);
+        }
+        // Copy the key since the command may close the session.
+        let key = AeadKey(session.key);
+
+        let mut cmd_buf = [0u8; MAX_SESSION_CMD_SIZE];
+        let cmd = cmd_buf
+            .get_mut(..cmd_len)
+            .ok_or(DpeErrorCode::InternalError)?;
+        cmd.copy_from_slice(ciphertext);
+        env.crypto
+            .aead_decrypt(&key, &hdr.iv(), hdr.to_le().as_bytes(), cmd, tag)?;
+
+        // The message is authentic, so consume its counter before running the command.
+        match hdr.counter.checked_add(1) {
+            Some(counter) => self.sessions[session_idx].counter = counter,
+            None => self.sessions[session_idx].zeroize(),
+        }
+
+        let inner = self
+            .execute_command(env, locality, cmd, true, false, vendor)?
+            .to_le();
+        let inner_bytes = inner.as_bytes();
+
+        let mut resp = SessionResp {
+            hdr: SessionMsgHdr {
+                magic: SessionMsgHdr::SESSION_RESP_MAGIC,
+                ..hdr
+            },
+            payload: [0; SessionResp::PAYLOAD_SIZE],
+            payload_size: 0,
+        };
+        // Leave room for the tag after the response.
+        let mut out = ResponseWriter::new(&mut resp.payload);
+        out.write(inner_bytes)?;
+        out.write(&[0; AEAD_TAG_SIZE])?;
+        let (data, tag_out) = out.into_written().split_at_mut(inner_bytes.len());
+        // The header is authenticated as it is sent.
+        let aad = resp.hdr.to_le();
+        let tag = env
+            .crypto
+            .aead_encrypt(&key, &resp.hdr.iv(), aad.as_bytes(), data)?;
+        tag_out.copy_from_slice(&tag);
+        resp.payload_size = (inner_bytes.len() + AEAD_TAG_SIZE) as u32;
+
+        Ok(Response::Session(resp))
+    }
+
+    /// Deserializes a plaintext command and executes it.
+    ///
+    /// # Arguments
+    ///
+    /// * `env` - DPE environment containing Crypto and Platform implementations
+    /// * `locality` - which hardware locality is making the request
+    /// * `cmd` - serialized command
+    /// * `authenticated` - whether the command came in a session or authenticated command message
+    /// * `defer` - whether a Sign command may be left pending
+    /// * `vendor` - executes commands in the vendor range
+    #[cfg_attr(not(feature = "no-cfi"), cfi_impl_fn)]
+    fn execute_command(
+        &mut self,
+        env: &mut DpeEnv<impl DpeTypes>,
+        locality: u32,
+        cmd: &[u8],
+        authenticated: bool,
+        defer: bool,
+        vendor: &mut impl VendorCommandHandler,
+    ) -> Result<Response, DpeErrorCode> {
+        let command = Command::deserialize(cmd)?;
+        let cmd_id = u32::from(&command);
+        dpe_debug!("command {:#x} locality={:#x}", cmd_id, locality);
+
+        // Only GetProfile may be sent in the clear when commands must be
+        // authenticated, so clients can find out that they must be.
+        if self.support.command_auth() && !authenticated && cmd_id != Command::GET_PROFILE {
+            dpe_debug!("command {:#x} not authenticated", cmd_id);
+            return Ok(Response::Error(ResponseHdr::new(
+                DpeErrorCode::CommandNotAllowed,
+            )));
+        }
+
+        // Let the integrator veto the command before it touches any state.
+        if !env.platform.authorize(cmd_id, locality) {
+            dpe_debug!("command {:#x} not allowed", cmd_id);
+            return Ok(Response::Error(ResponseHdr::new(
+                DpeErrorCode::CommandNotAllowed,
+            )));
+        }
+
+        // Reject forged handles before they are compared with any context.
+        if let Some(handle) = command.handle() {
+            if let Err(err_code) = self.verify_handle_mac(env, handle, locality) {
+                dpe_debug!("command {:#x} handle MAC mismatch", cmd_id);
+                return Ok(Response::Error(ResponseHdr::new(err_code)));
+            }
+        }
+
+        // Look up the context before the command runs, since the command may
+        // rotate its handle. Handles are bearer secrets, so only the index of
+        // the context is logged.
+        let idx = command
+            .handle()
+            .and_then(|handle| self.get_active_context_pos(handle, locality).ok());
+        if let Some(idx) = idx {
+            dpe_debug!("command {:#x} context={}", cmd_id, idx);
+        }
+        let counted_idx = if self.support.command_counter() {
+            idx
+        } else {
+            None
+        };
+
+        let resp = match cfi_launder(command) {
+            Command::GetProfile => Ok(Response::GetProfile(self.get_profile(&mut env.platform)?)),
+            Command::InitCtx(cmd, seed) => {
+                cmd.execute_with_seed(self, env, locality, seed.as_ref())
+            }
+            Command::DeriveContext(cmd, svn) => cmd.execute_with_svn(self, env, locality, *svn),
+            Command::CertifyKey(cmd) => cmd.execute(self, env, locality),
+            Command::Sign(cmd, message) if defer => {
+                cmd.start_with_message(self, env, locality, message)
+            }
+            Command::Sign(cmd, message) => cmd.execute_with_message(self, env, locality, message),
+            Command::RotateCtx(cmd) => cmd.execute(self, env, locality),
+            Command::DestroyCtx(cmd) => cmd.execute(self, env, locality),
+            Command::GetCertificateChain(cmd) => cmd.execute(self, env, locality),
+            Command::ExtendTci(cmd) => cmd.execute(self, env, locality),
+            Command::TagTci(cmd) => cmd.execute(self, env, locality),
+            Command::GetTaggedTci(cmd) => cmd.execute(self, env, locality),
+            Command::OpenSession(cmd) => cmd.execute(self, env, locality),
+            Command::CloseSession(cmd) => cmd.execute(self, env, locality),
+            Command::GetCertificateChunk(cmd) => cmd.execute(self, env, locality),
+            Command::ExportCdi(cmd) => cmd.execute(self, env, locality),
+            Command::GetCommandCounter(cmd) => cmd.execute(self, env, locality),
+            Command::HashInitialize(cmd) => cmd.execute(self, env, locality),
+            Command::HashUpdate(cmd, data) => cmd.execute_with_data(self, env, locality, data),
+            Command::HashFinal(cmd) => cmd.execute(self, env, locality),
+            Command::GetContextInfo(cmd) => cmd.execute(self, env, locality),
+            Command::Attest(cmd) => cmd.execute(self, env, locality),
+            Command::Seal(cmd, data) => cmd.execute_with_data(self, env, locality, data),
+            Command::Unseal(cmd, sealed) => cmd.execute_with_data(self, env, locality, sealed),
+            Command::Vendor(id, body) => {
+                self.execute_vendor_command(env, locality, id, body, vendor)
+            }
+        };
+
+        match resp {
+            Ok(resp) => {
+                if let Some(idx) = counted_idx {
+                    self.count_command(idx);
+                }
+                Ok(resp)
+            }
+            Err(err_code) => {
+                dpe_debug!(
+                    "command {:#x} failed: {:#x}",
+                    cmd_id,
+                    err_code.get_error_code()
+                );
+                Ok(Response::Error(ResponseHdr::new(err_code)))
+            }
+        }
+    }
+
+    /// Hands a command in the vendor range to `vendor` and wraps the payload
+    /// it writes in a response.
+    fn execute_vendor_command(
+        &mut self,
+        env: &mut DpeEnv<impl DpeTypes>,
+        locality: u32,
+        cmd_id: u32,
+        cmd: &[u8],
+        vendor: &mut impl VendorCommandHandler,
+    ) -> Result<Response, DpeErrorCode> {
+        let mut resp = VendorResp {
+            resp_hdr: ResponseHdr::new(DpeErrorCode::NoError),
+            payload: [0; VendorResp::PAYLOAD_SIZE],
+            payload_size: 0,
+        };
+        let len = vendor.execute(self, env, locality, cmd_id, cmd, &mut resp.payload)?;
+        if len > VendorResp::PAYLOAD_SIZE {
+            return Err(DpeErrorCode::InternalError);
+        }
+        resp.payload_size = len as u32;
+        Ok(Response::Vendor(resp))
+    }
+
+    /// Counts a successful command on the context at `idx`. Contexts that the
+    /// command destroyed or retired are left alone. The counter saturates
+    /// rather than wrapping so that it never repeats a value.
+    fn count_command(&mut self, idx: usize) {
+        if let Some(context) = self.contexts.get_mut(idx) {
+            if context.state == ContextState::Active {
+                context.command_counter = context.command_counter.saturating_add(1);
+            }
+        }
+    }
+
+    /// Finds the index of the context having `handle` in `locality`
+    /// Inlined so the callsite optimizer knows that idx < self.contexts.len()
+    /// and won't insert possible call to panic.
+    ///
+    /// # Arguments
+    ///
+    /// * `handle` - handle to search
+    /// * `locality` - locality to search
+    #[inline(always)]
+    pub fn get_active_context_pos(
+        &self,
+        handle: &ContextHandle,
+        locality: u32,
+    ) -> Result<usize, DpeErrorCode> {
+        let idx = self.get_active_context_pos_internal(handle, locality)?;
+        if idx >= self.contexts.len() {
+            return Err(DpeErrorCode::InternalError);
+        }
+        Ok(idx)
+    }
+
+    fn get_active_context_pos_internal(
+        &self,
+        handle: &ContextHandle,
+        locality: u32,
+    ) -> Result<usize, DpeErrorCode> {
+        // find all active contexts whose localities match the locality parameter
+        let mut valid_localities = self
+            .contexts
+            .iter()
+            .enumerate()
+            .filter(|(_, context)| {
+                context.state == ContextState::Active && context.locality == locality
+            })
+            .peekable();
+        if valid_localities.peek().is_none() {
+            self.check_not_retired(handle, locality)?;
+            return Err(DpeErrorCode::InvalidLocality);
+        }
+
+        // filter down the contexts with valid localities based on their context handle matching the input context handle
+        // the locality and handle filters are separated so that we can return InvalidHandle or InvalidLocality upon getting no valid contexts accordingly
+        let mut valid_handles_and_localities = valid_localities
+            .filter(|(_, context)| constant_time_eq(&context.handle.0, &handle.0))
+            .peekable();
+        if valid_handles_and_localities.peek().is_none() {
+            self.check_not_retired(handle, locality)?;
+            return Err(DpeErrorCode::InvalidHandle);
+        }
+        let (i, _) = valid_handles_and_localities
+            .find(|(_, context)| {
+                context.state == ContextState::Active
+                    && constant_time_eq(&context.handle.0, &handle.0)
+                    && context.locality == locality
+            })
+            .ok_or(DpeErrorCode::InternalError)?;
+        Ok(i)
+    }
+
+    /// Finds the index of the retired context having `handle` in `locality`.
+    ///
+    /// A retired parent whose child took over its handle can't be found.
+    pub(crate) fn get_retired_context_pos(
+        &self,
+        handle: &ContextHandle,
+        locality: u32,
+    ) -> Result<usize, DpeErrorCode> {
+        if constant_time_eq(&handle.0, &ContextHandle::INVALID.0) {
+            return Err(DpeErrorCode::InvalidHandle);
+        }
+        self.contexts
+            .iter()
+            .position(|context| {
+                context.state == ContextState::Retired
+                    && constant_time_eq(&context.handle.0, &handle.0)
+                    && context.locality == locality
+            })
+            .ok_or(DpeErrorCode::InvalidHandle)
+    }
+
+    /// Returns `DpeErrorCode::RetiredContext` if `handle` belongs to a retired
+    /// context in `locality`.
+    fn check_not_retired(&self, handle: &ContextHandle, locality: u32) -> Result<(), DpeErrorCode> {
+        if self.get_retired_context_pos(handle, locality).is_ok() {
+            return Err(DpeErrorCode::RetiredContext);
+        }
+        Ok(())
+    }
+
+    pub(crate) fn get_next_inactive_context_pos(&self) -> Option<usize> {
+        self.contexts
+            .iter()
+            .position(|context| context.state == ContextState::Inactive)
+    }
+
+    /// Recursive function that will return all of `context`'s descendants
+    ///
+    /// # Arguments
+    ///
+    /// * `context` - context to get descendants for
+    ///
+    /// Returns a u32 representing a bitmap of the node indices.
+    pub(crate) fn get_descendants(&self, context: &Context) -> Result<u64, DpeErrorCode> {
+        if context.state == ContextState::Inactive {
+            return Err(DpeErrorCode::InvalidHandle);
+        }
+
+        let mut descendants = context.children;
+        for child in ChildIter::new(context, &self.contexts) {
+            let (_, child) = child?;
+            descendants |= cfi_launder(self.get_descendants(child)?);
+        }
+        Ok(descendants)
+    }
+
+    /// Generates a random context handle that is unique from all other context handles
+    ///
+    /// With `Support::HANDLE_MAC`, only the start of the handle is random and
+    /// the rest is a MAC binding it to `locality`.
+    ///
+    /// # Arguments
+    ///
+    /// * `env` - DPE environment containing Crypto and Platform implementations
+    /// * `locality` - the locality the handle is issued to
+    pub(crate) fn generate_new_handle(
+        &self,
+        env: &mut DpeEnv<impl DpeTypes>,
+        locality: u32,
+    ) -> Result<ContextHandle, DpeErrorCode> {
+        for _ in 0..Self::MAX_NEW_HANDLE_ATTEMPTS {
+            let mut handle = ContextHandle::default();
+            if self.support.handle_mac() {
+                let (nonce, mac) = handle.0.split_at_mut(ContextHandle::NONCE_SIZE);
+                env.crypto.rand_bytes(nonce)?;
+                mac.copy_from_slice(&self.compute_handle_mac(env, nonce, locality)?);
+            } else {
+                env.crypto.rand_bytes(&mut handle.0)?;
+            }
+            if !handle.is_default()
+                && !self
+                    .contexts
+                    .iter()
+                    .any(|c| constant_time_eq(&c.handle.0, &handle.0))
+            {
+                return Ok(handle);
+            }
+        }
+        Err(DpeErrorCode::InternalError)
+    }
+
+    /// MACs the random part of a context handle together with the locality
+    /// it is issued to.
+    fn compute_handle_mac(
+        &self,
+        env: &mut DpeEnv<impl DpeTypes>,
+        nonce: &[u8],
+        locality: u32,
+    ) -> Result<[u8; ContextHandle::MAC_SIZE], DpeErrorCode> {
+        let mut data = [0u8; ContextHandle::NONCE_SIZE + size_of::<u32>()];
+        let (data_nonce, data_locality) = data.split_at_mut(ContextHandle::NONCE_SIZE);
+        data_nonce.copy_from_slice(nonce);
+        data_locality.copy_from_slice(&locality.to_le_bytes());
+
+        let digest = env
+            .crypto
+            .hmac(DPE_PROFILE.alg_len(), &self.handle_mac_key, &data)?;
+        let mut mac = [0u8; ContextHandle::MAC_SIZE];
+        mac.copy_from_slice(
+            digest
+                .bytes()
+                .get(..ContextHandle::MAC_SIZE)
+                .ok_or(DpeErrorCode::InternalError)?,
+        );
+        Ok(mac)
+    }
+
+    /// Checks that `handle` was issued to `locality` by this instance.
+    ///
+    /// Returns `InvalidHandle` if `Support::HANDLE_MAC` is set and the MAC in
+    /// `handle` does not match. The default handle is never MACed and always
+    /// passes.
+    ///
+    /// # Arguments
+    ///
+    /// * `env` - DPE environment containing Crypto and Platform implementations
+    /// * `handle` - handle supplied by the caller
+    /// * `locality` - which hardware locality is making the request
+    #[cfg_attr(not(feature = "no-cfi"), cfi_impl_fn)]
+    pub fn verify_handle_mac(
+        &self,
+        env: &mut DpeEnv<impl DpeTypes>,
+        handle: &ContextHandle,
+        locality: u32,
+    ) -> Result<(), DpeErrorCode> {
+        if !self.support.handle_mac() || handle.is_default() {
+            return Ok(());
+        }
+        let (nonce, mac) = handle.0.split_at(ContextHandle::NONCE_SIZE);
+        let expected = self.compute_handle_mac(env, nonce, locality)?;
+        let valid = constant_time_eq(&expected, mac);
+        if !valid {
+            return Err(DpeErrorCode::InvalidHandle);
+        } else {
+            #[cfg(not(feature = "no-cfi"))]
+            cfi_assert!(valid);
+        }
+        Ok(())
+    }
+
+    /// Rolls the context handle if the context is not the default context.
+    ///
+    /// # Arguments
+    ///
+    /// * `env` - DPE environment containing Crypto and Platform implementations
+    /// * `idx` - the index of the context
+    pub fn roll_onetime_use_handle(
+        &mut self,
+        env: &mut DpeEnv<impl DpeTypes>,
+        idx: usize,
+    ) -> Result<(), DpeErrorCode> {
+        if idx >= MAX_HANDLES {
+            return Err(DpeErrorCode::MaxTcis);
+        }
+        self.contexts[idx].handle = self.next_onetime_use_handle(env, &self.contexts[idx])?;
+        Ok(())
+    }
+
+    /// Returns the handle `context` gets once it is used: a new handle unless
+    /// it is the default context. Nothing is changed, so commands that update
+    /// a copy of a context can roll its handle before committing the copy.
+    ///
+    /// # Arguments
+    ///
+    /// * `env` - DPE environment containing Crypto and Platform implementations
+    /// * `context` - the context being used
+    pub(crate) fn next_onetime_use_handle(
+        &self,
+        env: &mut DpeEnv<impl DpeTypes>,
+        context: &Context,
+    ) -> Result<ContextHandle, DpeErrorCode> {
+        if !context.handle.is_default() {
+            self.generate_new_handle(env, context.locality)
+        } else {
+            #[cfg(not(feature = "no-cfi"))]
+            cfi_assert!(context.handle.is_default());
+            Ok(context.handle)
+        }
+    }
+
+    /// Get the TCI nodes from the context at `start_idx` to the root node following parent
+    /// links. These are the nodes that should contribute to CDI and key
+    /// derivation for the context at `start_idx`.
+    ///
+    /// # Arguments
+    ///
+    /// * `start_idx` - Index into context array
+    /// * `nodes` - Array to write TCI nodes to
+    ///
+    /// Returns the number of TCIs written to `nodes`
+    #[cfg_attr(not(feature = "no-cfi"), cfi_impl_fn)]
+    pub(crate) fn get_tcb_nodes(
+        &self,
+        start_idx: usize,
+        nodes: &mut [TciNodeData],
+    ) -> Result<usize, DpeErrorCode> {
+        let mut out_idx = 0;
+
+        for status in ChildToRootIter::new(start_idx, &self.contexts) {
+            let curr = status?;
+            if out_idx >= nodes.len() {
+                return Err(DpeErrorCode::InternalError);
+            }
+
+            nodes[out_idx] = curr.tci;
+            out_idx += 1;
+        }
+
+        if out_idx > nodes.len() {
+            return Err(DpeErrorCode::InternalError);
+        }
+        nodes[..out_idx].reverse();
+
+        Ok(out_idx)
+    }
+
+    /// Adds `measurement` to `context`. The current TCI is the measurement and
+    /// the cumulative TCI is the hash of the old cumulative TCI and the measurement.
+    ///
+    /// # Arguments
+    ///
+    /// * `env` - DPE environment containing Crypto and Platform implementations
+    /// * `context` - context to add `measurement`` to
+    /// * `measurement` - measurement to add to `context``
+    /// * `locality` - locality that `context`'s locality must match
+    #[cfg_attr(not(feature = "no-cfi"), cfi_impl_fn)]
+    pub(crate) fn add_tci_measurement(
         &self,
         env: &mut DpeEnv<impl DpeTypes>,
         context: &mut Context,
@@ -385,6 +1375,86 @@ impl DpeInstance {
         }
         context.tci.tci_cumulative.0.copy_from_slice(digest_bytes);
         context.tci.tci_current = *measurement;
+        context.chain_digest = self.compute_chain_digest(env, context)?;
+        Ok(())
+    }
+
+    /// Computes the chain digest of `context` from the stored chain digest of
+    /// its parent, or from its seed if it is a root.
+    ///
+    /// # Arguments
+    ///
+    /// * `env` - DPE environment containing Crypto and Platform implementations
+    /// * `context` - context whose TCI the digest covers
+    pub(crate) fn compute_chain_digest(
+        &self,
+        env: &mut DpeEnv<impl DpeTypes>,
+        context: &Context,
+    ) -> Result<[u8; DPE_PROFILE.get_hash_size()], DpeErrorCode> {
+        let parent_digest = match context.parent_pos() {
+            Some(parent_idx) => {
+                &self
+                    .contexts
+                    .get(parent_idx)
+                    .ok_or(DpeErrorCode::InternalError)?
+                    .chain_digest
+            }
+            None => &context.seed,
+        };
+        hash_chain_link(env, parent_digest, &context.tci)
+    }
+
+    /// Stores `context`, whose TCI changed, at `idx` and updates the chain
+    /// digests of its descendants to follow its new one, which must already
+    /// be in `context.chain_digest`. Nothing is stored if hashing fails.
+    ///
+    /// # Arguments
+    ///
+    /// * `env` - DPE environment containing Crypto and Platform implementations
+    /// * `idx` - index of the context being updated
+    /// * `context` - new value of the context
+    pub(crate) fn commit_tci_update(
+        &mut self,
+        env: &mut DpeEnv<impl DpeTypes>,
+        idx: usize,
+        context: Context,
+    ) -> Result<(), DpeErrorCode> {
+        let mut digests = [[0u8; DPE_PROFILE.get_hash_size()]; MAX_HANDLES];
+        for (digest, current) in digests.iter_mut().zip(self.contexts.iter()) {
+            *digest = current.chain_digest;
+        }
+        *digests.get_mut(idx).ok_or(DpeErrorCode::InternalError)? = context.chain_digest;
+
+        // Visit parents before their children, so that each child is hashed
+        // from the new digest of its parent.
+        let mut pending = [0u8; MAX_HANDLES];
+        pending[0] = idx as u8;
+        let mut pending_len = 1;
+        let mut visited = 0;
+        while pending_len > 0 {
+            pending_len -= 1;
+            let parent_idx = pending[pending_len] as usize;
+            let children = if parent_idx == idx {
+                context.children
+            } else {
+                self.contexts[parent_idx].children
+            };
+            for child_idx in flags_iter(children, MAX_HANDLES) {
+                visited += 1;
+                if visited >= MAX_HANDLES {
+                    return Err(DpeErrorCode::InternalError);
+                }
+                digests[child_idx] =
+                    hash_chain_link(env, &digests[parent_idx], &self.contexts[child_idx].tci)?;
+                pending[pending_len] = child_idx as u8;
+                pending_len += 1;
+            }
+        }
+
+        self.contexts[idx] = context;
+        for (current, digest) in self.contexts.iter_mut().zip(digests) {
+            current.chain_digest = digest;
+        }
         Ok(())
     }
 
@@ -418,7 +1488,11 @@ impl DpeInstance {
 
     /// Compute measurement hash for a child node.
     ///
-    /// Goes up the TciNodeData chain hashing each along the way until it gets to the root node.
+    /// Hashes each TciNodeData from the node up to the root, then the seed of
+    /// the root if it has one, followed by the internal inputs any of those
+    /// nodes use. With the `chain_digest_measurement` feature, the chain
+    /// digest of the node is hashed in place of the TciNodeData and seed,
+    /// which changes every derived CDI.
     ///
     /// # Arguments
     ///
@@ -430,16 +1504,40 @@ impl DpeInstance {
         env: &mut DpeEnv<impl DpeTypes>,
         start_idx: usize,
     ) -> Result<Digest, DpeErrorCode> {
+        let use_cache = self.support.measurement_cache();
+        if use_cache {
+            let context = self
+                .contexts
+                .get(start_idx)
+                .ok_or(DpeErrorCode::InternalError)?;
+            if context.measurement_cached() {
+                return Ok(Digest::new(&context.cached_measurement)?);
+            }
+        }
+
         let mut hasher = env.crypto.hash_initialize(DPE_PROFILE.alg_len())?;
+        #[cfg(feature = "chain_digest_measurement")]
+        hasher.update(
+            &self
+                .contexts
+                .get(start_idx)
+                .ok_or(DpeErrorCode::InternalError)?
+                .chain_digest,
+        )?;
 
         let mut uses_internal_input_info = false;
         let mut uses_internal_input_dice = false;
+        #[cfg(not(feature = "chain_digest_measurement"))]
+        let mut seed = None;
 
-        // Hash each node.
         for status in ChildToRootIter::new(start_idx, &self.contexts) {
             let context = status?;
 
-            hasher.update(context.tci.as_bytes())?;
+            #[cfg(not(feature = "chain_digest_measurement"))]
+            {
+                hasher.update(context.tci.as_bytes())?;
+                seed = Some(&context.seed);
+            }
 
             // Check if any context uses internal inputs
             uses_internal_input_info =
@@ -448,6 +1546,13 @@ impl DpeInstance {
                 uses_internal_input_dice || context.uses_internal_input_dice();
         }
 
+        // Seeds are newer than this derivation, so only hash one if it is set
+        // to keep the CDIs of unseeded contexts unchanged.
+        #[cfg(not(feature = "chain_digest_measurement"))]
+        if let Some(seed) = seed.filter(|seed| seed.iter().any(|b| *b != 0)) {
+            hasher.update(seed)?;
+        }
+
         // Add internal input info to hash
         if cfi_launder(uses_internal_input_info) {
             let mut internal_input_info = [0u8; INTERNAL_INPUT_INFO_SIZE];
@@ -458,17 +1563,108 @@ impl DpeInstance {
         // Add internal input dice to hash
         if cfi_launder(uses_internal_input_dice) {
             let mut offset = 0;
-            let mut cert_chunk = [0u8; MAX_CHUNK_SIZE];
+            #[cfg(feature = "no-large-stack")]
+            let mut scratch = Scratch::new(env.arena);
+            scratch_buffer!(scratch, dice_chunk, MAX_CHUNK_SIZE);
             while let Ok(len) =
                 env.platform
-                    .get_certificate_chain(offset, MAX_CHUNK_SIZE as u32, &mut cert_chunk)
+                    .get_dice_measurement(offset, MAX_CHUNK_SIZE as u32, dice_chunk)
             {
-                hasher.update(&cert_chunk[..len as usize])?;
+                if len == 0 {
+                    break;
+                }
+                hasher.update(
+                    dice_chunk
+                        .get(..len as usize)
+                        .ok_or(DpeErrorCode::InternalError)?,
+                )?;
                 offset += len;
             }
         }
 
-        Ok(hasher.finish()?)
+        let digest = hasher.finish()?;
+        if use_cache {
+            let context = &mut self.contexts[start_idx];
+            context.cached_measurement.copy_from_slice(
+                digest
+                    .bytes()
+                    .get(..DPE_PROFILE.get_hash_size())
+                    .ok_or(DpeErrorCode::InternalError)?,
+            );
+            context.measurement_cached = true.into();
+        }
+        Ok(digest)
+    }
+
+    /// Drops every cached measurement digest and CDI. Must be called whenever
+    /// a TCI changes, since the digest of every descendant covers it.
+    ///
+    /// # Arguments
+    ///
+    /// * `crypto` - Crypto implementation holding the cached CDIs
+    pub(crate) fn invalidate_measurement_cache(&mut self, crypto: &mut impl Crypto) {
+        for context in self.contexts.iter_mut() {
+            context.measurement_cached = false.into();
+            context.cdi_cached = false.into();
+        }
+        crypto.clear_cdi_cache();
+    }
+
+    /// Derives the CDI of a context from its measurement hash, returning both.
+    ///
+    /// With MEASUREMENT_CACHE, the CDI is kept by the crypto implementation
+    /// in the slot of the context's index, so later calls on an unchanged
+    /// context skip the KDF. Implementations without a CDI cache still get
+    /// the cached measurement digest. The caller releases the CDI with
+    /// `destroy_cdi` either way.
+    ///
+    /// # Arguments
+    ///
+    /// * `env` - DPE environment containing Crypto and Platform implementations
+    /// * `idx` - index of the context
+    pub(crate) fn derive_cdi<'a, T: DpeTypes + 'a>(
+        &mut self,
+        env: &mut DpeEnv<'a, T>,
+        idx: usize,
+    ) -> Result<(Digest, <T::Crypto<'a> as Crypto>::Cdi), DpeErrorCode> {
+        let digest = self.compute_measurement_hash(env, idx)?;
+        let use_cache = self.support.measurement_cache();
+        let context = self
+            .contexts
+            .get_mut(idx)
+            .ok_or(DpeErrorCode::InternalError)?;
+        if use_cache && context.cdi_cached() {
+            if let Ok(cdi) = env.crypto.cached_cdi(idx) {
+                return Ok((digest, cdi));
+            }
+        }
+
+        let cdi = env
+            .crypto
+            .derive_cdi(DPE_PROFILE.alg_len(), &digest, b"DPE")?;
+        if use_cache {
+            context.cdi_cached = env.crypto.cache_cdi(idx, &cdi).is_ok().into();
+        }
+        Ok((digest, cdi))
+    }
+
+    /// Renders the TCIs of the context tree as SPDM measurement blocks in the
+    /// DMTF measurement format, so an SPDM responder can serve GET_MEASUREMENTS.
+    ///
+    /// Each active or retired context produces one block of
+    /// `spdm::MEASUREMENT_BLOCK_SIZE` bytes holding its cumulative TCI as a
+    /// mutable firmware digest. Parents come before their children and
+    /// indices start at 1, so block `n` starts at byte
+    /// `(n - 1) * spdm::MEASUREMENT_BLOCK_SIZE`.
+    ///
+    /// # Arguments
+    ///
+    /// * `out` - buffer to write the blocks to. `spdm::MAX_MEASUREMENTS_SIZE`
+    ///   bytes is always enough.
+    ///
+    /// Returns the number of blocks and the number of bytes written.
+    pub fn get_spdm_measurements(&self, out: &mut [u8]) -> Result<(u8, usize), DpeErrorCode> {
+        spdm::write_measurement_blocks(&self.contexts, out)
     }
 
     /// Count number of contexts satisfying some predicate
@@ -481,6 +1677,23 @@ impl DpeInstance {
     }
 }
 
+/// Computes HASH(PARENT_CHAIN_DIGEST || TCI), the chain digest of a context
+/// whose parent has the chain digest `parent_digest`.
+fn hash_chain_link(
+    env: &mut DpeEnv<impl DpeTypes>,
+    parent_digest: &[u8; DPE_PROFILE.get_hash_size()],
+    tci: &TciNodeData,
+) -> Result<[u8; DPE_PROFILE.get_hash_size()], DpeErrorCode> {
+    let mut hasher = env.crypto.hash_initialize(DPE_PROFILE.alg_len())?;
+    hasher.update(parent_digest)?;
+    hasher.update(tci.measured_bytes())?;
+    let digest = hasher.finish()?;
+    digest
+        .bytes()
+        .try_into()
+        .map_err(|_| DpeErrorCode::InternalError)
+}
+
 /// Iterate over all of the bits set to 1 in a u32. Each iteration returns the bit index 0 being the
 /// least significant.
 ///
@@ -488,108 +1701,649 @@ impl DpeInstance {
 ///
 /// * `flags` - bits to be iterated over
 /// * `max` - number of bits to be considered
-pub(crate) fn flags_iter(flags: u32, max: usize) -> FlagsIter {
-    assert!((1..=u32::BITS).contains(&(max as u32)));
+pub(crate) fn flags_iter(flags: u64, max: usize) -> FlagsIter {
+    assert!((1..=u64::BITS).contains(&(max as u32)));
     FlagsIter {
-        flags: flags & (u32::MAX >> (u32::BITS - max as u32)),
+        flags: flags & (u64::MAX >> (u64::BITS - max as u32)),
     }
 }
 
 pub(crate) struct FlagsIter {
-    flags: u32,
+    flags: u64,
+}
+
+impl Iterator for FlagsIter {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        if self.flags == 0 {
+            return None;
+        }
+        let idx = self.flags.trailing_zeros() as usize;
+        self.flags &= !(1 << idx);
+        Some(idx)
+    }
 }
 
-impl Iterator for FlagsIter {
-    type Item = usize;
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::commands::{
+        DeriveContextCmd, DeriveContextFlags, DestroyCtxCmd, ExtendTciCmd, RotateCtxCmd,
+        RotateCtxFlags, SignFlags,
+    };
+    use crate::response::NewHandleResp;
+    use crate::support::test::SUPPORT;
+    use crate::{commands::CommandHdr, CURRENT_PROFILE_MAJOR_VERSION};
+    use caliptra_cfi_lib_git::CfiCounter;
+    use crypto::OpensslCrypto;
+    use platform::default::{DefaultPlatform, AUTO_INIT_LOCALITY, TEST_CERT_CHAIN};
+    use platform::{
+        CertValidity, PlatformError, SignerIdentifier, MAX_ISSUER_NAME_SIZE,
+        MAX_KEY_IDENTIFIER_SIZE, MAX_UEID_SIZE,
+    };
+    use zerocopy::AsBytes;
+
+    pub struct TestTypes;
+    impl DpeTypes for TestTypes {
+        type Crypto<'a> = OpensslCrypto;
+        type Platform<'a> = DefaultPlatform;
+    }
+
+    pub const TEST_HANDLE: ContextHandle =
+        ContextHandle([0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15]);
+    pub const SIMULATION_HANDLE: ContextHandle =
+        ContextHandle([1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16]);
+    pub const RANDOM_HANDLE: ContextHandle = ContextHandle([
+        51, 1, 232, 215, 231, 84, 219, 44, 245, 123, 10, 76, 167, 63, 37, 60,
+    ]);
+
+    pub const TEST_LOCALITIES: [u32; 2] = [AUTO_INIT_LOCALITY, u32::from_be_bytes(*b"OTHR")];
+
+    #[test]
+    fn test_execute_serialized_command() {
+        CfiCounter::reset_for_test();
+        let mut env = DpeEnv::<TestTypes> {
+            crypto: OpensslCrypto::new(),
+            platform: DefaultPlatform,
+            arena: &mut [],
+        };
+        let mut dpe = DpeInstance::new(&mut env, SUPPORT).unwrap();
+
+        assert_eq!(
+            Response::GetProfile(GetProfileResp::new(
+                SUPPORT,
+                env.platform.get_vendor_id().unwrap(),
+                env.platform.get_vendor_sku().unwrap(),
+                MAX_HANDLES as u32,
+                MAX_HANDLES as u32,
+            )),
+            dpe.execute_serialized_command(
+                &mut env,
+                TEST_LOCALITIES[0],
+                CommandHdr::new_for_test(Command::GET_PROFILE).as_bytes(),
+            )
+            .unwrap()
+        );
+
+        // The default context was initialized while creating the instance. Now lets create a
+        // simulation context.
+        let mut command = CommandHdr::new_for_test(Command::INITIALIZE_CONTEXT)
+            .as_bytes()
+            .to_vec();
+        command.extend(InitCtxCmd::new_simulation().as_bytes());
+        assert_eq!(
+            Response::InitCtx(NewHandleResp {
+                handle: RANDOM_HANDLE,
+                resp_hdr: ResponseHdr::new(DpeErrorCode::NoError),
+            }),
+            dpe.execute_serialized_command(&mut env, TEST_LOCALITIES[0], &command)
+                .unwrap()
+        );
+    }
+
+    #[cfg(not(any(
+        feature = "dpe_profile_mldsa87_sha384",
+        feature = "dpe_profile_ed25519_sha512"
+    )))]
+    #[test]
+    fn test_start_and_poll_command() {
+        CfiCounter::reset_for_test();
+        let mut env = DpeEnv::<TestTypes> {
+            crypto: OpensslCrypto::new(),
+            platform: DefaultPlatform,
+            arena: &mut [],
+        };
+        let mut dpe = DpeInstance::new(&mut env, Support::AUTO_INIT).unwrap();
+        let get_profile = CommandHdr::new_for_test(Command::GET_PROFILE);
+        let mut sign = CommandHdr::new_for_test(Command::SIGN).as_bytes().to_vec();
+        sign.extend(
+            SignCmd {
+                handle: ContextHandle::default(),
+                label: [0; DPE_PROFILE.get_hash_size()],
+                flags: SignFlags::empty(),
+                digest: [0x55; DPE_PROFILE.get_hash_size()],
+            }
+            .as_bytes(),
+        );
+
+        // Crypto that signs right away completes the command.
+        let Ok(CommandStatus::Complete(Response::Sign(_))) =
+            dpe.start_command(&mut env, TEST_LOCALITIES[0], &sign)
+        else {
+            panic!("Sign did not complete");
+        };
+
+        env.crypto.set_defer_signing(true);
+        let Ok(CommandStatus::Pending(token)) =
+            dpe.start_command(&mut env, TEST_LOCALITIES[0], &sign)
+        else {
+            panic!("Sign was not left pending");
+        };
+
+        // Nothing else runs until the signature is collected.
+        assert_eq!(
+            Ok(Response::Error(ResponseHdr::new(DpeErrorCode::Busy))),
+            dpe.execute_serialized_command(&mut env, TEST_LOCALITIES[0], get_profile.as_bytes())
+        );
+        assert_eq!(
+            Ok(CommandStatus::Complete(Response::Error(ResponseHdr::new(
+                DpeErrorCode::Busy
+            )))),
+            dpe.start_command(&mut env, TEST_LOCALITIES[0], &sign)
+        );
+        assert_eq!(
+            Err(DpeErrorCode::InvalidArgument),
+            dpe.poll_command(
+                &mut env,
+                TEST_LOCALITIES[0],
+                PendingToken(token.0.wrapping_add(1))
+            )
+        );
+        // Only the locality that started the command may collect it.
+        assert_eq!(
+            Err(DpeErrorCode::InvalidLocality),
+            dpe.poll_command(&mut env, TEST_LOCALITIES[1], token)
+        );
+        assert_eq!(
+            Err(DpeErrorCode::InvalidLocality),
+            dpe.cancel_command(&mut env, TEST_LOCALITIES[1], token)
+        );
+
+        let Ok(CommandStatus::Complete(Response::Sign(resp))) =
+            dpe.poll_command(&mut env, TEST_LOCALITIES[0], token)
+        else {
+            panic!("Sign did not complete");
+        };
+        assert_eq!(DpeErrorCode::NoError.get_error_code(), resp.resp_hdr.status);
+        assert_eq!(ContextHandle::default(), resp.new_context_handle);
+
+        // The token is spent and other commands run again.
+        assert_eq!(
+            Err(DpeErrorCode::InvalidArgument),
+            dpe.poll_command(&mut env, TEST_LOCALITIES[0], token)
+        );
+        assert!(matches!(
+            dpe.execute_serialized_command(&mut env, TEST_LOCALITIES[0], get_profile.as_bytes()),
+            Ok(Response::GetProfile(_))
+        ));
+    }
+
+    #[cfg(not(any(
+        feature = "dpe_profile_mldsa87_sha384",
+        feature = "dpe_profile_ed25519_sha512"
+    )))]
+    #[test]
+    fn test_cancel_and_expire_pending_command() {
+        CfiCounter::reset_for_test();
+        let mut env = DpeEnv::<TestTypes> {
+            crypto: OpensslCrypto::new(),
+            platform: DefaultPlatform,
+            arena: &mut [],
+        };
+        let mut dpe = DpeInstance::new(&mut env, Support::AUTO_INIT).unwrap();
+        let get_profile = CommandHdr::new_for_test(Command::GET_PROFILE);
+        let mut sign = CommandHdr::new_for_test(Command::SIGN).as_bytes().to_vec();
+        sign.extend(
+            SignCmd {
+                handle: ContextHandle::default(),
+                label: [0; DPE_PROFILE.get_hash_size()],
+                flags: SignFlags::empty(),
+                digest: [0x55; DPE_PROFILE.get_hash_size()],
+            }
+            .as_bytes(),
+        );
+        env.crypto.set_defer_signing(true);
+
+        // The locality that started a command can abandon it.
+        let Ok(CommandStatus::Pending(token)) =
+            dpe.start_command(&mut env, TEST_LOCALITIES[0], &sign)
+        else {
+            panic!("Sign was not left pending");
+        };
+        dpe.cancel_command(&mut env, TEST_LOCALITIES[0], token)
+            .unwrap();
+        assert_eq!(
+            Err(DpeErrorCode::InvalidArgument),
+            dpe.poll_command(&mut env, TEST_LOCALITIES[0], token)
+        );
+        assert!(matches!(
+            dpe.execute_serialized_command(&mut env, TEST_LOCALITIES[1], get_profile.as_bytes()),
+            Ok(Response::GetProfile(_))
+        ));
+
+        // A command that is never collected expires.
+        let Ok(CommandStatus::Pending(token)) =
+            dpe.start_command(&mut env, TEST_LOCALITIES[0], &sign)
+        else {
+            panic!("Sign was not left pending");
+        };
+        for _ in 0..crate::pending::MAX_TURNED_AWAY {
+            assert_eq!(
+                Ok(Response::Error(ResponseHdr::new(DpeErrorCode::Busy))),
+                dpe.execute_serialized_command(
+                    &mut env,
+                    TEST_LOCALITIES[1],
+                    get_profile.as_bytes()
+                )
+            );
+        }
+        assert!(matches!(
+            dpe.execute_serialized_command(&mut env, TEST_LOCALITIES[1], get_profile.as_bytes()),
+            Ok(Response::GetProfile(_))
+        ));
+        assert_eq!(
+            Err(DpeErrorCode::InvalidArgument),
+            dpe.poll_command(&mut env, TEST_LOCALITIES[0], token)
+        );
+
+        // An expired command is replaced by the one that ends it.
+        let Ok(CommandStatus::Pending(token)) =
+            dpe.start_command(&mut env, TEST_LOCALITIES[0], &sign)
+        else {
+            panic!("Sign was not left pending");
+        };
+        for _ in 0..crate::pending::MAX_TURNED_AWAY {
+            assert!(matches!(
+                dpe.start_command(&mut env, TEST_LOCALITIES[0], &sign),
+                Ok(CommandStatus::Complete(Response::Error(_)))
+            ));
+        }
+        let Ok(CommandStatus::Pending(new_token)) =
+            dpe.start_command(&mut env, TEST_LOCALITIES[0], &sign)
+        else {
+            panic!("Sign was not left pending");
+        };
+        assert_ne!(token, new_token);
+        assert!(matches!(
+            dpe.poll_command(&mut env, TEST_LOCALITIES[0], new_token),
+            Ok(CommandStatus::Complete(Response::Sign(_)))
+        ));
+    }
+
+    #[test]
+    fn test_execute_serialized_command_checks_locality() {
+        CfiCounter::reset_for_test();
+        let mut env = DpeEnv::<TestTypes> {
+            crypto: OpensslCrypto::new(),
+            platform: DefaultPlatform,
+            arena: &mut [],
+        };
+        let mut dpe = DpeInstance::new(&mut env, Support::AUTO_INIT | Support::EXTEND_TCI).unwrap();
+
+        let mut extend_tci = CommandHdr::new_for_test(Command::EXTEND_TCI)
+            .as_bytes()
+            .to_vec();
+        extend_tci.extend(
+            ExtendTciCmd {
+                handle: ContextHandle::default(),
+                data: [0; DPE_PROFILE.get_hash_size()],
+            }
+            .as_bytes(),
+        );
+
+        // The auto-initialized context is only reachable from AUTO_INIT_LOCALITY.
+        assert_eq!(
+            Response::Error(ResponseHdr::new(DpeErrorCode::InvalidLocality)),
+            dpe.execute_serialized_command(&mut env, TEST_LOCALITIES[1], &extend_tci)
+                .unwrap()
+        );
+
+        // Hand the default context over to the other locality.
+        let mut derive = CommandHdr::new_for_test(Command::DERIVE_CONTEXT)
+            .as_bytes()
+            .to_vec();
+        derive.extend(
+            DeriveContextCmd {
+                handle: ContextHandle::default(),
+                data: [0; DPE_PROFILE.get_tci_size()],
+                flags: DeriveContextFlags::MAKE_DEFAULT | DeriveContextFlags::CHANGE_LOCALITY,
+                tci_type: 0,
+                target_locality: TEST_LOCALITIES[1],
+            }
+            .as_bytes(),
+        );
+        dpe.execute_serialized_command(&mut env, TEST_LOCALITIES[0], &derive)
+            .unwrap();
+
+        assert_eq!(
+            Response::Error(ResponseHdr::new(DpeErrorCode::InvalidLocality)),
+            dpe.execute_serialized_command(&mut env, TEST_LOCALITIES[0], &extend_tci)
+                .unwrap()
+        );
+        assert_eq!(
+            Response::ExtendTci(NewHandleResp {
+                handle: ContextHandle::default(),
+                resp_hdr: ResponseHdr::new(DpeErrorCode::NoError),
+            }),
+            dpe.execute_serialized_command(&mut env, TEST_LOCALITIES[1], &extend_tci)
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn test_new_for_profile() {
+        CfiCounter::reset_for_test();
+        let mut env = DpeEnv::<TestTypes> {
+            crypto: OpensslCrypto::new(),
+            platform: DefaultPlatform,
+            arena: &mut [],
+        };
+
+        for profile in [
+            DpeProfile::P256Sha256,
+            DpeProfile::P384Sha384,
+            DpeProfile::Mldsa87Sha384,
+            DpeProfile::P521Sha512,
+            DpeProfile::Ed25519Sha512,
+        ] {
+            assert_eq!(DpeProfile::try_from(profile as u32), Ok(profile));
+
+            let dpe = DpeInstance::new_for_profile(&mut env, SUPPORT, profile);
+            if profile == DPE_PROFILE {
+                assert!(dpe.is_ok());
+            } else {
+                assert!(matches!(dpe, Err(DpeErrorCode::ArgumentNotSupported)));
+            }
+        }
+
+        assert_eq!(DpeProfile::try_from(0), Err(DpeErrorCode::InvalidArgument));
+    }
+
+    #[test]
+    fn test_get_profile() {
+        CfiCounter::reset_for_test();
+        let mut env = DpeEnv::<TestTypes> {
+            crypto: OpensslCrypto::new(),
+            platform: DefaultPlatform,
+            arena: &mut [],
+        };
+        let dpe = DpeInstance::new(&mut env, SUPPORT).unwrap();
+        let profile = dpe.get_profile(&mut env.platform).unwrap();
+        assert_eq!(profile.major_version, CURRENT_PROFILE_MAJOR_VERSION);
+        assert_eq!(profile.flags, SUPPORT.bits());
+        assert_eq!(profile.flags & Support::IS_SYMMETRIC.bits(), 0);
+
+        // Symmetric signing should be discoverable through GetProfile.
+        let dpe = DpeInstance::new(&mut env, SUPPORT | Support::IS_SYMMETRIC).unwrap();
+        let profile = dpe.get_profile(&mut env.platform).unwrap();
+        assert_ne!(profile.flags & Support::IS_SYMMETRIC.bits(), 0);
+
+        // Each capability is reported through its own flag.
+        for flag in Support::all().iter() {
+            let dpe = DpeInstance::new(&mut env, flag | Support::AUTO_INIT).unwrap();
+            let profile = dpe.get_profile(&mut env.platform).unwrap();
+            assert_eq!(profile.support(), flag | Support::AUTO_INIT);
+        }
+    }
+
+    const FMC_TCI_TYPE: u32 = u32::from_be_bytes(*b"FMC_");
+    const RUNTIME_TCI_TYPE: u32 = u32::from_be_bytes(*b"RT__");
+
+    /// Platform that only lets the auto-init locality destroy contexts and
+    /// only lets runtime contexts be derived from FMC contexts.
+    struct RestrictedPlatform(DefaultPlatform);
+
+    impl Platform for RestrictedPlatform {
+        fn get_certificate_chain(
+            &mut self,
+            offset: u32,
+            size: u32,
+            out: &mut [u8; MAX_CHUNK_SIZE],
+        ) -> Result<u32, PlatformError> {
+            self.0.get_certificate_chain(offset, size, out)
+        }
+
+        fn get_issuer_name(
+            &mut self,
+            out: &mut [u8; MAX_ISSUER_NAME_SIZE],
+        ) -> Result<usize, PlatformError> {
+            self.0.get_issuer_name(out)
+        }
+
+        fn get_signer_identifier(&mut self) -> Result<SignerIdentifier, PlatformError> {
+            self.0.get_signer_identifier()
+        }
+
+        fn get_issuer_key_identifier(
+            &mut self,
+            out: &mut [u8; MAX_KEY_IDENTIFIER_SIZE],
+        ) -> Result<(), PlatformError> {
+            self.0.get_issuer_key_identifier(out)
+        }
+
+        fn get_ueid(&mut self, out: &mut [u8; MAX_UEID_SIZE]) -> Result<usize, PlatformError> {
+            self.0.get_ueid(out)
+        }
+
+        fn get_vendor_id(&mut self) -> Result<u32, PlatformError> {
+            self.0.get_vendor_id()
+        }
+
+        fn get_vendor_sku(&mut self) -> Result<u32, PlatformError> {
+            self.0.get_vendor_sku()
+        }
+
+        fn get_auto_init_locality(&mut self) -> Result<u32, PlatformError> {
+            self.0.get_auto_init_locality()
+        }
+
+        fn write_str(&mut self, str: &str) -> Result<(), PlatformError> {
+            self.0.write_str(str)
+        }
+
+        fn get_cert_validity(&mut self) -> Result<CertValidity, PlatformError> {
+            self.0.get_cert_validity()
+        }
+
+        fn authorize(&mut self, cmd_id: u32, locality: u32) -> bool {
+            cmd_id != Command::DESTROY_CONTEXT || locality == AUTO_INIT_LOCALITY
+        }
 
-    fn next(&mut self) -> Option<usize> {
-        if self.flags == 0 {
-            return None;
+        fn validate_tci_type(&mut self, parent_type: u32, child_type: u32) -> bool {
+            child_type != RUNTIME_TCI_TYPE || parent_type == FMC_TCI_TYPE
         }
-        let idx = self.flags.trailing_zeros() as usize;
-        self.flags &= !(1 << idx);
-        Some(idx)
     }
-}
-
-#[cfg(test)]
-pub mod tests {
-    use super::*;
-    use crate::commands::{DeriveContextCmd, DeriveContextFlags};
-    use crate::response::NewHandleResp;
-    use crate::support::test::SUPPORT;
-    use crate::{commands::CommandHdr, CURRENT_PROFILE_MAJOR_VERSION};
-    use caliptra_cfi_lib_git::CfiCounter;
-    use crypto::OpensslCrypto;
-    use platform::default::{DefaultPlatform, AUTO_INIT_LOCALITY, TEST_CERT_CHAIN};
-    use zerocopy::AsBytes;
 
-    pub struct TestTypes;
-    impl DpeTypes for TestTypes {
+    struct RestrictedTypes;
+    impl DpeTypes for RestrictedTypes {
         type Crypto<'a> = OpensslCrypto;
-        type Platform<'a> = DefaultPlatform;
+        type Platform<'a> = RestrictedPlatform;
     }
 
-    pub const TEST_HANDLE: ContextHandle =
-        ContextHandle([0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15]);
-    pub const SIMULATION_HANDLE: ContextHandle =
-        ContextHandle([1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16]);
-    pub const RANDOM_HANDLE: ContextHandle = ContextHandle([
-        51, 1, 232, 215, 231, 84, 219, 44, 245, 123, 10, 76, 167, 63, 37, 60,
-    ]);
+    #[test]
+    fn test_authorize() {
+        CfiCounter::reset_for_test();
+        let mut env = DpeEnv::<RestrictedTypes> {
+            crypto: OpensslCrypto::new(),
+            platform: RestrictedPlatform(DefaultPlatform),
+            arena: &mut [],
+        };
+        let mut dpe = DpeInstance::new(&mut env, SUPPORT).unwrap();
+        InitCtxCmd::new_simulation()
+            .execute(&mut dpe, &mut env, TEST_LOCALITIES[1])
+            .unwrap();
 
-    pub const TEST_LOCALITIES: [u32; 2] = [AUTO_INIT_LOCALITY, u32::from_be_bytes(*b"OTHR")];
+        let destroy = |handle: &ContextHandle| {
+            let mut command = CommandHdr::new_for_test(Command::DESTROY_CONTEXT)
+                .as_bytes()
+                .to_vec();
+            command.extend(DestroyCtxCmd { handle: *handle }.as_bytes());
+            command
+        };
+
+        // The other locality may not destroy its own context.
+        let sim_handle = dpe.contexts[1].handle;
+        assert_eq!(
+            Ok(Response::Error(ResponseHdr::new(
+                DpeErrorCode::CommandNotAllowed
+            ))),
+            dpe.execute_serialized_command(&mut env, TEST_LOCALITIES[1], &destroy(&sim_handle))
+        );
+        assert_eq!(dpe.contexts[1].state, ContextState::Active);
+
+        // Other commands are still allowed from that locality.
+        assert!(matches!(
+            dpe.execute_serialized_command(
+                &mut env,
+                TEST_LOCALITIES[1],
+                CommandHdr::new_for_test(Command::GET_PROFILE).as_bytes(),
+            ),
+            Ok(Response::GetProfile(_))
+        ));
+
+        // The auto-init locality may destroy its context.
+        assert!(matches!(
+            dpe.execute_serialized_command(
+                &mut env,
+                TEST_LOCALITIES[0],
+                &destroy(&ContextHandle::default())
+            ),
+            Ok(Response::DestroyCtx(_))
+        ));
+    }
 
     #[test]
-    fn test_execute_serialized_command() {
+    fn test_validate_tci_type() {
+        CfiCounter::reset_for_test();
+        let mut env = DpeEnv::<RestrictedTypes> {
+            crypto: OpensslCrypto::new(),
+            platform: RestrictedPlatform(DefaultPlatform),
+            arena: &mut [],
+        };
+        let mut dpe = DpeInstance::new(&mut env, SUPPORT).unwrap();
+
+        let derive = |tci_type| DeriveContextCmd {
+            handle: ContextHandle::default(),
+            data: [0; DPE_PROFILE.get_tci_size()],
+            flags: DeriveContextFlags::MAKE_DEFAULT,
+            tci_type,
+            target_locality: 0,
+        };
+
+        // Runtime firmware can't be layered directly on the auto-initialized context.
+        assert_eq!(
+            Err(DpeErrorCode::TciTypeNotAllowed),
+            derive(RUNTIME_TCI_TYPE).execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
+        );
+        assert_eq!(dpe.contexts[0].state, ContextState::Active);
+        assert_eq!(dpe.contexts[1].state, ContextState::Inactive);
+
+        derive(FMC_TCI_TYPE)
+            .execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
+            .unwrap();
+        derive(RUNTIME_TCI_TYPE)
+            .execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
+            .unwrap();
+        assert_eq!(dpe.contexts[2].state, ContextState::Active);
+        assert_eq!(dpe.contexts[2].tci.tci_type, RUNTIME_TCI_TYPE);
+    }
+
+    #[test]
+    fn test_handle_mac() {
         CfiCounter::reset_for_test();
         let mut env = DpeEnv::<TestTypes> {
             crypto: OpensslCrypto::new(),
             platform: DefaultPlatform,
+            arena: &mut [],
         };
-        let mut dpe = DpeInstance::new(&mut env, SUPPORT).unwrap();
+        let mut dpe = DpeInstance::new(&mut env, SUPPORT | Support::HANDLE_MAC).unwrap();
+        assert_ne!(dpe.handle_mac_key, [0; DpeInstance::HANDLE_MAC_KEY_SIZE]);
+
+        let mut init_ctx = CommandHdr::new_for_test(Command::INITIALIZE_CONTEXT)
+            .as_bytes()
+            .to_vec();
+        init_ctx.extend(InitCtxCmd::new_simulation().as_bytes());
+        let Ok(Response::InitCtx(resp)) =
+            dpe.execute_serialized_command(&mut env, TEST_LOCALITIES[0], &init_ctx)
+        else {
+            panic!("Failed to initialize a simulation context");
+        };
+        let handle = resp.handle;
 
+        // The handle is only valid in the locality it was issued to.
         assert_eq!(
-            Response::GetProfile(GetProfileResp::new(
-                SUPPORT.bits(),
-                env.platform.get_vendor_id().unwrap(),
-                env.platform.get_vendor_sku().unwrap()
-            )),
+            Ok(()),
+            dpe.verify_handle_mac(&mut env, &handle, TEST_LOCALITIES[0])
+        );
+        assert_eq!(
+            Err(DpeErrorCode::InvalidHandle),
+            dpe.verify_handle_mac(&mut env, &handle, TEST_LOCALITIES[1])
+        );
+        assert_eq!(
+            Ok(()),
+            dpe.verify_handle_mac(&mut env, &ContextHandle::default(), TEST_LOCALITIES[1])
+        );
+
+        let rotate = |handle: &ContextHandle| {
+            let mut command = CommandHdr::new_for_test(Command::ROTATE_CONTEXT_HANDLE)
+                .as_bytes()
+                .to_vec();
+            command.extend(
+                RotateCtxCmd {
+                    handle: *handle,
+                    flags: RotateCtxFlags::empty(),
+                }
+                .as_bytes(),
+            );
+            command
+        };
+
+        // A handle without a valid MAC is rejected even if a context has it.
+        let idx = dpe
+            .get_active_context_pos(&handle, TEST_LOCALITIES[0])
+            .unwrap();
+        dpe.contexts[idx].handle = SIMULATION_HANDLE;
+        assert_eq!(
+            Response::Error(ResponseHdr::new(DpeErrorCode::InvalidHandle)),
             dpe.execute_serialized_command(
                 &mut env,
                 TEST_LOCALITIES[0],
-                CommandHdr::new_for_test(Command::GET_PROFILE).as_bytes(),
+                &rotate(&SIMULATION_HANDLE)
             )
             .unwrap()
         );
+        dpe.contexts[idx].handle = handle;
 
-        // The default context was initialized while creating the instance. Now lets create a
-        // simulation context.
-        let mut command = CommandHdr::new_for_test(Command::INITIALIZE_CONTEXT)
-            .as_bytes()
-            .to_vec();
-        command.extend(InitCtxCmd::new_simulation().as_bytes());
+        let mut forged = handle;
+        forged.0[ContextHandle::SIZE - 1] ^= 1;
         assert_eq!(
-            Response::InitCtx(NewHandleResp {
-                handle: RANDOM_HANDLE,
-                resp_hdr: ResponseHdr::new(DpeErrorCode::NoError),
-            }),
-            dpe.execute_serialized_command(&mut env, TEST_LOCALITIES[0], &command)
+            Response::Error(ResponseHdr::new(DpeErrorCode::InvalidHandle)),
+            dpe.execute_serialized_command(&mut env, TEST_LOCALITIES[0], &rotate(&forged))
                 .unwrap()
         );
-    }
 
-    #[test]
-    fn test_get_profile() {
-        CfiCounter::reset_for_test();
-        let mut env = DpeEnv::<TestTypes> {
-            crypto: OpensslCrypto::new(),
-            platform: DefaultPlatform,
+        // Rotated handles are MACed too.
+        let Ok(Response::RotateCtx(resp)) =
+            dpe.execute_serialized_command(&mut env, TEST_LOCALITIES[0], &rotate(&handle))
+        else {
+            panic!("Failed to rotate the simulation context");
         };
-        let dpe = DpeInstance::new(&mut env, SUPPORT).unwrap();
-        let profile = dpe.get_profile(&mut env.platform).unwrap();
-        assert_eq!(profile.major_version, CURRENT_PROFILE_MAJOR_VERSION);
-        assert_eq!(profile.flags, SUPPORT.bits());
+        assert_ne!(handle, resp.handle);
+        assert_eq!(
+            Ok(()),
+            dpe.verify_handle_mac(&mut env, &resp.handle, TEST_LOCALITIES[0])
+        );
     }
 
     #[test]
@@ -598,6 +2352,7 @@ pub mod tests {
         let mut env = DpeEnv::<TestTypes> {
             crypto: OpensslCrypto::new(),
             platform: DefaultPlatform,
+            arena: &mut [],
         };
         let mut dpe = DpeInstance::new(&mut env, Support::default()).unwrap();
         let expected_index = 7;
@@ -636,6 +2391,7 @@ pub mod tests {
         let mut env = DpeEnv::<TestTypes> {
             crypto: OpensslCrypto::new(),
             platform: DefaultPlatform,
+            arena: &mut [],
         };
 
         let mut dpe = DpeInstance::new(&mut env, Support::AUTO_INIT).unwrap();
@@ -688,6 +2444,7 @@ pub mod tests {
         let mut env = DpeEnv::<TestTypes> {
             crypto: OpensslCrypto::new(),
             platform: DefaultPlatform,
+            arena: &mut [],
         };
         let mut dpe = DpeInstance::new(&mut env, Support::default()).unwrap();
         let root = 7;
@@ -707,91 +2464,420 @@ pub mod tests {
         dpe.contexts[root].state = ContextState::Active;
         assert_eq!(dpe.get_descendants(&dpe.contexts[root]).unwrap(), 0);
 
-        // Child not active.
-        dpe.contexts[root].children = 1 << child_1;
-        assert_eq!(
-            dpe.get_descendants(&dpe.contexts[root]),
-            Err(DpeErrorCode::InvalidHandle)
-        );
+        // Child not active.
+        dpe.contexts[root].children = 1 << child_1;
+        assert_eq!(
+            dpe.get_descendants(&dpe.contexts[root]),
+            Err(DpeErrorCode::InvalidHandle)
+        );
+
+        // One child.
+        dpe.contexts[child_1].state = ContextState::Active;
+        let mut children = dpe.contexts[root].children;
+        assert_eq!(children, dpe.get_descendants(&dpe.contexts[root]).unwrap());
+
+        // Add grandchildren.
+        dpe.contexts[child_1_1].state = ContextState::Active;
+        dpe.contexts[child_1_2].state = ContextState::Active;
+        dpe.contexts[child_1_3].state = ContextState::Active;
+        dpe.contexts[child_1].children = (1 << child_1_1) | (1 << child_1_2) | (1 << child_1_3);
+        children |= dpe.contexts[child_1].children;
+        assert_eq!(children, dpe.get_descendants(&dpe.contexts[root]).unwrap());
+
+        // Add great-grandchildren.
+        dpe.contexts[child_1_2_1].state = ContextState::Active;
+        dpe.contexts[child_1_2].children = 1 << child_1_2_1;
+        children |= dpe.contexts[child_1_2].children;
+        assert_eq!(
+            dpe.contexts[child_1_2].children,
+            dpe.get_descendants(&dpe.contexts[child_1_2]).unwrap()
+        );
+        assert_eq!(children, dpe.get_descendants(&dpe.contexts[root]).unwrap());
+    }
+
+    #[test]
+    fn test_derive_cdi() {
+        CfiCounter::reset_for_test();
+        let mut env = DpeEnv::<TestTypes> {
+            crypto: OpensslCrypto::new(),
+            platform: DefaultPlatform,
+            arena: &mut [],
+        };
+        let mut dpe = DpeInstance::new(&mut env, SUPPORT).unwrap();
+
+        let mut last_cdi = vec![];
+
+        for i in 0..3 {
+            DeriveContextCmd {
+                handle: ContextHandle::default(),
+                data: [i; DPE_PROFILE.get_hash_size()],
+                flags: DeriveContextFlags::MAKE_DEFAULT,
+                tci_type: i as u32,
+                target_locality: 0,
+            }
+            .execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
+            .unwrap();
+
+            // Check the CDI changes each time.
+            let leaf_context_idx = dpe
+                .get_active_context_pos(&ContextHandle::default(), TEST_LOCALITIES[0])
+                .unwrap();
+            let digest = dpe
+                .compute_measurement_hash(&mut env, leaf_context_idx)
+                .unwrap();
+            let curr_cdi = env
+                .crypto
+                .derive_cdi(DPE_PROFILE.alg_len(), &digest, b"DPE")
+                .unwrap();
+            assert_ne!(last_cdi, curr_cdi.bytes());
+
+            last_cdi = curr_cdi.bytes().to_vec();
+        }
+
+        let leaf_idx = dpe
+            .get_active_context_pos(&ContextHandle::default(), TEST_LOCALITIES[0])
+            .unwrap();
+        let measured = measured_chain(&dpe, &mut env, leaf_idx);
+        let mut hasher = env.crypto.hash_initialize(DPE_PROFILE.alg_len()).unwrap();
+        hasher.update(&measured).unwrap();
+
+        let digest = hasher.finish().unwrap();
+        let answer = env
+            .crypto
+            .derive_cdi(DPE_PROFILE.alg_len(), &digest, b"DPE")
+            .unwrap();
+        assert_eq!(answer.bytes(), last_cdi);
+    }
+
+    /// Computes the chain digest of the context at `idx` from scratch, folding
+    /// in every TCI from the root down.
+    fn full_chain_digest(dpe: &DpeInstance, env: &mut DpeEnv<TestTypes>, idx: usize) -> Vec<u8> {
+        let mut nodes = [TciNodeData::new(); MAX_HANDLES];
+        let count = dpe.get_tcb_nodes(idx, &mut nodes).unwrap();
+        let mut digest = vec![0; DPE_PROFILE.get_hash_size()];
+        for node in &nodes[..count] {
+            let mut hasher = env.crypto.hash_initialize(DPE_PROFILE.alg_len()).unwrap();
+            hasher.update(&digest).unwrap();
+            hasher.update(node.measured_bytes()).unwrap();
+            digest = hasher.finish().unwrap().bytes().to_vec();
+        }
+        digest
+    }
+
+    /// Concatenates every TciNodeData from the context at `idx` up to the
+    /// root, which is what the measurement hash covers by default for an
+    /// unseeded root.
+    fn tci_measured_chain(dpe: &DpeInstance, idx: usize) -> Vec<u8> {
+        let mut bytes = vec![];
+        for status in ChildToRootIter::new(idx, &dpe.contexts) {
+            bytes.extend_from_slice(status.unwrap().tci.as_bytes());
+        }
+        bytes
+    }
+
+    /// Returns what `compute_measurement_hash` hashes for the TCIs of the
+    /// context at `idx`, ahead of any internal inputs.
+    fn measured_chain(dpe: &DpeInstance, env: &mut DpeEnv<TestTypes>, idx: usize) -> Vec<u8> {
+        if cfg!(feature = "chain_digest_measurement") {
+            full_chain_digest(dpe, env, idx)
+        } else {
+            tci_measured_chain(dpe, idx)
+        }
+    }
+
+    #[test]
+    fn test_default_measurement_hash() {
+        CfiCounter::reset_for_test();
+        let mut env = DpeEnv::<TestTypes> {
+            crypto: OpensslCrypto::new(),
+            platform: DefaultPlatform,
+            arena: &mut [],
+        };
+        let mut dpe = DpeInstance::new(&mut env, SUPPORT).unwrap();
+        for i in 0..3 {
+            DeriveContextCmd {
+                handle: ContextHandle::default(),
+                data: [i; DPE_PROFILE.get_hash_size()],
+                flags: DeriveContextFlags::MAKE_DEFAULT,
+                tci_type: i as u32,
+                target_locality: 0,
+            }
+            .execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
+            .unwrap();
+        }
+        let leaf_idx = dpe
+            .get_active_context_pos(&ContextHandle::default(), TEST_LOCALITIES[0])
+            .unwrap();
+
+        // Chain digests must not change the CDIs of existing devices unless
+        // chain_digest_measurement is enabled.
+        let per_tci = env
+            .crypto
+            .hash(DPE_PROFILE.alg_len(), &tci_measured_chain(&dpe, leaf_idx))
+            .unwrap();
+        let measurement = dpe.compute_measurement_hash(&mut env, leaf_idx).unwrap();
+        if cfg!(feature = "chain_digest_measurement") {
+            assert_ne!(per_tci.bytes(), measurement.bytes());
+        } else {
+            assert_eq!(per_tci.bytes(), measurement.bytes());
+        }
+    }
+
+    /// Checks the stored chain digest of every context that has a TCI.
+    fn check_chain_digests(dpe: &DpeInstance, env: &mut DpeEnv<TestTypes>) {
+        for (idx, context) in dpe.contexts.iter().enumerate() {
+            if context.state != ContextState::Inactive {
+                assert_eq!(
+                    context.chain_digest.to_vec(),
+                    full_chain_digest(dpe, env, idx),
+                    "context {idx}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_chain_digests() {
+        CfiCounter::reset_for_test();
+        let mut env = DpeEnv::<TestTypes> {
+            crypto: OpensslCrypto::new(),
+            platform: DefaultPlatform,
+            arena: &mut [],
+        };
+        let mut dpe =
+            DpeInstance::new(&mut env, SUPPORT | Support::EXTEND_TCI | Support::RECURSIVE).unwrap();
+        check_chain_digests(&dpe, &mut env);
+
+        // A retained parent with two children, one of which retires its
+        // parent when deriving a grandchild.
+        let derive = |handle, data, flags| DeriveContextCmd {
+            handle,
+            data: [data; DPE_PROFILE.get_hash_size()],
+            flags,
+            tci_type: data as u32,
+            target_locality: 0,
+        };
+        let mut children = vec![];
+        for data in [1, 2] {
+            match derive(
+                ContextHandle::default(),
+                data,
+                DeriveContextFlags::RETAIN_PARENT_CONTEXT,
+            )
+            .execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
+            .unwrap()
+            {
+                Response::DeriveContext(resp) => children.push(resp.handle),
+                _ => panic!("Incorrect response type"),
+            }
+        }
+        let grandchild = match derive(children[0], 3, DeriveContextFlags::empty())
+            .execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
+            .unwrap()
+        {
+            Response::DeriveContext(resp) => resp.handle,
+            _ => panic!("Incorrect response type"),
+        };
+        check_chain_digests(&dpe, &mut env);
+
+        // Extending the root has to update every descendant.
+        ExtendTciCmd {
+            handle: ContextHandle::default(),
+            data: [4; DPE_PROFILE.get_hash_size()],
+        }
+        .execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
+        .unwrap();
+        check_chain_digests(&dpe, &mut env);
 
-        // One child.
-        dpe.contexts[child_1].state = ContextState::Active;
-        let mut children = dpe.contexts[root].children;
-        assert_eq!(children, dpe.get_descendants(&dpe.contexts[root]).unwrap());
+        let grandchild = match derive(grandchild, 3, DeriveContextFlags::RECURSIVE)
+            .execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
+            .unwrap()
+        {
+            Response::DeriveContext(resp) => resp.parent_handle,
+            _ => panic!("Incorrect response type"),
+        };
+        check_chain_digests(&dpe, &mut env);
 
-        // Add grandchildren.
-        dpe.contexts[child_1_1].state = ContextState::Active;
-        dpe.contexts[child_1_2].state = ContextState::Active;
-        dpe.contexts[child_1_3].state = ContextState::Active;
-        dpe.contexts[child_1].children = (1 << child_1_1) | (1 << child_1_2) | (1 << child_1_3);
-        children |= dpe.contexts[child_1].children;
-        assert_eq!(children, dpe.get_descendants(&dpe.contexts[root]).unwrap());
+        InitCtxCmd::new_simulation()
+            .execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
+            .unwrap();
+        check_chain_digests(&dpe, &mut env);
 
-        // Add great-grandchildren.
-        dpe.contexts[child_1_2_1].state = ContextState::Active;
-        dpe.contexts[child_1_2].children = 1 << child_1_2_1;
-        children |= dpe.contexts[child_1_2].children;
+        // The measurement keys are derived from covers the whole chain.
+        let leaf_idx = dpe
+            .get_active_context_pos(&grandchild, TEST_LOCALITIES[0])
+            .unwrap();
+        let measured = measured_chain(&dpe, &mut env, leaf_idx);
+        let mut hasher = env.crypto.hash_initialize(DPE_PROFILE.alg_len()).unwrap();
+        hasher.update(&measured).unwrap();
+        let digest = hasher.finish().unwrap();
         assert_eq!(
-            dpe.contexts[child_1_2].children,
-            dpe.get_descendants(&dpe.contexts[child_1_2]).unwrap()
+            digest.bytes(),
+            dpe.compute_measurement_hash(&mut env, leaf_idx)
+                .unwrap()
+                .bytes()
         );
-        assert_eq!(children, dpe.get_descendants(&dpe.contexts[root]).unwrap());
     }
 
     #[test]
-    fn test_derive_cdi() {
+    fn test_measurement_cache() {
         CfiCounter::reset_for_test();
         let mut env = DpeEnv::<TestTypes> {
             crypto: OpensslCrypto::new(),
             platform: DefaultPlatform,
+            arena: &mut [],
         };
-        let mut dpe = DpeInstance::new(&mut env, SUPPORT).unwrap();
-
-        let mut last_cdi = vec![];
+        let mut dpe = DpeInstance::new(
+            &mut env,
+            SUPPORT | Support::MEASUREMENT_CACHE | Support::EXTEND_TCI | Support::RECURSIVE,
+        )
+        .unwrap();
+        let mut uncached = DpeInstance::new(&mut env, SUPPORT | Support::EXTEND_TCI).unwrap();
 
-        for i in 0..3 {
-            DeriveContextCmd {
+        // Derive a leaf below the default context, keeping the default context around.
+        let mut leaf_handles = vec![];
+        for dpe in [&mut dpe, &mut uncached] {
+            match (DeriveContextCmd {
                 handle: ContextHandle::default(),
-                data: [i; DPE_PROFILE.get_hash_size()],
-                flags: DeriveContextFlags::MAKE_DEFAULT,
-                tci_type: i as u32,
+                data: [1; DPE_PROFILE.get_hash_size()],
+                flags: DeriveContextFlags::RETAIN_PARENT_CONTEXT,
+                tci_type: 1,
                 target_locality: 0,
+            })
+            .execute(dpe, &mut env, TEST_LOCALITIES[0])
+            .unwrap()
+            {
+                Response::DeriveContext(resp) => leaf_handles.push(resp.handle),
+                _ => panic!("Incorrect response type"),
             }
-            .execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
+        }
+        let leaf_idx = dpe
+            .get_active_context_pos(&leaf_handles[0], TEST_LOCALITIES[0])
+            .unwrap();
+        let uncached_leaf_idx = uncached
+            .get_active_context_pos(&leaf_handles[1], TEST_LOCALITIES[0])
             .unwrap();
 
-            // Check the CDI changes each time.
-            let leaf_context_idx = dpe
-                .get_active_context_pos(&ContextHandle::default(), TEST_LOCALITIES[0])
-                .unwrap();
-            let digest = dpe
-                .compute_measurement_hash(&mut env, leaf_context_idx)
-                .unwrap();
-            let curr_cdi = env
-                .crypto
-                .derive_cdi(DPE_PROFILE.alg_len(), &digest, b"DPE")
-                .unwrap();
-            assert_ne!(last_cdi, curr_cdi);
+        // The first computation fills the cache and later ones reuse it.
+        assert!(!dpe.contexts[leaf_idx].measurement_cached());
+        let digest = dpe.compute_measurement_hash(&mut env, leaf_idx).unwrap();
+        assert!(dpe.contexts[leaf_idx].measurement_cached());
+        assert_eq!(digest.bytes(), dpe.contexts[leaf_idx].cached_measurement);
+        assert_eq!(
+            digest.bytes(),
+            dpe.compute_measurement_hash(&mut env, leaf_idx)
+                .unwrap()
+                .bytes()
+        );
+        assert_eq!(
+            digest.bytes(),
+            uncached
+                .compute_measurement_hash(&mut env, uncached_leaf_idx)
+                .unwrap()
+                .bytes()
+        );
+
+        // Extending the parent changes the leaf's measurements, so the cache is dropped.
+        for dpe in [&mut dpe, &mut uncached] {
+            ExtendTciCmd {
+                handle: ContextHandle::default(),
+                data: [2; DPE_PROFILE.get_hash_size()],
+            }
+            .execute(dpe, &mut env, TEST_LOCALITIES[0])
+            .unwrap();
+        }
+        assert!(dpe.contexts.iter().all(|c| !c.measurement_cached()));
+        let extended = dpe.compute_measurement_hash(&mut env, leaf_idx).unwrap();
+        assert_ne!(digest.bytes(), extended.bytes());
+        assert_eq!(
+            extended.bytes(),
+            uncached
+                .compute_measurement_hash(&mut env, uncached_leaf_idx)
+                .unwrap()
+                .bytes()
+        );
 
-            last_cdi = curr_cdi;
+        // So does a recursive derivation.
+        DeriveContextCmd {
+            handle: leaf_handles[0],
+            data: [3; DPE_PROFILE.get_hash_size()],
+            flags: DeriveContextFlags::RECURSIVE,
+            tci_type: 1,
+            target_locality: 0,
         }
+        .execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
+        .unwrap();
+        assert!(!dpe.contexts[leaf_idx].measurement_cached());
+        assert_ne!(
+            extended.bytes(),
+            dpe.compute_measurement_hash(&mut env, leaf_idx)
+                .unwrap()
+                .bytes()
+        );
+    }
 
-        let mut hasher = env.crypto.hash_initialize(DPE_PROFILE.alg_len()).unwrap();
-        let leaf_idx = dpe
+    #[test]
+    fn test_cdi_cache() {
+        CfiCounter::reset_for_test();
+        let mut env = DpeEnv::<TestTypes> {
+            crypto: OpensslCrypto::new(),
+            platform: DefaultPlatform,
+            arena: &mut [],
+        };
+        let mut dpe = DpeInstance::new(
+            &mut env,
+            SUPPORT | Support::MEASUREMENT_CACHE | Support::EXTEND_TCI,
+        )
+        .unwrap();
+        let idx = dpe
             .get_active_context_pos(&ContextHandle::default(), TEST_LOCALITIES[0])
             .unwrap();
 
-        for result in ChildToRootIter::new(leaf_idx, &dpe.contexts) {
-            let context = result.unwrap();
-            hasher.update(context.tci.as_bytes()).unwrap();
-        }
-
-        let digest = hasher.finish().unwrap();
-        let answer = env
+        // The first derivation fills the cache and later ones return the same CDI from it.
+        assert!(!dpe.contexts[idx].cdi_cached());
+        let (digest, cdi) = dpe.derive_cdi(&mut env, idx).unwrap();
+        assert!(dpe.contexts[idx].cdi_cached());
+        let expected = env
             .crypto
             .derive_cdi(DPE_PROFILE.alg_len(), &digest, b"DPE")
             .unwrap();
-        assert_eq!(answer, last_cdi);
+        assert_eq!(expected.bytes(), cdi.bytes());
+        assert_eq!(
+            expected.bytes(),
+            env.crypto.cached_cdi(idx).unwrap().bytes()
+        );
+        let (_, cached) = dpe.derive_cdi(&mut env, idx).unwrap();
+        assert_eq!(expected.bytes(), cached.bytes());
+
+        // A CDI is rederived after the measurements change.
+        ExtendTciCmd {
+            handle: ContextHandle::default(),
+            data: [2; DPE_PROFILE.get_hash_size()],
+        }
+        .execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
+        .unwrap();
+        assert!(!dpe.contexts[idx].cdi_cached());
+        assert!(env.crypto.cached_cdi(idx).is_err());
+        let (digest, extended) = dpe.derive_cdi(&mut env, idx).unwrap();
+        assert_ne!(expected.bytes(), extended.bytes());
+        assert_eq!(
+            env.crypto
+                .derive_cdi(DPE_PROFILE.alg_len(), &digest, b"DPE")
+                .unwrap()
+                .bytes(),
+            extended.bytes()
+        );
+
+        // Without the support flag nothing is cached.
+        let mut env = DpeEnv::<TestTypes> {
+            crypto: OpensslCrypto::new(),
+            platform: DefaultPlatform,
+            arena: &mut [],
+        };
+        let mut uncached = DpeInstance::new(&mut env, SUPPORT).unwrap();
+        uncached.derive_cdi(&mut env, idx).unwrap();
+        assert!(!uncached.contexts[idx].cdi_cached());
+        assert!(env.crypto.cached_cdi(idx).is_err());
     }
 
     #[test]
@@ -800,6 +2886,7 @@ pub mod tests {
         let mut env = DpeEnv::<TestTypes> {
             crypto: OpensslCrypto::new(),
             platform: DefaultPlatform,
+            arena: &mut [],
         };
         let mut dpe = DpeInstance::new(&mut env, SUPPORT | Support::INTERNAL_INFO).unwrap();
 
@@ -831,10 +2918,10 @@ pub mod tests {
         assert!(child_context.uses_internal_input_info());
         assert!(!parent_context.uses_internal_input_info());
 
+        let measured = measured_chain(&dpe, &mut env, child_context_idx);
         let mut hasher = env.crypto.hash_initialize(DPE_PROFILE.alg_len()).unwrap();
 
-        hasher.update(child_context.tci.as_bytes()).unwrap();
-        hasher.update(parent_context.tci.as_bytes()).unwrap();
+        hasher.update(&measured).unwrap();
         let mut internal_input_info = [0u8; INTERNAL_INPUT_INFO_SIZE];
         dpe.serialize_internal_input_info(&mut env.platform, &mut internal_input_info)
             .unwrap();
@@ -848,7 +2935,7 @@ pub mod tests {
             .crypto
             .derive_cdi(DPE_PROFILE.alg_len(), &digest, b"DPE")
             .unwrap();
-        assert_eq!(answer, cdi_with_internal_input_info);
+        assert_eq!(answer.bytes(), cdi_with_internal_input_info.bytes());
     }
 
     #[test]
@@ -857,6 +2944,7 @@ pub mod tests {
         let mut env = DpeEnv::<TestTypes> {
             crypto: OpensslCrypto::new(),
             platform: DefaultPlatform,
+            arena: &mut [],
         };
         let mut dpe = DpeInstance::new(&mut env, SUPPORT | Support::INTERNAL_DICE).unwrap();
 
@@ -888,10 +2976,10 @@ pub mod tests {
         assert!(child_context.uses_internal_input_dice());
         assert!(!parent_context.uses_internal_input_dice());
 
+        let measured = measured_chain(&dpe, &mut env, child_context_idx);
         let mut hasher = env.crypto.hash_initialize(DPE_PROFILE.alg_len()).unwrap();
 
-        hasher.update(child_context.tci.as_bytes()).unwrap();
-        hasher.update(parent_context.tci.as_bytes()).unwrap();
+        hasher.update(&measured).unwrap();
         hasher
             .update(&TEST_CERT_CHAIN[..TEST_CERT_CHAIN.len()])
             .unwrap();
@@ -901,7 +2989,7 @@ pub mod tests {
             .crypto
             .derive_cdi(DPE_PROFILE.alg_len(), &digest, b"DPE")
             .unwrap();
-        assert_eq!(answer, cdi_with_internal_input_dice)
+        assert_eq!(answer.bytes(), cdi_with_internal_input_dice.bytes())
     }
 
     #[test]
@@ -910,6 +2998,7 @@ pub mod tests {
         let mut env = DpeEnv::<TestTypes> {
             crypto: OpensslCrypto::new(),
             platform: DefaultPlatform,
+            arena: &mut [],
         };
         let tci_type = 0xdeadbeef_u32;
         let auto_init_measurement = [0x1; DPE_PROFILE.get_hash_size()];
@@ -935,4 +3024,351 @@ pub mod tests {
             Err(DpeErrorCode::ArgumentNotSupported)
         );
     }
+
+    const BOOT_TCI_TYPE: u32 = u32::from_be_bytes(*b"BOOT");
+    const BOOT_MEASUREMENT: [u8; DPE_PROFILE.get_tci_size()] = [0x5A; DPE_PROFILE.get_tci_size()];
+
+    /// Platform that measures the firmware it boots, or fails to if the flag
+    /// is set.
+    struct MeasuredBootPlatform(bool);
+
+    impl Platform for MeasuredBootPlatform {
+        fn get_certificate_chain(
+            &mut self,
+            offset: u32,
+            size: u32,
+            out: &mut [u8; MAX_CHUNK_SIZE],
+        ) -> Result<u32, PlatformError> {
+            DefaultPlatform.get_certificate_chain(offset, size, out)
+        }
+
+        fn get_issuer_name(
+            &mut self,
+            out: &mut [u8; MAX_ISSUER_NAME_SIZE],
+        ) -> Result<usize, PlatformError> {
+            DefaultPlatform.get_issuer_name(out)
+        }
+
+        fn get_signer_identifier(&mut self) -> Result<SignerIdentifier, PlatformError> {
+            DefaultPlatform.get_signer_identifier()
+        }
+
+        fn get_issuer_key_identifier(
+            &mut self,
+            out: &mut [u8; MAX_KEY_IDENTIFIER_SIZE],
+        ) -> Result<(), PlatformError> {
+            DefaultPlatform.get_issuer_key_identifier(out)
+        }
+
+        fn get_ueid(&mut self, out: &mut [u8; MAX_UEID_SIZE]) -> Result<usize, PlatformError> {
+            DefaultPlatform.get_ueid(out)
+        }
+
+        fn get_vendor_id(&mut self) -> Result<u32, PlatformError> {
+            DefaultPlatform.get_vendor_id()
+        }
+
+        fn get_vendor_sku(&mut self) -> Result<u32, PlatformError> {
+            DefaultPlatform.get_vendor_sku()
+        }
+
+        fn get_auto_init_locality(&mut self) -> Result<u32, PlatformError> {
+            DefaultPlatform.get_auto_init_locality()
+        }
+
+        fn write_str(&mut self, str: &str) -> Result<(), PlatformError> {
+            DefaultPlatform.write_str(str)
+        }
+
+        fn get_cert_validity(&mut self) -> Result<CertValidity, PlatformError> {
+            DefaultPlatform.get_cert_validity()
+        }
+
+        fn get_boot_measurement(&mut self, out: &mut [u8]) -> Result<u32, PlatformError> {
+            if self.0 {
+                return Err(PlatformError::BootMeasurementError(7));
+            }
+            out.copy_from_slice(&BOOT_MEASUREMENT);
+            Ok(BOOT_TCI_TYPE)
+        }
+    }
+
+    struct MeasuredBootTypes;
+    impl DpeTypes for MeasuredBootTypes {
+        type Crypto<'a> = OpensslCrypto;
+        type Platform<'a> = MeasuredBootPlatform;
+    }
+
+    #[test]
+    fn test_auto_init_boot_measurement() {
+        CfiCounter::reset_for_test();
+        let mut env = DpeEnv::<MeasuredBootTypes> {
+            crypto: OpensslCrypto::new(),
+            platform: MeasuredBootPlatform(false),
+            arena: &mut [],
+        };
+        let dpe = DpeInstance::new(&mut env, SUPPORT).unwrap();
+
+        let idx = dpe
+            .get_active_context_pos(&ContextHandle::default(), AUTO_INIT_LOCALITY)
+            .unwrap();
+        let tci = &dpe.contexts[idx].tci;
+        assert_eq!(tci.tci_type, BOOT_TCI_TYPE);
+        assert_eq!(tci.tci_current.0, BOOT_MEASUREMENT);
+        let mut hasher = env.crypto.hash_initialize(DPE_PROFILE.alg_len()).unwrap();
+        hasher.update(&[0; DPE_PROFILE.get_tci_size()]).unwrap();
+        hasher.update(&BOOT_MEASUREMENT).unwrap();
+        assert_eq!(tci.tci_cumulative.0, hasher.finish().unwrap().bytes());
+
+        // A platform that fails to measure its firmware fails to boot.
+        env.platform = MeasuredBootPlatform(true);
+        assert_eq!(
+            DpeInstance::new(&mut env, SUPPORT).err(),
+            Some(DpeErrorCode::Platform(PlatformError::BootMeasurementError(
+                7
+            )))
+        );
+
+        // Platforms without a boot measurement leave the context unmeasured.
+        let mut env = DpeEnv::<TestTypes> {
+            crypto: OpensslCrypto::new(),
+            platform: DefaultPlatform,
+            arena: &mut [],
+        };
+        let dpe = DpeInstance::new(&mut env, SUPPORT).unwrap();
+        let idx = dpe
+            .get_active_context_pos(&ContextHandle::default(), AUTO_INIT_LOCALITY)
+            .unwrap();
+        assert_eq!(dpe.contexts[idx].tci.tci_type, 0);
+        assert_eq!(dpe.contexts[idx].tci.tci_current, TciMeasurement::default());
+    }
+
+    #[test]
+    fn test_self_test() {
+        CfiCounter::reset_for_test();
+        let mut env = DpeEnv::<TestTypes> {
+            crypto: OpensslCrypto::new(),
+            platform: DefaultPlatform,
+            arena: &mut [],
+        };
+
+        let mut dpe = DpeInstance::new(&mut env, Support::AUTO_INIT | Support::X509).unwrap();
+        dpe.self_test(&mut env, TEST_LOCALITIES[0]).unwrap();
+
+        // The certificate it checked is the one left for GetCertificateChunk.
+        let cert = &dpe.pending_cert.cert[..dpe.pending_cert.size as usize];
+        let (_, parsed) = x509_parser::parse_x509_certificate(cert).unwrap();
+        assert_eq!(
+            parsed.tbs_certificate.as_ref(),
+            EcdsaCertificate::parse(cert).unwrap().tbs
+        );
+
+        // There is no default context in the other locality.
+        assert_eq!(
+            dpe.self_test(&mut env, TEST_LOCALITIES[1]),
+            Err(DpeErrorCode::InvalidLocality)
+        );
+
+        // X.509 has to be supported.
+        let mut dpe = DpeInstance::new(&mut env, Support::AUTO_INIT).unwrap();
+        assert_eq!(
+            dpe.self_test(&mut env, TEST_LOCALITIES[0]),
+            Err(DpeErrorCode::ArgumentNotSupported)
+        );
+    }
+
+    #[test]
+    fn test_serialize_round_trip() {
+        CfiCounter::reset_for_test();
+        let mut env = DpeEnv::<TestTypes> {
+            crypto: OpensslCrypto::new(),
+            platform: DefaultPlatform,
+            arena: &mut [],
+        };
+        let mut dpe = DpeInstance::new(&mut env, SUPPORT).unwrap();
+        DeriveContextCmd {
+            handle: ContextHandle::default(),
+            data: [1; DPE_PROFILE.get_hash_size()],
+            flags: DeriveContextFlags::MAKE_DEFAULT,
+            tci_type: 7,
+            target_locality: 0,
+        }
+        .execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
+        .unwrap();
+
+        let mut state = [0u8; DpeInstance::SERIALIZED_SIZE];
+        assert_eq!(
+            DpeInstance::SERIALIZED_SIZE,
+            dpe.serialize(&mut env, &mut state).unwrap()
+        );
+        let restored = DpeInstance::deserialize(&mut env, &state).unwrap();
+        assert_eq!(dpe.as_bytes(), restored.as_bytes());
+
+        // Output buffer too small.
+        assert_eq!(
+            Err(DpeErrorCode::InvalidArgument),
+            dpe.serialize(&mut env, &mut state[..DpeInstance::SERIALIZED_SIZE - 1])
+        );
+
+        // Corrupt the state.
+        let mut corrupted = state;
+        corrupted[DpeInstance::SERIALIZED_SIZE - 1] ^= 1;
+        assert!(DpeInstance::deserialize(&mut env, &corrupted).is_err());
+
+        // Corrupt the IV, which follows the five u32 header fields.
+        let mut corrupted = state;
+        corrupted[5 * size_of::<u32>()] ^= 1;
+        assert!(DpeInstance::deserialize(&mut env, &corrupted).is_err());
+
+        // A forged blob with a recomputed header fails to authenticate
+        // without the platform's key.
+        let mut forged = state;
+        let mut hdr = PersistedStateHdr::read_from_prefix(&forged[..]).unwrap();
+        let key = AeadKey([0; AEAD_KEY_SIZE]);
+        let (hdr_bytes, state_bytes) = forged.split_at_mut(size_of::<PersistedStateHdr>());
+        state_bytes.copy_from_slice(dpe.as_bytes());
+        hdr.tag = env
+            .crypto
+            .aead_encrypt(&key, &hdr.iv, hdr.aad().as_bytes(), state_bytes)
+            .unwrap();
+        hdr_bytes.copy_from_slice(hdr.as_bytes());
+        assert!(DpeInstance::deserialize(&mut env, &forged).is_err());
+
+        // Wrong version.
+        let mut corrupted = state;
+        corrupted[4] ^= 1;
+        assert!(DpeInstance::deserialize(&mut env, &corrupted).is_err());
+
+        // Truncated.
+        assert!(
+            DpeInstance::deserialize(&mut env, &state[..DpeInstance::SERIALIZED_SIZE - 1]).is_err()
+        );
+
+        // Secrets such as the handle MAC key are not stored in the clear but
+        // come back on restore.
+        let dpe = DpeInstance::new(&mut env, SUPPORT | Support::HANDLE_MAC).unwrap();
+        dpe.serialize(&mut env, &mut state).unwrap();
+        assert!(!state
+            .windows(DpeInstance::HANDLE_MAC_KEY_SIZE)
+            .any(|w| w == dpe.handle_mac_key));
+        let restored = DpeInstance::deserialize(&mut env, &state).unwrap();
+        assert_eq!(dpe.handle_mac_key, restored.handle_mac_key);
+    }
+
+    /// Wraps `cmd` in an authenticated command message with the simulator's
+    /// key for `locality`.
+    fn auth_cmd(locality: u32, counter: u32, cmd: &[u8]) -> Vec<u8> {
+        let hdr = AuthCmdHdr {
+            magic: AuthCmdHdr::AUTH_CMD_MAGIC,
+            counter,
+        };
+        let mut msg = hdr.to_le().as_bytes().to_vec();
+        msg.extend_from_slice(cmd);
+        let mac = OpensslCrypto::new()
+            .hmac(
+                DPE_PROFILE.alg_len(),
+                &platform::default::test_command_auth_key(locality),
+                &msg,
+            )
+            .unwrap();
+        msg.extend_from_slice(mac.bytes());
+        msg
+    }
+
+    #[test]
+    fn test_command_auth() {
+        CfiCounter::reset_for_test();
+        let mut env = DpeEnv::<TestTypes> {
+            crypto: OpensslCrypto::new(),
+            platform: DefaultPlatform,
+            arena: &mut [],
+        };
+        let mut dpe = DpeInstance::new(&mut env, SUPPORT | Support::COMMAND_AUTH).unwrap();
+
+        let mut init_sim = CommandHdr::new_for_test(Command::INITIALIZE_CONTEXT)
+            .as_bytes()
+            .to_vec();
+        init_sim.extend(InitCtxCmd::new_simulation().as_bytes());
+        let error = |err_code| Ok(Response::Error(ResponseHdr::new(err_code)));
+
+        // GetProfile is the only command that may be sent in the clear.
+        let get_profile = CommandHdr::new_for_test(Command::GET_PROFILE);
+        assert!(matches!(
+            dpe.execute_serialized_command(&mut env, TEST_LOCALITIES[1], get_profile.as_bytes()),
+            Ok(Response::GetProfile(_))
+        ));
+        assert_eq!(
+            error(DpeErrorCode::CommandNotAllowed),
+            dpe.execute_serialized_command(&mut env, TEST_LOCALITIES[1], &init_sim)
+        );
+
+        let msg = auth_cmd(TEST_LOCALITIES[1], 0, &init_sim);
+        assert!(matches!(
+            dpe.execute_serialized_command(&mut env, TEST_LOCALITIES[1], &msg),
+            Ok(Response::InitCtx(_))
+        ));
+
+        // The same message can't be replayed.
+        assert_eq!(
+            error(DpeErrorCode::AuthenticationFailed),
+            dpe.execute_serialized_command(&mut env, TEST_LOCALITIES[1], &msg)
+        );
+
+        // Nor sent from another locality, whose key differs.
+        assert_eq!(
+            error(DpeErrorCode::AuthenticationFailed),
+            dpe.execute_serialized_command(&mut env, TEST_LOCALITIES[0], &msg)
+        );
+
+        // A tampered command is rejected without consuming the counter.
+        let mut msg = auth_cmd(TEST_LOCALITIES[1], 1, &init_sim);
+        msg[size_of::<AuthCmdHdr>() + 4] ^= 1;
+        assert_eq!(
+            error(DpeErrorCode::AuthenticationFailed),
+            dpe.execute_serialized_command(&mut env, TEST_LOCALITIES[1], &msg)
+        );
+        let msg = auth_cmd(TEST_LOCALITIES[1], 1, &init_sim);
+        assert!(matches!(
+            dpe.execute_serialized_command(&mut env, TEST_LOCALITIES[1], &msg),
+            Ok(Response::InitCtx(_))
+        ));
+
+        // Each locality has its own counter.
+        let msg = auth_cmd(TEST_LOCALITIES[0], 0, get_profile.as_bytes());
+        assert!(matches!(
+            dpe.execute_serialized_command(&mut env, TEST_LOCALITIES[0], &msg),
+            Ok(Response::GetProfile(_))
+        ));
+
+        // Too short to hold a command and a MAC.
+        let msg = auth_cmd(TEST_LOCALITIES[1], 2, &[]);
+        assert_eq!(
+            error(DpeErrorCode::InvalidCommand),
+            dpe.execute_serialized_command(&mut env, TEST_LOCALITIES[1], &msg[..msg.len() - 1])
+        );
+
+        // Every locality the platform can list gets its own counter.
+        let mut dpe = DpeInstance::new(&mut env, SUPPORT | Support::COMMAND_AUTH).unwrap();
+        for locality in 0..MAX_AUTH_LOCALITIES as u32 {
+            let msg = auth_cmd(locality, 0, get_profile.as_bytes());
+            assert!(matches!(
+                dpe.execute_serialized_command(&mut env, locality, &msg),
+                Ok(Response::GetProfile(_))
+            ));
+        }
+        let locality = MAX_AUTH_LOCALITIES as u32;
+        let msg = auth_cmd(locality, 0, get_profile.as_bytes());
+        assert_eq!(
+            error(DpeErrorCode::InvalidLocality),
+            dpe.execute_serialized_command(&mut env, locality, &msg)
+        );
+
+        // Without support, authenticated commands are not recognized.
+        let mut dpe = DpeInstance::new(&mut env, SUPPORT).unwrap();
+        let msg = auth_cmd(TEST_LOCALITIES[1], 0, &init_sim);
+        assert_eq!(
+            error(DpeErrorCode::InvalidCommand),
+            dpe.execute_serialized_command(&mut env, TEST_LOCALITIES[1], &msg)
+        );
+    }
 }
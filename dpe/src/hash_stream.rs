@@ -0,0 +1,56 @@
+/*++
+Licensed under the Apache-2.0 license.
+
+Abstract:
+    State of hashes that span several commands.
+--*/
+use crate::U8Bool;
+use crypto::{HashState, HASH_STATE_SIZE};
+use zerocopy::{AsBytes, FromBytes};
+use zeroize::Zeroize;
+
+pub const MAX_HASH_STREAMS: usize = 4;
+
+/// Most streams a single locality may have open at once, so that no locality
+/// can take every stream slot.
+pub const MAX_HASH_STREAMS_PER_LOCALITY: usize = MAX_HASH_STREAMS / 2;
+
+const _: () = assert!(MAX_HASH_STREAMS_PER_LOCALITY > 0);
+const _: () = assert!(MAX_HASH_STREAMS_PER_LOCALITY <= MAX_HASH_STREAMS);
+
+#[repr(C, align(4))]
+#[derive(AsBytes, FromBytes, Copy, Clone, Zeroize)]
+pub struct HashStream {
+    /// Running hash saved by the crypto implementation.
+    pub(crate) state: [u8; HASH_STATE_SIZE],
+    /// Locality that started the hash. Other localities can't use it.
+    pub(crate) locality: u32,
+    pub(crate) active: U8Bool,
+    reserved: [u8; 3],
+}
+
+impl Default for HashStream {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl HashStream {
+    pub const fn new() -> HashStream {
+        HashStream {
+            state: [0; HASH_STATE_SIZE],
+            locality: 0,
+            active: U8Bool::new(false),
+            reserved: [0; 3],
+        }
+    }
+
+    pub fn active(&self) -> bool {
+        self.active.get()
+    }
+
+    /// Returns a copy of the running hash.
+    pub(crate) fn hash_state(&self) -> HashState {
+        HashState(self.state)
+    }
+}
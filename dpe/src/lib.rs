@@ -4,31 +4,65 @@ Licensed under the Apache-2.0 license.
 Abstract:
     DPE Library Crate.
 --*/
-#![cfg_attr(not(test), no_std)]
+#![cfg_attr(not(any(test, feature = "std")), no_std)]
 
 pub use dpe_instance::DpeInstance;
 use zeroize::Zeroize;
 
+#[macro_use]
+mod trace;
+#[macro_use]
+pub mod wire;
+#[macro_use]
+pub mod arena;
+
+pub mod command_auth;
 pub mod commands;
 pub mod context;
 pub mod dpe_instance;
+pub mod hash_stream;
+pub mod locality;
+pub mod pending;
 pub mod response;
+#[cfg(feature = "serde")]
+mod serde_hex;
+pub mod session;
+pub mod spdm;
 pub mod support;
+pub mod sync;
 pub mod validation;
 
 use core::mem::size_of;
-use response::GetProfileResp;
+use response::{DpeErrorCode, GetProfileResp};
+pub mod cbor;
+pub mod cwt;
+pub mod der;
 pub mod tci;
 pub mod x509;
 
 use zerocopy::{AsBytes, FromBytes};
 
 const MAX_CERT_SIZE: usize = 2048;
+/// Largest certificate or CSR CertifyKey can produce. Anything past the first
+/// `MAX_CERT_SIZE` bytes is read back with GetCertificateChunk.
+pub const MAX_CERTIFY_KEY_SIZE: usize = 4 * MAX_CERT_SIZE;
+/// Largest amount of data Seal encrypts and Unseal returns.
+pub const MAX_SEAL_DATA_SIZE: usize = 1024;
+/// Size of the header at the start of sealed data: the unseal policy as a
+/// little-endian u32 followed by the TCI it expects.
+pub const SEAL_HEADER_SIZE: usize = size_of::<u32>() + DPE_PROFILE.get_tci_size();
+/// Largest sealed data: the header and the AES-256-GCM IV and tag followed by
+/// the ciphertext.
+pub const MAX_SEALED_SIZE: usize =
+    SEAL_HEADER_SIZE + crypto::AEAD_IV_SIZE + crypto::AEAD_TAG_SIZE + MAX_SEAL_DATA_SIZE;
 #[cfg(not(feature = "arbitrary_max_handles"))]
 pub const MAX_HANDLES: usize = 24;
 #[cfg(feature = "arbitrary_max_handles")]
 include!(concat!(env!("OUT_DIR"), "/arbitrary_max_handles.rs"));
 
+// Each context tracks its children in a u64 bitmap.
+const _: () = assert!(MAX_HANDLES > 0 && MAX_HANDLES <= u64::BITS as usize);
+
 const CURRENT_PROFILE_MAJOR_VERSION: u16 = 0;
 const CURRENT_PROFILE_MINOR_VERSION: u16 = 10;
 
@@ -59,9 +93,28 @@ impl From<bool> for U8Bool {
     }
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum DpeProfile {
     P256Sha256 = 1,
     P384Sha384 = 2,
+    Mldsa87Sha384 = 3,
+    P521Sha512 = 4,
+    Ed25519Sha512 = 5,
+}
+
+impl TryFrom<u32> for DpeProfile {
+    type Error = DpeErrorCode;
+
+    fn try_from(profile: u32) -> Result<Self, Self::Error> {
+        match profile {
+            1 => Ok(DpeProfile::P256Sha256),
+            2 => Ok(DpeProfile::P384Sha384),
+            3 => Ok(DpeProfile::Mldsa87Sha384),
+            4 => Ok(DpeProfile::P521Sha512),
+            5 => Ok(DpeProfile::Ed25519Sha512),
+            _ => Err(DpeErrorCode::InvalidArgument),
+        }
+    }
 }
 
 impl DpeProfile {
@@ -69,10 +122,15 @@ impl DpeProfile {
         match self {
             DpeProfile::P256Sha256 => 32,
             DpeProfile::P384Sha384 => 48,
+            DpeProfile::Mldsa87Sha384 => 48,
+            DpeProfile::P521Sha512 => 64,
+            DpeProfile::Ed25519Sha512 => 64,
         }
     }
+    /// P-521 integers are two bytes wider than the SHA-512 digest. The other
+    /// profiles use the same size for both.
     pub const fn get_ecc_int_size(&self) -> usize {
-        self.get_tci_size()
+        self.alg_len().ecc_int_size()
     }
     pub const fn get_hash_size(&self) -> usize {
         self.get_tci_size()
@@ -81,8 +139,27 @@ impl DpeProfile {
         match self {
             DpeProfile::P256Sha256 => crypto::AlgLen::Bit256,
             DpeProfile::P384Sha384 => crypto::AlgLen::Bit384,
+            DpeProfile::Mldsa87Sha384 => crypto::AlgLen::Bit384,
+            DpeProfile::P521Sha512 => crypto::AlgLen::Bit512,
+            DpeProfile::Ed25519Sha512 => crypto::AlgLen::Bit512,
         }
     }
+    /// Whether derived keys in this profile use a post-quantum signature scheme.
+    pub const fn is_pq(&self) -> bool {
+        matches!(self, DpeProfile::Mldsa87Sha384)
+    }
+    /// Whether derived keys in this profile are Ed25519 keys.
+    pub const fn is_eddsa(&self) -> bool {
+        matches!(self, DpeProfile::Ed25519Sha512)
+    }
+    /// Whether a `DpeInstance` of this build can run this profile.
+    ///
+    /// Command and response structures are laid out for `DPE_PROFILE`, so
+    /// that is the only profile which can be served until they are sized for
+    /// the largest profile.
+    pub const fn is_supported(&self) -> bool {
+        *self as u32 == DPE_PROFILE as u32
+    }
 }
 
 #[cfg(feature = "dpe_profile_p256_sha256")]
@@ -91,6 +168,15 @@ pub const DPE_PROFILE: DpeProfile = DpeProfile::P256Sha256;
 #[cfg(feature = "dpe_profile_p384_sha384")]
 pub const DPE_PROFILE: DpeProfile = DpeProfile::P384Sha384;
 
+#[cfg(feature = "dpe_profile_mldsa87_sha384")]
+pub const DPE_PROFILE: DpeProfile = DpeProfile::Mldsa87Sha384;
+
+#[cfg(feature = "dpe_profile_p521_sha512")]
+pub const DPE_PROFILE: DpeProfile = DpeProfile::P521Sha512;
+
+#[cfg(feature = "dpe_profile_ed25519_sha512")]
+pub const DPE_PROFILE: DpeProfile = DpeProfile::Ed25519Sha512;
+
 // Recursive macro that does a union of all the flags passed to it. This is
 // const and looks about as nice as using the | operator.
 #[macro_export]
@@ -0,0 +1,358 @@
+/*++
+Licensed under the Apache-2.0 license.
+
+Abstract:
+    Table of the localities that may send commands to a DPE instance.
+--*/
+use crate::{response::DpeErrorCode, U8Bool};
+use platform::{LocalityDescriptor, Platform, PlatformError, MAX_LOCALITIES};
+use zerocopy::{AsBytes, FromBytes};
+use zeroize::Zeroize;
+
+/// Localities read from the platform when the instance is created.
+///
+/// When the platform has no table, every locality is accepted with every
+/// flag, as before locality tables were introduced.
+#[repr(C, align(4))]
+#[derive(AsBytes, FromBytes, Copy, Clone, Zeroize)]
+pub struct LocalityTable {
+    localities: [u32; MAX_LOCALITIES],
+    flags: [u32; MAX_LOCALITIES],
+    count: u32,
+    enforced: U8Bool,
+    reserved: [u8; 3],
+}
+
+impl Default for LocalityTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LocalityTable {
+    /// Table that accepts every locality.
+    pub const fn new() -> LocalityTable {
+        LocalityTable {
+            localities: [0; MAX_LOCALITIES],
+            flags: [0; MAX_LOCALITIES],
+            count: 0,
+            enforced: U8Bool::new(false),
+            reserved: [0; 3],
+        }
+    }
+
+    /// Reads the locality table of `platform`.
+    pub fn from_platform(platform: &mut impl Platform) -> Result<LocalityTable, DpeErrorCode> {
+        let mut descriptors = [LocalityDescriptor::default(); MAX_LOCALITIES];
+        let count = match platform.get_locality_table(&mut descriptors) {
+            Ok(count) => count,
+            Err(PlatformError::NotImplemented) => return Ok(Self::new()),
+            Err(e) => return Err(e.into()),
+        };
+        let descriptors = descriptors
+            .get(..count)
+            .ok_or(DpeErrorCode::Platform(PlatformError::LocalityTableError(0)))?;
+
+        let mut table = Self::new();
+        table.enforced = true.into();
+        for descriptor in descriptors {
+            if table.lookup(descriptor.locality).is_some() {
+                return Err(DpeErrorCode::Platform(PlatformError::LocalityTableError(
+                    descriptor.locality,
+                )));
+            }
+            let i = table.count as usize;
+            table.localities[i] = descriptor.locality;
+            table.flags[i] = descriptor.flags;
+            table.count += 1;
+        }
+        Ok(table)
+    }
+
+    /// Returns the flags of `locality`, or `None` if it is not in the table.
+    fn lookup(&self, locality: u32) -> Option<u32> {
+        let count = self.count as usize;
+        self.localities[..count]
+            .iter()
+            .zip(&self.flags[..count])
+            .find(|(l, _)| **l == locality)
+            .map(|(_, flags)| *flags)
+    }
+
+    /// Fails with `UnknownLocality` if `locality` may not send commands.
+    pub fn check_known(&self, locality: u32) -> Result<(), DpeErrorCode> {
+        self.check(locality, 0)
+    }
+
+    /// Fails with `UnknownLocality` if `locality` may not send commands and
+    /// with `InvalidLocality` if it lacks any of the `LocalityDescriptor`
+    /// flags in `required`.
+    pub fn check(&self, locality: u32, required: u32) -> Result<(), DpeErrorCode> {
+        if !self.enforced.get() {
+            return Ok(());
+        }
+        let flags = self.lookup(locality).ok_or(DpeErrorCode::UnknownLocality)?;
+        if flags & required != required {
+            return Err(DpeErrorCode::InvalidLocality);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        commands::{
+            Command, CommandExecution, CommandHdr, DeriveContextCmd, DeriveContextFlags, InitCtxCmd,
+        },
+        context::ContextHandle,
+        dpe_instance::{DpeEnv, DpeTypes},
+        response::{Response, ResponseHdr},
+        support::Support,
+        DpeInstance, DPE_PROFILE,
+    };
+    use caliptra_cfi_lib_git::CfiCounter;
+    use crypto::OpensslCrypto;
+    use platform::{
+        default::{DefaultPlatform, AUTO_INIT_LOCALITY},
+        CertValidity, SignerIdentifier, MAX_CHUNK_SIZE, MAX_ISSUER_NAME_SIZE,
+        MAX_KEY_IDENTIFIER_SIZE, MAX_UEID_SIZE,
+    };
+
+    const SIMULATION_LOCALITY: u32 = 1;
+    const PLAIN_LOCALITY: u32 = 2;
+    const UNKNOWN_LOCALITY: u32 = 3;
+
+    /// `DefaultPlatform` with a locality table.
+    struct TablePlatform(&'static [LocalityDescriptor]);
+
+    impl Platform for TablePlatform {
+        fn get_certificate_chain(
+            &mut self,
+            offset: u32,
+            size: u32,
+            out: &mut [u8; MAX_CHUNK_SIZE],
+        ) -> Result<u32, PlatformError> {
+            DefaultPlatform.get_certificate_chain(offset, size, out)
+        }
+
+        fn get_issuer_name(
+            &mut self,
+            out: &mut [u8; MAX_ISSUER_NAME_SIZE],
+        ) -> Result<usize, PlatformError> {
+            DefaultPlatform.get_issuer_name(out)
+        }
+
+        fn get_signer_identifier(&mut self) -> Result<SignerIdentifier, PlatformError> {
+            DefaultPlatform.get_signer_identifier()
+        }
+
+        fn get_issuer_key_identifier(
+            &mut self,
+            out: &mut [u8; MAX_KEY_IDENTIFIER_SIZE],
+        ) -> Result<(), PlatformError> {
+            DefaultPlatform.get_issuer_key_identifier(out)
+        }
+
+        fn get_ueid(&mut self, out: &mut [u8; MAX_UEID_SIZE]) -> Result<usize, PlatformError> {
+            DefaultPlatform.get_ueid(out)
+        }
+
+        fn get_vendor_id(&mut self) -> Result<u32, PlatformError> {
+            DefaultPlatform.get_vendor_id()
+        }
+
+        fn get_vendor_sku(&mut self) -> Result<u32, PlatformError> {
+            DefaultPlatform.get_vendor_sku()
+        }
+
+        fn get_auto_init_locality(&mut self) -> Result<u32, PlatformError> {
+            DefaultPlatform.get_auto_init_locality()
+        }
+
+        fn get_locality_table(
+            &mut self,
+            out: &mut [LocalityDescriptor; MAX_LOCALITIES],
+        ) -> Result<usize, PlatformError> {
+            out[..self.0.len()].copy_from_slice(self.0);
+            Ok(self.0.len())
+        }
+
+        fn write_str(&mut self, str: &str) -> Result<(), PlatformError> {
+            DefaultPlatform.write_str(str)
+        }
+
+        fn get_cert_validity(&mut self) -> Result<CertValidity, PlatformError> {
+            DefaultPlatform.get_cert_validity()
+        }
+    }
+
+    const TABLE: &[LocalityDescriptor] = &[
+        LocalityDescriptor {
+            locality: AUTO_INIT_LOCALITY,
+            flags: LocalityDescriptor::AUTO_INIT,
+        },
+        LocalityDescriptor {
+            locality: SIMULATION_LOCALITY,
+            flags: LocalityDescriptor::SIMULATION,
+        },
+        LocalityDescriptor {
+            locality: PLAIN_LOCALITY,
+            flags: 0,
+        },
+    ];
+
+    struct TableTypes;
+
+    impl DpeTypes for TableTypes {
+        type Crypto<'a> = OpensslCrypto;
+        type Platform<'a> = TablePlatform;
+    }
+
+    fn env(table: &'static [LocalityDescriptor]) -> DpeEnv<'static, TableTypes> {
+        DpeEnv {
+            crypto: OpensslCrypto::new(),
+            platform: TablePlatform(table),
+            arena: &mut [],
+        }
+    }
+
+    #[test]
+    fn test_without_table() {
+        let table = LocalityTable::from_platform(&mut DefaultPlatform).unwrap();
+        assert_eq!(table.check(u32::MAX, u32::MAX), Ok(()));
+    }
+
+    #[test]
+    fn test_check() {
+        let table = LocalityTable::from_platform(&mut TablePlatform(TABLE)).unwrap();
+        assert_eq!(
+            table.check(AUTO_INIT_LOCALITY, LocalityDescriptor::AUTO_INIT),
+            Ok(())
+        );
+        assert_eq!(
+            table.check(SIMULATION_LOCALITY, LocalityDescriptor::AUTO_INIT),
+            Err(DpeErrorCode::InvalidLocality)
+        );
+        assert_eq!(table.check_known(PLAIN_LOCALITY), Ok(()));
+        assert_eq!(
+            table.check_known(UNKNOWN_LOCALITY),
+            Err(DpeErrorCode::UnknownLocality)
+        );
+    }
+
+    #[test]
+    fn test_invalid_tables() {
+        const DUPLICATE: &[LocalityDescriptor] = &[
+            LocalityDescriptor {
+                locality: 5,
+                flags: 0,
+            },
+            LocalityDescriptor {
+                locality: 5,
+                flags: LocalityDescriptor::SIMULATION,
+            },
+        ];
+        assert_eq!(
+            LocalityTable::from_platform(&mut TablePlatform(DUPLICATE)).err(),
+            Some(DpeErrorCode::Platform(PlatformError::LocalityTableError(5)))
+        );
+
+        // Every slot of the table can be used.
+        const FULL: &[LocalityDescriptor] = &{
+            let mut table = [LocalityDescriptor {
+                locality: 0,
+                flags: 0,
+            }; MAX_LOCALITIES];
+            let mut i = 0;
+            while i < MAX_LOCALITIES {
+                table[i].locality = i as u32;
+                i += 1;
+            }
+            table
+        };
+        let table = LocalityTable::from_platform(&mut TablePlatform(FULL)).unwrap();
+        assert_eq!(table.check_known(MAX_LOCALITIES as u32 - 1), Ok(()));
+        assert_eq!(
+            table.check_known(MAX_LOCALITIES as u32),
+            Err(DpeErrorCode::UnknownLocality)
+        );
+    }
+
+    #[test]
+    fn test_auto_init_locality() {
+        CfiCounter::reset_for_test();
+        const NO_AUTO_INIT: &[LocalityDescriptor] = &[LocalityDescriptor {
+            locality: AUTO_INIT_LOCALITY,
+            flags: 0,
+        }];
+        assert_eq!(
+            DpeInstance::new(&mut env(NO_AUTO_INIT), Support::AUTO_INIT).err(),
+            Some(DpeErrorCode::InvalidLocality)
+        );
+        assert_eq!(
+            DpeInstance::new(&mut env(&TABLE[1..]), Support::AUTO_INIT).err(),
+            Some(DpeErrorCode::UnknownLocality)
+        );
+        assert!(DpeInstance::new(&mut env(TABLE), Support::AUTO_INIT).is_ok());
+    }
+
+    #[test]
+    fn test_commands_from_unknown_locality() {
+        CfiCounter::reset_for_test();
+        let mut env = env(TABLE);
+        let mut dpe = DpeInstance::new(&mut env, Support::AUTO_INIT).unwrap();
+
+        let get_profile = CommandHdr::new_for_test(Command::GET_PROFILE);
+        assert_eq!(
+            Response::Error(ResponseHdr::new(DpeErrorCode::UnknownLocality)),
+            dpe.execute_serialized_command(&mut env, UNKNOWN_LOCALITY, get_profile.as_bytes())
+                .unwrap()
+        );
+        assert!(matches!(
+            dpe.execute_serialized_command(&mut env, PLAIN_LOCALITY, get_profile.as_bytes()),
+            Ok(Response::GetProfile(_))
+        ));
+    }
+
+    #[test]
+    fn test_simulation_locality() {
+        CfiCounter::reset_for_test();
+        let mut env = env(TABLE);
+        let mut dpe = DpeInstance::new(&mut env, Support::AUTO_INIT | Support::SIMULATION).unwrap();
+
+        let init = InitCtxCmd::new_simulation();
+        assert_eq!(
+            init.execute(&mut dpe, &mut env, PLAIN_LOCALITY).err(),
+            Some(DpeErrorCode::InvalidLocality)
+        );
+        assert!(init
+            .execute(&mut dpe, &mut env, SIMULATION_LOCALITY)
+            .is_ok());
+    }
+
+    #[test]
+    fn test_change_to_unknown_locality() {
+        CfiCounter::reset_for_test();
+        let mut env = env(TABLE);
+        let mut dpe = DpeInstance::new(&mut env, Support::AUTO_INIT).unwrap();
+
+        let derive = |target_locality| DeriveContextCmd {
+            handle: ContextHandle::default(),
+            data: [0; DPE_PROFILE.get_hash_size()],
+            flags: DeriveContextFlags::CHANGE_LOCALITY,
+            tci_type: 0,
+            target_locality,
+        };
+        assert_eq!(
+            derive(UNKNOWN_LOCALITY)
+                .execute(&mut dpe, &mut env, AUTO_INIT_LOCALITY)
+                .err(),
+            Some(DpeErrorCode::UnknownLocality)
+        );
+        assert!(derive(PLAIN_LOCALITY)
+            .execute(&mut dpe, &mut env, AUTO_INIT_LOCALITY)
+            .is_ok());
+    }
+}
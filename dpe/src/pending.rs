@@ -0,0 +1,111 @@
+/*++
+Licensed under the Apache-2.0 license.
+
+Abstract:
+    State of a command waiting on the crypto implementation.
+--*/
+use crate::{
+    response::{DpeErrorCode, Response},
+    U8Bool,
+};
+use zerocopy::{AsBytes, FromBytes};
+use zeroize::Zeroize;
+
+/// Identifies a command `DpeInstance::start_command` left pending.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct PendingToken(pub(crate) u32);
+
+/// Outcome of `DpeInstance::start_command` and `DpeInstance::poll_command`.
+#[cfg_attr(test, derive(PartialEq, Debug, Eq))]
+#[allow(clippy::large_enum_variant)]
+pub enum CommandStatus {
+    /// The command finished. The response is in wire byte order, like the
+    /// one `execute_serialized_command` returns.
+    Complete(Response),
+    /// The crypto implementation is still working on the command. Poll it
+    /// again with the token.
+    Pending(PendingToken),
+}
+
+/// Number of commands a pending command turns away with
+/// `DpeErrorCode::Busy` before it is abandoned, so that a caller who never
+/// polls can't hold up the DPE forever.
+pub const MAX_TURNED_AWAY: u16 = 16;
+
+/// Sign command waiting for the crypto implementation to finish its
+/// signature. While one is pending, every other command fails with
+/// `DpeErrorCode::Busy`, so the context it signs for can't change.
+#[repr(C, align(4))]
+#[derive(AsBytes, FromBytes, Copy, Clone, Zeroize)]
+pub struct PendingOp {
+    /// Random token value of the operation, so that other callers can't
+    /// guess it.
+    pub(crate) id: u32,
+    /// Locality that started the operation, the only one that may poll or
+    /// cancel it.
+    pub(crate) locality: u32,
+    /// Index of the context whose key signs.
+    pub(crate) context_idx: u8,
+    pub(crate) active: U8Bool,
+    /// Number of commands turned away while the operation was pending.
+    turned_away: u16,
+}
+
+impl Default for PendingOp {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PendingOp {
+    pub const fn new() -> PendingOp {
+        PendingOp {
+            id: 0,
+            locality: 0,
+            context_idx: 0,
+            active: U8Bool::new(false),
+            turned_away: 0,
+        }
+    }
+
+    pub fn active(&self) -> bool {
+        self.active.get()
+    }
+
+    /// Marks a command `locality` sent, signing for the context at `idx`, as
+    /// pending under the random token `id`.
+    pub(crate) fn start(&mut self, idx: usize, locality: u32, id: u32) {
+        self.id = id;
+        self.locality = locality;
+        self.context_idx = idx as u8;
+        self.active = true.into();
+        self.turned_away = 0;
+    }
+
+    /// Checks that `locality` may poll or cancel the command with `token`.
+    pub(crate) fn check_owner(
+        &self,
+        token: PendingToken,
+        locality: u32,
+    ) -> Result<(), DpeErrorCode> {
+        if !self.active() || self.token() != token {
+            return Err(DpeErrorCode::InvalidArgument);
+        }
+        if self.locality != locality {
+            return Err(DpeErrorCode::InvalidLocality);
+        }
+        Ok(())
+    }
+
+    /// Counts a command turned away while this one is pending. Returns true
+    /// once `MAX_TURNED_AWAY` have been, and the command should be abandoned.
+    pub(crate) fn turn_away(&mut self) -> bool {
+        self.turned_away = self.turned_away.saturating_add(1);
+        self.turned_away > MAX_TURNED_AWAY
+    }
+
+    /// Returns the token of the pending command.
+    pub(crate) fn token(&self) -> PendingToken {
+        PendingToken(self.id)
+    }
+}
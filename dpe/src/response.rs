@@ -5,14 +5,18 @@ Abstract:
     DPE reponses and serialization.
 --*/
 use crate::{
-    context::ContextHandle, validation::ValidationError, CURRENT_PROFILE_MAJOR_VERSION,
-    CURRENT_PROFILE_MINOR_VERSION, DPE_PROFILE, MAX_CERT_SIZE, MAX_HANDLES,
+    context::ContextHandle, session::SessionMsgHdr, support::Support, tci::TciMeasurement,
+    validation::ValidationError, wire::WireEndian, CURRENT_PROFILE_MAJOR_VERSION,
+    CURRENT_PROFILE_MINOR_VERSION, DPE_PROFILE, MAX_CERT_SIZE, MAX_HANDLES, MAX_SEALED_SIZE,
+    MAX_SEAL_DATA_SIZE,
 };
-use crypto::CryptoError;
+use core::{cmp::min, mem::size_of};
+use crypto::{CryptoError, AEAD_IV_SIZE, AEAD_TAG_SIZE};
 use platform::PlatformError;
 use zerocopy::AsBytes;
 
 #[cfg_attr(test, derive(PartialEq, Debug, Eq))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[allow(clippy::large_enum_variant)]
 pub enum Response {
     GetProfile(GetProfileResp),
@@ -23,6 +27,23 @@ pub enum Response {
     Sign(SignResp),
     DestroyCtx(ResponseHdr),
     GetCertificateChain(GetCertificateChainResp),
+    ExtendTci(NewHandleResp),
+    TagTci(NewHandleResp),
+    GetTaggedTci(GetTaggedTciResp),
+    OpenSession(OpenSessionResp),
+    CloseSession(ResponseHdr),
+    GetCertificateChunk(GetCertificateChainResp),
+    ExportCdi(ExportCdiResp),
+    GetCommandCounter(GetCommandCounterResp),
+    HashInitialize(HashInitializeResp),
+    HashUpdate(ResponseHdr),
+    HashFinal(HashFinalResp),
+    GetContextInfo(GetContextInfoResp),
+    Attest(AttestResp),
+    Seal(SealResp),
+    Unseal(UnsealResp),
+    Vendor(VendorResp),
+    Session(SessionResp),
     Error(ResponseHdr),
 }
 
@@ -37,21 +58,78 @@ impl Response {
             Response::Sign(res) => res.as_bytes(),
             Response::DestroyCtx(res) => res.as_bytes(),
             Response::GetCertificateChain(res) => res.as_bytes(),
+            Response::ExtendTci(res) => res.as_bytes(),
+            Response::TagTci(res) => res.as_bytes(),
+            Response::GetTaggedTci(res) => res.as_bytes(),
+            Response::OpenSession(res) => res.as_bytes(),
+            Response::CloseSession(res) => res.as_bytes(),
+            Response::GetCertificateChunk(res) => res.as_bytes(),
+            Response::ExportCdi(res) => res.as_bytes(),
+            Response::GetCommandCounter(res) => res.as_bytes(),
+            Response::HashInitialize(res) => res.as_bytes(),
+            Response::HashUpdate(res) => res.as_bytes(),
+            Response::HashFinal(res) => res.as_bytes(),
+            Response::GetContextInfo(res) => res.as_bytes(),
+            Response::Attest(res) => res.as_bytes(),
+            Response::Seal(res) => res.as_bytes(),
+            Response::Unseal(res) => res.as_bytes(),
+            Response::Vendor(res) => res.msg_bytes(),
+            Response::Session(res) => res.msg_bytes(),
             Response::Error(res) => res.as_bytes(),
         }
     }
 }
 
+impl WireEndian for Response {
+    fn swap_bytes(&mut self) {
+        match self {
+            Response::GetProfile(res) => res.swap_bytes(),
+            Response::InitCtx(res) => res.swap_bytes(),
+            Response::DeriveContext(res) => res.swap_bytes(),
+            Response::RotateCtx(res) => res.swap_bytes(),
+            Response::CertifyKey(res) => res.swap_bytes(),
+            Response::Sign(res) => res.swap_bytes(),
+            Response::DestroyCtx(res) => res.swap_bytes(),
+            Response::GetCertificateChain(res) => res.swap_bytes(),
+            Response::ExtendTci(res) => res.swap_bytes(),
+            Response::TagTci(res) => res.swap_bytes(),
+            Response::GetTaggedTci(res) => res.swap_bytes(),
+            Response::OpenSession(res) => res.swap_bytes(),
+            Response::CloseSession(res) => res.swap_bytes(),
+            Response::GetCertificateChunk(res) => res.swap_bytes(),
+            Response::ExportCdi(res) => res.swap_bytes(),
+            Response::GetCommandCounter(res) => res.swap_bytes(),
+            Response::HashInitialize(res) => res.swap_bytes(),
+            Response::HashUpdate(res) => res.swap_bytes(),
+            Response::HashFinal(res) => res.swap_bytes(),
+            Response::GetContextInfo(res) => res.swap_bytes(),
+            Response::Attest(res) => res.swap_bytes(),
+            Response::Seal(res) => res.swap_bytes(),
+            Response::Unseal(res) => res.swap_bytes(),
+            Response::Vendor(res) => res.swap_bytes(),
+            Response::Session(res) => res.swap_bytes(),
+            Response::Error(res) => res.swap_bytes(),
+        }
+    }
+}
+
 // ABI Response structures
 
 #[repr(C)]
 #[derive(Debug, PartialEq, Eq, zerocopy::AsBytes, zerocopy::FromBytes)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ResponseHdr {
     pub magic: u32,
     pub status: u32,
     pub profile: u32,
 }
 
+impl_wire_endian!(ResponseHdr {
+    magic,
+    status,
+    profile
+});
+
 impl ResponseHdr {
     pub const DPE_RESPONSE_MAGIC: u32 = u32::from_be_bytes(*b"DPER");
 
@@ -66,6 +144,7 @@ impl ResponseHdr {
 
 #[repr(C)]
 #[derive(Debug, PartialEq, Eq, zerocopy::AsBytes, zerocopy::FromBytes)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct GetProfileResp {
     pub resp_hdr: ResponseHdr,
     pub major_version: u16,
@@ -74,17 +153,39 @@ pub struct GetProfileResp {
     pub vendor_sku: u32,
     pub max_tci_nodes: u32,
     pub flags: u32,
+    pub max_simulation_contexts: u32,
+    pub max_cert_chain_depth: u32,
 }
 
+impl_wire_endian!(GetProfileResp {
+    resp_hdr,
+    major_version,
+    minor_version,
+    vendor_id,
+    vendor_sku,
+    max_tci_nodes,
+    flags,
+    max_simulation_contexts,
+    max_cert_chain_depth,
+});
+
 impl GetProfileResp {
-    pub const fn new(flags: u32, vendor_id: u32, vendor_sku: u32) -> GetProfileResp {
+    pub const fn new(
+        support: Support,
+        vendor_id: u32,
+        vendor_sku: u32,
+        max_simulation_contexts: u32,
+        max_cert_chain_depth: u32,
+    ) -> GetProfileResp {
         GetProfileResp {
             major_version: CURRENT_PROFILE_MAJOR_VERSION,
             minor_version: CURRENT_PROFILE_MINOR_VERSION,
             vendor_id,
             vendor_sku,
             max_tci_nodes: MAX_HANDLES as u32,
-            flags,
+            flags: support.bits(),
+            max_simulation_contexts,
+            max_cert_chain_depth,
             resp_hdr: ResponseHdr {
                 magic: ResponseHdr::DPE_RESPONSE_MAGIC,
                 status: 0,
@@ -92,51 +193,445 @@ impl GetProfileResp {
             },
         }
     }
+
+    /// Decodes `flags`, keeping any bits this crate doesn't know about.
+    pub const fn support(&self) -> Support {
+        Support::from_bits_retain(self.flags)
+    }
 }
 
 #[repr(C)]
 #[derive(Debug, PartialEq, Eq, zerocopy::AsBytes, zerocopy::FromBytes)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct NewHandleResp {
     pub resp_hdr: ResponseHdr,
     pub handle: ContextHandle,
 }
 
+impl_wire_endian!(NewHandleResp { resp_hdr });
+
 #[repr(C)]
 #[derive(Debug, PartialEq, Eq, zerocopy::AsBytes, zerocopy::FromBytes)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct DeriveContextResp {
     pub resp_hdr: ResponseHdr,
     pub handle: ContextHandle,
     pub parent_handle: ContextHandle,
 }
 
+impl_wire_endian!(DeriveContextResp { resp_hdr });
+
 #[repr(C)]
 #[derive(Debug, PartialEq, Eq, zerocopy::AsBytes, zerocopy::FromBytes)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct CertifyKeyResp {
     pub resp_hdr: ResponseHdr,
     pub new_context_handle: ContextHandle,
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_hex"))]
     pub derived_pubkey_x: [u8; DPE_PROFILE.get_ecc_int_size()],
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_hex"))]
     pub derived_pubkey_y: [u8; DPE_PROFILE.get_ecc_int_size()],
+    /// Number of bytes of the certificate in `cert`, at most `MAX_CERT_SIZE`.
     pub cert_size: u32,
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_hex"))]
     pub cert: [u8; MAX_CERT_SIZE],
+    /// Total size of the certificate. If it is larger than `cert_size`, the
+    /// rest is read with GetCertificateChunk.
+    pub total_size: u32,
 }
 
+impl_wire_endian!(CertifyKeyResp {
+    resp_hdr,
+    cert_size,
+    total_size
+});
+
+#[cfg(not(feature = "dpe_profile_mldsa87_sha384"))]
 #[repr(C)]
 #[derive(Debug, PartialEq, Eq, zerocopy::AsBytes, zerocopy::FromBytes)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct SignResp {
     pub resp_hdr: ResponseHdr,
     pub new_context_handle: ContextHandle,
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_hex"))]
     pub sig_r_or_hmac: [u8; DPE_PROFILE.get_ecc_int_size()],
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_hex"))]
     pub sig_s: [u8; DPE_PROFILE.get_ecc_int_size()],
 }
 
+#[cfg(feature = "dpe_profile_mldsa87_sha384")]
+#[repr(C)]
+#[derive(Debug, PartialEq, Eq, zerocopy::AsBytes, zerocopy::FromBytes)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SignResp {
+    pub resp_hdr: ResponseHdr,
+    pub new_context_handle: ContextHandle,
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_hex"))]
+    pub sig: [u8; crypto::MlDsaSig::SIZE],
+    // Pads the response to a multiple of 4 bytes.
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_hex"))]
+    pub reserved: [u8; 1],
+}
+
+impl_wire_endian!(SignResp { resp_hdr });
+
 #[repr(C)]
 #[derive(Debug, PartialEq, Eq, zerocopy::AsBytes, zerocopy::FromBytes)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct GetCertificateChainResp {
     pub resp_hdr: ResponseHdr,
     pub certificate_size: u32,
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_hex"))]
     pub certificate_chain: [u8; MAX_CERT_SIZE],
 }
 
+impl_wire_endian!(GetCertificateChainResp {
+    resp_hdr,
+    certificate_size
+});
+
+#[repr(C)]
+#[derive(Debug, PartialEq, Eq, zerocopy::AsBytes, zerocopy::FromBytes)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct GetTaggedTciResp {
+    pub resp_hdr: ResponseHdr,
+    pub tci_cumulative: TciMeasurement,
+    pub tci_current: TciMeasurement,
+}
+
+impl_wire_endian!(GetTaggedTciResp { resp_hdr });
+
+#[repr(C)]
+#[derive(Debug, PartialEq, Eq, zerocopy::AsBytes, zerocopy::FromBytes)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct OpenSessionResp {
+    pub resp_hdr: ResponseHdr,
+    pub session_id: u32,
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_hex"))]
+    pub pub_key_x: [u8; DPE_PROFILE.get_ecc_int_size()],
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_hex"))]
+    pub pub_key_y: [u8; DPE_PROFILE.get_ecc_int_size()],
+    /// Alias key signature over `SESSION_SIGN_LABEL`, the client's public
+    /// key, the DPE's public key and the little-endian session ID.
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_hex"))]
+    pub sig_r: [u8; DPE_PROFILE.get_ecc_int_size()],
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_hex"))]
+    pub sig_s: [u8; DPE_PROFILE.get_ecc_int_size()],
+}
+
+impl_wire_endian!(OpenSessionResp {
+    resp_hdr,
+    session_id
+});
+
+#[repr(C)]
+#[derive(Debug, PartialEq, Eq, zerocopy::AsBytes, zerocopy::FromBytes)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ExportCdiResp {
+    pub resp_hdr: ResponseHdr,
+    pub new_context_handle: ContextHandle,
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_hex"))]
+    pub iv: [u8; AEAD_IV_SIZE],
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_hex"))]
+    pub tag: [u8; AEAD_TAG_SIZE],
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_hex"))]
+    pub wrapped_cdi: [u8; DPE_PROFILE.get_hash_size()],
+}
+
+impl_wire_endian!(ExportCdiResp { resp_hdr });
+
+#[repr(C)]
+#[derive(Debug, PartialEq, Eq, zerocopy::AsBytes, zerocopy::FromBytes)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct GetCommandCounterResp {
+    pub resp_hdr: ResponseHdr,
+    pub counter: u32,
+}
+
+impl_wire_endian!(GetCommandCounterResp { resp_hdr, counter });
+
+#[repr(C)]
+#[derive(Debug, PartialEq, Eq, zerocopy::AsBytes, zerocopy::FromBytes)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct HashInitializeResp {
+    pub resp_hdr: ResponseHdr,
+    pub stream_id: u32,
+}
+
+impl_wire_endian!(HashInitializeResp {
+    resp_hdr,
+    stream_id
+});
+
+#[repr(C)]
+#[derive(Debug, PartialEq, Eq, zerocopy::AsBytes, zerocopy::FromBytes)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct HashFinalResp {
+    pub resp_hdr: ResponseHdr,
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_hex"))]
+    pub digest: [u8; DPE_PROFILE.get_hash_size()],
+}
+
+impl_wire_endian!(HashFinalResp { resp_hdr });
+
+#[repr(C)]
+#[derive(Debug, PartialEq, Eq, zerocopy::AsBytes, zerocopy::FromBytes)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct GetContextInfoResp {
+    pub resp_hdr: ResponseHdr,
+    /// Index of the context in the context table. Handles are secrets, so
+    /// the tree is described with indices instead.
+    pub index: u32,
+    /// Index of the parent context. Only valid if `flags` has `HAS_PARENT`.
+    pub parent_index: u32,
+    pub flags: u32,
+    /// Number of direct children of the context.
+    pub child_count: u32,
+    pub tci_type: u32,
+    pub locality: u32,
+}
+
+impl_wire_endian!(GetContextInfoResp {
+    resp_hdr,
+    index,
+    parent_index,
+    flags,
+    child_count,
+    tci_type,
+    locality,
+});
+
+impl GetContextInfoResp {
+    /// The context has a parent, which may have been retired.
+    pub const HAS_PARENT: u32 = 1 << 31;
+}
+
+#[repr(C)]
+#[derive(Debug, PartialEq, Eq, zerocopy::AsBytes, zerocopy::FromBytes)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AttestResp {
+    pub resp_hdr: ResponseHdr,
+    pub new_context_handle: ContextHandle,
+    /// Total size of the token. If it is larger than `MAX_CERT_SIZE`, the
+    /// rest is read with GetCertificateChunk.
+    pub token_size: u32,
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_hex"))]
+    pub token: [u8; MAX_CERT_SIZE],
+}
+
+impl_wire_endian!(AttestResp {
+    resp_hdr,
+    token_size
+});
+
+#[repr(C)]
+#[derive(Debug, PartialEq, Eq, zerocopy::AsBytes, zerocopy::FromBytes)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SealResp {
+    pub resp_hdr: ResponseHdr,
+    pub new_context_handle: ContextHandle,
+    pub sealed_size: u32,
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_hex"))]
+    pub sealed: [u8; MAX_SEALED_SIZE],
+}
+
+impl_wire_endian!(SealResp {
+    resp_hdr,
+    sealed_size
+});
+
+#[repr(C)]
+#[derive(Debug, PartialEq, Eq, zerocopy::AsBytes, zerocopy::FromBytes)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct UnsealResp {
+    pub resp_hdr: ResponseHdr,
+    pub new_context_handle: ContextHandle,
+    pub data_size: u32,
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_hex"))]
+    pub data: [u8; MAX_SEAL_DATA_SIZE],
+}
+
+impl_wire_endian!(UnsealResp {
+    resp_hdr,
+    data_size
+});
+
+const fn max_size(a: usize, b: usize) -> usize {
+    if a > b {
+        a
+    } else {
+        b
+    }
+}
+
+/// Response to a command in the vendor range.
+///
+/// Only the header and the first `payload_size` bytes of `payload` are sent.
+#[repr(C)]
+#[derive(Debug, PartialEq, Eq, zerocopy::AsBytes, zerocopy::FromBytes)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct VendorResp {
+    pub resp_hdr: ResponseHdr,
+    /// Payload written by the `VendorCommandHandler`.
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_hex"))]
+    pub payload: [u8; VendorResp::PAYLOAD_SIZE],
+    pub payload_size: u32,
+}
+
+// `payload_size` is not sent, so it stays in host order.
+impl_wire_endian!(VendorResp { resp_hdr });
+
+impl VendorResp {
+    pub const PAYLOAD_SIZE: usize = 2048;
+
+    /// Returns the bytes of the response as sent over the transport.
+    pub fn msg_bytes(&self) -> &[u8] {
+        let len = size_of::<ResponseHdr>() + self.payload_size as usize;
+        let bytes = self.as_bytes();
+        bytes.get(..len).unwrap_or(bytes)
+    }
+}
+
+/// Largest response that can be wrapped in a session message.
+pub const MAX_SESSION_RESP_SIZE: usize = max_size(
+    size_of::<CertifyKeyResp>(),
+    max_size(
+        size_of::<SignResp>(),
+        max_size(
+            size_of::<GetCertificateChainResp>(),
+            size_of::<VendorResp>(),
+        ),
+    ),
+);
+
+/// An encrypted response to a command sent over a session.
+///
+/// Only the header and the first `payload_size` bytes of `payload` are sent.
+#[repr(C)]
+#[derive(Debug, PartialEq, Eq, zerocopy::AsBytes, zerocopy::FromBytes)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SessionResp {
+    pub hdr: SessionMsgHdr,
+    /// Encrypted response followed by the AEAD tag.
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_hex"))]
+    pub payload: [u8; SessionResp::PAYLOAD_SIZE],
+    pub payload_size: u32,
+}
+
+// `payload_size` is not sent, so it stays in host order.
+impl_wire_endian!(SessionResp { hdr });
+
+impl SessionResp {
+    pub const PAYLOAD_SIZE: usize = MAX_SESSION_RESP_SIZE + AEAD_TAG_SIZE;
+
+    /// Returns the bytes of the message as sent over the transport.
+    pub fn msg_bytes(&self) -> &[u8] {
+        let len = size_of::<SessionMsgHdr>() + self.payload_size as usize;
+        let bytes = self.as_bytes();
+        bytes.get(..len).unwrap_or(bytes)
+    }
+}
+
+/// Writes variable-length data into a response buffer and tracks how much
+/// room is left in it.
+///
+/// A writer made with `with_offset` discards that many bytes before it starts
+/// storing any. Writing a whole object through it stores the chunk that starts
+/// at the offset, so chunked reads need no bounds arithmetic of their own.
+pub struct ResponseWriter<'a> {
+    buf: &'a mut [u8],
+    skip: usize,
+    len: usize,
+}
+
+impl<'a> ResponseWriter<'a> {
+    pub fn new(buf: &'a mut [u8]) -> Self {
+        Self::with_offset(buf, 0)
+    }
+
+    /// Returns a writer that stores what is written after the first `offset`
+    /// bytes.
+    pub fn with_offset(buf: &'a mut [u8], offset: usize) -> Self {
+        ResponseWriter {
+            buf,
+            skip: offset,
+            len: 0,
+        }
+    }
+
+    /// Returns the number of bytes stored so far.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the number of bytes that can still be stored.
+    pub fn remaining(&self) -> usize {
+        self.buf.len() - self.len
+    }
+
+    /// Returns the bytes stored so far.
+    pub fn into_written(self) -> &'a mut [u8] {
+        let (written, _) = self.buf.split_at_mut(self.len);
+        written
+    }
+
+    /// Writes all of `bytes`.
+    ///
+    /// Fails with `OutputBufferTooSmall`, leaving the buffer as it was, if
+    /// they don't fit.
+    pub fn write(&mut self, bytes: &[u8]) -> Result<(), DpeErrorCode> {
+        if bytes.len().saturating_sub(self.skip) > self.remaining() {
+            return Err(DpeErrorCode::OutputBufferTooSmall);
+        }
+        self.write_chunk(bytes)?;
+        Ok(())
+    }
+
+    /// Writes as much of `bytes` as fits and returns how many were stored.
+    pub fn write_chunk(&mut self, bytes: &[u8]) -> Result<usize, DpeErrorCode> {
+        self.write_from(bytes.len(), |offset, out| {
+            let src = bytes
+                .get(offset..offset + out.len())
+                .ok_or(DpeErrorCode::InternalError)?;
+            out.copy_from_slice(src);
+            Ok(out.len())
+        })
+    }
+
+    /// Writes as much of a `size` byte object as fits and returns how many
+    /// bytes were stored.
+    ///
+    /// `read` is called with an offset into the object and the space to fill
+    /// from there, and returns how many bytes it filled. It isn't called if
+    /// nothing of the object is stored.
+    pub fn write_from(
+        &mut self,
+        size: usize,
+        read: impl FnOnce(usize, &mut [u8]) -> Result<usize, DpeErrorCode>,
+    ) -> Result<usize, DpeErrorCode> {
+        let offset = min(self.skip, size);
+        self.skip -= offset;
+        let len = min(size - offset, self.remaining());
+        if len == 0 {
+            return Ok(0);
+        }
+        let out = self
+            .buf
+            .get_mut(self.len..self.len + len)
+            .ok_or(DpeErrorCode::InternalError)?;
+        let len = read(offset, out)?;
+        if len > out.len() {
+            return Err(DpeErrorCode::InternalError);
+        }
+        self.len += len;
+        Ok(len)
+    }
+}
+
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
 #[repr(u32)]
 pub enum DpeErrorCode {
@@ -147,7 +642,23 @@ pub enum DpeErrorCode {
     ArgumentNotSupported = 4,
     InvalidHandle = 0x1000,
     InvalidLocality = 0x1001,
+    BadTag = 0x1002,
     MaxTcis = 0x1003,
+    MaxSessions = 0x1008,
+    CommandNotAllowed = 0x1009,
+    MaxHashStreams = 0x100A,
+    InvalidSession = 0x100B,
+    CertificateTooLarge = 0x100C,
+    InvalidCertificate = 0x100D,
+    TciTypeNotAllowed = 0x100E,
+    OutputBufferTooSmall = 0x100F,
+    AuthenticationFailed = 0x1010,
+    UnknownLocality = 0x1011,
+    SvnRollback = 0x1012,
+    UnsealFailed = 0x1013,
+    Busy = 0x1014,
+    RetiredContext = 0x1015,
+    MaxCertChainDepth = 0x1016,
     Platform(PlatformError) = 0x01000000,
     Crypto(CryptoError) = 0x02000000,
     Validation(ValidationError) = 0x03000000,
@@ -198,3 +709,79 @@ impl DpeErrorCode {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_codes() {
+        assert_eq!(0x100B, DpeErrorCode::InvalidSession.get_error_code());
+        assert_eq!(0x100C, DpeErrorCode::CertificateTooLarge.get_error_code());
+        assert_eq!(0x100D, DpeErrorCode::InvalidCertificate.get_error_code());
+        assert_eq!(0x100E, DpeErrorCode::TciTypeNotAllowed.get_error_code());
+        assert_eq!(0x100F, DpeErrorCode::OutputBufferTooSmall.get_error_code());
+        assert_eq!(0x1010, DpeErrorCode::AuthenticationFailed.get_error_code());
+        assert_eq!(0x1011, DpeErrorCode::UnknownLocality.get_error_code());
+        assert_eq!(0x1012, DpeErrorCode::SvnRollback.get_error_code());
+        assert_eq!(0x1013, DpeErrorCode::UnsealFailed.get_error_code());
+        assert_eq!(0x1014, DpeErrorCode::Busy.get_error_code());
+        assert_eq!(0x1015, DpeErrorCode::RetiredContext.get_error_code());
+        assert_eq!(0x1016, DpeErrorCode::MaxCertChainDepth.get_error_code());
+
+        let err = DpeErrorCode::Crypto(CryptoError::AuthenticationFailed);
+        assert_eq!(0x02000006, err.get_error_code());
+        assert_eq!(None, err.get_error_detail());
+
+        let err = DpeErrorCode::Crypto(CryptoError::CryptoLibError(7));
+        assert_eq!(0x02000002, err.get_error_code());
+        assert_eq!(Some(7), err.get_error_detail());
+    }
+
+    #[test]
+    fn test_response_writer() {
+        let mut buf = [0u8; 4];
+        let mut out = ResponseWriter::new(&mut buf);
+        assert_eq!(Ok(()), out.write(&[1, 2]));
+        assert_eq!(2, out.remaining());
+        assert_eq!(
+            Err(DpeErrorCode::OutputBufferTooSmall),
+            out.write(&[3, 4, 5])
+        );
+        assert_eq!(Ok(2), out.write_chunk(&[3, 4, 5]));
+        assert_eq!(Ok(0), out.write_chunk(&[6]));
+        assert_eq!(&[1, 2, 3, 4], out.into_written());
+    }
+
+    #[test]
+    fn test_response_writer_continuation() {
+        // Reading an object in chunks through writers at successive offsets
+        // returns all of it, across the boundaries of what is written.
+        let parts: [&[u8]; 3] = [&[0, 1, 2], &[], &[3, 4, 5, 6, 7]];
+        let mut read = Vec::new();
+        loop {
+            let mut buf = [0u8; 3];
+            let mut out = ResponseWriter::with_offset(&mut buf, read.len());
+            for part in parts {
+                out.write_chunk(part).unwrap();
+            }
+            let chunk = out.into_written();
+            if chunk.is_empty() {
+                break;
+            }
+            read.extend_from_slice(chunk);
+        }
+        assert_eq!(read, [0, 1, 2, 3, 4, 5, 6, 7]);
+
+        // A source may fill less than it is offered.
+        let mut buf = [0u8; 4];
+        let mut out = ResponseWriter::with_offset(&mut buf, 1);
+        let filled = out.write_from(8, |offset, out| {
+            assert_eq!((1, 4), (offset, out.len()));
+            out[0] = 0xAA;
+            Ok(1)
+        });
+        assert_eq!(Ok(1), filled);
+        assert_eq!(&[0xAA], out.into_written());
+    }
+}
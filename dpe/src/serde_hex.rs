@@ -0,0 +1,194 @@
+/*++
+Licensed under the Apache-2.0 license.
+
+Abstract:
+    Serializes the byte arrays of commands and responses as hex strings, so
+    handles, digests and certificates stay readable in JSON. Used with
+    `#[serde(with = "crate::serde_hex")]`, and directly by the handle and
+    measurement newtypes.
+--*/
+use crate::{context::ContextHandle, tci::TciMeasurement};
+use core::fmt;
+use serde::{
+    de::{Error, Unexpected, Visitor},
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+
+/// Formats bytes as lowercase hex without allocating.
+struct Hex<'a>(&'a [u8]);
+
+impl fmt::Display for Hex<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for byte in self.0 {
+            write!(f, "{byte:02x}")?;
+        }
+        Ok(())
+    }
+}
+
+impl Serialize for Hex<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+pub fn serialize<S: Serializer, B: AsRef<[u8]> + ?Sized>(
+    bytes: &B,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.collect_str(&Hex(bytes.as_ref()))
+}
+
+/// Serializes optional bytes as a hex string, or as none if absent.
+pub fn serialize_option<S: Serializer, B: AsRef<[u8]>>(
+    bytes: &Option<B>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    match bytes {
+        Some(bytes) => serializer.serialize_some(&Hex(bytes.as_ref())),
+        None => serializer.serialize_none(),
+    }
+}
+
+pub fn deserialize<'de, D: Deserializer<'de>, const N: usize>(
+    deserializer: D,
+) -> Result<[u8; N], D::Error> {
+    deserializer.deserialize_str(HexVisitor::<N>)
+}
+
+struct HexVisitor<const N: usize>;
+
+impl<'de, const N: usize> Visitor<'de> for HexVisitor<N> {
+    type Value = [u8; N];
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "a string of {} hex digits", N * 2)
+    }
+
+    fn visit_str<E: Error>(self, s: &str) -> Result<[u8; N], E> {
+        if s.len() != N * 2 {
+            return Err(E::invalid_length(s.len(), &self));
+        }
+        let mut bytes = [0u8; N];
+        for (byte, pair) in bytes.iter_mut().zip(s.as_bytes().chunks_exact(2)) {
+            let mut value = 0;
+            for &digit in pair {
+                let digit = char::from(digit)
+                    .to_digit(16)
+                    .ok_or_else(|| E::invalid_value(Unexpected::Str(s), &self))?;
+                value = (value << 4) | digit as u8;
+            }
+            *byte = value;
+        }
+        Ok(bytes)
+    }
+}
+
+impl Serialize for ContextHandle {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serialize(&self.0, serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for ContextHandle {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserialize(deserializer).map(ContextHandle)
+    }
+}
+
+impl Serialize for TciMeasurement {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serialize(&self.0, serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for TciMeasurement {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserialize(deserializer).map(TciMeasurement)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        commands::{
+            CertifyKeyCmd, CertifyKeyFlags, Command, GetCertificateChainCmd, SignCmd, SignFlags,
+        },
+        context::ContextHandle,
+        response::{GetProfileResp, NewHandleResp, Response, ResponseHdr},
+        support::Support,
+        DPE_PROFILE,
+    };
+    use zerocopy::AsBytes;
+
+    #[test]
+    fn test_canonical_json() {
+        let cmd = GetCertificateChainCmd {
+            offset: 0x10,
+            size: 0x20,
+        };
+        assert_eq!(
+            serde_json::to_string(&cmd).unwrap(),
+            r#"{"offset":16,"size":32}"#
+        );
+
+        let mut handle = ContextHandle::default();
+        handle.0[0] = 0xAB;
+        handle.0[15] = 0x01;
+        assert_eq!(
+            serde_json::to_string(&handle).unwrap(),
+            r#""ab000000000000000000000000000001""#
+        );
+
+        let message = [0xDE, 0xAD];
+        let cmd = Command::Sign(
+            SignCmd {
+                handle: ContextHandle::default(),
+                label: [0; DPE_PROFILE.get_hash_size()],
+                flags: SignFlags::IS_MESSAGE,
+                digest: [0; DPE_PROFILE.get_hash_size()],
+            },
+            &message,
+        );
+        let json = serde_json::to_value(&cmd).unwrap();
+        assert_eq!(json["Sign"][1], "dead");
+        assert_eq!(json["Sign"][0]["flags"], SignFlags::IS_MESSAGE.bits());
+    }
+
+    #[test]
+    fn test_round_trip_keeps_wire_format() {
+        let mut label = [0u8; DPE_PROFILE.get_hash_size()];
+        label.iter_mut().enumerate().for_each(|(i, b)| *b = i as u8);
+        let cmd = CertifyKeyCmd {
+            handle: ContextHandle([0x5A; ContextHandle::SIZE]),
+            flags: CertifyKeyFlags::IS_CA,
+            format: CertifyKeyCmd::FORMAT_CSR,
+            label,
+        };
+        let json = serde_json::to_string(&cmd).unwrap();
+        let decoded: CertifyKeyCmd = serde_json::from_str(&json).unwrap();
+        assert_eq!(decoded.as_bytes(), cmd.as_bytes());
+
+        let resp = Response::GetProfile(GetProfileResp::new(Support::X509, 1, 2, 3, 4));
+        let json = serde_json::to_string(&resp).unwrap();
+        let decoded: Response = serde_json::from_str(&json).unwrap();
+        assert_eq!(decoded.as_bytes(), resp.as_bytes());
+
+        let resp = Response::RotateCtx(NewHandleResp {
+            resp_hdr: ResponseHdr::new(crate::response::DpeErrorCode::NoError),
+            handle: ContextHandle([0xC3; ContextHandle::SIZE]),
+        });
+        let json = serde_json::to_string(&resp).unwrap();
+        let decoded: Response = serde_json::from_str(&json).unwrap();
+        assert_eq!(decoded, resp);
+    }
+
+    #[test]
+    fn test_rejects_bad_hex() {
+        let short = format!(r#""{}""#, "00".repeat(ContextHandle::SIZE - 1));
+        assert!(serde_json::from_str::<ContextHandle>(&short).is_err());
+
+        let not_hex = format!(r#""{}""#, "zz".repeat(ContextHandle::SIZE));
+        assert!(serde_json::from_str::<ContextHandle>(&not_hex).is_err());
+    }
+}
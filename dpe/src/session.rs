@@ -0,0 +1,143 @@
+/*++
+Licensed under the Apache-2.0 license.
+
+Abstract:
+    Encrypted session state and message framing.
+--*/
+use crate::{wire::WireEndian, U8Bool};
+use crypto::{AEAD_IV_SIZE, AEAD_KEY_SIZE};
+use zerocopy::{AsBytes, FromBytes};
+use zeroize::Zeroize;
+
+pub const MAX_SESSIONS: usize = 4;
+
+/// Most sessions a single locality may have open at once, so that no
+/// locality can take every session slot.
+pub const MAX_SESSIONS_PER_LOCALITY: usize = MAX_SESSIONS / 2;
+
+const _: () = assert!(MAX_SESSIONS_PER_LOCALITY > 0 && MAX_SESSIONS_PER_LOCALITY <= MAX_SESSIONS);
+
+/// Largest serialized command that can be carried in a session message.
+pub const MAX_SESSION_CMD_SIZE: usize = 512;
+
+/// Info string used to derive the session key from the ECDH shared secret.
+pub const SESSION_KEY_INFO: &[u8] = b"DPE_SESSION";
+
+/// Label prefixed to the OpenSession transcript the DPE signs with the
+/// alias key.
+pub const SESSION_SIGN_LABEL: &[u8] = b"DPE_SESSION_KEY";
+
+#[repr(C, align(4))]
+#[derive(AsBytes, FromBytes, Copy, Clone, Zeroize)]
+pub struct Session {
+    /// AES-256-GCM key shared with the client.
+    pub(crate) key: [u8; AEAD_KEY_SIZE],
+    /// Locality that opened the session. Messages from other localities are rejected.
+    pub(crate) locality: u32,
+    /// Counter the next command must carry. Each value is only accepted once.
+    pub(crate) counter: u32,
+    pub(crate) active: U8Bool,
+    reserved: [u8; 3],
+}
+
+impl Default for Session {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Session {
+    pub const fn new() -> Session {
+        Session {
+            key: [0; AEAD_KEY_SIZE],
+            locality: 0,
+            counter: 0,
+            active: U8Bool::new(false),
+            reserved: [0; 3],
+        }
+    }
+
+    pub fn active(&self) -> bool {
+        self.active.get()
+    }
+}
+
+/// Header of a message sent over an encrypted session.
+///
+/// The header is followed by the ciphertext and then the AEAD tag. The header
+/// itself is authenticated as additional data.
+#[repr(C)]
+#[derive(Debug, PartialEq, Eq, Clone, Copy, AsBytes, FromBytes)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SessionMsgHdr {
+    pub magic: u32,
+    pub session_id: u32,
+    pub counter: u32,
+}
+
+impl_wire_endian!(SessionMsgHdr {
+    magic,
+    session_id,
+    counter
+});
+
+impl SessionMsgHdr {
+    pub const SESSION_CMD_MAGIC: u32 = u32::from_be_bytes(*b"DPSC");
+    pub const SESSION_RESP_MAGIC: u32 = u32::from_be_bytes(*b"DPSR");
+
+    /// Returns whether `bytes` starts with a session command header.
+    pub fn is_session_cmd(bytes: &[u8]) -> bool {
+        match SessionMsgHdr::read_from_prefix(bytes).map(Self::from_le) {
+            Some(hdr) => hdr.magic == Self::SESSION_CMD_MAGIC,
+            None => false,
+        }
+    }
+
+    /// Builds the AEAD IV for this message.
+    ///
+    /// Commands and responses use different magic values, so the IV is never
+    /// reused for a given session key and counter.
+    pub fn iv(&self) -> [u8; AEAD_IV_SIZE] {
+        let mut iv = [0u8; AEAD_IV_SIZE];
+        iv[..4].copy_from_slice(&self.magic.to_le_bytes());
+        iv[4..8].copy_from_slice(&self.session_id.to_le_bytes());
+        iv[8..].copy_from_slice(&self.counter.to_le_bytes());
+        iv
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_session_cmd() {
+        let hdr = SessionMsgHdr {
+            magic: SessionMsgHdr::SESSION_CMD_MAGIC,
+            session_id: 0,
+            counter: 0,
+        };
+        assert!(SessionMsgHdr::is_session_cmd(hdr.as_bytes()));
+        assert!(!SessionMsgHdr::is_session_cmd(&hdr.as_bytes()[..4]));
+
+        let hdr = SessionMsgHdr {
+            magic: SessionMsgHdr::SESSION_RESP_MAGIC,
+            ..hdr
+        };
+        assert!(!SessionMsgHdr::is_session_cmd(hdr.as_bytes()));
+    }
+
+    #[test]
+    fn test_iv_differs_by_direction() {
+        let cmd = SessionMsgHdr {
+            magic: SessionMsgHdr::SESSION_CMD_MAGIC,
+            session_id: 1,
+            counter: 2,
+        };
+        let resp = SessionMsgHdr {
+            magic: SessionMsgHdr::SESSION_RESP_MAGIC,
+            ..cmd
+        };
+        assert_ne!(cmd.iv(), resp.iv());
+    }
+}
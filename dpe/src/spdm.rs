@@ -0,0 +1,138 @@
+/*++
+Licensed under the Apache-2.0 license.
+
+Abstract:
+    Renders the context tree as SPDM measurement blocks.
+--*/
+use crate::{
+    context::{Context, TreeIter},
+    response::DpeErrorCode,
+    DPE_PROFILE, MAX_HANDLES,
+};
+
+/// MeasurementSpecification bit for the DMTF measurement format.
+pub const MEASUREMENT_SPEC_DMTF: u8 = 1 << 0;
+
+/// DMTFSpecMeasurementValueType of a digest of mutable firmware.
+pub const DMTF_TYPE_MUTABLE_FIRMWARE: u8 = 0x01;
+
+/// Size of the Index, MeasurementSpecification and MeasurementSize fields.
+const BLOCK_HDR_SIZE: usize = 4;
+
+/// Size of the DMTFSpecMeasurementValueType and DMTFSpecMeasurementValueSize
+/// fields.
+const DMTF_HDR_SIZE: usize = 3;
+
+/// Size of each measurement block written by
+/// `DpeInstance::get_spdm_measurements`.
+pub const MEASUREMENT_BLOCK_SIZE: usize =
+    BLOCK_HDR_SIZE + DMTF_HDR_SIZE + DPE_PROFILE.get_tci_size();
+
+/// Largest output of `DpeInstance::get_spdm_measurements`.
+pub const MAX_MEASUREMENTS_SIZE: usize = MAX_HANDLES * MEASUREMENT_BLOCK_SIZE;
+
+/// Writes one measurement block per context in `contexts` to `out`.
+///
+/// Returns the number of blocks and the number of bytes written.
+pub(crate) fn write_measurement_blocks(
+    contexts: &[Context],
+    out: &mut [u8],
+) -> Result<(u8, usize), DpeErrorCode> {
+    let mut count = 0u8;
+    let mut chunks = out.chunks_exact_mut(MEASUREMENT_BLOCK_SIZE);
+    for node in TreeIter::new(contexts) {
+        let (_, context) = node?;
+        let block = chunks.next().ok_or(DpeErrorCode::InvalidArgument)?;
+        count = count.checked_add(1).ok_or(DpeErrorCode::InternalError)?;
+
+        let value = &context.tci.tci_cumulative.0;
+        let (hdr, dmtf) = block.split_at_mut(BLOCK_HDR_SIZE);
+        hdr[0] = count;
+        hdr[1] = MEASUREMENT_SPEC_DMTF;
+        hdr[2..].copy_from_slice(&((DMTF_HDR_SIZE + value.len()) as u16).to_le_bytes());
+        dmtf[0] = DMTF_TYPE_MUTABLE_FIRMWARE;
+        dmtf[1..DMTF_HDR_SIZE].copy_from_slice(&(value.len() as u16).to_le_bytes());
+        dmtf[DMTF_HDR_SIZE..].copy_from_slice(value);
+    }
+    Ok((count, count as usize * MEASUREMENT_BLOCK_SIZE))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        commands::{CommandExecution, DeriveContextCmd, DeriveContextFlags, ExtendTciCmd},
+        context::ContextHandle,
+        dpe_instance::{
+            tests::{TestTypes, TEST_LOCALITIES},
+            DpeEnv,
+        },
+        response::Response,
+        support::Support,
+        DpeInstance,
+    };
+    use caliptra_cfi_lib_git::CfiCounter;
+    use crypto::OpensslCrypto;
+    use platform::default::DefaultPlatform;
+
+    #[test]
+    fn test_get_spdm_measurements() {
+        CfiCounter::reset_for_test();
+        let mut env = DpeEnv::<TestTypes> {
+            crypto: OpensslCrypto::new(),
+            platform: DefaultPlatform,
+            arena: &mut [],
+        };
+        let mut dpe = DpeInstance::new(
+            &mut env,
+            Support::AUTO_INIT | Support::EXTEND_TCI | Support::RETAIN_PARENT_CONTEXT,
+        )
+        .unwrap();
+
+        let Response::DeriveContext(derive_resp) = DeriveContextCmd {
+            handle: ContextHandle::default(),
+            data: [1; DPE_PROFILE.get_hash_size()],
+            flags: DeriveContextFlags::RETAIN_PARENT_CONTEXT,
+            tci_type: 7,
+            target_locality: TEST_LOCALITIES[0],
+        }
+        .execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
+        .unwrap() else {
+            panic!("DeriveContext failed");
+        };
+        ExtendTciCmd {
+            handle: derive_resp.parent_handle,
+            data: [2; DPE_PROFILE.get_hash_size()],
+        }
+        .execute(&mut dpe, &mut env, TEST_LOCALITIES[0])
+        .unwrap();
+
+        let mut out = [0u8; MAX_MEASUREMENTS_SIZE];
+        let (count, len) = dpe.get_spdm_measurements(&mut out).unwrap();
+        assert_eq!(2, count);
+        assert_eq!(2 * MEASUREMENT_BLOCK_SIZE, len);
+
+        // The root comes first, followed by its child.
+        let root = dpe.contexts.iter().position(|c| c.children != 0).unwrap();
+        let child = dpe.contexts[root].children.trailing_zeros() as usize;
+        let tci_size = DPE_PROFILE.get_tci_size();
+        for (i, idx) in [root, child].into_iter().enumerate() {
+            let block = &out[i * MEASUREMENT_BLOCK_SIZE..][..MEASUREMENT_BLOCK_SIZE];
+            assert_eq!(i as u8 + 1, block[0]);
+            assert_eq!(MEASUREMENT_SPEC_DMTF, block[1]);
+            assert_eq!(
+                (DMTF_HDR_SIZE + tci_size) as u16,
+                u16::from_le_bytes([block[2], block[3]])
+            );
+            assert_eq!(DMTF_TYPE_MUTABLE_FIRMWARE, block[4]);
+            assert_eq!(tci_size as u16, u16::from_le_bytes([block[5], block[6]]));
+            assert_eq!(dpe.contexts[idx].tci.tci_cumulative.0, block[7..]);
+        }
+
+        // The output must hold every block.
+        assert_eq!(
+            Err(DpeErrorCode::InvalidArgument),
+            dpe.get_spdm_measurements(&mut out[..len - 1])
+        );
+    }
+}
@@ -3,7 +3,8 @@ use bitflags::bitflags;
 use zerocopy::{AsBytes, FromBytes};
 use zeroize::Zeroize;
 
-#[derive(Default, AsBytes, FromBytes, Zeroize, Copy, Clone)]
+#[derive(Default, AsBytes, FromBytes, Zeroize, Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[repr(C)]
 pub struct Support(u32);
 
@@ -12,14 +13,32 @@ bitflags! {
         const SIMULATION = 1u32 << 31;
         const RECURSIVE = 1u32 << 30;
         const AUTO_INIT = 1u32 << 29;
+        const TAGGING = 1u32 << 28;
         const ROTATE_CONTEXT = 1u32 << 27;
         const X509 = 1u32 << 26;
         const CSR = 1u32 << 25;
         const IS_SYMMETRIC = 1u32 << 24;
+        const EXTEND_TCI = 1u32 << 23;
         const INTERNAL_INFO = 1u32 << 22;
         const INTERNAL_DICE = 1u32 << 21;
         const IS_CA = 1u32 << 20;
         const RETAIN_PARENT_CONTEXT = 1u32 << 19;
+        const SESSIONS = 1u32 << 18;
+        const EXPORT_CDI = 1u32 << 17;
+        const CWT = 1u32 << 16;
+        const COMMAND_COUNTER = 1u32 << 15;
+        const HASH_STREAM = 1u32 << 14;
+        const MEASUREMENT_CACHE = 1u32 << 13;
+        const CONTEXT_INFO = 1u32 << 12;
+        const LAYERED_CERTS = 1u32 << 11;
+        const HANDLE_MAC = 1u32 << 10;
+        const DEVICE_ID_CERT = 1u32 << 9;
+        const COMMAND_AUTH = 1u32 << 8;
+        const SVN = 1u32 << 7;
+        const CERT_CACHE = 1u32 << 6;
+        const SIMULATION_SEED = 1u32 << 5;
+        const ATTEST = 1u32 << 4;
+        const SEAL = 1u32 << 3;
     }
 }
 
@@ -33,6 +52,9 @@ impl Support {
     pub fn auto_init(&self) -> bool {
         self.contains(Support::AUTO_INIT)
     }
+    pub fn tagging(&self) -> bool {
+        self.contains(Support::TAGGING)
+    }
     pub fn rotate_context(&self) -> bool {
         self.contains(Support::ROTATE_CONTEXT)
     }
@@ -45,6 +67,9 @@ impl Support {
     pub fn is_symmetric(&self) -> bool {
         self.contains(Support::IS_SYMMETRIC)
     }
+    pub fn extend_tci(&self) -> bool {
+        self.contains(Support::EXTEND_TCI)
+    }
     pub fn internal_info(&self) -> bool {
         self.contains(Support::INTERNAL_INFO)
     }
@@ -57,6 +82,54 @@ impl Support {
     pub fn retain_parent_context(&self) -> bool {
         self.contains(Support::RETAIN_PARENT_CONTEXT)
     }
+    pub fn sessions(&self) -> bool {
+        self.contains(Support::SESSIONS)
+    }
+    pub fn export_cdi(&self) -> bool {
+        self.contains(Support::EXPORT_CDI)
+    }
+    pub fn cwt(&self) -> bool {
+        self.contains(Support::CWT)
+    }
+    pub fn command_counter(&self) -> bool {
+        self.contains(Support::COMMAND_COUNTER)
+    }
+    pub fn hash_stream(&self) -> bool {
+        self.contains(Support::HASH_STREAM)
+    }
+    pub fn measurement_cache(&self) -> bool {
+        self.contains(Support::MEASUREMENT_CACHE)
+    }
+    pub fn context_info(&self) -> bool {
+        self.contains(Support::CONTEXT_INFO)
+    }
+    pub fn layered_certs(&self) -> bool {
+        self.contains(Support::LAYERED_CERTS)
+    }
+    pub fn handle_mac(&self) -> bool {
+        self.contains(Support::HANDLE_MAC)
+    }
+    pub fn device_id_cert(&self) -> bool {
+        self.contains(Support::DEVICE_ID_CERT)
+    }
+    pub fn command_auth(&self) -> bool {
+        self.contains(Support::COMMAND_AUTH)
+    }
+    pub fn svn(&self) -> bool {
+        self.contains(Support::SVN)
+    }
+    pub fn cert_cache(&self) -> bool {
+        self.contains(Support::CERT_CACHE)
+    }
+    pub fn simulation_seed(&self) -> bool {
+        self.contains(Support::SIMULATION_SEED)
+    }
+    pub fn attest(&self) -> bool {
+        self.contains(Support::ATTEST)
+    }
+    pub fn seal(&self) -> bool {
+        self.contains(Support::SEAL)
+    }
 
     /// Disables supported features based on compilation features
     pub fn preprocess_support(&self) -> Support {
@@ -74,6 +147,10 @@ impl Support {
         {
             support.insert(Support::AUTO_INIT);
         }
+        #[cfg(feature = "disable_tagging")]
+        {
+            support.insert(Support::TAGGING);
+        }
         #[cfg(feature = "disable_rotate_context")]
         {
             support.insert(Support::ROTATE_CONTEXT);
@@ -90,6 +167,10 @@ impl Support {
         {
             support.insert(Support::IS_SYMMETRIC);
         }
+        #[cfg(feature = "disable_extend_tci")]
+        {
+            support.insert(Support::EXTEND_TCI);
+        }
         #[cfg(feature = "disable_internal_info")]
         {
             support.insert(Support::INTERNAL_INFO);
@@ -106,6 +187,70 @@ impl Support {
         {
             support.insert(Support::RETAIN_PARENT_CONTEXT);
         }
+        #[cfg(feature = "disable_sessions")]
+        {
+            support.insert(Support::SESSIONS);
+        }
+        #[cfg(feature = "disable_export_cdi")]
+        {
+            support.insert(Support::EXPORT_CDI);
+        }
+        #[cfg(feature = "disable_cwt")]
+        {
+            support.insert(Support::CWT);
+        }
+        #[cfg(feature = "disable_command_counter")]
+        {
+            support.insert(Support::COMMAND_COUNTER);
+        }
+        #[cfg(feature = "disable_hash_stream")]
+        {
+            support.insert(Support::HASH_STREAM);
+        }
+        #[cfg(feature = "disable_measurement_cache")]
+        {
+            support.insert(Support::MEASUREMENT_CACHE);
+        }
+        #[cfg(feature = "disable_context_info")]
+        {
+            support.insert(Support::CONTEXT_INFO);
+        }
+        #[cfg(feature = "disable_layered_certs")]
+        {
+            support.insert(Support::LAYERED_CERTS);
+        }
+        #[cfg(feature = "disable_handle_mac")]
+        {
+            support.insert(Support::HANDLE_MAC);
+        }
+        #[cfg(feature = "disable_device_id_cert")]
+        {
+            support.insert(Support::DEVICE_ID_CERT);
+        }
+        #[cfg(feature = "disable_command_auth")]
+        {
+            support.insert(Support::COMMAND_AUTH);
+        }
+        #[cfg(feature = "disable_svn")]
+        {
+            support.insert(Support::SVN);
+        }
+        #[cfg(feature = "disable_cert_cache")]
+        {
+            support.insert(Support::CERT_CACHE);
+        }
+        #[cfg(feature = "disable_simulation_seed")]
+        {
+            support.insert(Support::SIMULATION_SEED);
+        }
+        #[cfg(feature = "disable_attest")]
+        {
+            support.insert(Support::ATTEST);
+        }
+        #[cfg(feature = "disable_seal")]
+        {
+            support.insert(Support::SEAL);
+        }
         self.difference(support)
     }
 }
@@ -134,6 +279,9 @@ pub mod test {
         // Supports auto-init.
         let flags = Support::AUTO_INIT.bits();
         assert_eq!(flags, 1 << 29);
+        // Supports tagging.
+        let flags = Support::TAGGING.bits();
+        assert_eq!(flags, 1 << 28);
         // Supports rotate context.
         let flags = Support::ROTATE_CONTEXT.bits();
         assert_eq!(flags, 1 << 27);
@@ -146,6 +294,9 @@ pub mod test {
         // Supports is symmetric.
         let flags = Support::IS_SYMMETRIC.bits();
         assert_eq!(flags, 1 << 24);
+        // Supports extend TCI.
+        let flags = Support::EXTEND_TCI.bits();
+        assert_eq!(flags, 1 << 23);
         // Supports internal info.
         let flags = Support::INTERNAL_INFO.bits();
         assert_eq!(flags, 1 << 22);
@@ -157,6 +308,54 @@ pub mod test {
         assert_eq!(flags, 1 << 20);
         let flags = Support::RETAIN_PARENT_CONTEXT.bits();
         assert_eq!(flags, 1 << 19);
+        // Supports encrypted sessions.
+        let flags = Support::SESSIONS.bits();
+        assert_eq!(flags, 1 << 18);
+        // Supports exporting wrapped CDIs.
+        let flags = Support::EXPORT_CDI.bits();
+        assert_eq!(flags, 1 << 17);
+        // Supports CWT certificates.
+        let flags = Support::CWT.bits();
+        assert_eq!(flags, 1 << 16);
+        // Supports per-context command counters.
+        let flags = Support::COMMAND_COUNTER.bits();
+        assert_eq!(flags, 1 << 15);
+        // Supports streaming hashes.
+        let flags = Support::HASH_STREAM.bits();
+        assert_eq!(flags, 1 << 14);
+        // Supports caching measurement digests and CDIs.
+        let flags = Support::MEASUREMENT_CACHE.bits();
+        assert_eq!(flags, 1 << 13);
+        // Supports GetContextInfo.
+        let flags = Support::CONTEXT_INFO.bits();
+        assert_eq!(flags, 1 << 12);
+        // Supports issuing certificates with the parent context's key.
+        let flags = Support::LAYERED_CERTS.bits();
+        assert_eq!(flags, 1 << 11);
+        // Supports MACing context handles.
+        let flags = Support::HANDLE_MAC.bits();
+        assert_eq!(flags, 1 << 10);
+        // Supports a self-signed DeviceID certificate.
+        let flags = Support::DEVICE_ID_CERT.bits();
+        assert_eq!(flags, 1 << 9);
+        // Supports HMAC-authenticated commands.
+        let flags = Support::COMMAND_AUTH.bits();
+        assert_eq!(flags, 1 << 8);
+        // Supports security version numbers in DeriveContext.
+        let flags = Support::SVN.bits();
+        assert_eq!(flags, 1 << 7);
+        // Supports returning the last certificate again from a repeated CertifyKey.
+        let flags = Support::CERT_CACHE.bits();
+        assert_eq!(flags, 1 << 6);
+        // Supports seeding simulation contexts.
+        let flags = Support::SIMULATION_SEED.bits();
+        assert_eq!(flags, 1 << 5);
+        // Supports attestation tokens.
+        let flags = Support::ATTEST.bits();
+        assert_eq!(flags, 1 << 4);
+        // Supports Seal and Unseal.
+        let flags = Support::SEAL.bits();
+        assert_eq!(flags, 1 << 3);
         // Supports a couple combos.
         let flags = (Support::SIMULATION
             | Support::AUTO_INIT
@@ -179,14 +378,32 @@ pub mod test {
             (1 << 31)
                 | (1 << 30)
                 | (1 << 29)
+                | (1 << 28)
                 | (1 << 27)
                 | (1 << 26)
                 | (1 << 25)
                 | (1 << 24)
+                | (1 << 23)
                 | (1 << 22)
                 | (1 << 21)
                 | (1 << 20)
                 | (1 << 19)
+                | (1 << 18)
+                | (1 << 17)
+                | (1 << 16)
+                | (1 << 15)
+                | (1 << 14)
+                | (1 << 13)
+                | (1 << 12)
+                | (1 << 11)
+                | (1 << 10)
+                | (1 << 9)
+                | (1 << 8)
+                | (1 << 7)
+                | (1 << 6)
+                | (1 << 5)
+                | (1 << 4)
+                | (1 << 3)
         );
     }
 }
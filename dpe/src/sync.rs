@@ -0,0 +1,244 @@
+// Licensed under the Apache-2.0 license.
+
+//! Sharing one DPE instance between several command sources.
+//!
+//! `DpeInstance` takes `&mut self` for every command, and commands must run
+//! one at a time: a command reads and updates several contexts, the pending
+//! certificate and the sessions, so interleaving two of them could leave the
+//! context tree inconsistent. `SyncDpe` holds an instance together with its
+//! environment and serializes commands behind a lock, so that mailbox or
+//! interrupt handlers can share it through `&SyncDpe`.
+//!
+//! The lock is pluggable through `RawMutex`. With the `critical-section`
+//! feature, `CriticalSection` masks interrupts or takes the platform's
+//! critical section for the duration of each command. With the `std` feature,
+//! `std::sync::Mutex<()>` can be used on hosts. Commands hold the lock from
+//! deserialization to the end of response serialization, so a handler waiting
+//! on it may wait for as long as the slowest command, typically CertifyKey.
+//!
+//! The state sits in a `RefCell` behind the lock, so a handler that calls back
+//! into the same `SyncDpe` while it is executing a command, e.g. from a
+//! `Platform` callback on a reentrant critical section, gets `InternalError`
+//! rather than a second mutable reference.
+
+use crate::{
+    dpe_instance::{DpeEnv, DpeTypes},
+    response::{DpeErrorCode, Response},
+    DpeInstance,
+};
+use core::cell::RefCell;
+
+/// A lock that gives exclusive access to the state of a `SyncDpe`.
+///
+/// # Safety
+///
+/// While `f` runs, no other call to `lock` on the same lock may run `f`, from
+/// any thread or interrupt handler. Calls nested within `f` may either block
+/// forever or run their own `f`; `SyncDpe` detects the latter.
+pub unsafe trait RawMutex {
+    /// Runs `f` while holding the lock.
+    fn lock<R>(&self, f: impl FnOnce() -> R) -> R;
+}
+
+/// Serializes commands with the `critical-section` crate, for no_std targets.
+#[cfg(feature = "critical-section")]
+#[derive(Default)]
+pub struct CriticalSection;
+
+// SAFETY: A critical section excludes every other critical section on the
+// system. Nested ones are reentrant, which `SyncDpe` rejects.
+#[cfg(feature = "critical-section")]
+unsafe impl RawMutex for CriticalSection {
+    fn lock<R>(&self, f: impl FnOnce() -> R) -> R {
+        critical_section::with(|_| f())
+    }
+}
+
+// SAFETY: The mutex guard excludes every other thread until it is dropped.
+#[cfg(any(test, feature = "std"))]
+unsafe impl RawMutex for std::sync::Mutex<()> {
+    fn lock<R>(&self, f: impl FnOnce() -> R) -> R {
+        // The guarded data is `()`, so a panic in another holder can't have
+        // left it inconsistent.
+        let _guard = self.lock().unwrap_or_else(|err| err.into_inner());
+        f()
+    }
+}
+
+struct State<'a, T: DpeTypes + 'a> {
+    dpe: DpeInstance,
+    env: DpeEnv<'a, T>,
+}
+
+/// A `DpeInstance` and its environment that can be shared between command
+/// sources. See the module documentation.
+pub struct SyncDpe<'a, T: DpeTypes + 'a, M: RawMutex> {
+    lock: M,
+    state: RefCell<State<'a, T>>,
+}
+
+// SAFETY: The state is only reached through `RawMutex::lock`, which excludes
+// other threads, and the `RefCell` borrow is released before the lock is.
+// The state itself may move between threads, so it must be `Send`.
+unsafe impl<'a, T: DpeTypes + 'a, M: RawMutex + Sync> Sync for SyncDpe<'a, T, M> where
+    DpeEnv<'a, T>: Send
+{
+}
+
+impl<'a, T: DpeTypes + 'a, M: RawMutex> SyncDpe<'a, T, M> {
+    pub fn new(dpe: DpeInstance, env: DpeEnv<'a, T>, lock: M) -> Self {
+        Self {
+            lock,
+            state: RefCell::new(State { dpe, env }),
+        }
+    }
+
+    /// Deserializes and executes `cmd` like
+    /// `DpeInstance::execute_serialized_command`, holding the lock until the
+    /// response is ready.
+    ///
+    /// # Arguments
+    ///
+    /// * `locality` - which hardware locality is making the request
+    /// * `cmd` - serialized command
+    pub fn execute_serialized_command(
+        &self,
+        locality: u32,
+        cmd: &[u8],
+    ) -> Result<Response, DpeErrorCode> {
+        self.with(|dpe, env| dpe.execute_serialized_command(env, locality, cmd))?
+    }
+
+    /// Runs `f` with exclusive access to the instance and its environment,
+    /// e.g. to serialize the instance or to execute vendor commands.
+    ///
+    /// Fails with `InternalError` if called from within another `with` on the
+    /// same instance.
+    pub fn with<R>(
+        &self,
+        f: impl FnOnce(&mut DpeInstance, &mut DpeEnv<'a, T>) -> R,
+    ) -> Result<R, DpeErrorCode> {
+        self.lock.lock(|| {
+            let mut state = self
+                .state
+                .try_borrow_mut()
+                .map_err(|_| DpeErrorCode::InternalError)?;
+            let State { dpe, env } = &mut *state;
+            Ok(f(dpe, env))
+        })
+    }
+
+    /// Returns the instance and its environment.
+    pub fn into_inner(self) -> (DpeInstance, DpeEnv<'a, T>) {
+        let State { dpe, env } = self.state.into_inner();
+        (dpe, env)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        commands::{Command, CommandHdr, DeriveContextCmd, DeriveContextFlags},
+        context::{ContextHandle, ContextState},
+        dpe_instance::tests::{TestTypes, TEST_LOCALITIES},
+        support::Support,
+        DPE_PROFILE, MAX_HANDLES,
+    };
+    use caliptra_cfi_lib_git::CfiCounter;
+    use crypto::OpensslCrypto;
+    use platform::default::DefaultPlatform;
+    use std::sync::Mutex;
+    use zerocopy::AsBytes;
+
+    fn new_sync_dpe(support: Support) -> SyncDpe<'static, TestTypes, Mutex<()>> {
+        let mut env = DpeEnv::<TestTypes> {
+            crypto: OpensslCrypto::new(),
+            platform: DefaultPlatform,
+            arena: &mut [],
+        };
+        let dpe = DpeInstance::new(&mut env, support).unwrap();
+        SyncDpe::new(dpe, env, Mutex::new(()))
+    }
+
+    fn derive_child_cmd() -> Vec<u8> {
+        let mut cmd = CommandHdr::new_for_test(Command::DERIVE_CONTEXT)
+            .as_bytes()
+            .to_vec();
+        cmd.extend_from_slice(
+            DeriveContextCmd {
+                handle: ContextHandle::default(),
+                data: [0; DPE_PROFILE.get_hash_size()],
+                flags: DeriveContextFlags::RETAIN_PARENT_CONTEXT
+                    | DeriveContextFlags::CHANGE_LOCALITY,
+                tci_type: 0,
+                target_locality: TEST_LOCALITIES[1],
+            }
+            .as_bytes(),
+        );
+        cmd
+    }
+
+    #[test]
+    fn test_concurrent_commands() {
+        CfiCounter::reset_for_test();
+        let dpe = new_sync_dpe(Support::AUTO_INIT | Support::RETAIN_PARENT_CONTEXT);
+        let cmd = derive_child_cmd();
+
+        // Each thread derives children of the default context in another
+        // locality until the context table is full. No command may observe another half done,
+        // so the table ends up exactly full.
+        let derived: usize = std::thread::scope(|s| {
+            let threads: Vec<_> = (0..4)
+                .map(|_| {
+                    s.spawn(|| {
+                        let mut derived = 0;
+                        while let Ok(Response::DeriveContext(_)) =
+                            dpe.execute_serialized_command(TEST_LOCALITIES[0], &cmd)
+                        {
+                            derived += 1;
+                        }
+                        derived
+                    })
+                })
+                .collect();
+            threads.into_iter().map(|t| t.join().unwrap()).sum()
+        });
+        assert_eq!(derived, MAX_HANDLES - 1);
+
+        let (dpe, _) = dpe.into_inner();
+        assert!(dpe.contexts.iter().all(|c| c.state == ContextState::Active));
+    }
+
+    /// Runs `f` without excluding anything, like a critical section nested
+    /// in another.
+    struct Reentrant;
+
+    // SAFETY: Only used from a single thread.
+    unsafe impl RawMutex for Reentrant {
+        fn lock<R>(&self, f: impl FnOnce() -> R) -> R {
+            f()
+        }
+    }
+
+    #[test]
+    fn test_reentrant_access() {
+        CfiCounter::reset_for_test();
+        let (dpe, env) = new_sync_dpe(Support::AUTO_INIT).into_inner();
+        let dpe = SyncDpe::new(dpe, env, Reentrant);
+        let get_profile = CommandHdr::new_for_test(Command::GET_PROFILE);
+
+        assert_eq!(
+            dpe.with(|_, _| {
+                dpe.execute_serialized_command(TEST_LOCALITIES[0], get_profile.as_bytes())
+            }),
+            Ok(Err(DpeErrorCode::InternalError))
+        );
+
+        // The instance is usable again once the outer call returns.
+        assert!(matches!(
+            dpe.execute_serialized_command(TEST_LOCALITIES[0], get_profile.as_bytes()),
+            Ok(Response::GetProfile(_))
+        ));
+    }
+}
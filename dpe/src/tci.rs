@@ -1,5 +1,6 @@
 // Licensed under the Apache-2.0 license.
-use crate::DPE_PROFILE;
+use crate::{U8Bool, DPE_PROFILE};
+use core::mem::size_of;
 use zerocopy::{AsBytes, FromBytes};
 use zeroize::Zeroize;
 
@@ -10,17 +11,38 @@ pub struct TciNodeData {
     pub tci_cumulative: TciMeasurement,
     pub tci_current: TciMeasurement,
     pub locality: u32,
+    /// Security version number of the context. Given to DeriveContext or
+    /// inherited from the parent, and never lower than the parent's. Only
+    /// reported in certificates if `has_svn` is set.
+    pub svn: u32,
+    /// Whether `svn` was given when the context was derived
+    pub has_svn: U8Bool,
+
+    // unused buffer added to make TciNodeData word aligned and remove padding
+    pub(crate) reserved: [u8; 3],
 }
 
 impl TciNodeData {
+    /// Size of the fields folded into the chain digest. The SVN comes after
+    /// them and is left out, so keys don't change with it.
+    const MEASURED_SIZE: usize = 2 * size_of::<u32>() + 2 * DPE_PROFILE.get_tci_size();
+
     pub const fn new() -> TciNodeData {
         TciNodeData {
             tci_type: 0,
             tci_cumulative: TciMeasurement([0; DPE_PROFILE.get_tci_size()]),
             tci_current: TciMeasurement([0; DPE_PROFILE.get_tci_size()]),
             locality: 0,
+            svn: 0,
+            has_svn: U8Bool::new(false),
+            reserved: [0; 3],
         }
     }
+
+    /// Returns the bytes of the node that are measured into the chain digest.
+    pub fn measured_bytes(&self) -> &[u8] {
+        &self.as_bytes()[..Self::MEASURED_SIZE]
+    }
 }
 
 #[repr(transparent)]
@@ -0,0 +1,35 @@
+/*++
+Licensed under the Apache-2.0 license.
+
+Abstract:
+    Optional tracing of command execution.
+--*/
+
+// Forwards to `log` and/or `defmt` when their features are enabled and only
+// evaluates its arguments otherwise, so values that exist just to be traced
+// don't trigger unused warnings. Messages must use format specifiers both
+// crates understand, such as `{}`, `{:?}` and `{:#x}`, on primitive values.
+macro_rules! dpe_log {
+    ($level:ident, $($arg:tt)*) => {{
+        #[cfg(feature = "log")]
+        log::$level!($($arg)*);
+        #[cfg(feature = "defmt")]
+        defmt::$level!($($arg)*);
+        #[cfg(not(any(feature = "log", feature = "defmt")))]
+        let _ = ($($arg)*);
+    }};
+}
+
+/// Records the outcome of the dispatcher.
+macro_rules! dpe_debug {
+    ($($arg:tt)*) => {
+        dpe_log!(debug, $($arg)*)
+    };
+}
+
+/// Records the arguments a command handler was called with.
+macro_rules! dpe_trace {
+    ($($arg:tt)*) => {
+        dpe_log!(trace, $($arg)*)
+    };
+}
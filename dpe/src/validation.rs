@@ -16,6 +16,7 @@ use caliptra_cfi_lib_git::{
     cfi_assert, cfi_assert_eq, cfi_assert_le, cfi_assert_lt, cfi_assert_ne,
 };
 use cfg_if::cfg_if;
+use zerocopy::AsBytes;
 
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
 #[repr(u16)]
@@ -47,6 +48,8 @@ pub enum ValidationError {
     InactiveParent = 0x16,
     InactiveChild = 0x17,
     DpeNotMarkedInitialized = 0x18,
+    TaggingNotSupported = 0x19,
+    InactiveContextNotScrubbed = 0x1A,
 }
 
 impl ValidationError {
@@ -204,11 +207,15 @@ impl<'a> DpeValidator<'a> {
         if !self.dpe.support.internal_info() && context.uses_internal_input_info() {
             return Err(ValidationError::InternalInfoNotSupported);
         }
+        if !self.dpe.support.tagging() && context.has_tag() {
+            return Err(ValidationError::TaggingNotSupported);
+        }
         cfg_if! {
             if #[cfg(not(feature = "no-cfi"))] {
                 cfi_assert!(self.dpe.support.simulation() || context.context_type != ContextType::Simulation);
                 cfi_assert!(self.dpe.support.internal_dice() || !context.uses_internal_input_dice());
                 cfi_assert!(self.dpe.support.internal_info() || !context.uses_internal_input_info());
+                cfi_assert!(self.dpe.support.tagging() || !context.has_tag());
             }
         }
         // initialized contexts will always have parent = Context::ROOT_INDEX and their allow_ca and allow_x509
@@ -246,8 +253,11 @@ impl<'a> DpeValidator<'a> {
             || context.allow_ca()
             || context.allow_x509()
             || context.uses_internal_input_info()
+            || context.has_tag()
         {
             Err(ValidationError::InactiveContextWithFlagSet)
+        } else if context.as_bytes() != Context::new().as_bytes() {
+            Err(ValidationError::InactiveContextNotScrubbed)
         } else {
             cfg_if! {
                 if #[cfg(not(feature = "no-cfi"))] {
@@ -258,6 +268,8 @@ impl<'a> DpeValidator<'a> {
                     cfi_assert!(!context.allow_ca());
                     cfi_assert!(!context.allow_x509());
                     cfi_assert!(!context.uses_internal_input_info());
+                    cfi_assert!(!context.has_tag());
+                    cfi_assert!(context.as_bytes() == Context::new().as_bytes());
                 }
             }
             Ok(())
@@ -282,7 +294,7 @@ impl<'a> DpeValidator<'a> {
             }
         }
         // Check if any children do not exist
-        for child in flags_iter(context.children, 32) {
+        for child in flags_iter(context.children, u64::BITS as usize) {
             if child >= MAX_HANDLES {
                 return Err(ValidationError::ChildDoesNotExist);
             }
@@ -459,7 +471,7 @@ pub mod tests {
         support::{test::SUPPORT, Support},
         tci::TciMeasurement,
         validation::{DpeValidator, ValidationError},
-        DpeInstance, U8Bool, DPE_PROFILE,
+        DpeInstance, U8Bool, DPE_PROFILE, MAX_HANDLES,
     };
 
     #[test]
@@ -468,6 +480,7 @@ pub mod tests {
         let mut env = DpeEnv::<TestTypes> {
             crypto: OpensslCrypto::new(),
             platform: DefaultPlatform,
+            arena: &mut [],
         };
         let mut dpe_validator = DpeValidator {
             dpe: &mut DpeInstance::new(&mut env, SUPPORT).unwrap(),
@@ -501,24 +514,27 @@ pub mod tests {
         dpe_validator.dpe.contexts[2].children = 0;
         assert_eq!(dpe_validator.validate_context_forest(), Ok(()));
 
-        // validation fails on multiple normal trees in forest
-        dpe_validator.dpe.contexts[10].state = ContextState::Active;
-        dpe_validator.dpe.contexts[10].children = (1 << 11) | (1 << 12);
-        dpe_validator.dpe.contexts[11].state = ContextState::Active;
-        dpe_validator.dpe.contexts[12].state = ContextState::Active;
+        // validation fails on multiple normal trees in forest, which must not
+        // overlap the contexts used above
+        const _: () = assert!(MAX_HANDLES >= 6);
+        let (a, b, c) = (MAX_HANDLES - 3, MAX_HANDLES - 2, MAX_HANDLES - 1);
+        dpe_validator.dpe.contexts[a].state = ContextState::Active;
+        dpe_validator.dpe.contexts[a].children = (1 << b) | (1 << c);
+        dpe_validator.dpe.contexts[b].state = ContextState::Active;
+        dpe_validator.dpe.contexts[c].state = ContextState::Active;
         assert_eq!(
             dpe_validator.validate_context_forest(),
             Err(ValidationError::MultipleNormalConnectedComponents)
         );
 
         // validation passes on forest with normal tree and simulation tree
-        dpe_validator.dpe.contexts[10].context_type = ContextType::Simulation;
-        dpe_validator.dpe.contexts[11].context_type = ContextType::Simulation;
-        dpe_validator.dpe.contexts[12].context_type = ContextType::Simulation;
+        dpe_validator.dpe.contexts[a].context_type = ContextType::Simulation;
+        dpe_validator.dpe.contexts[b].context_type = ContextType::Simulation;
+        dpe_validator.dpe.contexts[c].context_type = ContextType::Simulation;
         assert_eq!(dpe_validator.validate_context_forest(), Ok(()));
 
         // validation fails on tree with both simulation and normal contexts
-        dpe_validator.dpe.contexts[11].context_type = ContextType::Normal;
+        dpe_validator.dpe.contexts[b].context_type = ContextType::Normal;
         assert_eq!(
             dpe_validator.validate_context_forest(),
             Err(ValidationError::MixedContextTypeConnectedComponents)
@@ -531,6 +547,7 @@ pub mod tests {
         let mut env = DpeEnv::<TestTypes> {
             crypto: OpensslCrypto::new(),
             platform: DefaultPlatform,
+            arena: &mut [],
         };
         let mut dpe_validator = DpeValidator {
             dpe: &mut DpeInstance::new(&mut env, Support::empty()).unwrap(),
@@ -583,6 +600,7 @@ pub mod tests {
         let mut env = DpeEnv::<TestTypes> {
             crypto: OpensslCrypto::new(),
             platform: DefaultPlatform,
+            arena: &mut [],
         };
         let mut dpe_validator = DpeValidator {
             dpe: &mut DpeInstance::new(&mut env, Support::all().difference(Support::AUTO_INIT))
@@ -597,7 +615,7 @@ pub mod tests {
         );
 
         dpe_validator.dpe.contexts[0].parent_idx = Context::ROOT_INDEX;
-        dpe_validator.dpe.contexts[0].children = u32::MAX;
+        dpe_validator.dpe.contexts[0].children = u64::MAX;
         assert_eq!(
             dpe_validator.validate_dpe_state(),
             Err(ValidationError::InactiveContextWithChildren)
@@ -618,6 +636,14 @@ pub mod tests {
             Err(ValidationError::InactiveContextWithFlagSet)
         );
 
+        dpe_validator.dpe.contexts[0].allow_ca = U8Bool::new(false);
+        dpe_validator.dpe.contexts[0].locality = 1;
+        assert_eq!(
+            dpe_validator.validate_dpe_state(),
+            Err(ValidationError::InactiveContextNotScrubbed)
+        );
+        dpe_validator.dpe.contexts[0].locality = 0;
+
         // active context validation
         dpe_validator.dpe.has_initialized = U8Bool::new(true);
         dpe_validator.dpe.contexts[0].state = ContextState::Active;
@@ -628,34 +654,38 @@ pub mod tests {
         );
 
         dpe_validator.dpe.contexts[0].parent_idx = Context::ROOT_INDEX;
-        dpe_validator.dpe.contexts[0].children = 1 << 30;
-        assert_eq!(
-            dpe_validator.validate_dpe_state(),
-            Err(ValidationError::ChildDoesNotExist)
-        );
+        // Every bit in the children bitmap maps to a context when MAX_HANDLES is 64.
+        if MAX_HANDLES < u64::BITS as usize {
+            dpe_validator.dpe.contexts[0].children = 1 << MAX_HANDLES;
+            assert_eq!(
+                dpe_validator.validate_dpe_state(),
+                Err(ValidationError::ChildDoesNotExist)
+            );
+        }
 
-        dpe_validator.dpe.contexts[0].children = 1 << 10;
+        let idx = MAX_HANDLES - 1;
+        dpe_validator.dpe.contexts[0].children = 1 << idx;
         assert_eq!(
             dpe_validator.validate_dpe_state(),
             Err(ValidationError::InactiveChild)
         );
 
         dpe_validator.dpe.contexts[0].children = 0;
-        dpe_validator.dpe.contexts[0].parent_idx = 10;
+        dpe_validator.dpe.contexts[0].parent_idx = idx as u8;
         assert_eq!(
             dpe_validator.validate_dpe_state(),
             Err(ValidationError::InactiveParent)
         );
 
-        dpe_validator.dpe.contexts[10].state = ContextState::Active;
-        dpe_validator.dpe.contexts[0].children = 1 << 10;
+        dpe_validator.dpe.contexts[idx].state = ContextState::Active;
+        dpe_validator.dpe.contexts[0].children = 1 << idx;
         assert_eq!(
             dpe_validator.validate_dpe_state(),
             Err(ValidationError::ParentChildLinksCorrupted)
         );
 
         dpe_validator.dpe.contexts[0].children = 0;
-        dpe_validator.dpe.contexts[0].parent_idx = 10;
+        dpe_validator.dpe.contexts[0].parent_idx = idx as u8;
         assert_eq!(
             dpe_validator.validate_dpe_state(),
             Err(ValidationError::ParentChildLinksCorrupted)
@@ -708,6 +738,7 @@ pub mod tests {
         let mut env = DpeEnv::<TestTypes> {
             crypto: OpensslCrypto::new(),
             platform: DefaultPlatform,
+            arena: &mut [],
         };
         let mut dpe_validator = DpeValidator {
             dpe: &mut DpeInstance::new(&mut env, Support::empty()).unwrap(),
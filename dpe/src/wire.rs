@@ -0,0 +1,211 @@
+/*++
+Licensed under the Apache-2.0 license.
+
+Abstract:
+    Byte order of the command and response wire format.
+--*/
+
+/// Converts a command or response structure between host byte order and the
+/// little-endian byte order used on the wire.
+///
+/// Structures are read from and written to the wire with zerocopy, which
+/// copies their memory as-is. On big-endian hosts every multi-byte integer
+/// must be swapped after reading and before writing. Byte arrays such as
+/// handles and digests are already in wire order and are left alone.
+pub trait WireEndian: Sized {
+    /// Reverses the byte order of every multi-byte integer field.
+    fn swap_bytes(&mut self);
+
+    /// Converts `self` from host to wire byte order. This is a no-op on
+    /// little-endian hosts.
+    fn to_le(mut self) -> Self {
+        if cfg!(target_endian = "big") {
+            self.swap_bytes();
+        }
+        self
+    }
+
+    /// Converts `wire` from wire to host byte order. This is a no-op on
+    /// little-endian hosts.
+    fn from_le(wire: Self) -> Self {
+        wire.to_le()
+    }
+}
+
+impl WireEndian for u16 {
+    fn swap_bytes(&mut self) {
+        *self = u16::swap_bytes(*self);
+    }
+}
+
+impl WireEndian for u32 {
+    fn swap_bytes(&mut self) {
+        *self = u32::swap_bytes(*self);
+    }
+}
+
+/// Implements `WireEndian` for a structure by swapping the listed fields, or
+/// for a `bitflags` type by swapping its bits.
+macro_rules! impl_wire_endian {
+    (flags $ty:ty) => {
+        impl $crate::wire::WireEndian for $ty {
+            fn swap_bytes(&mut self) {
+                *self = Self::from_bits_retain(self.bits().swap_bytes());
+            }
+        }
+    };
+    ($ty:ty { $($field:ident),* $(,)? }) => {
+        impl $crate::wire::WireEndian for $ty {
+            fn swap_bytes(&mut self) {
+                $($crate::wire::WireEndian::swap_bytes(&mut self.$field);)*
+            }
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        commands::{
+            Command, CommandHdr, DeriveContextCmd, DeriveContextFlags, HashUpdateCmd, SignCmd,
+            SignFlags,
+        },
+        context::ContextHandle,
+        response::{
+            DpeErrorCode, GetContextInfoResp, GetProfileResp, Response, ResponseHdr, SessionResp,
+        },
+        session::SessionMsgHdr,
+        support::Support,
+        DPE_PROFILE,
+    };
+    use zerocopy::AsBytes;
+
+    const A: u32 = 0x0102_0304;
+    const B: u32 = 0x0506_0708;
+    const C: u32 = 0x090A_0B0C;
+
+    /// Returns `value` as a host with the other byte order would hold it.
+    fn swapped<T: WireEndian>(mut value: T) -> T {
+        value.swap_bytes();
+        value
+    }
+
+    fn derive_cmd(n: fn(u32) -> u32) -> DeriveContextCmd {
+        DeriveContextCmd {
+            handle: ContextHandle([0x11; ContextHandle::SIZE]),
+            data: [0x22; DPE_PROFILE.get_hash_size()],
+            flags: DeriveContextFlags::from_bits_retain(n(A)),
+            tci_type: n(B),
+            target_locality: n(C),
+        }
+    }
+
+    fn resp_hdr(n: fn(u32) -> u32) -> ResponseHdr {
+        ResponseHdr {
+            magic: n(A),
+            status: n(B),
+            profile: n(C),
+        }
+    }
+
+    #[test]
+    fn test_swap_bytes_covers_every_field() {
+        // Each structure is built twice, once with swapped integers. Every
+        // field is listed, so a field missing from `swap_bytes` fails here.
+        let hdr = |n: fn(u32) -> u32| CommandHdr {
+            magic: n(A),
+            cmd_id: n(B),
+            profile: n(C),
+        };
+        assert_eq!(swapped(hdr(|n| n)), hdr(u32::swap_bytes));
+
+        assert_eq!(swapped(derive_cmd(|n| n)), derive_cmd(u32::swap_bytes));
+
+        let sign = |n: fn(u32) -> u32| SignCmd {
+            handle: ContextHandle([0x11; ContextHandle::SIZE]),
+            label: [0x22; DPE_PROFILE.get_hash_size()],
+            flags: SignFlags::from_bits_retain(n(A)),
+            digest: [0x33; DPE_PROFILE.get_hash_size()],
+        };
+        assert_eq!(swapped(sign(|n| n)), sign(u32::swap_bytes));
+
+        let update = |n: fn(u32) -> u32| HashUpdateCmd {
+            stream_id: n(A),
+            size: n(B),
+        };
+        assert_eq!(swapped(update(|n| n)), update(u32::swap_bytes));
+
+        let profile = |n: fn(u32) -> u32, v: fn(u16) -> u16| GetProfileResp {
+            resp_hdr: resp_hdr(n),
+            major_version: v(0x0102),
+            minor_version: v(0x0304),
+            vendor_id: n(B),
+            vendor_sku: n(C),
+            max_tci_nodes: n(A),
+            flags: n(B),
+            max_simulation_contexts: n(C),
+            max_cert_chain_depth: n(A),
+        };
+        assert_eq!(
+            swapped(profile(|n| n, |v| v)),
+            profile(u32::swap_bytes, u16::swap_bytes)
+        );
+
+        let info = |n: fn(u32) -> u32| GetContextInfoResp {
+            resp_hdr: resp_hdr(n),
+            index: n(B),
+            parent_index: n(C),
+            flags: n(A),
+            child_count: n(B),
+            tci_type: n(C),
+            locality: n(A),
+        };
+        assert_eq!(swapped(info(|n| n)), info(u32::swap_bytes));
+    }
+
+    #[test]
+    fn test_session_resp_keeps_payload_size() {
+        // `payload_size` never goes on the wire and must stay usable by
+        // `msg_bytes`.
+        let resp = |n: fn(u32) -> u32| SessionResp {
+            hdr: SessionMsgHdr {
+                magic: n(A),
+                session_id: n(B),
+                counter: n(C),
+            },
+            payload: [0; SessionResp::PAYLOAD_SIZE],
+            payload_size: 4,
+        };
+        assert_eq!(swapped(resp(|n| n)), resp(u32::swap_bytes));
+    }
+
+    #[test]
+    fn test_forced_byte_swap_round_trip() {
+        // Emulate a big-endian host: its native structure holds the swapped
+        // integers, and converting it for the wire swaps them back.
+        let native = swapped(derive_cmd(|n| n));
+        let wire = swapped(native);
+        let mut command = CommandHdr::new(Command::DERIVE_CONTEXT).as_bytes().to_vec();
+        command.extend_from_slice(wire.as_bytes());
+        assert_eq!(
+            Command::deserialize(&command),
+            Ok(Command::DeriveContext(derive_cmd(|n| n), None))
+        );
+
+        // The same holds for responses, whose integers are little-endian on
+        // the wire.
+        let resp = Response::GetProfile(GetProfileResp::new(Support::X509, 1, 2, 3, 4));
+        let expected = resp.as_bytes().to_vec();
+        let native = swapped(resp);
+        assert_eq!(
+            &native.as_bytes()[..4],
+            &ResponseHdr::DPE_RESPONSE_MAGIC.to_be_bytes()
+        );
+        assert_eq!(swapped(native).as_bytes(), expected);
+        assert_eq!(
+            &expected[4..8],
+            &DpeErrorCode::NoError.get_error_code().to_le_bytes()
+        );
+    }
+}
@@ -3,16 +3,23 @@
 //! Lightweight X.509 encoding routines for DPE
 //!
 //! DPE requires encoding variable-length certificates. This module provides
-//! this functionality for a no_std environment.
+//! this functionality for a no_std environment on top of `crate::der`. It
+//! can also read back the certificates it produces, so they can be checked
+//! on targets without a full X.509 library.
 
 use crate::{
+    der::{DerReader, DerWriter},
     response::DpeErrorCode,
     tci::{TciMeasurement, TciNodeData},
     DpeProfile, DPE_PROFILE,
 };
 use bitflags::bitflags;
-use crypto::{EcdsaPub, EcdsaSig};
-use platform::{CertValidity, SignerIdentifier, MAX_KEY_IDENTIFIER_SIZE};
+use crypto::{Crypto, Digest, EcdsaPub, EcdsaSig, EcdsaSigFormat};
+#[cfg(feature = "dpe_profile_ed25519_sha512")]
+use crypto::{Ed25519Pub, Ed25519Sig};
+#[cfg(feature = "rsa")]
+use crypto::{RsaPadding, RsaPub, RsaSig};
+use platform::{CertValidity, Fwid, PlatformError, SignerIdentifier, MAX_KEY_IDENTIFIER_SIZE};
 
 pub enum DirectoryString<'a> {
     PrintableString(&'a [u8]),
@@ -38,24 +45,137 @@ impl DirectoryString<'_> {
 
 /// Type for specifying an X.509 RelativeDistinguisedName
 ///
-/// `serial` is expected to hold a hex string of the hash of the public key
+/// In layer certificates `serial` holds a hex string of the hash of the public
+/// key. Leaf certificates name the certified context instead, and carry the
+/// platform's vendor ID and SKU as hex strings in `org` and `org_unit`.
 pub struct Name<'a> {
     pub cn: DirectoryString<'a>,
     pub serial: DirectoryString<'a>,
+    /// organizationName, omitted if `None`
+    pub org: Option<DirectoryString<'a>>,
+    /// organizationalUnitName, omitted if `None`
+    pub org_unit: Option<DirectoryString<'a>>,
+}
+
+impl<'a> Name<'a> {
+    /// The attributes of the name in the order they are encoded, each with
+    /// its attribute type OID.
+    fn attributes(&self) -> impl Iterator<Item = (&'static [u8], &DirectoryString<'a>)> {
+        [
+            (&CertWriter::RDN_COMMON_NAME_OID[..], Some(&self.cn)),
+            (&CertWriter::RDN_SERIALNUMBER_OID[..], Some(&self.serial)),
+            (&CertWriter::RDN_ORGANIZATION_OID[..], self.org.as_ref()),
+            (
+                &CertWriter::RDN_ORGANIZATIONAL_UNIT_OID[..],
+                self.org_unit.as_ref(),
+            ),
+        ]
+        .into_iter()
+        .filter_map(|(oid, value)| Some((oid, value?)))
+    }
+}
+
+/// Returns `val` as a big-endian hex string, as used for the vendor ID and
+/// SKU in subject names.
+pub fn u32_hex_string(val: u32) -> [u8; 8] {
+    const HEX_CHARS: &[u8; 16] = b"0123456789ABCDEF";
+    let mut hex = [0u8; 8];
+    for (i, c) in hex.iter_mut().enumerate() {
+        *c = HEX_CHARS[((val >> (28 - 4 * i)) & 0xF) as usize];
+    }
+    hex
 }
 
 pub struct MeasurementData<'a> {
-    pub label: &'a [u8],
+    pub ueid: &'a [u8],
     pub tci_nodes: &'a [TciNodeData],
     pub is_ca: bool,
     pub supports_recursive: bool,
     pub subject_key_identifier: [u8; MAX_KEY_IDENTIFIER_SIZE],
     pub authority_key_identifier: [u8; MAX_KEY_IDENTIFIER_SIZE],
+    /// Command counter of the certified context, if counters are supported.
+    pub command_counter: Option<u32>,
+    /// Label the certified key was derived with. It follows the locality in
+    /// the vendorInfo of the last TcbInfo, so keys derived from one context
+    /// with different labels can be told apart.
+    pub label: Option<&'a [u8]>,
+    /// FWIDs from `Platform::get_additional_fwids`. They follow the FWIDs of
+    /// the certified context in the last TcbInfo.
+    pub additional_fwids: &'a [Fwid],
+}
+
+/// The public key a certificate or CSR is issued for.
+#[derive(Clone, Copy)]
+pub enum SubjectPubKey<'a> {
+    Ecdsa(&'a EcdsaPub),
+    #[cfg(feature = "rsa")]
+    Rsa(&'a RsaPub),
+    #[cfg(feature = "dpe_profile_ed25519_sha512")]
+    Ed25519(&'a Ed25519Pub),
+}
+
+impl SubjectPubKey<'_> {
+    /// Hashes the key the way it is fingerprinted in serial numbers and key
+    /// identifiers: the uncompressed point of an ECDSA key, the modulus of an
+    /// RSA key, or the encoded Ed25519 key.
+    pub fn digest(&self, crypto: &mut impl Crypto) -> Result<Digest, DpeErrorCode> {
+        match self {
+            SubjectPubKey::Ecdsa(pub_key) => {
+                const INT_SIZE: usize = DPE_PROFILE.alg_len().ecc_int_size();
+                if pub_key.x.len() != INT_SIZE || pub_key.y.len() != INT_SIZE {
+                    return Err(DpeErrorCode::InternalError);
+                }
+
+                let mut point = [0u8; 1 + 2 * INT_SIZE];
+                point[0] = 0x04;
+                point[1..1 + INT_SIZE].copy_from_slice(pub_key.x.bytes());
+                point[1 + INT_SIZE..].copy_from_slice(pub_key.y.bytes());
+                Ok(crypto.hash(DPE_PROFILE.alg_len(), &point)?)
+            }
+            #[cfg(feature = "rsa")]
+            SubjectPubKey::Rsa(pub_key) => {
+                Ok(crypto.hash(DPE_PROFILE.alg_len(), pub_key.modulus())?)
+            }
+            #[cfg(feature = "dpe_profile_ed25519_sha512")]
+            SubjectPubKey::Ed25519(pub_key) => {
+                Ok(crypto.hash(DPE_PROFILE.alg_len(), pub_key.bytes())?)
+            }
+        }
+    }
+}
+
+/// Length of the certificate serial numbers DPE issues. RFC 5280 allows at
+/// most 20 octets.
+pub const SERIAL_NUMBER_SIZE: usize = 20;
+
+/// Derives a certificate serial number from the subject public key.
+///
+/// The serial is the hash of the key, as given by `SubjectPubKey::digest`,
+/// truncated to `SERIAL_NUMBER_SIZE` bytes with the top bit cleared so it
+/// encodes as a positive INTEGER. Certifying the same key again yields the
+/// same serial.
+///
+/// # Arguments
+///
+/// * `crypto` - Crypto implementation used to hash the key.
+/// * `pub_key` - Subject public key.
+pub fn serial_number_from_pubkey(
+    crypto: &mut impl Crypto,
+    pub_key: SubjectPubKey,
+) -> Result<[u8; SERIAL_NUMBER_SIZE], DpeErrorCode> {
+    let digest = pub_key.digest(crypto)?;
+    if digest.len() < SERIAL_NUMBER_SIZE {
+        return Err(DpeErrorCode::InternalError);
+    }
+
+    let mut serial = [0u8; SERIAL_NUMBER_SIZE];
+    serial.copy_from_slice(&digest.bytes()[..SERIAL_NUMBER_SIZE]);
+    serial[0] &= 0x7F;
+    Ok(serial)
 }
 
 pub struct CertWriter<'a> {
-    certificate: &'a mut [u8],
-    offset: usize,
+    der: DerWriter<'a>,
     crit_dice: bool,
 }
 
@@ -69,24 +189,6 @@ bitflags! {
 }
 
 impl CertWriter<'_> {
-    const BOOL_TAG: u8 = 0x1;
-    const INTEGER_TAG: u8 = 0x2;
-    const BIT_STRING_TAG: u8 = 0x3;
-    const OCTET_STRING_TAG: u8 = 0x4;
-    const OID_TAG: u8 = 0x6;
-    const UTF8_STRING_TAG: u8 = 0xC;
-    const PRINTABLE_STRING_TAG: u8 = 0x13;
-    const GENERALIZE_TIME_TAG: u8 = 0x18;
-    const SEQUENCE_TAG: u8 = 0x30;
-    const SEQUENCE_OF_TAG: u8 = 0x30;
-    const SET_OF_TAG: u8 = 0x31;
-
-    const BOOL_SIZE: usize = 1;
-
-    // Constants for setting tag bits
-    const CONTEXT_SPECIFIC: u8 = 0x80; // Used for Implicit/Explicit tags
-    const CONSTRUCTED: u8 = 0x20; // SET{OF} and SEQUENCE{OF} have this bit set
-
     const X509_V3: u64 = 2;
     const CMS_V1: u64 = 1;
     const CMS_V3: u64 = 3;
@@ -95,8 +197,14 @@ impl CertWriter<'_> {
     const ECDSA_OID: &'static [u8] = match DPE_PROFILE {
         // ECDSA with SHA256
         DpeProfile::P256Sha256 => &[0x2A, 0x86, 0x48, 0xCE, 0x3D, 0x04, 0x03, 0x02],
-        // ECDSA with SHA384
-        DpeProfile::P384Sha384 => &[0x2A, 0x86, 0x48, 0xCE, 0x3D, 0x04, 0x03, 0x03],
+        // ECDSA with SHA384. The ML-DSA profile keeps a P-384 alias key.
+        DpeProfile::P384Sha384 | DpeProfile::Mldsa87Sha384 => {
+            &[0x2A, 0x86, 0x48, 0xCE, 0x3D, 0x04, 0x03, 0x03]
+        }
+        // ECDSA with SHA512. The Ed25519 profile keeps a P-521 alias key.
+        DpeProfile::P521Sha512 | DpeProfile::Ed25519Sha512 => {
+            &[0x2A, 0x86, 0x48, 0xCE, 0x3D, 0x04, 0x03, 0x04]
+        }
     };
 
     const EC_PUB_OID: &'static [u8] = &[0x2A, 0x86, 0x48, 0xCE, 0x3D, 0x02, 0x01];
@@ -105,18 +213,56 @@ impl CertWriter<'_> {
         // P256
         DpeProfile::P256Sha256 => &[0x2A, 0x86, 0x48, 0xCE, 0x3D, 0x03, 0x01, 0x07],
         // P384
-        DpeProfile::P384Sha384 => &[0x2B, 0x81, 0x04, 0x00, 0x22],
+        DpeProfile::P384Sha384 | DpeProfile::Mldsa87Sha384 => &[0x2B, 0x81, 0x04, 0x00, 0x22],
+        // P521
+        DpeProfile::P521Sha512 | DpeProfile::Ed25519Sha512 => &[0x2B, 0x81, 0x04, 0x00, 0x23],
     };
 
+    const SHA256_OID: &'static [u8] = &[0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x01];
+    const SHA384_OID: &'static [u8] = &[0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x02];
+    const SHA512_OID: &'static [u8] = &[0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x03];
+
     const HASH_OID: &'static [u8] = match DPE_PROFILE {
-        // SHA256
-        DpeProfile::P256Sha256 => &[0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x01],
-        // SHA384
-        DpeProfile::P384Sha384 => &[0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x02],
+        DpeProfile::P256Sha256 => Self::SHA256_OID,
+        DpeProfile::P384Sha384 | DpeProfile::Mldsa87Sha384 => Self::SHA384_OID,
+        DpeProfile::P521Sha512 | DpeProfile::Ed25519Sha512 => Self::SHA512_OID,
+    };
+
+    // rsaEncryption 1.2.840.113549.1.1.1
+    #[cfg(feature = "rsa")]
+    const RSA_ENCRYPTION_OID: &'static [u8] =
+        &[0x2A, 0x86, 0x48, 0x86, 0xF7, 0x0D, 0x01, 0x01, 0x01];
+
+    // id-mgf1 1.2.840.113549.1.1.8
+    #[cfg(feature = "rsa")]
+    const MGF1_OID: &'static [u8] = &[0x2A, 0x86, 0x48, 0x86, 0xF7, 0x0D, 0x01, 0x01, 0x08];
+
+    // id-RSASSA-PSS 1.2.840.113549.1.1.10
+    #[cfg(feature = "rsa")]
+    const RSASSA_PSS_OID: &'static [u8] = &[0x2A, 0x86, 0x48, 0x86, 0xF7, 0x0D, 0x01, 0x01, 0x0A];
+
+    #[cfg(feature = "rsa")]
+    const RSA_PKCS1_OID: &'static [u8] = match DPE_PROFILE {
+        // sha256WithRSAEncryption 1.2.840.113549.1.1.11
+        DpeProfile::P256Sha256 => &[0x2A, 0x86, 0x48, 0x86, 0xF7, 0x0D, 0x01, 0x01, 0x0B],
+        // sha384WithRSAEncryption 1.2.840.113549.1.1.12
+        DpeProfile::P384Sha384 | DpeProfile::Mldsa87Sha384 => {
+            &[0x2A, 0x86, 0x48, 0x86, 0xF7, 0x0D, 0x01, 0x01, 0x0C]
+        }
+        // sha512WithRSAEncryption 1.2.840.113549.1.1.13
+        DpeProfile::P521Sha512 | DpeProfile::Ed25519Sha512 => {
+            &[0x2A, 0x86, 0x48, 0x86, 0xF7, 0x0D, 0x01, 0x01, 0x0D]
+        }
     };
 
+    // id-Ed25519 1.3.101.112
+    #[cfg(feature = "dpe_profile_ed25519_sha512")]
+    const ED25519_OID: &'static [u8] = &[0x2B, 0x65, 0x70];
+
     const RDN_COMMON_NAME_OID: [u8; 3] = [0x55, 0x04, 0x03];
     const RDN_SERIALNUMBER_OID: [u8; 3] = [0x55, 0x04, 0x05];
+    const RDN_ORGANIZATION_OID: [u8; 3] = [0x55, 0x04, 0x0A];
+    const RDN_ORGANIZATIONAL_UNIT_OID: [u8; 3] = [0x55, 0x04, 0x0B];
 
     // tcg-dice-MultiTcbInfo 2.23.133.5.4.5
     const MULTI_TCBINFO_OID: &'static [u8] = &[0x67, 0x81, 0x05, 0x05, 0x04, 0x05];
@@ -124,6 +270,9 @@ impl CertWriter<'_> {
     // tcg-dice-Ueid 2.23.133.5.4.4
     const UEID_OID: &'static [u8] = &[0x67, 0x81, 0x05, 0x05, 0x04, 0x04];
 
+    // tcg-dice-TcbFreshness 2.23.133.5.4.11
+    const TCB_FRESHNESS_OID: &'static [u8] = &[0x67, 0x81, 0x05, 0x05, 0x04, 0x0B];
+
     // tcg-dice-kp-eca 2.23.133.5.4.100.12
     const ECA_OID: &'static [u8] = &[0x67, 0x81, 0x05, 0x05, 0x04, 0x64, 0x0C];
 
@@ -162,116 +311,64 @@ impl CertWriter<'_> {
     /// Else they will be marked as non-critical.
     pub fn new(cert: &mut [u8], crit_dice: bool) -> CertWriter {
         CertWriter {
-            certificate: cert,
-            offset: 0,
+            der: DerWriter::new(cert),
             crit_dice,
         }
     }
 
-    /// Calculate the number of bytes the ASN.1 size field will be
-    fn get_size_width(size: usize) -> Result<usize, DpeErrorCode> {
-        if size <= 127 {
-            Ok(1)
-        } else if size <= 255 {
-            Ok(2)
-        } else if size <= 65535 {
-            Ok(3)
-        } else {
-            Err(DpeErrorCode::InternalError)
-        }
-    }
-
-    /// Get the size of an ASN.1 structure
-    /// If tagged, includes the tag and size
-    fn get_structure_size(data_size: usize, tagged: bool) -> Result<usize, DpeErrorCode> {
-        let size = if tagged {
-            1 + Self::get_size_width(data_size)? + data_size
-        } else {
-            data_size
-        };
-
-        Ok(size)
-    }
-
-    /// Calculate the number of bytes the ASN.1 INTEGER will be
     /// If `tagged`, include the tag and size fields
-    fn get_integer_bytes_size(integer: &[u8], tagged: bool) -> Result<usize, DpeErrorCode> {
-        let mut len = integer.len();
-        for (i, &byte) in integer.iter().enumerate() {
-            if byte == 0 && i != integer.len() - 1 {
-                len -= 1;
-            } else if (byte & 0x80) != 0 {
-                len += 1;
-                break;
-            } else {
-                break;
-            }
+    fn get_rdn_size(name: &Name, tagged: bool) -> Result<usize, DpeErrorCode> {
+        let mut size = 0;
+        for (oid, value) in name.attributes() {
+            size += DerWriter::structure_size(
+                Self::get_rdn_attribute_size(oid, value, /*tagged=*/ true)?,
+                /*tagged=*/ true,
+            )?;
         }
-
-        Self::get_structure_size(len, tagged)
+        DerWriter::structure_size(size, tagged)
     }
 
-    /// Calculate the number of bytes the ASN.1 INTEGER will be
+    /// Get the size of an AttributeTypeAndValue SEQUENCE
     /// If `tagged`, include the tag and size fields
-    fn get_integer_size(integer: u64, tagged: bool) -> Result<usize, DpeErrorCode> {
-        let bytes = integer.to_be_bytes();
-        Self::get_integer_bytes_size(&bytes, tagged)
-    }
-
-    /// Calculate the number of bytes an ASN.1 raw bytes field will be.
-    /// Can be used for OCTET STRING, OID, UTF8 STRING, etc.
-    /// If `tagged`, include the tag and size fields
-    fn get_bytes_size(bytes: &[u8], tagged: bool) -> Result<usize, DpeErrorCode> {
-        Self::get_structure_size(bytes.len(), tagged)
-    }
-
-    /// If `tagged`, include the tag and size fields
-    fn get_rdn_size(name: &Name, tagged: bool) -> Result<usize, DpeErrorCode> {
-        let cn_seq_size = Self::get_structure_size(
-            Self::get_bytes_size(&Self::RDN_COMMON_NAME_OID, /*tagged=*/ true)?
-                + Self::get_bytes_size(name.cn.bytes(), true)?,
-            /*tagged=*/ true,
-        )?;
-        let serialnumber_seq_size = Self::get_structure_size(
-            Self::get_bytes_size(&Self::RDN_COMMON_NAME_OID, /*tagged=*/ true)?
-                + Self::get_bytes_size(name.serial.bytes(), /*tagged=*/ true)?,
-            /*tagged=*/ true,
-        )?;
-
-        let cn_set_size = Self::get_structure_size(cn_seq_size, /*tagged=*/ true)?;
-        let serialnumber_set_size =
-            Self::get_structure_size(serialnumber_seq_size, /*tagged=*/ true)?;
-
-        Self::get_structure_size(cn_set_size + serialnumber_set_size, tagged)
+    fn get_rdn_attribute_size(
+        oid: &[u8],
+        value: &DirectoryString,
+        tagged: bool,
+    ) -> Result<usize, DpeErrorCode> {
+        DerWriter::structure_size(
+            DerWriter::bytes_size(oid, /*tagged=*/ true)?
+                + DerWriter::bytes_size(value.bytes(), /*tagged=*/ true)?,
+            tagged,
+        )
     }
 
     /// Calculate the number of bytes for an ECC Public Key AlgorithmIdentifier
     /// If `tagged`, include the tag and size fields
     fn get_ec_pub_alg_id_size(tagged: bool) -> Result<usize, DpeErrorCode> {
-        let len = Self::get_bytes_size(Self::EC_PUB_OID, true)?
-            + Self::get_bytes_size(Self::CURVE_OID, true)?;
-        Self::get_structure_size(len, tagged)
+        let len = DerWriter::bytes_size(Self::EC_PUB_OID, true)?
+            + DerWriter::bytes_size(Self::CURVE_OID, true)?;
+        DerWriter::structure_size(len, tagged)
     }
 
     /// Calculate the number of bytes for an ECDSA signature AlgorithmIdentifier
     /// If `tagged`, include the tag and size fields
     fn get_ecdsa_sig_alg_id_size(tagged: bool) -> Result<usize, DpeErrorCode> {
-        let len = Self::get_bytes_size(Self::ECDSA_OID, true)?;
-        Self::get_structure_size(len, tagged)
+        let len = DerWriter::bytes_size(Self::ECDSA_OID, true)?;
+        DerWriter::structure_size(len, tagged)
     }
 
     /// Calculate the number of bytes for a Hash AlgorithmIdentifier
     /// If `tagged`, include the tag and size fields
     fn get_hash_alg_id_size(tagged: bool) -> Result<usize, DpeErrorCode> {
-        let len = Self::get_bytes_size(Self::HASH_OID, true)?;
-        Self::get_structure_size(len, tagged)
+        let len = DerWriter::bytes_size(Self::HASH_OID, true)?;
+        DerWriter::structure_size(len, tagged)
     }
 
     /// If `tagged`, include the tag and size fields
     fn get_validity_size(validity: &CertValidity, tagged: bool) -> Result<usize, DpeErrorCode> {
-        let len = Self::get_bytes_size(validity.not_before.as_slice(), true)?
-            + Self::get_bytes_size(validity.not_after.as_slice(), true)?;
-        Self::get_structure_size(len, tagged)
+        let len = DerWriter::bytes_size(validity.not_before.as_slice(), true)?
+            + DerWriter::bytes_size(validity.not_after.as_slice(), true)?;
+        DerWriter::structure_size(len, tagged)
     }
 
     /// Calculate the number of bytes an ECC SubjectPublicKeyInfo will be
@@ -282,25 +379,120 @@ impl CertWriter<'_> {
     ) -> Result<usize, DpeErrorCode> {
         let point_size = 1 + pubkey.x.len() + pubkey.y.len();
         let bitstring_size = 1 + point_size;
-        let seq_size = Self::get_structure_size(bitstring_size, /*tagged=*/ true)?
+        let seq_size = DerWriter::structure_size(bitstring_size, /*tagged=*/ true)?
             + Self::get_ec_pub_alg_id_size(/*tagged=*/ true)?;
 
-        Self::get_structure_size(seq_size, tagged)
+        DerWriter::structure_size(seq_size, tagged)
     }
 
+    /// Calculate the number of bytes an RSA Public Key AlgorithmIdentifier will be
     /// If `tagged`, include the tag and size fields
-    fn get_ecdsa_signature_bit_string_size(
-        sig: &EcdsaSig,
+    #[cfg(feature = "rsa")]
+    fn get_rsa_pub_alg_id_size(tagged: bool) -> Result<usize, DpeErrorCode> {
+        let len = DerWriter::bytes_size(Self::RSA_ENCRYPTION_OID, true)? + DerWriter::NULL_SIZE;
+        DerWriter::structure_size(len, tagged)
+    }
+
+    /// Calculate the number of bytes an RSAPublicKey will be
+    /// If `tagged`, include the tag and size fields
+    #[cfg(feature = "rsa")]
+    fn get_rsa_public_key_size(pubkey: &RsaPub, tagged: bool) -> Result<usize, DpeErrorCode> {
+        let len = DerWriter::integer_bytes_size(pubkey.modulus(), true)?
+            + DerWriter::integer_size(RsaPub::EXPONENT.into(), true)?;
+        DerWriter::structure_size(len, tagged)
+    }
+
+    /// Calculate the number of bytes an RSA SubjectPublicKeyInfo will be
+    /// If `tagged`, include the tag and size fields
+    #[cfg(feature = "rsa")]
+    fn get_rsa_subject_pubkey_info_size(
+        pubkey: &RsaPub,
         tagged: bool,
     ) -> Result<usize, DpeErrorCode> {
-        let seq_size = Self::get_structure_size(
-            Self::get_integer_bytes_size(sig.r.bytes(), /*tagged=*/ true)?
-                + Self::get_integer_bytes_size(sig.s.bytes(), /*tagged=*/ true)?,
+        let bitstring_size = 1 + Self::get_rsa_public_key_size(pubkey, /*tagged=*/ true)?;
+        let seq_size = DerWriter::structure_size(bitstring_size, /*tagged=*/ true)?
+            + Self::get_rsa_pub_alg_id_size(/*tagged=*/ true)?;
+
+        DerWriter::structure_size(seq_size, tagged)
+    }
+
+    /// Calculate the number of bytes the id-Ed25519 AlgorithmIdentifier will be
+    /// If `tagged`, include the tag and size fields
+    #[cfg(feature = "dpe_profile_ed25519_sha512")]
+    fn get_ed25519_alg_id_size(tagged: bool) -> Result<usize, DpeErrorCode> {
+        let len = DerWriter::bytes_size(Self::ED25519_OID, true)?;
+        DerWriter::structure_size(len, tagged)
+    }
+
+    /// Calculate the number of bytes an Ed25519 SubjectPublicKeyInfo will be
+    /// If `tagged`, include the tag and size fields
+    #[cfg(feature = "dpe_profile_ed25519_sha512")]
+    fn get_ed25519_subject_pubkey_info_size(tagged: bool) -> Result<usize, DpeErrorCode> {
+        let seq_size = DerWriter::bit_string_size(&[0; Ed25519Pub::SIZE], /*tagged=*/ true)?
+            + Self::get_ed25519_alg_id_size(/*tagged=*/ true)?;
+
+        DerWriter::structure_size(seq_size, tagged)
+    }
+
+    /// Calculate the number of bytes a SubjectPublicKeyInfo will be
+    /// If `tagged`, include the tag and size fields
+    fn get_subject_pubkey_info_size(
+        pubkey: SubjectPubKey,
+        tagged: bool,
+    ) -> Result<usize, DpeErrorCode> {
+        match pubkey {
+            SubjectPubKey::Ecdsa(pubkey) => {
+                Self::get_ecdsa_subject_pubkey_info_size(pubkey, tagged)
+            }
+            #[cfg(feature = "rsa")]
+            SubjectPubKey::Rsa(pubkey) => Self::get_rsa_subject_pubkey_info_size(pubkey, tagged),
+            #[cfg(feature = "dpe_profile_ed25519_sha512")]
+            SubjectPubKey::Ed25519(_) => Self::get_ed25519_subject_pubkey_info_size(tagged),
+        }
+    }
+
+    /// Calculate the number of bytes the RSASSA-PSS-params for the profile's
+    /// hash algorithm will be
+    /// If `tagged`, include the tag and size fields
+    #[cfg(feature = "rsa")]
+    fn get_rsassa_pss_params_size(tagged: bool) -> Result<usize, DpeErrorCode> {
+        let hash_alg_id_size = Self::get_hash_alg_id_size(/*tagged=*/ true)?;
+        let mgf_size = DerWriter::structure_size(
+            DerWriter::bytes_size(Self::MGF1_OID, true)? + hash_alg_id_size,
             /*tagged=*/ true,
         )?;
+        let salt_size = DerWriter::integer_size(DPE_PROFILE.get_hash_size() as u64, true)?;
+        let len = DerWriter::structure_size(hash_alg_id_size, /*tagged=*/ true)?
+            + DerWriter::structure_size(mgf_size, /*tagged=*/ true)?
+            + DerWriter::structure_size(salt_size, /*tagged=*/ true)?;
+        DerWriter::structure_size(len, tagged)
+    }
+
+    /// Calculate the number of bytes an RSA signature AlgorithmIdentifier will be
+    /// If `tagged`, include the tag and size fields
+    #[cfg(feature = "rsa")]
+    fn get_rsa_sig_alg_id_size(padding: RsaPadding, tagged: bool) -> Result<usize, DpeErrorCode> {
+        let len = match padding {
+            RsaPadding::Pkcs1v15 => {
+                DerWriter::bytes_size(Self::RSA_PKCS1_OID, true)? + DerWriter::NULL_SIZE
+            }
+            RsaPadding::Pss => {
+                DerWriter::bytes_size(Self::RSASSA_PSS_OID, true)?
+                    + Self::get_rsassa_pss_params_size(/*tagged=*/ true)?
+            }
+        };
+        DerWriter::structure_size(len, tagged)
+    }
+
+    /// If `tagged`, include the tag and size fields
+    fn get_ecdsa_signature_bit_string_size(
+        sig: &EcdsaSig,
+        tagged: bool,
+    ) -> Result<usize, DpeErrorCode> {
+        let seq_size = sig.serialized_size(EcdsaSigFormat::Der)?;
 
         // Wrapping structure size
-        Self::get_structure_size(1 + seq_size, tagged)
+        DerWriter::structure_size(1 + seq_size, tagged)
     }
 
     /// If `tagged`, include the tag and size fields
@@ -308,31 +500,66 @@ impl CertWriter<'_> {
         sig: &EcdsaSig,
         tagged: bool,
     ) -> Result<usize, DpeErrorCode> {
-        let seq_size = Self::get_structure_size(
-            Self::get_integer_bytes_size(sig.r.bytes(), /*tagged=*/ true)?
-                + Self::get_integer_bytes_size(sig.s.bytes(), /*tagged=*/ true)?,
-            /*tagged=*/ true,
-        )?;
+        let seq_size = sig.serialized_size(EcdsaSigFormat::Der)?;
 
         // Wrapping structure size
-        Self::get_structure_size(seq_size, tagged)
+        DerWriter::structure_size(seq_size, tagged)
     }
 
     /// version is marked as EXPLICIT [0]
     /// If `tagged`, include the explicit tag and size fields
     fn get_version_size(tagged: bool) -> Result<usize, DpeErrorCode> {
-        let integer_size = Self::get_integer_size(Self::X509_V3, /*tagged=*/ true)?;
+        let integer_size = DerWriter::integer_size(Self::X509_V3, /*tagged=*/ true)?;
 
         // If tagged, also add explicit wrapping
-        Self::get_structure_size(integer_size, tagged)
+        DerWriter::structure_size(integer_size, tagged)
+    }
+
+    /// Get the hash algorithm OID and digest of a FWID from the platform
+    fn platform_fwid(fwid: &Fwid) -> Result<(&'static [u8], &[u8]), DpeErrorCode> {
+        let hash_oid = match fwid.hash_alg {
+            Fwid::SHA256 => Self::SHA256_OID,
+            Fwid::SHA384 => Self::SHA384_OID,
+            Fwid::SHA512 => Self::SHA512_OID,
+            _ => {
+                return Err(DpeErrorCode::Platform(PlatformError::AdditionalFwidError(
+                    fwid.hash_alg,
+                )))
+            }
+        };
+        let digest = fwid.digest().ok_or(DpeErrorCode::InternalError)?;
+        Ok((hash_oid, digest))
     }
 
     /// Get the size of a DICE FWID structure
-    fn get_fwid_size(digest: &[u8], tagged: bool) -> Result<usize, DpeErrorCode> {
-        let size = Self::get_structure_size(Self::HASH_OID.len(), /*tagged=*/ true)?
-            + Self::get_structure_size(digest.len(), /*tagged=*/ true)?;
+    fn get_fwid_size(hash_oid: &[u8], digest: &[u8], tagged: bool) -> Result<usize, DpeErrorCode> {
+        let size = DerWriter::structure_size(hash_oid.len(), /*tagged=*/ true)?
+            + DerWriter::structure_size(digest.len(), /*tagged=*/ true)?;
+
+        DerWriter::structure_size(size, tagged)
+    }
 
-        Self::get_structure_size(size, tagged)
+    /// Get the size of the FWIDs of a tcg-dice-TcbInfo, without the tag and
+    /// size fields of the SEQUENCE OF
+    fn get_fwids_size(
+        node: &TciNodeData,
+        supports_recursive: bool,
+        additional_fwids: &[Fwid],
+    ) -> Result<usize, DpeErrorCode> {
+        let mut size =
+            Self::get_fwid_size(Self::HASH_OID, &node.tci_current.0, /*tagged=*/ true)?;
+        if supports_recursive {
+            size += Self::get_fwid_size(
+                Self::HASH_OID,
+                &node.tci_cumulative.0,
+                /*tagged=*/ true,
+            )?;
+        }
+        for fwid in additional_fwids {
+            let (hash_oid, digest) = Self::platform_fwid(fwid)?;
+            size += Self::get_fwid_size(hash_oid, digest, /*tagged=*/ true)?;
+        }
+        Ok(size)
     }
 
     /// Get the size of a tcg-dice-TcbInfo structure. For DPE, this is only used
@@ -341,20 +568,52 @@ impl CertWriter<'_> {
     fn get_tcb_info_size(
         node: &TciNodeData,
         supports_recursive: bool,
+        label: Option<&[u8]>,
+        additional_fwids: &[Fwid],
         tagged: bool,
     ) -> Result<usize, DpeErrorCode> {
-        let fwid0_size = Self::get_fwid_size(&node.tci_current.0, /*tagged=*/ true)?;
-        let fwid1_size = if supports_recursive {
-            Self::get_fwid_size(&node.tci_cumulative.0, /*tagged=*/ true)?
+        let fwids_size = DerWriter::structure_size(
+            Self::get_fwids_size(node, supports_recursive, additional_fwids)?,
+            /*tagged=*/ true,
+        )?;
+
+        let vendor_info_size = core::mem::size_of::<u32>() + label.map_or(0, |label| label.len());
+
+        let svn_size = if node.has_svn.get() {
+            DerWriter::integer_size(node.svn.into(), /*tagged=*/ true)?
         } else {
             0
         };
-        let fwids_size = Self::get_structure_size(fwid0_size + fwid1_size, /*tagged=*/ true)?;
 
-        let size = fwids_size
-            + (2 * Self::get_structure_size(core::mem::size_of::<u32>(), /*tagged=*/ true)?); // vendorInfo and type
+        let size = svn_size
+            + fwids_size
+            + DerWriter::structure_size(vendor_info_size, /*tagged=*/ true)? // vendorInfo
+            + DerWriter::structure_size(core::mem::size_of::<u32>(), /*tagged=*/ true)?; // type
+
+        DerWriter::structure_size(size, tagged)
+    }
 
-        Self::get_structure_size(size, tagged)
+    /// Get the size of the concatenated tcg-dice-TcbInfos of a MultiTcbInfo.
+    /// Only the last one, which measures the certified context, carries the
+    /// label and the additional FWIDs.
+    fn get_tcb_infos_size(measurements: &MeasurementData) -> Result<usize, DpeErrorCode> {
+        let last = measurements.tci_nodes.len().saturating_sub(1);
+        let mut size = 0;
+        for (i, node) in measurements.tci_nodes.iter().enumerate() {
+            let (label, additional_fwids) = if i == last {
+                (measurements.label, measurements.additional_fwids)
+            } else {
+                (None, &[][..])
+            };
+            size += Self::get_tcb_info_size(
+                node,
+                measurements.supports_recursive,
+                label,
+                additional_fwids,
+                /*tagged=*/ true,
+            )?;
+        }
+        Ok(size)
     }
 
     /// Get the size of a tcg-dice-MultiTcbInfo extension, including the extension
@@ -367,22 +626,38 @@ impl CertWriter<'_> {
             return Err(DpeErrorCode::InternalError);
         }
 
-        // Size of concatenated tcb infos
-        let tcb_infos_size = measurements.tci_nodes.len()
-            * Self::get_tcb_info_size(
-                &measurements.tci_nodes[0],
-                measurements.supports_recursive,
-                /*tagged=*/ true,
-            )?;
+        let tcb_infos_size = Self::get_tcb_infos_size(measurements)?;
 
         // Size of tcb infos including SEQUENCE OF tag/size
-        let multi_tcb_info_size = Self::get_structure_size(tcb_infos_size, /*tagged=*/ true)?;
+        let multi_tcb_info_size = DerWriter::structure_size(tcb_infos_size, /*tagged=*/ true)?;
+
+        let size = DerWriter::structure_size(Self::MULTI_TCBINFO_OID.len(), /*tagged=*/true)? // Extension OID
+            + DerWriter::structure_size(1, /*tagged=*/true)? // Critical bool
+            + DerWriter::structure_size(multi_tcb_info_size, /*tagged=*/true)?; // OCTET STRING
 
-        let size = Self::get_structure_size(Self::MULTI_TCBINFO_OID.len(), /*tagged=*/true)? // Extension OID
-            + Self::get_structure_size(1, /*tagged=*/true)? // Critical bool
-            + Self::get_structure_size(multi_tcb_info_size, /*tagged=*/true)?; // OCTET STRING
+        DerWriter::structure_size(size, tagged)
+    }
+
+    /// Get the size of a tcg-dice-TcbFreshness extension, including the
+    /// extension OID and critical bits. Zero if there is no command counter.
+    fn get_tcb_freshness_size(
+        measurements: &MeasurementData,
+        tagged: bool,
+    ) -> Result<usize, DpeErrorCode> {
+        if measurements.command_counter.is_none() {
+            return Ok(0);
+        }
+        // Extension data is sequence -> octet string. To compute size, wrap
+        // in tagging twice.
+        let ext_size = DerWriter::structure_size(
+            DerWriter::structure_size(core::mem::size_of::<u32>(), /*tagged=*/ true)?,
+            /*tagged=*/ true,
+        )?;
+        let size = DerWriter::structure_size(Self::TCB_FRESHNESS_OID.len(), /*tagged=*/true)? // Extension OID
+            + DerWriter::structure_size(1, /*tagged=*/true)? // Critical bool
+            + DerWriter::structure_size(ext_size, /*tagged=*/true)?; // OCTET STRING
 
-        Self::get_structure_size(size, tagged)
+        DerWriter::structure_size(size, tagged)
     }
 
     /// Get the size of a tcg-dice-Ueid extension, including the extension
@@ -390,43 +665,58 @@ impl CertWriter<'_> {
     fn get_ueid_size(measurements: &MeasurementData, tagged: bool) -> Result<usize, DpeErrorCode> {
         // Extension data is sequence -> octet string. To compute size, wrap
         // in tagging twice.
-        let ext_size = Self::get_structure_size(
-            Self::get_structure_size(measurements.label.len(), /*tagged=*/ true)?,
+        let ext_size = DerWriter::structure_size(
+            DerWriter::structure_size(measurements.ueid.len(), /*tagged=*/ true)?,
             /*tagged=*/ true,
         )?;
-        let size = Self::get_structure_size(Self::UEID_OID.len(), /*tagged=*/true)? // Extension OID
-            + Self::get_structure_size(1, /*tagged=*/true)? // Critical bool
-            + Self::get_structure_size(ext_size, /*tagged=*/true)?; // OCTET STRING
+        let size = DerWriter::structure_size(Self::UEID_OID.len(), /*tagged=*/true)? // Extension OID
+            + DerWriter::structure_size(1, /*tagged=*/true)? // Critical bool
+            + DerWriter::structure_size(ext_size, /*tagged=*/true)?; // OCTET STRING
+
+        DerWriter::structure_size(size, tagged)
+    }
 
-        Self::get_structure_size(size, tagged)
+    /// Get the size of the BasicConstraints sequence. cA defaults to FALSE,
+    /// so DER leaves it out of leaf certificates.
+    fn get_basic_constraints_value_size(
+        measurements: &MeasurementData,
+    ) -> Result<usize, DpeErrorCode> {
+        if measurements.is_ca {
+            DerWriter::structure_size(DerWriter::BOOL_SIZE, /*tagged=*/ true)
+        } else {
+            Ok(0)
+        }
     }
 
     /// Get the size of a basicConstraints extension, including the extension
     /// OID and critical bits.
-    fn get_basic_constraints_size(tagged: bool) -> Result<usize, DpeErrorCode> {
+    fn get_basic_constraints_size(
+        measurements: &MeasurementData,
+        tagged: bool,
+    ) -> Result<usize, DpeErrorCode> {
         // Extension data is sequence -> octet string. To compute size, wrap
         // in tagging twice.
-        let ext_size = Self::get_structure_size(
-            Self::get_structure_size(Self::BOOL_SIZE, /*tagged=*/ true)?,
+        let ext_size = DerWriter::structure_size(
+            Self::get_basic_constraints_value_size(measurements)?,
             /*tagged=*/ true,
         )?;
-        let size = Self::get_structure_size(Self::BASIC_CONSTRAINTS_OID.len(), /*tagged=*/true)? // Extension OID
-            + Self::get_structure_size(Self::BOOL_SIZE, /*tagged=*/true)? // Critical bool
-            + Self::get_structure_size(ext_size, /*tagged=*/true)?; // OCTET STRING
+        let size = DerWriter::structure_size(Self::BASIC_CONSTRAINTS_OID.len(), /*tagged=*/true)? // Extension OID
+            + DerWriter::structure_size(DerWriter::BOOL_SIZE, /*tagged=*/true)? // Critical bool
+            + DerWriter::structure_size(ext_size, /*tagged=*/true)?; // OCTET STRING
 
-        Self::get_structure_size(size, tagged)
+        DerWriter::structure_size(size, tagged)
     }
 
     /// Get the size of a keyUsage extension, including the extension
     /// OID and critical bits.
     fn get_key_usage_size(tagged: bool) -> Result<usize, DpeErrorCode> {
         // Extension data is a 2-byte BIT STRING
-        let ext_size = Self::get_structure_size(2, /*tagged=*/ true)?;
-        let size = Self::get_structure_size(Self::KEY_USAGE_OID.len(), /*tagged=*/true)? // Extension OID
-            + Self::get_structure_size(Self::BOOL_SIZE, /*tagged=*/true)? // Critical bool
-            + Self::get_structure_size(ext_size, /*tagged=*/true)?; // OCTET STRING
+        let ext_size = DerWriter::structure_size(2, /*tagged=*/ true)?;
+        let size = DerWriter::structure_size(Self::KEY_USAGE_OID.len(), /*tagged=*/true)? // Extension OID
+            + DerWriter::structure_size(DerWriter::BOOL_SIZE, /*tagged=*/true)? // Critical bool
+            + DerWriter::structure_size(ext_size, /*tagged=*/true)?; // OCTET STRING
 
-        Self::get_structure_size(size, tagged)
+        DerWriter::structure_size(size, tagged)
     }
 
     /// Get the size of an extendedKeyUsage extension, including the extension
@@ -443,15 +733,15 @@ impl CertWriter<'_> {
 
         // Extension data is sequence -> octet string. To compute size, wrap
         // in tagging twice.
-        let ext_size = Self::get_structure_size(
-            Self::get_structure_size(policy_oid_size, /*tagged=*/ true)?,
+        let ext_size = DerWriter::structure_size(
+            DerWriter::structure_size(policy_oid_size, /*tagged=*/ true)?,
             /*tagged=*/ true,
         )?;
-        let size = Self::get_structure_size(Self::EXTENDED_KEY_USAGE_OID.len(), /*tagged=*/true)? // Extension OID
-            + Self::get_structure_size(Self::BOOL_SIZE, /*tagged=*/true)? // Critical bool
-            + Self::get_structure_size(ext_size, /*tagged=*/true)?; // OCTET STRING
+        let size = DerWriter::structure_size(Self::EXTENDED_KEY_USAGE_OID.len(), /*tagged=*/true)? // Extension OID
+            + DerWriter::structure_size(DerWriter::BOOL_SIZE, /*tagged=*/true)? // Critical bool
+            + DerWriter::structure_size(ext_size, /*tagged=*/true)?; // OCTET STRING
 
-        Self::get_structure_size(size, tagged)
+        DerWriter::structure_size(size, tagged)
     }
 
     /// Get the size of an subjectKeyIdentifier extension, including the extension
@@ -461,19 +751,19 @@ impl CertWriter<'_> {
         tagged: bool,
         is_x509: bool,
     ) -> Result<usize, DpeErrorCode> {
-        if !measurements.is_ca || !is_x509 {
+        if !is_x509 {
             return Ok(0);
         }
         let ski_size = measurements.subject_key_identifier.len();
 
         // Extension data is sequence -> octet string. To compute size, wrap
         // in tagging twice.
-        let ext_size = Self::get_structure_size(ski_size, /*tagged=*/ true)?;
-        let size = Self::get_structure_size(Self::SUBJECT_KEY_IDENTIFIER_OID.len(), /*tagged=*/true)? // Extension OID
-            + Self::get_structure_size(Self::BOOL_SIZE, /*tagged=*/true)? // Critical bool
-            + Self::get_structure_size(ext_size, /*tagged=*/true)?; // OCTET STRING
+        let ext_size = DerWriter::structure_size(ski_size, /*tagged=*/ true)?;
+        let size = DerWriter::structure_size(Self::SUBJECT_KEY_IDENTIFIER_OID.len(), /*tagged=*/true)? // Extension OID
+            + DerWriter::structure_size(DerWriter::BOOL_SIZE, /*tagged=*/true)? // Critical bool
+            + DerWriter::structure_size(ext_size, /*tagged=*/true)?; // OCTET STRING
 
-        Self::get_structure_size(size, tagged)
+        DerWriter::structure_size(size, tagged)
     }
 
     /// Get the size of an authorityKeyIdentifier extension, including the extension
@@ -483,23 +773,23 @@ impl CertWriter<'_> {
         tagged: bool,
         is_x509: bool,
     ) -> Result<usize, DpeErrorCode> {
-        if !measurements.is_ca || !is_x509 {
+        if !is_x509 {
             return Ok(0);
         }
         let aki_size = Self::get_key_identifier_size(
             &measurements.authority_key_identifier,
-            true,
-            /*explicit=*/ true,
+            /*tagged=*/ true,
+            /*explicit=*/ false,
         )?;
 
         // Extension data is sequence -> octet string. To compute size, wrap
         // in tagging twice.
-        let ext_size = Self::get_structure_size(aki_size, /*tagged=*/ true)?;
-        let size = Self::get_structure_size(Self::AUTHORITY_KEY_IDENTIFIER_OID.len(), /*tagged=*/true)? // Extension OID
-            + Self::get_structure_size(Self::BOOL_SIZE, /*tagged=*/true)? // Critical bool
-            + Self::get_structure_size(ext_size, /*tagged=*/true)?; // OCTET STRING
+        let ext_size = DerWriter::structure_size(aki_size, /*tagged=*/ true)?;
+        let size = DerWriter::structure_size(Self::AUTHORITY_KEY_IDENTIFIER_OID.len(), /*tagged=*/true)? // Extension OID
+            + DerWriter::structure_size(DerWriter::BOOL_SIZE, /*tagged=*/true)? // Critical bool
+            + DerWriter::structure_size(ext_size, /*tagged=*/true)?; // OCTET STRING
 
-        Self::get_structure_size(size, tagged)
+        DerWriter::structure_size(size, tagged)
     }
 
     /// Get the size of the TBS Extensions field.
@@ -511,7 +801,8 @@ impl CertWriter<'_> {
     ) -> Result<usize, DpeErrorCode> {
         let mut size = Self::get_multi_tcb_info_size(measurements, /*tagged=*/ true)?
             + Self::get_ueid_size(measurements, /*tagged=*/ true)?
-            + Self::get_basic_constraints_size(/*tagged=*/ true)?
+            + Self::get_tcb_freshness_size(measurements, /*tagged=*/ true)?
+            + Self::get_basic_constraints_size(measurements, /*tagged=*/ true)?
             + Self::get_key_usage_size(/*tagged=*/ true)?
             + Self::get_extended_key_usage_size(measurements, /*tagged=*/ true)?
             + Self::get_subject_key_identifier_extension_size(
@@ -526,9 +817,9 @@ impl CertWriter<'_> {
             )?;
 
         // Determine whether to include the explicit tag wrapping in the size calculation
-        size = Self::get_structure_size(size, /*tagged=*/ explicit)?;
+        size = DerWriter::structure_size(size, /*tagged=*/ explicit)?;
 
-        Self::get_structure_size(size, tagged)
+        DerWriter::structure_size(size, tagged)
     }
 
     /// Get the size of the ASN.1 TBSCertificate structure
@@ -537,18 +828,18 @@ impl CertWriter<'_> {
         serial_number: &[u8],
         issuer_der: &[u8],
         subject_name: &Name,
-        pubkey: &EcdsaPub,
+        pubkey: SubjectPubKey,
         measurements: &MeasurementData,
         validity: &CertValidity,
         tagged: bool,
     ) -> Result<usize, DpeErrorCode> {
         let tbs_size = Self::get_version_size(/*tagged=*/ true)?
-            + Self::get_integer_bytes_size(serial_number, /*tagged=*/ true)?
+            + DerWriter::integer_bytes_size(serial_number, /*tagged=*/ true)?
             + Self::get_ecdsa_sig_alg_id_size(/*tagged=*/ true)?
             + issuer_der.len()
             + Self::get_validity_size(validity, /*tagged=*/ true)?
             + Self::get_rdn_size(subject_name, /*tagged=*/ true)?
-            + Self::get_ecdsa_subject_pubkey_info_size(pubkey, /*tagged=*/ true)?
+            + Self::get_subject_pubkey_info_size(pubkey, /*tagged=*/ true)?
             + Self::get_extensions_size(
                 measurements,
                 /*tagged=*/ true,
@@ -556,23 +847,23 @@ impl CertWriter<'_> {
                 /*is_x509=*/ true,
             )?;
 
-        Self::get_structure_size(tbs_size, tagged)
+        DerWriter::structure_size(tbs_size, tagged)
     }
 
     /// Get the size of the ASN.1 CertificationRequestInfo structure
     /// If `tagged`, include the tag and size fields
     fn get_certification_request_info_size(
         subject_name: &Name,
-        pubkey: &EcdsaPub,
+        pubkey: SubjectPubKey,
         measurements: &MeasurementData,
         tagged: bool,
     ) -> Result<usize, DpeErrorCode> {
-        let cert_req_info_size = Self::get_integer_size(Self::CSR_V0, true)?
+        let cert_req_info_size = DerWriter::integer_size(Self::CSR_V0, true)?
             + Self::get_rdn_size(subject_name, /*tagged=*/ true)?
-            + Self::get_ecdsa_subject_pubkey_info_size(pubkey, /*tagged=*/ true)?
+            + Self::get_subject_pubkey_info_size(pubkey, /*tagged=*/ true)?
             + Self::get_attributes_size(measurements, /*tagged=*/ true)?;
 
-        Self::get_structure_size(cert_req_info_size, tagged)
+        DerWriter::structure_size(cert_req_info_size, tagged)
     }
 
     /// Get the size of the CMS version which differs based on the SignerIdentifier
@@ -581,8 +872,10 @@ impl CertWriter<'_> {
             SignerIdentifier::IssuerAndSerialNumber {
                 issuer_name: _,
                 serial_number: _,
-            } => Self::get_integer_size(Self::CMS_V1, true),
-            SignerIdentifier::SubjectKeyIdentifier(_) => Self::get_integer_size(Self::CMS_V3, true),
+            } => DerWriter::integer_size(Self::CMS_V1, true),
+            SignerIdentifier::SubjectKeyIdentifier(_) => {
+                DerWriter::integer_size(Self::CMS_V3, true)
+            }
         }
     }
 
@@ -599,7 +892,7 @@ impl CertWriter<'_> {
             + Self::get_ecdsa_sig_alg_id_size(/*tagged=*/ true)?
             + Self::get_ecdsa_signature_octet_string_size(sig, /*tagged=*/ true)?;
 
-        Self::get_structure_size(signer_info_size, tagged)
+        DerWriter::structure_size(signer_info_size, tagged)
     }
 
     /// Get the size of the ASN.1 SignedData structure
@@ -612,20 +905,20 @@ impl CertWriter<'_> {
         explicit: bool,
     ) -> Result<usize, DpeErrorCode> {
         let signed_data_size = Self::get_cms_version_size(sid)?
-            + Self::get_structure_size(
+            + DerWriter::structure_size(
                 Self::get_hash_alg_id_size(/*tagged=*/ true)?,
                 /*tagged=*/ true,
             )?
             + Self::get_encap_content_info_size(csr, /*tagged=*/ true)?
-            + Self::get_structure_size(
+            + DerWriter::structure_size(
                 Self::get_signer_info_size(sig, sid, /*tagged=*/ true)?,
                 /*tagged=*/ true,
             )?;
 
         // Determine whether to include the explicit tag wrapping in the size calculation
-        let explicit_signed_data_size = Self::get_structure_size(signed_data_size, explicit)?;
+        let explicit_signed_data_size = DerWriter::structure_size(signed_data_size, explicit)?;
 
-        Self::get_structure_size(explicit_signed_data_size, tagged)
+        DerWriter::structure_size(explicit_signed_data_size, tagged)
     }
 
     /// Get the size of the ASN.1 SignerIdentifier structure
@@ -661,9 +954,9 @@ impl CertWriter<'_> {
         tagged: bool,
     ) -> Result<usize, DpeErrorCode> {
         let issuer_and_serial_number_size =
-            Self::get_integer_bytes_size(serial_number, /*tagged=*/ true)? + issuer_der.len();
+            DerWriter::integer_bytes_size(serial_number, /*tagged=*/ true)? + issuer_der.len();
 
-        Self::get_structure_size(issuer_and_serial_number_size, tagged)
+        DerWriter::structure_size(issuer_and_serial_number_size, tagged)
     }
 
     /// Get the size of the ASN.1 SubjectKeyIdentifier structure
@@ -676,9 +969,9 @@ impl CertWriter<'_> {
         let subject_key_identifier_size = subject_key_identifier.len();
 
         // Determine whether to include the explicit tag wrapping in the size calculation
-        let explicit_bytes_size = Self::get_structure_size(subject_key_identifier_size, explicit)?;
+        let explicit_bytes_size = DerWriter::structure_size(subject_key_identifier_size, explicit)?;
 
-        Self::get_structure_size(explicit_bytes_size, tagged)
+        DerWriter::structure_size(explicit_bytes_size, tagged)
     }
 
     /// Get the size of the ASN.1 KeyIdentifier structure
@@ -691,9 +984,9 @@ impl CertWriter<'_> {
         let key_identifier_size = key_identifier.len();
 
         // Determine whether to include the explicit tag wrapping in the size calculation
-        let explicit_bytes_size = Self::get_structure_size(key_identifier_size, explicit)?;
+        let explicit_bytes_size = DerWriter::structure_size(key_identifier_size, explicit)?;
 
-        Self::get_structure_size(explicit_bytes_size, tagged)
+        DerWriter::structure_size(explicit_bytes_size, tagged)
     }
 
     fn get_econtent_size(
@@ -704,19 +997,19 @@ impl CertWriter<'_> {
         let bytes_size = bytes.len();
 
         // Determine whether to include the explicit tag wrapping in the size calculation
-        let explicit_bytes_size = Self::get_structure_size(bytes_size, explicit)?;
+        let explicit_bytes_size = DerWriter::structure_size(bytes_size, explicit)?;
 
-        Self::get_structure_size(explicit_bytes_size, tagged)
+        DerWriter::structure_size(explicit_bytes_size, tagged)
     }
 
     /// Get the size of the ASN.1 EncapsulatedContentInfo structure
     /// If `tagged`, include the tag and size fields
     fn get_encap_content_info_size(csr: &[u8], tagged: bool) -> Result<usize, DpeErrorCode> {
         let encap_content_info_size =
-            Self::get_structure_size(Self::ID_DATA_OID.len(), /*tagged=*/ true)?
+            DerWriter::structure_size(Self::ID_DATA_OID.len(), /*tagged=*/ true)?
                 + Self::get_econtent_size(csr, /*tagged=*/ true, /*explicit=*/ true)?;
 
-        Self::get_structure_size(encap_content_info_size, tagged)
+        DerWriter::structure_size(encap_content_info_size, tagged)
     }
 
     /// Get the size of the ASN.1 Attribute structure
@@ -726,8 +1019,8 @@ impl CertWriter<'_> {
         tagged: bool,
     ) -> Result<usize, DpeErrorCode> {
         let attribute_size =
-            Self::get_structure_size(Self::ID_DATA_OID.len(), /*tagged=*/ true)?
-                + Self::get_structure_size(
+            DerWriter::structure_size(Self::ID_DATA_OID.len(), /*tagged=*/ true)?
+                + DerWriter::structure_size(
                     Self::get_extensions_size(
                         measurements,
                         /*tagged=*/ true,
@@ -737,7 +1030,7 @@ impl CertWriter<'_> {
                     /*tagged=*/ true,
                 )?;
 
-        Self::get_structure_size(attribute_size, tagged)
+        DerWriter::structure_size(attribute_size, tagged)
     }
 
     /// Get the size of the ASN.1 Attributes structure
@@ -748,95 +1041,7 @@ impl CertWriter<'_> {
     ) -> Result<usize, DpeErrorCode> {
         let attribute_size = Self::get_attribute_size(measurements, /*tagged=*/ true)?;
 
-        Self::get_structure_size(attribute_size, tagged)
-    }
-
-    /// Write all of `bytes` to the certificate buffer
-    fn encode_bytes(&mut self, bytes: &[u8]) -> Result<usize, DpeErrorCode> {
-        let size = bytes.len();
-
-        if self.offset >= self.certificate.len() || self.offset + size > self.certificate.len() {
-            return Err(DpeErrorCode::InternalError);
-        }
-
-        self.certificate
-            .get_mut(self.offset..self.offset + size)
-            .ok_or(DpeErrorCode::InternalError)?
-            .copy_from_slice(bytes);
-        self.offset += size;
-
-        Ok(size)
-    }
-
-    /// Write a single `byte` to be certificate buffer
-    fn encode_byte(&mut self, byte: u8) -> Result<usize, DpeErrorCode> {
-        if self.offset >= self.certificate.len() {
-            return Err(DpeErrorCode::InternalError);
-        }
-
-        self.certificate[self.offset] = byte;
-        self.offset += 1;
-        Ok(1)
-    }
-
-    /// DER-encodes the tag field of an ASN.1 type
-    fn encode_tag_field(&mut self, tag: u8) -> Result<usize, DpeErrorCode> {
-        self.encode_byte(tag)
-    }
-
-    /// DER-encodes the size field of an ASN.1 type)
-    fn encode_size_field(&mut self, size: usize) -> Result<usize, DpeErrorCode> {
-        let size_width = Self::get_size_width(size)?;
-
-        if size_width == 1 {
-            self.encode_byte(size as u8)?;
-        } else {
-            let rem = size_width - 1;
-            self.encode_byte(0x80 | rem as u8)?;
-
-            for i in (0..rem).rev() {
-                self.encode_byte((size >> (i * 8)) as u8)?;
-            }
-        }
-
-        Ok(size_width)
-    }
-
-    /// DER-encodes a big-endian integer buffer as an ASN.1 INTEGER
-    fn encode_integer_bytes(&mut self, integer: &[u8]) -> Result<usize, DpeErrorCode> {
-        let mut bytes_written = self.encode_tag_field(Self::INTEGER_TAG)?;
-
-        let size = Self::get_integer_bytes_size(integer, false)?;
-        bytes_written += self.encode_size_field(size)?;
-
-        // Compute where to start reading from integer (strips leading zeros)
-        let integer_offset = integer.len().saturating_sub(size);
-
-        // If size got larger it is because a null byte needs to be prepended
-        if size > integer.len() {
-            bytes_written += self.encode_byte(0)?;
-        }
-
-        if integer_offset >= integer.len() {
-            return Err(DpeErrorCode::InternalError);
-        }
-        bytes_written += self.encode_bytes(&integer[integer_offset..])?;
-
-        Ok(bytes_written)
-    }
-
-    /// DER-encodes `integer` as an ASN.1 INTEGER
-    fn encode_integer(&mut self, integer: u64) -> Result<usize, DpeErrorCode> {
-        self.encode_integer_bytes(&integer.to_be_bytes())
-    }
-
-    /// DER-encodes `oid` as an ASN.1 ObjectIdentifier
-    fn encode_oid(&mut self, oid: &[u8]) -> Result<usize, DpeErrorCode> {
-        let mut bytes_written = self.encode_tag_field(Self::OID_TAG)?;
-        bytes_written += self.encode_size_field(oid.len())?;
-        bytes_written += self.encode_bytes(oid)?;
-
-        Ok(bytes_written)
+        DerWriter::structure_size(attribute_size, tagged)
     }
 
     /// Encode a DirectoryString for an RDN. Multiple string types are allowed, so
@@ -844,18 +1049,19 @@ impl CertWriter<'_> {
     /// will do an exact DER comparison when building cert chains.
     fn encode_rdn_string(&mut self, s: &DirectoryString) -> Result<usize, DpeErrorCode> {
         let (val, tag) = match s {
-            DirectoryString::PrintableString(val) => (val, Self::PRINTABLE_STRING_TAG),
-            DirectoryString::Utf8String(val) => (val, Self::UTF8_STRING_TAG),
+            DirectoryString::PrintableString(val) => (val, DerWriter::PRINTABLE_STRING_TAG),
+            DirectoryString::Utf8String(val) => (val, DerWriter::UTF8_STRING_TAG),
         };
-        let mut bytes_written = self.encode_tag_field(tag)?;
-        bytes_written += self.encode_size_field(val.len())?;
-        bytes_written += self.encode_bytes(val)?;
+        let mut bytes_written = self.der.write_tag(tag)?;
+        bytes_written += self.der.write_size(val.len())?;
+        bytes_written += self.der.write_bytes(val)?;
 
         Ok(bytes_written)
     }
 
     /// DER-encodes a RelativeDistinguishedName with CommonName and SerialNumber
-    /// fields.
+    /// fields, followed by OrganizationName and OrganizationalUnitName if
+    /// present.
     ///
     /// RelativeDistinguishedName ::=
     ///     SET SIZE (1..MAX) OF AttributeTypeAndValue
@@ -873,42 +1079,27 @@ impl CertWriter<'_> {
     ///     ...
     ///     }
     pub fn encode_rdn(&mut self, name: &Name) -> Result<usize, DpeErrorCode> {
-        let cn_size =
-            Self::get_structure_size(Self::RDN_COMMON_NAME_OID.len(), /*tagged=*/ true)?
-                + Self::get_structure_size(name.cn.len(), /*tagged=*/ true)?;
-        let serialnumber_size =
-            Self::get_structure_size(Self::RDN_SERIALNUMBER_OID.len(), /*tagged=*/ true)?
-                + Self::get_structure_size(name.serial.len(), /*tagged=*/ true)?;
-
-        let rdn_name_set_size = Self::get_structure_size(cn_size, /*tagged=*/ true)?;
-        let rnd_serial_set_size =
-            Self::get_structure_size(serialnumber_size, /*tagged=*/ true)?;
-        let rdn_seq_size = Self::get_structure_size(rdn_name_set_size, /*tagged=*/ true)?
-            + Self::get_structure_size(rnd_serial_set_size, /*tagged=*/ true)?;
-
         // Encode RDN SEQUENCE OF
-        let mut bytes_written = self.encode_tag_field(Self::SEQUENCE_OF_TAG)?;
-        bytes_written += self.encode_size_field(rdn_seq_size)?;
-
-        // Encode RDN SET
-        bytes_written += self.encode_tag_field(Self::SET_OF_TAG)?;
-        bytes_written += self.encode_size_field(rdn_name_set_size)?;
-
-        // Encode CN SEQUENCE
-        bytes_written += self.encode_tag_field(Self::SEQUENCE_TAG)?;
-        bytes_written += self.encode_size_field(cn_size)?;
-        bytes_written += self.encode_oid(&Self::RDN_COMMON_NAME_OID)?;
-        bytes_written += self.encode_rdn_string(&name.cn)?;
-
-        // Encode RDN SET
-        bytes_written += self.encode_tag_field(Self::SET_OF_TAG)?;
-        bytes_written += self.encode_size_field(rnd_serial_set_size)?;
+        let mut bytes_written = self.der.write_tag(DerWriter::SEQUENCE_OF_TAG)?;
+        bytes_written += self
+            .der
+            .write_size(Self::get_rdn_size(name, /*tagged=*/ false)?)?;
+
+        for (oid, value) in name.attributes() {
+            // Encode RDN SET
+            bytes_written += self.der.write_tag(DerWriter::SET_OF_TAG)?;
+            bytes_written += self.der.write_size(Self::get_rdn_attribute_size(
+                oid, value, /*tagged=*/ true,
+            )?)?;
 
-        // Encode SERIALNUMBER SEQUENCE
-        bytes_written += self.encode_tag_field(Self::SEQUENCE_TAG)?;
-        bytes_written += self.encode_size_field(serialnumber_size)?;
-        bytes_written += self.encode_oid(&Self::RDN_SERIALNUMBER_OID)?;
-        bytes_written += self.encode_rdn_string(&name.serial)?;
+            // Encode AttributeTypeAndValue SEQUENCE
+            bytes_written += self.der.write_tag(DerWriter::SEQUENCE_TAG)?;
+            bytes_written += self.der.write_size(Self::get_rdn_attribute_size(
+                oid, value, /*tagged=*/ false,
+            )?)?;
+            bytes_written += self.der.write_oid(oid)?;
+            bytes_written += self.encode_rdn_string(value)?;
+        }
 
         Ok(bytes_written)
     }
@@ -929,10 +1120,10 @@ impl CertWriter<'_> {
     fn encode_ec_pub_alg_id(&mut self) -> Result<usize, DpeErrorCode> {
         let seq_size = Self::get_ec_pub_alg_id_size(/*tagged=*/ false)?;
 
-        let mut bytes_written = self.encode_tag_field(Self::SEQUENCE_TAG)?;
-        bytes_written += self.encode_size_field(seq_size)?;
-        bytes_written += self.encode_oid(Self::EC_PUB_OID)?;
-        bytes_written += self.encode_oid(Self::CURVE_OID)?;
+        let mut bytes_written = self.der.write_tag(DerWriter::SEQUENCE_TAG)?;
+        bytes_written += self.der.write_size(seq_size)?;
+        bytes_written += self.der.write_oid(Self::EC_PUB_OID)?;
+        bytes_written += self.der.write_oid(Self::CURVE_OID)?;
 
         Ok(bytes_written)
     }
@@ -947,9 +1138,9 @@ impl CertWriter<'_> {
     fn encode_ecdsa_sig_alg_id(&mut self) -> Result<usize, DpeErrorCode> {
         let seq_size = Self::get_ecdsa_sig_alg_id_size(/*tagged=*/ false)?;
 
-        let mut bytes_written = self.encode_tag_field(Self::SEQUENCE_TAG)?;
-        bytes_written += self.encode_size_field(seq_size)?;
-        bytes_written += self.encode_oid(Self::ECDSA_OID)?;
+        let mut bytes_written = self.der.write_tag(DerWriter::SEQUENCE_TAG)?;
+        bytes_written += self.der.write_size(seq_size)?;
+        bytes_written += self.der.write_oid(Self::ECDSA_OID)?;
 
         Ok(bytes_written)
     }
@@ -964,9 +1155,9 @@ impl CertWriter<'_> {
     fn encode_hash_alg_id(&mut self) -> Result<usize, DpeErrorCode> {
         let seq_size = Self::get_hash_alg_id_size(/*tagged=*/ false)?;
 
-        let mut bytes_written = self.encode_tag_field(Self::SEQUENCE_TAG)?;
-        bytes_written += self.encode_size_field(seq_size)?;
-        bytes_written += self.encode_oid(Self::HASH_OID)?;
+        let mut bytes_written = self.der.write_tag(DerWriter::SEQUENCE_TAG)?;
+        bytes_written += self.der.write_size(seq_size)?;
+        bytes_written += self.der.write_oid(Self::HASH_OID)?;
 
         Ok(bytes_written)
     }
@@ -975,16 +1166,16 @@ impl CertWriter<'_> {
     fn encode_validity(&mut self, validity: &CertValidity) -> Result<usize, DpeErrorCode> {
         let seq_size = Self::get_validity_size(validity, /*tagged=*/ false)?;
 
-        let mut bytes_written = self.encode_tag_field(Self::SEQUENCE_TAG)?;
-        bytes_written += self.encode_size_field(seq_size)?;
+        let mut bytes_written = self.der.write_tag(DerWriter::SEQUENCE_TAG)?;
+        bytes_written += self.der.write_size(seq_size)?;
 
-        bytes_written += self.encode_tag_field(Self::GENERALIZE_TIME_TAG)?;
-        bytes_written += self.encode_size_field(validity.not_before.len())?;
-        bytes_written += self.encode_bytes(validity.not_before.as_slice())?;
+        bytes_written += self.der.write_tag(DerWriter::GENERALIZED_TIME_TAG)?;
+        bytes_written += self.der.write_size(validity.not_before.len())?;
+        bytes_written += self.der.write_bytes(validity.not_before.as_slice())?;
 
-        bytes_written += self.encode_tag_field(Self::GENERALIZE_TIME_TAG)?;
-        bytes_written += self.encode_size_field(validity.not_after.len())?;
-        bytes_written += self.encode_bytes(validity.not_after.as_slice())?;
+        bytes_written += self.der.write_tag(DerWriter::GENERALIZED_TIME_TAG)?;
+        bytes_written += self.der.write_size(validity.not_after.len())?;
+        bytes_written += self.der.write_bytes(validity.not_after.as_slice())?;
 
         Ok(bytes_written)
     }
@@ -1010,22 +1201,189 @@ impl CertWriter<'_> {
     ) -> Result<usize, DpeErrorCode> {
         let point_size = 1 + pubkey.x.len() + pubkey.y.len();
         let bitstring_size = 1 + point_size;
-        let seq_size = Self::get_structure_size(bitstring_size, /*tagged=*/ true)?
+        let seq_size = DerWriter::structure_size(bitstring_size, /*tagged=*/ true)?
             + Self::get_ec_pub_alg_id_size(/*tagged=*/ true)?;
 
-        let mut bytes_written = self.encode_tag_field(Self::SEQUENCE_TAG)?;
-        bytes_written += self.encode_size_field(seq_size)?;
+        let mut bytes_written = self.der.write_tag(DerWriter::SEQUENCE_TAG)?;
+        bytes_written += self.der.write_size(seq_size)?;
         bytes_written += self.encode_ec_pub_alg_id()?;
 
-        bytes_written += self.encode_tag_field(Self::BIT_STRING_TAG)?;
-        bytes_written += self.encode_size_field(bitstring_size)?;
+        bytes_written += self.der.write_tag(DerWriter::BIT_STRING_TAG)?;
+        bytes_written += self.der.write_size(bitstring_size)?;
         // First byte of BIT STRING is the number of unused bits. But all bits
         // are used.
-        bytes_written += self.encode_byte(0)?;
+        bytes_written += self.der.write_byte(0)?;
 
-        bytes_written += self.encode_byte(0x4)?;
-        bytes_written += self.encode_bytes(pubkey.x.bytes())?;
-        bytes_written += self.encode_bytes(pubkey.y.bytes())?;
+        bytes_written += self.der.write_byte(0x4)?;
+        bytes_written += self.der.write_bytes(pubkey.x.bytes())?;
+        bytes_written += self.der.write_bytes(pubkey.y.bytes())?;
+
+        Ok(bytes_written)
+    }
+
+    /// DER-encodes the AlgorithmIdentifier of an RSA public key
+    ///
+    /// AlgorithmIdentifier  ::=  SEQUENCE  {
+    ///     algorithm   OBJECT IDENTIFIER,
+    ///     parameters  NULL
+    ///     }
+    #[cfg(feature = "rsa")]
+    fn encode_rsa_pub_alg_id(&mut self) -> Result<usize, DpeErrorCode> {
+        let seq_size = Self::get_rsa_pub_alg_id_size(/*tagged=*/ false)?;
+
+        let mut bytes_written = self.der.write_sequence_header(seq_size)?;
+        bytes_written += self.der.write_oid(Self::RSA_ENCRYPTION_OID)?;
+        bytes_written += self.der.write_null()?;
+
+        Ok(bytes_written)
+    }
+
+    /// Encode SubjectPublicKeyInfo for an RSA public key
+    ///
+    /// SubjectPublicKeyInfo  ::=  SEQUENCE  {
+    ///        algorithm            AlgorithmIdentifier,
+    ///        subjectPublicKey     BIT STRING  }
+    ///
+    /// subjectPublicKey is a BIT STRING containing an RSAPublicKey
+    ///
+    /// RSAPublicKey ::= SEQUENCE {
+    ///     modulus           INTEGER,  -- n
+    ///     publicExponent    INTEGER   -- e
+    /// }
+    ///
+    /// Returns number of bytes written to `certificate`
+    #[cfg(feature = "rsa")]
+    fn encode_rsa_subject_pubkey_info(&mut self, pubkey: &RsaPub) -> Result<usize, DpeErrorCode> {
+        let seq_size = Self::get_rsa_subject_pubkey_info_size(pubkey, /*tagged=*/ false)?;
+        let rsa_pub_size = Self::get_rsa_public_key_size(pubkey, /*tagged=*/ false)?;
+
+        let mut bytes_written = self.der.write_sequence_header(seq_size)?;
+        bytes_written += self.encode_rsa_pub_alg_id()?;
+
+        bytes_written += self.der.write_header(
+            DerWriter::BIT_STRING_TAG,
+            1 + DerWriter::structure_size(rsa_pub_size, /*tagged=*/ true)?,
+        )?;
+        // All bits are used.
+        bytes_written += self.der.write_byte(0)?;
+
+        bytes_written += self.der.write_sequence_header(rsa_pub_size)?;
+        bytes_written += self.der.write_integer_bytes(pubkey.modulus())?;
+        bytes_written += self.der.write_integer(RsaPub::EXPONENT.into())?;
+
+        Ok(bytes_written)
+    }
+
+    /// DER-encodes the id-Ed25519 AlgorithmIdentifier, used both for keys
+    /// and for signatures
+    ///
+    /// AlgorithmIdentifier  ::=  SEQUENCE  {
+    ///     algorithm   OBJECT IDENTIFIER
+    ///     }
+    ///
+    /// RFC 8410 requires the parameters to be absent.
+    #[cfg(feature = "dpe_profile_ed25519_sha512")]
+    fn encode_ed25519_alg_id(&mut self) -> Result<usize, DpeErrorCode> {
+        let seq_size = Self::get_ed25519_alg_id_size(/*tagged=*/ false)?;
+
+        let mut bytes_written = self.der.write_sequence_header(seq_size)?;
+        bytes_written += self.der.write_oid(Self::ED25519_OID)?;
+
+        Ok(bytes_written)
+    }
+
+    /// Encode SubjectPublicKeyInfo for an Ed25519 public key
+    ///
+    /// SubjectPublicKeyInfo  ::=  SEQUENCE  {
+    ///        algorithm            AlgorithmIdentifier,
+    ///        subjectPublicKey     BIT STRING  }
+    ///
+    /// subjectPublicKey holds the 32-byte encoded key (RFC 8410).
+    ///
+    /// Returns number of bytes written to `certificate`
+    #[cfg(feature = "dpe_profile_ed25519_sha512")]
+    fn encode_ed25519_subject_pubkey_info(
+        &mut self,
+        pubkey: &Ed25519Pub,
+    ) -> Result<usize, DpeErrorCode> {
+        let seq_size = Self::get_ed25519_subject_pubkey_info_size(/*tagged=*/ false)?;
+
+        let mut bytes_written = self.der.write_sequence_header(seq_size)?;
+        bytes_written += self.encode_ed25519_alg_id()?;
+        bytes_written += self.der.write_bit_string(pubkey.bytes())?;
+
+        Ok(bytes_written)
+    }
+
+    /// Encode SubjectPublicKeyInfo for `pubkey`
+    fn encode_subject_pubkey_info(&mut self, pubkey: SubjectPubKey) -> Result<usize, DpeErrorCode> {
+        match pubkey {
+            SubjectPubKey::Ecdsa(pubkey) => self.encode_ecdsa_subject_pubkey_info(pubkey),
+            #[cfg(feature = "rsa")]
+            SubjectPubKey::Rsa(pubkey) => self.encode_rsa_subject_pubkey_info(pubkey),
+            #[cfg(feature = "dpe_profile_ed25519_sha512")]
+            SubjectPubKey::Ed25519(pubkey) => self.encode_ed25519_subject_pubkey_info(pubkey),
+        }
+    }
+
+    /// DER-encodes the AlgorithmIdentifier of an RSA signature with the
+    /// profile's hash algorithm
+    ///
+    /// For RSASSA-PKCS1-v1_5 the parameters are NULL. For RSASSA-PSS they are
+    /// (RFC 4055)
+    ///
+    /// RSASSA-PSS-params  ::=  SEQUENCE  {
+    ///     hashAlgorithm      [0] HashAlgorithm,
+    ///     maskGenAlgorithm   [1] MaskGenAlgorithm,
+    ///     saltLength         [2] INTEGER,
+    ///     trailerField       [3] INTEGER DEFAULT 1 }
+    ///
+    /// with MGF1 over the same hash and a salt as long as the digest.
+    #[cfg(feature = "rsa")]
+    fn encode_rsa_sig_alg_id(&mut self, padding: RsaPadding) -> Result<usize, DpeErrorCode> {
+        let seq_size = Self::get_rsa_sig_alg_id_size(padding, /*tagged=*/ false)?;
+        let mut bytes_written = self.der.write_sequence_header(seq_size)?;
+
+        match padding {
+            RsaPadding::Pkcs1v15 => {
+                bytes_written += self.der.write_oid(Self::RSA_PKCS1_OID)?;
+                bytes_written += self.der.write_null()?;
+            }
+            RsaPadding::Pss => {
+                bytes_written += self.der.write_oid(Self::RSASSA_PSS_OID)?;
+
+                let hash_alg_id_size = Self::get_hash_alg_id_size(/*tagged=*/ true)?;
+                let mgf_size = DerWriter::bytes_size(Self::MGF1_OID, true)? + hash_alg_id_size;
+                let salt_len = DPE_PROFILE.get_hash_size() as u64;
+                bytes_written +=
+                    self.der
+                        .write_sequence_header(Self::get_rsassa_pss_params_size(
+                            /*tagged=*/ false,
+                        )?)?;
+
+                // hashAlgorithm
+                bytes_written += self.der.write_context_header(0, true, hash_alg_id_size)?;
+                bytes_written += self.encode_hash_alg_id()?;
+
+                // maskGenAlgorithm
+                bytes_written += self.der.write_context_header(
+                    1,
+                    true,
+                    DerWriter::structure_size(mgf_size, /*tagged=*/ true)?,
+                )?;
+                bytes_written += self.der.write_sequence_header(mgf_size)?;
+                bytes_written += self.der.write_oid(Self::MGF1_OID)?;
+                bytes_written += self.encode_hash_alg_id()?;
+
+                // saltLength
+                bytes_written += self.der.write_context_header(
+                    2,
+                    true,
+                    DerWriter::integer_size(salt_len, /*tagged=*/ true)?,
+                )?;
+                bytes_written += self.der.write_integer(salt_len)?;
+            }
+        }
 
         Ok(bytes_written)
     }
@@ -1037,23 +1395,15 @@ impl CertWriter<'_> {
     ///     s  INTEGER
     ///   }
     fn encode_ecdsa_signature_bit_string(&mut self, sig: &EcdsaSig) -> Result<usize, DpeErrorCode> {
-        let seq_size = Self::get_integer_bytes_size(sig.r.bytes(), /*tagged=*/ true)?
-            + Self::get_integer_bytes_size(sig.s.bytes(), /*tagged=*/ true)?;
+        let mut seq = [0u8; EcdsaSig::MAX_SERIALIZED_SIZE];
+        let seq_size = sig.serialize(EcdsaSigFormat::Der, &mut seq)?;
 
         // Encode BIT STRING
-        let mut bytes_written = self.encode_tag_field(Self::BIT_STRING_TAG)?;
-        bytes_written += self.encode_size_field(Self::get_structure_size(
-            1 + seq_size,
-            /*tagged=*/ true,
-        )?)?;
+        let mut bytes_written = self.der.write_tag(DerWriter::BIT_STRING_TAG)?;
+        bytes_written += self.der.write_size(1 + seq_size)?;
         // Unused bits
-        bytes_written += self.encode_byte(0)?;
-
-        // Encode SEQUENCE
-        bytes_written += self.encode_tag_field(Self::SEQUENCE_TAG)?;
-        bytes_written += self.encode_size_field(seq_size)?;
-        bytes_written += self.encode_integer_bytes(sig.r.bytes())?;
-        bytes_written += self.encode_integer_bytes(sig.s.bytes())?;
+        bytes_written += self.der.write_byte(0)?;
+        bytes_written += self.der.write_bytes(&seq[..seq_size])?;
 
         Ok(bytes_written)
     }
@@ -1068,53 +1418,54 @@ impl CertWriter<'_> {
         &mut self,
         sig: &EcdsaSig,
     ) -> Result<usize, DpeErrorCode> {
-        let seq_size = Self::get_integer_bytes_size(sig.r.bytes(), /*tagged=*/ true)?
-            + Self::get_integer_bytes_size(sig.s.bytes(), /*tagged=*/ true)?;
+        let mut seq = [0u8; EcdsaSig::MAX_SERIALIZED_SIZE];
+        let seq_size = sig.serialize(EcdsaSigFormat::Der, &mut seq)?;
 
         // Encode OCTET STRING
-        let mut bytes_written = self.encode_tag_field(Self::OCTET_STRING_TAG)?;
-        bytes_written +=
-            self.encode_size_field(Self::get_structure_size(seq_size, /*tagged=*/ true)?)?;
-
-        // Encode SEQUENCE
-        bytes_written += self.encode_tag_field(Self::SEQUENCE_TAG)?;
-        bytes_written += self.encode_size_field(seq_size)?;
-        bytes_written += self.encode_integer_bytes(sig.r.bytes())?;
-        bytes_written += self.encode_integer_bytes(sig.s.bytes())?;
+        let mut bytes_written = self.der.write_tag(DerWriter::OCTET_STRING_TAG)?;
+        bytes_written += self.der.write_size(seq_size)?;
+        bytes_written += self.der.write_bytes(&seq[..seq_size])?;
 
         Ok(bytes_written)
     }
 
     pub fn encode_version(&mut self) -> Result<usize, DpeErrorCode> {
         // Version is EXPLICIT field number 0
-        let mut bytes_written = self.encode_byte(Self::CONTEXT_SPECIFIC | Self::CONSTRUCTED)?;
-        bytes_written += self.encode_size_field(Self::get_integer_size(
+        let mut bytes_written = self
+            .der
+            .write_byte(DerWriter::CONTEXT_SPECIFIC | DerWriter::CONSTRUCTED)?;
+        bytes_written += self.der.write_size(DerWriter::integer_size(
             Self::X509_V3,
             /*tagged=*/ true,
         )?)?;
-        bytes_written += self.encode_integer(Self::X509_V3)?;
+        bytes_written += self.der.write_integer(Self::X509_V3)?;
 
         Ok(bytes_written)
     }
 
-    fn encode_fwid(&mut self, tci: &TciMeasurement) -> Result<usize, DpeErrorCode> {
-        let mut bytes_written = self.encode_byte(Self::SEQUENCE_TAG)?;
-        bytes_written +=
-            self.encode_size_field(Self::get_fwid_size(&tci.0, /*tagged=*/ false)?)?;
+    fn encode_fwid(&mut self, hash_oid: &[u8], digest: &[u8]) -> Result<usize, DpeErrorCode> {
+        let mut bytes_written = self.der.write_byte(DerWriter::SEQUENCE_TAG)?;
+        bytes_written += self.der.write_size(Self::get_fwid_size(
+            hash_oid, digest, /*tagged=*/ false,
+        )?)?;
 
         // hashAlg OID
-        bytes_written += self.encode_byte(Self::OID_TAG)?;
-        bytes_written += self.encode_size_field(Self::HASH_OID.len())?;
-        bytes_written += self.encode_bytes(Self::HASH_OID)?;
+        bytes_written += self.der.write_byte(DerWriter::OID_TAG)?;
+        bytes_written += self.der.write_size(hash_oid.len())?;
+        bytes_written += self.der.write_bytes(hash_oid)?;
 
         // digest OCTET STRING
-        bytes_written += self.encode_byte(Self::OCTET_STRING_TAG)?;
-        bytes_written += self.encode_size_field(tci.0.len())?;
-        bytes_written += self.encode_bytes(&tci.0)?;
+        bytes_written += self.der.write_byte(DerWriter::OCTET_STRING_TAG)?;
+        bytes_written += self.der.write_size(digest.len())?;
+        bytes_written += self.der.write_bytes(digest)?;
 
         Ok(bytes_written)
     }
 
+    fn encode_tci_fwid(&mut self, tci: &TciMeasurement) -> Result<usize, DpeErrorCode> {
+        self.encode_fwid(Self::HASH_OID, &tci.0)
+    }
+
     /// Encode a tcg-dice-TcbInfo structure
     ///
     /// https://trustedcomputinggroup.org/wp-content/uploads/TCG_DICE_Attestation_Architecture_r22_02dec2020.pdf
@@ -1131,44 +1482,74 @@ impl CertWriter<'_> {
         &mut self,
         node: &TciNodeData,
         supports_recursive: bool,
+        label: Option<&[u8]>,
+        additional_fwids: &[Fwid],
     ) -> Result<usize, DpeErrorCode> {
-        let tcb_info_size =
-            Self::get_tcb_info_size(node, supports_recursive, /*tagged=*/ false)?;
+        let tcb_info_size = Self::get_tcb_info_size(
+            node,
+            supports_recursive,
+            label,
+            additional_fwids,
+            /*tagged=*/ false,
+        )?;
         // TcbInfo sequence
-        let mut bytes_written = self.encode_byte(Self::SEQUENCE_TAG)?;
-        bytes_written += self.encode_size_field(tcb_info_size)?;
+        let mut bytes_written = self.der.write_byte(DerWriter::SEQUENCE_TAG)?;
+        bytes_written += self.der.write_size(tcb_info_size)?;
+
+        // svn INTEGER
+        // IMPLICIT [3] Primitive
+        // Only present if the SVN was given when the context was derived
+        if node.has_svn.get() {
+            let svn = u64::from(node.svn).to_be_bytes();
+            let size = DerWriter::integer_size(node.svn.into(), /*tagged=*/ false)?;
+            bytes_written += self.der.write_byte(DerWriter::CONTEXT_SPECIFIC | 0x03)?;
+            bytes_written += self.der.write_size(size)?;
+            // A u32 never fills the u64, so the slice includes the leading
+            // zero byte if one is needed.
+            bytes_written += self.der.write_bytes(&svn[svn.len() - size..])?;
+        }
 
         // fwids SEQUENCE OF
         // IMPLICIT [6] Constructed
-        let fwid_size = Self::get_fwid_size(&node.tci_current.0, /*tagged=*/ true)?;
-        bytes_written += self.encode_byte(Self::CONTEXT_SPECIFIC | Self::CONSTRUCTED | 0x06)?;
-        if supports_recursive {
-            bytes_written += self.encode_size_field(fwid_size * 2)?;
-        } else {
-            bytes_written += self.encode_size_field(fwid_size)?;
-        }
+        bytes_written += self
+            .der
+            .write_byte(DerWriter::CONTEXT_SPECIFIC | DerWriter::CONSTRUCTED | 0x06)?;
+        bytes_written += self.der.write_size(Self::get_fwids_size(
+            node,
+            supports_recursive,
+            additional_fwids,
+        )?)?;
 
         // fwid[0] current measurement
-        bytes_written += self.encode_fwid(&node.tci_current)?;
+        bytes_written += self.encode_tci_fwid(&node.tci_current)?;
 
         // fwid[1] journey measurement
         // Omit fwid[1] from tcb_info if DPE_PROFILE does not support recursive
         if supports_recursive {
-            bytes_written += self.encode_fwid(&node.tci_cumulative)?;
+            bytes_written += self.encode_tci_fwid(&node.tci_cumulative)?;
+        }
+
+        // Platform FWIDs, each with its own hash algorithm
+        for fwid in additional_fwids {
+            let (hash_oid, digest) = Self::platform_fwid(fwid)?;
+            bytes_written += self.encode_fwid(hash_oid, digest)?;
         }
 
         // vendorInfo OCTET STRING
         // IMPLICIT[8] Primitive
+        // The locality, followed by the label of the certified key
         let vinfo = &node.locality.to_be_bytes();
-        bytes_written += self.encode_byte(Self::CONTEXT_SPECIFIC | 0x08)?;
-        bytes_written += self.encode_size_field(vinfo.len())?;
-        bytes_written += self.encode_bytes(vinfo)?;
+        let label = label.unwrap_or(&[]);
+        bytes_written += self.der.write_byte(DerWriter::CONTEXT_SPECIFIC | 0x08)?;
+        bytes_written += self.der.write_size(vinfo.len() + label.len())?;
+        bytes_written += self.der.write_bytes(vinfo)?;
+        bytes_written += self.der.write_bytes(label)?;
 
         // type OCTET STRING
         // IMPLICIT[9] Primitive
-        bytes_written += self.encode_byte(Self::CONTEXT_SPECIFIC | 0x09)?;
-        bytes_written += self.encode_size_field(core::mem::size_of::<u32>())?;
-        bytes_written += self.encode_bytes(&node.tci_type.to_be_bytes())?;
+        bytes_written += self.der.write_byte(DerWriter::CONTEXT_SPECIFIC | 0x09)?;
+        bytes_written += self.der.write_size(core::mem::size_of::<u32>())?;
+        bytes_written += self.der.write_bytes(&node.tci_type.to_be_bytes())?;
 
         Ok(bytes_written)
     }
@@ -1184,37 +1565,40 @@ impl CertWriter<'_> {
             Self::get_multi_tcb_info_size(measurements, /*tagged=*/ false)?;
 
         // Encode Extension
-        let mut bytes_written = self.encode_byte(Self::SEQUENCE_TAG)?;
-        bytes_written += self.encode_size_field(multi_tcb_info_size)?;
-        bytes_written += self.encode_oid(Self::MULTI_TCBINFO_OID)?;
+        let mut bytes_written = self.der.write_byte(DerWriter::SEQUENCE_TAG)?;
+        bytes_written += self.der.write_size(multi_tcb_info_size)?;
+        bytes_written += self.der.write_oid(Self::MULTI_TCBINFO_OID)?;
 
         let crit = if self.crit_dice { 0xFF } else { 0x00 };
-        bytes_written += self.encode_byte(Self::BOOL_TAG)?;
-        bytes_written += self.encode_size_field(Self::BOOL_SIZE)?;
-        bytes_written += self.encode_byte(crit)?;
+        bytes_written += self.der.write_byte(DerWriter::BOOL_TAG)?;
+        bytes_written += self.der.write_size(DerWriter::BOOL_SIZE)?;
+        bytes_written += self.der.write_byte(crit)?;
 
-        let tcb_infos_size = if !measurements.tci_nodes.is_empty() {
-            Self::get_tcb_info_size(
-                &measurements.tci_nodes[0],
-                measurements.supports_recursive,
-                /*tagged=*/ true,
-            )? * measurements.tci_nodes.len()
-        } else {
-            0
-        };
-        bytes_written += self.encode_byte(Self::OCTET_STRING_TAG)?;
-        bytes_written += self.encode_size_field(Self::get_structure_size(
+        let tcb_infos_size = Self::get_tcb_infos_size(measurements)?;
+        bytes_written += self.der.write_byte(DerWriter::OCTET_STRING_TAG)?;
+        bytes_written += self.der.write_size(DerWriter::structure_size(
             tcb_infos_size,
             /*tagged=*/ true,
         )?)?;
 
         // Encode MultiTcbInfo
-        bytes_written += self.encode_byte(Self::SEQUENCE_OF_TAG)?;
-        bytes_written += self.encode_size_field(tcb_infos_size)?;
+        bytes_written += self.der.write_byte(DerWriter::SEQUENCE_OF_TAG)?;
+        bytes_written += self.der.write_size(tcb_infos_size)?;
 
         // Encode multiple tcg-dice-TcbInfos
-        for node in measurements.tci_nodes {
-            bytes_written += self.encode_tcb_info(node, measurements.supports_recursive)?;
+        let last = measurements.tci_nodes.len().saturating_sub(1);
+        for (i, node) in measurements.tci_nodes.iter().enumerate() {
+            let (label, additional_fwids) = if i == last {
+                (measurements.label, measurements.additional_fwids)
+            } else {
+                (None, &[][..])
+            };
+            bytes_written += self.encode_tcb_info(
+                node,
+                measurements.supports_recursive,
+                label,
+                additional_fwids,
+            )?;
         }
 
         Ok(bytes_written)
@@ -1227,37 +1611,85 @@ impl CertWriter<'_> {
         let ueid_size = Self::get_ueid_size(measurements, /*tagged=*/ false)?;
 
         // Encode Extension
-        let mut bytes_written = self.encode_byte(Self::SEQUENCE_TAG)?;
-        bytes_written += self.encode_size_field(ueid_size)?;
-        bytes_written += self.encode_oid(Self::UEID_OID)?;
+        let mut bytes_written = self.der.write_byte(DerWriter::SEQUENCE_TAG)?;
+        bytes_written += self.der.write_size(ueid_size)?;
+        bytes_written += self.der.write_oid(Self::UEID_OID)?;
 
         let crit = if self.crit_dice { 0xFF } else { 0x00 };
-        bytes_written += self.encode_byte(Self::BOOL_TAG)?;
-        bytes_written += self.encode_size_field(Self::BOOL_SIZE)?;
-        bytes_written += self.encode_byte(crit)?;
+        bytes_written += self.der.write_byte(DerWriter::BOOL_TAG)?;
+        bytes_written += self.der.write_size(DerWriter::BOOL_SIZE)?;
+        bytes_written += self.der.write_byte(crit)?;
 
         // Extension data is sequence -> octet string. To compute size, wrap
         // in tagging twice.
-        bytes_written += self.encode_byte(Self::OCTET_STRING_TAG)?;
-        bytes_written += self.encode_size_field(Self::get_structure_size(
-            Self::get_structure_size(measurements.label.len(), /*tagged=*/ true)?,
+        bytes_written += self.der.write_byte(DerWriter::OCTET_STRING_TAG)?;
+        bytes_written += self.der.write_size(DerWriter::structure_size(
+            DerWriter::structure_size(measurements.ueid.len(), /*tagged=*/ true)?,
             /*tagged=*/ true,
         )?)?;
 
         // Sequence size to just a tagged OCTET_STRING
-        bytes_written += self.encode_byte(Self::SEQUENCE_TAG)?;
-        bytes_written += self.encode_size_field(Self::get_structure_size(
-            measurements.label.len(),
+        bytes_written += self.der.write_byte(DerWriter::SEQUENCE_TAG)?;
+        bytes_written += self.der.write_size(DerWriter::structure_size(
+            measurements.ueid.len(),
             /*tagged=*/ true,
         )?)?;
 
-        bytes_written += self.encode_byte(Self::OCTET_STRING_TAG)?;
-        bytes_written += self.encode_size_field(Self::get_structure_size(
-            measurements.label.len(),
+        bytes_written += self.der.write_byte(DerWriter::OCTET_STRING_TAG)?;
+        bytes_written += self.der.write_size(DerWriter::structure_size(
+            measurements.ueid.len(),
             /*tagged=*/ false,
         )?)?;
 
-        bytes_written += self.encode_bytes(measurements.label)?;
+        bytes_written += self.der.write_bytes(measurements.ueid)?;
+
+        Ok(bytes_written)
+    }
+
+    /// Encode a tcg-dice-TcbFreshness extension carrying the command counter
+    /// as a big-endian nonce. Nothing is written if there is no counter.
+    ///
+    /// TcbFreshness ::= SEQUENCE {
+    ///     nonce OCTET STRING
+    /// }
+    ///
+    /// https://trustedcomputinggroup.org/wp-content/uploads/TCG_DICE_Attestation_Architecture_r22_02dec2020.pdf
+    fn encode_tcb_freshness(
+        &mut self,
+        measurements: &MeasurementData,
+    ) -> Result<usize, DpeErrorCode> {
+        let Some(command_counter) = measurements.command_counter else {
+            return Ok(0);
+        };
+        let nonce = command_counter.to_be_bytes();
+        let tcb_freshness_size =
+            Self::get_tcb_freshness_size(measurements, /*tagged=*/ false)?;
+
+        // Encode Extension
+        let mut bytes_written = self.der.write_byte(DerWriter::SEQUENCE_TAG)?;
+        bytes_written += self.der.write_size(tcb_freshness_size)?;
+        bytes_written += self.der.write_oid(Self::TCB_FRESHNESS_OID)?;
+
+        let crit = if self.crit_dice { 0xFF } else { 0x00 };
+        bytes_written += self.der.write_byte(DerWriter::BOOL_TAG)?;
+        bytes_written += self.der.write_size(DerWriter::BOOL_SIZE)?;
+        bytes_written += self.der.write_byte(crit)?;
+
+        bytes_written += self.der.write_byte(DerWriter::OCTET_STRING_TAG)?;
+        bytes_written += self.der.write_size(DerWriter::structure_size(
+            DerWriter::structure_size(nonce.len(), /*tagged=*/ true)?,
+            /*tagged=*/ true,
+        )?)?;
+
+        bytes_written += self.der.write_byte(DerWriter::SEQUENCE_TAG)?;
+        bytes_written += self.der.write_size(DerWriter::structure_size(
+            nonce.len(),
+            /*tagged=*/ true,
+        )?)?;
+
+        bytes_written += self.der.write_byte(DerWriter::OCTET_STRING_TAG)?;
+        bytes_written += self.der.write_size(nonce.len())?;
+        bytes_written += self.der.write_bytes(&nonce)?;
 
         Ok(bytes_written)
     }
@@ -1269,38 +1701,36 @@ impl CertWriter<'_> {
         &mut self,
         measurements: &MeasurementData,
     ) -> Result<usize, DpeErrorCode> {
-        let basic_constraints_size = Self::get_basic_constraints_size(/*tagged=*/ false)?;
+        let basic_constraints_size =
+            Self::get_basic_constraints_size(measurements, /*tagged=*/ false)?;
+        let constraints_size = Self::get_basic_constraints_value_size(measurements)?;
 
         // Encode Extension
-        let mut bytes_written = self.encode_byte(Self::SEQUENCE_TAG)?;
-        bytes_written += self.encode_size_field(basic_constraints_size)?;
-        bytes_written += self.encode_oid(Self::BASIC_CONSTRAINTS_OID)?;
+        let mut bytes_written = self.der.write_byte(DerWriter::SEQUENCE_TAG)?;
+        bytes_written += self.der.write_size(basic_constraints_size)?;
+        bytes_written += self.der.write_oid(Self::BASIC_CONSTRAINTS_OID)?;
 
-        bytes_written += self.encode_byte(Self::BOOL_TAG)?;
-        bytes_written += self.encode_size_field(Self::BOOL_SIZE)?;
-        bytes_written += self.encode_byte(0xFF)?;
+        bytes_written += self.der.write_byte(DerWriter::BOOL_TAG)?;
+        bytes_written += self.der.write_size(DerWriter::BOOL_SIZE)?;
+        bytes_written += self.der.write_byte(0xFF)?;
 
         // Extension data is sequence -> octet string. To compute size, wrap
         // in tagging twice.
-        bytes_written += self.encode_byte(Self::OCTET_STRING_TAG)?;
-        bytes_written += self.encode_size_field(Self::get_structure_size(
-            Self::get_structure_size(1, /*tagged=*/ true)?,
-            /*tagged=*/ true,
-        )?)?;
-
-        // Sequence size to just a tagged bool
-        bytes_written += self.encode_byte(Self::SEQUENCE_TAG)?;
-        bytes_written += self.encode_size_field(Self::get_structure_size(
-            Self::BOOL_SIZE,
+        bytes_written += self.der.write_byte(DerWriter::OCTET_STRING_TAG)?;
+        bytes_written += self.der.write_size(DerWriter::structure_size(
+            constraints_size,
             /*tagged=*/ true,
         )?)?;
 
-        bytes_written += self.encode_byte(Self::BOOL_TAG)?;
-        bytes_written += self.encode_size_field(Self::BOOL_SIZE)?;
+        // Sequence holds cA for CAs and is empty for leaves, since DER omits
+        // fields set to their DEFAULT value.
+        bytes_written += self.der.write_byte(DerWriter::SEQUENCE_TAG)?;
+        bytes_written += self.der.write_size(constraints_size)?;
+
         if measurements.is_ca {
-            bytes_written += self.encode_byte(0xFF)?;
-        } else {
-            bytes_written += self.encode_byte(0x00)?;
+            bytes_written += self.der.write_byte(DerWriter::BOOL_TAG)?;
+            bytes_written += self.der.write_size(DerWriter::BOOL_SIZE)?;
+            bytes_written += self.der.write_byte(0xFF)?;
         }
 
         Ok(bytes_written)
@@ -1313,26 +1743,27 @@ impl CertWriter<'_> {
         let key_usage_size = Self::get_key_usage_size(/*tagged=*/ false)?;
 
         // Encode Extension
-        let mut bytes_written = self.encode_byte(Self::SEQUENCE_TAG)?;
-        bytes_written += self.encode_size_field(key_usage_size)?;
-        bytes_written += self.encode_oid(Self::KEY_USAGE_OID)?;
+        let mut bytes_written = self.der.write_byte(DerWriter::SEQUENCE_TAG)?;
+        bytes_written += self.der.write_size(key_usage_size)?;
+        bytes_written += self.der.write_oid(Self::KEY_USAGE_OID)?;
 
-        bytes_written += self.encode_byte(Self::BOOL_TAG)?;
-        bytes_written += self.encode_size_field(Self::BOOL_SIZE)?;
-        bytes_written += self.encode_byte(0xFF)?;
+        bytes_written += self.der.write_byte(DerWriter::BOOL_TAG)?;
+        bytes_written += self.der.write_size(DerWriter::BOOL_SIZE)?;
+        bytes_written += self.der.write_byte(0xFF)?;
 
         // Extension data is sequence -> octet string. To compute size, wrap
         // in tagging twice.
-        bytes_written += self.encode_byte(Self::OCTET_STRING_TAG)?;
-        bytes_written +=
-            self.encode_size_field(Self::get_structure_size(2, /*tagged=*/ true)?)?;
+        bytes_written += self.der.write_byte(DerWriter::OCTET_STRING_TAG)?;
+        bytes_written += self
+            .der
+            .write_size(DerWriter::structure_size(2, /*tagged=*/ true)?)?;
 
-        bytes_written += self.encode_byte(Self::BIT_STRING_TAG)?;
+        bytes_written += self.der.write_byte(DerWriter::BIT_STRING_TAG)?;
 
         // Bit string is 2 bytes:
         // * Unused bits
         // * KeyUsage bits
-        bytes_written += self.encode_size_field(2)?;
+        bytes_written += self.der.write_size(2)?;
 
         // Count trailing bits in KeyUsage byte as unused
         let (key_usage, unused_bits) = if is_ca {
@@ -1345,9 +1776,9 @@ impl CertWriter<'_> {
         };
 
         // Unused bits
-        bytes_written += self.encode_byte(unused_bits)?;
+        bytes_written += self.der.write_byte(unused_bits)?;
 
-        bytes_written += self.encode_byte(key_usage.0)?;
+        bytes_written += self.der.write_byte(key_usage.0)?;
 
         Ok(bytes_written)
     }
@@ -1356,8 +1787,8 @@ impl CertWriter<'_> {
     ///
     /// The included EKU OIDs is as follows based on whether or not this certificate is for a CA:
     ///
-    /// is_ca = true: id-tcg-kp-identityLoc (2.23.133.8.7)
-    /// is_ca = false: id-tcg-kp-attestLoc (2.23.133.8.9)
+    /// is_ca = true: tcg-dice-kp-eca (2.23.133.5.4.100.12)
+    /// is_ca = false: tcg-dice-kp-attestLoc (2.23.133.5.4.100.9)
     ///
     /// https://datatracker.ietf.org/doc/html/rfc5280
     fn encode_extended_key_usage(
@@ -1374,30 +1805,30 @@ impl CertWriter<'_> {
         let extended_key_usage_size = Self::get_extended_key_usage_size(measurements, false)?;
 
         // Encode Extension
-        let mut bytes_written = self.encode_byte(Self::SEQUENCE_TAG)?;
-        bytes_written += self.encode_size_field(extended_key_usage_size)?;
-        bytes_written += self.encode_oid(Self::EXTENDED_KEY_USAGE_OID)?;
+        let mut bytes_written = self.der.write_byte(DerWriter::SEQUENCE_TAG)?;
+        bytes_written += self.der.write_size(extended_key_usage_size)?;
+        bytes_written += self.der.write_oid(Self::EXTENDED_KEY_USAGE_OID)?;
 
-        bytes_written += self.encode_byte(Self::BOOL_TAG)?;
-        bytes_written += self.encode_size_field(Self::BOOL_SIZE)?;
-        bytes_written += self.encode_byte(0xFF)?;
+        bytes_written += self.der.write_byte(DerWriter::BOOL_TAG)?;
+        bytes_written += self.der.write_size(DerWriter::BOOL_SIZE)?;
+        bytes_written += self.der.write_byte(0xFF)?;
 
         // Extension data is sequence -> octet string. To compute size, wrap
         // in tagging twice.
-        bytes_written += self.encode_byte(Self::OCTET_STRING_TAG)?;
-        bytes_written += self.encode_size_field(Self::get_structure_size(
-            Self::get_structure_size(policy_oid.len(), /*tagged=*/ true)?,
+        bytes_written += self.der.write_byte(DerWriter::OCTET_STRING_TAG)?;
+        bytes_written += self.der.write_size(DerWriter::structure_size(
+            DerWriter::structure_size(policy_oid.len(), /*tagged=*/ true)?,
             /*tagged=*/ true,
         )?)?;
 
         // Sequence size is the size of all the EKU OIDs.
-        bytes_written += self.encode_byte(Self::SEQUENCE_TAG)?;
-        bytes_written += self.encode_size_field(Self::get_structure_size(
+        bytes_written += self.der.write_byte(DerWriter::SEQUENCE_TAG)?;
+        bytes_written += self.der.write_size(DerWriter::structure_size(
             policy_oid.len(),
             /*tagged=*/ true,
         )?)?;
 
-        bytes_written += self.encode_oid(policy_oid)?;
+        bytes_written += self.der.write_oid(policy_oid)?;
 
         Ok(bytes_written)
     }
@@ -1412,7 +1843,7 @@ impl CertWriter<'_> {
         measurements: &MeasurementData,
         is_x509: bool,
     ) -> Result<usize, DpeErrorCode> {
-        if !measurements.is_ca || !is_x509 {
+        if !is_x509 {
             return Ok(0);
         }
 
@@ -1423,31 +1854,31 @@ impl CertWriter<'_> {
         )?;
 
         // Encode Extension
-        let mut bytes_written = self.encode_byte(Self::SEQUENCE_TAG)?;
-        bytes_written += self.encode_size_field(aki_extension_size)?;
-        bytes_written += self.encode_oid(Self::AUTHORITY_KEY_IDENTIFIER_OID)?;
+        let mut bytes_written = self.der.write_byte(DerWriter::SEQUENCE_TAG)?;
+        bytes_written += self.der.write_size(aki_extension_size)?;
+        bytes_written += self.der.write_oid(Self::AUTHORITY_KEY_IDENTIFIER_OID)?;
 
-        bytes_written += self.encode_byte(Self::BOOL_TAG)?;
-        bytes_written += self.encode_size_field(Self::BOOL_SIZE)?;
+        bytes_written += self.der.write_byte(DerWriter::BOOL_TAG)?;
+        bytes_written += self.der.write_size(DerWriter::BOOL_SIZE)?;
         // authority key identifier extension must NOT be marked critical
-        bytes_written += self.encode_byte(0x00)?;
+        bytes_written += self.der.write_byte(0x00)?;
 
         // Extension data is sequence -> octet string. To compute size, wrap
         // in tagging once.
         let key_identifier_size = Self::get_key_identifier_size(
             &measurements.authority_key_identifier,
             /*tagged=*/ true,
-            /*explicit=*/ true,
+            /*explicit=*/ false,
         )?;
-        bytes_written += self.encode_byte(Self::OCTET_STRING_TAG)?;
-        bytes_written += self.encode_size_field(Self::get_structure_size(
+        bytes_written += self.der.write_byte(DerWriter::OCTET_STRING_TAG)?;
+        bytes_written += self.der.write_size(DerWriter::structure_size(
             key_identifier_size,
             /*tagged=*/ true,
         )?)?;
 
         // Encode extension data sequence
-        bytes_written += self.encode_byte(Self::SEQUENCE_TAG)?;
-        bytes_written += self.encode_size_field(key_identifier_size)?;
+        bytes_written += self.der.write_byte(DerWriter::SEQUENCE_TAG)?;
+        bytes_written += self.der.write_size(key_identifier_size)?;
         bytes_written += self.encode_key_identifier(&measurements.authority_key_identifier)?;
 
         Ok(bytes_written)
@@ -1458,7 +1889,7 @@ impl CertWriter<'_> {
         measurements: &MeasurementData,
         is_x509: bool,
     ) -> Result<usize, DpeErrorCode> {
-        if !measurements.is_ca || !is_x509 {
+        if !is_x509 {
             return Ok(0);
         }
         let ski_extension_size = Self::get_subject_key_identifier_extension_size(
@@ -1468,27 +1899,29 @@ impl CertWriter<'_> {
         )?;
 
         // Encode Extension
-        let mut bytes_written = self.encode_byte(Self::SEQUENCE_TAG)?;
-        bytes_written += self.encode_size_field(ski_extension_size)?;
-        bytes_written += self.encode_oid(Self::SUBJECT_KEY_IDENTIFIER_OID)?;
+        let mut bytes_written = self.der.write_byte(DerWriter::SEQUENCE_TAG)?;
+        bytes_written += self.der.write_size(ski_extension_size)?;
+        bytes_written += self.der.write_oid(Self::SUBJECT_KEY_IDENTIFIER_OID)?;
 
-        bytes_written += self.encode_byte(Self::BOOL_TAG)?;
-        bytes_written += self.encode_size_field(Self::BOOL_SIZE)?;
+        bytes_written += self.der.write_byte(DerWriter::BOOL_TAG)?;
+        bytes_written += self.der.write_size(DerWriter::BOOL_SIZE)?;
         // subject key identifier extension must NOT be marked critical
-        bytes_written += self.encode_byte(0x00)?;
+        bytes_written += self.der.write_byte(0x00)?;
 
         // Extension data is sequence -> octet string. To compute size, wrap
         // in tagging once.
-        bytes_written += self.encode_byte(Self::OCTET_STRING_TAG)?;
-        bytes_written += self.encode_size_field(Self::get_structure_size(
+        bytes_written += self.der.write_byte(DerWriter::OCTET_STRING_TAG)?;
+        bytes_written += self.der.write_size(DerWriter::structure_size(
             measurements.subject_key_identifier.len(),
             /*tagged=*/ true,
         )?)?;
 
         // SubjectKeyIdentifier := OCTET STRING
-        bytes_written += self.encode_byte(Self::OCTET_STRING_TAG)?;
-        bytes_written += self.encode_size_field(measurements.subject_key_identifier.len())?;
-        bytes_written += self.encode_bytes(&measurements.subject_key_identifier)?;
+        bytes_written += self.der.write_byte(DerWriter::OCTET_STRING_TAG)?;
+        bytes_written += self
+            .der
+            .write_size(measurements.subject_key_identifier.len())?;
+        bytes_written += self.der.write_bytes(&measurements.subject_key_identifier)?;
 
         Ok(bytes_written)
     }
@@ -1501,8 +1934,10 @@ impl CertWriter<'_> {
         let mut bytes_written = 0;
         if is_x509 {
             // Extensions is EXPLICIT field number 3
-            bytes_written += self.encode_byte(Self::CONTEXT_SPECIFIC | Self::CONSTRUCTED | 0x03)?;
-            bytes_written += self.encode_size_field(Self::get_extensions_size(
+            bytes_written += self
+                .der
+                .write_byte(DerWriter::CONTEXT_SPECIFIC | DerWriter::CONSTRUCTED | 0x03)?;
+            bytes_written += self.der.write_size(Self::get_extensions_size(
                 measurements,
                 /*tagged=*/ true,
                 /*explicit=*/ false,
@@ -1511,8 +1946,8 @@ impl CertWriter<'_> {
         }
 
         // SEQUENCE OF Extension
-        bytes_written += self.encode_byte(Self::SEQUENCE_OF_TAG)?;
-        bytes_written += self.encode_size_field(Self::get_extensions_size(
+        bytes_written += self.der.write_byte(DerWriter::SEQUENCE_OF_TAG)?;
+        bytes_written += self.der.write_size(Self::get_extensions_size(
             measurements,
             /*tagged=*/ false,
             /*explicit=*/ false,
@@ -1521,6 +1956,7 @@ impl CertWriter<'_> {
 
         bytes_written += self.encode_multi_tcb_info(measurements)?;
         bytes_written += self.encode_ueid(measurements)?;
+        bytes_written += self.encode_tcb_freshness(measurements)?;
         bytes_written += self.encode_basic_constraints(measurements)?;
         bytes_written += self.encode_key_usage(measurements.is_ca)?;
         bytes_written += self.encode_extended_key_usage(measurements)?;
@@ -1538,8 +1974,8 @@ impl CertWriter<'_> {
             SignerIdentifier::IssuerAndSerialNumber {
                 issuer_name: _,
                 serial_number: _,
-            } => self.encode_integer(Self::CMS_V1),
-            SignerIdentifier::SubjectKeyIdentifier(_) => self.encode_integer(Self::CMS_V3),
+            } => self.der.write_integer(Self::CMS_V1),
+            SignerIdentifier::SubjectKeyIdentifier(_) => self.der.write_integer(Self::CMS_V3),
         }
     }
 
@@ -1563,15 +1999,16 @@ impl CertWriter<'_> {
         sid: &SignerIdentifier,
     ) -> Result<usize, DpeErrorCode> {
         // SignedData is EXPLICIT field number 0
-        let mut bytes_written =
-            self.encode_byte(Self::CONTEXT_SPECIFIC | Self::CONSTRUCTED | 0x0)?;
-        bytes_written += self.encode_size_field(Self::get_signed_data_size(
+        let mut bytes_written = self
+            .der
+            .write_byte(DerWriter::CONTEXT_SPECIFIC | DerWriter::CONSTRUCTED | 0x0)?;
+        bytes_written += self.der.write_size(Self::get_signed_data_size(
             csr, sig, sid, /*tagged=*/ true, /*explicit=*/ false,
         )?)?;
 
         // SignedData sequence
-        bytes_written += self.encode_tag_field(Self::SEQUENCE_TAG)?;
-        bytes_written += self.encode_size_field(Self::get_signed_data_size(
+        bytes_written += self.der.write_tag(DerWriter::SEQUENCE_TAG)?;
+        bytes_written += self.der.write_size(Self::get_signed_data_size(
             csr, sig, sid, /*tagged=*/ false, /*explicit=*/ false,
         )?)?;
 
@@ -1579,18 +2016,20 @@ impl CertWriter<'_> {
         bytes_written += self.encode_cms_version(sid)?;
 
         // digestAlgorithms
-        bytes_written += self.encode_tag_field(Self::SET_OF_TAG)?;
-        bytes_written +=
-            self.encode_size_field(Self::get_hash_alg_id_size(/*tagged=*/ true)?)?;
+        bytes_written += self.der.write_tag(DerWriter::SET_OF_TAG)?;
+        bytes_written += self
+            .der
+            .write_size(Self::get_hash_alg_id_size(/*tagged=*/ true)?)?;
         bytes_written += self.encode_hash_alg_id()?;
 
         // encapContentInfo
         bytes_written += self.encode_encapsulated_content_info(csr)?;
 
         // signerInfos
-        bytes_written += self.encode_tag_field(Self::SET_OF_TAG)?;
-        bytes_written +=
-            self.encode_size_field(Self::get_signer_info_size(sig, sid, /*tagged=*/ true)?)?;
+        bytes_written += self.der.write_tag(DerWriter::SET_OF_TAG)?;
+        bytes_written += self
+            .der
+            .write_size(Self::get_signer_info_size(sig, sid, /*tagged=*/ true)?)?;
         bytes_written += self.encode_signer_info(sig, sid)?;
 
         Ok(bytes_written)
@@ -1609,24 +2048,25 @@ impl CertWriter<'_> {
     #[allow(clippy::identity_op)]
     fn encode_attributes(&mut self, measurements: &MeasurementData) -> Result<usize, DpeErrorCode> {
         // Attributes is EXPLICIT field number 0
-        let mut bytes_written =
-            self.encode_byte(Self::CONTEXT_SPECIFIC | Self::CONSTRUCTED | 0x0)?;
-        bytes_written += self.encode_size_field(Self::get_attributes_size(
+        let mut bytes_written = self
+            .der
+            .write_byte(DerWriter::CONTEXT_SPECIFIC | DerWriter::CONSTRUCTED | 0x0)?;
+        bytes_written += self.der.write_size(Self::get_attributes_size(
             measurements,
             /*tagged=*/ false,
         )?)?;
 
         // Attribute Sequence
-        bytes_written += self.encode_tag_field(Self::SEQUENCE_TAG)?;
-        bytes_written += self.encode_size_field(Self::get_attribute_size(
+        bytes_written += self.der.write_tag(DerWriter::SEQUENCE_TAG)?;
+        bytes_written += self.der.write_size(Self::get_attribute_size(
             measurements,
             /*tagged=*/ false,
         )?)?;
-        bytes_written += self.encode_oid(Self::EXTENSION_REQUEST_OID)?;
+        bytes_written += self.der.write_oid(Self::EXTENSION_REQUEST_OID)?;
 
         // attrValues SET OF
-        bytes_written += self.encode_tag_field(Self::SET_OF_TAG)?;
-        bytes_written += self.encode_size_field(Self::get_extensions_size(
+        bytes_written += self.der.write_tag(DerWriter::SET_OF_TAG)?;
+        bytes_written += self.der.write_size(Self::get_extensions_size(
             measurements,
             /*tagged=*/ true,
             /*explicit=*/ false,
@@ -1658,8 +2098,8 @@ impl CertWriter<'_> {
         let signer_info_size = Self::get_signer_info_size(sig, sid, /*tagged=*/ false)?;
 
         // SignerInfo Sequence
-        let mut bytes_written = self.encode_tag_field(Self::SEQUENCE_TAG)?;
-        bytes_written += self.encode_size_field(signer_info_size)?;
+        let mut bytes_written = self.der.write_tag(DerWriter::SEQUENCE_TAG)?;
+        bytes_written += self.der.write_size(signer_info_size)?;
 
         // CMS version
         bytes_written += self.encode_cms_version(sid)?;
@@ -1715,14 +2155,14 @@ impl CertWriter<'_> {
         )?;
 
         // IssuerAndSerialNumber sequence
-        let mut bytes_written = self.encode_tag_field(Self::SEQUENCE_TAG)?;
-        bytes_written += self.encode_size_field(issuer_and_serial_number_size)?;
+        let mut bytes_written = self.der.write_tag(DerWriter::SEQUENCE_TAG)?;
+        bytes_written += self.der.write_size(issuer_and_serial_number_size)?;
 
         // issuer
-        bytes_written += self.encode_bytes(issuer_name)?;
+        bytes_written += self.der.write_bytes(issuer_name)?;
 
         // serialNumber
-        bytes_written += self.encode_integer_bytes(serial_number)?;
+        bytes_written += self.der.write_integer_bytes(serial_number)?;
 
         Ok(bytes_written)
     }
@@ -1736,21 +2176,21 @@ impl CertWriter<'_> {
         subject_key_identifier: &[u8],
     ) -> Result<usize, DpeErrorCode> {
         // SubjectKeyIdentifier is IMPLICIT field number 0
-        let mut bytes_written = self.encode_byte(Self::CONTEXT_SPECIFIC | 0x0)?;
-        bytes_written += self.encode_size_field(Self::get_subject_key_identifier_size(
+        let mut bytes_written = self.der.write_byte(DerWriter::CONTEXT_SPECIFIC | 0x0)?;
+        bytes_written += self.der.write_size(Self::get_subject_key_identifier_size(
             subject_key_identifier,
             /*tagged=*/ true,
             /*explicit=*/ false,
         )?)?;
 
         // SubjectKeyIdentifier OCTET STRING
-        bytes_written += self.encode_tag_field(Self::OCTET_STRING_TAG)?;
-        bytes_written += self.encode_size_field(Self::get_subject_key_identifier_size(
+        bytes_written += self.der.write_tag(DerWriter::OCTET_STRING_TAG)?;
+        bytes_written += self.der.write_size(Self::get_subject_key_identifier_size(
             subject_key_identifier,
             /*tagged=*/ false,
             /*explicit=*/ false,
         )?)?;
-        bytes_written += self.encode_bytes(subject_key_identifier)?;
+        bytes_written += self.der.write_bytes(subject_key_identifier)?;
 
         Ok(bytes_written)
     }
@@ -1760,22 +2200,15 @@ impl CertWriter<'_> {
     /// KeyIdentifier ::= OCTET STRING
     #[allow(clippy::identity_op)]
     fn encode_key_identifier(&mut self, key_identifier: &[u8]) -> Result<usize, DpeErrorCode> {
-        // KeyIdentifier is IMPLICIT field number 0
-        let mut bytes_written = self.encode_byte(Self::CONTEXT_SPECIFIC | 0x0)?;
-        bytes_written += self.encode_size_field(Self::get_key_identifier_size(
-            key_identifier,
-            /*tagged=*/ true,
-            /*explicit=*/ false,
-        )?)?;
-
-        // KeyIdentifier := OCTET STRING
-        bytes_written += self.encode_tag_field(Self::OCTET_STRING_TAG)?;
-        bytes_written += self.encode_size_field(Self::get_key_identifier_size(
+        // KeyIdentifier is IMPLICIT field number 0, so the context tag
+        // replaces the OCTET STRING tag.
+        let mut bytes_written = self.der.write_byte(DerWriter::CONTEXT_SPECIFIC | 0x0)?;
+        bytes_written += self.der.write_size(Self::get_key_identifier_size(
             key_identifier,
             /*tagged=*/ false,
             /*explicit=*/ false,
         )?)?;
-        bytes_written += self.encode_bytes(key_identifier)?;
+        bytes_written += self.der.write_bytes(key_identifier)?;
 
         Ok(bytes_written)
     }
@@ -1786,18 +2219,19 @@ impl CertWriter<'_> {
     #[allow(clippy::identity_op)]
     fn encode_econtent(&mut self, bytes: &[u8]) -> Result<usize, DpeErrorCode> {
         // eContent is EXPLICIT field number 0
-        let mut bytes_written =
-            self.encode_byte(Self::CONTEXT_SPECIFIC | Self::CONSTRUCTED | 0x0)?;
-        bytes_written += self.encode_size_field(Self::get_econtent_size(
+        let mut bytes_written = self
+            .der
+            .write_byte(DerWriter::CONTEXT_SPECIFIC | DerWriter::CONSTRUCTED | 0x0)?;
+        bytes_written += self.der.write_size(Self::get_econtent_size(
             bytes, /*tagged=*/ true, /*explicit=*/ false,
         )?)?;
 
         // eContent OCTET STRING
-        bytes_written += self.encode_byte(Self::OCTET_STRING_TAG)?;
-        bytes_written += self.encode_size_field(Self::get_econtent_size(
+        bytes_written += self.der.write_byte(DerWriter::OCTET_STRING_TAG)?;
+        bytes_written += self.der.write_size(Self::get_econtent_size(
             bytes, /*tagged=*/ false, /*explicit=*/ false,
         )?)?;
-        bytes_written += self.encode_bytes(bytes)?;
+        bytes_written += self.der.write_bytes(bytes)?;
 
         Ok(bytes_written)
     }
@@ -1813,9 +2247,9 @@ impl CertWriter<'_> {
             Self::get_encap_content_info_size(csr, /*tagged=*/ false)?;
 
         // EncapsulatedContentInfo Sequence
-        let mut bytes_written = self.encode_byte(Self::SEQUENCE_TAG)?;
-        bytes_written += self.encode_size_field(encap_content_info_size)?;
-        bytes_written += self.encode_oid(Self::ID_DATA_OID)?;
+        let mut bytes_written = self.der.write_byte(DerWriter::SEQUENCE_TAG)?;
+        bytes_written += self.der.write_size(encap_content_info_size)?;
+        bytes_written += self.der.write_oid(Self::ID_DATA_OID)?;
 
         bytes_written += self.encode_econtent(csr)?;
 
@@ -1845,7 +2279,7 @@ impl CertWriter<'_> {
     /// * `serial_number` - A byte slice holding the serial number.
     /// * `issuer_name` - A DER encoded issuer RDN.
     /// * `subject_name` - The subject name RDN struct to encode.
-    /// * `pubkey` - ECDSA Public key.
+    /// * `pubkey` - Subject public key.
     /// * `measurements` - DPE measurement data.
     /// * `validity` - Time period in which certificate is valid.
     pub fn encode_ecdsa_tbs(
@@ -1853,7 +2287,7 @@ impl CertWriter<'_> {
         serial_number: &[u8],
         issuer_name: &[u8],
         subject_name: &Name,
-        pubkey: &EcdsaPub,
+        pubkey: SubjectPubKey,
         measurements: &MeasurementData,
         validity: &CertValidity,
     ) -> Result<usize, DpeErrorCode> {
@@ -1868,20 +2302,20 @@ impl CertWriter<'_> {
         )?;
 
         // TBS sequence
-        let mut bytes_written = self.encode_tag_field(Self::SEQUENCE_TAG)?;
-        bytes_written += self.encode_size_field(tbs_size)?;
+        let mut bytes_written = self.der.write_tag(DerWriter::SEQUENCE_TAG)?;
+        bytes_written += self.der.write_size(tbs_size)?;
 
         // version
         bytes_written += self.encode_version()?;
 
         // serialNumber
-        bytes_written += self.encode_integer_bytes(serial_number)?;
+        bytes_written += self.der.write_integer_bytes(serial_number)?;
 
         // signature
         bytes_written += self.encode_ecdsa_sig_alg_id()?;
 
         // issuer
-        bytes_written += self.encode_bytes(issuer_name)?;
+        bytes_written += self.der.write_bytes(issuer_name)?;
 
         // validity
         bytes_written += self.encode_validity(validity)?;
@@ -1890,7 +2324,7 @@ impl CertWriter<'_> {
         bytes_written += self.encode_rdn(subject_name)?;
 
         // subjectPublicKeyInfo
-        bytes_written += self.encode_ecdsa_subject_pubkey_info(pubkey)?;
+        bytes_written += self.encode_subject_pubkey_info(pubkey)?;
 
         // extensions
         bytes_written += self.encode_extensions(measurements, /*is_x509=*/ true)?;
@@ -1916,11 +2350,11 @@ impl CertWriter<'_> {
             + Self::get_ecdsa_signature_bit_string_size(sig, /*tagged=*/ true)?;
 
         // Certificate sequence
-        let mut bytes_written = self.encode_tag_field(Self::SEQUENCE_TAG)?;
-        bytes_written += self.encode_size_field(cert_size)?;
+        let mut bytes_written = self.der.write_tag(DerWriter::SEQUENCE_TAG)?;
+        bytes_written += self.der.write_size(cert_size)?;
 
         // TBS
-        bytes_written += self.encode_bytes(tbs)?;
+        bytes_written += self.der.write_bytes(tbs)?;
 
         // Alg ID
         bytes_written += self.encode_ecdsa_sig_alg_id()?;
@@ -1942,14 +2376,14 @@ impl CertWriter<'_> {
     ///
     /// # Arguments
     ///
-    /// * `pubkey` - ECDSA Public key.
+    /// * `pub_key` - Subject public key.
     /// * `subject_name` - The subject name RDN struct to encode.
     /// * `measurements` - DPE measurement data.
     ///
     /// Returns number of bytes written to `certificate`
     pub fn encode_certification_request_info(
         &mut self,
-        pub_key: &EcdsaPub,
+        pub_key: SubjectPubKey,
         subject_name: &Name,
         measurements: &MeasurementData,
     ) -> Result<usize, DpeErrorCode> {
@@ -1961,17 +2395,17 @@ impl CertWriter<'_> {
         )?;
 
         // CertificationRequestInfo Sequence
-        let mut bytes_written = self.encode_tag_field(Self::SEQUENCE_TAG)?;
-        bytes_written += self.encode_size_field(cert_req_info_size)?;
+        let mut bytes_written = self.der.write_tag(DerWriter::SEQUENCE_TAG)?;
+        bytes_written += self.der.write_size(cert_req_info_size)?;
 
         // version
-        bytes_written += self.encode_integer(Self::CSR_V0)?;
+        bytes_written += self.der.write_integer(Self::CSR_V0)?;
 
         // subject
         bytes_written += self.encode_rdn(subject_name)?;
 
         // subjectPublicKeyInfo
-        bytes_written += self.encode_ecdsa_subject_pubkey_info(pub_key)?;
+        bytes_written += self.encode_subject_pubkey_info(pub_key)?;
 
         // attributes
         bytes_written += self.encode_attributes(measurements)?;
@@ -1998,11 +2432,11 @@ impl CertWriter<'_> {
             + Self::get_ecdsa_signature_bit_string_size(sig, /*tagged=*/ true)?;
 
         // CertificateRequest sequence
-        let mut bytes_written = self.encode_tag_field(Self::SEQUENCE_TAG)?;
-        bytes_written += self.encode_size_field(csr_size)?;
+        let mut bytes_written = self.der.write_tag(DerWriter::SEQUENCE_TAG)?;
+        bytes_written += self.der.write_size(csr_size)?;
 
         // CertificationRequestInfo
-        bytes_written += self.encode_bytes(cert_req_info)?;
+        bytes_written += self.der.write_bytes(cert_req_info)?;
 
         // Alg ID
         bytes_written += self.encode_ecdsa_sig_alg_id()?;
@@ -2013,6 +2447,70 @@ impl CertWriter<'_> {
         Ok(bytes_written)
     }
 
+    /// Encode an PKCS #10 CSR self-signed with an RSA key
+    ///
+    /// The same as `encode_csr`, with an RSA signature. `padding` selects the
+    /// signature algorithm identifier and must be the scheme `sig` was made
+    /// with.
+    ///
+    /// Returns number of bytes written to `certificate`
+    #[cfg(feature = "rsa")]
+    pub fn encode_rsa_csr(
+        &mut self,
+        cert_req_info: &[u8],
+        sig: &RsaSig,
+        padding: RsaPadding,
+    ) -> Result<usize, DpeErrorCode> {
+        let csr_size = cert_req_info.len()
+            + Self::get_rsa_sig_alg_id_size(padding, /*tagged=*/ true)?
+            + DerWriter::bit_string_size(sig.bytes(), /*tagged=*/ true)?;
+
+        // CertificateRequest sequence
+        let mut bytes_written = self.der.write_sequence_header(csr_size)?;
+
+        // CertificationRequestInfo
+        bytes_written += self.der.write_bytes(cert_req_info)?;
+
+        // Alg ID
+        bytes_written += self.encode_rsa_sig_alg_id(padding)?;
+
+        // Signature
+        bytes_written += self.der.write_bit_string(sig.bytes())?;
+
+        Ok(bytes_written)
+    }
+
+    /// Encode an PKCS #10 CSR self-signed with an Ed25519 key
+    ///
+    /// The same as `encode_csr`, with an Ed25519 signature over
+    /// `cert_req_info` itself.
+    ///
+    /// Returns number of bytes written to `certificate`
+    #[cfg(feature = "dpe_profile_ed25519_sha512")]
+    pub fn encode_ed25519_csr(
+        &mut self,
+        cert_req_info: &[u8],
+        sig: &Ed25519Sig,
+    ) -> Result<usize, DpeErrorCode> {
+        let csr_size = cert_req_info.len()
+            + Self::get_ed25519_alg_id_size(/*tagged=*/ true)?
+            + DerWriter::bit_string_size(sig.bytes(), /*tagged=*/ true)?;
+
+        // CertificateRequest sequence
+        let mut bytes_written = self.der.write_sequence_header(csr_size)?;
+
+        // CertificationRequestInfo
+        bytes_written += self.der.write_bytes(cert_req_info)?;
+
+        // Alg ID
+        bytes_written += self.encode_ed25519_alg_id()?;
+
+        // Signature
+        bytes_written += self.der.write_bit_string(sig.bytes())?;
+
+        Ok(bytes_written)
+    }
+
     /// Encode a CMS ContentInfo message
     ///
     /// ContentInfo  ::=  SEQUENCE  {
@@ -2025,16 +2523,17 @@ impl CertWriter<'_> {
         sig: &EcdsaSig,
         sid: &SignerIdentifier,
     ) -> Result<usize, DpeErrorCode> {
-        let size = Self::get_structure_size(Self::ID_SIGNED_DATA_OID.len(), /*tagged=*/ true)?
-            + Self::get_signed_data_size(
-                csr, sig, sid, /*tagged=*/ true, /*explicit=*/ true,
-            )?;
+        let size =
+            DerWriter::structure_size(Self::ID_SIGNED_DATA_OID.len(), /*tagged=*/ true)?
+                + Self::get_signed_data_size(
+                    csr, sig, sid, /*tagged=*/ true, /*explicit=*/ true,
+                )?;
 
-        let cms_size = Self::get_structure_size(size, false)?;
+        let cms_size = DerWriter::structure_size(size, false)?;
 
-        let mut bytes_written = self.encode_byte(Self::SEQUENCE_TAG)?;
-        bytes_written += self.encode_size_field(cms_size)?;
-        bytes_written += self.encode_oid(Self::ID_SIGNED_DATA_OID)?;
+        let mut bytes_written = self.der.write_byte(DerWriter::SEQUENCE_TAG)?;
+        bytes_written += self.der.write_size(cms_size)?;
+        bytes_written += self.der.write_oid(Self::ID_SIGNED_DATA_OID)?;
 
         bytes_written += self.encode_signed_data(csr, sig, sid)?;
 
@@ -2042,14 +2541,122 @@ impl CertWriter<'_> {
     }
 }
 
+/// The parts of an ECDSA X.509 certificate produced by `CertWriter` that are
+/// needed to check it against the key it certifies.
+///
+/// Parsing checks that the structure is well-formed DER, that the certificate
+/// is v3, and that both signature algorithm fields name the profile's ECDSA
+/// algorithm. It does not verify the signature.
+pub struct EcdsaCertificate<'a> {
+    /// DER encoding of the TBSCertificate, which the signature covers.
+    pub tbs: &'a [u8],
+    pub serial_number: &'a [u8],
+    /// DER encoding of the issuer Name.
+    pub issuer: &'a [u8],
+    /// DER encoding of the subject Name.
+    pub subject: &'a [u8],
+    /// Uncompressed EC point of the subject key.
+    pub subject_public_key: &'a [u8],
+    /// Contents of the extensions field, if present.
+    pub extensions: Option<&'a [u8]>,
+    pub signature_r: &'a [u8],
+    pub signature_s: &'a [u8],
+}
+
+impl<'a> EcdsaCertificate<'a> {
+    /// Parses a DER encoded ECDSA certificate.
+    ///
+    /// Certificate  ::=  SEQUENCE  {
+    ///    tbsCertificate       TBSCertificate,
+    ///    signatureAlgorithm   AlgorithmIdentifier,
+    ///    signatureValue       BIT STRING  }
+    pub fn parse(cert: &'a [u8]) -> Result<EcdsaCertificate<'a>, DpeErrorCode> {
+        let mut reader = DerReader::new(cert);
+        let mut cert_seq = reader.read_sequence()?;
+        reader.finish()?;
+
+        let tbs = cert_seq.read_raw(DerWriter::SEQUENCE_TAG)?;
+        Self::parse_ecdsa_sig_alg_id(&mut cert_seq)?;
+        let mut sig = DerReader::new(cert_seq.read_bit_string()?);
+        cert_seq.finish()?;
+
+        // ECDSA-Sig-Value ::= SEQUENCE { r INTEGER, s INTEGER }
+        let mut sig_seq = sig.read_sequence()?;
+        sig.finish()?;
+        let signature_r = sig_seq.read_integer_bytes()?;
+        let signature_s = sig_seq.read_integer_bytes()?;
+        sig_seq.finish()?;
+
+        let mut tbs_reader = DerReader::new(tbs);
+        let mut tbs_seq = tbs_reader.read_sequence()?;
+
+        // version [0] EXPLICIT Version
+        let mut version = DerReader::new(tbs_seq.read(DerWriter::context_tag(0, true))?);
+        if version.read_integer_bytes()? != [CertWriter::X509_V3 as u8] {
+            return Err(DpeErrorCode::InvalidCertificate);
+        }
+        version.finish()?;
+
+        let serial_number = tbs_seq.read_integer_bytes()?;
+        Self::parse_ecdsa_sig_alg_id(&mut tbs_seq)?;
+        let issuer = tbs_seq.read_raw(DerWriter::SEQUENCE_TAG)?;
+        tbs_seq.read_sequence()?; // validity
+        let subject = tbs_seq.read_raw(DerWriter::SEQUENCE_TAG)?;
+        let subject_public_key = Self::parse_ecdsa_subject_pubkey_info(&mut tbs_seq)?;
+        let extensions = tbs_seq.read_optional(DerWriter::context_tag(3, true))?;
+        tbs_seq.finish()?;
+
+        Ok(EcdsaCertificate {
+            tbs,
+            serial_number,
+            issuer,
+            subject,
+            subject_public_key,
+            extensions,
+            signature_r,
+            signature_s,
+        })
+    }
+
+    fn parse_ecdsa_sig_alg_id(reader: &mut DerReader) -> Result<(), DpeErrorCode> {
+        let mut alg_id = reader.read_sequence()?;
+        if alg_id.read_oid()? != CertWriter::ECDSA_OID {
+            return Err(DpeErrorCode::InvalidCertificate);
+        }
+        alg_id.finish()
+    }
+
+    fn parse_ecdsa_subject_pubkey_info(
+        reader: &mut DerReader<'a>,
+    ) -> Result<&'a [u8], DpeErrorCode> {
+        let mut spki = reader.read_sequence()?;
+        let mut alg_id = spki.read_sequence()?;
+        if alg_id.read_oid()? != CertWriter::EC_PUB_OID
+            || alg_id.read_oid()? != CertWriter::CURVE_OID
+        {
+            return Err(DpeErrorCode::InvalidCertificate);
+        }
+        alg_id.finish()?;
+        let point = spki.read_bit_string()?;
+        spki.finish()?;
+        Ok(point)
+    }
+}
+
 #[cfg(test)]
 pub(crate) mod tests {
+    use crate::response::DpeErrorCode;
     use crate::tci::{TciMeasurement, TciNodeData};
-    use crate::x509::{CertWriter, DirectoryString, MeasurementData, Name};
+    use crate::x509::{
+        CertWriter, DirectoryString, EcdsaCertificate, MeasurementData, Name, SubjectPubKey,
+    };
     use crate::{DpeProfile, DPE_PROFILE};
     use crypto::{CryptoBuf, EcdsaPub, EcdsaSig};
     use openssl::hash::{Hasher, MessageDigest};
-    use platform::{ArrayVec, CertValidity, MAX_KEY_IDENTIFIER_SIZE};
+    use platform::{
+        ArrayVec, CertValidity, Fwid as PlatformFwid, PlatformError, MAX_FWID_DIGEST_SIZE,
+        MAX_KEY_IDENTIFIER_SIZE,
+    };
     use std::str;
     use x509_parser::certificate::X509CertificateParser;
     use x509_parser::nom::Parser;
@@ -2058,7 +2665,7 @@ pub(crate) mod tests {
 
     #[derive(asn1::Asn1Read)]
     pub struct Fwid<'a> {
-        pub(crate) _hash_alg: asn1::ObjectIdentifier,
+        pub(crate) hash_alg: asn1::ObjectIdentifier,
         pub(crate) digest: &'a [u8],
     }
 
@@ -2071,7 +2678,7 @@ pub(crate) mod tests {
         #[implicit(2)]
         _version: Option<asn1::Utf8String<'a>>,
         #[implicit(3)]
-        _svn: Option<u64>,
+        pub svn: Option<u64>,
         #[implicit(4)]
         _layer: Option<u64>,
         #[implicit(5)]
@@ -2087,13 +2694,20 @@ pub(crate) mod tests {
     }
 
     #[derive(asn1::Asn1Read)]
-    struct Ueid<'a> {
+    pub struct Ueid<'a> {
         pub(crate) ueid: &'a [u8],
     }
 
+    #[derive(asn1::Asn1Read)]
+    pub struct TcbFreshness<'a> {
+        pub(crate) nonce: &'a [u8],
+    }
+
     const TEST_ISSUER: Name = Name {
         cn: DirectoryString::PrintableString(b"Caliptra Alias"),
         serial: DirectoryString::PrintableString(&[0x00; DPE_PROFILE.get_hash_size() * 2]),
+        org: None,
+        org_unit: None,
     };
 
     fn encode_test_issuer() -> Vec<u8> {
@@ -2104,46 +2718,14 @@ pub(crate) mod tests {
         issuer_der
     }
 
-    #[test]
-    fn test_integers() {
-        let buffer_cases = [
-            [0; 8],
-            [0xFF, 0xFF, 0xFF, 0xFF, 0x00, 0x00, 0x00, 0x00],
-            [0x01, 0x02, 0x03, 0x04, 0x00, 0x00, 0x00, 0x00],
-            [0x00, 0x00, 0xFF, 0x04, 0x00, 0x00, 0x00, 0x00],
-            [0x01, 0x02, 0x03, 0x04, 0x00, 0x00, 0x00, 0x00],
-        ];
-
-        for c in buffer_cases {
-            let mut cert = [0u8; 128];
-            let mut w = CertWriter::new(&mut cert, true);
-            let byte_count = w.encode_integer_bytes(&c).unwrap();
-            let n = asn1::parse_single::<u64>(&cert[..byte_count]).unwrap();
-            assert_eq!(n, u64::from_be_bytes(c));
-            assert_eq!(
-                CertWriter::get_integer_bytes_size(&c, true).unwrap(),
-                byte_count
-            );
-        }
-
-        let integer_cases = [0xFFFFFFFF00000000, 0x0102030405060708, 0x2];
-
-        for c in integer_cases {
-            let mut cert = [0; 128];
-            let mut w = CertWriter::new(&mut cert, true);
-            let byte_count = w.encode_integer(c).unwrap();
-            let n = asn1::parse_single::<u64>(&cert[..byte_count]).unwrap();
-            assert_eq!(n, c);
-            assert_eq!(CertWriter::get_integer_size(c, true).unwrap(), byte_count);
-        }
-    }
-
     #[test]
     fn test_rdn() {
         let mut cert = [0u8; 256];
         let test_name = Name {
             cn: DirectoryString::PrintableString(b"Caliptra Alias"),
             serial: DirectoryString::PrintableString(&[0x0u8; DPE_PROFILE.get_hash_size() * 2]),
+            org: None,
+            org_unit: None,
         };
 
         let mut w = CertWriter::new(&mut cert, true);
@@ -2168,6 +2750,36 @@ pub(crate) mod tests {
         );
     }
 
+    #[test]
+    fn test_rdn_vendor() {
+        let mut cert = [0u8; 256];
+        let vendor_id = super::u32_hex_string(0x1234ABCD);
+        let vendor_sku = super::u32_hex_string(0x5);
+        assert_eq!(&vendor_id, b"1234ABCD");
+        assert_eq!(&vendor_sku, b"00000005");
+        let test_name = Name {
+            cn: DirectoryString::PrintableString(b"DPE Leaf"),
+            serial: DirectoryString::PrintableString(&[0x30; DPE_PROFILE.get_hash_size() * 2]),
+            org: Some(DirectoryString::PrintableString(&vendor_id)),
+            org_unit: Some(DirectoryString::PrintableString(&vendor_sku)),
+        };
+
+        let mut w = CertWriter::new(&mut cert, true);
+        let bytes_written = w.encode_rdn(&test_name).unwrap();
+        assert_eq!(
+            CertWriter::get_rdn_size(&test_name, true).unwrap(),
+            bytes_written
+        );
+
+        let (_, name) = X509Name::from_der(&cert[..bytes_written]).unwrap();
+        let expected = format!(
+            "CN=DPE Leaf, serialNumber={}, O=1234ABCD, OU=00000005",
+            str::from_utf8(test_name.serial.bytes()).unwrap()
+        );
+        let actual = name.to_string_with_registry(oid_registry()).unwrap();
+        assert_eq!(expected, actual);
+    }
+
     #[test]
     fn test_subject_pubkey() {
         let mut cert = [0u8; 256];
@@ -2184,6 +2796,113 @@ pub(crate) mod tests {
         );
     }
 
+    #[cfg(feature = "rsa")]
+    #[test]
+    fn test_rsa_subject_pubkey() {
+        use crypto::{RsaKeySize, RsaPub};
+
+        let mut cert = [0u8; 1024];
+        let mut modulus = [0x5Au8; RsaKeySize::Bit4096.modulus_size()];
+        modulus[0] = 0xC1;
+        let test_key = RsaPub::new(&modulus).unwrap();
+
+        let mut w = CertWriter::new(&mut cert, true);
+        let bytes_written = w
+            .encode_subject_pubkey_info(SubjectPubKey::Rsa(&test_key))
+            .unwrap();
+        assert_eq!(
+            CertWriter::get_subject_pubkey_info_size(SubjectPubKey::Rsa(&test_key), true).unwrap(),
+            bytes_written
+        );
+
+        let (_, spki) = SubjectPublicKeyInfo::from_der(&cert[..bytes_written]).unwrap();
+        assert_eq!(spki.algorithm.algorithm, oid!(1.2.840 .113549 .1 .1 .1));
+        let Ok(public_key::PublicKey::RSA(rsa)) = spki.parsed() else {
+            panic!("Not an RSA key");
+        };
+        // The modulus has its top bit set, so it is prefixed with a zero.
+        assert_eq!(rsa.modulus[0], 0);
+        assert_eq!(&rsa.modulus[1..], &modulus);
+        assert_eq!(rsa.exponent, RsaPub::EXPONENT.to_be_bytes()[1..]);
+    }
+
+    #[cfg(feature = "dpe_profile_ed25519_sha512")]
+    #[test]
+    fn test_ed25519_subject_pubkey() {
+        use crypto::Ed25519Pub;
+
+        let mut cert = [0u8; 64];
+        let test_key = Ed25519Pub([0x5A; Ed25519Pub::SIZE]);
+
+        let mut w = CertWriter::new(&mut cert, true);
+        let bytes_written = w
+            .encode_subject_pubkey_info(SubjectPubKey::Ed25519(&test_key))
+            .unwrap();
+        assert_eq!(
+            CertWriter::get_subject_pubkey_info_size(SubjectPubKey::Ed25519(&test_key), true)
+                .unwrap(),
+            bytes_written
+        );
+
+        let (_, spki) = SubjectPublicKeyInfo::from_der(&cert[..bytes_written]).unwrap();
+        assert_eq!(spki.algorithm.algorithm, oid!(1.3.101 .112));
+        // RFC 8410: the parameters are absent.
+        assert!(spki.algorithm.parameters.is_none());
+        assert_eq!(spki.subject_public_key.data, &test_key.0[..]);
+    }
+
+    #[cfg(feature = "rsa")]
+    #[test]
+    fn test_rsa_sig_alg_id() {
+        use crypto::RsaPadding;
+        use x509_parser::signature_algorithm::SignatureAlgorithm;
+
+        let expected_hash_oid = || match DPE_PROFILE {
+            DpeProfile::P256Sha256 => oid!(2.16.840 .1 .101 .3 .4 .2 .1),
+            DpeProfile::P384Sha384 | DpeProfile::Mldsa87Sha384 => {
+                oid!(2.16.840 .1 .101 .3 .4 .2 .2)
+            }
+            DpeProfile::P521Sha512 | DpeProfile::Ed25519Sha512 => {
+                oid!(2.16.840 .1 .101 .3 .4 .2 .3)
+            }
+        };
+        for (padding, expected_oid) in [
+            (
+                RsaPadding::Pkcs1v15,
+                match DPE_PROFILE {
+                    DpeProfile::P256Sha256 => oid!(1.2.840 .113549 .1 .1 .11),
+                    DpeProfile::P384Sha384 | DpeProfile::Mldsa87Sha384 => {
+                        oid!(1.2.840 .113549 .1 .1 .12)
+                    }
+                    DpeProfile::P521Sha512 | DpeProfile::Ed25519Sha512 => {
+                        oid!(1.2.840 .113549 .1 .1 .13)
+                    }
+                },
+            ),
+            (RsaPadding::Pss, oid!(1.2.840 .113549 .1 .1 .10)),
+        ] {
+            let mut buf = [0u8; 128];
+            let mut w = CertWriter::new(&mut buf, true);
+            let bytes_written = w.encode_rsa_sig_alg_id(padding).unwrap();
+            assert_eq!(
+                CertWriter::get_rsa_sig_alg_id_size(padding, true).unwrap(),
+                bytes_written
+            );
+
+            let (rest, alg_id) = AlgorithmIdentifier::from_der(&buf[..bytes_written]).unwrap();
+            assert!(rest.is_empty());
+            assert_eq!(alg_id.algorithm, expected_oid);
+            match (padding, SignatureAlgorithm::try_from(&alg_id).unwrap()) {
+                (RsaPadding::Pkcs1v15, SignatureAlgorithm::RSA) => (),
+                (RsaPadding::Pss, SignatureAlgorithm::RSASSA_PSS(params)) => {
+                    assert_eq!(params.hash_algorithm_oid(), &expected_hash_oid());
+                    assert_eq!(params.salt_length(), DPE_PROFILE.get_hash_size() as u32);
+                }
+                _ => panic!("Wrong signature algorithm for {padding:?}"),
+            }
+        }
+    }
+
     #[test]
     fn test_tcb_info() {
         let mut node = TciNodeData::new();
@@ -2196,13 +2915,15 @@ pub(crate) mod tests {
         let mut cert = [0u8; 256];
         let mut w = CertWriter::new(&mut cert, true);
         let mut supports_recursive = true;
-        let mut bytes_written = w.encode_tcb_info(&node, supports_recursive).unwrap();
+        let mut bytes_written = w
+            .encode_tcb_info(&node, supports_recursive, None, &[])
+            .unwrap();
 
         let mut parsed_tcb_info = asn1::parse_single::<TcbInfo>(&cert[..bytes_written]).unwrap();
 
         assert_eq!(
             bytes_written,
-            CertWriter::get_tcb_info_size(&node, supports_recursive, true).unwrap()
+            CertWriter::get_tcb_info_size(&node, supports_recursive, None, &[], true).unwrap()
         );
 
         // FWIDs
@@ -2220,17 +2941,20 @@ pub(crate) mod tests {
             parsed_tcb_info.vendor_info.unwrap(),
             node.locality.to_be_bytes()
         );
+        assert!(parsed_tcb_info.svn.is_none());
 
         // test tbs_info with supports_recursive = false
         supports_recursive = false;
         w = CertWriter::new(&mut cert, true);
-        bytes_written = w.encode_tcb_info(&node, supports_recursive).unwrap();
+        bytes_written = w
+            .encode_tcb_info(&node, supports_recursive, None, &[])
+            .unwrap();
 
         parsed_tcb_info = asn1::parse_single::<TcbInfo>(&cert[..bytes_written]).unwrap();
 
         assert_eq!(
             bytes_written,
-            CertWriter::get_tcb_info_size(&node, supports_recursive, true).unwrap()
+            CertWriter::get_tcb_info_size(&node, supports_recursive, None, &[], true).unwrap()
         );
 
         // Check that only FWID[0] is present
@@ -2238,6 +2962,189 @@ pub(crate) mod tests {
         let expected_current = fwid_itr.next().unwrap().digest;
         assert!(fwid_itr.next().is_none());
         assert_eq!(expected_current, node.tci_current.0);
+
+        // A label follows the locality
+        let label = [0x4C; 16];
+        w = CertWriter::new(&mut cert, true);
+        bytes_written = w
+            .encode_tcb_info(&node, supports_recursive, Some(&label), &[])
+            .unwrap();
+
+        parsed_tcb_info = asn1::parse_single::<TcbInfo>(&cert[..bytes_written]).unwrap();
+
+        assert_eq!(
+            bytes_written,
+            CertWriter::get_tcb_info_size(&node, supports_recursive, Some(&label), &[], true)
+                .unwrap()
+        );
+        let vendor_info = parsed_tcb_info.vendor_info.unwrap();
+        assert_eq!(vendor_info[..4], node.locality.to_be_bytes());
+        assert_eq!(vendor_info[4..], label);
+    }
+
+    #[test]
+    fn test_tcb_info_svn() {
+        let mut node = TciNodeData::new();
+        node.has_svn = true.into();
+
+        for svn in [0, 0x7F, 0x80, 0x1234, u32::MAX] {
+            node.svn = svn;
+            let mut cert = [0u8; 256];
+            let mut w = CertWriter::new(&mut cert, true);
+            let bytes_written = w.encode_tcb_info(&node, true, None, &[]).unwrap();
+            assert_eq!(
+                bytes_written,
+                CertWriter::get_tcb_info_size(&node, true, None, &[], true).unwrap()
+            );
+
+            let parsed_tcb_info = asn1::parse_single::<TcbInfo>(&cert[..bytes_written]).unwrap();
+            assert_eq!(parsed_tcb_info.svn, Some(u64::from(svn)));
+            assert_eq!(parsed_tcb_info.fwids.unwrap().count(), 2);
+        }
+    }
+
+    #[test]
+    fn test_tcb_info_additional_fwids() {
+        let mut node = TciNodeData::new();
+        node.tci_cumulative = TciMeasurement([0xaau8; DPE_PROFILE.get_hash_size()]);
+        node.tci_current = TciMeasurement([0xbbu8; DPE_PROFILE.get_hash_size()]);
+
+        // The platform FWIDs keep their own algorithm whatever the profile.
+        let fwids = [
+            PlatformFwid {
+                hash_alg: PlatformFwid::SHA256,
+                digest: [0x11; MAX_FWID_DIGEST_SIZE],
+            },
+            PlatformFwid {
+                hash_alg: PlatformFwid::SHA512,
+                digest: [0x22; MAX_FWID_DIGEST_SIZE],
+            },
+        ];
+
+        let mut cert = [0u8; 512];
+        let mut w = CertWriter::new(&mut cert, true);
+        let bytes_written = w.encode_tcb_info(&node, true, None, &fwids).unwrap();
+        assert_eq!(
+            bytes_written,
+            CertWriter::get_tcb_info_size(&node, true, None, &fwids, true).unwrap()
+        );
+
+        let parsed_tcb_info = asn1::parse_single::<TcbInfo>(&cert[..bytes_written]).unwrap();
+        let parsed: Vec<Fwid> = parsed_tcb_info.fwids.unwrap().collect();
+        assert_eq!(parsed.len(), 4);
+
+        let profile_oid = match DPE_PROFILE {
+            DpeProfile::P256Sha256 => asn1::oid!(2, 16, 840, 1, 101, 3, 4, 2, 1),
+            DpeProfile::P384Sha384 | DpeProfile::Mldsa87Sha384 => {
+                asn1::oid!(2, 16, 840, 1, 101, 3, 4, 2, 2)
+            }
+            DpeProfile::P521Sha512 | DpeProfile::Ed25519Sha512 => {
+                asn1::oid!(2, 16, 840, 1, 101, 3, 4, 2, 3)
+            }
+        };
+        assert_eq!(parsed[0].hash_alg, profile_oid);
+        assert_eq!(parsed[0].digest, node.tci_current.0);
+        assert_eq!(parsed[1].hash_alg, profile_oid);
+        assert_eq!(parsed[1].digest, node.tci_cumulative.0);
+        assert_eq!(
+            parsed[2].hash_alg,
+            asn1::oid!(2, 16, 840, 1, 101, 3, 4, 2, 1)
+        );
+        assert_eq!(parsed[2].digest, [0x11; 32]);
+        assert_eq!(
+            parsed[3].hash_alg,
+            asn1::oid!(2, 16, 840, 1, 101, 3, 4, 2, 3)
+        );
+        assert_eq!(parsed[3].digest, [0x22; 64]);
+
+        // FWIDs with an unknown algorithm are rejected
+        let bad = [PlatformFwid {
+            hash_alg: 7,
+            ..fwids[0]
+        }];
+        let mut w = CertWriter::new(&mut cert, true);
+        assert_eq!(
+            w.encode_tcb_info(&node, true, None, &bad),
+            Err(DpeErrorCode::Platform(PlatformError::AdditionalFwidError(
+                7
+            )))
+        );
+    }
+
+    #[test]
+    fn test_multi_tcb_info_additional_fwids() {
+        let nodes = [TciNodeData::new(), TciNodeData::new()];
+        let fwids = [PlatformFwid {
+            hash_alg: PlatformFwid::SHA384,
+            digest: [0x33; MAX_FWID_DIGEST_SIZE],
+        }];
+        let measurements = MeasurementData {
+            ueid: &[],
+            tci_nodes: &nodes,
+            is_ca: false,
+            supports_recursive: false,
+            subject_key_identifier: [0u8; MAX_KEY_IDENTIFIER_SIZE],
+            authority_key_identifier: [0u8; MAX_KEY_IDENTIFIER_SIZE],
+            command_counter: None,
+            label: None,
+            additional_fwids: &fwids,
+        };
+
+        let mut cert = [0u8; 512];
+        let mut w = CertWriter::new(&mut cert, true);
+        let bytes_written = w.encode_multi_tcb_info(&measurements).unwrap();
+        assert_eq!(
+            bytes_written,
+            CertWriter::get_multi_tcb_info_size(&measurements, /*tagged=*/ true).unwrap()
+        );
+
+        let mut parser = X509ExtensionParser::new().with_deep_parse_extensions(false);
+        let ext = parser.parse(&cert[..bytes_written]).unwrap().1;
+        let tcb_infos: Vec<TcbInfo> = asn1::parse_single::<asn1::SequenceOf<TcbInfo>>(ext.value)
+            .unwrap()
+            .collect();
+
+        // Only the certified context carries the platform FWIDs
+        assert_eq!(tcb_infos[0].fwids.clone().unwrap().count(), 1);
+        let fwids: Vec<Fwid> = tcb_infos[1].fwids.clone().unwrap().collect();
+        assert_eq!(fwids.len(), 2);
+        assert_eq!(
+            fwids[1].hash_alg,
+            asn1::oid!(2, 16, 840, 1, 101, 3, 4, 2, 2)
+        );
+        assert_eq!(fwids[1].digest, [0x33; 48]);
+    }
+
+    #[test]
+    fn test_basic_constraints() {
+        for is_ca in [false, true] {
+            let measurements = MeasurementData {
+                ueid: &[],
+                tci_nodes: &[],
+                is_ca,
+                supports_recursive: false,
+                subject_key_identifier: [0u8; MAX_KEY_IDENTIFIER_SIZE],
+                authority_key_identifier: [0u8; MAX_KEY_IDENTIFIER_SIZE],
+                command_counter: None,
+                label: None,
+                additional_fwids: &[],
+            };
+            let mut cert = [0u8; 32];
+            let mut w = CertWriter::new(&mut cert, true);
+            let bytes_written = w.encode_basic_constraints(&measurements).unwrap();
+            assert_eq!(
+                bytes_written,
+                CertWriter::get_basic_constraints_size(&measurements, /*tagged=*/ true).unwrap()
+            );
+
+            // DER leaves cA out of leaf certificates since it defaults to FALSE.
+            let expected: &[u8] = if is_ca {
+                &[0x04, 0x05, 0x30, 0x03, 0x01, 0x01, 0xFF]
+            } else {
+                &[0x04, 0x02, 0x30, 0x00]
+            };
+            assert!(cert[..bytes_written].ends_with(expected));
+        }
     }
 
     fn get_key_usage(is_ca: bool) -> KeyUsage {
@@ -2278,6 +3185,8 @@ pub(crate) mod tests {
         let test_subject_name = Name {
             cn: DirectoryString::PrintableString(b"DPE Leaf"),
             serial: DirectoryString::PrintableString(&[0x00; DPE_PROFILE.get_hash_size() * 2]),
+            org: None,
+            org_unit: None,
         };
 
         const ECC_INT_SIZE: usize = DPE_PROFILE.get_ecc_int_size();
@@ -2289,12 +3198,15 @@ pub(crate) mod tests {
         let node = TciNodeData::new();
 
         let measurements = MeasurementData {
-            label: &[0xCC; DPE_PROFILE.get_hash_size()],
+            ueid: &[0xCC; 17],
             tci_nodes: &[node],
             is_ca: false,
             supports_recursive: true,
             subject_key_identifier: [0u8; MAX_KEY_IDENTIFIER_SIZE],
             authority_key_identifier: [0u8; MAX_KEY_IDENTIFIER_SIZE],
+            command_counter: Some(0x01020304),
+            label: Some(&[0x4C; 16]),
+            additional_fwids: &[],
         };
 
         let mut not_before = ArrayVec::new();
@@ -2315,7 +3227,7 @@ pub(crate) mod tests {
                 &test_serial,
                 &issuer_der,
                 &test_subject_name,
-                &test_pub,
+                SubjectPubKey::Ecdsa(&test_pub),
                 &measurements,
                 &validity,
             )
@@ -2337,22 +3249,42 @@ pub(crate) mod tests {
             .unwrap();
         assert!(ueid.critical);
         let parsed_ueid = asn1::parse_single::<Ueid>(ueid.value).unwrap();
-        assert_eq!(parsed_ueid.ueid, measurements.label);
+        assert_eq!(parsed_ueid.ueid, measurements.ueid);
+
+        let tcb_freshness = cert
+            .get_extension_unique(&oid!(2.23.133 .5 .4 .11))
+            .unwrap()
+            .unwrap();
+        assert!(tcb_freshness.critical);
+        let parsed_tcb_freshness = asn1::parse_single::<TcbFreshness>(tcb_freshness.value).unwrap();
+        assert_eq!(parsed_tcb_freshness.nonce, &[0x01, 0x02, 0x03, 0x04]);
     }
 
     const TEST_SERIAL: &[u8] = &[0x1F; 20];
     const TEST_ISSUER_NAME: Name = Name {
         cn: DirectoryString::PrintableString(b"Caliptra Alias"),
         serial: DirectoryString::PrintableString(&[0x00; DPE_PROFILE.get_hash_size() * 2]),
+        org: None,
+        org_unit: None,
     };
     const TEST_SUBJECT_NAME: Name = Name {
         cn: DirectoryString::PrintableString(b"DPE Leaf"),
         serial: DirectoryString::PrintableString(&[0x00; DPE_PROFILE.get_hash_size() * 2]),
+        org: None,
+        org_unit: None,
     };
 
     const ECC_INT_SIZE: usize = DPE_PROFILE.get_ecc_int_size();
 
     fn build_test_tbs<'a>(is_ca: bool, cert_buf: &'a mut [u8]) -> (usize, TbsCertificate<'a>) {
+        build_test_tbs_with_nodes(is_ca, &[TciNodeData::new()], cert_buf)
+    }
+
+    fn build_test_tbs_with_nodes<'a>(
+        is_ca: bool,
+        tci_nodes: &[TciNodeData],
+        cert_buf: &'a mut [u8],
+    ) -> (usize, TbsCertificate<'a>) {
         let mut issuer_der = [0u8; 1024];
         let mut issuer_writer = CertWriter::new(&mut issuer_der, true);
         let issuer_len = issuer_writer.encode_rdn(&TEST_ISSUER_NAME).unwrap();
@@ -2362,11 +3294,14 @@ pub(crate) mod tests {
             y: CryptoBuf::new(&[0xBB; ECC_INT_SIZE]).unwrap(),
         };
 
-        let node = TciNodeData::new();
-
         let mut hasher = match DPE_PROFILE {
             DpeProfile::P256Sha256 => Hasher::new(MessageDigest::sha256()).unwrap(),
-            DpeProfile::P384Sha384 => Hasher::new(MessageDigest::sha384()).unwrap(),
+            DpeProfile::P384Sha384 | DpeProfile::Mldsa87Sha384 => {
+                Hasher::new(MessageDigest::sha384()).unwrap()
+            }
+            DpeProfile::P521Sha512 | DpeProfile::Ed25519Sha512 => {
+                Hasher::new(MessageDigest::sha512()).unwrap()
+            }
         };
         hasher.update(&[0x04]).unwrap();
         hasher.update(test_pub.x.bytes()).unwrap();
@@ -2375,12 +3310,15 @@ pub(crate) mod tests {
         let digest = &hasher.finish().unwrap();
         subject_key_identifier.copy_from_slice(&digest[..MAX_KEY_IDENTIFIER_SIZE]);
         let measurements = MeasurementData {
-            label: &[0; DPE_PROFILE.get_hash_size()],
-            tci_nodes: &[node],
+            ueid: &[0; 17],
+            tci_nodes,
             is_ca,
             supports_recursive: true,
             subject_key_identifier,
             authority_key_identifier: subject_key_identifier,
+            command_counter: None,
+            label: None,
+            additional_fwids: &[],
         };
 
         let mut not_before = ArrayVec::new();
@@ -2402,7 +3340,7 @@ pub(crate) mod tests {
                 &TEST_SERIAL,
                 &issuer_der[..issuer_len],
                 &TEST_SUBJECT_NAME,
-                &test_pub,
+                SubjectPubKey::Ecdsa(&test_pub),
                 &measurements,
                 &validity,
             )
@@ -2490,6 +3428,76 @@ pub(crate) mod tests {
         }
     }
 
+    #[test]
+    fn test_parse_ecdsa_certificate() {
+        let mut cert_buf = [0u8; 1024];
+        let (len, expected) = build_test_cert(false, &mut cert_buf);
+        let cert_bytes = &cert_buf[..len];
+
+        let cert = EcdsaCertificate::parse(cert_bytes).unwrap();
+        assert_eq!(cert.tbs, expected.tbs_certificate.as_ref());
+        assert_eq!(cert.serial_number, TEST_SERIAL);
+        assert_eq!(cert.issuer, expected.issuer().as_raw());
+        assert_eq!(cert.subject, expected.subject().as_raw());
+        assert_eq!(
+            cert.subject_public_key,
+            expected.public_key().subject_public_key.data.as_ref()
+        );
+        assert!(cert.extensions.is_some());
+        assert_eq!(cert.signature_r, [0xCC; ECC_INT_SIZE]);
+        assert_eq!(cert.signature_s, [0xDD; ECC_INT_SIZE]);
+
+        // Any truncation or trailing data is rejected.
+        for cut in [1, len / 2, len - 1] {
+            assert_eq!(
+                EcdsaCertificate::parse(&cert_bytes[..cut]).err(),
+                Some(DpeErrorCode::InvalidCertificate)
+            );
+        }
+        let mut trailing = cert_bytes.to_vec();
+        trailing.push(0);
+        assert_eq!(
+            EcdsaCertificate::parse(&trailing).err(),
+            Some(DpeErrorCode::InvalidCertificate)
+        );
+    }
+
+    #[test]
+    fn test_cert_larger_than_64k() {
+        // Enough TcbInfos that the MultiTcbInfo extension, the extensions,
+        // the TBS and the certificate all need a 3-byte long-form length.
+        let nodes = vec![TciNodeData::new(); 1024];
+        let mut tbs_buf = vec![0u8; 256 * 1024];
+        let (tbs_written, tbs) = build_test_tbs_with_nodes(false, &nodes, &mut tbs_buf);
+        assert!(tbs_written > 65535);
+
+        let multi_tcb_info = tbs
+            .get_extension_unique(&oid!(2.23.133 .5 .4 .5))
+            .unwrap()
+            .unwrap();
+        assert!(multi_tcb_info.value.len() > 65535);
+        let parsed = asn1::parse_single::<asn1::SequenceOf<TcbInfo>>(multi_tcb_info.value)
+            .unwrap()
+            .count();
+        assert_eq!(parsed, nodes.len());
+
+        let test_sig = EcdsaSig {
+            r: CryptoBuf::new(&[0xCC; ECC_INT_SIZE]).unwrap(),
+            s: CryptoBuf::new(&[0xDD; ECC_INT_SIZE]).unwrap(),
+        };
+        let mut cert_buf = vec![0u8; 256 * 1024];
+        let mut w = CertWriter::new(&mut cert_buf, true);
+        let bytes_written = w
+            .encode_ecdsa_certificate(&tbs_buf[..tbs_written], &test_sig)
+            .unwrap();
+        assert!(bytes_written > 65535);
+
+        let mut parser = X509CertificateParser::new().with_deep_parse_extensions(true);
+        let (rem, cert) = parser.parse(&cert_buf[..bytes_written]).unwrap();
+        assert!(rem.is_empty());
+        assert_eq!(cert.tbs_certificate.as_ref(), &tbs_buf[..tbs_written]);
+    }
+
     #[test]
     fn test_full_ca() {
         let mut cert_buf = [0u8; 1024];
@@ -2528,7 +3536,12 @@ pub(crate) mod tests {
         let pub_key = &cert.tbs_certificate.subject_pki.subject_public_key.data;
         let mut hasher = match DPE_PROFILE {
             DpeProfile::P256Sha256 => Hasher::new(MessageDigest::sha256()).unwrap(),
-            DpeProfile::P384Sha384 => Hasher::new(MessageDigest::sha384()).unwrap(),
+            DpeProfile::P384Sha384 | DpeProfile::Mldsa87Sha384 => {
+                Hasher::new(MessageDigest::sha384()).unwrap()
+            }
+            DpeProfile::P521Sha512 | DpeProfile::Ed25519Sha512 => {
+                Hasher::new(MessageDigest::sha512()).unwrap()
+            }
         };
         hasher.update(pub_key).unwrap();
         let expected_key_identifier: &[u8] = &hasher.finish().unwrap();
@@ -2556,10 +3569,9 @@ pub(crate) mod tests {
                 assert!(!extension.critical);
                 if let ParsedExtension::AuthorityKeyIdentifier(aki) = extension.parsed_extension() {
                     let key_identifier = aki.key_identifier.clone().unwrap();
-                    // skip first two bytes - first byte is 0x04 der encoding byte and second byte is size byte
                     // cert is self signed so authority_key_id == subject_key_id
                     assert_eq!(
-                        &key_identifier.0[2..],
+                        key_identifier.0,
                         &expected_key_identifier[..MAX_KEY_IDENTIFIER_SIZE]
                     );
                     assert!(aki.authority_cert_issuer.is_none());
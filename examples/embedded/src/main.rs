@@ -0,0 +1,296 @@
+/*++
+Licensed under the Apache-2.0 license.
+
+Abstract:
+    Bare-metal firmware skeleton that runs the DPE on thumbv7em-none-eabihf.
+
+    The crypto and platform implementations are stubs that fail every call,
+    and the mailbox never delivers a command, so the binary does nothing
+    useful. It exists to prove that the dpe, crypto and platform crates build
+    without std or an allocator: any use of either in them, or in their
+    dependencies, breaks this build. CI also bounds the stack a command
+    takes on this target with cargo-call-stack.
+--*/
+#![no_std]
+#![no_main]
+
+use crypto::{
+    AeadKey, AlgLen, Crypto, CryptoError, Digest, EcdsaPub, EcdsaSig, Hasher, HmacSig,
+    AEAD_IV_SIZE, AEAD_TAG_SIZE,
+};
+#[cfg(feature = "no-large-stack")]
+use dpe::arena::ARENA_SIZE;
+use dpe::{
+    dpe_instance::{DpeEnv, DpeTypes},
+    response::{Response, ResponseHdr},
+    support::Support,
+    DpeInstance,
+};
+use platform::{
+    CertValidity, Platform, PlatformError, SignerIdentifier, MAX_CHUNK_SIZE, MAX_ISSUER_NAME_SIZE,
+    MAX_KEY_IDENTIFIER_SIZE, MAX_UEID_SIZE,
+};
+
+/// Largest command the mailbox holds.
+const MAX_COMMAND_SIZE: usize = 4096;
+
+struct StubHasher;
+
+impl Hasher for StubHasher {
+    fn update(&mut self, _bytes: &[u8]) -> Result<(), CryptoError> {
+        Err(CryptoError::NotImplemented)
+    }
+
+    fn finish(self) -> Result<Digest, CryptoError> {
+        Err(CryptoError::NotImplemented)
+    }
+}
+
+/// Stands in for the driver of the SoC's crypto engine.
+struct StubCrypto;
+
+impl Crypto for StubCrypto {
+    type Cdi = ();
+    type Hasher<'c> = StubHasher;
+    type PrivKey = ();
+
+    fn rand_bytes(&mut self, _dst: &mut [u8]) -> Result<(), CryptoError> {
+        Err(CryptoError::NotImplemented)
+    }
+
+    fn hash_initialize(&mut self, _algs: AlgLen) -> Result<Self::Hasher<'_>, CryptoError> {
+        Err(CryptoError::NotImplemented)
+    }
+
+    fn derive_cdi(
+        &mut self,
+        _algs: AlgLen,
+        _measurement: &Digest,
+        _info: &[u8],
+    ) -> Result<Self::Cdi, CryptoError> {
+        Err(CryptoError::NotImplemented)
+    }
+
+    fn derive_key_pair(
+        &mut self,
+        _algs: AlgLen,
+        _cdi: &Self::Cdi,
+        _label: &[u8],
+        _info: &[u8],
+    ) -> Result<(Self::PrivKey, EcdsaPub), CryptoError> {
+        Err(CryptoError::NotImplemented)
+    }
+
+    fn ecdsa_sign_with_alias(
+        &mut self,
+        _algs: AlgLen,
+        _digest: &Digest,
+    ) -> Result<EcdsaSig, CryptoError> {
+        Err(CryptoError::NotImplemented)
+    }
+
+    fn ecdsa_sign_with_derived(
+        &mut self,
+        _algs: AlgLen,
+        _digest: &Digest,
+        _priv_key: &Self::PrivKey,
+        _pub_key: &EcdsaPub,
+    ) -> Result<EcdsaSig, CryptoError> {
+        Err(CryptoError::NotImplemented)
+    }
+
+    fn hmac_sign_with_derived(
+        &mut self,
+        _algs: AlgLen,
+        _cdi: &Self::Cdi,
+        _label: &[u8],
+        _info: &[u8],
+        _digest: &Digest,
+    ) -> Result<HmacSig, CryptoError> {
+        Err(CryptoError::NotImplemented)
+    }
+
+    fn ecdh_derive_key(
+        &mut self,
+        _algs: AlgLen,
+        _peer_pub: &EcdsaPub,
+        _info: &[u8],
+    ) -> Result<(AeadKey, EcdsaPub), CryptoError> {
+        Err(CryptoError::NotImplemented)
+    }
+
+    fn aead_encrypt(
+        &mut self,
+        _key: &AeadKey,
+        _iv: &[u8; AEAD_IV_SIZE],
+        _aad: &[u8],
+        _data: &mut [u8],
+    ) -> Result<[u8; AEAD_TAG_SIZE], CryptoError> {
+        Err(CryptoError::NotImplemented)
+    }
+
+    fn aead_decrypt(
+        &mut self,
+        _key: &AeadKey,
+        _iv: &[u8; AEAD_IV_SIZE],
+        _aad: &[u8],
+        _data: &mut [u8],
+        _tag: &[u8; AEAD_TAG_SIZE],
+    ) -> Result<(), CryptoError> {
+        Err(CryptoError::NotImplemented)
+    }
+
+    fn derive_aead_key(
+        &mut self,
+        _algs: AlgLen,
+        _cdi: &Self::Cdi,
+        _label: &[u8],
+        _info: &[u8],
+    ) -> Result<AeadKey, CryptoError> {
+        Err(CryptoError::NotImplemented)
+    }
+
+    fn wrap_cdi(
+        &mut self,
+        _cdi: &Self::Cdi,
+        _key: &AeadKey,
+        _aad: &[u8],
+        _wrapped: &mut [u8],
+    ) -> Result<([u8; AEAD_IV_SIZE], [u8; AEAD_TAG_SIZE]), CryptoError> {
+        Err(CryptoError::NotImplemented)
+    }
+}
+
+/// Stands in for the ROM's certificate store and identity fuses.
+struct StubPlatform;
+
+impl Platform for StubPlatform {
+    fn get_certificate_chain(
+        &mut self,
+        _offset: u32,
+        _size: u32,
+        _out: &mut [u8; MAX_CHUNK_SIZE],
+    ) -> Result<u32, PlatformError> {
+        Err(PlatformError::NotImplemented)
+    }
+
+    fn get_issuer_name(
+        &mut self,
+        _out: &mut [u8; MAX_ISSUER_NAME_SIZE],
+    ) -> Result<usize, PlatformError> {
+        Err(PlatformError::NotImplemented)
+    }
+
+    fn get_signer_identifier(&mut self) -> Result<SignerIdentifier, PlatformError> {
+        Err(PlatformError::NotImplemented)
+    }
+
+    fn get_issuer_key_identifier(
+        &mut self,
+        _out: &mut [u8; MAX_KEY_IDENTIFIER_SIZE],
+    ) -> Result<(), PlatformError> {
+        Err(PlatformError::NotImplemented)
+    }
+
+    fn get_ueid(&mut self, _out: &mut [u8; MAX_UEID_SIZE]) -> Result<usize, PlatformError> {
+        Err(PlatformError::NotImplemented)
+    }
+
+    fn get_vendor_id(&mut self) -> Result<u32, PlatformError> {
+        Ok(0)
+    }
+
+    fn get_vendor_sku(&mut self) -> Result<u32, PlatformError> {
+        Ok(0)
+    }
+
+    fn get_auto_init_locality(&mut self) -> Result<u32, PlatformError> {
+        Ok(0)
+    }
+
+    fn write_str(&mut self, _str: &str) -> Result<(), PlatformError> {
+        Ok(())
+    }
+
+    fn get_cert_validity(&mut self) -> Result<CertValidity, PlatformError> {
+        Err(PlatformError::NotImplemented)
+    }
+}
+
+struct EmbeddedTypes;
+
+impl DpeTypes for EmbeddedTypes {
+    type Crypto<'a> = StubCrypto;
+    type Platform<'a> = StubPlatform;
+}
+
+/// Copies the next command from the mailbox into `cmd`, returning the
+/// sender's locality and the command's length.
+fn mailbox_receive(_cmd: &mut [u8; MAX_COMMAND_SIZE]) -> Option<(u32, usize)> {
+    None
+}
+
+/// Sends a response back through the mailbox.
+fn mailbox_send(_resp: &[u8]) {}
+
+/// Runs one command and sends its response.
+///
+/// CI bounds the stack this uses with cargo-call-stack, so it keeps its
+/// symbol name and is never inlined into `_start`.
+#[no_mangle]
+#[inline(never)]
+fn dpe_handle_command(
+    dpe: &mut DpeInstance,
+    env: &mut DpeEnv<EmbeddedTypes>,
+    locality: u32,
+    cmd: &[u8],
+) {
+    let resp = dpe
+        .execute_serialized_command(env, locality, cmd)
+        .unwrap_or_else(|e| Response::Error(ResponseHdr::new(e)));
+    mailbox_send(resp.as_bytes());
+}
+
+#[no_mangle]
+pub extern "C" fn _start() -> ! {
+    // With `no-large-stack`, the scratch buffers of commands are taken from
+    // static memory instead of the stack.
+    #[cfg(feature = "no-large-stack")]
+    let arena = {
+        static mut ARENA: [u8; ARENA_SIZE] = [0; ARENA_SIZE];
+        // SAFETY: `_start` runs once and never returns, so this is the only
+        // reference to the arena.
+        unsafe { &mut *core::ptr::addr_of_mut!(ARENA) }
+    };
+    #[cfg(not(feature = "no-large-stack"))]
+    let arena: &mut [u8] = &mut [];
+
+    let mut env = DpeEnv::<EmbeddedTypes> {
+        crypto: StubCrypto,
+        platform: StubPlatform,
+        arena,
+    };
+    let Ok(mut dpe) = DpeInstance::new(&mut env, Support::empty()) else {
+        halt();
+    };
+
+    let mut cmd = [0u8; MAX_COMMAND_SIZE];
+    loop {
+        let Some((locality, len)) = mailbox_receive(&mut cmd) else {
+            core::hint::spin_loop();
+            continue;
+        };
+        dpe_handle_command(&mut dpe, &mut env, locality, &cmd[..len]);
+    }
+}
+
+fn halt() -> ! {
+    loop {
+        core::hint::spin_loop();
+    }
+}
+
+#[panic_handler]
+fn panic(_info: &core::panic::PanicInfo) -> ! {
+    halt()
+}
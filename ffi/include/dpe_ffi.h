@@ -0,0 +1,311 @@
+/* Licensed under the Apache-2.0 license */
+
+#ifndef DPE_FFI_H
+#define DPE_FFI_H
+
+/* Generated by cbindgen from ffi/src. Do not edit by hand. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+// Size of the scratch space a running hash is kept in.
+#define DPE_HASH_CONTEXT_SIZE 256
+
+// Status returned when a call succeeds.
+#define DPE_STATUS_OK 0
+
+// Status returned when no instance has been created.
+#define DPE_STATUS_INTERNAL_ERROR 1
+
+// Status returned for NULL pointers.
+#define DPE_STATUS_INVALID_ARGUMENT 3
+
+// Status returned when the response doesn't fit in the caller's buffer.
+#define DPE_STATUS_OUTPUT_BUFFER_TOO_SMALL 4111
+
+// Flag letting a locality own the auto-initialized default context.
+#define DPE_LOCALITY_AUTO_INIT (1 << 0)
+
+// Flag letting a locality create simulation contexts.
+#define DPE_LOCALITY_SIMULATION (1 << 1)
+
+// FWID digest computed with SHA-256.
+#define DPE_FWID_SHA256 1
+
+// FWID digest computed with SHA-384.
+#define DPE_FWID_SHA384 2
+
+// FWID digest computed with SHA-512.
+#define DPE_FWID_SHA512 3
+
+// Size of the digest buffer of a FWID.
+#define DPE_FWID_DIGEST_SIZE 64
+
+// Scratch space for a running hash. The DPE never looks inside it.
+typedef struct DpeHashContext {
+  uint8_t bytes[DPE_HASH_CONTEXT_SIZE];
+} DpeHashContext;
+
+// Table of crypto callbacks. Copied by `dpe_new`.
+typedef struct DpeCryptoCallbacks {
+  // Passed unchanged as the first argument of every callback.
+  void *ctx;
+  // Fills `dst` with `len` random bytes.
+  uint32_t (*rand_bytes)(void *ctx, uint8_t *dst, size_t len);
+  // Starts a hash in `hash_ctx`.
+  uint32_t (*hash_initialize)(void *ctx, uint32_t alg, struct DpeHashContext *hash_ctx);
+  // Adds `len` bytes at `data` to the hash in `hash_ctx`.
+  uint32_t (*hash_update)(void *ctx, struct DpeHashContext *hash_ctx, const uint8_t *data, size_t len);
+  // Writes the digest of the hash in `hash_ctx` to `digest` and releases
+  // anything held by `hash_ctx`.
+  uint32_t (*hash_finish)(void *ctx, struct DpeHashContext *hash_ctx, uint8_t *digest);
+  // Releases anything held by a hash that will not be finished. Optional.
+  void (*hash_abort)(void *ctx, struct DpeHashContext *hash_ctx);
+  // Derives a CDI from the platform's base CDI, the `measurement` digest
+  // and `info`.
+  uint32_t (*derive_cdi)(void *ctx,
+                         uint32_t alg,
+                         const uint8_t *measurement,
+                         const uint8_t *info,
+                         size_t info_len,
+                         uint8_t *cdi);
+  // Derives an ECDSA key pair from `cdi`, `label` and `info`.
+  uint32_t (*derive_key_pair)(void *ctx,
+                              uint32_t alg,
+                              const uint8_t *cdi,
+                              const uint8_t *label,
+                              size_t label_len,
+                              const uint8_t *info,
+                              size_t info_len,
+                              uint8_t *priv_key,
+                              uint8_t *pub_x,
+                              uint8_t *pub_y);
+  // Signs `digest` with the platform alias key.
+  uint32_t (*ecdsa_sign_with_alias)(void *ctx,
+                                    uint32_t alg,
+                                    const uint8_t *digest,
+                                    uint8_t *sig_r,
+                                    uint8_t *sig_s);
+  // Signs `digest` with a key returned by `derive_key_pair`.
+  uint32_t (*ecdsa_sign_with_derived)(void *ctx,
+                                      uint32_t alg,
+                                      const uint8_t *digest,
+                                      const uint8_t *priv_key,
+                                      const uint8_t *pub_x,
+                                      const uint8_t *pub_y,
+                                      uint8_t *sig_r,
+                                      uint8_t *sig_s);
+  // Computes an HMAC over `digest` with a key derived from `cdi`, `label`
+  // and `info`.
+  uint32_t (*hmac_sign_with_derived)(void *ctx,
+                                     uint32_t alg,
+                                     const uint8_t *cdi,
+                                     const uint8_t *label,
+                                     size_t label_len,
+                                     const uint8_t *info,
+                                     size_t info_len,
+                                     const uint8_t *digest,
+                                     uint8_t *hmac);
+  // Generates an ephemeral key pair, agrees on a secret with the peer key
+  // and derives a 32-byte AES-256-GCM key from it. Only needed for
+  // sessions.
+  uint32_t (*ecdh_derive_key)(void *ctx,
+                              uint32_t alg,
+                              const uint8_t *peer_x,
+                              const uint8_t *peer_y,
+                              const uint8_t *info,
+                              size_t info_len,
+                              uint8_t *key,
+                              uint8_t *pub_x,
+                              uint8_t *pub_y);
+  // Encrypts `data` in place with AES-256-GCM and writes the 16-byte tag.
+  // The IV is 12 bytes. Only needed for sessions.
+  uint32_t (*aead_encrypt)(void *ctx,
+                           const uint8_t *key,
+                           const uint8_t *iv,
+                           const uint8_t *aad,
+                           size_t aad_len,
+                           uint8_t *data,
+                           size_t data_len,
+                           uint8_t *tag);
+  // Authenticates and decrypts `data` in place with AES-256-GCM. Only
+  // needed for sessions.
+  uint32_t (*aead_decrypt)(void *ctx,
+                           const uint8_t *key,
+                           const uint8_t *iv,
+                           const uint8_t *aad,
+                           size_t aad_len,
+                           uint8_t *data,
+                           size_t data_len,
+                           const uint8_t *tag);
+  // Encrypts `cdi_len` bytes of `cdi` into `wrapped` with AES-256-GCM under
+  // `key`, writing the random 12-byte IV and the 16-byte tag. Only needed
+  // for ExportCdi.
+  uint32_t (*wrap_cdi)(void *ctx,
+                       const uint8_t *cdi,
+                       size_t cdi_len,
+                       const uint8_t *key,
+                       const uint8_t *aad,
+                       size_t aad_len,
+                       uint8_t *wrapped,
+                       uint8_t *iv,
+                       uint8_t *tag);
+  // Derives a 32-byte AES-256-GCM key from `cdi`, `label` and `info`.
+  // Only needed for Seal and Unseal.
+  uint32_t (*derive_aead_key)(void *ctx,
+                              uint32_t alg,
+                              const uint8_t *cdi,
+                              const uint8_t *label,
+                              size_t label_len,
+                              const uint8_t *info,
+                              size_t info_len,
+                              uint8_t *key);
+} DpeCryptoCallbacks;
+
+// Reads a chunk of a DER blob such as the certificate chain.
+typedef uint32_t (*DpeChunkCallback)(void *ctx,
+                                     uint32_t offset,
+                                     uint32_t size,
+                                     uint8_t *out,
+                                     uint32_t *out_len);
+
+// Writes a variable length value into `out`, which holds `cap` bytes.
+typedef uint32_t (*DpeBufferCallback)(void *ctx, uint8_t *out, size_t cap, size_t *out_len);
+
+// Locality that may send commands, with a combination of the
+// `DPE_LOCALITY_*` flags.
+typedef struct DpeLocalityDescriptor {
+  uint32_t locality;
+  uint32_t flags;
+} DpeLocalityDescriptor;
+
+// FWID added to leaf certificates. `hash_alg` is one of the
+// `DPE_FWID_*` algorithms and `digest` is left-aligned.
+typedef struct DpeFwid {
+  uint32_t hash_alg;
+  uint8_t digest[DPE_FWID_DIGEST_SIZE];
+} DpeFwid;
+
+// Table of platform callbacks. Copied by `dpe_new`.
+typedef struct DpePlatformCallbacks {
+  // Passed unchanged as the first argument of every callback.
+  void *ctx;
+  uint32_t vendor_id;
+  uint32_t vendor_sku;
+  // Locality that owns the auto-initialized default context.
+  uint32_t auto_init_locality;
+  // Copies up to `size` bytes of the DER certificate chain, starting at
+  // `offset`, into `out` and stores the number copied in `out_len`.
+  // Copying fewer than `size` bytes marks the end of the chain.
+  DpeChunkCallback get_certificate_chain;
+  // Same as `get_certificate_chain`, for the measurements mixed into
+  // contexts derived with INTERNAL_INPUT_DICE. Optional; the certificate
+  // chain is used when NULL.
+  DpeChunkCallback get_dice_measurement;
+  // Writes the DER issuer name of the DPE's certificates.
+  DpeBufferCallback get_issuer_name;
+  // Writes the serial number of the issuer's certificate. Used for the
+  // CSR signer identifier when `get_signer_key_identifier` is NULL.
+  // Optional.
+  DpeBufferCallback get_issuer_serial_number;
+  // Writes the 20-byte key identifier naming the CSR signer. Optional.
+  uint32_t (*get_signer_key_identifier)(void *ctx, uint8_t *out);
+  // Writes the 20-byte key identifier of the issuer's key.
+  uint32_t (*get_issuer_key_identifier)(void *ctx, uint8_t *out);
+  // Writes the device's UEID.
+  DpeBufferCallback get_ueid;
+  // Writes the notBefore and notAfter times of issued certificates as
+  // GeneralizedTime strings such as "99991231235959Z". Each buffer holds
+  // 24 bytes.
+  uint32_t (*get_cert_validity)(void *ctx,
+                                uint8_t *not_before,
+                                size_t *not_before_len,
+                                uint8_t *not_after,
+                                size_t *not_after_len);
+  // Prints `len` bytes of UTF-8 debug output. Optional; output is dropped
+  // when NULL.
+  void (*write_str)(void *ctx, const uint8_t *str, size_t len);
+  // Writes the 32-byte key exported CDIs are wrapped under. Optional.
+  uint32_t (*get_cdi_wrapping_key)(void *ctx, uint8_t *out);
+  // Decides whether `locality` may run command `cmd_id`. Optional; every
+  // command is allowed when NULL.
+  bool (*authorize)(void *ctx, uint32_t cmd_id, uint32_t locality);
+  // Decides whether a context of type `child_type` may be derived from one of
+  // type `parent_type`. Optional; every derivation is allowed when NULL.
+  bool (*validate_tci_type)(void *ctx,
+                            uint32_t parent_type,
+                            uint32_t child_type);
+  // Writes the `len`-byte measurement the auto-initialized default context
+  // is extended with and stores its TCI type in `tci_type`. Optional; the
+  // default context is left unmeasured when NULL.
+  uint32_t (*get_boot_measurement)(void *ctx,
+                                   uint8_t *out,
+                                   size_t len,
+                                   uint32_t *tci_type);
+  // Writes the 32-byte key commands from `locality` are authenticated with.
+  // Optional; authenticated commands are rejected when NULL.
+  uint32_t (*get_command_auth_key)(void *ctx, uint32_t locality, uint8_t *out);
+  // Writes the descriptors of the localities that may send commands to
+  // `out`, which holds `cap` of them, and stores how many in `count`.
+  // Optional; every locality may send commands when NULL.
+  uint32_t (*get_locality_table)(void *ctx,
+                                 struct DpeLocalityDescriptor *out,
+                                 size_t cap,
+                                 size_t *count);
+  // Writes the FWIDs added to leaf certificates to `out`, which holds
+  // `cap` of them, and stores how many in `count`. Optional; no FWIDs are
+  // added when NULL.
+  uint32_t (*get_additional_fwids)(void *ctx, struct DpeFwid *out, size_t cap, size_t *count);
+} DpePlatformCallbacks;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// Creates the DPE instance, replacing any previous one.
+//
+// `support` holds the Support flags as reported by GetProfile. Returns 0 on
+// success or a DPE status code.
+//
+// # Safety
+//
+// `crypto` and `platform` must be NULL or point to valid callback tables.
+// The tables are copied, but their `ctx` pointers must stay valid for as
+// long as the instance is used. Must not be called concurrently with any
+// other function in this library.
+uint32_t dpe_new(const struct DpeCryptoCallbacks *crypto,
+                 const struct DpePlatformCallbacks *platform,
+                 uint32_t support);
+
+// Returns the size of the largest response `dpe_execute_command` can write.
+// A buffer this large never fails with `DPE_STATUS_OUTPUT_BUFFER_TOO_SMALL`.
+size_t dpe_max_response_size(void);
+
+// Executes the serialized command in `cmd` on behalf of `locality` and
+// writes the serialized response to `resp`.
+//
+// Returns 0 if a response was written, in which case `resp_len` holds its
+// length and the command's own status is in the response header. Otherwise
+// returns `DPE_STATUS_INVALID_ARGUMENT` for NULL pointers,
+// `DPE_STATUS_OUTPUT_BUFFER_TOO_SMALL` if the response is longer than
+// `resp_cap`, or `DPE_STATUS_INTERNAL_ERROR` if `dpe_new` has not succeeded.
+//
+// # Safety
+//
+// `cmd` must be valid for `cmd_len` bytes, `resp` must be valid for
+// `resp_cap` bytes and `resp_len` must be writable. Must not be called
+// concurrently with any other function in this library.
+uint32_t dpe_execute_command(uint32_t locality,
+                             const uint8_t *cmd,
+                             size_t cmd_len,
+                             uint8_t *resp,
+                             size_t resp_cap,
+                             size_t *resp_len);
+
+#ifdef __cplusplus
+} // extern "C"
+#endif // __cplusplus
+
+#endif /* DPE_FFI_H */
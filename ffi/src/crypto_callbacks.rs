@@ -0,0 +1,682 @@
+// Licensed under the Apache-2.0 license
+
+//! Crypto callbacks supplied by the embedding firmware.
+//!
+//! Every callback returns 0 on success. Any other value is reported to the
+//! DPE as `CryptoError::CryptoLibError` carrying that value. A callback left
+//! NULL is reported as `CryptoError::NotImplemented`.
+//!
+//! `alg` is the hash size in bytes: 32, 48 or 64. Digests, CDIs and HMACs are
+//! `alg` bytes long. ECC private keys, coordinates and signature components
+//! are big-endian, zero-padded to the curve's integer size (32, 48 or 66
+//! bytes).
+
+use core::ffi::c_void;
+use crypto::{
+    AeadKey, AlgLen, Cdi, Crypto, CryptoBuf, CryptoError, Digest, EcdsaPub, EcdsaSig, Hasher,
+    HmacSig, AEAD_IV_SIZE, AEAD_KEY_SIZE, AEAD_TAG_SIZE,
+};
+use zeroize::Zeroize;
+
+/// Size of the scratch space a running hash is kept in.
+pub const DPE_HASH_CONTEXT_SIZE: usize = 256;
+
+/// Large enough for any digest, CDI or ECC integer.
+const BUF_SIZE: usize = CryptoBuf::MAX_SIZE;
+
+/// Scratch space for a running hash. The DPE never looks inside it.
+#[repr(C, align(8))]
+pub struct DpeHashContext {
+    pub bytes: [u8; DPE_HASH_CONTEXT_SIZE],
+}
+
+/// Table of crypto callbacks. Copied by `dpe_new`.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct DpeCryptoCallbacks {
+    /// Passed unchanged as the first argument of every callback.
+    pub ctx: *mut c_void,
+    /// Fills `dst` with `len` random bytes.
+    pub rand_bytes: Option<unsafe extern "C" fn(ctx: *mut c_void, dst: *mut u8, len: usize) -> u32>,
+    /// Starts a hash in `hash_ctx`.
+    pub hash_initialize: Option<
+        unsafe extern "C" fn(ctx: *mut c_void, alg: u32, hash_ctx: *mut DpeHashContext) -> u32,
+    >,
+    /// Adds `len` bytes at `data` to the hash in `hash_ctx`.
+    pub hash_update: Option<
+        unsafe extern "C" fn(
+            ctx: *mut c_void,
+            hash_ctx: *mut DpeHashContext,
+            data: *const u8,
+            len: usize,
+        ) -> u32,
+    >,
+    /// Writes the digest of the hash in `hash_ctx` to `digest` and releases
+    /// anything held by `hash_ctx`.
+    pub hash_finish: Option<
+        unsafe extern "C" fn(
+            ctx: *mut c_void,
+            hash_ctx: *mut DpeHashContext,
+            digest: *mut u8,
+        ) -> u32,
+    >,
+    /// Releases anything held by a hash that will not be finished. Optional.
+    pub hash_abort: Option<unsafe extern "C" fn(ctx: *mut c_void, hash_ctx: *mut DpeHashContext)>,
+    /// Derives a CDI from the platform's base CDI, the `measurement` digest
+    /// and `info`.
+    pub derive_cdi: Option<
+        unsafe extern "C" fn(
+            ctx: *mut c_void,
+            alg: u32,
+            measurement: *const u8,
+            info: *const u8,
+            info_len: usize,
+            cdi: *mut u8,
+        ) -> u32,
+    >,
+    /// Derives an ECDSA key pair from `cdi`, `label` and `info`.
+    pub derive_key_pair: Option<
+        unsafe extern "C" fn(
+            ctx: *mut c_void,
+            alg: u32,
+            cdi: *const u8,
+            label: *const u8,
+            label_len: usize,
+            info: *const u8,
+            info_len: usize,
+            priv_key: *mut u8,
+            pub_x: *mut u8,
+            pub_y: *mut u8,
+        ) -> u32,
+    >,
+    /// Signs `digest` with the platform alias key.
+    pub ecdsa_sign_with_alias: Option<
+        unsafe extern "C" fn(
+            ctx: *mut c_void,
+            alg: u32,
+            digest: *const u8,
+            sig_r: *mut u8,
+            sig_s: *mut u8,
+        ) -> u32,
+    >,
+    /// Signs `digest` with a key returned by `derive_key_pair`.
+    pub ecdsa_sign_with_derived: Option<
+        unsafe extern "C" fn(
+            ctx: *mut c_void,
+            alg: u32,
+            digest: *const u8,
+            priv_key: *const u8,
+            pub_x: *const u8,
+            pub_y: *const u8,
+            sig_r: *mut u8,
+            sig_s: *mut u8,
+        ) -> u32,
+    >,
+    /// Computes an HMAC over `digest` with a key derived from `cdi`, `label`
+    /// and `info`.
+    pub hmac_sign_with_derived: Option<
+        unsafe extern "C" fn(
+            ctx: *mut c_void,
+            alg: u32,
+            cdi: *const u8,
+            label: *const u8,
+            label_len: usize,
+            info: *const u8,
+            info_len: usize,
+            digest: *const u8,
+            hmac: *mut u8,
+        ) -> u32,
+    >,
+    /// Generates an ephemeral key pair, agrees on a secret with the peer key
+    /// and derives a 32-byte AES-256-GCM key from it. Only needed for
+    /// sessions.
+    pub ecdh_derive_key: Option<
+        unsafe extern "C" fn(
+            ctx: *mut c_void,
+            alg: u32,
+            peer_x: *const u8,
+            peer_y: *const u8,
+            info: *const u8,
+            info_len: usize,
+            key: *mut u8,
+            pub_x: *mut u8,
+            pub_y: *mut u8,
+        ) -> u32,
+    >,
+    /// Encrypts `data` in place with AES-256-GCM and writes the 16-byte tag.
+    /// The IV is 12 bytes. Only needed for sessions.
+    pub aead_encrypt: Option<
+        unsafe extern "C" fn(
+            ctx: *mut c_void,
+            key: *const u8,
+            iv: *const u8,
+            aad: *const u8,
+            aad_len: usize,
+            data: *mut u8,
+            data_len: usize,
+            tag: *mut u8,
+        ) -> u32,
+    >,
+    /// Authenticates and decrypts `data` in place with AES-256-GCM. Only
+    /// needed for sessions.
+    pub aead_decrypt: Option<
+        unsafe extern "C" fn(
+            ctx: *mut c_void,
+            key: *const u8,
+            iv: *const u8,
+            aad: *const u8,
+            aad_len: usize,
+            data: *mut u8,
+            data_len: usize,
+            tag: *const u8,
+        ) -> u32,
+    >,
+    /// Encrypts `cdi_len` bytes of `cdi` into `wrapped` with AES-256-GCM under
+    /// `key`, writing the random 12-byte IV and the 16-byte tag. Only needed
+    /// for ExportCdi.
+    pub wrap_cdi: Option<
+        unsafe extern "C" fn(
+            ctx: *mut c_void,
+            cdi: *const u8,
+            cdi_len: usize,
+            key: *const u8,
+            aad: *const u8,
+            aad_len: usize,
+            wrapped: *mut u8,
+            iv: *mut u8,
+            tag: *mut u8,
+        ) -> u32,
+    >,
+    /// Derives a 32-byte AES-256-GCM key from `cdi`, `label` and `info`.
+    /// Only needed for Seal and Unseal.
+    pub derive_aead_key: Option<
+        unsafe extern "C" fn(
+            ctx: *mut c_void,
+            alg: u32,
+            cdi: *const u8,
+            label: *const u8,
+            label_len: usize,
+            info: *const u8,
+            info_len: usize,
+            key: *mut u8,
+        ) -> u32,
+    >,
+}
+
+fn required<T>(callback: Option<T>) -> Result<T, CryptoError> {
+    callback.ok_or(CryptoError::NotImplemented)
+}
+
+fn check(status: u32) -> Result<(), CryptoError> {
+    if status == 0 {
+        Ok(())
+    } else {
+        Err(CryptoError::CryptoLibError(status))
+    }
+}
+
+fn check_len(bytes: &[u8], len: usize) -> Result<(), CryptoError> {
+    if bytes.len() != len {
+        return Err(CryptoError::Size);
+    }
+    Ok(())
+}
+
+fn alg_id(algs: AlgLen) -> u32 {
+    algs.size() as u32
+}
+
+/// `Crypto` implementation that forwards to a `DpeCryptoCallbacks` table.
+pub struct FfiCrypto(pub DpeCryptoCallbacks);
+
+/// Running hash kept by the callbacks in a `DpeHashContext`.
+pub struct FfiHasher<'c> {
+    callbacks: &'c DpeCryptoCallbacks,
+    algs: AlgLen,
+    hash_ctx: DpeHashContext,
+    finished: bool,
+}
+
+impl Hasher for FfiHasher<'_> {
+    fn update(&mut self, bytes: &[u8]) -> Result<(), CryptoError> {
+        let hash_update = required(self.callbacks.hash_update)?;
+        // SAFETY: The callback receives a valid hash context and `bytes`.
+        check(unsafe {
+            hash_update(
+                self.callbacks.ctx,
+                &mut self.hash_ctx,
+                bytes.as_ptr(),
+                bytes.len(),
+            )
+        })
+    }
+
+    fn finish(mut self) -> Result<Digest, CryptoError> {
+        let hash_finish = required(self.callbacks.hash_finish)?;
+        let mut digest = [0u8; BUF_SIZE];
+        self.finished = true;
+        // SAFETY: `digest` is large enough for any supported hash.
+        check(unsafe { hash_finish(self.callbacks.ctx, &mut self.hash_ctx, digest.as_mut_ptr()) })?;
+        Digest::new(&digest[..self.algs.size()])
+    }
+}
+
+impl Drop for FfiHasher<'_> {
+    fn drop(&mut self) {
+        if self.finished {
+            return;
+        }
+        if let Some(hash_abort) = self.callbacks.hash_abort {
+            // SAFETY: The hash context was started and has not been finished.
+            unsafe { hash_abort(self.callbacks.ctx, &mut self.hash_ctx) }
+        }
+    }
+}
+
+impl Crypto for FfiCrypto {
+    type Cdi = Cdi;
+    type Hasher<'c> = FfiHasher<'c> where Self: 'c;
+    type PrivKey = CryptoBuf;
+
+    fn rand_bytes(&mut self, dst: &mut [u8]) -> Result<(), CryptoError> {
+        let rand_bytes = required(self.0.rand_bytes)?;
+        // SAFETY: `dst` is valid for `dst.len()` bytes.
+        check(unsafe { rand_bytes(self.0.ctx, dst.as_mut_ptr(), dst.len()) })
+    }
+
+    fn hash_initialize(&mut self, algs: AlgLen) -> Result<Self::Hasher<'_>, CryptoError> {
+        let hash_initialize = required(self.0.hash_initialize)?;
+        let mut hash_ctx = DpeHashContext {
+            bytes: [0; DPE_HASH_CONTEXT_SIZE],
+        };
+        // SAFETY: `hash_ctx` is a valid, writable hash context.
+        check(unsafe { hash_initialize(self.0.ctx, alg_id(algs), &mut hash_ctx) })?;
+        Ok(FfiHasher {
+            callbacks: &self.0,
+            algs,
+            hash_ctx,
+            finished: false,
+        })
+    }
+
+    fn derive_cdi(
+        &mut self,
+        algs: AlgLen,
+        measurement: &Digest,
+        info: &[u8],
+    ) -> Result<Self::Cdi, CryptoError> {
+        let derive_cdi = required(self.0.derive_cdi)?;
+        check_len(measurement.bytes(), algs.size())?;
+        let mut cdi = [0u8; BUF_SIZE];
+        // SAFETY: Inputs are valid for their lengths and `cdi` holds `alg`
+        // bytes.
+        let status = unsafe {
+            derive_cdi(
+                self.0.ctx,
+                alg_id(algs),
+                measurement.bytes().as_ptr(),
+                info.as_ptr(),
+                info.len(),
+                cdi.as_mut_ptr(),
+            )
+        };
+        let result = check(status).and_then(|_| Cdi::new(&cdi[..algs.size()]));
+        cdi.zeroize();
+        result
+    }
+
+    fn derive_key_pair(
+        &mut self,
+        algs: AlgLen,
+        cdi: &Self::Cdi,
+        label: &[u8],
+        info: &[u8],
+    ) -> Result<(Self::PrivKey, EcdsaPub), CryptoError> {
+        let derive_key_pair = required(self.0.derive_key_pair)?;
+        check_len(cdi.bytes(), algs.size())?;
+        let len = algs.ecc_int_size();
+        let mut priv_key = [0u8; BUF_SIZE];
+        let mut x = [0u8; BUF_SIZE];
+        let mut y = [0u8; BUF_SIZE];
+        // SAFETY: Inputs are valid for their lengths and each output holds an
+        // ECC integer.
+        let status = unsafe {
+            derive_key_pair(
+                self.0.ctx,
+                alg_id(algs),
+                cdi.bytes().as_ptr(),
+                label.as_ptr(),
+                label.len(),
+                info.as_ptr(),
+                info.len(),
+                priv_key.as_mut_ptr(),
+                x.as_mut_ptr(),
+                y.as_mut_ptr(),
+            )
+        };
+        let result = check(status).and_then(|_| {
+            let pub_key = EcdsaPub {
+                x: CryptoBuf::new(&x[..len])?,
+                y: CryptoBuf::new(&y[..len])?,
+            };
+            Ok((CryptoBuf::new(&priv_key[..len])?, pub_key))
+        });
+        priv_key.zeroize();
+        result
+    }
+
+    fn ecdsa_sign_with_alias(
+        &mut self,
+        algs: AlgLen,
+        digest: &Digest,
+    ) -> Result<EcdsaSig, CryptoError> {
+        let ecdsa_sign_with_alias = required(self.0.ecdsa_sign_with_alias)?;
+        check_len(digest.bytes(), algs.size())?;
+        let len = algs.ecc_int_size();
+        let mut r = [0u8; BUF_SIZE];
+        let mut s = [0u8; BUF_SIZE];
+        // SAFETY: `digest` holds `alg` bytes and each output holds an ECC
+        // integer.
+        check(unsafe {
+            ecdsa_sign_with_alias(
+                self.0.ctx,
+                alg_id(algs),
+                digest.bytes().as_ptr(),
+                r.as_mut_ptr(),
+                s.as_mut_ptr(),
+            )
+        })?;
+        Ok(EcdsaSig {
+            r: CryptoBuf::new(&r[..len])?,
+            s: CryptoBuf::new(&s[..len])?,
+        })
+    }
+
+    fn ecdsa_sign_with_derived(
+        &mut self,
+        algs: AlgLen,
+        digest: &Digest,
+        priv_key: &Self::PrivKey,
+        pub_key: &EcdsaPub,
+    ) -> Result<EcdsaSig, CryptoError> {
+        let ecdsa_sign_with_derived = required(self.0.ecdsa_sign_with_derived)?;
+        let len = algs.ecc_int_size();
+        check_len(digest.bytes(), algs.size())?;
+        check_len(priv_key.bytes(), len)?;
+        check_len(pub_key.x.bytes(), len)?;
+        check_len(pub_key.y.bytes(), len)?;
+        let mut r = [0u8; BUF_SIZE];
+        let mut s = [0u8; BUF_SIZE];
+        // SAFETY: Inputs have the documented lengths and each output holds an
+        // ECC integer.
+        check(unsafe {
+            ecdsa_sign_with_derived(
+                self.0.ctx,
+                alg_id(algs),
+                digest.bytes().as_ptr(),
+                priv_key.bytes().as_ptr(),
+                pub_key.x.bytes().as_ptr(),
+                pub_key.y.bytes().as_ptr(),
+                r.as_mut_ptr(),
+                s.as_mut_ptr(),
+            )
+        })?;
+        Ok(EcdsaSig {
+            r: CryptoBuf::new(&r[..len])?,
+            s: CryptoBuf::new(&s[..len])?,
+        })
+    }
+
+    fn hmac_sign_with_derived(
+        &mut self,
+        algs: AlgLen,
+        cdi: &Self::Cdi,
+        label: &[u8],
+        info: &[u8],
+        digest: &Digest,
+    ) -> Result<HmacSig, CryptoError> {
+        let hmac_sign_with_derived = required(self.0.hmac_sign_with_derived)?;
+        check_len(cdi.bytes(), algs.size())?;
+        check_len(digest.bytes(), algs.size())?;
+        let mut hmac = [0u8; BUF_SIZE];
+        // SAFETY: Inputs are valid for their lengths and `hmac` holds `alg`
+        // bytes.
+        check(unsafe {
+            hmac_sign_with_derived(
+                self.0.ctx,
+                alg_id(algs),
+                cdi.bytes().as_ptr(),
+                label.as_ptr(),
+                label.len(),
+                info.as_ptr(),
+                info.len(),
+                digest.bytes().as_ptr(),
+                hmac.as_mut_ptr(),
+            )
+        })?;
+        HmacSig::new(&hmac[..algs.size()])
+    }
+
+    fn ecdh_derive_key(
+        &mut self,
+        algs: AlgLen,
+        peer_pub: &EcdsaPub,
+        info: &[u8],
+    ) -> Result<(AeadKey, EcdsaPub), CryptoError> {
+        let ecdh_derive_key = required(self.0.ecdh_derive_key)?;
+        let len = algs.ecc_int_size();
+        check_len(peer_pub.x.bytes(), len)?;
+        check_len(peer_pub.y.bytes(), len)?;
+        let mut key = AeadKey([0; AEAD_KEY_SIZE]);
+        let mut x = [0u8; BUF_SIZE];
+        let mut y = [0u8; BUF_SIZE];
+        // SAFETY: Inputs have the documented lengths and each output is large
+        // enough.
+        check(unsafe {
+            ecdh_derive_key(
+                self.0.ctx,
+                alg_id(algs),
+                peer_pub.x.bytes().as_ptr(),
+                peer_pub.y.bytes().as_ptr(),
+                info.as_ptr(),
+                info.len(),
+                key.0.as_mut_ptr(),
+                x.as_mut_ptr(),
+                y.as_mut_ptr(),
+            )
+        })?;
+        let pub_key = EcdsaPub {
+            x: CryptoBuf::new(&x[..len])?,
+            y: CryptoBuf::new(&y[..len])?,
+        };
+        Ok((key, pub_key))
+    }
+
+    fn aead_encrypt(
+        &mut self,
+        key: &AeadKey,
+        iv: &[u8; AEAD_IV_SIZE],
+        aad: &[u8],
+        data: &mut [u8],
+    ) -> Result<[u8; AEAD_TAG_SIZE], CryptoError> {
+        let aead_encrypt = required(self.0.aead_encrypt)?;
+        let mut tag = [0u8; AEAD_TAG_SIZE];
+        // SAFETY: Every buffer is valid for its documented length.
+        check(unsafe {
+            aead_encrypt(
+                self.0.ctx,
+                key.0.as_ptr(),
+                iv.as_ptr(),
+                aad.as_ptr(),
+                aad.len(),
+                data.as_mut_ptr(),
+                data.len(),
+                tag.as_mut_ptr(),
+            )
+        })?;
+        Ok(tag)
+    }
+
+    fn aead_decrypt(
+        &mut self,
+        key: &AeadKey,
+        iv: &[u8; AEAD_IV_SIZE],
+        aad: &[u8],
+        data: &mut [u8],
+        tag: &[u8; AEAD_TAG_SIZE],
+    ) -> Result<(), CryptoError> {
+        let aead_decrypt = required(self.0.aead_decrypt)?;
+        // SAFETY: Every buffer is valid for its documented length.
+        check(unsafe {
+            aead_decrypt(
+                self.0.ctx,
+                key.0.as_ptr(),
+                iv.as_ptr(),
+                aad.as_ptr(),
+                aad.len(),
+                data.as_mut_ptr(),
+                data.len(),
+                tag.as_ptr(),
+            )
+        })
+    }
+
+    fn wrap_cdi(
+        &mut self,
+        cdi: &Self::Cdi,
+        key: &AeadKey,
+        aad: &[u8],
+        wrapped: &mut [u8],
+    ) -> Result<([u8; AEAD_IV_SIZE], [u8; AEAD_TAG_SIZE]), CryptoError> {
+        let wrap_cdi = required(self.0.wrap_cdi)?;
+        check_len(wrapped, cdi.bytes().len())?;
+        let mut iv = [0u8; AEAD_IV_SIZE];
+        let mut tag = [0u8; AEAD_TAG_SIZE];
+        // SAFETY: `wrapped` is as long as the CDI and the other buffers are
+        // valid for their documented lengths.
+        check(unsafe {
+            wrap_cdi(
+                self.0.ctx,
+                cdi.bytes().as_ptr(),
+                cdi.bytes().len(),
+                key.0.as_ptr(),
+                aad.as_ptr(),
+                aad.len(),
+                wrapped.as_mut_ptr(),
+                iv.as_mut_ptr(),
+                tag.as_mut_ptr(),
+            )
+        })?;
+        Ok((iv, tag))
+    }
+
+    fn derive_aead_key(
+        &mut self,
+        algs: AlgLen,
+        cdi: &Self::Cdi,
+        label: &[u8],
+        info: &[u8],
+    ) -> Result<AeadKey, CryptoError> {
+        let derive_aead_key = required(self.0.derive_aead_key)?;
+        check_len(cdi.bytes(), algs.size())?;
+        let mut key = AeadKey([0; AEAD_KEY_SIZE]);
+        // SAFETY: Inputs are valid for their lengths and `key` holds an
+        // AES-256 key.
+        check(unsafe {
+            derive_aead_key(
+                self.0.ctx,
+                alg_id(algs),
+                cdi.bytes().as_ptr(),
+                label.as_ptr(),
+                label.len(),
+                info.as_ptr(),
+                info.len(),
+                key.0.as_mut_ptr(),
+            )
+        })?;
+        Ok(key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::ptr::null_mut;
+
+    const NO_CALLBACKS: DpeCryptoCallbacks = DpeCryptoCallbacks {
+        ctx: null_mut(),
+        rand_bytes: None,
+        hash_initialize: None,
+        hash_update: None,
+        hash_finish: None,
+        hash_abort: None,
+        derive_cdi: None,
+        derive_key_pair: None,
+        ecdsa_sign_with_alias: None,
+        ecdsa_sign_with_derived: None,
+        hmac_sign_with_derived: None,
+        ecdh_derive_key: None,
+        aead_encrypt: None,
+        aead_decrypt: None,
+        wrap_cdi: None,
+        derive_aead_key: None,
+    };
+
+    unsafe extern "C" fn fill_rand(_ctx: *mut c_void, dst: *mut u8, len: usize) -> u32 {
+        core::slice::from_raw_parts_mut(dst, len).fill(0xa5);
+        0
+    }
+
+    unsafe extern "C" fn fail_rand(_ctx: *mut c_void, _dst: *mut u8, _len: usize) -> u32 {
+        7
+    }
+
+    unsafe extern "C" fn count_init(
+        ctx: *mut c_void,
+        _alg: u32,
+        _hash_ctx: *mut DpeHashContext,
+    ) -> u32 {
+        *(ctx as *mut u32) += 1;
+        0
+    }
+
+    unsafe extern "C" fn count_abort(ctx: *mut c_void, _hash_ctx: *mut DpeHashContext) {
+        *(ctx as *mut u32) -= 1;
+    }
+
+    #[test]
+    fn test_status_mapping() {
+        let mut crypto = FfiCrypto(NO_CALLBACKS);
+        let mut buf = [0u8; 4];
+        assert_eq!(
+            Err(CryptoError::NotImplemented),
+            crypto.rand_bytes(&mut buf)
+        );
+
+        crypto.0.rand_bytes = Some(fill_rand);
+        crypto.rand_bytes(&mut buf).unwrap();
+        assert_eq!([0xa5; 4], buf);
+
+        crypto.0.rand_bytes = Some(fail_rand);
+        assert_eq!(
+            Err(CryptoError::CryptoLibError(7)),
+            crypto.rand_bytes(&mut buf)
+        );
+    }
+
+    #[test]
+    fn test_unfinished_hash_is_aborted() {
+        let mut live_hashes = 0u32;
+        let mut crypto = FfiCrypto(DpeCryptoCallbacks {
+            ctx: &mut live_hashes as *mut u32 as *mut c_void,
+            hash_initialize: Some(count_init),
+            hash_abort: Some(count_abort),
+            ..NO_CALLBACKS
+        });
+
+        let hasher = crypto.hash_initialize(AlgLen::Bit256).unwrap();
+        // `hash_finish` is missing, so the hash is aborted instead.
+        assert_eq!(Err(CryptoError::NotImplemented), hasher.finish());
+        assert_eq!(0, live_hashes);
+    }
+}
@@ -0,0 +1,623 @@
+/*++
+Licensed under the Apache-2.0 license.
+
+Abstract:
+    C bindings for embedding the DPE in firmware that isn't written in Rust.
+
+    The firmware links the static library built from this crate, registers
+    its crypto and platform callbacks with `dpe_new` and then passes each
+    serialized command to `dpe_execute_command`. See include/dpe_ffi.h.
+
+    There is a single DPE instance. None of the functions are reentrant, so
+    the caller must serialize calls across threads and interrupt handlers.
+--*/
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+mod crypto_callbacks;
+mod platform_callbacks;
+
+pub use crypto_callbacks::{
+    DpeCryptoCallbacks, DpeHashContext, FfiCrypto, FfiHasher, DPE_HASH_CONTEXT_SIZE,
+};
+pub use platform_callbacks::{
+    DpeBufferCallback, DpeChunkCallback, DpeLocalityDescriptor, DpePlatformCallbacks, FfiPlatform,
+    DPE_LOCALITY_AUTO_INIT, DPE_LOCALITY_SIMULATION,
+};
+
+use core::{mem::size_of, ptr::addr_of_mut, slice};
+use dpe::{
+    dpe_instance::{DpeEnv, DpeTypes},
+    response::{DpeErrorCode, Response, ResponseHdr, ResponseWriter},
+    support::Support,
+    DpeInstance,
+};
+
+/// Status returned when a call succeeds.
+pub const DPE_STATUS_OK: u32 = 0;
+/// Status returned when no instance has been created.
+pub const DPE_STATUS_INTERNAL_ERROR: u32 = 1;
+/// Status returned for NULL pointers.
+pub const DPE_STATUS_INVALID_ARGUMENT: u32 = 3;
+/// Status returned when the response doesn't fit in the caller's buffer.
+pub const DPE_STATUS_OUTPUT_BUFFER_TOO_SMALL: u32 = 0x100F;
+
+pub struct FfiTypes;
+
+impl DpeTypes for FfiTypes {
+    type Crypto<'a> = FfiCrypto;
+    type Platform<'a> = FfiPlatform;
+}
+
+struct FfiState {
+    dpe: DpeInstance,
+    env: DpeEnv<'static, FfiTypes>,
+}
+
+static mut STATE: Option<FfiState> = None;
+
+/// Creates the DPE instance, replacing any previous one.
+///
+/// `support` holds the Support flags as reported by GetProfile. Returns 0 on
+/// success or a DPE status code.
+///
+/// # Safety
+///
+/// `crypto` and `platform` must be NULL or point to valid callback tables.
+/// The tables are copied, but their `ctx` pointers must stay valid for as
+/// long as the instance is used. Must not be called concurrently with any
+/// other function in this library.
+#[no_mangle]
+pub unsafe extern "C" fn dpe_new(
+    crypto: *const DpeCryptoCallbacks,
+    platform: *const DpePlatformCallbacks,
+    support: u32,
+) -> u32 {
+    let (Some(crypto), Some(platform)) = (crypto.as_ref(), platform.as_ref()) else {
+        return DpeErrorCode::InvalidArgument.get_error_code();
+    };
+    let Some(support) = Support::from_bits(support) else {
+        return DpeErrorCode::InvalidArgument.get_error_code();
+    };
+
+    let mut env = DpeEnv::<FfiTypes> {
+        crypto: FfiCrypto(*crypto),
+        platform: FfiPlatform(*platform),
+        arena: &mut [],
+    };
+    match DpeInstance::new(&mut env, support) {
+        Ok(dpe) => {
+            *addr_of_mut!(STATE) = Some(FfiState { dpe, env });
+            DpeErrorCode::NoError.get_error_code()
+        }
+        Err(e) => e.get_error_code(),
+    }
+}
+
+/// Returns the size of the largest response `dpe_execute_command` can write.
+/// A buffer this large never fails with `DPE_STATUS_OUTPUT_BUFFER_TOO_SMALL`.
+#[no_mangle]
+pub extern "C" fn dpe_max_response_size() -> usize {
+    size_of::<Response>()
+}
+
+/// Executes the serialized command in `cmd` on behalf of `locality` and
+/// writes the serialized response to `resp`.
+///
+/// Returns 0 if a response was written, in which case `resp_len` holds its
+/// length and the command's own status is in the response header. Otherwise
+/// returns `DPE_STATUS_INVALID_ARGUMENT` for NULL pointers,
+/// `DPE_STATUS_OUTPUT_BUFFER_TOO_SMALL` if the response is longer than
+/// `resp_cap`, or `DPE_STATUS_INTERNAL_ERROR` if `dpe_new` has not succeeded.
+///
+/// # Safety
+///
+/// `cmd` must be valid for `cmd_len` bytes, `resp` must be valid for
+/// `resp_cap` bytes and `resp_len` must be writable. Must not be called
+/// concurrently with any other function in this library.
+#[no_mangle]
+pub unsafe extern "C" fn dpe_execute_command(
+    locality: u32,
+    cmd: *const u8,
+    cmd_len: usize,
+    resp: *mut u8,
+    resp_cap: usize,
+    resp_len: *mut usize,
+) -> u32 {
+    if cmd.is_null() || resp.is_null() || resp_len.is_null() {
+        return DpeErrorCode::InvalidArgument.get_error_code();
+    }
+    let Some(state) = (*addr_of_mut!(STATE)).as_mut() else {
+        return DpeErrorCode::InternalError.get_error_code();
+    };
+
+    let cmd = slice::from_raw_parts(cmd, cmd_len);
+    let resp = slice::from_raw_parts_mut(resp, resp_cap);
+    let response = state
+        .dpe
+        .execute_serialized_command(&mut state.env, locality, cmd)
+        .unwrap_or_else(|e| Response::Error(ResponseHdr::new(e)));
+    let mut out = ResponseWriter::new(resp);
+    if let Err(e) = out.write(response.as_bytes()) {
+        return e.get_error_code();
+    }
+    *resp_len = out.len();
+    DpeErrorCode::NoError.get_error_code()
+}
+
+#[cfg(not(any(feature = "std", test)))]
+#[panic_handler]
+fn panic(_info: &core::panic::PanicInfo) -> ! {
+    loop {
+        core::hint::spin_loop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::crypto::{AlgLen, Cdi, Crypto, CryptoBuf, Digest, EcdsaPub, OpensslCrypto};
+    use core::ffi::c_void;
+    use dpe::{
+        commands::{CertifyKeyCmd, CertifyKeyFlags, Command, CommandHdr},
+        context::ContextHandle,
+        response::{CertifyKeyResp, GetProfileResp},
+        DPE_PROFILE,
+    };
+    use openssl::hash::MessageDigest;
+    use platform::{
+        default::DefaultPlatform, Platform, SignerIdentifier, MAX_CHUNK_SIZE, MAX_ISSUER_NAME_SIZE,
+        MAX_KEY_IDENTIFIER_SIZE, MAX_UEID_SIZE,
+    };
+    use std::{ptr::null_mut, sync::Mutex};
+    use zerocopy::{AsBytes, FromBytes};
+
+    // Tests share the single instance.
+    static LOCK: Mutex<()> = Mutex::new(());
+
+    fn algs(alg: u32) -> AlgLen {
+        match alg {
+            32 => AlgLen::Bit256,
+            48 => AlgLen::Bit384,
+            _ => AlgLen::Bit512,
+        }
+    }
+
+    unsafe fn crypto<'a>(ctx: *mut c_void) -> &'a mut OpensslCrypto {
+        &mut *(ctx as *mut OpensslCrypto)
+    }
+
+    unsafe fn input<'a>(ptr: *const u8, len: usize) -> &'a [u8] {
+        slice::from_raw_parts(ptr, len)
+    }
+
+    unsafe fn output(ptr: *mut u8, bytes: &[u8]) {
+        slice::from_raw_parts_mut(ptr, bytes.len()).copy_from_slice(bytes);
+    }
+
+    unsafe extern "C" fn rand_bytes(ctx: *mut c_void, dst: *mut u8, len: usize) -> u32 {
+        crypto(ctx)
+            .rand_bytes(slice::from_raw_parts_mut(dst, len))
+            .map_or(1, |_| 0)
+    }
+
+    unsafe extern "C" fn hash_initialize(
+        _ctx: *mut c_void,
+        alg: u32,
+        hash_ctx: *mut DpeHashContext,
+    ) -> u32 {
+        let digest = match algs(alg) {
+            AlgLen::Bit256 => MessageDigest::sha256(),
+            AlgLen::Bit384 => MessageDigest::sha384(),
+            AlgLen::Bit512 => MessageDigest::sha512(),
+        };
+        let hasher = Box::new(openssl::hash::Hasher::new(digest).unwrap());
+        (hash_ctx as *mut *mut openssl::hash::Hasher).write(Box::into_raw(hasher));
+        0
+    }
+
+    unsafe extern "C" fn hash_update(
+        _ctx: *mut c_void,
+        hash_ctx: *mut DpeHashContext,
+        data: *const u8,
+        len: usize,
+    ) -> u32 {
+        let hasher = *(hash_ctx as *mut *mut openssl::hash::Hasher);
+        (*hasher).update(input(data, len)).map_or(1, |_| 0)
+    }
+
+    unsafe extern "C" fn hash_finish(
+        _ctx: *mut c_void,
+        hash_ctx: *mut DpeHashContext,
+        digest: *mut u8,
+    ) -> u32 {
+        let mut hasher = Box::from_raw(*(hash_ctx as *mut *mut openssl::hash::Hasher));
+        output(digest, &hasher.finish().unwrap());
+        0
+    }
+
+    unsafe extern "C" fn hash_abort(_ctx: *mut c_void, hash_ctx: *mut DpeHashContext) {
+        drop(Box::from_raw(
+            *(hash_ctx as *mut *mut openssl::hash::Hasher),
+        ));
+    }
+
+    unsafe extern "C" fn derive_cdi(
+        ctx: *mut c_void,
+        alg: u32,
+        measurement: *const u8,
+        info: *const u8,
+        info_len: usize,
+        cdi: *mut u8,
+    ) -> u32 {
+        let algs = algs(alg);
+        let measurement = Digest::new(input(measurement, algs.size())).unwrap();
+        match crypto(ctx).derive_cdi(algs, &measurement, input(info, info_len)) {
+            Ok(derived) => {
+                output(cdi, derived.bytes());
+                0
+            }
+            Err(_) => 1,
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    unsafe extern "C" fn derive_key_pair(
+        ctx: *mut c_void,
+        alg: u32,
+        cdi: *const u8,
+        label: *const u8,
+        label_len: usize,
+        info: *const u8,
+        info_len: usize,
+        priv_key: *mut u8,
+        pub_x: *mut u8,
+        pub_y: *mut u8,
+    ) -> u32 {
+        let algs = algs(alg);
+        let cdi = Cdi::new(input(cdi, algs.size())).unwrap();
+        match crypto(ctx).derive_key_pair(
+            algs,
+            &cdi,
+            input(label, label_len),
+            input(info, info_len),
+        ) {
+            Ok((priv_bytes, pub_key)) => {
+                // Keys come back without leading zeroes.
+                let len = algs.ecc_int_size();
+                let padding = len - priv_bytes.len();
+                slice::from_raw_parts_mut(priv_key, padding).fill(0);
+                output(priv_key.add(padding), priv_bytes.bytes());
+                output(pub_x, pub_key.x.bytes());
+                output(pub_y, pub_key.y.bytes());
+                0
+            }
+            Err(_) => 1,
+        }
+    }
+
+    unsafe extern "C" fn ecdsa_sign_with_alias(
+        ctx: *mut c_void,
+        alg: u32,
+        digest: *const u8,
+        sig_r: *mut u8,
+        sig_s: *mut u8,
+    ) -> u32 {
+        let algs = algs(alg);
+        let digest = Digest::new(input(digest, algs.size())).unwrap();
+        match crypto(ctx).ecdsa_sign_with_alias(algs, &digest) {
+            Ok(sig) => {
+                output(sig_r, sig.r.bytes());
+                output(sig_s, sig.s.bytes());
+                0
+            }
+            Err(_) => 1,
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    unsafe extern "C" fn ecdsa_sign_with_derived(
+        ctx: *mut c_void,
+        alg: u32,
+        digest: *const u8,
+        priv_key: *const u8,
+        pub_x: *const u8,
+        pub_y: *const u8,
+        sig_r: *mut u8,
+        sig_s: *mut u8,
+    ) -> u32 {
+        let algs = algs(alg);
+        let len = algs.ecc_int_size();
+        let digest = Digest::new(input(digest, algs.size())).unwrap();
+        let priv_key = CryptoBuf::new(input(priv_key, len)).unwrap();
+        let pub_key = EcdsaPub {
+            x: CryptoBuf::new(input(pub_x, len)).unwrap(),
+            y: CryptoBuf::new(input(pub_y, len)).unwrap(),
+        };
+        match crypto(ctx).ecdsa_sign_with_derived(algs, &digest, &priv_key, &pub_key) {
+            Ok(sig) => {
+                output(sig_r, sig.r.bytes());
+                output(sig_s, sig.s.bytes());
+                0
+            }
+            Err(_) => 1,
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    unsafe extern "C" fn hmac_sign_with_derived(
+        ctx: *mut c_void,
+        alg: u32,
+        cdi: *const u8,
+        label: *const u8,
+        label_len: usize,
+        info: *const u8,
+        info_len: usize,
+        digest: *const u8,
+        hmac: *mut u8,
+    ) -> u32 {
+        let algs = algs(alg);
+        let cdi = Cdi::new(input(cdi, algs.size())).unwrap();
+        let digest = Digest::new(input(digest, algs.size())).unwrap();
+        match crypto(ctx).hmac_sign_with_derived(
+            algs,
+            &cdi,
+            input(label, label_len),
+            input(info, info_len),
+            &digest,
+        ) {
+            Ok(sig) => {
+                output(hmac, sig.bytes());
+                0
+            }
+            Err(_) => 1,
+        }
+    }
+
+    const CRYPTO_CALLBACKS: DpeCryptoCallbacks = DpeCryptoCallbacks {
+        ctx: null_mut(),
+        rand_bytes: Some(rand_bytes),
+        hash_initialize: Some(hash_initialize),
+        hash_update: Some(hash_update),
+        hash_finish: Some(hash_finish),
+        hash_abort: Some(hash_abort),
+        derive_cdi: Some(derive_cdi),
+        derive_key_pair: Some(derive_key_pair),
+        ecdsa_sign_with_alias: Some(ecdsa_sign_with_alias),
+        ecdsa_sign_with_derived: Some(ecdsa_sign_with_derived),
+        hmac_sign_with_derived: Some(hmac_sign_with_derived),
+        ecdh_derive_key: None,
+        aead_encrypt: None,
+        aead_decrypt: None,
+        wrap_cdi: None,
+        derive_aead_key: None,
+    };
+
+    unsafe extern "C" fn get_certificate_chain(
+        _ctx: *mut c_void,
+        offset: u32,
+        size: u32,
+        out: *mut u8,
+        out_len: *mut u32,
+    ) -> u32 {
+        let mut chunk = [0u8; MAX_CHUNK_SIZE];
+        match DefaultPlatform.get_certificate_chain(offset, size, &mut chunk) {
+            Ok(len) => {
+                output(out, &chunk[..len as usize]);
+                *out_len = len;
+                0
+            }
+            Err(_) => 1,
+        }
+    }
+
+    unsafe extern "C" fn get_issuer_name(
+        _ctx: *mut c_void,
+        out: *mut u8,
+        _cap: usize,
+        out_len: *mut usize,
+    ) -> u32 {
+        let mut name = [0u8; MAX_ISSUER_NAME_SIZE];
+        let len = DefaultPlatform.get_issuer_name(&mut name).unwrap();
+        output(out, &name[..len]);
+        *out_len = len;
+        0
+    }
+
+    unsafe extern "C" fn get_signer_key_identifier(_ctx: *mut c_void, out: *mut u8) -> u32 {
+        match DefaultPlatform.get_signer_identifier() {
+            Ok(SignerIdentifier::SubjectKeyIdentifier(ski)) => {
+                output(out, &ski);
+                0
+            }
+            _ => 1,
+        }
+    }
+
+    unsafe extern "C" fn get_issuer_key_identifier(_ctx: *mut c_void, out: *mut u8) -> u32 {
+        let mut key_id = [0u8; MAX_KEY_IDENTIFIER_SIZE];
+        DefaultPlatform
+            .get_issuer_key_identifier(&mut key_id)
+            .unwrap();
+        output(out, &key_id);
+        0
+    }
+
+    unsafe extern "C" fn get_ueid(
+        _ctx: *mut c_void,
+        out: *mut u8,
+        _cap: usize,
+        out_len: *mut usize,
+    ) -> u32 {
+        let mut ueid = [0u8; MAX_UEID_SIZE];
+        let len = DefaultPlatform.get_ueid(&mut ueid).unwrap();
+        output(out, &ueid[..len]);
+        *out_len = len;
+        0
+    }
+
+    unsafe extern "C" fn get_cert_validity(
+        _ctx: *mut c_void,
+        not_before: *mut u8,
+        not_before_len: *mut usize,
+        not_after: *mut u8,
+        not_after_len: *mut usize,
+    ) -> u32 {
+        let validity = DefaultPlatform.get_cert_validity().unwrap();
+        output(not_before, &validity.not_before);
+        *not_before_len = validity.not_before.len();
+        output(not_after, &validity.not_after);
+        *not_after_len = validity.not_after.len();
+        0
+    }
+
+    const PLATFORM_CALLBACKS: DpePlatformCallbacks = DpePlatformCallbacks {
+        ctx: null_mut(),
+        vendor_id: 0,
+        vendor_sku: 0,
+        auto_init_locality: 0,
+        get_certificate_chain: Some(get_certificate_chain),
+        get_dice_measurement: None,
+        get_issuer_name: Some(get_issuer_name),
+        get_issuer_serial_number: None,
+        get_signer_key_identifier: Some(get_signer_key_identifier),
+        get_issuer_key_identifier: Some(get_issuer_key_identifier),
+        get_ueid: Some(get_ueid),
+        get_cert_validity: Some(get_cert_validity),
+        write_str: None,
+        get_cdi_wrapping_key: None,
+        authorize: None,
+        validate_tci_type: None,
+        get_boot_measurement: None,
+        get_command_auth_key: None,
+        get_locality_table: None,
+        get_additional_fwids: None,
+    };
+
+    fn execute(cmd: &[u8]) -> Vec<u8> {
+        let mut resp = vec![0u8; dpe_max_response_size()];
+        let mut resp_len = 0;
+        assert_eq!(0, unsafe {
+            dpe_execute_command(
+                0,
+                cmd.as_ptr(),
+                cmd.len(),
+                resp.as_mut_ptr(),
+                resp.len(),
+                &mut resp_len,
+            )
+        });
+        resp.truncate(resp_len);
+        resp
+    }
+
+    #[test]
+    fn test_execute_command() {
+        let _lock = LOCK.lock().unwrap();
+        let mut openssl = OpensslCrypto::new();
+        let crypto = DpeCryptoCallbacks {
+            ctx: &mut openssl as *mut OpensslCrypto as *mut c_void,
+            ..CRYPTO_CALLBACKS
+        };
+        let support = Support::AUTO_INIT | Support::X509;
+        assert_eq!(0, unsafe {
+            dpe_new(&crypto, &PLATFORM_CALLBACKS, support.bits())
+        });
+
+        let resp = execute(CommandHdr::new(Command::GET_PROFILE).as_bytes());
+        let profile = GetProfileResp::read_from(resp.as_slice()).unwrap();
+        assert_eq!(0, profile.resp_hdr.status);
+        assert_eq!(support.bits(), profile.flags);
+
+        // The buffer only needs to hold the response being returned.
+        let cmd = CommandHdr::new(Command::GET_PROFILE);
+        let mut resp = vec![0u8; size_of::<GetProfileResp>()];
+        let mut resp_len = 0;
+        let mut execute_into = |resp: &mut [u8]| unsafe {
+            dpe_execute_command(
+                0,
+                cmd.as_bytes().as_ptr(),
+                cmd.as_bytes().len(),
+                resp.as_mut_ptr(),
+                resp.len(),
+                &mut resp_len,
+            )
+        };
+        assert_eq!(DPE_STATUS_OK, execute_into(&mut resp[..]));
+        assert_eq!(
+            DPE_STATUS_OUTPUT_BUFFER_TOO_SMALL,
+            execute_into(&mut resp[1..])
+        );
+        assert_eq!(
+            DPE_STATUS_OUTPUT_BUFFER_TOO_SMALL,
+            DpeErrorCode::OutputBufferTooSmall.get_error_code()
+        );
+
+        let mut cmd = CommandHdr::new(Command::CERTIFY_KEY).as_bytes().to_vec();
+        cmd.extend(
+            CertifyKeyCmd {
+                handle: ContextHandle::default(),
+                flags: CertifyKeyFlags::empty(),
+                label: [0; DPE_PROFILE.get_hash_size()],
+                format: CertifyKeyCmd::FORMAT_X509,
+            }
+            .as_bytes(),
+        );
+        let resp = execute(&cmd);
+        let certify_key = CertifyKeyResp::read_from_prefix(resp.as_slice()).unwrap();
+        assert_eq!(0, certify_key.resp_hdr.status);
+        assert_ne!(0, certify_key.cert_size);
+
+        // Command failures are reported in the response.
+        let resp = execute(&[0; 4]);
+        let hdr = ResponseHdr::read_from_prefix(resp.as_slice()).unwrap();
+        assert_eq!(DpeErrorCode::InvalidCommand.get_error_code(), hdr.status);
+
+        // The callbacks point at this stack frame.
+        unsafe { *addr_of_mut!(STATE) = None };
+    }
+
+    #[test]
+    fn test_invalid_arguments() {
+        let _lock = LOCK.lock().unwrap();
+        let invalid_argument = DpeErrorCode::InvalidArgument.get_error_code();
+        assert_eq!(DPE_STATUS_INVALID_ARGUMENT, invalid_argument);
+        assert_eq!(
+            DPE_STATUS_INTERNAL_ERROR,
+            DpeErrorCode::InternalError.get_error_code()
+        );
+        assert_eq!(DPE_STATUS_OK, DpeErrorCode::NoError.get_error_code());
+        assert_eq!(invalid_argument, unsafe {
+            dpe_new(core::ptr::null(), &PLATFORM_CALLBACKS, 0)
+        });
+        assert_eq!(invalid_argument, unsafe {
+            dpe_new(&CRYPTO_CALLBACKS, core::ptr::null(), 0)
+        });
+
+        let cmd = [0u8; 12];
+        let mut resp = vec![0u8; dpe_max_response_size()];
+        let mut resp_len = 0;
+        assert_eq!(invalid_argument, unsafe {
+            dpe_execute_command(
+                0,
+                core::ptr::null(),
+                0,
+                resp.as_mut_ptr(),
+                resp.len(),
+                &mut resp_len,
+            )
+        });
+
+        // Commands fail until an instance has been created.
+        unsafe { *addr_of_mut!(STATE) = None };
+        assert_eq!(DpeErrorCode::InternalError.get_error_code(), unsafe {
+            dpe_execute_command(
+                0,
+                cmd.as_ptr(),
+                cmd.len(),
+                resp.as_mut_ptr(),
+                resp.len(),
+                &mut resp_len,
+            )
+        });
+    }
+}
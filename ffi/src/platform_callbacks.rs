@@ -0,0 +1,621 @@
+// Licensed under the Apache-2.0 license
+
+//! Platform callbacks supplied by the embedding firmware.
+//!
+//! Callbacks that return a status use 0 for success. Any other value is
+//! reported through the `PlatformError` variant for that callback. Lengths
+//! written back by a callback are checked against the buffer it was given.
+
+use core::{cmp::min, ffi::c_void};
+use platform::{
+    ArrayVec, CertValidity, Fwid, LocalityDescriptor, Platform, PlatformError, SignerIdentifier,
+    CDI_WRAPPING_KEY_SIZE, COMMAND_AUTH_KEY_SIZE, MAX_ADDITIONAL_FWIDS, MAX_CHUNK_SIZE,
+    MAX_FWID_DIGEST_SIZE, MAX_ISSUER_NAME_SIZE, MAX_KEY_IDENTIFIER_SIZE, MAX_LOCALITIES,
+    MAX_SN_SIZE, MAX_UEID_SIZE, MAX_VALIDITY_SIZE,
+};
+
+/// Reads a chunk of a DER blob such as the certificate chain.
+pub type DpeChunkCallback = unsafe extern "C" fn(
+    ctx: *mut c_void,
+    offset: u32,
+    size: u32,
+    out: *mut u8,
+    out_len: *mut u32,
+) -> u32;
+
+/// Writes a variable length value into `out`, which holds `cap` bytes.
+pub type DpeBufferCallback =
+    unsafe extern "C" fn(ctx: *mut c_void, out: *mut u8, cap: usize, out_len: *mut usize) -> u32;
+
+/// Flag letting a locality own the auto-initialized default context.
+pub const DPE_LOCALITY_AUTO_INIT: u32 = 1 << 0;
+/// Flag letting a locality create simulation contexts.
+pub const DPE_LOCALITY_SIMULATION: u32 = 1 << 1;
+
+const _: () = assert!(
+    DPE_LOCALITY_AUTO_INIT == LocalityDescriptor::AUTO_INIT
+        && DPE_LOCALITY_SIMULATION == LocalityDescriptor::SIMULATION
+);
+
+/// FWID digest computed with SHA-256.
+pub const DPE_FWID_SHA256: u32 = 1;
+/// FWID digest computed with SHA-384.
+pub const DPE_FWID_SHA384: u32 = 2;
+/// FWID digest computed with SHA-512.
+pub const DPE_FWID_SHA512: u32 = 3;
+/// Size of the digest buffer of a FWID.
+pub const DPE_FWID_DIGEST_SIZE: usize = 64;
+
+const _: () = assert!(
+    DPE_FWID_SHA256 == Fwid::SHA256
+        && DPE_FWID_SHA384 == Fwid::SHA384
+        && DPE_FWID_SHA512 == Fwid::SHA512
+        && DPE_FWID_DIGEST_SIZE == MAX_FWID_DIGEST_SIZE
+);
+
+/// Locality that may send commands, with a combination of the
+/// `DPE_LOCALITY_*` flags.
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct DpeLocalityDescriptor {
+    pub locality: u32,
+    pub flags: u32,
+}
+
+/// FWID added to leaf certificates. `hash_alg` is one of the
+/// `DPE_FWID_*` algorithms and `digest` is left-aligned.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct DpeFwid {
+    pub hash_alg: u32,
+    pub digest: [u8; DPE_FWID_DIGEST_SIZE],
+}
+
+impl Default for DpeFwid {
+    fn default() -> Self {
+        DpeFwid {
+            hash_alg: 0,
+            digest: [0; DPE_FWID_DIGEST_SIZE],
+        }
+    }
+}
+
+/// Table of platform callbacks. Copied by `dpe_new`.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct DpePlatformCallbacks {
+    /// Passed unchanged as the first argument of every callback.
+    pub ctx: *mut c_void,
+    pub vendor_id: u32,
+    pub vendor_sku: u32,
+    /// Locality that owns the auto-initialized default context.
+    pub auto_init_locality: u32,
+    /// Copies up to `size` bytes of the DER certificate chain, starting at
+    /// `offset`, into `out` and stores the number copied in `out_len`.
+    /// Copying fewer than `size` bytes marks the end of the chain.
+    pub get_certificate_chain: Option<DpeChunkCallback>,
+    /// Same as `get_certificate_chain`, for the measurements mixed into
+    /// contexts derived with INTERNAL_INPUT_DICE. Optional; the certificate
+    /// chain is used when NULL.
+    pub get_dice_measurement: Option<DpeChunkCallback>,
+    /// Writes the DER issuer name of the DPE's certificates.
+    pub get_issuer_name: Option<DpeBufferCallback>,
+    /// Writes the serial number of the issuer's certificate. Used for the
+    /// CSR signer identifier when `get_signer_key_identifier` is NULL.
+    /// Optional.
+    pub get_issuer_serial_number: Option<DpeBufferCallback>,
+    /// Writes the 20-byte key identifier naming the CSR signer. Optional.
+    pub get_signer_key_identifier:
+        Option<unsafe extern "C" fn(ctx: *mut c_void, out: *mut u8) -> u32>,
+    /// Writes the 20-byte key identifier of the issuer's key.
+    pub get_issuer_key_identifier:
+        Option<unsafe extern "C" fn(ctx: *mut c_void, out: *mut u8) -> u32>,
+    /// Writes the device's UEID.
+    pub get_ueid: Option<DpeBufferCallback>,
+    /// Writes the notBefore and notAfter times of issued certificates as
+    /// GeneralizedTime strings such as "99991231235959Z". Each buffer holds
+    /// 24 bytes.
+    pub get_cert_validity: Option<
+        unsafe extern "C" fn(
+            ctx: *mut c_void,
+            not_before: *mut u8,
+            not_before_len: *mut usize,
+            not_after: *mut u8,
+            not_after_len: *mut usize,
+        ) -> u32,
+    >,
+    /// Prints `len` bytes of UTF-8 debug output. Optional; output is dropped
+    /// when NULL.
+    pub write_str: Option<unsafe extern "C" fn(ctx: *mut c_void, str: *const u8, len: usize)>,
+    /// Writes the 32-byte key exported CDIs are wrapped under. Optional.
+    pub get_cdi_wrapping_key: Option<unsafe extern "C" fn(ctx: *mut c_void, out: *mut u8) -> u32>,
+    /// Decides whether `locality` may run command `cmd_id`. Optional; every
+    /// command is allowed when NULL.
+    pub authorize:
+        Option<unsafe extern "C" fn(ctx: *mut c_void, cmd_id: u32, locality: u32) -> bool>,
+    /// Decides whether a context of type `child_type` may be derived from one
+    /// of type `parent_type`. Optional; every derivation is allowed when NULL.
+    pub validate_tci_type:
+        Option<unsafe extern "C" fn(ctx: *mut c_void, parent_type: u32, child_type: u32) -> bool>,
+    /// Writes the `len`-byte measurement the auto-initialized default context
+    /// is extended with and stores its TCI type in `tci_type`. Optional; the
+    /// default context is left unmeasured when NULL.
+    pub get_boot_measurement: Option<
+        unsafe extern "C" fn(ctx: *mut c_void, out: *mut u8, len: usize, tci_type: *mut u32) -> u32,
+    >,
+    /// Writes the 32-byte key commands from `locality` are authenticated
+    /// with. Optional; authenticated commands are rejected when NULL.
+    pub get_command_auth_key:
+        Option<unsafe extern "C" fn(ctx: *mut c_void, locality: u32, out: *mut u8) -> u32>,
+    /// Writes the descriptors of the localities that may send commands to
+    /// `out`, which holds `cap` of them, and stores how many in `count`.
+    /// Optional; every locality may send commands when NULL.
+    pub get_locality_table: Option<
+        unsafe extern "C" fn(
+            ctx: *mut c_void,
+            out: *mut DpeLocalityDescriptor,
+            cap: usize,
+            count: *mut usize,
+        ) -> u32,
+    >,
+    /// Writes the FWIDs added to leaf certificates to `out`, which holds
+    /// `cap` of them, and stores how many in `count`. Optional; no FWIDs are
+    /// added when NULL.
+    pub get_additional_fwids: Option<
+        unsafe extern "C" fn(
+            ctx: *mut c_void,
+            out: *mut DpeFwid,
+            cap: usize,
+            count: *mut usize,
+        ) -> u32,
+    >,
+}
+
+fn required<T>(callback: Option<T>) -> Result<T, PlatformError> {
+    callback.ok_or(PlatformError::NotImplemented)
+}
+
+fn check(status: u32, err: fn(u32) -> PlatformError) -> Result<(), PlatformError> {
+    if status == 0 {
+        Ok(())
+    } else {
+        Err(err(status))
+    }
+}
+
+/// Calls a `DpeBufferCallback` for `out` and returns the length it wrote.
+fn read_buffer(
+    ctx: *mut c_void,
+    callback: Option<DpeBufferCallback>,
+    out: &mut [u8],
+    err: fn(u32) -> PlatformError,
+) -> Result<usize, PlatformError> {
+    let callback = required(callback)?;
+    let mut len = 0;
+    // SAFETY: `out` is valid for `out.len()` bytes.
+    check(
+        unsafe { callback(ctx, out.as_mut_ptr(), out.len(), &mut len) },
+        err,
+    )?;
+    if len > out.len() {
+        return Err(err(0));
+    }
+    Ok(len)
+}
+
+/// `Platform` implementation that forwards to a `DpePlatformCallbacks` table.
+pub struct FfiPlatform(pub DpePlatformCallbacks);
+
+impl FfiPlatform {
+    fn read_chunk(
+        &mut self,
+        callback: DpeChunkCallback,
+        offset: u32,
+        size: u32,
+        out: &mut [u8; MAX_CHUNK_SIZE],
+    ) -> Result<u32, PlatformError> {
+        let mut len = 0;
+        let size = min(size, MAX_CHUNK_SIZE as u32);
+        // SAFETY: `out` holds MAX_CHUNK_SIZE bytes and `size` is clamped to it.
+        let status = unsafe { callback(self.0.ctx, offset, size, out.as_mut_ptr(), &mut len) };
+        if status != 0 || len > size {
+            return Err(PlatformError::CertificateChainError);
+        }
+        Ok(len)
+    }
+}
+
+impl Platform for FfiPlatform {
+    fn get_certificate_chain(
+        &mut self,
+        offset: u32,
+        size: u32,
+        out: &mut [u8; MAX_CHUNK_SIZE],
+    ) -> Result<u32, PlatformError> {
+        let callback = required(self.0.get_certificate_chain)?;
+        self.read_chunk(callback, offset, size, out)
+    }
+
+    fn get_dice_measurement(
+        &mut self,
+        offset: u32,
+        size: u32,
+        out: &mut [u8; MAX_CHUNK_SIZE],
+    ) -> Result<u32, PlatformError> {
+        let callback = self.0.get_dice_measurement.or(self.0.get_certificate_chain);
+        self.read_chunk(required(callback)?, offset, size, out)
+    }
+
+    fn get_issuer_name(
+        &mut self,
+        out: &mut [u8; MAX_ISSUER_NAME_SIZE],
+    ) -> Result<usize, PlatformError> {
+        read_buffer(
+            self.0.ctx,
+            self.0.get_issuer_name,
+            out,
+            PlatformError::IssuerNameError,
+        )
+    }
+
+    fn get_signer_identifier(&mut self) -> Result<SignerIdentifier, PlatformError> {
+        if let Some(get_signer_key_identifier) = self.0.get_signer_key_identifier {
+            let mut ski = [0u8; MAX_KEY_IDENTIFIER_SIZE];
+            // SAFETY: `ski` holds MAX_KEY_IDENTIFIER_SIZE bytes.
+            check(
+                unsafe { get_signer_key_identifier(self.0.ctx, ski.as_mut_ptr()) },
+                PlatformError::SubjectKeyIdentifierError,
+            )?;
+            return Ok(SignerIdentifier::SubjectKeyIdentifier(ArrayVec::from(ski)));
+        }
+
+        let mut name = [0u8; MAX_ISSUER_NAME_SIZE];
+        let name_len = self.get_issuer_name(&mut name)?;
+        let mut serial = [0u8; MAX_SN_SIZE];
+        let serial_len = read_buffer(
+            self.0.ctx,
+            self.0.get_issuer_serial_number,
+            &mut serial,
+            PlatformError::SerialNumberError,
+        )?;
+
+        let mut issuer_name = ArrayVec::new();
+        issuer_name
+            .try_extend_from_slice(&name[..name_len])
+            .map_err(|_| PlatformError::IssuerNameError(0))?;
+        let mut serial_number = ArrayVec::new();
+        serial_number
+            .try_extend_from_slice(&serial[..serial_len])
+            .map_err(|_| PlatformError::SerialNumberError(0))?;
+        Ok(SignerIdentifier::IssuerAndSerialNumber {
+            issuer_name,
+            serial_number,
+        })
+    }
+
+    fn get_issuer_key_identifier(
+        &mut self,
+        out: &mut [u8; MAX_KEY_IDENTIFIER_SIZE],
+    ) -> Result<(), PlatformError> {
+        let get_issuer_key_identifier = required(self.0.get_issuer_key_identifier)?;
+        // SAFETY: `out` holds MAX_KEY_IDENTIFIER_SIZE bytes.
+        check(
+            unsafe { get_issuer_key_identifier(self.0.ctx, out.as_mut_ptr()) },
+            PlatformError::IssuerKeyIdentifierError,
+        )
+    }
+
+    fn get_ueid(&mut self, out: &mut [u8; MAX_UEID_SIZE]) -> Result<usize, PlatformError> {
+        read_buffer(self.0.ctx, self.0.get_ueid, out, PlatformError::UeidError)
+    }
+
+    fn get_vendor_id(&mut self) -> Result<u32, PlatformError> {
+        Ok(self.0.vendor_id)
+    }
+
+    fn get_vendor_sku(&mut self) -> Result<u32, PlatformError> {
+        Ok(self.0.vendor_sku)
+    }
+
+    fn get_auto_init_locality(&mut self) -> Result<u32, PlatformError> {
+        Ok(self.0.auto_init_locality)
+    }
+
+    fn write_str(&mut self, str: &str) -> Result<(), PlatformError> {
+        if let Some(write_str) = self.0.write_str {
+            // SAFETY: `str` is valid for `str.len()` bytes.
+            unsafe { write_str(self.0.ctx, str.as_ptr(), str.len()) }
+        }
+        Ok(())
+    }
+
+    fn get_cert_validity(&mut self) -> Result<CertValidity, PlatformError> {
+        let get_cert_validity = required(self.0.get_cert_validity)?;
+        let mut not_before = [0u8; MAX_VALIDITY_SIZE];
+        let mut not_after = [0u8; MAX_VALIDITY_SIZE];
+        let mut not_before_len = 0;
+        let mut not_after_len = 0;
+        // SAFETY: Both buffers hold MAX_VALIDITY_SIZE bytes.
+        check(
+            unsafe {
+                get_cert_validity(
+                    self.0.ctx,
+                    not_before.as_mut_ptr(),
+                    &mut not_before_len,
+                    not_after.as_mut_ptr(),
+                    &mut not_after_len,
+                )
+            },
+            PlatformError::CertValidityError,
+        )?;
+
+        if not_before_len > MAX_VALIDITY_SIZE || not_after_len > MAX_VALIDITY_SIZE {
+            return Err(PlatformError::CertValidityError(0));
+        }
+
+        let mut validity = CertValidity {
+            not_before: ArrayVec::new(),
+            not_after: ArrayVec::new(),
+        };
+        validity
+            .not_before
+            .try_extend_from_slice(&not_before[..not_before_len])
+            .map_err(|_| PlatformError::CertValidityError(0))?;
+        validity
+            .not_after
+            .try_extend_from_slice(&not_after[..not_after_len])
+            .map_err(|_| PlatformError::CertValidityError(0))?;
+        Ok(validity)
+    }
+
+    fn get_cdi_wrapping_key(
+        &mut self,
+        out: &mut [u8; CDI_WRAPPING_KEY_SIZE],
+    ) -> Result<(), PlatformError> {
+        let get_cdi_wrapping_key = required(self.0.get_cdi_wrapping_key)?;
+        // SAFETY: `out` holds CDI_WRAPPING_KEY_SIZE bytes.
+        let status = unsafe { get_cdi_wrapping_key(self.0.ctx, out.as_mut_ptr()) };
+        // PlatformError has no variant for key retrieval, so a failure reads
+        // the same as a platform without a wrapping key.
+        check(status, |_| PlatformError::NotImplemented)
+    }
+
+    fn authorize(&mut self, cmd_id: u32, locality: u32) -> bool {
+        match self.0.authorize {
+            // SAFETY: The callback only receives integers.
+            Some(authorize) => unsafe { authorize(self.0.ctx, cmd_id, locality) },
+            None => true,
+        }
+    }
+
+    fn validate_tci_type(&mut self, parent_type: u32, child_type: u32) -> bool {
+        match self.0.validate_tci_type {
+            // SAFETY: The callback only receives integers.
+            Some(validate_tci_type) => unsafe {
+                validate_tci_type(self.0.ctx, parent_type, child_type)
+            },
+            None => true,
+        }
+    }
+
+    fn get_boot_measurement(&mut self, out: &mut [u8]) -> Result<u32, PlatformError> {
+        let get_boot_measurement = required(self.0.get_boot_measurement)?;
+        let mut tci_type = 0;
+        // SAFETY: `out` is valid for `out.len()` bytes.
+        let status =
+            unsafe { get_boot_measurement(self.0.ctx, out.as_mut_ptr(), out.len(), &mut tci_type) };
+        check(status, PlatformError::BootMeasurementError)?;
+        Ok(tci_type)
+    }
+
+    fn get_command_auth_key(
+        &mut self,
+        locality: u32,
+        out: &mut [u8; COMMAND_AUTH_KEY_SIZE],
+    ) -> Result<(), PlatformError> {
+        let get_command_auth_key = required(self.0.get_command_auth_key)?;
+        // SAFETY: `out` holds COMMAND_AUTH_KEY_SIZE bytes.
+        let status = unsafe { get_command_auth_key(self.0.ctx, locality, out.as_mut_ptr()) };
+        // As with the CDI wrapping key, a failure reads the same as a
+        // platform without the key.
+        check(status, |_| PlatformError::NotImplemented)
+    }
+
+    fn get_locality_table(
+        &mut self,
+        out: &mut [LocalityDescriptor; MAX_LOCALITIES],
+    ) -> Result<usize, PlatformError> {
+        let get_locality_table = required(self.0.get_locality_table)?;
+        let mut table = [DpeLocalityDescriptor::default(); MAX_LOCALITIES];
+        let mut count = 0;
+        // SAFETY: `table` holds MAX_LOCALITIES descriptors.
+        let status =
+            unsafe { get_locality_table(self.0.ctx, table.as_mut_ptr(), table.len(), &mut count) };
+        check(status, PlatformError::LocalityTableError)?;
+        let table = table
+            .get(..count)
+            .ok_or(PlatformError::LocalityTableError(0))?;
+        for (out, entry) in out.iter_mut().zip(table) {
+            *out = LocalityDescriptor {
+                locality: entry.locality,
+                flags: entry.flags,
+            };
+        }
+        Ok(count)
+    }
+
+    fn get_additional_fwids(
+        &mut self,
+        out: &mut [Fwid; MAX_ADDITIONAL_FWIDS],
+    ) -> Result<usize, PlatformError> {
+        let get_additional_fwids = required(self.0.get_additional_fwids)?;
+        let mut fwids = [DpeFwid::default(); MAX_ADDITIONAL_FWIDS];
+        let mut count = 0;
+        // SAFETY: `fwids` holds MAX_ADDITIONAL_FWIDS FWIDs.
+        let status = unsafe {
+            get_additional_fwids(self.0.ctx, fwids.as_mut_ptr(), fwids.len(), &mut count)
+        };
+        check(status, PlatformError::AdditionalFwidError)?;
+        let fwids = fwids
+            .get(..count)
+            .ok_or(PlatformError::AdditionalFwidError(0))?;
+        for (out, fwid) in out.iter_mut().zip(fwids) {
+            *out = Fwid {
+                hash_alg: fwid.hash_alg,
+                digest: fwid.digest,
+            };
+        }
+        Ok(count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::ptr::null_mut;
+
+    const NO_CALLBACKS: DpePlatformCallbacks = DpePlatformCallbacks {
+        ctx: null_mut(),
+        vendor_id: 0x1234,
+        vendor_sku: 0x5678,
+        auto_init_locality: 0,
+        get_certificate_chain: None,
+        get_dice_measurement: None,
+        get_issuer_name: None,
+        get_issuer_serial_number: None,
+        get_signer_key_identifier: None,
+        get_issuer_key_identifier: None,
+        get_ueid: None,
+        get_cert_validity: None,
+        write_str: None,
+        get_cdi_wrapping_key: None,
+        authorize: None,
+        validate_tci_type: None,
+        get_boot_measurement: None,
+        get_command_auth_key: None,
+        get_locality_table: None,
+        get_additional_fwids: None,
+    };
+
+    unsafe extern "C" fn overlong_name(
+        _ctx: *mut c_void,
+        _out: *mut u8,
+        cap: usize,
+        out_len: *mut usize,
+    ) -> u32 {
+        *out_len = cap + 1;
+        0
+    }
+
+    unsafe extern "C" fn failing_ueid(
+        _ctx: *mut c_void,
+        _out: *mut u8,
+        _cap: usize,
+        _out_len: *mut usize,
+    ) -> u32 {
+        3
+    }
+
+    unsafe extern "C" fn overlong_chunk(
+        _ctx: *mut c_void,
+        _offset: u32,
+        size: u32,
+        _out: *mut u8,
+        out_len: *mut u32,
+    ) -> u32 {
+        *out_len = size + 1;
+        0
+    }
+
+    unsafe extern "C" fn overlong_locality_table(
+        _ctx: *mut c_void,
+        _out: *mut DpeLocalityDescriptor,
+        cap: usize,
+        count: *mut usize,
+    ) -> u32 {
+        *count = cap + 1;
+        0
+    }
+
+    unsafe extern "C" fn overlong_fwids(
+        _ctx: *mut c_void,
+        _out: *mut DpeFwid,
+        cap: usize,
+        count: *mut usize,
+    ) -> u32 {
+        *count = cap + 1;
+        0
+    }
+
+    #[test]
+    fn test_callback_results_are_checked() {
+        let mut platform = FfiPlatform(DpePlatformCallbacks {
+            get_issuer_name: Some(overlong_name),
+            get_ueid: Some(failing_ueid),
+            get_certificate_chain: Some(overlong_chunk),
+            get_locality_table: Some(overlong_locality_table),
+            get_additional_fwids: Some(overlong_fwids),
+            ..NO_CALLBACKS
+        });
+
+        assert_eq!(
+            Err(PlatformError::IssuerNameError(0)),
+            platform.get_issuer_name(&mut [0; MAX_ISSUER_NAME_SIZE])
+        );
+        assert_eq!(
+            Err(PlatformError::UeidError(3)),
+            platform.get_ueid(&mut [0; MAX_UEID_SIZE])
+        );
+        assert_eq!(
+            Err(PlatformError::CertificateChainError),
+            platform.get_certificate_chain(0, 16, &mut [0; MAX_CHUNK_SIZE])
+        );
+        // The DICE measurement falls back to the certificate chain.
+        assert_eq!(
+            Err(PlatformError::CertificateChainError),
+            platform.get_dice_measurement(0, 16, &mut [0; MAX_CHUNK_SIZE])
+        );
+        assert_eq!(
+            Err(PlatformError::LocalityTableError(0)),
+            platform.get_locality_table(&mut [LocalityDescriptor::default(); MAX_LOCALITIES])
+        );
+        assert_eq!(
+            Err(PlatformError::AdditionalFwidError(0)),
+            platform.get_additional_fwids(&mut [Fwid::default(); MAX_ADDITIONAL_FWIDS])
+        );
+    }
+
+    #[test]
+    fn test_missing_callbacks() {
+        let mut platform = FfiPlatform(NO_CALLBACKS);
+        assert_eq!(Ok(0x1234), platform.get_vendor_id());
+        assert_eq!(Ok(0x5678), platform.get_vendor_sku());
+        assert_eq!(Ok(()), platform.write_str("dropped"));
+        assert!(platform.authorize(0, 0));
+        assert!(platform.validate_tci_type(0, 0));
+        assert_eq!(
+            Err(PlatformError::NotImplemented),
+            platform.get_signer_identifier()
+        );
+        assert_eq!(
+            Err(PlatformError::NotImplemented),
+            platform.get_cert_validity()
+        );
+        assert_eq!(
+            Err(PlatformError::NotImplemented),
+            platform.get_boot_measurement(&mut [0; 32])
+        );
+        assert_eq!(
+            Err(PlatformError::NotImplemented),
+            platform.get_command_auth_key(0, &mut [0; COMMAND_AUTH_KEY_SIZE])
+        );
+        assert_eq!(
+            Err(PlatformError::NotImplemented),
+            platform.get_locality_table(&mut [LocalityDescriptor::default(); MAX_LOCALITIES])
+        );
+        assert_eq!(
+            Err(PlatformError::NotImplemented),
+            platform.get_additional_fwids(&mut [Fwid::default(); MAX_ADDITIONAL_FWIDS])
+        );
+    }
+}
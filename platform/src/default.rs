@@ -1,8 +1,9 @@
 // Licensed under the Apache-2.0 license
 
 use crate::{
-    CertValidity, Platform, PlatformError, SignerIdentifier, MAX_CHUNK_SIZE, MAX_ISSUER_NAME_SIZE,
-    MAX_KEY_IDENTIFIER_SIZE,
+    CertValidity, Platform, PlatformError, SignerIdentifier, CDI_WRAPPING_KEY_SIZE,
+    COMMAND_AUTH_KEY_SIZE, MAX_CHUNK_SIZE, MAX_ISSUER_NAME_SIZE, MAX_KEY_IDENTIFIER_SIZE,
+    MAX_UEID_SIZE, STATE_PROTECTION_KEY_SIZE,
 };
 use arrayvec::ArrayVec;
 use cfg_if::cfg_if;
@@ -15,6 +16,27 @@ pub const VENDOR_ID: u32 = 0;
 pub const VENDOR_SKU: u32 = 0;
 pub const NOT_BEFORE: &str = "20230227000000Z";
 pub const NOT_AFTER: &str = "99991231235959Z";
+/// Key the simulator wraps exported CDIs under. Real platforms must keep
+/// this key secret.
+pub const TEST_CDI_WRAPPING_KEY: [u8; CDI_WRAPPING_KEY_SIZE] = [0xC0; CDI_WRAPPING_KEY_SIZE];
+/// Key the simulator protects its persisted state with. Real platforms must
+/// keep this key secret.
+pub const TEST_STATE_PROTECTION_KEY: [u8; STATE_PROTECTION_KEY_SIZE] =
+    [0x5E; STATE_PROTECTION_KEY_SIZE];
+
+/// Key the simulator authenticates commands from `locality` with: 0xA5 bytes
+/// following the little-endian locality. Real platforms must keep these keys
+/// secret.
+pub fn test_command_auth_key(locality: u32) -> [u8; COMMAND_AUTH_KEY_SIZE] {
+    let mut key = [0xA5; COMMAND_AUTH_KEY_SIZE];
+    key[..4].copy_from_slice(&locality.to_le_bytes());
+    key
+}
+// EAT UEID of type RAND (0x01) followed by 16 bytes
+pub const UEID: [u8; 17] = [
+    0x01, 0x44, 0x50, 0x45, 0x2d, 0x53, 0x49, 0x4d, 0x55, 0x4c, 0x41, 0x54, 0x4f, 0x52, 0x00, 0x00,
+    0x01,
+];
 
 // Run ./generate.sh to generate all test certs and test private keys
 #[cfg(feature = "dpe_profile_p256_sha256")]
@@ -23,12 +45,18 @@ pub const TEST_CERT_CHAIN: &[u8] = include_bytes!("test_data/cert_256.der");
 #[cfg(feature = "dpe_profile_p384_sha384")]
 pub const TEST_CERT_CHAIN: &[u8] = include_bytes!("test_data/cert_384.der");
 
+#[cfg(feature = "dpe_profile_p521_sha512")]
+pub const TEST_CERT_CHAIN: &[u8] = include_bytes!("test_data/cert_521.der");
+
 #[cfg(feature = "dpe_profile_p256_sha256")]
 pub const TEST_CERT_PEM: &[u8] = include_bytes!("test_data/cert_256.pem");
 
 #[cfg(feature = "dpe_profile_p384_sha384")]
 pub const TEST_CERT_PEM: &[u8] = include_bytes!("test_data/cert_384.pem");
 
+#[cfg(feature = "dpe_profile_p521_sha512")]
+pub const TEST_CERT_PEM: &[u8] = include_bytes!("test_data/cert_521.pem");
+
 cfg_if! {
     if #[cfg(feature = "openssl")] {
         mod parse {
@@ -166,6 +194,14 @@ impl Platform for DefaultPlatform {
         Ok(())
     }
 
+    fn get_ueid(&mut self, out: &mut [u8; MAX_UEID_SIZE]) -> Result<usize, PlatformError> {
+        if UEID.len() > out.len() {
+            return Err(PlatformError::UeidError(0));
+        }
+        out[..UEID.len()].copy_from_slice(&UEID);
+        Ok(UEID.len())
+    }
+
     fn get_vendor_id(&mut self) -> Result<u32, PlatformError> {
         Ok(VENDOR_ID)
     }
@@ -197,4 +233,29 @@ impl Platform for DefaultPlatform {
             not_after: not_after_vec,
         })
     }
+
+    fn get_cdi_wrapping_key(
+        &mut self,
+        out: &mut [u8; CDI_WRAPPING_KEY_SIZE],
+    ) -> Result<(), PlatformError> {
+        out.copy_from_slice(&TEST_CDI_WRAPPING_KEY);
+        Ok(())
+    }
+
+    fn get_command_auth_key(
+        &mut self,
+        locality: u32,
+        out: &mut [u8; COMMAND_AUTH_KEY_SIZE],
+    ) -> Result<(), PlatformError> {
+        *out = test_command_auth_key(locality);
+        Ok(())
+    }
+
+    fn get_state_protection_key(
+        &mut self,
+        out: &mut [u8; STATE_PROTECTION_KEY_SIZE],
+    ) -> Result<(), PlatformError> {
+        out.copy_from_slice(&TEST_STATE_PROTECTION_KEY);
+        Ok(())
+    }
 }
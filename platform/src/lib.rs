@@ -13,6 +13,7 @@ pub use arrayvec::ArrayVec;
 #[cfg(any(feature = "openssl", feature = "rustcrypto"))]
 pub mod default;
 
+pub mod mctp;
 pub mod printer;
 
 pub const MAX_CHUNK_SIZE: usize = 2048;
@@ -20,6 +21,14 @@ pub const MAX_ISSUER_NAME_SIZE: usize = 128;
 pub const MAX_SN_SIZE: usize = 20;
 pub const MAX_KEY_IDENTIFIER_SIZE: usize = 20;
 pub const MAX_VALIDITY_SIZE: usize = 24;
+pub const MAX_UEID_SIZE: usize = 33;
+pub const CDI_WRAPPING_KEY_SIZE: usize = 32;
+pub const COMMAND_AUTH_KEY_SIZE: usize = 32;
+pub const STATE_PROTECTION_KEY_SIZE: usize = 32;
+pub const MAX_LOCALITIES: usize = 64;
+pub const MAX_ADDITIONAL_FWIDS: usize = 4;
+pub const MAX_FWID_DIGEST_SIZE: usize = 64;
+pub const MAX_SUBJECT_ATTR_SIZE: usize = 64;
 
 #[derive(Debug, PartialEq, Eq)]
 pub enum SignerIdentifier {
@@ -36,6 +45,78 @@ pub struct CertValidity {
     pub not_after: ArrayVec<u8, { MAX_VALIDITY_SIZE }>,
 }
 
+/// Subject name attributes the platform gives a leaf certificate in place of
+/// the ones DPE derives from the certified context.
+///
+/// Both must be non-empty UTF-8.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct SubjectName {
+    /// commonName
+    pub cn: ArrayVec<u8, { MAX_SUBJECT_ATTR_SIZE }>,
+    /// serialNumber
+    pub serial: ArrayVec<u8, { MAX_SUBJECT_ATTR_SIZE }>,
+}
+
+/// Describes a locality that may send commands to the DPE.
+#[repr(C)]
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub struct LocalityDescriptor {
+    pub locality: u32,
+    /// Combination of `LocalityDescriptor::AUTO_INIT` and
+    /// `LocalityDescriptor::SIMULATION`.
+    pub flags: u32,
+}
+
+impl LocalityDescriptor {
+    /// The locality may own the auto-initialized default context.
+    pub const AUTO_INIT: u32 = 1 << 0;
+    /// The locality may create simulation contexts.
+    pub const SIMULATION: u32 = 1 << 1;
+}
+
+/// A firmware identifier the platform adds to the certified TcbInfo, such
+/// as a digest of its configuration.
+#[repr(C)]
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Fwid {
+    /// One of `Fwid::SHA256`, `Fwid::SHA384` or `Fwid::SHA512`. It may differ
+    /// from the hash algorithm of the DPE profile.
+    pub hash_alg: u32,
+    /// The digest, left-aligned. Only the first `digest_size()` bytes are
+    /// used.
+    pub digest: [u8; MAX_FWID_DIGEST_SIZE],
+}
+
+impl Fwid {
+    pub const SHA256: u32 = 1;
+    pub const SHA384: u32 = 2;
+    pub const SHA512: u32 = 3;
+
+    /// Returns the digest size of `hash_alg`, or `None` if it is unknown.
+    pub fn digest_size(&self) -> Option<usize> {
+        match self.hash_alg {
+            Self::SHA256 => Some(32),
+            Self::SHA384 => Some(48),
+            Self::SHA512 => Some(64),
+            _ => None,
+        }
+    }
+
+    /// Returns the digest, or `None` if `hash_alg` is unknown.
+    pub fn digest(&self) -> Option<&[u8]> {
+        self.digest.get(..self.digest_size()?)
+    }
+}
+
+impl Default for Fwid {
+    fn default() -> Self {
+        Fwid {
+            hash_alg: 0,
+            digest: [0; MAX_FWID_DIGEST_SIZE],
+        }
+    }
+}
+
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
 #[repr(u16)]
 pub enum PlatformError {
@@ -47,6 +128,11 @@ pub enum PlatformError {
     SubjectKeyIdentifierError(u32) = 0x6,
     CertValidityError(u32) = 0x7,
     IssuerKeyIdentifierError(u32) = 0x8,
+    UeidError(u32) = 0x9,
+    BootMeasurementError(u32) = 0xA,
+    LocalityTableError(u32) = 0xB,
+    AdditionalFwidError(u32) = 0xC,
+    SubjectNameError(u32) = 0xD,
 }
 
 impl PlatformError {
@@ -67,6 +153,11 @@ impl PlatformError {
             PlatformError::SubjectKeyIdentifierError(code) => Some(*code),
             PlatformError::CertValidityError(code) => Some(*code),
             PlatformError::IssuerKeyIdentifierError(code) => Some(*code),
+            PlatformError::UeidError(code) => Some(*code),
+            PlatformError::BootMeasurementError(code) => Some(*code),
+            PlatformError::LocalityTableError(code) => Some(*code),
+            PlatformError::AdditionalFwidError(code) => Some(*code),
+            PlatformError::SubjectNameError(code) => Some(*code),
         }
     }
 }
@@ -86,6 +177,27 @@ pub trait Platform {
         out: &mut [u8; MAX_CHUNK_SIZE],
     ) -> Result<u32, PlatformError>;
 
+    /// Retrieves a chunk of the platform's DICE measurements. These are mixed
+    /// into the CDI of contexts derived with `INTERNAL_INPUT_DICE`.
+    ///
+    /// Follows the same chunking rules as `get_certificate_chain`. By default
+    /// the certificate chain is used since it attests to every DICE layer
+    /// below the DPE.
+    ///
+    /// # Arguments
+    ///
+    /// * `offset` - Index where to start reading bytes from in the measurements.
+    /// * `size` - The requested size of the chunk.
+    /// * `out` - Output buffer for the measurement chunk to be written to
+    fn get_dice_measurement(
+        &mut self,
+        offset: u32,
+        size: u32,
+        out: &mut [u8; MAX_CHUNK_SIZE],
+    ) -> Result<u32, PlatformError> {
+        self.get_certificate_chain(offset, size, out)
+    }
+
     /// Retrieves the parent certificate's DER encoded issuer name.
     ///
     /// # Arguments
@@ -110,12 +222,103 @@ pub trait Platform {
         out: &mut [u8; MAX_KEY_IDENTIFIER_SIZE],
     ) -> Result<(), PlatformError>;
 
+    /// Retrieves the device's Universal Entity ID (UEID)
+    ///
+    /// The UEID is encoded into the tcg-dice-Ueid extension of certificates
+    /// produced by CertifyKey.
+    ///
+    /// # Arguments
+    ///
+    /// * `out` - Output buffer for the UEID to be written to.
+    fn get_ueid(&mut self, out: &mut [u8; MAX_UEID_SIZE]) -> Result<usize, PlatformError>;
+
+    /// Get the vendor ID reported by GetProfile and in the organizationName
+    /// of leaf certificate subjects.
     fn get_vendor_id(&mut self) -> Result<u32, PlatformError>;
 
+    /// Get the vendor SKU reported by GetProfile and in the
+    /// organizationalUnitName of leaf certificate subjects.
     fn get_vendor_sku(&mut self) -> Result<u32, PlatformError>;
 
     fn get_auto_init_locality(&mut self) -> Result<u32, PlatformError>;
 
+    /// Retrieves the measurement of the firmware the DPE was booted for.
+    ///
+    /// When the DPE auto-initializes, the default context is extended with
+    /// this measurement as soon as it is created, so ROM does not need to
+    /// send InitializeContext or DeriveContext itself. The default returns
+    /// `PlatformError::NotImplemented`, which leaves the default context
+    /// unmeasured.
+    ///
+    /// Returns the TCI type of the measured firmware.
+    ///
+    /// # Arguments
+    ///
+    /// * `out` - Output buffer for the measurement. It is as long as a digest of the DPE profile.
+    fn get_boot_measurement(&mut self, _out: &mut [u8]) -> Result<u32, PlatformError> {
+        Err(PlatformError::NotImplemented)
+    }
+
+    /// Retrieves the table of localities that may send commands.
+    ///
+    /// The DPE reads the table once when it is created and fails commands
+    /// from any other locality with `UnknownLocality`. The flags of each
+    /// entry say whether the locality may own the auto-initialized context
+    /// and whether it may create simulation contexts. The default returns
+    /// `PlatformError::NotImplemented`, which accepts every locality with
+    /// every flag.
+    ///
+    /// Returns the number of descriptors written.
+    ///
+    /// # Arguments
+    ///
+    /// * `out` - Output buffer for the locality descriptors.
+    fn get_locality_table(
+        &mut self,
+        _out: &mut [LocalityDescriptor; MAX_LOCALITIES],
+    ) -> Result<usize, PlatformError> {
+        Err(PlatformError::NotImplemented)
+    }
+
+    /// Retrieves FWIDs the platform adds to leaf certificates, after the
+    /// FWIDs of the certified context in its TcbInfo.
+    ///
+    /// This lets the platform attest to state DPE doesn't measure, such as a
+    /// configuration hash. Each FWID names its own hash algorithm. The
+    /// default returns `PlatformError::NotImplemented`, which adds none.
+    ///
+    /// Returns the number of FWIDs written.
+    ///
+    /// # Arguments
+    ///
+    /// * `out` - Output buffer for the FWIDs.
+    fn get_additional_fwids(
+        &mut self,
+        _out: &mut [Fwid; MAX_ADDITIONAL_FWIDS],
+    ) -> Result<usize, PlatformError> {
+        Err(PlatformError::NotImplemented)
+    }
+
+    /// Names the subject of a leaf certificate issued for a context.
+    ///
+    /// By default the commonName holds the context's TCI type and the
+    /// serialNumber its cumulative TCI, both in hex, so each certificate in a
+    /// chain shows which firmware layer it belongs to. A platform that knows
+    /// the names of its layers can return its own. The default returns
+    /// `PlatformError::NotImplemented`, which keeps the derived name.
+    ///
+    /// # Arguments
+    ///
+    /// * `tci_type` - TCI type of the certified context.
+    /// * `tci_cumulative` - Cumulative TCI of the certified context.
+    fn get_leaf_subject_name(
+        &mut self,
+        _tci_type: u32,
+        _tci_cumulative: &[u8],
+    ) -> Result<SubjectName, PlatformError> {
+        Err(PlatformError::NotImplemented)
+    }
+
     fn write_str(&mut self, str: &str) -> Result<(), PlatformError>;
 
     /// Retrieves the DPE certificate's validity period
@@ -125,4 +328,87 @@ pub trait Platform {
     ///
     /// Example: 99991231235959Z is December 31st, 9999 23:59:59 UTC
     fn get_cert_validity(&mut self) -> Result<CertValidity, PlatformError>;
+
+    /// Retrieves the AES-256-GCM key that CDIs returned by ExportCdi are
+    /// wrapped under.
+    ///
+    /// Only the secure element the CDIs are exported to should be able to
+    /// obtain this key. Platforms that don't export CDIs can rely on the
+    /// default, which returns `PlatformError::NotImplemented`.
+    ///
+    /// # Arguments
+    ///
+    /// * `out` - Output buffer for the wrapping key to be written to.
+    fn get_cdi_wrapping_key(
+        &mut self,
+        _out: &mut [u8; CDI_WRAPPING_KEY_SIZE],
+    ) -> Result<(), PlatformError> {
+        Err(PlatformError::NotImplemented)
+    }
+
+    /// Retrieves the HMAC key that commands from `locality` are authenticated
+    /// with when the DPE supports authenticated commands.
+    ///
+    /// Each key should only be known to the DPE and the software running in
+    /// its locality. The default returns `PlatformError::NotImplemented`,
+    /// which rejects every authenticated command.
+    ///
+    /// # Arguments
+    ///
+    /// * `locality` - Locality that sent the command.
+    /// * `out` - Output buffer for the key to be written to.
+    fn get_command_auth_key(
+        &mut self,
+        _locality: u32,
+        _out: &mut [u8; COMMAND_AUTH_KEY_SIZE],
+    ) -> Result<(), PlatformError> {
+        Err(PlatformError::NotImplemented)
+    }
+
+    /// Retrieves the AES-256-GCM key that state persisted by
+    /// `DpeInstance::serialize` is encrypted and authenticated with.
+    ///
+    /// The key must stay the same across the resets the state is persisted
+    /// over and should only be readable by the DPE. The default returns
+    /// `PlatformError::NotImplemented`, which makes persisting state fail.
+    ///
+    /// # Arguments
+    ///
+    /// * `out` - Output buffer for the key to be written to.
+    fn get_state_protection_key(
+        &mut self,
+        _out: &mut [u8; STATE_PROTECTION_KEY_SIZE],
+    ) -> Result<(), PlatformError> {
+        Err(PlatformError::NotImplemented)
+    }
+
+    /// Decides whether `locality` may run the command with ID `cmd_id`.
+    ///
+    /// The DPE consults this before executing every command and fails it with
+    /// `CommandNotAllowed` when it returns false. The default allows every
+    /// command from every locality.
+    ///
+    /// # Arguments
+    ///
+    /// * `cmd_id` - Command ID from the command header.
+    /// * `locality` - Locality that sent the command.
+    fn authorize(&mut self, _cmd_id: u32, _locality: u32) -> bool {
+        true
+    }
+
+    /// Decides whether a context of type `child_type` may be derived from a
+    /// context of type `parent_type`.
+    ///
+    /// TCI types are defined by the integrator. This lets it enforce its
+    /// layering policy, such as only letting runtime firmware be derived from
+    /// FMC. DeriveContext fails with `TciTypeNotAllowed` when it returns
+    /// false. The default allows every derivation.
+    ///
+    /// # Arguments
+    ///
+    /// * `parent_type` - TCI type of the context being derived from.
+    /// * `child_type` - TCI type requested for the new context.
+    fn validate_tci_type(&mut self, _parent_type: u32, _child_type: u32) -> bool {
+        true
+    }
 }
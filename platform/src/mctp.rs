@@ -0,0 +1,440 @@
+// Licensed under the Apache-2.0 license
+
+//! MCTP binding for DPE commands and responses.
+//!
+//! A DPE message is an MCTP message whose first byte is the message type,
+//! followed by the serialized command or response. Messages longer than the
+//! transmission unit are split into packets, each starting with the DSP0236
+//! transport header:
+//!
+//! ```text
+//! byte 0: reserved (7:4), header version (3:0)
+//! byte 1: destination EID
+//! byte 2: source EID
+//! byte 3: SOM (7), EOM (6), packet sequence (5:4), tag owner (3), tag (2:0)
+//! ```
+//!
+//! The simulator and the client both use this module, so MCTP framing can be
+//! tested end to end without hardware.
+
+use zerocopy::{AsBytes, FromBytes};
+
+/// Transport header version defined by DSP0236.
+pub const MCTP_HDR_VERSION: u8 = 1;
+pub const MCTP_HDR_SIZE: usize = core::mem::size_of::<MctpHdr>();
+/// Smallest transmission unit every MCTP endpoint must accept.
+pub const BASELINE_MTU: usize = 64;
+/// Largest transmission unit `Fragmenter` builds packets for.
+pub const MAX_MTU: usize = 1024;
+pub const MAX_PACKET_SIZE: usize = MCTP_HDR_SIZE + MAX_MTU;
+/// DPE has no assigned MCTP message type. The simulator and client carry it
+/// in this vendor defined type.
+pub const MSG_TYPE_DPE: u8 = 0x7E;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum MctpError {
+    /// The packet is shorter than a header or has an unknown header version.
+    InvalidHeader,
+    /// A packet that doesn't start a message arrived without a matching
+    /// message in progress.
+    UnexpectedPacket,
+    /// A packet was skipped or repeated.
+    OutOfSequence,
+    /// A packet other than the last is not the same size as the first.
+    InvalidPacketSize,
+    /// The message doesn't fit in the reassembly buffer.
+    MessageTooLarge,
+    /// The first packet doesn't hold the message type.
+    EmptyMessage,
+    /// The MTU is smaller than `BASELINE_MTU` or larger than `MAX_MTU`.
+    InvalidMtu,
+}
+
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, AsBytes, FromBytes)]
+pub struct MctpHdr {
+    pub version: u8,
+    pub dest_eid: u8,
+    pub src_eid: u8,
+    pub flags: u8,
+}
+
+impl MctpHdr {
+    pub const SOM: u8 = 1 << 7;
+    pub const EOM: u8 = 1 << 6;
+    pub const TAG_OWNER: u8 = 1 << 3;
+    const SEQ_SHIFT: u8 = 4;
+    const SEQ_MASK: u8 = 0x3;
+    const TAG_MASK: u8 = 0x7;
+
+    pub fn som(&self) -> bool {
+        self.flags & Self::SOM != 0
+    }
+
+    pub fn eom(&self) -> bool {
+        self.flags & Self::EOM != 0
+    }
+
+    pub fn pkt_seq(&self) -> u8 {
+        (self.flags >> Self::SEQ_SHIFT) & Self::SEQ_MASK
+    }
+
+    pub fn info(&self) -> MsgInfo {
+        MsgInfo {
+            dest_eid: self.dest_eid,
+            src_eid: self.src_eid,
+            tag_owner: self.flags & Self::TAG_OWNER != 0,
+            msg_tag: self.flags & Self::TAG_MASK,
+        }
+    }
+}
+
+/// Addressing shared by every packet of a message.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct MsgInfo {
+    pub dest_eid: u8,
+    pub src_eid: u8,
+    /// Set on requests, which own the tag, and clear on their responses.
+    pub tag_owner: bool,
+    /// Tag matching a response to its request. Only the low 3 bits are used.
+    pub msg_tag: u8,
+}
+
+impl MsgInfo {
+    /// Returns the addressing for the response to a message with this
+    /// addressing.
+    pub fn response(&self) -> MsgInfo {
+        MsgInfo {
+            dest_eid: self.src_eid,
+            src_eid: self.dest_eid,
+            tag_owner: false,
+            msg_tag: self.msg_tag,
+        }
+    }
+
+    fn header(&self, som: bool, eom: bool, seq: u8) -> MctpHdr {
+        let mut flags = (seq & MctpHdr::SEQ_MASK) << MctpHdr::SEQ_SHIFT;
+        flags |= self.msg_tag & MctpHdr::TAG_MASK;
+        if som {
+            flags |= MctpHdr::SOM;
+        }
+        if eom {
+            flags |= MctpHdr::EOM;
+        }
+        if self.tag_owner {
+            flags |= MctpHdr::TAG_OWNER;
+        }
+        MctpHdr {
+            version: MCTP_HDR_VERSION,
+            dest_eid: self.dest_eid,
+            src_eid: self.src_eid,
+            flags,
+        }
+    }
+}
+
+/// Splits a message into packets of at most `mtu` payload bytes.
+pub struct Fragmenter<'a> {
+    info: MsgInfo,
+    mtu: usize,
+    msg_type: u8,
+    body: &'a [u8],
+    /// Offset into the message, counting the message type byte.
+    offset: usize,
+    seq: u8,
+}
+
+impl<'a> Fragmenter<'a> {
+    pub fn new(
+        info: MsgInfo,
+        mtu: usize,
+        msg_type: u8,
+        body: &'a [u8],
+    ) -> Result<Fragmenter<'a>, MctpError> {
+        if !(BASELINE_MTU..=MAX_MTU).contains(&mtu) {
+            return Err(MctpError::InvalidMtu);
+        }
+        Ok(Fragmenter {
+            info,
+            mtu,
+            msg_type,
+            body,
+            offset: 0,
+            seq: 0,
+        })
+    }
+
+    fn msg_len(&self) -> usize {
+        1 + self.body.len()
+    }
+
+    /// Writes the next packet to `out` and returns its length, or None once
+    /// the whole message has been written.
+    pub fn next_packet(&mut self, out: &mut [u8; MAX_PACKET_SIZE]) -> Option<usize> {
+        let start = self.offset;
+        if start >= self.msg_len() {
+            return None;
+        }
+        let end = self.msg_len().min(start + self.mtu);
+        let hdr = self
+            .info
+            .header(start == 0, end == self.msg_len(), self.seq);
+        out[..MCTP_HDR_SIZE].copy_from_slice(hdr.as_bytes());
+
+        let payload = &mut out[MCTP_HDR_SIZE..MCTP_HDR_SIZE + end - start];
+        if start == 0 {
+            payload[0] = self.msg_type;
+            payload[1..].copy_from_slice(&self.body[..end - 1]);
+        } else {
+            payload.copy_from_slice(&self.body[start - 1..end - 1]);
+        }
+
+        self.offset = end;
+        self.seq = self.seq.wrapping_add(1) & MctpHdr::SEQ_MASK;
+        Some(MCTP_HDR_SIZE + end - start)
+    }
+}
+
+/// A message rebuilt from its packets.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Message<'a> {
+    pub info: MsgInfo,
+    pub msg_type: u8,
+    pub body: &'a [u8],
+}
+
+/// Rebuilds messages of up to `N` bytes, counting the message type, from
+/// their packets. Only one message is reassembled at a time.
+pub struct Reassembler<const N: usize> {
+    buf: [u8; N],
+    len: usize,
+    /// Addressing of the message in progress, if any.
+    info: Option<MsgInfo>,
+    next_seq: u8,
+    packet_size: usize,
+}
+
+impl<const N: usize> Default for Reassembler<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> Reassembler<N> {
+    pub const fn new() -> Self {
+        Self {
+            buf: [0; N],
+            len: 0,
+            info: None,
+            next_seq: 0,
+            packet_size: 0,
+        }
+    }
+
+    /// Adds `packet` to the message in progress and returns the message once
+    /// its last packet has arrived.
+    ///
+    /// A packet that starts a message drops any message in progress. On error
+    /// the message in progress is dropped.
+    pub fn push(&mut self, packet: &[u8]) -> Result<Option<Message<'_>>, MctpError> {
+        match self.accept(packet) {
+            Ok(true) => {
+                let info = self.info.take().ok_or(MctpError::UnexpectedPacket)?;
+                Ok(Some(Message {
+                    info,
+                    msg_type: self.buf[0],
+                    body: &self.buf[1..self.len],
+                }))
+            }
+            Ok(false) => Ok(None),
+            Err(err) => {
+                self.info = None;
+                Err(err)
+            }
+        }
+    }
+
+    /// Copies the payload of `packet` into the buffer. Returns whether it
+    /// completed the message.
+    fn accept(&mut self, packet: &[u8]) -> Result<bool, MctpError> {
+        let hdr = MctpHdr::read_from_prefix(packet).ok_or(MctpError::InvalidHeader)?;
+        if hdr.version & 0xF != MCTP_HDR_VERSION {
+            return Err(MctpError::InvalidHeader);
+        }
+        let payload = &packet[MCTP_HDR_SIZE..];
+
+        if hdr.som() {
+            if payload.is_empty() {
+                return Err(MctpError::EmptyMessage);
+            }
+            self.info = Some(hdr.info());
+            self.len = 0;
+            self.packet_size = payload.len();
+        } else {
+            if self.info != Some(hdr.info()) {
+                return Err(MctpError::UnexpectedPacket);
+            }
+            if hdr.pkt_seq() != self.next_seq {
+                return Err(MctpError::OutOfSequence);
+            }
+            let size_ok = if hdr.eom() {
+                payload.len() <= self.packet_size
+            } else {
+                payload.len() == self.packet_size
+            };
+            if !size_ok {
+                return Err(MctpError::InvalidPacketSize);
+            }
+        }
+
+        let end = self.len + payload.len();
+        self.buf
+            .get_mut(self.len..end)
+            .ok_or(MctpError::MessageTooLarge)?
+            .copy_from_slice(payload);
+        self.len = end;
+        self.next_seq = hdr.pkt_seq().wrapping_add(1) & MctpHdr::SEQ_MASK;
+        Ok(hdr.eom())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const REQUEST: MsgInfo = MsgInfo {
+        dest_eid: 8,
+        src_eid: 9,
+        tag_owner: true,
+        msg_tag: 5,
+    };
+
+    fn packets(info: MsgInfo, mtu: usize, body: &[u8]) -> Vec<Vec<u8>> {
+        let mut fragmenter = Fragmenter::new(info, mtu, MSG_TYPE_DPE, body).unwrap();
+        let mut packet = [0u8; MAX_PACKET_SIZE];
+        let mut packets = Vec::new();
+        while let Some(len) = fragmenter.next_packet(&mut packet) {
+            packets.push(packet[..len].to_vec());
+        }
+        packets
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let body: Vec<u8> = (0..1000u32).map(|i| i as u8).collect();
+        for mtu in [BASELINE_MTU, 100, MAX_MTU] {
+            // Cover messages that end exactly on and just past a packet boundary.
+            for len in [0, 1, mtu - 1, mtu, 2 * mtu - 1, 1000] {
+                let body = &body[..len.min(body.len())];
+                let packets = packets(REQUEST, mtu, body);
+                assert_eq!(packets.len(), (body.len() + mtu) / mtu);
+
+                let mut reassembler = Reassembler::<1001>::new();
+                let (last, rest) = packets.split_last().unwrap();
+                for packet in rest {
+                    assert_eq!(Ok(None), reassembler.push(packet));
+                }
+                assert_eq!(
+                    Ok(Some(Message {
+                        info: REQUEST,
+                        msg_type: MSG_TYPE_DPE,
+                        body,
+                    })),
+                    reassembler.push(last)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_header() {
+        let packets = packets(REQUEST, BASELINE_MTU, &[0; 200]);
+        assert_eq!(4, packets.len());
+
+        let first = MctpHdr::read_from_prefix(packets[0].as_slice()).unwrap();
+        assert_eq!(
+            MctpHdr {
+                version: MCTP_HDR_VERSION,
+                dest_eid: 8,
+                src_eid: 9,
+                flags: MctpHdr::SOM | MctpHdr::TAG_OWNER | 5,
+            },
+            first
+        );
+        assert_eq!(MSG_TYPE_DPE, packets[0][MCTP_HDR_SIZE]);
+
+        let last = MctpHdr::read_from_prefix(packets[3].as_slice()).unwrap();
+        assert!(last.eom() && !last.som());
+        assert_eq!(3, last.pkt_seq());
+
+        assert_eq!(
+            MsgInfo {
+                dest_eid: 9,
+                src_eid: 8,
+                tag_owner: false,
+                msg_tag: 5,
+            },
+            REQUEST.response()
+        );
+    }
+
+    #[test]
+    fn test_reassembly_errors() {
+        let packets = packets(REQUEST, BASELINE_MTU, &[0; 200]);
+        let mut reassembler = Reassembler::<256>::new();
+
+        // A message can't start in the middle.
+        assert_eq!(
+            Err(MctpError::UnexpectedPacket),
+            reassembler.push(&packets[1])
+        );
+
+        // Skipping a packet drops the message.
+        reassembler.push(&packets[0]).unwrap();
+        assert_eq!(Err(MctpError::OutOfSequence), reassembler.push(&packets[2]));
+        assert_eq!(
+            Err(MctpError::UnexpectedPacket),
+            reassembler.push(&packets[3])
+        );
+
+        // Packets from another tag don't belong to the message in progress.
+        let other = packets(
+            MsgInfo {
+                msg_tag: 6,
+                ..REQUEST
+            },
+            BASELINE_MTU,
+            &[0; 200],
+        );
+        reassembler.push(&packets[0]).unwrap();
+        assert_eq!(
+            Err(MctpError::UnexpectedPacket),
+            reassembler.push(&other[1])
+        );
+
+        // Middle packets must be as large as the first.
+        reassembler.push(&packets[0]).unwrap();
+        assert_eq!(
+            Err(MctpError::InvalidPacketSize),
+            reassembler.push(&packets[1][..BASELINE_MTU])
+        );
+
+        // The message must fit in the buffer.
+        let mut small = Reassembler::<100>::new();
+        small.push(&packets[0]).unwrap();
+        assert_eq!(Err(MctpError::MessageTooLarge), small.push(&packets[1]));
+
+        let mut bad_version = packets[0].clone();
+        bad_version[0] = 2;
+        assert_eq!(
+            Err(MctpError::InvalidHeader),
+            reassembler.push(&bad_version)
+        );
+        assert_eq!(
+            Err(MctpError::EmptyMessage),
+            reassembler.push(&packets[0][..MCTP_HDR_SIZE])
+        );
+        assert_eq!(Err(MctpError::InvalidHeader), reassembler.push(&[1, 2]));
+        assert!(Fragmenter::new(REQUEST, BASELINE_MTU - 1, MSG_TYPE_DPE, &[]).is_err());
+    }
+}
@@ -11,8 +11,15 @@ openssl ec -in key_256.pem -outform DER -out key_256.der
 openssl x509 -in cert_256.pem -outform DER -out cert_256.der
 
 openssl ecparam -name secp384r1 -genkey -noout -out key_384.pem
-openssl req -new -key key_384.pem -x509 -nodes -days 365 -out cert_384.pem \
+openssl req -new -key key_384.pem -x509 -sha384 -nodes -days 365 -out cert_384.pem \
   -addext keyUsage=critical,keyCertSign \
   -subj /CN="DPE Test Alias"/
 openssl ec -in key_384.pem -outform DER -out key_384.der
-openssl x509 -in cert_384.pem -outform DER -out cert_384.der
\ No newline at end of file
+openssl x509 -in cert_384.pem -outform DER -out cert_384.der
+
+openssl ecparam -name secp521r1 -genkey -noout -out key_521.pem
+openssl req -new -key key_521.pem -x509 -sha512 -nodes -days 365 -out cert_521.pem \
+  -addext keyUsage=critical,keyCertSign \
+  -subj /CN="DPE Test Alias"/
+openssl ec -in key_521.pem -outform DER -out key_521.der
+openssl x509 -in cert_521.pem -outform DER -out cert_521.der
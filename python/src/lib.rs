@@ -0,0 +1,474 @@
+/*++
+Licensed under the Apache-2.0 license.
+
+Abstract:
+    Python bindings for the DPE client library.
+--*/
+use crypto::{CryptoBuf, EcdsaPub, EcdsaPubFormat, OpensslCrypto};
+use dpe::{
+    commands::{
+        CertifyKeyCmd, CertifyKeyFlags, DeriveContextCmd, DeriveContextFlags, DestroyCtxCmd,
+        ExtendTciCmd, GetTaggedTciCmd, InitCtxCmd, RotateCtxCmd, RotateCtxFlags, SignCmd,
+        SignFlags, TagTciCmd,
+    },
+    context::ContextHandle,
+    dpe_instance::{DpeEnv, DpeTypes},
+    response::DpeErrorCode,
+    support::Support,
+    DpeInstance, DPE_PROFILE,
+};
+use dpe_client::{
+    signature_der, ClientError, DpeClient, InProcessTransport, TcpTransport, Transport,
+    UnixTransport, SIMULATOR_SOCKET_PATH,
+};
+use platform::default::{DefaultPlatform, AUTO_INIT_LOCALITY};
+use pyo3::{
+    create_exception,
+    exceptions::{PyException, PyValueError},
+    prelude::*,
+    types::PyBytes,
+};
+use std::net::ToSocketAddrs;
+
+create_exception!(
+    dpe_py,
+    DpeError,
+    PyException,
+    "The DPE completed a command with a non-zero status. The status is the first argument."
+);
+
+fn dpe_error(status: u32) -> PyErr {
+    DpeError::new_err((status, format!("DPE returned status {status:#x}")))
+}
+
+fn to_py_err(err: ClientError) -> PyErr {
+    match err {
+        ClientError::Io(err) => err.into(),
+        ClientError::Dpe(status) => dpe_error(status),
+        ClientError::MalformedResponse => PyValueError::new_err("malformed DPE response"),
+        ClientError::Mctp(err) => PyValueError::new_err(format!("MCTP error: {err:?}")),
+    }
+}
+
+/// Copies `bytes` into a fixed-size command field.
+fn to_array<const N: usize>(name: &str, bytes: &[u8]) -> PyResult<[u8; N]> {
+    bytes
+        .try_into()
+        .map_err(|_| PyValueError::new_err(format!("{name} must be {N} bytes")))
+}
+
+fn to_handle(handle: &[u8]) -> PyResult<ContextHandle> {
+    Ok(ContextHandle(to_array("handle", handle)?))
+}
+
+fn to_bytes(py: Python<'_>, bytes: &[u8]) -> Py<PyBytes> {
+    PyBytes::new(py, bytes).into()
+}
+
+fn encode_pub_key(py: Python<'_>, pub_key: &EcdsaPub, format: EcdsaPubFormat) -> Py<PyBytes> {
+    let mut out = [0; EcdsaPub::MAX_SERIALIZED_SIZE];
+    // The coordinates come from a response of this profile, so they always
+    // fit.
+    let len = pub_key.serialize(format, &mut out).unwrap();
+    to_bytes(py, &out[..len])
+}
+
+struct PyTypes;
+
+impl DpeTypes for PyTypes {
+    type Crypto<'a> = OpensslCrypto;
+    type Platform<'a> = DefaultPlatform;
+}
+
+/// The transports a `Client` can be created with.
+enum AnyTransport {
+    Unix(UnixTransport),
+    Tcp(TcpTransport),
+    InProcess(Box<InProcessTransport<'static, PyTypes>>),
+}
+
+impl Transport for AnyTransport {
+    fn send(&mut self, locality: u32, cmd: &[u8]) -> Result<Vec<u8>, ClientError> {
+        match self {
+            AnyTransport::Unix(transport) => transport.send(locality, cmd),
+            AnyTransport::Tcp(transport) => transport.send(locality, cmd),
+            AnyTransport::InProcess(transport) => transport.send(locality, cmd),
+        }
+    }
+}
+
+/// Response to GetProfile.
+#[pyclass(get_all)]
+struct Profile {
+    profile: u32,
+    major_version: u16,
+    minor_version: u16,
+    vendor_id: u32,
+    vendor_sku: u32,
+    max_tci_nodes: u32,
+    flags: u32,
+    max_simulation_contexts: u32,
+    max_cert_chain_depth: u32,
+}
+
+/// A key certified by CertifyKey. `cert` holds the whole certificate, even
+/// if it had to be read back in chunks. The public key is also given as a
+/// DER SubjectPublicKeyInfo and as a compressed SEC1 point.
+#[pyclass(get_all)]
+struct CertifiedKey {
+    handle: Py<PyBytes>,
+    pub_key_x: Py<PyBytes>,
+    pub_key_y: Py<PyBytes>,
+    pub_key_spki: Py<PyBytes>,
+    pub_key_compressed: Py<PyBytes>,
+    cert: Py<PyBytes>,
+}
+
+/// An ECDSA signature returned by Sign, or an HMAC in `r` for symmetric
+/// signing. `der` holds the ECDSA signature as a DER ECDSA-Sig-Value, and is
+/// `None` for symmetric signing.
+#[pyclass(get_all)]
+struct Signature {
+    handle: Py<PyBytes>,
+    r: Py<PyBytes>,
+    s: Py<PyBytes>,
+    der: Option<Py<PyBytes>>,
+}
+
+/// Sends DPE commands from a single locality and parses the responses.
+///
+/// Handles, labels, digests and TCI data are `bytes` of the profile's sizes.
+/// Commands that fail with a DPE status raise `DpeError`.
+#[pyclass(unsendable)]
+struct Client(DpeClient<AnyTransport>);
+
+#[pymethods]
+impl Client {
+    /// Connects to a DPE listening on a Unix socket, such as the simulator.
+    #[staticmethod]
+    #[pyo3(signature = (path = SIMULATOR_SOCKET_PATH.to_string(), locality = AUTO_INIT_LOCALITY))]
+    fn unix(path: String, locality: u32) -> Self {
+        Client(DpeClient::new(
+            AnyTransport::Unix(UnixTransport::new(path)),
+            locality,
+        ))
+    }
+
+    /// Connects to a DPE listening on TCP at `addr`, given as "host:port".
+    #[staticmethod]
+    #[pyo3(signature = (addr, locality = AUTO_INIT_LOCALITY))]
+    fn tcp(addr: &str, locality: u32) -> PyResult<Self> {
+        let addr = addr
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| PyValueError::new_err("address did not resolve"))?;
+        Ok(Client(DpeClient::new(
+            AnyTransport::Tcp(TcpTransport::new(addr)),
+            locality,
+        )))
+    }
+
+    /// Runs a DPE supporting `support` in this process on the default test
+    /// platform, so tests don't need a simulator.
+    #[staticmethod]
+    #[pyo3(signature = (support, locality = AUTO_INIT_LOCALITY))]
+    fn in_process(support: u32, locality: u32) -> PyResult<Self> {
+        let mut env = DpeEnv::<PyTypes> {
+            crypto: OpensslCrypto::new(),
+            platform: DefaultPlatform,
+            arena: &mut [],
+        };
+        let dpe = DpeInstance::new(&mut env, Support::from_bits_retain(support))
+            .map_err(|err| dpe_error(err.get_error_code()))?;
+        Ok(Client(DpeClient::new(
+            AnyTransport::InProcess(Box::new(InProcessTransport::new(dpe, env))),
+            locality,
+        )))
+    }
+
+    #[getter]
+    fn locality(&self) -> u32 {
+        self.0.locality()
+    }
+
+    #[setter]
+    fn set_locality(&mut self, locality: u32) {
+        self.0.set_locality(locality);
+    }
+
+    fn get_profile(&mut self) -> PyResult<Profile> {
+        let resp = self.0.get_profile().map_err(to_py_err)?;
+        Ok(Profile {
+            profile: resp.resp_hdr.profile,
+            major_version: resp.major_version,
+            minor_version: resp.minor_version,
+            vendor_id: resp.vendor_id,
+            vendor_sku: resp.vendor_sku,
+            max_tci_nodes: resp.max_tci_nodes,
+            flags: resp.flags,
+            max_simulation_contexts: resp.max_simulation_contexts,
+            max_cert_chain_depth: resp.max_cert_chain_depth,
+        })
+    }
+
+    /// Initializes the default context, or a simulation context if
+    /// `simulation` is set. Returns the new handle.
+    #[pyo3(signature = (simulation = false))]
+    fn initialize_context(&mut self, py: Python<'_>, simulation: bool) -> PyResult<Py<PyBytes>> {
+        let cmd = if simulation {
+            InitCtxCmd::new_simulation()
+        } else {
+            InitCtxCmd::new_use_default()
+        };
+        let resp = self.0.initialize_context(&cmd).map_err(to_py_err)?;
+        Ok(to_bytes(py, &resp.handle.0))
+    }
+
+    /// Returns the new handle and the parent's new handle. The child is
+    /// created in the client's locality unless `target_locality` is given.
+    #[pyo3(signature = (handle, data, flags = 0, tci_type = 0, target_locality = None))]
+    fn derive_context(
+        &mut self,
+        py: Python<'_>,
+        handle: &[u8],
+        data: &[u8],
+        flags: u32,
+        tci_type: u32,
+        target_locality: Option<u32>,
+    ) -> PyResult<(Py<PyBytes>, Py<PyBytes>)> {
+        let target_locality = target_locality.unwrap_or(self.0.locality());
+        let resp = self
+            .0
+            .derive_context(&DeriveContextCmd {
+                handle: to_handle(handle)?,
+                data: to_array("data", data)?,
+                flags: DeriveContextFlags::from_bits_retain(flags),
+                tci_type,
+                target_locality,
+            })
+            .map_err(to_py_err)?;
+        Ok((
+            to_bytes(py, &resp.handle.0),
+            to_bytes(py, &resp.parent_handle.0),
+        ))
+    }
+
+    /// Certifies the key derived with `label`, which defaults to all zeros.
+    #[pyo3(signature = (handle, label = None, format = CertifyKeyCmd::FORMAT_X509, flags = 0))]
+    fn certify_key(
+        &mut self,
+        py: Python<'_>,
+        handle: &[u8],
+        label: Option<&[u8]>,
+        format: u32,
+        flags: u32,
+    ) -> PyResult<CertifiedKey> {
+        let label = match label {
+            Some(label) => to_array("label", label)?,
+            None => [0; DPE_PROFILE.get_hash_size()],
+        };
+        let resp = self
+            .0
+            .certify_key(&CertifyKeyCmd {
+                handle: to_handle(handle)?,
+                flags: CertifyKeyFlags::from_bits_retain(flags),
+                format,
+                label,
+            })
+            .map_err(to_py_err)?;
+        let cert = self
+            .0
+            .read_certificate(resp.total_size)
+            .map_err(to_py_err)?;
+        let pub_key = EcdsaPub {
+            x: CryptoBuf::new(&resp.derived_pubkey_x).unwrap(),
+            y: CryptoBuf::new(&resp.derived_pubkey_y).unwrap(),
+        };
+        Ok(CertifiedKey {
+            handle: to_bytes(py, &resp.new_context_handle.0),
+            pub_key_x: to_bytes(py, &resp.derived_pubkey_x),
+            pub_key_y: to_bytes(py, &resp.derived_pubkey_y),
+            pub_key_spki: encode_pub_key(py, &pub_key, EcdsaPubFormat::Spki),
+            pub_key_compressed: encode_pub_key(py, &pub_key, EcdsaPubFormat::Sec1Compressed),
+            cert: to_bytes(py, &cert),
+        })
+    }
+
+    /// Signs `digest` with the key derived with `label`, which defaults to all
+    /// zeros.
+    #[pyo3(signature = (handle, digest, label = None, flags = 0))]
+    fn sign(
+        &mut self,
+        py: Python<'_>,
+        handle: &[u8],
+        digest: &[u8],
+        label: Option<&[u8]>,
+        flags: u32,
+    ) -> PyResult<Signature> {
+        let label = match label {
+            Some(label) => to_array("label", label)?,
+            None => [0; DPE_PROFILE.get_hash_size()],
+        };
+        let resp = self
+            .0
+            .sign(&SignCmd {
+                handle: to_handle(handle)?,
+                label,
+                flags: SignFlags::from_bits_retain(flags),
+                digest: to_array("digest", digest)?,
+            })
+            .map_err(to_py_err)?;
+        let der = if flags & SignFlags::IS_SYMMETRIC.bits() == 0 {
+            Some(to_bytes(py, &signature_der(&resp).map_err(to_py_err)?))
+        } else {
+            None
+        };
+        Ok(Signature {
+            handle: to_bytes(py, &resp.new_context_handle.0),
+            r: to_bytes(py, &resp.sig_r_or_hmac),
+            s: to_bytes(py, &resp.sig_s),
+            der,
+        })
+    }
+
+    #[pyo3(signature = (handle, flags = 0))]
+    fn rotate_context_handle(
+        &mut self,
+        py: Python<'_>,
+        handle: &[u8],
+        flags: u32,
+    ) -> PyResult<Py<PyBytes>> {
+        let resp = self
+            .0
+            .rotate_context_handle(&RotateCtxCmd {
+                handle: to_handle(handle)?,
+                flags: RotateCtxFlags::from_bits_retain(flags),
+            })
+            .map_err(to_py_err)?;
+        Ok(to_bytes(py, &resp.handle.0))
+    }
+
+    fn destroy_context(&mut self, handle: &[u8]) -> PyResult<()> {
+        self.0
+            .destroy_context(&DestroyCtxCmd {
+                handle: to_handle(handle)?,
+            })
+            .map_err(to_py_err)?;
+        Ok(())
+    }
+
+    /// Reads the whole certificate chain.
+    fn get_certificate_chain(&mut self, py: Python<'_>) -> PyResult<Py<PyBytes>> {
+        let chain = self.0.read_certificate_chain().map_err(to_py_err)?;
+        Ok(to_bytes(py, &chain))
+    }
+
+    /// Reads the whole certificate chain as concatenated PEM certificates.
+    fn get_certificate_chain_pem(&mut self) -> PyResult<String> {
+        self.0.read_certificate_chain_pem().map_err(to_py_err)
+    }
+
+    fn extend_tci(&mut self, py: Python<'_>, handle: &[u8], data: &[u8]) -> PyResult<Py<PyBytes>> {
+        let resp = self
+            .0
+            .extend_tci(&ExtendTciCmd {
+                handle: to_handle(handle)?,
+                data: to_array("data", data)?,
+            })
+            .map_err(to_py_err)?;
+        Ok(to_bytes(py, &resp.handle.0))
+    }
+
+    fn tag_tci(&mut self, py: Python<'_>, handle: &[u8], tag: u32) -> PyResult<Py<PyBytes>> {
+        let resp = self
+            .0
+            .tag_tci(&TagTciCmd {
+                handle: to_handle(handle)?,
+                tag,
+            })
+            .map_err(to_py_err)?;
+        Ok(to_bytes(py, &resp.handle.0))
+    }
+
+    /// Returns the cumulative and current TCI of the context tagged `tag`.
+    fn get_tagged_tci(&mut self, py: Python<'_>, tag: u32) -> PyResult<(Py<PyBytes>, Py<PyBytes>)> {
+        let resp = self
+            .0
+            .get_tagged_tci(&GetTaggedTciCmd { tag })
+            .map_err(to_py_err)?;
+        Ok((
+            to_bytes(py, &resp.tci_cumulative.0),
+            to_bytes(py, &resp.tci_current.0),
+        ))
+    }
+}
+
+/// DPE statuses without a payload, exported as `STATUS_<NAME>`.
+const STATUSES: [(&str, DpeErrorCode); 20] = [
+    ("NO_ERROR", DpeErrorCode::NoError),
+    ("INTERNAL_ERROR", DpeErrorCode::InternalError),
+    ("INVALID_COMMAND", DpeErrorCode::InvalidCommand),
+    ("INVALID_ARGUMENT", DpeErrorCode::InvalidArgument),
+    ("ARGUMENT_NOT_SUPPORTED", DpeErrorCode::ArgumentNotSupported),
+    ("INVALID_HANDLE", DpeErrorCode::InvalidHandle),
+    ("INVALID_LOCALITY", DpeErrorCode::InvalidLocality),
+    ("BAD_TAG", DpeErrorCode::BadTag),
+    ("MAX_TCIS", DpeErrorCode::MaxTcis),
+    ("MAX_SESSIONS", DpeErrorCode::MaxSessions),
+    ("COMMAND_NOT_ALLOWED", DpeErrorCode::CommandNotAllowed),
+    ("MAX_HASH_STREAMS", DpeErrorCode::MaxHashStreams),
+    ("INVALID_SESSION", DpeErrorCode::InvalidSession),
+    ("CERTIFICATE_TOO_LARGE", DpeErrorCode::CertificateTooLarge),
+    ("INVALID_CERTIFICATE", DpeErrorCode::InvalidCertificate),
+    ("TCI_TYPE_NOT_ALLOWED", DpeErrorCode::TciTypeNotAllowed),
+    (
+        "OUTPUT_BUFFER_TOO_SMALL",
+        DpeErrorCode::OutputBufferTooSmall,
+    ),
+    ("AUTHENTICATION_FAILED", DpeErrorCode::AuthenticationFailed),
+    ("UNKNOWN_LOCALITY", DpeErrorCode::UnknownLocality),
+    ("SVN_ROLLBACK", DpeErrorCode::SvnRollback),
+    ("UNSEAL_FAILED", DpeErrorCode::UnsealFailed),
+    ("BUSY", DpeErrorCode::Busy),
+    ("RETIRED_CONTEXT", DpeErrorCode::RetiredContext),
+    ("MAX_CERT_CHAIN_DEPTH", DpeErrorCode::MaxCertChainDepth),
+];
+
+#[pymodule]
+fn dpe_py(py: Python<'_>, m: &PyModule) -> PyResult<()> {
+    m.add("DpeError", py.get_type::<DpeError>())?;
+    m.add_class::<Client>()?;
+    m.add_class::<Profile>()?;
+    m.add_class::<CertifiedKey>()?;
+    m.add_class::<Signature>()?;
+
+    m.add("PROFILE", DPE_PROFILE as u32)?;
+    m.add("HASH_SIZE", DPE_PROFILE.get_hash_size())?;
+    m.add("ECC_INT_SIZE", DPE_PROFILE.get_ecc_int_size())?;
+    m.add("DEFAULT_HANDLE", to_bytes(py, &ContextHandle::default().0))?;
+    m.add("SIMULATOR_SOCKET_PATH", SIMULATOR_SOCKET_PATH)?;
+    m.add("AUTO_INIT_LOCALITY", AUTO_INIT_LOCALITY)?;
+    m.add("FORMAT_X509", CertifyKeyCmd::FORMAT_X509)?;
+    m.add("FORMAT_CSR", CertifyKeyCmd::FORMAT_CSR)?;
+    m.add("FORMAT_CWT", CertifyKeyCmd::FORMAT_CWT)?;
+
+    for (name, flag) in Support::all().iter_names() {
+        m.add(&format!("SUPPORT_{name}"), flag.bits())?;
+    }
+    for (name, flag) in DeriveContextFlags::all().iter_names() {
+        m.add(&format!("DERIVE_{name}"), flag.bits())?;
+    }
+    for (name, flag) in CertifyKeyFlags::all().iter_names() {
+        m.add(&format!("CERTIFY_KEY_{name}"), flag.bits())?;
+    }
+    for (name, flag) in SignFlags::all().iter_names() {
+        m.add(&format!("SIGN_{name}"), flag.bits())?;
+    }
+    for (name, flag) in RotateCtxFlags::all().iter_names() {
+        m.add(&format!("ROTATE_{name}"), flag.bits())?;
+    }
+    for (name, status) in STATUSES {
+        m.add(&format!("STATUS_{name}"), status.get_error_code())?;
+    }
+    Ok(())
+}
@@ -0,0 +1,141 @@
+# Licensed under the Apache-2.0 license
+
+import hashlib
+
+import pytest
+from cryptography import x509
+from cryptography.hazmat.primitives import hashes, serialization
+from cryptography.hazmat.primitives.asymmetric import ec, utils
+
+import dpe_py as dpe
+
+# Curve and hash of each profile, keyed by the ECC integer size.
+CURVES = {
+    32: (ec.SECP256R1(), hashes.SHA256(), hashlib.sha256),
+    48: (ec.SECP384R1(), hashes.SHA384(), hashlib.sha384),
+    66: (ec.SECP521R1(), hashes.SHA512(), hashlib.sha512),
+}
+CURVE, HASH, HASHLIB = CURVES[dpe.ECC_INT_SIZE]
+
+
+def new_client(support=dpe.SUPPORT_AUTO_INIT | dpe.SUPPORT_X509):
+    return dpe.Client.in_process(support)
+
+
+def split_der(chain):
+    """Splits concatenated DER certificates."""
+    certs = []
+    while chain:
+        # Every certificate is a SEQUENCE with a long-form length.
+        assert chain[0] == 0x30 and chain[1] & 0x80
+        len_size = chain[1] & 0x7F
+        size = 2 + len_size + int.from_bytes(chain[2 : 2 + len_size], "big")
+        certs.append(x509.load_der_x509_certificate(chain[:size]))
+        chain = chain[size:]
+    return certs
+
+
+def verify_issued_by(cert, issuer):
+    assert cert.issuer == issuer.subject
+    issuer.public_key().verify(
+        cert.signature,
+        cert.tbs_certificate_bytes,
+        ec.ECDSA(cert.signature_hash_algorithm),
+    )
+
+
+def test_get_profile():
+    profile = new_client().get_profile()
+    assert profile.profile == dpe.PROFILE
+    assert profile.flags == dpe.SUPPORT_AUTO_INIT | dpe.SUPPORT_X509
+
+
+def test_certify_key():
+    client = new_client()
+    certified = client.certify_key(dpe.DEFAULT_HANDLE)
+    assert certified.handle == dpe.DEFAULT_HANDLE
+
+    # The certificate certifies the key in the response.
+    cert = x509.load_der_x509_certificate(certified.cert)
+    numbers = cert.public_key().public_numbers()
+    assert numbers.x.to_bytes(dpe.ECC_INT_SIZE, "big") == certified.pub_key_x
+    assert numbers.y.to_bytes(dpe.ECC_INT_SIZE, "big") == certified.pub_key_y
+
+    # It is issued by the last certificate in the chain.
+    chain = split_der(client.get_certificate_chain())
+    verify_issued_by(cert, chain[-1])
+
+
+def test_get_certificate_chain_pem():
+    client = new_client()
+    certs = x509.load_pem_x509_certificates(
+        client.get_certificate_chain_pem().encode()
+    )
+    assert certs == split_der(client.get_certificate_chain())
+
+
+def test_certify_key_pub_key_encodings():
+    certified = new_client().certify_key(dpe.DEFAULT_HANDLE)
+    pub_key = x509.load_der_x509_certificate(certified.cert).public_key()
+    assert certified.pub_key_spki == pub_key.public_bytes(
+        serialization.Encoding.DER,
+        serialization.PublicFormat.SubjectPublicKeyInfo,
+    )
+    assert certified.pub_key_compressed == pub_key.public_bytes(
+        serialization.Encoding.X962,
+        serialization.PublicFormat.CompressedPoint,
+    )
+    assert serialization.load_der_public_key(certified.pub_key_spki) == pub_key
+
+
+def test_sign():
+    client = new_client()
+    certified = client.certify_key(dpe.DEFAULT_HANDLE)
+    digest = HASHLIB(b"message").digest()
+    signature = client.sign(dpe.DEFAULT_HANDLE, digest)
+
+    pub_key = ec.EllipticCurvePublicNumbers(
+        int.from_bytes(certified.pub_key_x, "big"),
+        int.from_bytes(certified.pub_key_y, "big"),
+        CURVE,
+    ).public_key()
+    der_sig = utils.encode_dss_signature(
+        int.from_bytes(signature.r, "big"), int.from_bytes(signature.s, "big")
+    )
+    assert signature.der == der_sig
+    pub_key.verify(signature.der, digest, ec.ECDSA(utils.Prehashed(HASH)))
+
+
+def test_context_lifecycle():
+    client = new_client(dpe.SUPPORT_X509 | dpe.SUPPORT_ROTATE_CONTEXT)
+    handle = client.initialize_context()
+    assert handle == dpe.DEFAULT_HANDLE
+
+    handle, _ = client.derive_context(
+        handle,
+        bytes([1] * dpe.HASH_SIZE),
+        flags=dpe.DERIVE_MAKE_DEFAULT | dpe.DERIVE_INPUT_ALLOW_X509,
+    )
+    assert handle == dpe.DEFAULT_HANDLE
+
+    rotated = client.rotate_context_handle(handle)
+    assert rotated != dpe.DEFAULT_HANDLE
+    client.destroy_context(rotated)
+
+
+def test_dpe_error():
+    client = new_client()
+
+    # The default context was already initialized.
+    with pytest.raises(dpe.DpeError) as err:
+        client.initialize_context()
+    assert err.value.args[0] == dpe.STATUS_ARGUMENT_NOT_SUPPORTED
+
+    with pytest.raises(dpe.DpeError) as err:
+        client.certify_key(bytes([0xAA] * len(dpe.DEFAULT_HANDLE)))
+    assert err.value.args[0] == dpe.STATUS_INVALID_HANDLE
+
+
+def test_bad_argument_size():
+    with pytest.raises(ValueError):
+        new_client().sign(dpe.DEFAULT_HANDLE, b"too short")
@@ -0,0 +1,622 @@
+// Licensed under the Apache-2.0 license
+
+//! Crypto fault injection for `--fault`.
+//!
+//! `FaultyCrypto` passes every operation to the real backend, except the
+//! ones selected by its faults, which fail. Operations are counted from the
+//! last call to `set_faults`, so the simulator counts from the first command
+//! rather than from initializing the instance.
+
+use crypto::{
+    AeadKey, AlgLen, Crypto, CryptoError, Digest, EcdsaPub, EcdsaSig, HashState, HmacSig,
+    AEAD_IV_SIZE, AEAD_TAG_SIZE,
+};
+use std::str::FromStr;
+
+/// Error detail of every injected failure, so they can be told apart from
+/// real ones.
+pub const INJECTED_FAULT: u32 = 0xFA17;
+
+/// A crypto operation to make fail. Counts start at 1.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Fault {
+    /// Fail the Nth hash. Calls to `hash`, `hash_initialize` and
+    /// `hash_state_initialize` are hashes, HMACs are not.
+    Hash(u64),
+    /// Fail the Nth signature, with the alias key or a derived key.
+    Sign(u64),
+    /// Let the first N requests for random bytes through and fail every one
+    /// after them. ECDH and CDI export draw random bytes too.
+    Rng(u64),
+}
+
+impl FromStr for Fault {
+    type Err = String;
+
+    /// Parses `hash:N`, `sign:N` or `rng:N`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (op, n) = s
+            .split_once(':')
+            .ok_or_else(|| format!("expected OP:N, got `{s}`"))?;
+        let n = n.parse().map_err(|err| format!("bad count `{n}`: {err}"))?;
+        match op {
+            "hash" | "sign" if n == 0 => Err(format!("{op} operations are counted from 1")),
+            "hash" => Ok(Fault::Hash(n)),
+            "sign" => Ok(Fault::Sign(n)),
+            "rng" => Ok(Fault::Rng(n)),
+            _ => Err(format!(
+                "unknown operation `{op}`, expected hash, sign or rng"
+            )),
+        }
+    }
+}
+
+/// Wraps a `Crypto` implementation and fails the operations selected by
+/// `set_faults`.
+pub struct FaultyCrypto<C> {
+    inner: C,
+    faults: Vec<Fault>,
+    hashes: u64,
+    signs: u64,
+    rngs: u64,
+    /// CDIs and private keys derived and not yet destroyed.
+    live_handles: u64,
+}
+
+impl<C: Crypto> FaultyCrypto<C> {
+    /// Wraps `inner` without any faults.
+    pub fn new(inner: C) -> Self {
+        Self {
+            inner,
+            faults: Vec::new(),
+            hashes: 0,
+            signs: 0,
+            rngs: 0,
+            live_handles: 0,
+        }
+    }
+
+    /// Number of CDIs and private keys that were derived and not destroyed.
+    pub fn live_handles(&self) -> u64 {
+        self.live_handles
+    }
+
+    /// Replaces the faults and restarts counting operations.
+    pub fn set_faults(&mut self, faults: &[Fault]) {
+        self.faults = faults.to_vec();
+        self.hashes = 0;
+        self.signs = 0;
+        self.rngs = 0;
+    }
+
+    fn count_hash(&mut self) -> Result<(), CryptoError> {
+        self.hashes += 1;
+        if self.faults.contains(&Fault::Hash(self.hashes)) {
+            return Err(CryptoError::HashError(INJECTED_FAULT));
+        }
+        Ok(())
+    }
+
+    fn count_sign(&mut self) -> Result<(), CryptoError> {
+        self.signs += 1;
+        if self.faults.contains(&Fault::Sign(self.signs)) {
+            return Err(CryptoError::CryptoLibError(INJECTED_FAULT));
+        }
+        Ok(())
+    }
+
+    fn count_rng(&mut self) -> Result<(), CryptoError> {
+        self.rngs += 1;
+        let exhausted = self
+            .faults
+            .iter()
+            .any(|fault| matches!(fault, Fault::Rng(n) if self.rngs > *n));
+        if exhausted {
+            return Err(CryptoError::CryptoLibError(INJECTED_FAULT));
+        }
+        Ok(())
+    }
+}
+
+impl<C: Crypto> Crypto for FaultyCrypto<C> {
+    type Cdi = C::Cdi;
+    type Hasher<'c> = C::Hasher<'c> where Self: 'c;
+    type PrivKey = C::PrivKey;
+
+    fn rand_bytes(&mut self, dst: &mut [u8]) -> Result<(), CryptoError> {
+        self.count_rng()?;
+        self.inner.rand_bytes(dst)
+    }
+
+    fn hash(&mut self, algs: AlgLen, bytes: &[u8]) -> Result<Digest, CryptoError> {
+        self.count_hash()?;
+        self.inner.hash(algs, bytes)
+    }
+
+    fn hmac(&mut self, algs: AlgLen, key: &[u8], data: &[u8]) -> Result<Digest, CryptoError> {
+        self.inner.hmac(algs, key, data)
+    }
+
+    fn hash_initialize(&mut self, algs: AlgLen) -> Result<Self::Hasher<'_>, CryptoError> {
+        self.count_hash()?;
+        self.inner.hash_initialize(algs)
+    }
+
+    fn hash_state_initialize(&mut self, algs: AlgLen) -> Result<HashState, CryptoError> {
+        self.count_hash()?;
+        self.inner.hash_state_initialize(algs)
+    }
+
+    fn hash_state_update(
+        &mut self,
+        algs: AlgLen,
+        state: &mut HashState,
+        bytes: &[u8],
+    ) -> Result<(), CryptoError> {
+        self.inner.hash_state_update(algs, state, bytes)
+    }
+
+    fn hash_state_finish(
+        &mut self,
+        algs: AlgLen,
+        state: &HashState,
+    ) -> Result<Digest, CryptoError> {
+        self.inner.hash_state_finish(algs, state)
+    }
+
+    fn derive_cdi(
+        &mut self,
+        algs: AlgLen,
+        measurement: &Digest,
+        info: &[u8],
+    ) -> Result<Self::Cdi, CryptoError> {
+        let cdi = self.inner.derive_cdi(algs, measurement, info)?;
+        self.live_handles += 1;
+        Ok(cdi)
+    }
+
+    fn derive_key_pair(
+        &mut self,
+        algs: AlgLen,
+        cdi: &Self::Cdi,
+        label: &[u8],
+        info: &[u8],
+    ) -> Result<(Self::PrivKey, EcdsaPub), CryptoError> {
+        let key_pair = self.inner.derive_key_pair(algs, cdi, label, info)?;
+        self.live_handles += 1;
+        Ok(key_pair)
+    }
+
+    fn cache_cdi(&mut self, slot: usize, cdi: &Self::Cdi) -> Result<(), CryptoError> {
+        self.inner.cache_cdi(slot, cdi)
+    }
+
+    fn cached_cdi(&mut self, slot: usize) -> Result<Self::Cdi, CryptoError> {
+        let cdi = self.inner.cached_cdi(slot)?;
+        self.live_handles += 1;
+        Ok(cdi)
+    }
+
+    fn clear_cdi_cache(&mut self) {
+        self.inner.clear_cdi_cache()
+    }
+
+    fn destroy_cdi(&mut self, cdi: Self::Cdi) -> Result<(), CryptoError> {
+        self.live_handles -= 1;
+        self.inner.destroy_cdi(cdi)
+    }
+
+    fn destroy_key(&mut self, priv_key: Self::PrivKey) -> Result<(), CryptoError> {
+        self.live_handles -= 1;
+        self.inner.destroy_key(priv_key)
+    }
+
+    fn ecdsa_sign_with_alias(
+        &mut self,
+        algs: AlgLen,
+        digest: &Digest,
+    ) -> Result<EcdsaSig, CryptoError> {
+        self.count_sign()?;
+        self.inner.ecdsa_sign_with_alias(algs, digest)
+    }
+
+    fn ecdsa_sign_with_derived(
+        &mut self,
+        algs: AlgLen,
+        digest: &Digest,
+        priv_key: &Self::PrivKey,
+        pub_key: &EcdsaPub,
+    ) -> Result<EcdsaSig, CryptoError> {
+        self.count_sign()?;
+        self.inner
+            .ecdsa_sign_with_derived(algs, digest, priv_key, pub_key)
+    }
+
+    fn ecdsa_verify(
+        &mut self,
+        algs: AlgLen,
+        digest: &Digest,
+        pub_key: &EcdsaPub,
+        sig: &EcdsaSig,
+    ) -> Result<(), CryptoError> {
+        self.inner.ecdsa_verify(algs, digest, pub_key, sig)
+    }
+
+    fn hmac_sign_with_derived(
+        &mut self,
+        algs: AlgLen,
+        cdi: &Self::Cdi,
+        label: &[u8],
+        info: &[u8],
+        digest: &Digest,
+    ) -> Result<HmacSig, CryptoError> {
+        self.count_sign()?;
+        self.inner
+            .hmac_sign_with_derived(algs, cdi, label, info, digest)
+    }
+
+    fn ecdh_derive_key(
+        &mut self,
+        algs: AlgLen,
+        peer_pub: &EcdsaPub,
+        info: &[u8],
+    ) -> Result<(AeadKey, EcdsaPub), CryptoError> {
+        self.count_rng()?;
+        self.inner.ecdh_derive_key(algs, peer_pub, info)
+    }
+
+    fn aead_encrypt(
+        &mut self,
+        key: &AeadKey,
+        iv: &[u8; AEAD_IV_SIZE],
+        aad: &[u8],
+        data: &mut [u8],
+    ) -> Result<[u8; AEAD_TAG_SIZE], CryptoError> {
+        self.inner.aead_encrypt(key, iv, aad, data)
+    }
+
+    fn aead_decrypt(
+        &mut self,
+        key: &AeadKey,
+        iv: &[u8; AEAD_IV_SIZE],
+        aad: &[u8],
+        data: &mut [u8],
+        tag: &[u8; AEAD_TAG_SIZE],
+    ) -> Result<(), CryptoError> {
+        self.inner.aead_decrypt(key, iv, aad, data, tag)
+    }
+
+    fn derive_aead_key(
+        &mut self,
+        algs: AlgLen,
+        cdi: &Self::Cdi,
+        label: &[u8],
+        info: &[u8],
+    ) -> Result<AeadKey, CryptoError> {
+        self.inner.derive_aead_key(algs, cdi, label, info)
+    }
+
+    fn wrap_cdi(
+        &mut self,
+        cdi: &Self::Cdi,
+        key: &AeadKey,
+        aad: &[u8],
+        wrapped: &mut [u8],
+    ) -> Result<([u8; AEAD_IV_SIZE], [u8; AEAD_TAG_SIZE]), CryptoError> {
+        self.count_rng()?;
+        self.inner.wrap_cdi(cdi, key, aad, wrapped)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{SimCrypto, SimTypes};
+    use dpe::{
+        commands::{
+            AttestCmd, CertifyKeyCmd, CertifyKeyFlags, CommandExecution, DeriveContextCmd,
+            DeriveContextFlags, ExtendTciCmd, InitCtxCmd, RotateCtxCmd, RotateCtxFlags, SignCmd,
+            SignFlags, TagTciCmd,
+        },
+        context::ContextHandle,
+        dpe_instance::DpeEnv,
+        response::{DpeErrorCode, Response},
+        support::Support,
+        DpeInstance, DPE_PROFILE,
+    };
+    use platform::default::{DefaultPlatform, AUTO_INIT_LOCALITY};
+    use zerocopy::AsBytes;
+
+    fn new_env() -> DpeEnv<'static, SimTypes> {
+        DpeEnv {
+            crypto: FaultyCrypto::new(SimCrypto::new()),
+            platform: DefaultPlatform,
+            arena: &mut [],
+        }
+    }
+
+    fn new_dpe(env: &mut DpeEnv<SimTypes>) -> DpeInstance {
+        let support = Support::AUTO_INIT | Support::SIMULATION | Support::X509;
+        DpeInstance::new(env, support).unwrap()
+    }
+
+    #[test]
+    fn test_parse_fault() {
+        assert_eq!("hash:3".parse(), Ok(Fault::Hash(3)));
+        assert_eq!("sign:1".parse(), Ok(Fault::Sign(1)));
+        assert_eq!("rng:0".parse(), Ok(Fault::Rng(0)));
+        assert!("hash:0".parse::<Fault>().is_err());
+        assert!("sign".parse::<Fault>().is_err());
+        assert!("aead:1".parse::<Fault>().is_err());
+        assert!("rng:-1".parse::<Fault>().is_err());
+    }
+
+    #[test]
+    fn test_counts_from_set_faults() {
+        let mut crypto = FaultyCrypto::new(SimCrypto::new());
+        let algs = DPE_PROFILE.alg_len();
+        crypto.hash(algs, b"before").unwrap();
+
+        crypto.set_faults(&[Fault::Hash(2), Fault::Rng(1)]);
+        assert!(crypto.hash(algs, b"first").is_ok());
+        assert_eq!(
+            crypto.hash_initialize(algs).err(),
+            Some(CryptoError::HashError(INJECTED_FAULT))
+        );
+        assert!(crypto.hash(algs, b"third").is_ok());
+
+        let mut buf = [0u8; 4];
+        assert!(crypto.rand_bytes(&mut buf).is_ok());
+        for _ in 0..3 {
+            assert_eq!(
+                crypto.rand_bytes(&mut buf),
+                Err(CryptoError::CryptoLibError(INJECTED_FAULT))
+            );
+        }
+
+        crypto.set_faults(&[]);
+        assert!(crypto.rand_bytes(&mut buf).is_ok());
+    }
+
+    #[test]
+    fn test_hash_faults_leave_no_partial_state() {
+        // Fail each hash DeriveContext does in turn, until it does none.
+        let cmd = DeriveContextCmd {
+            handle: ContextHandle::default(),
+            data: [0x11; DPE_PROFILE.get_hash_size()],
+            flags: DeriveContextFlags::MAKE_DEFAULT | DeriveContextFlags::INPUT_ALLOW_X509,
+            tci_type: 0x5446_5043,
+            target_locality: AUTO_INIT_LOCALITY,
+        };
+        for n in 1.. {
+            let mut env = new_env();
+            let mut dpe = new_dpe(&mut env);
+            let before = dpe.as_bytes().to_vec();
+
+            env.crypto.set_faults(&[Fault::Hash(n)]);
+            match cmd.execute(&mut dpe, &mut env, AUTO_INIT_LOCALITY) {
+                Err(err) => {
+                    assert_eq!(
+                        err,
+                        DpeErrorCode::Crypto(CryptoError::HashError(INJECTED_FAULT))
+                    );
+                    assert_eq!(dpe.as_bytes(), before, "hash {n} left partial state");
+                }
+                Ok(_) => {
+                    assert!(n > 1, "DeriveContext did not hash");
+                    break;
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_sign_fault_keeps_contexts() {
+        let mut env = new_env();
+        let mut dpe = new_dpe(&mut env);
+        let before = dpe.as_bytes().to_vec();
+        let cmd = CertifyKeyCmd {
+            handle: ContextHandle::default(),
+            flags: CertifyKeyFlags::empty(),
+            label: [0; DPE_PROFILE.get_hash_size()],
+            format: CertifyKeyCmd::FORMAT_X509,
+        };
+
+        env.crypto.set_faults(&[Fault::Sign(1)]);
+        assert_eq!(
+            cmd.execute(&mut dpe, &mut env, AUTO_INIT_LOCALITY).err(),
+            Some(DpeErrorCode::Crypto(CryptoError::CryptoLibError(
+                INJECTED_FAULT
+            )))
+        );
+        assert_eq!(dpe.as_bytes(), before);
+
+        // Only the first signature fails.
+        assert!(cmd.execute(&mut dpe, &mut env, AUTO_INIT_LOCALITY).is_ok());
+    }
+
+    #[test]
+    fn test_rng_exhaustion_leaks_no_context() {
+        let mut env = new_env();
+        let mut dpe = new_dpe(&mut env);
+        let before = dpe.as_bytes().to_vec();
+
+        // A simulation context needs a random handle.
+        env.crypto.set_faults(&[Fault::Rng(0)]);
+        assert_eq!(
+            InitCtxCmd::new_simulation()
+                .execute(&mut dpe, &mut env, AUTO_INIT_LOCALITY)
+                .err(),
+            Some(DpeErrorCode::Crypto(CryptoError::CryptoLibError(
+                INJECTED_FAULT
+            )))
+        );
+        assert_eq!(dpe.as_bytes(), before);
+
+        env.crypto.set_faults(&[]);
+        assert!(InitCtxCmd::new_simulation()
+            .execute(&mut dpe, &mut env, AUTO_INIT_LOCALITY)
+            .is_ok());
+    }
+
+    /// Locality of the context `new_dpe_with_child` derives.
+    const CHILD_LOCALITY: u32 = 1;
+
+    /// Builds an instance whose auto-initialized context has a child with a
+    /// random handle in `CHILD_LOCALITY`, and returns the child's handle.
+    /// Commands on the child rotate its handle, which takes random bytes.
+    fn new_dpe_with_child(env: &mut DpeEnv<SimTypes>) -> (DpeInstance, ContextHandle) {
+        let support = Support::AUTO_INIT
+            | Support::X509
+            | Support::CSR
+            | Support::CWT
+            | Support::RECURSIVE
+            | Support::RETAIN_PARENT_CONTEXT
+            | Support::EXTEND_TCI
+            | Support::TAGGING
+            | Support::ROTATE_CONTEXT
+            | Support::LAYERED_CERTS
+            | Support::CERT_CACHE
+            | Support::ATTEST;
+        let mut dpe = DpeInstance::new(env, support).unwrap();
+        let cmd = DeriveContextCmd {
+            handle: ContextHandle::default(),
+            data: [0x11; DPE_PROFILE.get_hash_size()],
+            flags: DeriveContextFlags::RETAIN_PARENT_CONTEXT
+                | DeriveContextFlags::INPUT_ALLOW_X509
+                | DeriveContextFlags::CHANGE_LOCALITY,
+            tci_type: 0,
+            target_locality: CHILD_LOCALITY,
+        };
+        match cmd.execute(&mut dpe, env, AUTO_INIT_LOCALITY) {
+            Ok(Response::DeriveContext(resp)) => (dpe, resp.handle),
+            _ => panic!("DeriveContext failed"),
+        }
+    }
+
+    /// Fails each hash, signature and request for random bytes `run` makes
+    /// in turn, and checks that none of the failures changes the instance,
+    /// including the pending certificate, or leaves a CDI or key behind.
+    fn check_atomic(
+        run: impl Fn(
+            &mut DpeInstance,
+            &mut DpeEnv<SimTypes>,
+            ContextHandle,
+        ) -> Result<Response, DpeErrorCode>,
+    ) {
+        let faults: [fn(u64) -> Fault; 3] = [Fault::Hash, Fault::Sign, |n| Fault::Rng(n - 1)];
+        for fault in faults {
+            for n in 1.. {
+                let mut env = new_env();
+                let (mut dpe, handle) = new_dpe_with_child(&mut env);
+                let before = dpe.as_bytes().to_vec();
+
+                env.crypto.set_faults(&[fault(n)]);
+                let result = run(&mut dpe, &mut env, handle);
+                assert_eq!(env.crypto.live_handles(), 0, "{:?} leaked", fault(n));
+                match result {
+                    Ok(_) => break,
+                    Err(DpeErrorCode::Crypto(err))
+                        if err.get_error_detail() == Some(INJECTED_FAULT) =>
+                    {
+                        assert!(
+                            dpe.as_bytes() == before,
+                            "{:?} left partial state",
+                            fault(n)
+                        );
+                    }
+                    Err(err) => panic!("{:?} failed with {err:?}", fault(n)),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_derive_context_is_atomic() {
+        for flags in [
+            DeriveContextFlags::INPUT_ALLOW_X509,
+            DeriveContextFlags::RETAIN_PARENT_CONTEXT,
+            DeriveContextFlags::RECURSIVE,
+        ] {
+            check_atomic(|dpe, env, handle| {
+                DeriveContextCmd {
+                    handle,
+                    data: [0x22; DPE_PROFILE.get_hash_size()],
+                    flags,
+                    tci_type: 0,
+                    target_locality: CHILD_LOCALITY,
+                }
+                .execute(dpe, env, CHILD_LOCALITY)
+            });
+        }
+    }
+
+    #[test]
+    fn test_extend_tci_is_atomic() {
+        check_atomic(|dpe, env, handle| {
+            ExtendTciCmd {
+                handle,
+                data: [0x33; DPE_PROFILE.get_hash_size()],
+            }
+            .execute(dpe, env, CHILD_LOCALITY)
+        });
+    }
+
+    #[test]
+    fn test_certify_key_is_atomic() {
+        for format in [
+            CertifyKeyCmd::FORMAT_X509,
+            CertifyKeyCmd::FORMAT_CSR,
+            CertifyKeyCmd::FORMAT_CWT,
+        ] {
+            check_atomic(|dpe, env, handle| {
+                CertifyKeyCmd {
+                    handle,
+                    flags: CertifyKeyFlags::empty(),
+                    label: [0; DPE_PROFILE.get_hash_size()],
+                    format,
+                }
+                .execute(dpe, env, CHILD_LOCALITY)
+            });
+        }
+    }
+
+    #[test]
+    fn test_attest_is_atomic() {
+        check_atomic(|dpe, env, handle| {
+            AttestCmd {
+                handle,
+                label: [0; DPE_PROFILE.get_hash_size()],
+                nonce: [0x55; DPE_PROFILE.get_hash_size()],
+            }
+            .execute(dpe, env, CHILD_LOCALITY)
+        });
+    }
+
+    #[test]
+    fn test_sign_is_atomic() {
+        check_atomic(|dpe, env, handle| {
+            SignCmd {
+                handle,
+                label: [0; DPE_PROFILE.get_hash_size()],
+                flags: SignFlags::empty(),
+                digest: [0x44; DPE_PROFILE.get_hash_size()],
+            }
+            .execute(dpe, env, CHILD_LOCALITY)
+        });
+    }
+
+    #[test]
+    fn test_handle_rotation_is_atomic() {
+        check_atomic(|dpe, env, handle| {
+            TagTciCmd { handle, tag: 7 }.execute(dpe, env, CHILD_LOCALITY)
+        });
+        check_atomic(|dpe, env, handle| {
+            RotateCtxCmd {
+                handle,
+                flags: RotateCtxFlags::empty(),
+            }
+            .execute(dpe, env, CHILD_LOCALITY)
+        });
+    }
+}
@@ -3,50 +3,195 @@
 #[cfg(not(any(feature = "openssl", feature = "rustcrypto")))]
 compile_error!("must provide a crypto implementation");
 
-use clap::Parser;
+use clap::{Parser, ValueEnum};
 use log::{error, info, trace, warn};
 use platform::default::DefaultPlatform;
+use platform::mctp::{
+    Fragmenter, Message, Reassembler, BASELINE_MTU, MAX_PACKET_SIZE, MSG_TYPE_DPE,
+};
 use std::fs;
 use std::io::{Error, ErrorKind, Read, Write};
-use std::os::unix::net::{UnixListener, UnixStream};
-use std::path::Path;
-use std::process;
+use std::net::{SocketAddr, TcpListener};
+use std::os::unix::net::UnixListener;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::thread;
+use std::time::Duration;
 
 use dpe::{
     commands::Command,
     dpe_instance::{DpeEnv, DpeTypes},
-    response::Response,
+    response::{Response, ResponseHdr},
     support::Support,
+    wire::WireEndian,
     DpeInstance,
 };
+use dpe_client::transport::{
+    read_frame, read_mctp_packet, write_frame, write_mctp_packet, MAX_REQUEST_SIZE,
+};
 
 #[cfg(feature = "rustcrypto")]
 use crypto::RustCryptoImpl;
 
 #[cfg(feature = "openssl")]
 use crypto::OpensslCrypto;
+use crypto::{HkdfKdf, Kdf, Sp800108CtrKdf};
+
+mod fault;
+use fault::{Fault, FaultyCrypto};
+
+mod replay;
+use replay::Recorder;
+
+mod state_file;
 
 const SOCKET_PATH: &str = "/tmp/dpe-sim.socket";
 
-fn handle_request(dpe: &mut DpeInstance, env: &mut DpeEnv<impl DpeTypes>, stream: &mut UnixStream) {
-    let mut buf = [0u8; 4096];
-    let (locality, cmd) = {
-        let len = stream.read(&mut buf).unwrap();
-        (
-            u32::from_le_bytes(buf[..4].try_into().unwrap()),
-            &buf[4..len],
-        )
+/// Largest MCTP message accepted in `--mctp` mode, counting the message type.
+const MAX_MCTP_REQUEST_SIZE: usize = 4096;
+
+/// How long to wait between polls for new connections while checking for shutdown.
+const ACCEPT_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// The DPE instance and its environment, shared by every connection.
+struct SimState {
+    dpe: DpeInstance,
+    env: DpeEnv<'static, SimTypes>,
+    recorder: Option<Recorder>,
+}
+
+/// Handles a single request read from `stream`.
+///
+/// Requests and responses are each preceded by their length, as written by
+/// `write_frame`. Returns false once the peer has closed the connection or
+/// sent a malformed request, at which point the connection should be
+/// dropped. If `connection_locality` is set it replaces the locality sent by
+/// the client.
+fn handle_request(
+    state: &Mutex<SimState>,
+    stream: &mut (impl Read + Write),
+    connection_locality: Option<u32>,
+) -> bool {
+    let request = match read_frame(stream, MAX_REQUEST_SIZE) {
+        Ok(Some(request)) if request.len() >= 4 => request,
+        Ok(Some(request)) => {
+            warn!("Dropping {} byte request without a locality", request.len());
+            return false;
+        }
+        Ok(None) => return false,
+        Err(err) => {
+            warn!("Failed to read request: {err}");
+            return false;
+        }
+    };
+    let (locality, cmd) = request.split_at(4);
+    let locality =
+        connection_locality.unwrap_or_else(|| u32::from_le_bytes(locality.try_into().unwrap()));
+
+    let response = execute(state, locality, cmd);
+    if let Err(err) = write_frame(stream, &[response.as_bytes()]) {
+        warn!("Failed to write response: {err}");
+        return false;
+    }
+    true
+}
+
+/// Handles a single request carried in MCTP packets read from `stream`.
+///
+/// Returns false once the peer has closed the connection. Packets that can't
+/// be reassembled are dropped.
+fn handle_mctp_request(
+    state: &Mutex<SimState>,
+    stream: &mut (impl Read + Write),
+    connection_locality: Option<u32>,
+    reassembler: &mut Reassembler<MAX_MCTP_REQUEST_SIZE>,
+) -> bool {
+    let mut buf = [0u8; MAX_PACKET_SIZE];
+    loop {
+        let packet = match read_mctp_packet(stream, &mut buf) {
+            Ok(Some(packet)) => packet,
+            Ok(None) => return false,
+            Err(err) => {
+                warn!("Failed to read MCTP packet: {err}");
+                return false;
+            }
+        };
+        match reassembler.push(packet) {
+            Ok(Some(msg)) => return respond_mctp(state, stream, connection_locality, &msg),
+            Ok(None) => (),
+            Err(err) => warn!("Dropping MCTP packet: {err:?}"),
+        }
+    }
+}
+
+/// Executes a reassembled MCTP request and sends the response back in
+/// baseline MTU packets.
+///
+/// The locality is the source EID of the request unless `connection_locality`
+/// is set.
+fn respond_mctp(
+    state: &Mutex<SimState>,
+    stream: &mut impl Write,
+    connection_locality: Option<u32>,
+    msg: &Message,
+) -> bool {
+    if msg.msg_type != MSG_TYPE_DPE {
+        warn!("Dropping MCTP message of type {:#04x}", msg.msg_type);
+        return true;
+    }
+
+    let locality = connection_locality.unwrap_or(msg.info.src_eid.into());
+    let response = execute(state, locality, msg.body);
+    let Ok(mut fragmenter) = Fragmenter::new(
+        msg.info.response(),
+        BASELINE_MTU,
+        MSG_TYPE_DPE,
+        response.as_bytes(),
+    ) else {
+        return false;
     };
+    let mut out = [0u8; MAX_PACKET_SIZE];
+    while let Some(len) = fragmenter.next_packet(&mut out) {
+        if let Err(err) = write_mctp_packet(stream, &out[..len]) {
+            warn!("Failed to write response: {err}");
+            return false;
+        }
+    }
+    true
+}
 
+/// Executes `cmd` against the shared instance, recording and tracing it.
+fn execute(state: &Mutex<SimState>, locality: u32, cmd: &[u8]) -> Response {
     trace!("----------------------------------");
+    // The command itself isn't logged, since its handle is a bearer secret.
     if let Ok(command) = Command::deserialize(cmd) {
-        trace!("| Locality `{locality:#x}` requested {command:x?}",);
+        let cmd_id = u32::from(&command);
+        trace!("| Locality `{locality:#x}` requested command {cmd_id:#x}");
     } else {
-        trace!("| Locality `{locality:#010x}` requested invalid command. {cmd:02x?}")
+        trace!(
+            "| Locality `{locality:#010x}` requested invalid command of {} bytes",
+            cmd.len()
+        )
     }
     trace!("|");
 
-    let response = dpe.execute_serialized_command(env, locality, cmd).unwrap();
+    let response = {
+        // Keep serving other connections if one panicked while holding the lock.
+        let mut state = state.lock().unwrap_or_else(PoisonError::into_inner);
+        let SimState { dpe, env, recorder } = &mut *state;
+        let response = match dpe.execute_serialized_command(env, locality, cmd) {
+            Ok(response) => response,
+            Err(err) => Response::Error(ResponseHdr::new(err)).to_le(),
+        };
+        // Record while still holding the lock so the log keeps execution order.
+        if let Some(recorder) = recorder {
+            if let Err(err) = recorder.record(locality, cmd, response.as_bytes()) {
+                warn!("Failed to record command: {err}");
+            }
+        }
+        response
+    };
 
     let response_code = match response {
         Response::GetProfile(ref res) => res.resp_hdr.status,
@@ -57,13 +202,46 @@ fn handle_request(dpe: &mut DpeInstance, env: &mut DpeEnv<impl DpeTypes>, stream
         Response::Sign(ref res) => res.resp_hdr.status,
         Response::DestroyCtx(ref resp_hdr) => resp_hdr.status,
         Response::GetCertificateChain(ref res) => res.resp_hdr.status,
+        Response::ExtendTci(ref res) => res.resp_hdr.status,
+        Response::TagTci(ref res) => res.resp_hdr.status,
+        Response::GetTaggedTci(ref res) => res.resp_hdr.status,
+        Response::OpenSession(ref res) => res.resp_hdr.status,
+        Response::CloseSession(ref resp_hdr) => resp_hdr.status,
+        Response::GetCertificateChunk(ref res) => res.resp_hdr.status,
+        Response::ExportCdi(ref res) => res.resp_hdr.status,
+        Response::GetCommandCounter(ref res) => res.resp_hdr.status,
+        Response::HashInitialize(ref res) => res.resp_hdr.status,
+        Response::HashUpdate(ref resp_hdr) => resp_hdr.status,
+        Response::HashFinal(ref res) => res.resp_hdr.status,
+        Response::GetContextInfo(ref res) => res.resp_hdr.status,
+        Response::Attest(ref res) => res.resp_hdr.status,
+        Response::Seal(ref res) => res.resp_hdr.status,
+        Response::Unseal(ref res) => res.resp_hdr.status,
+        Response::Vendor(ref res) => res.resp_hdr.status,
+        // The status of a session response is encrypted.
+        Response::Session(_) => 0,
         Response::Error(ref resp_hdr) => resp_hdr.status,
     };
     // There are a few vendor error codes starting at 0x1000, so this can be a 2 bytes.
     trace!("| Response Code {response_code:#06x}");
     trace!("----------------------------------");
 
-    stream.write_all(response.as_bytes()).unwrap();
+    response
+}
+
+/// Serves requests on one connection until the peer closes it.
+fn handle_connection(
+    state: &Mutex<SimState>,
+    mut stream: impl Read + Write,
+    connection_locality: Option<u32>,
+    mctp: bool,
+) {
+    if mctp {
+        let mut reassembler = Box::new(Reassembler::<MAX_MCTP_REQUEST_SIZE>::new());
+        while handle_mctp_request(state, &mut stream, connection_locality, &mut reassembler) {}
+    } else {
+        while handle_request(state, &mut stream, connection_locality) {}
+    }
 }
 
 fn cleanup() {
@@ -72,10 +250,91 @@ fn cleanup() {
     }
 }
 
+/// Accepts connections until `running` is cleared, serving each one on its
+/// own thread.
+///
+/// `accept` must not block so that a shutdown request is noticed while idle.
+/// Requests from all connections are executed one at a time against the
+/// shared DPE instance. When `locality_per_connection` is set, the n-th
+/// connection acts as locality n, so the first one owns the auto-initialized
+/// context. Otherwise every connection acts as `listener_locality` if it is
+/// set, and each request carries its own locality if it is not. When `mctp`
+/// is set, requests and responses are carried in MCTP packets.
+fn serve<S: Read + Write + Send + 'static>(
+    state: Arc<Mutex<SimState>>,
+    running: &AtomicBool,
+    locality_per_connection: bool,
+    listener_locality: Option<u32>,
+    mctp: bool,
+    mut accept: impl FnMut() -> std::io::Result<S>,
+) -> std::io::Result<()> {
+    let mut next_locality = 0u32;
+    while running.load(Ordering::SeqCst) {
+        match accept() {
+            Ok(stream) => {
+                let connection_locality = if locality_per_connection {
+                    let locality = next_locality;
+                    next_locality = next_locality.wrapping_add(1);
+                    info!("Connection assigned locality {locality:#x}");
+                    Some(locality)
+                } else {
+                    listener_locality
+                };
+                let state = state.clone();
+                thread::spawn(move || handle_connection(&state, stream, connection_locality, mctp));
+            }
+            Err(err) if err.kind() == ErrorKind::WouldBlock => thread::sleep(ACCEPT_POLL_INTERVAL),
+            Err(err) => {
+                error!("Failed to open socket: {err}");
+                return Err(err);
+            }
+        }
+    }
+    info!("DPE simulator shutting down");
+    Ok(())
+}
+
 /// Starts a DPE simulator that will receive commands and send responses over unix streams.
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 struct Args {
+    /// Listen for commands on a TCP address instead of the Unix socket.
+    #[arg(long, value_name = "ADDR:PORT")]
+    tcp: Option<SocketAddr>,
+
+    /// Locality of every request received over TCP. The locality sent with
+    /// each request is ignored, since any peer could claim any locality.
+    #[arg(long, value_name = "LOCALITY", value_parser = parse_u32, default_value = "0")]
+    tcp_locality: u32,
+
+    /// Give each connection its own locality instead of using the locality
+    /// sent with each request, or --tcp-locality. Connections are numbered
+    /// from 0 in the order they are accepted.
+    #[arg(long)]
+    locality_per_connection: bool,
+
+    /// Exchange MCTP packets instead of locality-prefixed commands. Each
+    /// packet is preceded by its length as a little-endian u16, and the source
+    /// EID of a request is used as its locality.
+    #[arg(long)]
+    mctp: bool,
+
+    /// Log every command and response to FILE.
+    #[arg(long, value_name = "FILE")]
+    record: Option<PathBuf>,
+
+    /// Replay a log written with --record against a fresh instance, check that
+    /// every response matches, and exit. Pass the same --supports-* flags used
+    /// when recording.
+    #[arg(long, value_name = "FILE", conflicts_with = "record")]
+    replay: Option<PathBuf>,
+
+    /// Restore the DPE state from FILE on startup if it exists, and save it
+    /// there on shutdown. A restored instance keeps the --supports-* flags
+    /// it was created with.
+    #[arg(long, value_name = "FILE", conflicts_with = "replay")]
+    state_file: Option<PathBuf>,
+
     /// Supports simulation contexts.
     #[arg(long)]
     supports_simulation: bool,
@@ -88,6 +347,10 @@ struct Args {
     #[arg(long)]
     supports_auto_init: bool,
 
+    /// Supports the TagTci and GetTaggedTci commands.
+    #[arg(long)]
+    supports_tagging: bool,
+
     /// Supports the RotateContextHandle command.
     #[arg(long)]
     supports_rotate_context: bool,
@@ -119,15 +382,138 @@ struct Args {
     /// Supports the RETAIN_PARENT_CONTEXT extension to DeriveContext
     #[arg(long)]
     supports_retain_parent_context: bool,
+
+    /// Supports the ExtendTci command.
+    #[arg(long)]
+    supports_extend_tci: bool,
+
+    /// Supports the OpenSession and CloseSession commands.
+    #[arg(long)]
+    supports_sessions: bool,
+
+    /// Supports the ExportCdi command.
+    #[arg(long)]
+    supports_export_cdi: bool,
+
+    /// Supports the CWT format in CertifyKey.
+    #[arg(long)]
+    supports_cwt: bool,
+
+    /// Supports per-context command counters and the GetCommandCounter command.
+    #[arg(long)]
+    supports_command_counter: bool,
+
+    /// Supports hashing data over several commands with HashInitialize, HashUpdate and HashFinal.
+    #[arg(long)]
+    supports_hash_stream: bool,
+
+    /// Caches measurement digests and CDIs so signing doesn't walk the context chain and run the
+    /// KDF every time.
+    #[arg(long)]
+    supports_measurement_cache: bool,
+
+    /// Supports inspecting the context tree with GetContextInfo.
+    #[arg(long)]
+    supports_context_info: bool,
+
+    /// Issues the X.509 certificates of derived contexts with the parent
+    /// context's key and adds those keys' certificates to the chain.
+    #[arg(long)]
+    supports_layered_certs: bool,
+
+    /// Binds random context handles to their locality with a MAC so that
+    /// forged handles are rejected before any context is looked up.
+    #[arg(long)]
+    supports_handle_mac: bool,
+
+    /// Issues a self-signed DeviceID certificate when the default context is
+    /// initialized and serves it in place of the platform's certificate chain.
+    #[arg(long)]
+    supports_device_id_cert: bool,
+
+    /// Requires every command except GetProfile to carry an HMAC computed
+    /// with its locality's key, or to be sent over an encrypted session.
+    #[arg(long)]
+    supports_command_auth: bool,
+
+    /// Supports security version numbers in DeriveContext.
+    #[arg(long)]
+    supports_svn: bool,
+
+    /// Supports returning the last certificate again from a repeated CertifyKey.
+    #[arg(long)]
+    supports_cert_cache: bool,
+
+    /// Supports seeding simulation contexts in InitializeContext.
+    #[arg(long)]
+    supports_simulation_seed: bool,
+
+    /// Supports attestation tokens.
+    #[arg(long)]
+    supports_attest: bool,
+
+    /// Supports the Seal and Unseal commands.
+    #[arg(long)]
+    supports_seal: bool,
+
+    /// Maximum number of simulation contexts that may be open at once.
+    /// Defaults to the size of the context table.
+    #[arg(long, value_name = "COUNT")]
+    max_simulation_contexts: Option<u32>,
+
+    /// Maximum number of contexts, counted from a root, that DeriveContext
+    /// may chain together. Defaults to the size of the context table.
+    #[arg(long, value_name = "DEPTH")]
+    max_cert_chain_depth: Option<u32>,
+
+    /// Key derivation function used for CDIs and derived keys.
+    #[arg(long, value_enum, default_value_t = KdfArg::Hkdf)]
+    kdf: KdfArg,
+
+    /// Make a crypto operation fail: `hash:N` fails the Nth hash, `sign:N`
+    /// the Nth signature and `rng:N` every request for random bytes after the
+    /// first N. Operations are counted from the first command. May be given
+    /// more than once.
+    #[arg(long, value_name = "OP:N")]
+    fault: Vec<Fault>,
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum KdfArg {
+    /// HKDF (RFC 5869).
+    Hkdf,
+    /// NIST SP 800-108 counter mode with HMAC.
+    #[value(name = "sp800-108-ctr")]
+    Sp800108Ctr,
+}
+
+impl KdfArg {
+    fn kdf(self) -> &'static dyn Kdf {
+        match self {
+            KdfArg::Hkdf => &HkdfKdf,
+            KdfArg::Sp800108Ctr => &Sp800108CtrKdf,
+        }
+    }
 }
 
+/// Parses a decimal or 0x-prefixed hex integer.
+fn parse_u32(arg: &str) -> Result<u32, String> {
+    let result = match arg.strip_prefix("0x") {
+        Some(hex) => u32::from_str_radix(hex, 16),
+        None => arg.parse(),
+    };
+    result.map_err(|err| err.to_string())
+}
+
+#[cfg(feature = "rustcrypto")]
+type SimCrypto = RustCryptoImpl;
+#[cfg(feature = "openssl")]
+type SimCrypto = OpensslCrypto;
+
 struct SimTypes {}
 
 impl DpeTypes for SimTypes {
-    #[cfg(feature = "rustcrypto")]
-    type Crypto<'a> = RustCryptoImpl;
-    #[cfg(feature = "openssl")]
-    type Crypto<'a> = OpensslCrypto;
+    type Crypto<'a> = FaultyCrypto<SimCrypto>;
 
     type Platform<'a> = DefaultPlatform;
 }
@@ -136,23 +522,14 @@ fn main() -> std::io::Result<()> {
     env_logger::init();
     let args = Args::parse();
 
-    let socket = Path::new(SOCKET_PATH);
-    // Delete old socket if necessary
-    if socket.exists() {
-        cleanup();
-    }
-
-    let listener = UnixListener::bind(socket)?;
-
-    ctrlc::set_handler(move || {
-        cleanup();
-        process::exit(0);
-    })
-    .unwrap();
+    let running = Arc::new(AtomicBool::new(true));
+    let handler_running = running.clone();
+    ctrlc::set_handler(move || handler_running.store(false, Ordering::SeqCst)).unwrap();
 
     let mut support = Support::default();
     support.set(Support::SIMULATION, args.supports_simulation);
     support.set(Support::AUTO_INIT, args.supports_auto_init);
+    support.set(Support::TAGGING, args.supports_tagging);
     support.set(Support::X509, args.supports_x509);
     support.set(Support::CSR, args.supports_csr);
     support.set(Support::RECURSIVE, args.supports_recursive);
@@ -165,33 +542,239 @@ fn main() -> std::io::Result<()> {
         Support::RETAIN_PARENT_CONTEXT,
         args.supports_retain_parent_context,
     );
+    support.set(Support::EXTEND_TCI, args.supports_extend_tci);
+    support.set(Support::SESSIONS, args.supports_sessions);
+    support.set(Support::EXPORT_CDI, args.supports_export_cdi);
+    support.set(Support::CWT, args.supports_cwt);
+    support.set(Support::COMMAND_COUNTER, args.supports_command_counter);
+    support.set(Support::HASH_STREAM, args.supports_hash_stream);
+    support.set(Support::MEASUREMENT_CACHE, args.supports_measurement_cache);
+    support.set(Support::CONTEXT_INFO, args.supports_context_info);
+    support.set(Support::LAYERED_CERTS, args.supports_layered_certs);
+    support.set(Support::HANDLE_MAC, args.supports_handle_mac);
+    support.set(Support::DEVICE_ID_CERT, args.supports_device_id_cert);
+    support.set(Support::COMMAND_AUTH, args.supports_command_auth);
+    support.set(Support::SVN, args.supports_svn);
+    support.set(Support::CERT_CACHE, args.supports_cert_cache);
+    support.set(Support::SIMULATION_SEED, args.supports_simulation_seed);
+    support.set(Support::ATTEST, args.supports_attest);
+    support.set(Support::SEAL, args.supports_seal);
 
     let mut env = DpeEnv::<SimTypes> {
-        crypto: <SimTypes as DpeTypes>::Crypto::new(),
+        crypto: FaultyCrypto::new(SimCrypto::with_kdf(args.kdf.kdf())),
         platform: DefaultPlatform,
+        arena: &mut [],
     };
 
-    let mut dpe = DpeInstance::new(&mut env, support).map_err(|err| {
-        Error::new(
-            ErrorKind::Other,
-            format!("{err:?} while creating new DPE instance"),
-        )
-    })?;
+    let restored = match &args.state_file {
+        Some(path) => state_file::load(path, &mut env)?,
+        None => None,
+    };
+    let mut dpe = match restored {
+        Some(dpe) => dpe,
+        None => DpeInstance::new(&mut env, support).map_err(|err| {
+            Error::new(
+                ErrorKind::Other,
+                format!("{err:?} while creating new DPE instance"),
+            )
+        })?,
+    };
+    if let Some(max) = args.max_simulation_contexts {
+        dpe.set_max_simulation_contexts(max).map_err(|err| {
+            Error::new(
+                ErrorKind::InvalidInput,
+                format!("{err:?} while setting --max-simulation-contexts"),
+            )
+        })?;
+    }
+    if let Some(max) = args.max_cert_chain_depth {
+        dpe.set_max_cert_chain_depth(max).map_err(|err| {
+            Error::new(
+                ErrorKind::InvalidInput,
+                format!("{err:?} while setting --max-cert-chain-depth"),
+            )
+        })?;
+    }
 
-    info!("DPE listening to socket {SOCKET_PATH}");
+    env.crypto.set_faults(&args.fault);
 
-    for stream in listener.incoming() {
-        match stream {
-            Ok(mut stream) => {
-                handle_request(&mut dpe, &mut env, &mut stream);
-            }
-            Err(err) => {
-                error!("Failed to open socket: {err}");
+    if let Some(path) = args.replay {
+        return replay::replay(&path, &mut dpe, &mut env);
+    }
+    let recorder = args.record.as_deref().map(Recorder::create).transpose()?;
+    let state = Arc::new(Mutex::new(SimState { dpe, env, recorder }));
+
+    let result = match args.tcp {
+        Some(addr) => {
+            let listener = TcpListener::bind(addr)?;
+            listener.set_nonblocking(true)?;
+            info!("DPE listening on {}", listener.local_addr()?);
+
+            serve(
+                state.clone(),
+                &running,
+                args.locality_per_connection,
+                Some(args.tcp_locality),
+                args.mctp,
+                || {
+                    let (stream, peer) = listener.accept()?;
+                    trace!("Accepted connection from {peer}");
+                    stream.set_nonblocking(false)?;
+                    stream.set_nodelay(true)?;
+                    Ok(stream)
+                },
+            )
+        }
+        None => {
+            let socket = Path::new(SOCKET_PATH);
+            // Delete old socket if necessary
+            if socket.exists() {
                 cleanup();
-                break;
             }
+
+            let listener = UnixListener::bind(socket)?;
+            listener.set_nonblocking(true)?;
+            info!("DPE listening to socket {SOCKET_PATH}");
+
+            let result = serve(
+                state.clone(),
+                &running,
+                args.locality_per_connection,
+                None,
+                args.mctp,
+                || {
+                    let (stream, _) = listener.accept()?;
+                    stream.set_nonblocking(false)?;
+                    Ok(stream)
+                },
+            );
+            cleanup();
+            result
+        }
+    };
+
+    if let Some(path) = &args.state_file {
+        // Save the state even if a connection thread panicked while holding it.
+        let mut state = state.lock().unwrap_or_else(PoisonError::into_inner);
+        let SimState { dpe, env, .. } = &mut *state;
+        if let Err(err) = state_file::save(path, dpe, env) {
+            error!("Failed to save DPE state to {}: {err}", path.display());
+            return Err(err);
         }
     }
+    result
+}
 
-    Ok(())
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dpe::{
+        commands::{CertifyKeyCmd, CertifyKeyFlags, CommandHdr},
+        context::ContextHandle,
+        DPE_PROFILE,
+    };
+    use platform::default::AUTO_INIT_LOCALITY;
+    use zerocopy::{AsBytes, FromBytes};
+
+    /// Hands out its input one byte per read, like a connection that splits
+    /// a request across many segments, and collects what is written to it.
+    struct TrickleStream {
+        input: Vec<u8>,
+        pos: usize,
+        output: Vec<u8>,
+    }
+
+    impl Read for TrickleStream {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            match (self.input.get(self.pos), buf.first_mut()) {
+                (Some(byte), Some(out)) => {
+                    *out = *byte;
+                    self.pos += 1;
+                    Ok(1)
+                }
+                _ => Ok(0),
+            }
+        }
+    }
+
+    impl Write for TrickleStream {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.output.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Sends a CertifyKey for the default context claiming `claimed_locality`
+    /// and returns the response status.
+    fn certify_default(
+        state: &Mutex<SimState>,
+        claimed_locality: u32,
+        connection_locality: Option<u32>,
+    ) -> u32 {
+        let cmd = CertifyKeyCmd {
+            handle: ContextHandle::default(),
+            flags: CertifyKeyFlags::empty(),
+            label: [0; DPE_PROFILE.get_hash_size()],
+            format: CertifyKeyCmd::FORMAT_X509,
+        };
+        let mut request = Vec::new();
+        write_frame(
+            &mut request,
+            &[
+                &claimed_locality.to_le_bytes(),
+                CommandHdr::new(Command::CERTIFY_KEY).as_bytes(),
+                cmd.as_bytes(),
+            ],
+        )
+        .unwrap();
+        let mut stream = TrickleStream {
+            input: request,
+            pos: 0,
+            output: Vec::new(),
+        };
+
+        assert!(handle_request(state, &mut stream, connection_locality));
+        // The whole request was consumed and nothing else.
+        assert_eq!(stream.pos, stream.input.len());
+        let response = read_frame(&mut stream.output.as_slice(), usize::MAX)
+            .unwrap()
+            .unwrap();
+        ResponseHdr::read_from_prefix(response.as_slice())
+            .unwrap()
+            .status
+    }
+
+    #[test]
+    fn test_server_assigned_locality() {
+        let mut env = DpeEnv::<SimTypes> {
+            crypto: FaultyCrypto::new(SimCrypto::new()),
+            platform: DefaultPlatform,
+            arena: &mut [],
+        };
+        let dpe = DpeInstance::new(&mut env, Support::AUTO_INIT | Support::X509).unwrap();
+        let state = Mutex::new(SimState {
+            dpe,
+            env,
+            recorder: None,
+        });
+        let other_locality = u32::from_be_bytes(*b"OTHR");
+
+        // Without a connection locality the client picks its own.
+        assert_eq!(0, certify_default(&state, AUTO_INIT_LOCALITY, None));
+        assert_ne!(0, certify_default(&state, other_locality, None));
+
+        // A listener locality overrides whatever the client claims.
+        assert_eq!(
+            0,
+            certify_default(&state, other_locality, Some(AUTO_INIT_LOCALITY))
+        );
+        assert_ne!(
+            0,
+            certify_default(&state, AUTO_INIT_LOCALITY, Some(other_locality))
+        );
+    }
 }
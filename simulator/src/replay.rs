@@ -0,0 +1,128 @@
+// Licensed under the Apache-2.0 license
+
+//! Recording and replaying of simulator sessions.
+//!
+//! A log is a sequence of frames, one per executed command, in the order the
+//! commands ran:
+//!
+//! ```text
+//! locality:     u32 (little-endian)
+//! command_len:  u32 (little-endian)
+//! command:      [u8; command_len]
+//! response_len: u32 (little-endian)
+//! response:     [u8; response_len]
+//! ```
+//!
+//! The locality is the one the command was executed with, after any
+//! per-connection override.
+
+use log::{error, info};
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Error, ErrorKind, Read, Result, Write};
+use std::path::Path;
+
+use dpe::{
+    dpe_instance::{DpeEnv, DpeTypes},
+    DpeInstance,
+};
+
+/// Appends executed commands and their responses to a log file.
+pub struct Recorder {
+    out: BufWriter<File>,
+}
+
+impl Recorder {
+    pub fn create(path: &Path) -> Result<Self> {
+        Ok(Self {
+            out: BufWriter::new(File::create(path)?),
+        })
+    }
+
+    /// Logs one command. The frame is flushed right away so the log is
+    /// complete even if the simulator is killed.
+    pub fn record(&mut self, locality: u32, command: &[u8], response: &[u8]) -> Result<()> {
+        self.out.write_all(&locality.to_le_bytes())?;
+        write_bytes(&mut self.out, command)?;
+        write_bytes(&mut self.out, response)?;
+        self.out.flush()
+    }
+}
+
+fn write_bytes(out: &mut impl Write, bytes: &[u8]) -> Result<()> {
+    let len = u32::try_from(bytes.len()).map_err(|_| Error::from(ErrorKind::InvalidInput))?;
+    out.write_all(&len.to_le_bytes())?;
+    out.write_all(bytes)
+}
+
+fn read_u32(input: &mut impl Read) -> Result<u32> {
+    let mut bytes = [0u8; 4];
+    input.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_bytes(input: &mut impl Read) -> Result<Vec<u8>> {
+    let mut bytes = vec![0u8; read_u32(input)? as usize];
+    input.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+/// One command read back from a log.
+struct Frame {
+    locality: u32,
+    command: Vec<u8>,
+    response: Vec<u8>,
+}
+
+/// Reads the next frame, or `None` at the end of the log.
+fn read_frame(input: &mut impl Read) -> Result<Option<Frame>> {
+    let locality = match read_u32(input) {
+        Ok(locality) => locality,
+        Err(err) if err.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+        Err(err) => return Err(err),
+    };
+    Ok(Some(Frame {
+        locality,
+        command: read_bytes(input)?,
+        response: read_bytes(input)?,
+    }))
+}
+
+/// Replays the log at `path` against `dpe`, which should be freshly created
+/// with the same support flags as the recorded instance.
+///
+/// Fails on the first response that differs from the recorded one. Commands
+/// that consume randomness only reproduce when the simulator is built with
+/// the `deterministic_rand` feature.
+pub fn replay(path: &Path, dpe: &mut DpeInstance, env: &mut DpeEnv<impl DpeTypes>) -> Result<()> {
+    let mut input = BufReader::new(File::open(path)?);
+    let mut count = 0usize;
+
+    while let Some(frame) = read_frame(&mut input)? {
+        let response = dpe
+            .execute_serialized_command(env, frame.locality, &frame.command)
+            .map_err(|err| {
+                Error::new(
+                    ErrorKind::Other,
+                    format!("{err:?} while replaying command {count}"),
+                )
+            })?;
+
+        if response.as_bytes() != frame.response {
+            error!(
+                "Command {count} from locality {:#x} diverged.\n  command:  {:02x?}\n  recorded: {:02x?}\n  replayed: {:02x?}",
+                frame.locality,
+                frame.command,
+                frame.response,
+                response.as_bytes()
+            );
+            return Err(Error::new(
+                ErrorKind::Other,
+                format!("replayed response to command {count} does not match the log"),
+            ));
+        }
+        count += 1;
+    }
+
+    info!("Replayed {count} commands from {}", path.display());
+    Ok(())
+}
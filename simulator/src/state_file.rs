@@ -0,0 +1,115 @@
+// Licensed under the Apache-2.0 license
+
+//! Persisting the simulator's DPE instance across restarts.
+//!
+//! The file holds the output of `DpeInstance::serialize`, encrypted under the
+//! platform's state protection key. It is written to a
+//! temporary file next to the state file and renamed over it, so a simulator
+//! killed while saving leaves the previous state intact.
+
+use log::info;
+use std::fs;
+use std::io::{Error, ErrorKind, Result};
+use std::path::Path;
+
+use dpe::{
+    dpe_instance::{DpeEnv, DpeTypes},
+    DpeInstance,
+};
+
+/// Restores the instance saved in `path`, or returns `None` if there is no
+/// state file yet.
+pub fn load(path: &Path, env: &mut DpeEnv<impl DpeTypes>) -> Result<Option<DpeInstance>> {
+    let bytes = match fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err),
+    };
+    let dpe = DpeInstance::deserialize(env, &bytes).map_err(|err| {
+        Error::new(
+            ErrorKind::InvalidData,
+            format!("{err:?} while restoring {}", path.display()),
+        )
+    })?;
+    info!("Restored DPE state from {}", path.display());
+    Ok(Some(dpe))
+}
+
+/// Saves `dpe` to `path`, replacing any previous state.
+pub fn save(path: &Path, dpe: &DpeInstance, env: &mut DpeEnv<impl DpeTypes>) -> Result<()> {
+    let mut bytes = vec![0u8; DpeInstance::SERIALIZED_SIZE];
+    let len = dpe.serialize(env, &mut bytes).map_err(|err| {
+        Error::new(
+            ErrorKind::Other,
+            format!("{err:?} while serializing DPE state"),
+        )
+    })?;
+
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    fs::write(&tmp, &bytes[..len])?;
+    fs::rename(&tmp, path)?;
+    info!("Saved DPE state to {}", path.display());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fault::FaultyCrypto;
+    use crate::{SimCrypto, SimTypes};
+    use dpe::{
+        commands::{CommandExecution, DeriveContextCmd, DeriveContextFlags},
+        context::ContextHandle,
+        support::Support,
+        DPE_PROFILE,
+    };
+    use platform::default::{DefaultPlatform, AUTO_INIT_LOCALITY};
+    use zerocopy::AsBytes;
+
+    fn new_env() -> DpeEnv<'static, SimTypes> {
+        DpeEnv {
+            crypto: FaultyCrypto::new(SimCrypto::new()),
+            platform: DefaultPlatform,
+            arena: &mut [],
+        }
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let path = std::env::temp_dir().join(format!("dpe-sim-state-{}", std::process::id()));
+        let _ = fs::remove_file(&path);
+
+        let mut env = new_env();
+        assert!(load(&path, &mut env).unwrap().is_none());
+
+        let mut dpe = DpeInstance::new(&mut env, Support::AUTO_INIT).unwrap();
+        let derive = DeriveContextCmd {
+            handle: ContextHandle::default(),
+            data: [0xAA; DPE_PROFILE.get_hash_size()],
+            flags: DeriveContextFlags::MAKE_DEFAULT,
+            tci_type: 7,
+            target_locality: AUTO_INIT_LOCALITY,
+        };
+        assert!(derive
+            .execute(&mut dpe, &mut env, AUTO_INIT_LOCALITY)
+            .is_ok());
+        save(&path, &dpe, &mut env).unwrap();
+
+        // A fresh simulator picks up the derived context.
+        let mut env = new_env();
+        let restored = load(&path, &mut env).unwrap().unwrap();
+        assert_eq!(dpe.as_bytes(), restored.as_bytes());
+
+        // Corrupted state is refused rather than silently replaced.
+        let mut bytes = fs::read(&path).unwrap();
+        *bytes.last_mut().unwrap() ^= 1;
+        fs::write(&path, bytes).unwrap();
+        assert_eq!(
+            load(&path, &mut env).unwrap_err().kind(),
+            ErrorKind::InvalidData
+        );
+
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,391 @@
+// Licensed under the Apache-2.0 license
+
+//! Sends single DPE commands to the simulator from the command line.
+//!
+//! Handles, TCIs, labels and digests are given and printed as hex. Each
+//! invocation sends one command, so a handle printed by one command is passed
+//! to the next with --handle.
+//!
+//! Example:
+//!
+//!   dpe-cli get-profile
+//!   dpe-cli derive-child --tci 0102...
+//!   dpe-cli certify-key --out cert.der
+
+use clap::{Parser, Subcommand, ValueEnum};
+use dpe::{
+    commands::{
+        CertifyKeyCmd, CertifyKeyFlags, DeriveContextCmd, DeriveContextFlags, DestroyCtxCmd,
+        InitCtxCmd, SignCmd, SignFlags,
+    },
+    context::ContextHandle,
+    DPE_PROFILE,
+};
+use dpe_client::{
+    signature_der, ClientError, DpeClient, TcpTransport, Transport, UnixTransport,
+    SIMULATOR_SOCKET_PATH,
+};
+use std::{fs, net::SocketAddr, path::PathBuf, process::ExitCode};
+
+/// Sends DPE commands to the simulator.
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    /// Unix socket the simulator listens on.
+    #[arg(long, value_name = "PATH", default_value = SIMULATOR_SOCKET_PATH)]
+    socket: PathBuf,
+
+    /// Connect to a simulator started with --tcp instead of the Unix socket.
+    #[arg(long, value_name = "ADDR:PORT", conflicts_with = "socket")]
+    tcp: Option<SocketAddr>,
+
+    /// Locality the command is sent from.
+    #[arg(long, value_parser = parse_u32, default_value = "0")]
+    locality: u32,
+
+    #[command(subcommand)]
+    command: CliCommand,
+}
+
+#[derive(Subcommand, Debug)]
+enum CliCommand {
+    /// Prints the profile, version and supported features of the DPE.
+    GetProfile,
+
+    /// Initializes the default context, or a simulation context.
+    Init {
+        /// Initialize a simulation context and print its handle.
+        #[arg(long)]
+        simulation: bool,
+
+        /// Derive the simulation context's keys from this seed, so they are
+        /// the same every run. Shorter values are zero-padded.
+        #[arg(long, value_parser = parse_digest, requires = "simulation")]
+        seed: Option<[u8; DPE_PROFILE.get_hash_size()]>,
+    },
+
+    /// Derives a child context that measures TCI.
+    DeriveChild {
+        /// Handle of the parent context.
+        #[arg(long, value_parser = parse_handle, default_value = "00")]
+        handle: ContextHandle,
+
+        /// Measurement of the child. Shorter values are zero-padded.
+        #[arg(long, value_parser = parse_digest)]
+        tci: [u8; DPE_PROFILE.get_hash_size()],
+
+        /// Type of the measurement, as recorded in the certificate.
+        #[arg(long, value_parser = parse_u32, default_value = "0")]
+        tci_type: u32,
+
+        /// Locality the child context belongs to.
+        #[arg(long, value_parser = parse_u32)]
+        target_locality: Option<u32>,
+
+        /// Keep the parent context valid and print its new handle.
+        #[arg(long)]
+        retain_parent: bool,
+
+        /// Make the child the default context of its locality.
+        #[arg(long)]
+        make_default: bool,
+
+        /// Allow the child to certify CA keys.
+        #[arg(long)]
+        allow_ca: bool,
+
+        /// Extend the parent instead of creating a child.
+        #[arg(long)]
+        recursive: bool,
+    },
+
+    /// Certifies a key derived from a context and writes the certificate.
+    CertifyKey {
+        /// Handle of the context to certify.
+        #[arg(long, value_parser = parse_handle, default_value = "00")]
+        handle: ContextHandle,
+
+        /// Label the key is derived with. Shorter values are zero-padded.
+        #[arg(long, value_parser = parse_digest, default_value = "00")]
+        label: [u8; DPE_PROFILE.get_hash_size()],
+
+        /// Format of the certificate.
+        #[arg(long, value_enum, default_value_t = CertFormat::X509)]
+        format: CertFormat,
+
+        /// Certify the key as a CA.
+        #[arg(long)]
+        is_ca: bool,
+
+        /// File to write the certificate, CSR or COSE_Key to.
+        #[arg(long, value_name = "FILE")]
+        out: PathBuf,
+    },
+
+    /// Signs a digest with a key derived from a context.
+    Sign {
+        /// Handle of the context to sign with.
+        #[arg(long, value_parser = parse_handle, default_value = "00")]
+        handle: ContextHandle,
+
+        /// Label the key is derived with. Shorter values are zero-padded.
+        #[arg(long, value_parser = parse_digest, default_value = "00")]
+        label: [u8; DPE_PROFILE.get_hash_size()],
+
+        /// Digest to sign. Shorter values are zero-padded.
+        #[arg(long, value_parser = parse_digest)]
+        digest: [u8; DPE_PROFILE.get_hash_size()],
+
+        /// Compute an HMAC instead of an ECDSA signature.
+        #[arg(long)]
+        symmetric: bool,
+
+        /// File to write the DER ECDSA signature to.
+        #[arg(long, value_name = "FILE", conflicts_with = "symmetric")]
+        out: Option<PathBuf>,
+    },
+
+    /// Writes the DPE's certificate chain.
+    GetCertificateChain {
+        /// File to write the chain to.
+        #[arg(long, value_name = "FILE")]
+        out: PathBuf,
+
+        /// Write PEM certificates instead of concatenated DER.
+        #[arg(long)]
+        pem: bool,
+    },
+
+    /// Destroys a context.
+    Destroy {
+        /// Handle of the context to destroy.
+        #[arg(long, value_parser = parse_handle, default_value = "00")]
+        handle: ContextHandle,
+    },
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum CertFormat {
+    X509,
+    Csr,
+    Cwt,
+    /// The derived public key as an EC2 COSE_Key.
+    CoseKey,
+}
+
+impl CertFormat {
+    fn format(self) -> u32 {
+        match self {
+            CertFormat::X509 => CertifyKeyCmd::FORMAT_X509,
+            CertFormat::Csr => CertifyKeyCmd::FORMAT_CSR,
+            CertFormat::Cwt => CertifyKeyCmd::FORMAT_CWT,
+            CertFormat::CoseKey => CertifyKeyCmd::FORMAT_COSE_KEY,
+        }
+    }
+}
+
+/// Parses a decimal or 0x-prefixed hex integer.
+fn parse_u32(arg: &str) -> Result<u32, String> {
+    let result = match arg.strip_prefix("0x") {
+        Some(hex) => u32::from_str_radix(hex, 16),
+        None => arg.parse(),
+    };
+    result.map_err(|err| err.to_string())
+}
+
+/// Parses hex into `N` bytes, zero-padding values shorter than `N`.
+fn parse_hex<const N: usize>(arg: &str) -> Result<[u8; N], String> {
+    let arg = arg.strip_prefix("0x").unwrap_or(arg);
+    if arg.len() % 2 != 0 {
+        return Err("odd number of hex digits".into());
+    }
+    if arg.len() > 2 * N {
+        return Err(format!("longer than {N} bytes"));
+    }
+    let mut bytes = [0; N];
+    for (byte, digits) in bytes.iter_mut().zip(arg.as_bytes().chunks(2)) {
+        let digits = std::str::from_utf8(digits).map_err(|err| err.to_string())?;
+        *byte = u8::from_str_radix(digits, 16).map_err(|err| err.to_string())?;
+    }
+    Ok(bytes)
+}
+
+fn parse_digest(arg: &str) -> Result<[u8; DPE_PROFILE.get_hash_size()], String> {
+    parse_hex(arg)
+}
+
+fn parse_handle(arg: &str) -> Result<ContextHandle, String> {
+    parse_hex(arg).map(ContextHandle)
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+fn run(client: &mut DpeClient<impl Transport>, command: CliCommand) -> Result<(), ClientError> {
+    match command {
+        CliCommand::GetProfile => {
+            let profile = client.get_profile()?;
+            println!("profile: {}", profile.resp_hdr.profile);
+            println!(
+                "version: {}.{}",
+                profile.major_version, profile.minor_version
+            );
+            println!("vendor id: {:#x}", profile.vendor_id);
+            println!("vendor sku: {:#x}", profile.vendor_sku);
+            println!("max tci nodes: {}", profile.max_tci_nodes);
+            println!("flags: {:#010x}", profile.flags);
+            println!(
+                "max simulation contexts: {}",
+                profile.max_simulation_contexts
+            );
+            println!("max cert chain depth: {}", profile.max_cert_chain_depth);
+        }
+        CliCommand::Init { simulation, seed } => {
+            let resp = match seed {
+                Some(seed) => client
+                    .initialize_context_with_seed(&InitCtxCmd::new_seeded_simulation(), &seed)?,
+                None if simulation => client.initialize_context(&InitCtxCmd::new_simulation())?,
+                None => client.initialize_context(&InitCtxCmd::new_use_default())?,
+            };
+            println!("handle: {}", hex(&resp.handle.0));
+        }
+        CliCommand::DeriveChild {
+            handle,
+            tci,
+            tci_type,
+            target_locality,
+            retain_parent,
+            make_default,
+            allow_ca,
+            recursive,
+        } => {
+            let mut flags = DeriveContextFlags::empty();
+            flags.set(DeriveContextFlags::RETAIN_PARENT_CONTEXT, retain_parent);
+            flags.set(DeriveContextFlags::MAKE_DEFAULT, make_default);
+            flags.set(DeriveContextFlags::INPUT_ALLOW_CA, allow_ca);
+            flags.set(DeriveContextFlags::RECURSIVE, recursive);
+            flags.set(
+                DeriveContextFlags::CHANGE_LOCALITY,
+                target_locality.is_some(),
+            );
+            let resp = client.derive_context(&DeriveContextCmd {
+                handle,
+                data: tci,
+                flags,
+                tci_type,
+                target_locality: target_locality.unwrap_or(client.locality()),
+            })?;
+            println!("handle: {}", hex(&resp.handle.0));
+            println!("parent handle: {}", hex(&resp.parent_handle.0));
+        }
+        CliCommand::CertifyKey {
+            handle,
+            label,
+            format,
+            is_ca,
+            out,
+        } => {
+            let mut flags = CertifyKeyFlags::empty();
+            flags.set(CertifyKeyFlags::IS_CA, is_ca);
+            let resp = client.certify_key(&CertifyKeyCmd {
+                handle,
+                flags,
+                label,
+                format: format.format(),
+            })?;
+            let cert = client.read_certificate(resp.total_size)?;
+            fs::write(&out, &cert)?;
+            println!("handle: {}", hex(&resp.new_context_handle.0));
+            println!("public key x: {}", hex(&resp.derived_pubkey_x));
+            println!("public key y: {}", hex(&resp.derived_pubkey_y));
+            println!("wrote {} bytes to {}", cert.len(), out.display());
+        }
+        CliCommand::Sign {
+            handle,
+            label,
+            digest,
+            symmetric,
+            out,
+        } => {
+            let mut flags = SignFlags::empty();
+            flags.set(SignFlags::IS_SYMMETRIC, symmetric);
+            let resp = client.sign(&SignCmd {
+                handle,
+                label,
+                flags,
+                digest,
+            })?;
+            println!("handle: {}", hex(&resp.new_context_handle.0));
+            if symmetric {
+                println!("hmac: {}", hex(&resp.sig_r_or_hmac));
+            } else {
+                println!("r: {}", hex(&resp.sig_r_or_hmac));
+                println!("s: {}", hex(&resp.sig_s));
+            }
+            if let Some(out) = out {
+                fs::write(&out, signature_der(&resp)?)?;
+                println!("wrote signature to {}", out.display());
+            }
+        }
+        CliCommand::GetCertificateChain { out, pem } => {
+            if pem {
+                fs::write(&out, client.read_certificate_chain_pem()?)?;
+            } else {
+                fs::write(&out, client.read_certificate_chain()?)?;
+            }
+            println!("wrote certificate chain to {}", out.display());
+        }
+        CliCommand::Destroy { handle } => {
+            client.destroy_context(&DestroyCtxCmd { handle })?;
+        }
+    }
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    let args = Args::parse();
+    let result = match args.tcp {
+        Some(addr) => run(
+            &mut DpeClient::new(TcpTransport::new(addr), args.locality),
+            args.command,
+        ),
+        None => run(
+            &mut DpeClient::new(UnixTransport::new(args.socket), args.locality),
+            args.command,
+        ),
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(ClientError::Dpe(status)) => {
+            eprintln!("DPE returned error {status:#x}");
+            ExitCode::FAILURE
+        }
+        Err(err) => {
+            eprintln!("{err:?}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
+
+    #[test]
+    fn test_args() {
+        Args::command().debug_assert();
+    }
+
+    #[test]
+    fn test_parse_hex() {
+        assert_eq!(parse_hex::<4>("0102"), Ok([1, 2, 0, 0]));
+        assert_eq!(parse_hex::<2>("0xabCD"), Ok([0xab, 0xcd]));
+        assert!(parse_hex::<2>("010203").is_err());
+        assert!(parse_hex::<2>("012").is_err());
+        assert!(parse_hex::<2>("zz").is_err());
+        assert_eq!(parse_u32("0x10"), Ok(16));
+        assert_eq!(parse_u32("10"), Ok(10));
+    }
+}
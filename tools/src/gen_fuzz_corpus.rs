@@ -0,0 +1,332 @@
+// Licensed under the Apache-2.0 license
+
+//! Writes one well-formed serialized command per file to seed the fuzzers.
+//!
+//! Usage: gen_fuzz_corpus [OUTPUT_DIR]
+//!
+//! OUTPUT_DIR defaults to dpe/fuzz/common_corpus.
+
+use std::{env, fs, path::PathBuf};
+use {
+    dpe::commands::{
+        AttestCmd, CertifyKeyCmd, CertifyKeyFlags, CloseSessionCmd, Command, CommandHdr,
+        DeriveContextCmd, DeriveContextFlags, DestroyCtxCmd, ExportCdiCmd, ExtendTciCmd,
+        GetCertificateChainCmd, GetCertificateChunkCmd, GetCommandCounterCmd, GetContextInfoCmd,
+        GetTaggedTciCmd, HashFinalCmd, HashUpdateCmd, InitCtxCmd, OpenSessionCmd, RotateCtxCmd,
+        RotateCtxFlags, SealCmd, SealFlags, SignCmd, SignFlags, TagTciCmd, UnsealCmd,
+    },
+    dpe::context::ContextHandle,
+    dpe::{DPE_PROFILE, SEAL_HEADER_SIZE},
+    platform::MAX_CHUNK_SIZE,
+    zerocopy::AsBytes,
+};
+
+fn serialize(cmd_id: u32, body: &[u8]) -> Vec<u8> {
+    let mut command = CommandHdr::new(cmd_id).as_bytes().to_vec();
+    command.extend_from_slice(body);
+    command
+}
+
+fn seeds() -> Vec<(&'static str, Vec<u8>)> {
+    let derive = |flags| DeriveContextCmd {
+        handle: ContextHandle::default(),
+        data: [1; DPE_PROFILE.get_hash_size()],
+        flags,
+        tci_type: 0,
+        target_locality: 0,
+    };
+    let certify_key = |format| CertifyKeyCmd {
+        handle: ContextHandle::default(),
+        flags: CertifyKeyFlags::empty(),
+        format,
+        label: [0; DPE_PROFILE.get_hash_size()],
+    };
+
+    vec![
+        ("get_profile", serialize(Command::GET_PROFILE, &[])),
+        (
+            "initialize_context_default",
+            serialize(
+                Command::INITIALIZE_CONTEXT,
+                InitCtxCmd::new_use_default().as_bytes(),
+            ),
+        ),
+        (
+            "initialize_context_simulation",
+            serialize(
+                Command::INITIALIZE_CONTEXT,
+                InitCtxCmd::new_simulation().as_bytes(),
+            ),
+        ),
+        (
+            "derive_context",
+            serialize(
+                Command::DERIVE_CONTEXT,
+                derive(DeriveContextFlags::MAKE_DEFAULT | DeriveContextFlags::INPUT_ALLOW_X509)
+                    .as_bytes(),
+            ),
+        ),
+        (
+            "derive_context_recursive",
+            serialize(
+                Command::DERIVE_CONTEXT,
+                derive(DeriveContextFlags::RECURSIVE).as_bytes(),
+            ),
+        ),
+        (
+            "certify_key_x509",
+            serialize(
+                Command::CERTIFY_KEY,
+                certify_key(CertifyKeyCmd::FORMAT_X509).as_bytes(),
+            ),
+        ),
+        (
+            "certify_key_csr",
+            serialize(
+                Command::CERTIFY_KEY,
+                certify_key(CertifyKeyCmd::FORMAT_CSR).as_bytes(),
+            ),
+        ),
+        (
+            "certify_key_cwt",
+            serialize(
+                Command::CERTIFY_KEY,
+                certify_key(CertifyKeyCmd::FORMAT_CWT).as_bytes(),
+            ),
+        ),
+        (
+            "certify_key_cose_key",
+            serialize(
+                Command::CERTIFY_KEY,
+                certify_key(CertifyKeyCmd::FORMAT_COSE_KEY).as_bytes(),
+            ),
+        ),
+        (
+            "sign",
+            serialize(
+                Command::SIGN,
+                SignCmd {
+                    handle: ContextHandle::default(),
+                    label: [0; DPE_PROFILE.get_hash_size()],
+                    flags: SignFlags::empty(),
+                    digest: [0; DPE_PROFILE.get_hash_size()],
+                }
+                .as_bytes(),
+            ),
+        ),
+        (
+            "sign_message",
+            serialize(Command::SIGN, &{
+                let mut body = SignCmd {
+                    handle: ContextHandle::default(),
+                    label: [0; DPE_PROFILE.get_hash_size()],
+                    flags: SignFlags::IS_MESSAGE,
+                    digest: [0; DPE_PROFILE.get_hash_size()],
+                }
+                .as_bytes()
+                .to_vec();
+                body.extend_from_slice(&4u32.to_le_bytes());
+                body.extend_from_slice(b"DPE!");
+                body
+            }),
+        ),
+        (
+            "rotate_context_handle",
+            serialize(
+                Command::ROTATE_CONTEXT_HANDLE,
+                RotateCtxCmd {
+                    handle: ContextHandle::default(),
+                    flags: RotateCtxFlags::empty(),
+                }
+                .as_bytes(),
+            ),
+        ),
+        (
+            "destroy_context",
+            serialize(
+                Command::DESTROY_CONTEXT,
+                DestroyCtxCmd {
+                    handle: ContextHandle::default(),
+                }
+                .as_bytes(),
+            ),
+        ),
+        (
+            "get_certificate_chain",
+            serialize(
+                Command::GET_CERTIFICATE_CHAIN,
+                GetCertificateChainCmd {
+                    offset: 0,
+                    size: MAX_CHUNK_SIZE as u32,
+                }
+                .as_bytes(),
+            ),
+        ),
+        (
+            "extend_tci",
+            serialize(
+                Command::EXTEND_TCI,
+                ExtendTciCmd {
+                    handle: ContextHandle::default(),
+                    data: [2; DPE_PROFILE.get_hash_size()],
+                }
+                .as_bytes(),
+            ),
+        ),
+        (
+            "tag_tci",
+            serialize(
+                Command::TAG_TCI,
+                TagTciCmd {
+                    handle: ContextHandle::default(),
+                    tag: 1,
+                }
+                .as_bytes(),
+            ),
+        ),
+        (
+            "get_tagged_tci",
+            serialize(
+                Command::GET_TAGGED_TCI,
+                GetTaggedTciCmd { tag: 1 }.as_bytes(),
+            ),
+        ),
+        (
+            "open_session",
+            serialize(
+                Command::OPEN_SESSION,
+                OpenSessionCmd {
+                    pub_key_x: [0; DPE_PROFILE.get_ecc_int_size()],
+                    pub_key_y: [0; DPE_PROFILE.get_ecc_int_size()],
+                }
+                .as_bytes(),
+            ),
+        ),
+        (
+            "close_session",
+            serialize(
+                Command::CLOSE_SESSION,
+                CloseSessionCmd { session_id: 1 }.as_bytes(),
+            ),
+        ),
+        (
+            "get_certificate_chunk",
+            serialize(
+                Command::GET_CERTIFICATE_CHUNK,
+                GetCertificateChunkCmd {
+                    offset: 0,
+                    size: MAX_CHUNK_SIZE as u32,
+                }
+                .as_bytes(),
+            ),
+        ),
+        (
+            "export_cdi",
+            serialize(
+                Command::EXPORT_CDI,
+                ExportCdiCmd {
+                    handle: ContextHandle::default(),
+                }
+                .as_bytes(),
+            ),
+        ),
+        (
+            "get_command_counter",
+            serialize(
+                Command::GET_COMMAND_COUNTER,
+                GetCommandCounterCmd {
+                    handle: ContextHandle::default(),
+                }
+                .as_bytes(),
+            ),
+        ),
+        ("hash_initialize", serialize(Command::HASH_INITIALIZE, &[])),
+        (
+            "hash_update",
+            serialize(Command::HASH_UPDATE, &{
+                let mut body = HashUpdateCmd {
+                    stream_id: 0,
+                    size: 4,
+                }
+                .as_bytes()
+                .to_vec();
+                body.extend_from_slice(b"DPE!");
+                body
+            }),
+        ),
+        (
+            "hash_final",
+            serialize(
+                Command::HASH_FINAL,
+                HashFinalCmd { stream_id: 0 }.as_bytes(),
+            ),
+        ),
+        (
+            "get_context_info",
+            serialize(
+                Command::GET_CONTEXT_INFO,
+                GetContextInfoCmd {
+                    handle: ContextHandle::default(),
+                }
+                .as_bytes(),
+            ),
+        ),
+        (
+            "seal",
+            serialize(Command::SEAL, &{
+                let mut body = SealCmd {
+                    handle: ContextHandle::default(),
+                    label: [0; DPE_PROFILE.get_hash_size()],
+                    flags: SealFlags::empty(),
+                    tci: [0; DPE_PROFILE.get_tci_size()],
+                    size: 4,
+                }
+                .as_bytes()
+                .to_vec();
+                body.extend_from_slice(b"DPE!");
+                body
+            }),
+        ),
+        (
+            "unseal",
+            serialize(Command::UNSEAL, &{
+                let mut body = UnsealCmd {
+                    handle: ContextHandle::default(),
+                    label: [0; DPE_PROFILE.get_hash_size()],
+                    size: (SEAL_HEADER_SIZE + 32) as u32,
+                }
+                .as_bytes()
+                .to_vec();
+                body.extend_from_slice(&[0; SEAL_HEADER_SIZE + 32]);
+                body
+            }),
+        ),
+        (
+            "attest",
+            serialize(
+                Command::ATTEST,
+                AttestCmd {
+                    handle: ContextHandle::default(),
+                    label: [0; DPE_PROFILE.get_hash_size()],
+                    nonce: [0; DPE_PROFILE.get_hash_size()],
+                }
+                .as_bytes(),
+            ),
+        ),
+    ]
+}
+
+fn main() {
+    let out_dir = env::args()
+        .nth(1)
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from("dpe/fuzz/common_corpus"));
+    fs::create_dir_all(&out_dir).unwrap();
+
+    for (name, command) in seeds() {
+        // Every seed must be accepted by the parser, or it only exercises
+        // the header checks.
+        assert!(Command::deserialize(&command).is_ok());
+        fs::write(out_dir.join(format!("{name}.dat")), command).unwrap();
+    }
+}
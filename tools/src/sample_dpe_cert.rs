@@ -99,6 +99,7 @@ fn main() {
     let mut env = DpeEnv::<TestTypes> {
         crypto: OpensslCrypto::new(),
         platform: DefaultPlatform,
+        arena: &mut [],
     };
 
     let mut dpe = DpeInstance::new(&mut env, support).unwrap();
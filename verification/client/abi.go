@@ -27,14 +27,32 @@ type Support struct {
 	Simulation          bool
 	Recursive           bool
 	AutoInit            bool
+	Tagging             bool
 	RotateContext       bool
 	X509                bool
 	Csr                 bool
 	IsSymmetric         bool
+	ExtendTci           bool
 	InternalInfo        bool
 	InternalDice        bool
 	IsCA                bool
 	RetainParentContext bool
+	Sessions            bool
+	ExportCdi           bool
+	Cwt                 bool
+	CommandCounter      bool
+	HashStream          bool
+	MeasurementCache    bool
+	ContextInfo         bool
+	LayeredCerts        bool
+	HandleMac           bool
+	DeviceIDCert        bool
+	CommandAuth         bool
+	Svn                 bool
+	CertCache           bool
+	SimulationSeed      bool
+	Attest              bool
+	Seal                bool
 }
 
 // profileCommandCodes holds command codes for a specific revision of the
@@ -137,13 +155,15 @@ type InitCtxResp struct {
 
 // GetProfileResp is the response from GetProfile
 type GetProfileResp struct {
-	Profile      Profile
-	MajorVersion uint16
-	MinorVersion uint16
-	VendorID     uint32
-	VendorSku    uint32
-	MaxTciNodes  uint32
-	Flags        uint32
+	Profile               Profile
+	MajorVersion          uint16
+	MinorVersion          uint16
+	VendorID              uint32
+	VendorSku             uint32
+	MaxTciNodes           uint32
+	Flags                 uint32
+	MaxSimulationContexts uint32
+	MaxCertChainDepth     uint32
 }
 
 // CertifyKeyFlags is the input flags to CertifyKey
@@ -151,7 +171,11 @@ type CertifyKeyFlags uint32
 
 // Supported flags to CertifyKey
 const (
-	CertifyAddIsCA CertifyKeyFlags = 1 << 30
+	CertifyAddIsCA      CertifyKeyFlags = 1 << 30
+	CertifyRandomSerial CertifyKeyFlags = 1 << 29
+	CertifyRsa3072      CertifyKeyFlags = 1 << 28
+	CertifyRsa4096      CertifyKeyFlags = 1 << 27
+	CertifyRsaPss       CertifyKeyFlags = 1 << 26
 )
 
 // CertifyKeyFormat is the requested output format of the DPE key certification
@@ -161,6 +185,7 @@ type CertifyKeyFormat uint32
 const (
 	CertifyKeyX509 CertifyKeyFormat = 0
 	CertifyKeyCsr  CertifyKeyFormat = 1
+	CertifyKeyCwt  CertifyKeyFormat = 2
 )
 
 // CertifyKeyReq is the input request to CertifyKey
@@ -246,6 +271,9 @@ type SignFlags uint32
 // Supported Sign flags
 const (
 	IsSymmetric SignFlags = 1 << 30
+	// IsMessage asks DPE to hash a message that follows the command.
+	// SignReq carries no message, so Sign rejects it.
+	IsMessage SignFlags = 1 << 29
 )
 
 // SignReq is the input request to Sign
@@ -382,12 +410,14 @@ func getProfile(t Transport) (*GetProfileResp, error) {
 	// Define an anonymous struct for the actual wire-format members of GetProfile,
 	// since GetProfileResp includes the actual profile copied from the response header.
 	respStruct := struct {
-		MajorVersion uint16
-		MinorVersion uint16
-		VendorID     uint32
-		VendorSku    uint32
-		MaxTciNodes  uint32
-		Flags        uint32
+		MajorVersion          uint16
+		MinorVersion          uint16
+		VendorID              uint32
+		VendorSku             uint32
+		MaxTciNodes           uint32
+		Flags                 uint32
+		MaxSimulationContexts uint32
+		MaxCertChainDepth     uint32
 	}{}
 
 	// GetProfile command code is 1 in all revisions of the spec
@@ -399,13 +429,15 @@ func getProfile(t Transport) (*GetProfileResp, error) {
 
 	return &GetProfileResp{
 		// Special case for GetProfile: copy the profile from the inner packet header into the response structure.
-		Profile:      respHdr.Profile,
-		MajorVersion: respStruct.MajorVersion,
-		MinorVersion: respStruct.MinorVersion,
-		VendorID:     respStruct.VendorID,
-		VendorSku:    respStruct.VendorSku,
-		MaxTciNodes:  respStruct.MaxTciNodes,
-		Flags:        respStruct.Flags,
+		Profile:               respHdr.Profile,
+		MajorVersion:          respStruct.MajorVersion,
+		MinorVersion:          respStruct.MinorVersion,
+		VendorID:              respStruct.VendorID,
+		VendorSku:             respStruct.VendorSku,
+		MaxTciNodes:           respStruct.MaxTciNodes,
+		Flags:                 respStruct.Flags,
+		MaxSimulationContexts: respStruct.MaxSimulationContexts,
+		MaxCertChainDepth:     respStruct.MaxCertChainDepth,
 	}, nil
 }
 
@@ -435,6 +467,7 @@ func (c *DPEABI[CurveParameter, Digest]) CertifyKeyABI(cmd *CertifyKeyReq[Digest
 		DerivedPublicKeyY CurveParameter
 		CertificateSize   uint32
 		Certificate       [2048]byte
+		TotalSize         uint32
 	}{}
 
 	_, err := execCommand(c.transport, c.constants.Codes.CertifyKey, c.Profile, cmd, &respStruct)
@@ -446,6 +479,9 @@ func (c *DPEABI[CurveParameter, Digest]) CertifyKeyABI(cmd *CertifyKeyReq[Digest
 	if respStruct.CertificateSize > 2048 {
 		return nil, fmt.Errorf("DPE reported a %d-byte cert, which was larger than 2048", respStruct.CertificateSize)
 	}
+	if respStruct.TotalSize != respStruct.CertificateSize {
+		return nil, fmt.Errorf("DPE reported a %d-byte cert, of which only %d bytes fit in the response", respStruct.TotalSize, respStruct.CertificateSize)
+	}
 
 	return &CertifyKeyResp[CurveParameter, Digest]{
 		NewContextHandle:  respStruct.NewContextHandle,
@@ -650,6 +686,10 @@ func (c *DPEABI[_, Digest]) Sign(handle *ContextHandle, label []byte, flags Sign
 		return nil, fmt.Errorf("invalid toBeSigned length")
 	}
 
+	if flags&IsMessage != 0 {
+		return nil, fmt.Errorf("sign requests carry no message")
+	}
+
 	l, err := NewDigest[Digest](label)
 	if err != nil {
 		return nil, err
@@ -692,6 +732,9 @@ func (s *Support) ToFlags() uint32 {
 	if s.AutoInit {
 		flags |= (1 << 29)
 	}
+	if s.Tagging {
+		flags |= (1 << 28)
+	}
 	if s.RotateContext {
 		flags |= (1 << 27)
 	}
@@ -704,6 +747,9 @@ func (s *Support) ToFlags() uint32 {
 	if s.IsSymmetric {
 		flags |= (1 << 24)
 	}
+	if s.ExtendTci {
+		flags |= (1 << 23)
+	}
 	if s.InternalInfo {
 		flags |= (1 << 22)
 	}
@@ -716,5 +762,88 @@ func (s *Support) ToFlags() uint32 {
 	if s.RetainParentContext {
 		flags |= (1 << 19)
 	}
+	if s.Sessions {
+		flags |= (1 << 18)
+	}
+	if s.ExportCdi {
+		flags |= (1 << 17)
+	}
+	if s.Cwt {
+		flags |= (1 << 16)
+	}
+	if s.CommandCounter {
+		flags |= (1 << 15)
+	}
+	if s.HashStream {
+		flags |= (1 << 14)
+	}
+	if s.MeasurementCache {
+		flags |= (1 << 13)
+	}
+	if s.ContextInfo {
+		flags |= (1 << 12)
+	}
+	if s.LayeredCerts {
+		flags |= (1 << 11)
+	}
+	if s.HandleMac {
+		flags |= (1 << 10)
+	}
+	if s.DeviceIDCert {
+		flags |= (1 << 9)
+	}
+	if s.CommandAuth {
+		flags |= (1 << 8)
+	}
+	if s.Svn {
+		flags |= (1 << 7)
+	}
+	if s.CertCache {
+		flags |= (1 << 6)
+	}
+	if s.SimulationSeed {
+		flags |= (1 << 5)
+	}
+	if s.Attest {
+		flags |= (1 << 4)
+	}
+	if s.Seal {
+		flags |= (1 << 3)
+	}
 	return flags
 }
+
+// Support decodes the profile-defined support flags reported by GetProfile
+func (r *GetProfileResp) Support() Support {
+	return Support{
+		Simulation:          r.Flags&(1<<31) != 0,
+		Recursive:           r.Flags&(1<<30) != 0,
+		AutoInit:            r.Flags&(1<<29) != 0,
+		Tagging:             r.Flags&(1<<28) != 0,
+		RotateContext:       r.Flags&(1<<27) != 0,
+		X509:                r.Flags&(1<<26) != 0,
+		Csr:                 r.Flags&(1<<25) != 0,
+		IsSymmetric:         r.Flags&(1<<24) != 0,
+		ExtendTci:           r.Flags&(1<<23) != 0,
+		InternalInfo:        r.Flags&(1<<22) != 0,
+		InternalDice:        r.Flags&(1<<21) != 0,
+		IsCA:                r.Flags&(1<<20) != 0,
+		RetainParentContext: r.Flags&(1<<19) != 0,
+		Sessions:            r.Flags&(1<<18) != 0,
+		ExportCdi:           r.Flags&(1<<17) != 0,
+		Cwt:                 r.Flags&(1<<16) != 0,
+		CommandCounter:      r.Flags&(1<<15) != 0,
+		HashStream:          r.Flags&(1<<14) != 0,
+		MeasurementCache:    r.Flags&(1<<13) != 0,
+		ContextInfo:         r.Flags&(1<<12) != 0,
+		LayeredCerts:        r.Flags&(1<<11) != 0,
+		HandleMac:           r.Flags&(1<<10) != 0,
+		DeviceIDCert:        r.Flags&(1<<9) != 0,
+		CommandAuth:         r.Flags&(1<<8) != 0,
+		Svn:                 r.Flags&(1<<7) != 0,
+		CertCache:           r.Flags&(1<<6) != 0,
+		SimulationSeed:      r.Flags&(1<<5) != 0,
+		Attest:              r.Flags&(1<<4) != 0,
+		Seal:                r.Flags&(1<<3) != 0,
+	}
+}
@@ -21,6 +21,21 @@ const (
 	StatusCryptoError          Status = 0x1005
 	StatusHashError            Status = 0x1006
 	StatusRandError            Status = 0x1007
+	StatusMaxSessions          Status = 0x1008
+	StatusCommandNotAllowed    Status = 0x1009
+	StatusMaxHashStreams       Status = 0x100A
+	StatusInvalidSession       Status = 0x100B
+	StatusCertificateTooLarge  Status = 0x100C
+	StatusInvalidCertificate   Status = 0x100D
+	StatusTciTypeNotAllowed    Status = 0x100E
+	StatusOutputBufferTooSmall Status = 0x100F
+	StatusAuthenticationFailed Status = 0x1010
+	StatusUnknownLocality      Status = 0x1011
+	StatusSvnRollback          Status = 0x1012
+	StatusUnsealFailed         Status = 0x1013
+	StatusBusy                 Status = 0x1014
+	StatusRetiredContext       Status = 0x1015
+	StatusMaxCertChainDepth    Status = 0x1016
 )
 
 // Error returns an informational string for all DPE error codes
@@ -48,6 +63,36 @@ func (s Status) Error() string {
 		return "error in hashing buffer"
 	case StatusRandError:
 		return "error in random byte generation"
+	case StatusMaxSessions:
+		return "maximum number of sessions are open"
+	case StatusCommandNotAllowed:
+		return "command is not allowed from this locality"
+	case StatusMaxHashStreams:
+		return "maximum number of hash streams are active"
+	case StatusInvalidSession:
+		return "session does not exist or message counter is stale"
+	case StatusCertificateTooLarge:
+		return "certificate does not fit in the output buffer"
+	case StatusInvalidCertificate:
+		return "certificate could not be parsed"
+	case StatusTciTypeNotAllowed:
+		return "TCI type may not be derived from the parent's TCI type"
+	case StatusOutputBufferTooSmall:
+		return "response does not fit in the output buffer"
+	case StatusAuthenticationFailed:
+		return "command MAC or counter is invalid"
+	case StatusUnknownLocality:
+		return "locality is not in the platform's locality table"
+	case StatusSvnRollback:
+		return "security version number is lower than the parent's"
+	case StatusUnsealFailed:
+		return "sealed data does not match the context's measurements"
+	case StatusBusy:
+		return "another command is still pending"
+	case StatusRetiredContext:
+		return "context was retired by deriving a child without retaining it"
+	case StatusMaxCertChainDepth:
+		return "derived context would exceed the maximum certificate chain depth"
 	default:
 		return fmt.Sprintf("unrecognized status code 0x%0x", uint32(s))
 	}
@@ -64,6 +64,9 @@ func (s *DpeSimulator) PowerOn() error {
 	if s.supports.AutoInit {
 		args = append(args, "--supports-auto-init")
 	}
+	if s.supports.Tagging {
+		args = append(args, "--supports-tagging")
+	}
 	if s.supports.RotateContext {
 		args = append(args, "--supports-rotate-context")
 	}
@@ -79,6 +82,9 @@ func (s *DpeSimulator) PowerOn() error {
 	if s.supports.IsSymmetric {
 		args = append(args, "--supports-is-symmetric")
 	}
+	if s.supports.ExtendTci {
+		args = append(args, "--supports-extend-tci")
+	}
 	if s.supports.InternalInfo {
 		args = append(args, "--supports-internal-info")
 	}
@@ -88,6 +94,54 @@ func (s *DpeSimulator) PowerOn() error {
 	if s.supports.RetainParentContext {
 		args = append(args, "--supports-retain-parent-context")
 	}
+	if s.supports.Sessions {
+		args = append(args, "--supports-sessions")
+	}
+	if s.supports.ExportCdi {
+		args = append(args, "--supports-export-cdi")
+	}
+	if s.supports.Cwt {
+		args = append(args, "--supports-cwt")
+	}
+	if s.supports.CommandCounter {
+		args = append(args, "--supports-command-counter")
+	}
+	if s.supports.HashStream {
+		args = append(args, "--supports-hash-stream")
+	}
+	if s.supports.MeasurementCache {
+		args = append(args, "--supports-measurement-cache")
+	}
+	if s.supports.ContextInfo {
+		args = append(args, "--supports-context-info")
+	}
+	if s.supports.LayeredCerts {
+		args = append(args, "--supports-layered-certs")
+	}
+	if s.supports.HandleMac {
+		args = append(args, "--supports-handle-mac")
+	}
+	if s.supports.DeviceIDCert {
+		args = append(args, "--supports-device-id-cert")
+	}
+	if s.supports.CommandAuth {
+		args = append(args, "--supports-command-auth")
+	}
+	if s.supports.Svn {
+		args = append(args, "--supports-svn")
+	}
+	if s.supports.CertCache {
+		args = append(args, "--supports-cert-cache")
+	}
+	if s.supports.SimulationSeed {
+		args = append(args, "--supports-simulation-seed")
+	}
+	if s.supports.Attest {
+		args = append(args, "--supports-attest")
+	}
+	if s.supports.Seal {
+		args = append(args, "--supports-seal")
+	}
 
 	s.cmd = exec.Command(s.exePath, args...)
 	s.cmd.Stdout = os.Stdout
@@ -145,27 +199,39 @@ func (s *DpeSimulator) SendCmd(buf []byte) ([]byte, error) {
 	if err != nil {
 		return nil, err
 	}
+	defer conn.Close()
 
-	// Prepend the command with the locality.
-	prepended := bytes.NewBuffer(make([]byte, 0, 4+len(buf)))
-	if err := binary.Write(prepended, binary.LittleEndian, s.currentLocality); err != nil {
+	// Frame the locality and command with their length.
+	framed := bytes.NewBuffer(make([]byte, 0, 8+len(buf)))
+	if err := binary.Write(framed, binary.LittleEndian, uint32(4+len(buf))); err != nil {
 		return nil, err
 	}
-	if _, err := prepended.Write(buf); err != nil {
+	if err := binary.Write(framed, binary.LittleEndian, s.currentLocality); err != nil {
+		return nil, err
+	}
+	if _, err := framed.Write(buf); err != nil {
 		return nil, err
 	}
 
-	// Send the prepended command.
-	numSent, err := conn.Write(prepended.Bytes())
+	// Send the framed command.
+	numSent, err := conn.Write(framed.Bytes())
 	if err != nil {
 		return nil, err
 	}
-	if numSent != len(prepended.Bytes()) {
+	if numSent != len(framed.Bytes()) {
 		return nil, errors.New("didn't send the whole command")
 	}
 
-	// Get the response.
-	return io.ReadAll(conn)
+	// Get the response, which is framed the same way.
+	var respLen uint32
+	if err := binary.Read(conn, binary.LittleEndian, &respLen); err != nil {
+		return nil, err
+	}
+	resp := make([]byte, respLen)
+	if _, err := io.ReadFull(conn, resp); err != nil {
+		return nil, err
+	}
+	return resp, nil
 }
 
 // GetSupport gets supported DPE features from the simulator
@@ -53,6 +53,9 @@ type TcgUeidExtension struct {
 	Ueid []uint8 `asn1:"ueid,implicit"`
 }
 
+// MaxUeidSize is the largest UEID a platform can provide
+const MaxUeidSize = 33
+
 // Fwid represents a TCG DICE FWID structure
 type Fwid struct {
 	HashAlg asn1.ObjectIdentifier
@@ -197,7 +200,7 @@ func TestCertifyKeyCsr(d client.TestDPEInstance, c client.DPEClient, t *testing.
 	}
 
 	// Check fields and extensions in the CSR
-	checkCertifyKeyExtensions(t, csr.Extensions, flags, label, csr.PublicKey, false, certChain[len(certChain)-1].SubjectKeyId)
+	checkCertifyKeyExtensions(t, csr.Extensions, flags, csr.PublicKey, false, certChain[len(certChain)-1].SubjectKeyId)
 	checkPubKey(t, profile, csr.PublicKey, *certifyKeyResp)
 
 	// Check that CSR is self-signed
@@ -274,9 +277,9 @@ func removeTcgDiceExtendedKeyUsages(t *testing.T, certs []*x509.Certificate) {
 }
 
 // A tcg-dice-Ueid extension MUST be added
-// This SHALL be populated by the LABEL input parameter to CertifyKey
+// This SHALL be populated by the device ID provided by the platform
 // The extension SHOULD be marked as critical
-func checkCertifyKeyTcgUeidExtension(t *testing.T, extensions []pkix.Extension, label []byte) {
+func checkCertifyKeyTcgUeidExtension(t *testing.T, extensions []pkix.Extension) {
 	t.Helper()
 
 	ueid, err := getUeid(extensions)
@@ -284,9 +287,8 @@ func checkCertifyKeyTcgUeidExtension(t *testing.T, extensions []pkix.Extension,
 		t.Errorf("[ERROR]: tcg-dice-Ueid extension is missing: %v", err)
 	}
 
-	if !reflect.DeepEqual(ueid.Ueid, label) {
-		// Ueid extn value doen not match the label
-		t.Errorf("[ERROR]: tcg-dice-Ueid value does not match with the \"Label\" passed in CertifyKeyRequest")
+	if len(ueid.Ueid) == 0 || len(ueid.Ueid) > MaxUeidSize {
+		t.Errorf("[ERROR]: tcg-dice-Ueid value has invalid length %d", len(ueid.Ueid))
 	}
 }
 
@@ -333,7 +335,7 @@ func checkCertifyKeyExtendedKeyUsages(t *testing.T, extensions []pkix.Extension,
 // Checks for KeyUsage Extension as per spec
 // If IsCA = true, KeyUsage extension MUST contain DigitalSignature and KeyCertSign
 // If IsCA = false, KeyUsage extension MUST contain  only DigitalSignature
-func checkCertifyKeyExtensions(t *testing.T, extensions []pkix.Extension, flags client.CertifyKeyFlags, label []byte, pubkey any, IsX509 bool, IssuerSki []byte) {
+func checkCertifyKeyExtensions(t *testing.T, extensions []pkix.Extension, flags client.CertifyKeyFlags, pubkey any, IsX509 bool, IssuerSki []byte) {
 	t.Helper()
 
 	bc, err := getBasicConstraints(extensions)
@@ -343,10 +345,10 @@ func checkCertifyKeyExtensions(t *testing.T, extensions []pkix.Extension, flags
 
 	checkCertifyKeyBasicConstraints(t, extensions, flags)
 	checkCertifyKeyExtendedKeyUsages(t, extensions, bc.IsCA)
-	checkCertifyKeyTcgUeidExtension(t, extensions, label)
+	checkCertifyKeyTcgUeidExtension(t, extensions)
 	if IsX509 {
-		checkCertifyKeySubjectKeyIdentifierExtension(t, extensions, bc.IsCA, pubkey)
-		checkCertifyKeyAuthorityKeyIdentifierExtension(t, extensions, bc.IsCA, IssuerSki)
+		checkCertifyKeySubjectKeyIdentifierExtension(t, extensions, pubkey)
+		checkCertifyKeyAuthorityKeyIdentifierExtension(t, extensions, IssuerSki)
 	}
 
 	// Check MultiTcbInfo Extension structure
@@ -377,64 +379,74 @@ func checkCertifyKeyExtensions(t *testing.T, extensions []pkix.Extension, flags
 
 }
 
+// Validates that the label the key was derived with follows the locality in
+// the vendorInfo of the last TcbInfo, which measures the certified context.
+func checkCertifyKeyLabel(t *testing.T, extensions []pkix.Extension, label []byte) {
+	t.Helper()
+
+	multiTcbInfo, err := getMultiTcbInfo(extensions)
+	if err != nil {
+		t.Error(err)
+		return
+	}
+	if len(multiTcbInfo) == 0 {
+		t.Errorf("[ERROR]: Certificate MultiTcbInfo is empty")
+		return
+	}
+
+	vendorInfo := multiTcbInfo[len(multiTcbInfo)-1].VendorInfo
+	if len(vendorInfo) < 4 || !bytes.Equal(vendorInfo[4:], label) {
+		t.Errorf("[ERROR]: Certified TcbInfo vendorInfo %x does not end with the label %x", vendorInfo, label)
+	}
+}
+
 // Validates SubjectKeyIdentifier in certificate returned by CertifyKey command
-// against the isCa flag set in the BasicConstraints extension
-// The SubjectKeyIdentifier extension MUST be included if isCA is true
-// and MUST be excluded if isCA is false.
-func checkCertifyKeySubjectKeyIdentifierExtension(t *testing.T, extensions []pkix.Extension, ca bool, pubkey any) {
+// The SubjectKeyIdentifier extension MUST be included and MUST hold the
+// truncated hash of the subject public key.
+func checkCertifyKeySubjectKeyIdentifierExtension(t *testing.T, extensions []pkix.Extension, pubkey any) {
 	t.Helper()
 
 	ski, err := getSubjectKeyIdentifier(extensions)
 	if err != nil {
 		t.Errorf("[ERROR]: Failed to retrieve SubjectKeyIdentifier extension: %v", err)
 	}
-	if ca {
-		if ski == nil {
-			t.Errorf("[ERROR]: The certificate is a CA but the SubjectKeyIdentifier extension is not present.")
-		}
-		ecdsaPub, ok := pubkey.(*ecdsa.PublicKey)
-		if !ok {
-			t.Fatal("[FATAL]: Public key is not a ecdsa key")
-		}
-		var hasher hash.Hash
-		if ecdsaPub.Curve.Params().BitSize == 256 {
-			hasher = sha256.New()
-		} else {
-			hasher = sha512.New384()
-		}
-		hasher.Write([]byte{0x04})
-		hasher.Write(ecdsaPub.X.Bytes())
-		hasher.Write(ecdsaPub.Y.Bytes())
-		expectedKeyIdentifier := hasher.Sum(nil)[:20]
-		if !reflect.DeepEqual(ski, expectedKeyIdentifier) {
-			t.Errorf("[ERROR]: The value of the subject key identifier %v is not equal to the hash of the public key %v", ski, expectedKeyIdentifier)
-		}
-	} else if !ca && ski != nil {
-		t.Errorf("[ERROR]: The certificate is not a CA but the SubjectKeyIdentifier extension is present.")
+	if ski == nil {
+		t.Fatal("[ERROR]: The SubjectKeyIdentifier extension is not present.")
+	}
+	ecdsaPub, ok := pubkey.(*ecdsa.PublicKey)
+	if !ok {
+		t.Fatal("[FATAL]: Public key is not a ecdsa key")
+	}
+	var hasher hash.Hash
+	if ecdsaPub.Curve.Params().BitSize == 256 {
+		hasher = sha256.New()
+	} else {
+		hasher = sha512.New384()
+	}
+	hasher.Write([]byte{0x04})
+	hasher.Write(ecdsaPub.X.Bytes())
+	hasher.Write(ecdsaPub.Y.Bytes())
+	expectedKeyIdentifier := hasher.Sum(nil)[:20]
+	if !reflect.DeepEqual(ski, expectedKeyIdentifier) {
+		t.Errorf("[ERROR]: The value of the subject key identifier %v is not equal to the hash of the public key %v", ski, expectedKeyIdentifier)
 	}
 }
 
 // Validates AuthorityKeyIdentifier in certificate returned by CertifyKey command
-// against the isCa flag set in the BasicConstraints extension
-// The AuthorityKeyIdentifier extension MUST be included if isCA is true
-// and MUST be excluded if isCA is false.
-func checkCertifyKeyAuthorityKeyIdentifierExtension(t *testing.T, extensions []pkix.Extension, ca bool, IssuerSki []byte) {
+// The AuthorityKeyIdentifier extension MUST be included and MUST match the
+// issuer's SubjectKeyIdentifier.
+func checkCertifyKeyAuthorityKeyIdentifierExtension(t *testing.T, extensions []pkix.Extension, IssuerSki []byte) {
 	t.Helper()
 
 	aki, err := getAuthorityKeyIdentifier(extensions)
 	if err != nil {
 		t.Errorf("[ERROR]: Failed to retrieve AuthorityKeyIdentifier extension: %v", err)
 	}
-	if ca {
-		if aki.KeyIdentifier == nil {
-			t.Fatal("[ERROR]: The certificate is a CA but the AuthorityKeyIdentifier extension is not present.")
-		}
-		// skip first two bytes - first byte is 0x04 der encoding byte and second byte is size byte
-		if !reflect.DeepEqual(aki.KeyIdentifier[2:], IssuerSki) {
-			t.Errorf("[ERROR]: The value of the authority key identifier %v is not equal to the issuer's subject key identifier %v", aki, IssuerSki)
-		}
-	} else if !ca && aki.KeyIdentifier != nil {
-		t.Errorf("[ERROR]: The certificate is not a CA but the AuthorityKeyIdentifier extension is present.")
+	if aki.KeyIdentifier == nil {
+		t.Fatal("[ERROR]: The AuthorityKeyIdentifier extension is not present.")
+	}
+	if !reflect.DeepEqual(aki.KeyIdentifier, IssuerSki) {
+		t.Errorf("[ERROR]: The value of the authority key identifier %v is not equal to the issuer's subject key identifier %v", aki, IssuerSki)
 	}
 }
 
@@ -588,7 +600,8 @@ func testCertifyKey(d client.TestDPEInstance, c client.DPEClient, t *testing.T,
 		checkPubKey(t, profile, leafCert.PublicKey, *certifyKeyResp)
 
 		// Check all extensions
-		checkCertifyKeyExtensions(t, leafCert.Extensions, params.Flags, params.Label, leafCert.PublicKey, true, certChain[len(certChain)-1].SubjectKeyId)
+		checkCertifyKeyExtensions(t, leafCert.Extensions, params.Flags, leafCert.PublicKey, true, certChain[len(certChain)-1].SubjectKeyId)
+		checkCertifyKeyLabel(t, leafCert.Extensions, params.Label)
 
 		// Ensure full certificate chain has valid signatures
 		// This also checks certificate lifetime, signatures as part of cert chain validation
@@ -41,5 +41,13 @@ func TestGetProfile(d client.TestDPEInstance, client client.DPEClient, t *testin
 		if rsp.Flags != d.GetSupport().ToFlags() {
 			t.Fatalf("Incorrect support flags. 0x%08x != 0x%08x", d.GetSupport().ToFlags(), rsp.Flags)
 		}
+		// Every reported bit must map to a known capability.
+		support := rsp.Support()
+		if support.ToFlags() != rsp.Flags {
+			t.Fatalf("Unknown support flags reported. 0x%08x", rsp.Flags&^support.ToFlags())
+		}
+		if support != *d.GetSupport() {
+			t.Fatalf("Incorrect support. %+v != %+v", *d.GetSupport(), support)
+		}
 	}
 }
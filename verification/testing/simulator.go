@@ -60,6 +60,11 @@ func GetSimulatorTargets() []TestTarget {
 			getTestTarget([]string{"AutoInit"}),
 			[]TestCase{GetProfileTestCase},
 		},
+		{
+			"GetProfile_Tagging",
+			getTestTarget([]string{"Tagging"}),
+			[]TestCase{GetProfileTestCase},
+		},
 		{
 			"GetProfile_RotateContext",
 			getTestTarget([]string{"RotateContext"}),
@@ -80,6 +85,11 @@ func GetSimulatorTargets() []TestTarget {
 			getTestTarget([]string{"IsSymmetric"}),
 			[]TestCase{GetProfileTestCase},
 		},
+		{
+			"GetProfile_ExtendTci",
+			getTestTarget([]string{"ExtendTci"}),
+			[]TestCase{GetProfileTestCase},
+		},
 		{
 			"GetProfile_InternalInfo",
 			getTestTarget([]string{"InternalInfo"}),
@@ -100,6 +110,86 @@ func GetSimulatorTargets() []TestTarget {
 			getTestTarget([]string{"RetainParentContext"}),
 			[]TestCase{GetProfileTestCase},
 		},
+		{
+			"GetProfile_Sessions",
+			getTestTarget([]string{"Sessions"}),
+			[]TestCase{GetProfileTestCase},
+		},
+		{
+			"GetProfile_ExportCdi",
+			getTestTarget([]string{"ExportCdi"}),
+			[]TestCase{GetProfileTestCase},
+		},
+		{
+			"GetProfile_Cwt",
+			getTestTarget([]string{"Cwt"}),
+			[]TestCase{GetProfileTestCase},
+		},
+		{
+			"GetProfile_CommandCounter",
+			getTestTarget([]string{"CommandCounter"}),
+			[]TestCase{GetProfileTestCase},
+		},
+		{
+			"GetProfile_HashStream",
+			getTestTarget([]string{"HashStream"}),
+			[]TestCase{GetProfileTestCase},
+		},
+		{
+			"GetProfile_MeasurementCache",
+			getTestTarget([]string{"MeasurementCache"}),
+			[]TestCase{GetProfileTestCase},
+		},
+		{
+			"GetProfile_ContextInfo",
+			getTestTarget([]string{"ContextInfo"}),
+			[]TestCase{GetProfileTestCase},
+		},
+		{
+			"GetProfile_LayeredCerts",
+			getTestTarget([]string{"LayeredCerts"}),
+			[]TestCase{GetProfileTestCase},
+		},
+		{
+			"GetProfile_HandleMac",
+			getTestTarget([]string{"HandleMac"}),
+			[]TestCase{GetProfileTestCase},
+		},
+		{
+			"GetProfile_DeviceIDCert",
+			getTestTarget([]string{"DeviceIDCert"}),
+			[]TestCase{GetProfileTestCase},
+		},
+		{
+			"GetProfile_CommandAuth",
+			getTestTarget([]string{"CommandAuth"}),
+			[]TestCase{GetProfileTestCase},
+		},
+		{
+			"GetProfile_Svn",
+			getTestTarget([]string{"Svn"}),
+			[]TestCase{GetProfileTestCase},
+		},
+		{
+			"GetProfile_CertCache",
+			getTestTarget([]string{"CertCache"}),
+			[]TestCase{GetProfileTestCase},
+		},
+		{
+			"GetProfile_SimulationSeed",
+			getTestTarget([]string{"SimulationSeed"}),
+			[]TestCase{GetProfileTestCase},
+		},
+		{
+			"GetProfile_Attest",
+			getTestTarget([]string{"Attest"}),
+			[]TestCase{GetProfileTestCase},
+		},
+		{
+			"GetProfile_Seal",
+			getTestTarget([]string{"Seal"}),
+			[]TestCase{GetProfileTestCase},
+		},
 		{
 			"GetProfile_Combo01",
 			getTestTarget([]string{"Simulation", "AutoInit", "RotateContext", "Csr", "InternalDice", "IsCA"}),